@@ -0,0 +1,55 @@
+//! Collect the build metadata embedded in the kernel image, see `src/version.rs`, and lay out
+//! the image for the platform, see `layout.rs`.
+
+#![allow(non_camel_case_types)]
+
+mod layout;
+
+use std::env;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let dirty = Command::new("git")
+        .args(["status", "--porcelain", "--untracked-files=no"])
+        .output()
+        .map(|out| out.status.success() && !out.stdout.is_empty())
+        .unwrap_or(false);
+
+    // Honour SOURCE_DATE_EPOCH so reproducible builds get the same image
+    let build_time = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs())
+                .unwrap_or(0)
+        });
+
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(str::to_string))
+        .collect();
+    features.sort();
+
+    println!(
+        "cargo:rustc-env=REL4_GIT_HASH={}{}",
+        git_hash,
+        if dirty { "-dirty" } else { "" }
+    );
+    println!("cargo:rustc-env=REL4_BUILD_TIME={}", build_time);
+    println!("cargo:rustc-env=REL4_FEATURES={}", features.join(","));
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/index");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    println!("cargo:rerun-if-changed=layout.rs");
+
+    layout::generate();
+}
@@ -0,0 +1,262 @@
+//! The memory layout of each platform, and the linker script generated from it.
+//!
+//! The platform is picked by `REL4_PLATFORM`, by default the one of the target architecture the
+//! tests run on. Its layout is checked here, before anything is compiled, and written to
+//! `$OUT_DIR/platform.rs` for `src/config.rs` to hold the constants of `sel4_common` against,
+//! and to `$OUT_DIR/linker.ld`, which the image build links the kernel with, see
+//! `xtask/src/main.rs`. The script asserts that the sections fit the kernel region, so an image
+//! which outgrew it fails to link instead of failing to boot.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+/// Where the kernel of a board lives
+struct platform_t {
+    name: &'static str,
+    arch: &'static str,
+    /// The physical address the kernel image is loaded at
+    load_paddr: usize,
+    /// The end of the RAM the image is loaded into
+    ram_end: usize,
+    /// The virtual address of `load_paddr` in the kernel window
+    load_vaddr: usize,
+    /// The end of the part of the kernel window the image is mapped in
+    window_end: usize,
+    /// The largest untyped handed to the root server at boot, `CONFIG_MAX_BOOT_UNTYPED_BITS`
+    max_boot_untyped_bits: usize,
+}
+
+const PAGE_SIZE: usize = 0x1000;
+const LARGE_PAGE_SIZE: usize = 0x20_0000;
+
+const PLATFORMS: [platform_t; 2] = [
+    // The kernel window maps the GiB around the image with one page, below the kernel devices
+    platform_t {
+        name: "spike",
+        arch: "riscv64",
+        load_paddr: 0x8400_0000,
+        ram_end: 0x8800_0000,
+        load_vaddr: 0xffff_ffff_8400_0000,
+        window_end: 0xffff_ffff_c000_0000,
+        max_boot_untyped_bits: 38,
+    },
+    // QEMU gives the virt board 1 GiB of RAM unless told otherwise
+    platform_t {
+        name: "qemu-arm-virt",
+        arch: "aarch64",
+        load_paddr: 0x4000_0000,
+        ram_end: 0x8000_0000,
+        load_vaddr: 0xffff_ff80_4000_0000,
+        window_end: 0xffff_ffff_c000_0000,
+        max_boot_untyped_bits: 38,
+    },
+];
+
+/// The kernel stack of each core, `src/config.rs` asserts it is `CONFIG_KERNEL_STACK_BITS` of
+/// `sel4_common`
+const KERNEL_STACK_BITS: usize = 12;
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    exit(1)
+}
+
+/// Pick, check and write out the layout of the platform.
+pub fn generate() {
+    println!("cargo:rerun-if-env-changed=REL4_PLATFORM");
+    let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+    if arch != "riscv64" && arch != "aarch64" {
+        fail(&format!(
+            "rel4 builds for riscv64 and aarch64, not {}",
+            arch
+        ));
+    }
+    let platform = match env::var("REL4_PLATFORM") {
+        Ok(name) => PLATFORMS
+            .iter()
+            .find(|platform| platform.name == name)
+            .unwrap_or_else(|| {
+                let names: Vec<_> = PLATFORMS.iter().map(|platform| platform.name).collect();
+                fail(&format!(
+                    "unknown REL4_PLATFORM `{}`, expected one of {}",
+                    name,
+                    names.join(", ")
+                ))
+            }),
+        Err(_) => PLATFORMS
+            .iter()
+            .find(|platform| platform.arch == arch)
+            .unwrap(),
+    };
+    if platform.arch != arch {
+        fail(&format!(
+            "platform `{}` is {}, the kernel is built for {}",
+            platform.name, platform.arch, arch
+        ));
+    }
+    // `src/config.rs` asserts it is `CONFIG_MAX_NUM_NODES` of `sel4_common`
+    let cores = match env::var_os("CARGO_FEATURE_ENABLE_SMP") {
+        Some(_) => 4,
+        None => 1,
+    };
+    if let Err(message) = validate(platform, cores) {
+        fail(&format!(
+            "the layout of `{}` is broken: {}",
+            platform.name, message
+        ));
+    }
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    write(
+        &out_dir.join("platform.rs"),
+        &platform_consts(platform, cores),
+    );
+    write(&out_dir.join("linker.ld"), &linker_script(platform));
+}
+
+fn write(path: &Path, contents: &str) {
+    if let Err(err) = fs::write(path, contents) {
+        fail(&format!("cannot write {}: {}", path.display(), err));
+    }
+}
+
+/// The size of the kernel region, the part of the RAM and of the window from the load address
+fn region_size(platform: &platform_t) -> usize {
+    (platform.ram_end - platform.load_paddr).min(platform.window_end - platform.load_vaddr)
+}
+
+fn validate(platform: &platform_t, cores: usize) -> Result<(), String> {
+    if platform.load_paddr % PAGE_SIZE != 0 || platform.load_vaddr % PAGE_SIZE != 0 {
+        return Err(format!(
+            "the load address {:#x} or its mapping {:#x} is not page aligned",
+            platform.load_paddr, platform.load_vaddr
+        ));
+    }
+    if platform.load_paddr >= platform.ram_end {
+        return Err(format!(
+            "the load address {:#x} is past the end of the RAM at {:#x}",
+            platform.load_paddr, platform.ram_end
+        ));
+    }
+    if platform.load_vaddr >= platform.window_end {
+        return Err(format!(
+            "the image is mapped at {:#x}, past the end of its window at {:#x}",
+            platform.load_vaddr, platform.window_end
+        ));
+    }
+    // The kernel window maps physical memory at a fixed offset, the image moves with it
+    if platform.load_vaddr % LARGE_PAGE_SIZE != platform.load_paddr % LARGE_PAGE_SIZE {
+        return Err(format!(
+            "{:#x} and {:#x} differ within a large page",
+            platform.load_paddr, platform.load_vaddr
+        ));
+    }
+    let stacks = cores << KERNEL_STACK_BITS;
+    if stacks >= region_size(platform) {
+        return Err(format!(
+            "the kernel stacks of {} cores take {:#x} bytes, the kernel region has {:#x}",
+            cores,
+            stacks,
+            region_size(platform)
+        ));
+    }
+    Ok(())
+}
+
+/// The constants `src/config.rs` includes
+fn platform_consts(platform: &platform_t, cores: usize) -> String {
+    format!(
+        "/// The platform the kernel is built for, see `layout.rs`\n\
+         pub const PLATFORM_NAME: &str = \"{}\";\n\
+         pub const PLATFORM_LOAD_PADDR: usize = {:#x};\n\
+         pub const PLATFORM_LOAD_VADDR: usize = {:#x};\n\
+         /// The bytes the kernel image may take from the load address\n\
+         pub const PLATFORM_KERNEL_REGION_SIZE: usize = {:#x};\n\
+         /// Boot untypeds are split to this size at most\n\
+         pub const PLATFORM_MAX_BOOT_UNTYPED_BITS: usize = {};\n\
+         /// The cores and the kernel stack size the layout was checked with\n\
+         pub const PLATFORM_CHECKED_NUM_NODES: usize = {};\n\
+         pub const PLATFORM_CHECKED_STACK_BITS: usize = {};\n",
+        platform.name,
+        platform.load_paddr,
+        platform.load_vaddr,
+        region_size(platform),
+        platform.max_boot_untyped_bits,
+        cores,
+        KERNEL_STACK_BITS
+    )
+}
+
+/// The linker script of the kernel image, laid out like that of seL4: the boot code and data
+/// first, then the kernel proper, each section loaded at its physical address.
+fn linker_script(platform: &platform_t) -> String {
+    let mut script = String::new();
+    let output_arch = match platform.arch {
+        "riscv64" => "riscv",
+        _ => "aarch64",
+    };
+    let _ = write!(
+        script,
+        "/* Generated by build.rs for `{}`, see layout.rs */\n\
+         OUTPUT_ARCH({})\n\
+         ENTRY(_start)\n\
+         \n\
+         KERNEL_ELF_PADDR_BASE = {:#x};\n\
+         KERNEL_ELF_BASE = {:#x};\n\
+         KERNEL_OFFSET = KERNEL_ELF_BASE - KERNEL_ELF_PADDR_BASE;\n\
+         KERNEL_REGION_END = KERNEL_ELF_BASE + {:#x};\n\
+         \n\
+         SECTIONS\n\
+         {{\n\
+         \x20   . = KERNEL_ELF_BASE;\n",
+        platform.name,
+        output_arch,
+        platform.load_paddr,
+        platform.load_vaddr,
+        region_size(platform)
+    );
+    let sections: [(&str, &str); 9] = [
+        (".boot.text", "*(.boot.text)"),
+        (".boot.rodata", "*(.boot.rodata)"),
+        (".boot.data", "*(.boot.data)"),
+        (".boot.bss", "*(.boot.bss)"),
+        (".text", "*(.text.start) *(.text .text.*)"),
+        (".rodata", "*(.srodata .srodata.*) *(.rodata .rodata.*)"),
+        (".rel4_version", "KEEP(*(.rel4_version))"),
+        (".data", "*(.sdata .sdata.*) *(.data .data.*)"),
+        (".bss", "*(.sbss .sbss.*) *(.bss .bss.*) *(COMMON)"),
+    ];
+    for (name, inputs) in sections {
+        if name == ".text" {
+            script.push_str("    ki_boot_end = .;\n");
+        }
+        if name == ".data" && platform.arch == "riscv64" {
+            script.push_str("    __global_pointer$ = . + 0x800;\n");
+        }
+        let _ = writeln!(
+            script,
+            "    {name} . : AT(ADDR({name}) - KERNEL_OFFSET)\n    {{\n        \
+             . = ALIGN({align});\n        {inputs}\n    }}",
+            name = name,
+            align = if name == ".text" { PAGE_SIZE } else { 8 },
+            inputs = inputs
+        );
+    }
+    script.push_str(
+        "    . = ALIGN(4K);\n\
+         \x20   ki_end = .;\n\
+         \n\
+         \x20   /DISCARD/ :\n\
+         \x20   {\n\
+         \x20       *(.note.gnu.build-id) *(.comment) *(.eh_frame*)\n\
+         \x20   }\n\
+         }\n\
+         \n\
+         ASSERT(ki_end <= KERNEL_REGION_END,\n\
+         \x20      \"rel4: the kernel image does not fit the kernel region of the platform\")\n",
+    );
+    script
+}
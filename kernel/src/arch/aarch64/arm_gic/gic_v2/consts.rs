@@ -2,7 +2,12 @@
 pub const GIC_V2_PPTR: usize = 0xffffffffffe00000;
 pub const GIC_V2_DISTRIBUTOR_PPTR: usize = GIC_V2_PPTR + 0x1000;
 pub const GIC_V2_CONTROLLER_PPTR: usize = GIC_V2_PPTR + 0x2000;
+/// The virtual interface control registers (GICH), mapped with the other GIC frames by
+/// `map_kernel_devices` when the platform has them
+#[cfg(feature = "ENABLE_ARM_HYP")]
+pub const GIC_V2_VCPUCTRL_PPTR: usize = GIC_V2_PPTR + 0x3000;
 
 pub const IRQ_SET_ALL: u32 = 0xffffffff;
 pub const IRQ_MASK: u32 = (1 << (10)) - 1;
 pub const IRQ_NONE: u32 = 1023;
+pub const SPI_START: usize = 32;
@@ -1,10 +1,11 @@
 use super::consts::*;
-use super::{Gic_Cpu_Iface_Map, Gic_Dist_Map};
+use super::{Gic_Cpu_Iface_Map_Regs, Gic_Dist_Map_Regs};
 use aarch64_cpu::registers::Readable;
+use sel4_common::mmio::mmio_t;
 use tock_registers::interfaces::Writeable;
 
-static GIC_DIST: Gic_Dist_Map = Gic_Dist_Map::new(GIC_V2_DISTRIBUTOR_PPTR as *mut u8);
-static GIC_CPUIFACE: Gic_Cpu_Iface_Map = Gic_Cpu_Iface_Map::new(GIC_V2_CONTROLLER_PPTR as *mut u8);
+static GIC_DIST: mmio_t<Gic_Dist_Map_Regs> = mmio_t::new(GIC_V2_DISTRIBUTOR_PPTR);
+static GIC_CPUIFACE: mmio_t<Gic_Cpu_Iface_Map_Regs> = mmio_t::new(GIC_V2_CONTROLLER_PPTR);
 // This is for aarch64 only
 pub fn cpu_iface_init() {
     GIC_DIST.regs().enable_clr[0].set(IRQ_SET_ALL);
@@ -40,6 +41,13 @@ pub fn irq_enable(irq: usize) {
     GIC_DIST.regs().enable_set[word].set(1 << bits);
 }
 
+/// Disable the IRQ at the distributor, it stays pending if it was
+pub fn irq_disable(irq: usize) {
+    let word = irq >> 5;
+    let bits = (irq & 0x1f) as u32;
+    GIC_DIST.regs().enable_clr[word].set(1 << bits);
+}
+
 /// Get the current interrupt number
 pub fn gic_int_ack() -> usize {
     GIC_CPUIFACE.regs().int_ack.get() as usize
@@ -49,3 +57,35 @@ pub fn gic_int_ack() -> usize {
 pub fn ack_irq(irq: usize) {
     GIC_CPUIFACE.regs().eoi.set(irq as _);
 }
+
+/// Make `irq` edge triggered, or level triggered for false.
+///
+/// Each `GICD_ICFGR` word holds the configuration of sixteen interrupts, two bits each, of which
+/// the upper one selects edge triggering. The lower one is implementation defined and kept.
+pub fn irq_set_trigger(irq: usize, edge: bool) {
+    let word = irq >> 4;
+    let mask = 0b10 << ((irq & 0xf) * 2);
+    let config = GIC_DIST.regs().config[word].get();
+    GIC_DIST.regs().config[word].set(if edge { config | mask } else { config & !mask });
+}
+
+/// Raise the software generated interrupt `irq` on the CPU interfaces in `mask`.
+pub fn send_sgi(irq: usize, mask: usize) {
+    unsafe {
+        core::arch::asm!("dsb sy");
+    }
+    GIC_DIST
+        .regs()
+        .sgi_control
+        .set((((mask & 0xff) << 16) | (irq & 0xf)) as u32);
+}
+
+/// Route a shared peripheral interrupt to the CPU interface of `core`.
+///
+/// Each `GICD_ITARGETSR` word holds the target mask of four interrupts, one byte each.
+pub fn irq_set_target_core(irq: usize, core: usize) {
+    let word = irq >> 2;
+    let shift = (irq & 0x3) * 8;
+    let targets = GIC_DIST.regs().targets[word].get() & !(0xff << shift);
+    GIC_DIST.regs().targets[word].set(targets | ((1 << core) << shift));
+}
@@ -1 +1,43 @@
+//! The GICv3 frames are mapped by `map_kernel_devices` like those of the GICv2, at the offsets
+//! of the qemu-arm-virt board. The redistributors follow each other, one pair of frames per core.
+pub const GIC_V3_PPTR: usize = 0xffffffffffe00000;
+pub const GIC_V3_DISTRIBUTOR_PPTR: usize = GIC_V3_PPTR + 0x1000;
+pub const GIC_V3_REDISTRIBUTOR_PPTR: usize = GIC_V3_PPTR + 0x11000;
+/// The size of the frames of a redistributor, the control frame and the SGI and PPI frame
+pub const GICR_STRIDE: usize = 0x20000;
+/// The control frame of the ITS, mapped after the redistributors of all cores
+#[cfg(feature = "ENABLE_GIC_ITS")]
+pub const GIC_V3_ITS_PPTR: usize =
+    GIC_V3_REDISTRIBUTOR_PPTR + sel4_common::sel4_config::CONFIG_MAX_NUM_NODES * GICR_STRIDE;
 
+pub const IRQ_SET_ALL: u32 = 0xffffffff;
+pub const IRQ_MASK: u32 = (1 << 24) - 1;
+pub const IRQ_NONE: u32 = 1023;
+pub const SPI_START: usize = 32;
+
+/// The priority of every interrupt, above the priority mask of the CPU interface
+pub const IRQ_PRIORITY: u32 = 0xa0;
+pub const IRQ_PRIORITY_ALL: u32 = IRQ_PRIORITY * 0x01010101;
+/// The priority mask of the CPU interface, every interrupt above the lowest priority is signalled
+pub const DEFAULT_PMR_VALUE: usize = 0xff;
+
+/// `GICD_CTLR`: group 1 interrupts are forwarded, affinity routing is on, a write is pending
+pub const GICD_CTLR_ENABLE_G1NS: u32 = 1 << 1;
+pub const GICD_CTLR_ARE_NS: u32 = 1 << 4;
+pub const GICD_CTLR_RWP: u32 = 1 << 31;
+/// `GICR_CTLR`: a write to `GICR_ICENABLER0` is pending
+pub const GICR_CTLR_RWP: u32 = 1 << 3;
+/// `GICR_WAKER`: the core is asleep, and so is its redistributor interface
+pub const GICR_WAKER_PROCESSOR_SLEEP: u32 = 1 << 1;
+pub const GICR_WAKER_CHILDREN_ASLEEP: u32 = 1 << 2;
+/// `GICR_TYPER`: the last redistributor, and where its affinity is
+pub const GICR_TYPER_LAST: u64 = 1 << 4;
+pub const GICR_TYPER_AFFINITY_SHIFT: usize = 32;
+/// `GICR_TYPER`: the redistributor supports LPIs, and its processor number
+pub const GICR_TYPER_PLPIS: u64 = 1 << 0;
+pub const GICR_TYPER_PROC_NUM_SHIFT: usize = 8;
+/// `GICR_CTLR`: LPIs are enabled
+pub const GICR_CTLR_ENABLE_LPIS: u32 = 1 << 0;
+
+/// The first LPI
+pub const LPI_INTID_BASE: usize = 8192;
@@ -1 +1,270 @@
+//! The GICv3 driver, used instead of the GICv2 one with the `ENABLE_GIC_V3` feature.
+//!
+//! The distributor routes shared peripheral interrupts by affinity, so `GICD_IROUTER` takes the
+//! MPIDR affinity of a core instead of a mask of CPU interfaces. SGIs and PPIs are configured in
+//! the redistributor of each core, and the CPU interface is reached through the ICC system
+//! registers. Every interrupt is a non-secure group 1 interrupt of the same priority, so they
+//! never preempt each other.
 
+use super::consts::*;
+use super::{Gic_V3_Dist_Map_Regs, Gic_V3_Rdist_Map_Regs};
+#[cfg(feature = "ENABLE_GIC_ITS")]
+use crate::config::{maxIRQ, LPI_IRQ_BASE};
+use core::arch::asm;
+use sel4_common::arch::sysreg::{
+    icc_bpr1_el1, icc_ctlr_el1, icc_eoir1_el1, icc_iar1_el1, icc_igrpen1_el1, icc_pmr_el1,
+    icc_sgi1r_el1, icc_sre_el1, mpidr_el1,
+};
+use sel4_common::mmio::mmio_t;
+use sel4_common::sel4_config::CONFIG_MAX_NUM_NODES;
+use sel4_common::utils::cpu_id;
+use tock_registers::interfaces::{Readable, Writeable};
+
+static GIC_DIST: mmio_t<Gic_V3_Dist_Map_Regs> = mmio_t::new(GIC_V3_DISTRIBUTOR_PPTR);
+
+/// The redistributor frames of each core, found by [`gicr_init`]
+static mut gic_rdist: [usize; CONFIG_MAX_NUM_NODES] = [0; CONFIG_MAX_NUM_NODES];
+/// The MPIDR affinity of each core, in the layout of `GICD_IROUTER`
+static mut gic_core_affinity: [usize; CONFIG_MAX_NUM_NODES] = [0; CONFIG_MAX_NUM_NODES];
+
+/// The redistributor of the current core
+#[inline]
+fn rdist() -> &'static Gic_V3_Rdist_Map_Regs {
+    unsafe { &*(gic_rdist[cpu_id()] as *const Gic_V3_Rdist_Map_Regs) }
+}
+
+/// The affinity fields of `MPIDR_EL1`, in place
+#[inline]
+fn mpidr_affinity() -> usize {
+    mpidr_el1::read()
+        & (mpidr_el1::AFF0.mask()
+            | mpidr_el1::AFF1.mask()
+            | mpidr_el1::AFF2.mask()
+            | mpidr_el1::AFF3.mask())
+}
+
+fn wait_dist_rwp() {
+    while GIC_DIST.regs().ctlr.get() & GICD_CTLR_RWP != 0 {}
+}
+
+fn wait_rdist_rwp() {
+    while rdist().ctlr.get() & GICR_CTLR_RWP != 0 {}
+}
+
+/// Make every SPI a disabled, level triggered group 1 interrupt routed to the current core and
+/// enable the distributor with affinity routing. Run once, on the boot core.
+fn dist_init() {
+    let regs = GIC_DIST.regs();
+    regs.ctlr.set(0);
+    wait_dist_rwp();
+
+    // INTIDs from 1020 on are special
+    let nr_lines = (32 * ((regs.typer.get() & 0x1f) as usize + 1)).min(1020);
+    for word in SPI_START / 32..nr_lines / 32 {
+        regs.icenabler[word].set(IRQ_SET_ALL);
+        regs.icpendr[word].set(IRQ_SET_ALL);
+        regs.igroupr[word].set(IRQ_SET_ALL);
+    }
+    for word in SPI_START / 16..nr_lines / 16 {
+        regs.icfgr[word].set(0);
+    }
+    for word in SPI_START / 4..nr_lines / 4 {
+        regs.ipriorityr[word].set(IRQ_PRIORITY_ALL);
+    }
+    wait_dist_rwp();
+
+    regs.ctlr.set(GICD_CTLR_ARE_NS | GICD_CTLR_ENABLE_G1NS);
+    wait_dist_rwp();
+
+    let affinity = mpidr_affinity() as u64;
+    for irq in SPI_START..nr_lines {
+        regs.irouter[irq].set(affinity);
+    }
+}
+
+/// Find the redistributor of the current core, wake it and make its SGIs and PPIs disabled
+/// group 1 interrupts.
+fn gicr_init() {
+    let mpidr = mpidr_el1::read();
+    // `GICR_TYPER` packs the affinity without the gap below `AFF3`
+    let affinity = (mpidr_el1::AFF3.get(mpidr) << 24)
+        | (mpidr_el1::AFF2.get(mpidr) << 16)
+        | (mpidr_el1::AFF1.get(mpidr) << 8)
+        | mpidr_el1::AFF0.get(mpidr);
+    let mut found = None;
+    for i in 0..CONFIG_MAX_NUM_NODES {
+        let base = GIC_V3_REDISTRIBUTOR_PPTR + i * GICR_STRIDE;
+        let typer = unsafe { &*(base as *const Gic_V3_Rdist_Map_Regs) }
+            .typer
+            .get();
+        if (typer >> GICR_TYPER_AFFINITY_SHIFT) as usize == affinity {
+            found = Some(base);
+            break;
+        }
+        if typer & GICR_TYPER_LAST != 0 {
+            break;
+        }
+    }
+    let Some(base) = found else {
+        panic!("GICv3: no redistributor for MPIDR {:#x}", mpidr);
+    };
+    unsafe {
+        gic_rdist[cpu_id()] = base;
+    }
+
+    let regs = rdist();
+    regs.waker
+        .set(regs.waker.get() & !GICR_WAKER_PROCESSOR_SLEEP);
+    while regs.waker.get() & GICR_WAKER_CHILDREN_ASLEEP != 0 {}
+
+    regs.icenabler0.set(IRQ_SET_ALL);
+    regs.icpendr0.set(IRQ_SET_ALL);
+    wait_rdist_rwp();
+    regs.igroupr0.set(IRQ_SET_ALL);
+    for word in 0..SPI_START / 4 {
+        regs.ipriorityr[word].set(IRQ_PRIORITY_ALL);
+    }
+}
+
+/// Switch the CPU interface of the current core to the system registers and let it signal every
+/// group 1 interrupt.
+fn cpu_iface_init() {
+    unsafe {
+        icc_sre_el1::write(icc_sre_el1::SRE.set(icc_sre_el1::read(), 1));
+        asm!("isb");
+        icc_pmr_el1::write(DEFAULT_PMR_VALUE);
+        // All priority bits are group priority
+        icc_bpr1_el1::write(0);
+        // The end of interrupt deactivates the interrupt as well
+        icc_ctlr_el1::write(icc_ctlr_el1::EOIMODE.set(icc_ctlr_el1::read(), 0));
+    }
+    let mut irq = icc_iar1_el1::INTID.get(icc_iar1_el1::read());
+    while irq != IRQ_NONE as usize {
+        unsafe {
+            icc_eoir1_el1::write(irq);
+        }
+        irq = icc_iar1_el1::INTID.get(icc_iar1_el1::read());
+    }
+    unsafe {
+        icc_igrpen1_el1::write(icc_igrpen1_el1::ENABLE.val(1));
+        asm!("isb");
+    }
+}
+
+pub fn cpu_initLocalIRQController() {
+    unsafe {
+        gic_core_affinity[cpu_id()] = mpidr_affinity();
+    }
+    if cpu_id() == 0 {
+        dist_init();
+    }
+    gicr_init();
+    #[cfg(feature = "ENABLE_GIC_ITS")]
+    if cpu_id() == 0 {
+        super::its::its_init(rdist());
+    }
+    cpu_iface_init();
+}
+
+/// Enable the IRQ, SGIs and PPIs in the redistributor of the current core
+pub fn irq_enable(irq: usize) {
+    #[cfg(feature = "ENABLE_GIC_ITS")]
+    if irq >= LPI_IRQ_BASE {
+        super::its::its_set_lpi_enabled(irq, true);
+        return;
+    }
+    let bit = 1 << (irq & 0x1f);
+    match irq < SPI_START {
+        true => rdist().isenabler0.set(bit),
+        false => GIC_DIST.regs().isenabler[irq >> 5].set(bit),
+    }
+}
+
+/// Disable the IRQ, it stays pending if it was
+pub fn irq_disable(irq: usize) {
+    #[cfg(feature = "ENABLE_GIC_ITS")]
+    if irq >= LPI_IRQ_BASE {
+        super::its::its_set_lpi_enabled(irq, false);
+        return;
+    }
+    let bit = 1 << (irq & 0x1f);
+    match irq < SPI_START {
+        true => {
+            rdist().icenabler0.set(bit);
+            wait_rdist_rwp();
+        }
+        false => {
+            GIC_DIST.regs().icenabler[irq >> 5].set(bit);
+            wait_dist_rwp();
+        }
+    }
+}
+
+/// Get the current interrupt number, LPIs are turned into their kernel IRQs
+pub fn gic_int_ack() -> usize {
+    let intid = icc_iar1_el1::INTID.get(icc_iar1_el1::read());
+    #[cfg(feature = "ENABLE_GIC_ITS")]
+    if intid >= LPI_INTID_BASE {
+        return intid - LPI_INTID_BASE + LPI_IRQ_BASE;
+    } else if intid > maxIRQ {
+        // Would read as an LPI, the kernel never enables these
+        return IRQ_NONE as usize;
+    }
+    intid
+}
+
+/// Acknowledge the interrupt
+pub fn ack_irq(irq: usize) {
+    #[cfg(feature = "ENABLE_GIC_ITS")]
+    let irq = match irq >= LPI_IRQ_BASE {
+        true => irq - LPI_IRQ_BASE + LPI_INTID_BASE,
+        false => irq,
+    };
+    unsafe {
+        icc_eoir1_el1::write(irq);
+    }
+}
+
+/// Make `irq` edge triggered, or level triggered for false.
+///
+/// The configuration of PPIs is in the redistributor of the current core, that of SGIs is fixed.
+pub fn irq_set_trigger(irq: usize, edge: bool) {
+    let word = irq >> 4;
+    let mask = 0b10 << ((irq & 0xf) * 2);
+    let config = match irq < SPI_START {
+        true => &rdist().icfgr[word],
+        false => &GIC_DIST.regs().icfgr[word],
+    };
+    let value = config.get();
+    config.set(if edge { value | mask } else { value & !mask });
+}
+
+/// Raise the software generated interrupt `irq` on the cores in `mask`.
+///
+/// `ICC_SGI1R_EL1` addresses the cores of one cluster at a time, so there is a write per core.
+pub fn send_sgi(irq: usize, mask: usize) {
+    unsafe {
+        asm!("dsb sy");
+    }
+    for core in (0..CONFIG_MAX_NUM_NODES).filter(|core| mask & (1 << core) != 0) {
+        let affinity = unsafe { gic_core_affinity[core] };
+        let aff0 = mpidr_el1::AFF0.get(affinity);
+        let sgi = icc_sgi1r_el1::TARGET_LIST.val(1 << (aff0 & 0xf))
+            | icc_sgi1r_el1::RS.val(aff0 >> 4)
+            | icc_sgi1r_el1::AFF1.val(mpidr_el1::AFF1.get(affinity))
+            | icc_sgi1r_el1::AFF2.val(mpidr_el1::AFF2.get(affinity))
+            | icc_sgi1r_el1::AFF3.val(mpidr_el1::AFF3.get(affinity))
+            | icc_sgi1r_el1::INTID.val(irq);
+        unsafe {
+            icc_sgi1r_el1::write(sgi);
+        }
+    }
+    unsafe {
+        asm!("isb");
+    }
+}
+
+/// Route a shared peripheral interrupt to `core`, which must have initialised its CPU interface.
+pub fn irq_set_target_core(irq: usize, core: usize) {
+    GIC_DIST.regs().irouter[irq].set(unsafe { gic_core_affinity[core] } as u64);
+}
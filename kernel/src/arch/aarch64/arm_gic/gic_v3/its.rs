@@ -0,0 +1,360 @@
+//! The GICv3 Interrupt Translation Service, enabled by the `ENABLE_GIC_ITS` feature.
+//!
+//! A PCIe device signals an MSI by writing its event ID to `GITS_TRANSLATER`, and the ITS
+//! translates the pair of its device ID and the event ID to an LPI. `ARMIRQIssueIRQHandlerMSI`
+//! maps such a pair to one of the `CONFIG_MAX_NUM_LPIS` LPIs and issues a handler cap for it, the
+//! LPI is then delivered, masked and acknowledged like any other IRQ. LPI `n` is INTID
+//! `LPI_INTID_BASE + n` and kernel IRQ `LPI_IRQ_BASE + n`.
+//!
+//! All LPIs are delivered to the boot core. The tables of the ITS are static, which bounds the
+//! devices to `CONFIG_MAX_NUM_ITS_DEVICES` with `BIT!(ITS_DEVICE_EVENT_BITS)` events each. The ITS
+//! has to name redistributors by processor number (`GITS_TYPER.PTA` clear), the kernel does not
+//! know their physical addresses.
+
+use super::consts::*;
+use super::{Gic_V3_Its_Map_Regs, Gic_V3_Rdist_Map_Regs};
+use crate::config::{CONFIG_MAX_NUM_ITS_DEVICES, CONFIG_MAX_NUM_LPIS, LPI_IRQ_BASE};
+use core::mem::size_of;
+use core::ptr::{addr_of, addr_of_mut};
+use log::warn;
+use sel4_common::mmio::mmio_t;
+use sel4_common::BIT;
+use sel4_vspace::{clean_invalidate_d_range, kpptr_to_paddr};
+use tock_registers::interfaces::{Readable, Writeable};
+
+static GIC_ITS: mmio_t<Gic_V3_Its_Map_Regs> = mmio_t::new(GIC_V3_ITS_PPTR);
+
+/// The LPIs are the INTIDs from `LPI_INTID_BASE` below `BIT!(LPI_ID_BITS)`
+const LPI_ID_BITS: usize = 14;
+/// The events of a device are below `BIT!(ITS_DEVICE_EVENT_BITS)`
+pub const ITS_DEVICE_EVENT_BITS: usize = 5;
+/// The size of the device and of the collection table, one page of the largest size
+const ITS_TABLE_SIZE: usize = 0x10000;
+/// The largest ITT entry `GITS_TYPER` can report
+const ITS_ITT_ENTRY_MAX: usize = 16;
+const ITS_CMD_QUEUE_LEN: usize = 128;
+
+const _: () = assert!(LPI_INTID_BASE + CONFIG_MAX_NUM_LPIS <= BIT!(LPI_ID_BITS));
+
+/// `GITS_TYPER`: the size of the ITT entries, the device ID bits, redistributors by address
+const GITS_TYPER_ITT_ENTRY_SIZE_SHIFT: usize = 4;
+const GITS_TYPER_DEVBITS_SHIFT: usize = 13;
+const GITS_TYPER_PTA: u64 = 1 << 19;
+const GITS_CTLR_ENABLED: u32 = 1 << 0;
+/// `GITS_BASER<n>` and `GITS_CBASER`: the table is valid, its cacheability and shareability
+const GITS_BASER_VALID: u64 = 1 << 63;
+const GITS_BASER_INNER_WB: u64 = 0b111 << 59;
+const GITS_BASER_INNER_SHAREABLE: u64 = 0b01 << 10;
+/// `GITS_BASER<n>`: what the table holds, the size of its entries and of its pages
+const GITS_BASER_TYPE_SHIFT: usize = 56;
+const GITS_BASER_TYPE_DEVICE: u64 = 1;
+const GITS_BASER_TYPE_COLLECTION: u64 = 4;
+const GITS_BASER_ENTRY_SIZE_SHIFT: usize = 48;
+const GITS_BASER_PAGE_SIZE_SHIFT: usize = 8;
+/// `GICR_PROPBASER` and `GICR_PENDBASER`: cacheability and shareability of the tables
+const GICR_BASER_INNER_WB: u64 = 0b111 << 7;
+const GICR_BASER_INNER_SHAREABLE: u64 = 0b01 << 10;
+/// `GICR_PENDBASER`: the pending table is zeroed
+const GICR_PENDBASER_PTZ: u64 = 1 << 62;
+/// An LPI configuration byte: the priority, a reserved bit which is one, the LPI is enabled
+const LPI_PROP_RES1: u8 = 1 << 1;
+const LPI_PROP_ENABLE: u8 = 1 << 0;
+
+/// The ITS commands the kernel issues
+const GITS_CMD_SYNC: u64 = 0x05;
+const GITS_CMD_MAPD: u64 = 0x08;
+const GITS_CMD_MAPC: u64 = 0x09;
+const GITS_CMD_MAPTI: u64 = 0x0a;
+const GITS_CMD_INV: u64 = 0x0c;
+const GITS_CMD_DISCARD: u64 = 0x0f;
+const GITS_CMD_VALID: u64 = 1 << 63;
+/// The collection of all LPIs
+const ITS_COLLECTION: u64 = 0;
+
+/// The LPI configuration table, a byte per LPI
+#[repr(C, align(4096))]
+struct lpi_prop_table_t([u8; BIT!(LPI_ID_BITS) - LPI_INTID_BASE]);
+/// The LPI pending table of the boot core, a bit per INTID
+#[repr(C, align(65536))]
+struct lpi_pend_table_t([u8; BIT!(LPI_ID_BITS) / 8]);
+/// The device or the collection table of the ITS
+#[repr(C, align(65536))]
+struct its_table_t([u8; ITS_TABLE_SIZE]);
+#[repr(C, align(4096))]
+struct its_cmd_queue_t([[u64; 4]; ITS_CMD_QUEUE_LEN]);
+/// The interrupt translation table of a device
+#[repr(C, align(256))]
+struct its_itt_t([u8; BIT!(ITS_DEVICE_EVENT_BITS) * ITS_ITT_ENTRY_MAX]);
+
+const EMPTY_ITT: its_itt_t = its_itt_t([0; BIT!(ITS_DEVICE_EVENT_BITS) * ITS_ITT_ENTRY_MAX]);
+
+static mut its_prop_table: lpi_prop_table_t =
+    lpi_prop_table_t([0; BIT!(LPI_ID_BITS) - LPI_INTID_BASE]);
+static mut its_pend_table: lpi_pend_table_t = lpi_pend_table_t([0; BIT!(LPI_ID_BITS) / 8]);
+static mut its_device_table: its_table_t = its_table_t([0; ITS_TABLE_SIZE]);
+static mut its_collection_table: its_table_t = its_table_t([0; ITS_TABLE_SIZE]);
+static mut its_cmd_queue: its_cmd_queue_t = its_cmd_queue_t([[0; 4]; ITS_CMD_QUEUE_LEN]);
+static mut its_itts: [its_itt_t; CONFIG_MAX_NUM_ITS_DEVICES] =
+    [EMPTY_ITT; CONFIG_MAX_NUM_ITS_DEVICES];
+
+#[derive(Clone, Copy)]
+struct its_device_t {
+    /// The device ID, valid while `events` is not 0
+    id: usize,
+    /// The number of events mapped to LPIs, the slot is free at 0
+    events: usize,
+}
+
+struct its_state_t {
+    /// The ITS is set up, no LPI can be mapped otherwise
+    ready: bool,
+    /// The device IDs below are supported
+    max_devices: usize,
+    /// The redistributor of the boot core, by processor number
+    rdbase: u64,
+    /// The command queue entry the next command goes to
+    cmd_write: usize,
+    devices: [its_device_t; CONFIG_MAX_NUM_ITS_DEVICES],
+    /// The device slot and the event each LPI is mapped to
+    lpis: [Option<(usize, usize)>; CONFIG_MAX_NUM_LPIS],
+}
+
+static mut ksITS: its_state_t = its_state_t {
+    ready: false,
+    max_devices: 0,
+    rdbase: 0,
+    cmd_write: 0,
+    devices: [its_device_t { id: 0, events: 0 }; CONFIG_MAX_NUM_ITS_DEVICES],
+    lpis: [None; CONFIG_MAX_NUM_LPIS],
+};
+
+#[inline]
+fn its() -> &'static mut its_state_t {
+    unsafe { &mut *addr_of_mut!(ksITS) }
+}
+
+/// Clean the data cache lines of the `size` bytes at `addr`, the ITS may not snoop them.
+#[inline]
+fn its_flush<T>(addr: *const T, size: usize) {
+    clean_invalidate_d_range(addr as usize, addr as usize + size);
+}
+
+/// Queue `cmd` and wait until the ITS has consumed it.
+fn its_send(cmd: [u64; 4]) {
+    let its = its();
+    let queue = unsafe { &mut (*addr_of_mut!(its_cmd_queue)).0 };
+    queue[its.cmd_write] = cmd;
+    its_flush(&queue[its.cmd_write], size_of::<[u64; 4]>());
+    its.cmd_write = (its.cmd_write + 1) % ITS_CMD_QUEUE_LEN;
+    let offset = (its.cmd_write * size_of::<[u64; 4]>()) as u64;
+    GIC_ITS.regs().cwriter.set(offset);
+    // The low bits of `GITS_CREADR` flag a stalled queue
+    while GIC_ITS.regs().creadr.get() & !0x1f != offset {}
+}
+
+/// Wait until the effects of the earlier commands are visible at the boot core.
+fn its_sync() {
+    its_send([GITS_CMD_SYNC, 0, its().rdbase << 16, 0]);
+}
+
+/// Point the table register `n` of the ITS at a static table if it holds devices or collections.
+///
+/// # Returns
+///
+/// The entry size of a device table, 0 for other tables.
+fn its_baser_init(n: usize) -> usize {
+    let baser = &GIC_ITS.regs().baser[n];
+    let value = baser.get();
+    let kind = (value >> GITS_BASER_TYPE_SHIFT) & 0x7;
+    let table = match kind {
+        GITS_BASER_TYPE_DEVICE => unsafe { addr_of!(its_device_table) as usize },
+        GITS_BASER_TYPE_COLLECTION => unsafe { addr_of!(its_collection_table) as usize },
+        _ => return 0,
+    };
+    its_flush(table as *const u8, ITS_TABLE_SIZE);
+    let base = kpptr_to_paddr(table) as u64
+        | GITS_BASER_VALID
+        | GITS_BASER_INNER_WB
+        | GITS_BASER_INNER_SHAREABLE;
+    // Ask for 64KiB pages, the page size may be fixed to a smaller one
+    baser.set(base | (0b10 << GITS_BASER_PAGE_SIZE_SHIFT));
+    let page_bits = ((baser.get() >> GITS_BASER_PAGE_SIZE_SHIFT) & 0x3).min(0b10);
+    let pages = ITS_TABLE_SIZE / [0x1000, 0x4000, 0x10000][page_bits as usize];
+    baser.set(base | (page_bits << GITS_BASER_PAGE_SIZE_SHIFT) | (pages - 1) as u64);
+    match kind {
+        GITS_BASER_TYPE_DEVICE => ((value >> GITS_BASER_ENTRY_SIZE_SHIFT) & 0x1f) as usize + 1,
+        _ => 0,
+    }
+}
+
+/// Set up the ITS and the LPIs of the boot core, whose redistributor is `rdist`. Without LPI
+/// support in the redistributor or an ITS the kernel can drive, no LPI can be mapped.
+pub fn its_init(rdist: &Gic_V3_Rdist_Map_Regs) {
+    let rd_typer = rdist.typer.get();
+    let typer = GIC_ITS.regs().typer.get();
+    if rd_typer & GICR_TYPER_PLPIS == 0 {
+        warn!("GICv3: the redistributor does not support LPIs");
+        return;
+    }
+    if typer & GITS_TYPER_PTA != 0 {
+        warn!("GICv3 ITS: redistributors named by address are not supported");
+        return;
+    }
+    let itt_entry_size = ((typer >> GITS_TYPER_ITT_ENTRY_SIZE_SHIFT) & 0xf) as usize + 1;
+    assert!(itt_entry_size <= ITS_ITT_ENTRY_MAX);
+
+    let device_entry_size = (0..8).map(its_baser_init).max().unwrap_or(0);
+    let dev_bits = ((typer >> GITS_TYPER_DEVBITS_SHIFT) & 0x1f) as usize + 1;
+    its().max_devices = match device_entry_size {
+        0 => BIT!(dev_bits),
+        size => (ITS_TABLE_SIZE / size).min(BIT!(dev_bits)),
+    };
+
+    unsafe {
+        its_flush(
+            addr_of!(its_itts),
+            size_of::<[its_itt_t; CONFIG_MAX_NUM_ITS_DEVICES]>(),
+        );
+        its_flush(addr_of!(its_cmd_queue), size_of::<its_cmd_queue_t>());
+        GIC_ITS.regs().cbaser.set(
+            kpptr_to_paddr(addr_of!(its_cmd_queue) as usize) as u64
+                | GITS_BASER_VALID
+                | GITS_BASER_INNER_WB
+                | GITS_BASER_INNER_SHAREABLE,
+        );
+    }
+    GIC_ITS.regs().cwriter.set(0);
+    GIC_ITS
+        .regs()
+        .ctlr
+        .set(GIC_ITS.regs().ctlr.get() | GITS_CTLR_ENABLED);
+
+    // Every LPI starts disabled
+    unsafe {
+        (*addr_of_mut!(its_prop_table))
+            .0
+            .fill(IRQ_PRIORITY as u8 | LPI_PROP_RES1);
+        its_flush(addr_of!(its_prop_table), size_of::<lpi_prop_table_t>());
+        its_flush(addr_of!(its_pend_table), size_of::<lpi_pend_table_t>());
+        rdist.propbaser.set(
+            kpptr_to_paddr(addr_of!(its_prop_table) as usize) as u64
+                | GICR_BASER_INNER_WB
+                | GICR_BASER_INNER_SHAREABLE
+                | (LPI_ID_BITS - 1) as u64,
+        );
+        rdist.pendbaser.set(
+            kpptr_to_paddr(addr_of!(its_pend_table) as usize) as u64
+                | GICR_BASER_INNER_WB
+                | GICR_BASER_INNER_SHAREABLE
+                | GICR_PENDBASER_PTZ,
+        );
+    }
+    rdist.ctlr.set(rdist.ctlr.get() | GICR_CTLR_ENABLE_LPIS);
+
+    its().rdbase = (rd_typer >> GICR_TYPER_PROC_NUM_SHIFT) & 0xffff;
+    its_send([
+        GITS_CMD_MAPC,
+        0,
+        GITS_CMD_VALID | (its().rdbase << 16) | ITS_COLLECTION,
+        0,
+    ]);
+    its_sync();
+    its().ready = true;
+}
+
+/// Whether LPIs can be mapped.
+#[inline]
+pub fn its_ready() -> bool {
+    its().ready
+}
+
+/// The device IDs below are supported.
+#[inline]
+pub fn its_max_devices() -> usize {
+    its().max_devices
+}
+
+/// Whether the event `event` of the device `device` is mapped to an LPI.
+pub fn its_event_mapped(device: usize, event: usize) -> bool {
+    let its = its();
+    its.lpis
+        .iter()
+        .flatten()
+        .any(|&(slot, e)| its.devices[slot].id == device && e == event)
+}
+
+/// Whether an event of the device `device` can be mapped, it has mapped events already or there
+/// is a free device slot for it.
+pub fn its_device_available(device: usize) -> bool {
+    its()
+        .devices
+        .iter()
+        .any(|d| d.events == 0 || d.id == device)
+}
+
+/// Map the event `event` of the device `device` to the LPI of the kernel IRQ `irq`, which stays
+/// disabled. The caller checked the mapping with the functions above.
+pub fn its_map_lpi(irq: usize, device: usize, event: usize) {
+    let its = its();
+    let slot = match its
+        .devices
+        .iter()
+        .position(|d| d.events != 0 && d.id == device)
+    {
+        Some(slot) => slot,
+        None => {
+            let slot = its.devices.iter().position(|d| d.events == 0).unwrap();
+            its.devices[slot].id = device;
+            let itt = unsafe { addr_of!(its_itts[slot]) as usize };
+            its_send([
+                GITS_CMD_MAPD | ((device as u64) << 32),
+                (ITS_DEVICE_EVENT_BITS - 1) as u64,
+                GITS_CMD_VALID | kpptr_to_paddr(itt) as u64,
+                0,
+            ]);
+            slot
+        }
+    };
+    its.devices[slot].events += 1;
+    let lpi = irq - LPI_IRQ_BASE;
+    its_send([
+        GITS_CMD_MAPTI | ((device as u64) << 32),
+        event as u64 | (((LPI_INTID_BASE + lpi) as u64) << 32),
+        ITS_COLLECTION,
+        0,
+    ]);
+    its_sync();
+    its.lpis[lpi] = Some((slot, event));
+}
+
+/// Remove the mapping of the LPI of the kernel IRQ `irq`, and that of its device with its last
+/// event.
+pub fn its_unmap_lpi(irq: usize) {
+    let its = its();
+    let Some((slot, event)) = its.lpis[irq - LPI_IRQ_BASE].take() else {
+        return;
+    };
+    let device = its.devices[slot].id as u64;
+    its_send([GITS_CMD_DISCARD | (device << 32), event as u64, 0, 0]);
+    its.devices[slot].events -= 1;
+    if its.devices[slot].events == 0 {
+        its_send([GITS_CMD_MAPD | (device << 32), 0, 0, 0]);
+    }
+    its_sync();
+}
+
+/// Enable or disable the LPI of the kernel IRQ `irq`.
+pub fn its_set_lpi_enabled(irq: usize, enabled: bool) {
+    let lpi = irq - LPI_IRQ_BASE;
+    unsafe {
+        let prop = &mut (*addr_of_mut!(its_prop_table)).0[lpi];
+        *prop = IRQ_PRIORITY as u8 | LPI_PROP_RES1 | if enabled { LPI_PROP_ENABLE } else { 0 };
+        its_flush(prop, 1);
+    }
+    // The ITS caches the configuration of mapped LPIs
+    if let Some((slot, event)) = its().lpis[lpi] {
+        let device = its().devices[slot].id as u64;
+        its_send([GITS_CMD_INV | (device << 32), event as u64, 0, 0]);
+        its_sync();
+    }
+}
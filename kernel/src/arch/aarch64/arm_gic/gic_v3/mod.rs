@@ -1,2 +1,82 @@
 pub mod consts;
 pub mod gic_v3;
+#[cfg(feature = "ENABLE_GIC_ITS")]
+pub mod its;
+
+pub use gic_v3::{irq_disable, irq_enable};
+use tock_registers::register_structs;
+use tock_registers::registers::{ReadOnly, ReadWrite};
+
+register_structs! {
+    /// GICv3 Distributor registers.
+    #[allow(non_snake_case)]
+    pub Gic_V3_Dist_Map_Regs {
+        (0x0000 => ctlr: ReadWrite<u32>),
+        (0x0004 => typer: ReadOnly<u32>),
+        (0x0008 => iidr: ReadOnly<u32>),
+        (0x000c => _reserved_1),
+        (0x0080 => igroupr: [ReadWrite<u32>; 0x20]),
+        (0x0100 => isenabler: [ReadWrite<u32>; 0x20]),
+        (0x0180 => icenabler: [ReadWrite<u32>; 0x20]),
+        (0x0200 => ispendr: [ReadWrite<u32>; 0x20]),
+        (0x0280 => icpendr: [ReadWrite<u32>; 0x20]),
+        (0x0300 => isactiver: [ReadWrite<u32>; 0x20]),
+        (0x0380 => icactiver: [ReadWrite<u32>; 0x20]),
+        (0x0400 => ipriorityr: [ReadWrite<u32>; 0xff]),
+        (0x07fc => _reserved_2),
+        (0x0c00 => icfgr: [ReadWrite<u32>; 0x40]),
+        (0x0d00 => igrpmodr: [ReadWrite<u32>; 0x20]),
+        (0x0d80 => _reserved_3),
+        (0x6000 => irouter: [ReadWrite<u64>; 0x400]),
+        (0x8000 => @END),
+    }
+}
+
+register_structs! {
+    /// GICv3 Redistributor registers, the control frame followed by the SGI and PPI frame.
+    #[allow(non_snake_case)]
+    pub Gic_V3_Rdist_Map_Regs {
+        (0x00000 => ctlr: ReadWrite<u32>),
+        (0x00004 => iidr: ReadOnly<u32>),
+        (0x00008 => typer: ReadOnly<u64>),
+        (0x00010 => statusr: ReadWrite<u32>),
+        (0x00014 => waker: ReadWrite<u32>),
+        (0x00018 => _reserved_1),
+        (0x00070 => propbaser: ReadWrite<u64>),
+        (0x00078 => pendbaser: ReadWrite<u64>),
+        (0x00080 => _reserved_2),
+        (0x10080 => igroupr0: ReadWrite<u32>),
+        (0x10084 => _reserved_3),
+        (0x10100 => isenabler0: ReadWrite<u32>),
+        (0x10104 => _reserved_4),
+        (0x10180 => icenabler0: ReadWrite<u32>),
+        (0x10184 => _reserved_5),
+        (0x10200 => ispendr0: ReadWrite<u32>),
+        (0x10204 => _reserved_6),
+        (0x10280 => icpendr0: ReadWrite<u32>),
+        (0x10284 => _reserved_7),
+        (0x10400 => ipriorityr: [ReadWrite<u32>; 8]),
+        (0x10420 => _reserved_8),
+        (0x10c00 => icfgr: [ReadWrite<u32>; 2]),
+        (0x10c08 => _reserved_9),
+        (0x20000 => @END),
+    }
+}
+
+register_structs! {
+    /// GICv3 ITS control registers.
+    #[allow(non_snake_case)]
+    pub Gic_V3_Its_Map_Regs {
+        (0x0000 => ctlr: ReadWrite<u32>),
+        (0x0004 => iidr: ReadOnly<u32>),
+        (0x0008 => typer: ReadOnly<u64>),
+        (0x0010 => _reserved_1),
+        (0x0080 => cbaser: ReadWrite<u64>),
+        (0x0088 => cwriter: ReadWrite<u64>),
+        (0x0090 => creadr: ReadOnly<u64>),
+        (0x0098 => _reserved_2),
+        (0x0100 => baser: [ReadWrite<u64>; 8]),
+        (0x0140 => _reserved_3),
+        (0x10000 => @END),
+    }
+}
@@ -1,2 +1,20 @@
 pub mod gic_v2;
 pub mod gic_v3;
+
+#[cfg(all(feature = "ENABLE_GIC_V3", feature = "ENABLE_ARM_HYP"))]
+compile_error!("ENABLE_ARM_HYP drives the GICv2 virtual interface, it does not support GICv3");
+
+/// The driver of the GIC the kernel is built for, the GICv3 with the `ENABLE_GIC_V3` feature and
+/// the GICv2 otherwise. Both provide the same functions.
+pub mod gic {
+    #[cfg(not(feature = "ENABLE_GIC_V3"))]
+    pub use super::gic_v2::{
+        consts::{IRQ_MASK, SPI_START},
+        gic_v2::*,
+    };
+    #[cfg(feature = "ENABLE_GIC_V3")]
+    pub use super::gic_v3::{
+        consts::{IRQ_MASK, SPI_START},
+        gic_v3::*,
+    };
+}
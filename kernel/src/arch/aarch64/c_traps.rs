@@ -4,8 +4,9 @@ use core::arch::asm;
 
 #[cfg(feature = "ENABLE_SMP")]
 use crate::{
-    deps::{clh_is_self_in_queue, clh_lock_acquire, clh_lock_release},
+    ffi::{clh_is_self_in_queue, clh_lock_release},
     interrupt::getActiveIRQ,
+    smp::ipi::{ipi_lock_acquire, ipi_stop_waiting},
 };
 
 #[cfg(feature = "ENABLE_SMP")]
@@ -14,18 +15,22 @@ use sel4_task::get_currenct_thread;
 
 #[no_mangle]
 pub fn restore_user_context() {
-    // NODE_UNLOCK_IF_HELD;
-
-    // this is just a empty "do {} while (0)", I think it is only meaningfully under multi core case
-    // at that case the micro NODE_UNLOCK_IF_HELD is
-    // do {                         \
-    //     if(clh_is_self_in_queue()) {                         \
-    //         NODE_UNLOCK;                                     \
-    //     }                                                    \
-    // } while(0)
-
     // c_exit_hook();
+    #[cfg(feature = "ENABLE_MCS")]
+    sel4_task::sched_context::commit_time();
+    #[cfg(feature = "ENABLE_TICKLESS")]
+    crate::interrupt::timer::timer_program_next();
     get_currenct_thread().tcbArch.load_thread_local();
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    super::vcpu_switch(get_currenct_thread().tcbArch.get_vcpu());
+    // NODE_UNLOCK_IF_HELD: every entry but the IPI path takes the big kernel lock
+    #[cfg(feature = "ENABLE_SMP")]
+    unsafe {
+        ipi_stop_waiting();
+        if clh_is_self_in_queue() {
+            clh_lock_release(cpu_id());
+        }
+    }
 
     // #ifdef CONFIG_HAVE_FPU
     //     lazyFPURestore(NODE_STATE(ksCurThread));
@@ -75,15 +80,15 @@ pub fn c_handle_interrupt() {
     // if hart_id() != 0 {
     //     debug!("c_handle_interrupt");
     // }
+    #[cfg(feature = "ENABLE_IRQ_LATENCY")]
+    crate::interrupt::latency::irq_latency_entry();
     entry_hook();
 
     #[cfg(feature = "ENABLE_SMP")]
     {
         use crate::config::INTERRUPT_IPI_0;
         if getActiveIRQ() != INTERRUPT_IPI_0 {
-            unsafe {
-                clh_lock_acquire(cpu_id(), true);
-            }
+            ipi_lock_acquire(true);
         }
     }
     // debug!("c_handle_interrupt");
@@ -94,9 +99,7 @@ pub fn c_handle_interrupt() {
 #[no_mangle]
 pub fn c_handle_syscall(_cptr: usize, _msgInfo: usize, syscall: usize) {
     #[cfg(feature = "ENABLE_SMP")]
-    unsafe {
-        clh_lock_acquire(cpu_id(), false);
-    }
+    ipi_lock_acquire(false);
     entry_hook();
     // ffi_call!(c_entry_hook);
     // if hart_id() == 0 {
@@ -107,8 +110,10 @@ pub fn c_handle_syscall(_cptr: usize, _msgInfo: usize, syscall: usize) {
 }
 
 /// This function should be the first thing called from after entry.
-/// This function Save TPIDR(TLS) in aarch64.
+/// This function Save TPIDR(TLS) in aarch64 and, with `ENABLE_MCS`, reads the time of the entry.
 #[inline]
 pub fn entry_hook() {
     get_currenct_thread().tcbArch.save_thread_local();
+    #[cfg(feature = "ENABLE_MCS")]
+    sel4_task::sched_context::update_timestamp(super::read_time());
 }
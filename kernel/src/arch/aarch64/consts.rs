@@ -1,2 +1,7 @@
 pub const ARMDataAbort: usize = 0;
 pub const ARMPrefetchAbort: usize = 1;
+
+/// Exception class of an SMC in AArch32 state
+pub const ESR_EC_SMC32: usize = 0x13;
+/// Exception class of an SMC in AArch64 state
+pub const ESR_EC_SMC64: usize = 0x17;
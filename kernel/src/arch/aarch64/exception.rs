@@ -1,5 +1,7 @@
 use crate::arch::aarch64::consts::ARMDataAbort;
 use crate::arch::aarch64::consts::ARMPrefetchAbort;
+#[cfg(feature = "ENABLE_ARM_HYP")]
+use crate::arch::aarch64::consts::{ESR_EC_SMC32, ESR_EC_SMC64};
 use crate::kernel::boot::current_fault;
 use crate::syscall::handle_fault;
 use aarch64_cpu::registers::Readable;
@@ -10,12 +12,45 @@ use sel4_common::structures::exception_t;
 use sel4_common::utils::global_read;
 use sel4_task::{activateThread, get_currenct_thread, get_current_domain, schedule};
 
+use super::c_traps::entry_hook;
 use super::instruction::*;
 
 #[no_mangle]
 pub fn handleUserLevelFault(w_a: usize, w_b: usize) -> exception_t {
+    entry_hook();
     unsafe {
-        current_fault = seL4_Fault_t::new_user_exeception(w_a, w_b);
+        current_fault = user_level_fault(w_a, w_b);
+        handle_fault(get_currenct_thread());
+    }
+    schedule();
+    activateThread();
+    exception_t::EXCEPTION_NONE
+}
+
+/// The fault of an exception of the current thread with syndrome `w_a`. An SMC of a thread with a
+/// VCPU is forwarded as a VCPU fault for the VMM to emulate the firmware call.
+fn user_level_fault(w_a: usize, w_b: usize) -> seL4_Fault_t {
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    {
+        use sel4_common::arch::sysreg::esr_el1;
+        let ec = esr_el1::EC.get(w_a);
+        if (ec == ESR_EC_SMC64 || ec == ESR_EC_SMC32)
+            && get_currenct_thread().tcbArch.get_vcpu() != 0
+        {
+            return seL4_Fault_t::new_vcpu_fault(w_a);
+        }
+    }
+    seL4_Fault_t::new_user_exeception(w_a, w_b)
+}
+
+/// Entry of an exception the vectors trapped at EL2 for a thread with a VCPU, with the syndrome
+/// `hsr`. SMCs at EL0 are undefined instructions unless `HCR_EL2.TSC` traps them here.
+#[cfg(feature = "ENABLE_ARM_HYP")]
+#[no_mangle]
+pub fn handleVCPUFault(hsr: usize) -> exception_t {
+    entry_hook();
+    unsafe {
+        current_fault = seL4_Fault_t::new_vcpu_fault(hsr);
         handle_fault(get_currenct_thread());
     }
     schedule();
@@ -25,6 +60,7 @@ pub fn handleUserLevelFault(w_a: usize, w_b: usize) -> exception_t {
 
 #[no_mangle]
 pub fn handleVMFaultEvent(vm_faultType: usize) -> exception_t {
+    entry_hook();
     let status = handle_vm_fault(vm_faultType);
     if status != exception_t::EXCEPTION_NONE {
         handle_fault(get_currenct_thread());
@@ -76,6 +112,19 @@ pub fn handle_vm_fault(type_: usize) -> exception_t {
         ARMDataAbort => {
             let addr = get_far();
             let fault = get_esr();
+            // use the IPA
+            #[cfg(feature = "ENABLE_ARM_HYP")]
+            if get_currenct_thread().tcbArch.get_vcpu() != 0 {
+                let ipa = address_translate_s1(addr).unwrap_or_else(|| get_hpfar_ipa(addr));
+                unsafe {
+                    current_fault = guest_data_abort_fault(ipa, fault);
+                }
+                return exception_t::EXCEPTION_FAULT;
+            }
+            #[cfg(feature = "ENABLE_MMIO_EMULATION")]
+            if super::mmio::handle_mmio_fault(get_currenct_thread(), addr, fault) {
+                return exception_t::EXCEPTION_NONE;
+            }
             log::debug!("fault addr: {:#x} esr: {:#x}", addr, fault);
             unsafe {
                 current_fault = seL4_Fault_t::new_vm_fault(addr, fault, 0);
@@ -98,3 +147,16 @@ pub fn handle_vm_fault(type_: usize) -> exception_t {
         _ => panic!("Invalid VM fault type:{}", type_),
     }
 }
+
+/// The fault of a data abort at `ipa` of a thread with a VCPU, with the access decoded from the
+/// syndrome `esr` so the VMM does not have to decode the instruction.
+#[cfg(feature = "ENABLE_ARM_HYP")]
+fn guest_data_abort_fault(ipa: usize, esr: usize) -> seL4_Fault_t {
+    use sel4_common::arch::sysreg::esr_el1;
+    let width = if esr_el1::ISV.get(esr) != 0 {
+        1 << esr_el1::SAS.get(esr)
+    } else {
+        0
+    };
+    seL4_Fault_t::new_guest_vm_fault(ipa, esr_el1::ISS.get(esr), width, esr_el1::WNR.get(esr))
+}
@@ -1,42 +1,37 @@
-/// Get value from the system register
-macro_rules! mrs {
-    ($reg: literal) => {
-        {
-            let value: usize;
-            unsafe {
-                core::arch::asm!(concat!("mrs {0}, ", $reg), out(reg) value);
-            }
-            value
-        }
-    };
-}
-
-#[allow(unused)]
-/// Set the value of the system register
-macro_rules! msr {
-    ($reg: literal, $v: literal) => {
-        {
-            unsafe {
-                core::arch::asm!(concat!("msr ", $reg, ", {0}"), const $v);
-            }
-        }
-    };
-    ($reg: literal, $v: ident) => {
-        {
-            unsafe {
-                core::arch::asm!(concat!("msr ", $reg, ", {0}"), in(reg) $v);
-            }
-        }
-    };
-}
+use sel4_common::arch::sysreg::{esr_el1, far_el1};
 
 /// Get the value of the FAR register.
 #[inline]
 pub fn get_far() -> usize {
-    mrs!("far_el1")
+    far_el1::read()
 }
 
 #[inline]
 pub fn get_esr() -> usize {
-    mrs!("esr_el1")
+    esr_el1::read()
+}
+
+/// Translate `va` with stage 1 of the EL1&0 regime, like `addressTranslateS1` of the C kernel.
+///
+/// # Returns
+///
+/// The IPA `va` maps to, `None` if the walk failed.
+#[cfg(feature = "ENABLE_ARM_HYP")]
+pub fn address_translate_s1(va: usize) -> Option<usize> {
+    use sel4_common::arch::sysreg::par_el1;
+    unsafe {
+        core::arch::asm!("at s1e1r, {}", "isb", in(reg) va);
+    }
+    let par = par_el1::read();
+    if par_el1::F.get(par) != 0 {
+        return None;
+    }
+    Some(par_el1::PA.get(par) << 12 | (va & 0xfff))
+}
+
+/// The IPA of the last stage-2 abort, `va` gives its offset in the page.
+#[cfg(feature = "ENABLE_ARM_HYP")]
+pub fn get_hpfar_ipa(va: usize) -> usize {
+    use sel4_common::arch::sysreg::hpfar_el2;
+    hpfar_el2::FIPA.get(hpfar_el2::read()) << 12 | (va & 0xfff)
 }
@@ -0,0 +1,243 @@
+//! Emulated MMIO regions, enabled by the `ENABLE_MMIO_EMULATION` feature.
+//!
+//! `ARMVSpaceEmulateMMIO` marks a range of a vspace as emulated and binds it to an endpoint. A
+//! data abort of a thread of that vspace in the range, with a valid syndrome, is not a `VMFault`
+//! for its fault handler: the kernel calls the endpoint of the region with an `MMIOFault` giving
+//! the address, width, direction and the value of a store. The reply gives the value of a load,
+//! which the kernel writes to the target register, and the thread resumes after the instruction.
+//! Accesses the syndrome does not describe, like pairs or writeback, still raise a `VMFault`.
+//!
+//! Only the unmapped parts of a region fault, so a region is usually left unmapped. The endpoint
+//! cap is kept in a kernel slot as a child of the cap it was taken from, revoking that cap unbinds
+//! the region. Deleting the vspace root removes its regions.
+
+use core::intrinsics::unlikely;
+
+use log::debug;
+use sel4_common::arch::sysreg::esr_el1;
+use sel4_common::fault::seL4_Fault_t;
+use sel4_common::sel4_config::{
+    seL4_AlignmentError, seL4_IllegalOperation, seL4_InvalidArgument, seL4_InvalidCapability,
+    seL4_NotEnoughMemory, seL4_TruncatedMessage, tcbVTable, PAGE_BITS,
+};
+use sel4_common::structures::{exception_t, seL4_IPCBuffer};
+use sel4_common::utils::convert_to_checked_mut_type_ref;
+use sel4_common::IS_ALIGNED;
+use sel4_cspace::interface::{cap_t, cte_insert, cte_t, mdb_node_t, CapTag};
+use sel4_ipc::endpoint_t;
+use sel4_task::tcb_t;
+
+use crate::config::{CONFIG_MAX_MMIO_REGIONS, USER_TOP};
+use crate::kernel::boot::{current_syscall_error, get_extra_cap_by_index};
+use crate::syscall::{get_syscall_arg, invocation::perform_invocation};
+
+/// A range of a vspace whose accesses are forwarded to an endpoint, unused with a `vspace` of 0.
+struct mmio_region_t {
+    /// The vspace root the region belongs to
+    vspace: usize,
+    base: usize,
+    size: usize,
+    /// The endpoint cap the faults are sent through
+    ep: cte_t,
+}
+
+const EMPTY_REGION: mmio_region_t = mmio_region_t {
+    vspace: 0,
+    base: 0,
+    size: 0,
+    ep: cte_t {
+        cap: cap_t { words: [0; 2] },
+        cteMDBNode: mdb_node_t { words: [0; 2] },
+    },
+};
+
+static mut ksMMIORegions: [mmio_region_t; CONFIG_MAX_MMIO_REGIONS] =
+    [EMPTY_REGION; CONFIG_MAX_MMIO_REGIONS];
+
+#[inline]
+fn regions() -> &'static mut [mmio_region_t; CONFIG_MAX_MMIO_REGIONS] {
+    unsafe { &mut *core::ptr::addr_of_mut!(ksMMIORegions) }
+}
+
+/// Unbind `region` and drop its endpoint cap.
+fn clear_region(region: &mut mmio_region_t) {
+    region.ep.delete_one();
+    region.vspace = 0;
+    region.base = 0;
+    region.size = 0;
+}
+
+/// Remove the regions of the vspace root at `vspace`, called when it is deleted.
+pub fn mmio_regions_clear(vspace: usize) {
+    for region in regions().iter_mut().filter(|r| r.vspace == vspace) {
+        clear_region(region);
+    }
+}
+
+/// Decode ARMVSpaceEmulateMMIO on the vspace root cap `cap`: base and size of the region, and the
+/// endpoint cap as extra cap 0. A size of 0 removes the region at the base instead.
+pub fn decode_vspace_emulate_mmio(
+    length: usize,
+    cap: &cap_t,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> exception_t {
+    perform_invocation(
+        decode_emulate_mmio(length, cap, buffer),
+        |(vspace, base, size, ep)| {
+            if let Some(region) = regions()
+                .iter_mut()
+                .find(|r| r.vspace == vspace && r.base == base)
+            {
+                clear_region(region);
+            }
+            if let Some((cap, src_slot)) = ep {
+                let region = regions().iter_mut().find(|r| r.vspace == 0).unwrap();
+                region.vspace = vspace;
+                region.base = base;
+                region.size = size;
+                cte_insert(&cap, src_slot, &mut region.ep);
+            }
+            exception_t::EXCEPTION_NONE
+        },
+    )
+}
+
+fn decode_emulate_mmio(
+    length: usize,
+    cap: &cap_t,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<(usize, usize, usize, Option<(cap_t, &'static mut cte_t)>), exception_t> {
+    if length < 2 {
+        debug!("VSpaceRoot EmulateMMIO: Truncated message.");
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    if !cap.is_valid_native_root() {
+        debug!("VSpaceRoot EmulateMMIO: VSpace is not mapped.");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 0;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let vspace = cap.get_pgd_base_ptr();
+    let base = get_syscall_arg(0, buffer);
+    let size = get_syscall_arg(1, buffer);
+    if size == 0 {
+        return Ok((vspace, base, 0, None));
+    }
+    if !IS_ALIGNED!(base, PAGE_BITS) || !IS_ALIGNED!(size, PAGE_BITS) {
+        debug!("VSpaceRoot EmulateMMIO: Region is not page aligned.");
+        unsafe {
+            current_syscall_error._type = seL4_AlignmentError;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    if base >= USER_TOP || size > USER_TOP - base {
+        debug!("VSpaceRoot EmulateMMIO: Region exceeds the user addressable region.");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidArgument;
+            current_syscall_error.invalidArgumentNumber = 1;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let overlaps = regions().iter().any(|r| {
+        r.vspace == vspace && r.base != base && r.base < base + size && base < r.base + r.size
+    });
+    if overlaps {
+        debug!("VSpaceRoot EmulateMMIO: Region overlaps another one.");
+        unsafe {
+            current_syscall_error._type = seL4_IllegalOperation;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let Some(src_slot) = get_extra_cap_by_index(0) else {
+        debug!("VSpaceRoot EmulateMMIO: Truncated message.");
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    };
+    let ep_cap = src_slot.cap;
+    // The same rights as a fault handler, the kernel calls the endpoint
+    if unlikely(
+        ep_cap.get_cap_type() != CapTag::CapEndpointCap
+            || ep_cap.get_ep_can_send() == 0
+            || (ep_cap.get_ep_can_grant() == 0 && ep_cap.get_ep_can_grant_reply() == 0),
+    ) {
+        debug!("VSpaceRoot EmulateMMIO: endpoint cap with send and grant reply required.");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 1;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let replaces = regions()
+        .iter()
+        .any(|r| r.vspace == vspace && r.base == base);
+    if !replaces && regions().iter().all(|r| r.vspace != 0) {
+        debug!("VSpaceRoot EmulateMMIO: No free region.");
+        unsafe {
+            current_syscall_error._type = seL4_NotEnoughMemory;
+            current_syscall_error.memoryLeft = 0;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    Ok((vspace, base, size, Some((ep_cap, src_slot))))
+}
+
+/// Forward the data abort of `thread` at `addr` with syndrome `esr` to the endpoint of the
+/// emulated region it hit.
+///
+/// # Returns
+///
+/// Whether the fault was sent. It is not if the address is in no region, the syndrome does not
+/// describe the access, or the endpoint cap of the region was revoked.
+pub fn handle_mmio_fault(thread: &mut tcb_t, addr: usize, esr: usize) -> bool {
+    if esr_el1::ISV.get(esr) == 0 {
+        return false;
+    }
+    let vspace_cap = thread.get_cspace(tcbVTable).cap;
+    if vspace_cap.get_cap_type() != CapTag::CapPageGlobalDirectoryCap {
+        return false;
+    }
+    let vspace = vspace_cap.get_pgd_base_ptr();
+    let Some(region) = regions()
+        .iter()
+        .find(|r| r.vspace == vspace && r.base <= addr && addr - r.base < r.size)
+    else {
+        return false;
+    };
+    let ep_cap = region.ep.cap;
+    if ep_cap.get_cap_type() != CapTag::CapEndpointCap {
+        return false;
+    }
+    let write = esr_el1::WNR.get(esr);
+    let register = esr_el1::SRT.get(esr);
+    let width = 1 << esr_el1::SAS.get(esr);
+    let data = match write {
+        0 => 0,
+        _ if width == 8 => thread.tcbArch.get_gp_register(register),
+        _ => thread.tcbArch.get_gp_register(register) & ((1 << (width * 8)) - 1),
+    };
+    thread.tcbFault = seL4_Fault_t::new_mmio_fault(
+        addr,
+        data,
+        width,
+        write,
+        esr_el1::SSE.get(esr),
+        esr_el1::SF.get(esr),
+        register,
+    );
+    convert_to_checked_mut_type_ref::<endpoint_t>(ep_cap.get_ep_ptr()).send_ipc(
+        thread,
+        true,
+        true,
+        ep_cap.get_ep_can_grant() != 0,
+        ep_cap.get_ep_badge(),
+        true,
+    );
+    true
+}
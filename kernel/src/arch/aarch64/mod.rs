@@ -4,16 +4,38 @@ mod consts;
 mod exception;
 mod ffi;
 pub(self) mod instruction;
+#[cfg(feature = "ENABLE_MMIO_EMULATION")]
+mod mmio;
 mod pg;
 mod platform;
+#[cfg(feature = "ENABLE_ARM_SMC")]
+mod smc;
+#[cfg(feature = "ENABLE_ARM_HYP")]
+mod vcpu;
+#[cfg(feature = "ENABLE_ARM_HYP")]
+mod vgic;
+mod zva;
 
 pub mod arm_gic;
 
-use aarch64_cpu::registers::{Writeable, CNTV_CTL_EL0, CNTV_TVAL_EL0};
+use aarch64_cpu::registers::{Readable, CNTVCT_EL0};
 pub use boot::try_init_kernel;
+#[cfg(feature = "ENABLE_SMP")]
+pub use boot::try_init_kernel_secondary_core;
 pub use c_traps::restore_user_context;
+#[cfg(feature = "ENABLE_MMIO_EMULATION")]
+pub use mmio::{decode_vspace_emulate_mmio, mmio_regions_clear};
 pub(crate) use pg::set_vm_root_for_flush;
 pub use platform::init_freemem;
+#[cfg(feature = "ENABLE_ARM_SMC")]
+pub use smc::decode_arm_smc_invocation;
+#[cfg(feature = "ENABLE_ARM_HYP")]
+pub use vcpu::{decode_vcpu_invocation, vcpu_switch, vcpu_t, vgic_maintenance};
+#[cfg(feature = "ENABLE_ARM_HYP")]
+pub use vgic::vgic_init;
+#[cfg(feature = "ENABLE_SMP")]
+pub use zva::zva_init_secondary;
+pub use zva::{zva_init, zva_zero};
 
 pub fn read_sip() -> usize {
     // let temp: usize;
@@ -24,15 +46,7 @@ pub fn read_sip() -> usize {
     todo!("read_sip")
 }
 
-/// Reset the current Timer
-#[no_mangle]
-pub fn resetTimer() {
-    /*
-        SYSTEM_WRITE_WORD(CNT_TVAL, TIMER_RELOAD);
-        SYSTEM_WRITE_WORD(CNT_CTL, BIT(0));
-    */
-    const TIMER_CLOCK_HZ: u64 = 62500000;
-    // TODO: Set a proper timer clock
-    CNTV_TVAL_EL0.set(TIMER_CLOCK_HZ / 1000 * 10);
-    CNTV_CTL_EL0.set(1);
+/// Read the virtual counter of the generic timer
+pub fn read_time() -> usize {
+    CNTVCT_EL0.get() as usize
 }
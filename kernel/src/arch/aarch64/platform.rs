@@ -2,9 +2,9 @@ use aarch64_cpu::registers::Writeable;
 use aarch64_cpu::registers::{TPIDR_EL1, VBAR_EL1};
 use core::arch::asm;
 use sel4_common::arch::config::{KERNEL_ELF_BASE, PADDR_TOP};
-use sel4_common::ffi::kernel_stack_alloc;
+use sel4_common::ffi::kernel_stack_top;
 use sel4_common::ffi_addr;
-use sel4_common::sel4_config::{wordBits, CONFIG_KERNEL_STACK_BITS};
+use sel4_common::sel4_config::wordBits;
 use sel4_common::utils::cpu_id;
 
 use super::ffi::*;
@@ -17,7 +17,7 @@ use crate::structures::*;
 use log::debug;
 use sel4_vspace::*;
 
-use super::arm_gic::gic_v2::gic_v2::cpu_initLocalIRQController;
+use super::arm_gic::gic::cpu_initLocalIRQController;
 
 #[allow(unused)]
 pub fn init_cpu() -> bool {
@@ -25,11 +25,8 @@ pub fn init_cpu() -> bool {
 
     // Setup kernel stack pointer.
 
-    // Wrapping_add, first argument is CURRENT_CPU_INDEX
-    //
-    let mut stack_top =
-        unsafe { &kernel_stack_alloc.data[0][0 + (1 << CONFIG_KERNEL_STACK_BITS)] as *const u8 }
-            as u64;
+    // Every core runs on its own stack, the index of the core is kept in the low bits
+    let mut stack_top = kernel_stack_top(cpu_id()) as u64;
     stack_top |= cpu_id() as u64; //the judge of enable smp have done in cpu_id
 
     TPIDR_EL1.set(stack_top);
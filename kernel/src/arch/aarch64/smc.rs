@@ -0,0 +1,88 @@
+//! Firmware calls through the SMC cap, enabled by the `ENABLE_ARM_SMC` feature.
+//!
+//! `ARMSMCCall` takes the eight arguments of an SMC in the message registers, issues the SMC from
+//! the kernel and replies with the eight result registers. A badged SMC cap may only make the
+//! call whose function ID, the first argument, is the badge, so a PSCI or SiP service can be
+//! handed out on its own.
+
+use core::arch::asm;
+
+use sel4_common::arch::{ArchReg, MessageLabel};
+use sel4_common::message_info::seL4_MessageInfo_t;
+use sel4_common::sel4_config::{
+    seL4_IllegalOperation, seL4_InvalidArgument, seL4_TruncatedMessage,
+};
+use sel4_common::structures::{exception_t, seL4_IPCBuffer};
+use sel4_cspace::interface::cap_t;
+use sel4_task::{get_currenct_thread, ThreadStateMachine};
+
+use crate::kernel::boot::current_syscall_error;
+use crate::syscall::get_syscall_arg;
+
+/// The number of argument and result registers of an SMC
+const SMC_NUM_ARGS: usize = 8;
+
+pub fn decode_arm_smc_invocation(
+    label: MessageLabel,
+    length: usize,
+    cap: &cap_t,
+    call: bool,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> exception_t {
+    if label != MessageLabel::ARMSMCCall {
+        unsafe {
+            current_syscall_error._type = seL4_IllegalOperation;
+        }
+        return exception_t::EXCEPTION_SYSCALL_ERROR;
+    }
+    if length < SMC_NUM_ARGS {
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return exception_t::EXCEPTION_SYSCALL_ERROR;
+    }
+    let badge = cap.get_smc_badge();
+    if badge != 0 && badge != get_syscall_arg(0, buffer) {
+        unsafe {
+            current_syscall_error._type = seL4_InvalidArgument;
+            current_syscall_error.invalidArgumentNumber = 0;
+        }
+        return exception_t::EXCEPTION_SYSCALL_ERROR;
+    }
+
+    let mut args = [0; SMC_NUM_ARGS];
+    for (i, arg) in args.iter_mut().enumerate() {
+        *arg = get_syscall_arg(i, buffer);
+    }
+    get_currenct_thread().set_restart();
+    invoke_smc_call(&mut args, call)
+}
+
+fn invoke_smc_call(args: &mut [usize; SMC_NUM_ARGS], call: bool) -> exception_t {
+    unsafe {
+        asm!(
+            "smc #0",
+            inout("x0") args[0],
+            inout("x1") args[1],
+            inout("x2") args[2],
+            inout("x3") args[3],
+            inout("x4") args[4],
+            inout("x5") args[5],
+            inout("x6") args[6],
+            inout("x7") args[7],
+        );
+    }
+    let thread = get_currenct_thread();
+    if call {
+        thread.tcbArch.set_register(ArchReg::Badge, 0);
+        let mut length = 0;
+        for (i, result) in args.iter().enumerate() {
+            length = thread.set_mr(i, *result);
+        }
+        thread.tcbArch.set_register(
+            ArchReg::MsgInfo,
+            seL4_MessageInfo_t::new(0, 0, 0, length).to_word(),
+        );
+    }
+    exception_t::EXCEPTION_NONE
+}
@@ -0,0 +1,293 @@
+//! Virtual CPUs of guests, enabled by the `ENABLE_ARM_HYP` feature.
+//!
+//! A VCPU object is bound to a thread with `ARMVCPUSetTCB` and carries the state of the virtual
+//! GIC CPU interface of the guest, see the `vgic` module. The state is loaded into the GIC of the
+//! core when the thread is switched to, and saved as soon as another thread runs there, so a VCPU
+//! is only loaded on the core its thread is running on.
+//!
+//! Only the virtual GIC is switched so far. `HCR_EL2` and the EL1 system registers of the guest
+//! are not, the thread of a VCPU still runs at EL0 of the host.
+
+use sel4_common::arch::MessageLabel;
+use sel4_common::fault::seL4_Fault_t;
+use sel4_common::sel4_config::{
+    seL4_DeleteFirst, seL4_IllegalOperation, seL4_InvalidCapability, seL4_RangeError,
+    seL4_TruncatedMessage, CONFIG_MAX_NUM_NODES,
+};
+use sel4_common::structures::{exception_t, seL4_IPCBuffer};
+use sel4_common::utils::{convert_to_mut_type_ref, convert_to_option_mut_type_ref, cpu_id};
+use sel4_common::BIT;
+use sel4_cspace::interface::{cap_t, CapTag};
+use sel4_task::{get_currenct_thread, tcb_t, ThreadStateMachine};
+
+use super::vgic::{
+    get_gic_vcpu_ctrl_eisr, get_gic_vcpu_ctrl_lr, set_gic_vcpu_ctrl_lr, vgic_misr_eoi,
+    vgic_num_list_regs, vgic_t, virq_clear_eoiirqen, virq_is_active, virq_pending_new,
+};
+use crate::kernel::boot::{current_fault, current_syscall_error, get_extra_cap_by_index};
+use crate::syscall::{get_syscall_arg, handle_fault};
+
+/// `loaded_core` of a VCPU that is not loaded on any core
+const VCPU_NOT_LOADED: usize = usize::MAX;
+
+/// The VCPU loaded on each core, 0 for none
+static mut armHSCurVCPU: [usize; CONFIG_MAX_NUM_NODES] = [0; CONFIG_MAX_NUM_NODES];
+
+/// This is `vcpu_t` in the sel4_c_impl, without the saved system registers.
+#[repr(C)]
+pub struct vcpu_t {
+    /// The thread the VCPU is bound to, 0 for none
+    pub vcpuTCB: usize,
+    /// The core the VCPU is loaded on, `VCPU_NOT_LOADED` when its state is in `vgic`
+    loaded_core: usize,
+    /// The list registers written to `vgic` while the VCPU was loaded on another core, one bit
+    /// each, to be written to the GIC by that core
+    dirty_lrs: usize,
+    pub vgic: vgic_t,
+}
+
+impl vcpu_t {
+    #[inline]
+    pub fn get_ptr(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Initialise the VCPU, which was just retyped.
+    pub fn init(&mut self) {
+        self.vcpuTCB = 0;
+        self.loaded_core = VCPU_NOT_LOADED;
+        self.dirty_lrs = 0;
+        self.vgic = vgic_t::new();
+    }
+
+    /// Write the list registers that were injected from another core to the GIC.
+    fn flush_dirty_lrs(&mut self) {
+        let mut dirty = self.dirty_lrs;
+        while dirty != 0 {
+            let idx = dirty.trailing_zeros() as usize;
+            set_gic_vcpu_ctrl_lr(idx, self.vgic.lr[idx]);
+            dirty &= dirty - 1;
+        }
+        self.dirty_lrs = 0;
+    }
+
+    /// Save the VCPU from the current core.
+    fn save(&mut self) {
+        self.flush_dirty_lrs();
+        self.vgic.save();
+        self.loaded_core = VCPU_NOT_LOADED;
+        unsafe {
+            armHSCurVCPU[cpu_id()] = 0;
+        }
+    }
+
+    /// Load the VCPU on the current core.
+    fn restore(&mut self) {
+        self.vgic.restore();
+        self.dirty_lrs = 0;
+        self.loaded_core = cpu_id();
+        unsafe {
+            armHSCurVCPU[cpu_id()] = self.get_ptr();
+        }
+    }
+
+    /// Take the VCPU off the core it is loaded on. On another core, which only happens while its
+    /// thread runs there, the list registers are left to the next VCPU loaded there and the state
+    /// of the virtual interface since the thread was switched to is lost.
+    fn unload(&mut self) {
+        if self.loaded_core == cpu_id() {
+            self.save();
+        } else if self.loaded_core != VCPU_NOT_LOADED {
+            unsafe {
+                armHSCurVCPU[self.loaded_core] = 0;
+            }
+            self.loaded_core = VCPU_NOT_LOADED;
+            self.dirty_lrs = 0;
+        }
+    }
+
+    /// Unbind the VCPU from its thread, if it has one.
+    pub fn dissociate(&mut self) {
+        self.unload();
+        if let Some(tcb) = convert_to_option_mut_type_ref::<tcb_t>(self.vcpuTCB) {
+            tcb.tcbArch.set_vcpu(0);
+        }
+        self.vcpuTCB = 0;
+    }
+
+    /// Bind the VCPU to `tcb`, unbinding both from what they were bound to before.
+    pub fn associate(&mut self, tcb: &mut tcb_t) {
+        if let Some(old) = convert_to_option_mut_type_ref::<vcpu_t>(tcb.tcbArch.get_vcpu()) {
+            old.dissociate();
+        }
+        self.dissociate();
+        self.vcpuTCB = tcb.get_ptr();
+        tcb.tcbArch.set_vcpu(self.get_ptr());
+    }
+
+    /// Put the virtual interrupt `virq` into the list register `idx`.
+    fn inject_irq(&mut self, idx: usize, virq: u32) {
+        self.vgic.lr[idx] = virq;
+        if self.loaded_core == cpu_id() {
+            set_gic_vcpu_ctrl_lr(idx, virq);
+        } else if self.loaded_core != VCPU_NOT_LOADED {
+            // The core writes it to the GIC when it enters the kernel for the reschedule
+            self.dirty_lrs |= BIT!(idx);
+            #[cfg(feature = "ENABLE_SMP")]
+            crate::smp::ipi::ipi_reschedule_mask(BIT!(self.loaded_core));
+        }
+    }
+
+    /// The list register `idx` as the guest sees it.
+    fn get_lr(&self, idx: usize) -> u32 {
+        if self.loaded_core == cpu_id() {
+            get_gic_vcpu_ctrl_lr(idx)
+        } else {
+            self.vgic.lr[idx]
+        }
+    }
+}
+
+/// Load the VCPU at `new`, 0 for none, on the current core on the way to user mode.
+pub fn vcpu_switch(new: usize) {
+    let cur = unsafe { armHSCurVCPU[cpu_id()] };
+    if new == cur {
+        if let Some(vcpu) = convert_to_option_mut_type_ref::<vcpu_t>(cur) {
+            vcpu.flush_dirty_lrs();
+        }
+        return;
+    }
+    if let Some(vcpu) = convert_to_option_mut_type_ref::<vcpu_t>(cur) {
+        vcpu.save();
+    }
+    if let Some(vcpu) = convert_to_option_mut_type_ref::<vcpu_t>(new) {
+        vcpu.restore();
+    }
+}
+
+/// Handle the maintenance interrupt of the virtual interface of the current core. The thread of
+/// the loaded VCPU takes a `VGICMaintenance` fault with the list register of the interrupt the
+/// guest completed.
+pub fn vgic_maintenance() {
+    let Some(vcpu) = convert_to_option_mut_type_ref::<vcpu_t>(unsafe { armHSCurVCPU[cpu_id()] })
+    else {
+        return;
+    };
+    let eisr = get_gic_vcpu_ctrl_eisr();
+    let fault = if vgic_misr_eoi() && eisr != 0 {
+        let idx = eisr.trailing_zeros() as usize;
+        // The GIC is not trusted to give a list register it has
+        if idx < vgic_num_list_regs() {
+            let virq = virq_clear_eoiirqen(get_gic_vcpu_ctrl_lr(idx));
+            set_gic_vcpu_ctrl_lr(idx, virq);
+            // `ARMVCPUInjectIRQ` checks the saved list registers
+            vcpu.vgic.lr[idx] = virq;
+            seL4_Fault_t::new_vgic_maintenance_fault(idx, 1)
+        } else {
+            seL4_Fault_t::new_vgic_maintenance_fault(0, 0)
+        }
+    } else {
+        seL4_Fault_t::new_vgic_maintenance_fault(0, 0)
+    };
+    if let Some(thread) = convert_to_option_mut_type_ref::<tcb_t>(vcpu.vcpuTCB) {
+        if thread.is_runnable() {
+            unsafe {
+                current_fault = fault;
+            }
+            handle_fault(thread);
+        }
+    }
+}
+
+/// Decode an invocation of the VCPU cap `cap`.
+pub fn decode_vcpu_invocation(
+    label: MessageLabel,
+    length: usize,
+    cap: &cap_t,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> exception_t {
+    let vcpu = convert_to_mut_type_ref::<vcpu_t>(cap.get_vcpu_ptr());
+    match label {
+        MessageLabel::ARMVCPUSetTCB => decode_vcpu_set_tcb(vcpu),
+        MessageLabel::ARMVCPUInjectIRQ => decode_vcpu_inject_irq(vcpu, length, buffer),
+        _ => {
+            unsafe {
+                current_syscall_error._type = seL4_IllegalOperation;
+            }
+            exception_t::EXCEPTION_SYSCALL_ERROR
+        }
+    }
+}
+
+fn decode_vcpu_set_tcb(vcpu: &mut vcpu_t) -> exception_t {
+    let Some(tcb_slot) = get_extra_cap_by_index(0) else {
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return exception_t::EXCEPTION_SYSCALL_ERROR;
+    };
+    if tcb_slot.cap.get_cap_type() != CapTag::CapThreadCap {
+        unsafe {
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 1;
+        }
+        return exception_t::EXCEPTION_SYSCALL_ERROR;
+    }
+    get_currenct_thread().set_restart();
+    vcpu.associate(convert_to_mut_type_ref::<tcb_t>(tcb_slot.cap.get_tcb_ptr()));
+    exception_t::EXCEPTION_NONE
+}
+
+/// Report that argument `arg` is not in `min..=max`.
+fn range_error(arg: usize, min: usize, max: usize) -> exception_t {
+    unsafe {
+        current_syscall_error._type = seL4_RangeError;
+        current_syscall_error.rangeErrorMin = min;
+        current_syscall_error.rangeErrorMax = max;
+        current_syscall_error.invalidArgumentNumber = arg;
+    }
+    exception_t::EXCEPTION_SYSCALL_ERROR
+}
+
+/// The message register packs the interrupt like seL4: the virtual IRQ in bits 0..16, the
+/// priority in bits 16..24, the group in bits 24..32 and the list register in bits 32..40.
+fn decode_vcpu_inject_irq(
+    vcpu: &mut vcpu_t,
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> exception_t {
+    if length < 1 {
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return exception_t::EXCEPTION_SYSCALL_ERROR;
+    }
+    let mr0 = get_syscall_arg(0, buffer);
+    let vid = mr0 & 0xffff;
+    let priority = (mr0 >> 16) & 0xff;
+    let group = (mr0 >> 24) & 0xff;
+    let index = (mr0 >> 32) & 0xff;
+
+    if vid >= BIT!(10) {
+        return range_error(1, 0, BIT!(10) - 1);
+    }
+    if priority >= BIT!(5) {
+        return range_error(2, 0, BIT!(5) - 1);
+    }
+    if group > 1 {
+        return range_error(3, 0, 1);
+    }
+    if index >= vgic_num_list_regs() {
+        return range_error(4, 0, vgic_num_list_regs() - 1);
+    }
+    if virq_is_active(vcpu.get_lr(index)) {
+        unsafe {
+            current_syscall_error._type = seL4_DeleteFirst;
+        }
+        return exception_t::EXCEPTION_SYSCALL_ERROR;
+    }
+
+    get_currenct_thread().set_restart();
+    vcpu.inject_irq(index, virq_pending_new(group, priority, vid));
+    exception_t::EXCEPTION_NONE
+}
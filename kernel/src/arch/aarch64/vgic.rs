@@ -0,0 +1,145 @@
+//! The virtual CPU interface of the GICv2, enabled by the `ENABLE_ARM_HYP` feature.
+//!
+//! The kernel does not emulate a distributor for guests. A VMM delivers virtual interrupts with
+//! `ARMVCPUInjectIRQ`, which writes them into a list register (`GICH_LR`) of the VCPU, and the
+//! guest acknowledges and completes them on its virtual CPU interface. Every injected interrupt
+//! asks for a maintenance interrupt on completion, which the kernel turns into a
+//! `VGICMaintenance` fault of the thread the VCPU is bound to, so the VMM can forward the EOI to
+//! its emulated distributor and reuse the list register.
+
+use super::arm_gic::gic_v2::consts::GIC_V2_VCPUCTRL_PPTR;
+use super::arm_gic::gic_v2::Gic_VCPU_Ctrl_Map_Regs;
+use crate::config::INTERRUPT_VGIC_MAINTENANCE;
+use aarch64_cpu::registers::Readable;
+use sel4_common::mmio::mmio_t;
+use tock_registers::interfaces::Writeable;
+
+static GIC_VCPUCTRL: mmio_t<Gic_VCPU_Ctrl_Map_Regs> = mmio_t::new(GIC_V2_VCPUCTRL_PPTR);
+
+/// The number of list registers a GICv2 can have
+pub const GIC_VCPU_MAX_NUM_LR: usize = 64;
+
+/// The number of list registers of the GIC, read by [`vgic_init`]
+static mut gic_vcpu_num_list_regs: usize = 0;
+
+/// `GICH_HCR.En`, the virtual CPU interface is on
+pub const VGIC_HCR_EN: u32 = 1 << 0;
+/// `GICH_MISR.EOI`, a list register with `EOI` set became invalid
+const VGIC_MISR_EOI: u32 = 1 << 0;
+
+/// Fields of a list register holding a virtual interrupt without a physical one
+const VIRQ_VID_MASK: u32 = 0x3ff;
+const VIRQ_EOIIRQEN: u32 = 1 << 19;
+const VIRQ_PRIORITY_SHIFT: u32 = 23;
+const VIRQ_STATE_PENDING: u32 = 0b01 << 28;
+const VIRQ_STATE_ACTIVE: u32 = 0b10 << 28;
+const VIRQ_GROUP_SHIFT: u32 = 30;
+
+/// Set up the virtual interface control of the current core and enable its maintenance
+/// interrupt, called on every core.
+pub fn vgic_init() {
+    unsafe {
+        gic_vcpu_num_list_regs = (GIC_VCPUCTRL.regs().vtr.get() & 0x3f) as usize + 1;
+    }
+    GIC_VCPUCTRL.regs().hcr.set(0);
+    super::arm_gic::gic_v2::irq_enable(INTERRUPT_VGIC_MAINTENANCE);
+}
+
+/// The number of list registers of the GIC.
+#[inline]
+pub fn vgic_num_list_regs() -> usize {
+    unsafe { gic_vcpu_num_list_regs }
+}
+
+/// The list register `idx` of the current core.
+#[inline]
+pub fn get_gic_vcpu_ctrl_lr(idx: usize) -> u32 {
+    GIC_VCPUCTRL.regs().lr[idx].get()
+}
+
+/// Write `virq` to the list register `idx` of the current core.
+#[inline]
+pub fn set_gic_vcpu_ctrl_lr(idx: usize, virq: u32) {
+    GIC_VCPUCTRL.regs().lr[idx].set(virq);
+}
+
+/// The list registers of the current core that hold a completed interrupt, one bit each.
+#[inline]
+pub fn get_gic_vcpu_ctrl_eisr() -> u64 {
+    let regs = GIC_VCPUCTRL.regs();
+    regs.eisr0.get() as u64 | (regs.eisr1.get() as u64) << 32
+}
+
+/// Whether the maintenance interrupt was raised for a completed interrupt.
+#[inline]
+pub fn vgic_misr_eoi() -> bool {
+    GIC_VCPUCTRL.regs().misr.get() & VGIC_MISR_EOI != 0
+}
+
+/// The list register value of the pending virtual interrupt `vid`, which raises a maintenance
+/// interrupt when the guest completes it.
+#[inline]
+pub const fn virq_pending_new(group: usize, priority: usize, vid: usize) -> u32 {
+    ((group as u32 & 1) << VIRQ_GROUP_SHIFT)
+        | VIRQ_STATE_PENDING
+        | ((priority as u32 & 0x1f) << VIRQ_PRIORITY_SHIFT)
+        | VIRQ_EOIIRQEN
+        | (vid as u32 & VIRQ_VID_MASK)
+}
+
+/// Whether the guest is still handling the interrupt in the list register value `virq`.
+#[inline]
+pub const fn virq_is_active(virq: u32) -> bool {
+    virq & VIRQ_STATE_ACTIVE != 0
+}
+
+/// `virq` without the request for a maintenance interrupt, once it was taken.
+#[inline]
+pub const fn virq_clear_eoiirqen(virq: u32) -> u32 {
+    virq & !VIRQ_EOIIRQEN
+}
+
+/// The state of the virtual CPU interface of a VCPU while it is not loaded.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct vgic_t {
+    pub hcr: u32,
+    pub vmcr: u32,
+    pub apr: u32,
+    pub lr: [u32; GIC_VCPU_MAX_NUM_LR],
+}
+
+impl vgic_t {
+    /// The state of a new VCPU: the interface is on, no interrupt is pending.
+    pub const fn new() -> Self {
+        Self {
+            hcr: VGIC_HCR_EN,
+            vmcr: 0,
+            apr: 0,
+            lr: [0; GIC_VCPU_MAX_NUM_LR],
+        }
+    }
+
+    /// Save the virtual CPU interface of the current core and turn it off.
+    pub fn save(&mut self) {
+        let regs = GIC_VCPUCTRL.regs();
+        self.hcr = regs.hcr.get();
+        self.vmcr = regs.vmcr.get();
+        self.apr = regs.apr.get();
+        for i in 0..vgic_num_list_regs() {
+            self.lr[i] = regs.lr[i].get();
+        }
+        regs.hcr.set(0);
+    }
+
+    /// Load the state into the virtual CPU interface of the current core.
+    pub fn restore(&self) {
+        let regs = GIC_VCPUCTRL.regs();
+        regs.vmcr.set(self.vmcr);
+        regs.apr.set(self.apr);
+        for i in 0..vgic_num_list_regs() {
+            regs.lr[i].set(self.lr[i]);
+        }
+        regs.hcr.set(self.hcr);
+    }
+}
@@ -0,0 +1,76 @@
+//! Zeroing whole cache blocks with `DC ZVA`.
+//!
+//! `DCZID_EL0` gives the size of the block `DC ZVA` zeroes, and its `DZP` bit tells whether the
+//! instruction is prohibited, e.g. by a hypervisor or firmware that traps it. `zva_init` reads it
+//! on the boot core and every secondary core checks it again, `DC ZVA` is only used if all cores
+//! allow it with the same block size. Without it `clear_memory` falls back to plain stores.
+
+use core::arch::asm;
+
+use log::info;
+use sel4_common::BIT;
+
+/// The block size of `DC ZVA` in bytes, 0 if not used
+static mut ksDczvaBlockSize: usize = 0;
+
+/// Prohibited bit of `DCZID_EL0`
+const DCZID_DZP: usize = BIT!(4);
+
+/// The block size `DC ZVA` zeroes on the current core, 0 if it is prohibited
+fn zva_local_block_size() -> usize {
+    let dczid: usize;
+    unsafe {
+        asm!("mrs {}, dczid_el0", out(reg) dczid);
+    }
+    if dczid & DCZID_DZP != 0 {
+        return 0;
+    }
+    // BS is log2 of the block size in words
+    4 << (dczid & 0xf)
+}
+
+/// Detect `DC ZVA`, called once on the boot core before any object is created.
+pub fn zva_init() {
+    unsafe {
+        ksDczvaBlockSize = zva_local_block_size();
+        info!("DC ZVA block size {} (0 for not used)", ksDczvaBlockSize);
+    }
+}
+
+#[cfg(feature = "ENABLE_SMP")]
+/// Stop using `DC ZVA` if the current secondary core does not allow it with the block size of the
+/// boot core. Called with the big kernel lock held.
+pub fn zva_init_secondary() {
+    unsafe {
+        if ksDczvaBlockSize != 0 && zva_local_block_size() != ksDczvaBlockSize {
+            info!(
+                "DC ZVA differs on core {}, not used",
+                sel4_common::utils::cpu_id()
+            );
+            ksDczvaBlockSize = 0;
+        }
+    }
+}
+
+/// Zero the `BIT!(bits)` bytes at `ptr` with `DC ZVA`.
+///
+/// # Returns
+///
+/// false if `DC ZVA` is not used or the region is not made of whole blocks, and nothing was zeroed
+pub fn zva_zero(ptr: usize, bits: usize) -> bool {
+    let block = unsafe { ksDczvaBlockSize };
+    if block == 0 || BIT!(bits) < block || ptr & (block - 1) != 0 {
+        return false;
+    }
+    let mut addr = ptr;
+    while addr < ptr + BIT!(bits) {
+        unsafe {
+            asm!("dc zva, {}", in(reg) addr);
+        }
+        addr += block;
+    }
+    unsafe {
+        asm!("dsb ish");
+    }
+    true
+}
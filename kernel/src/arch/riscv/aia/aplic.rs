@@ -0,0 +1,64 @@
+//! The S-level APLIC domain in MSI delivery mode. A source is inactive until its IRQ is first
+//! enabled or given a trigger, from then on it stays enabled in the APLIC and is masked at the
+//! IMSIC.
+
+use super::consts::*;
+use super::Aplic_Map_Regs;
+use crate::config::CONFIG_APLIC_NUM_SOURCES;
+use sel4_common::mmio::mmio_t;
+use tock_registers::interfaces::{Readable, Writeable};
+
+static APLIC: mmio_t<Aplic_Map_Regs> = mmio_t::new(APLIC_S_PPTR);
+
+/// Make every source inactive and enable the domain in MSI delivery mode. Run once, on the boot
+/// core.
+pub fn aplic_init() {
+    let regs = APLIC.regs();
+    regs.domaincfg.set(0);
+    for source in 1..CONFIG_APLIC_NUM_SOURCES {
+        regs.sourcecfg[source - 1].set(APLIC_SOURCECFG_SM_INACTIVE);
+    }
+    regs.domaincfg.set(APLIC_DOMAINCFG_IE | APLIC_DOMAINCFG_DM);
+}
+
+/// Give `source` the source mode `mode` and send it as the identity of the same number to `hart`.
+/// The target of an inactive source reads as zero, so it is written afterwards.
+fn aplic_source_configure(source: usize, mode: u32, hart: usize) {
+    let regs = APLIC.regs();
+    regs.sourcecfg[source - 1].set(mode);
+    aplic_source_set_target(source, hart);
+    regs.setienum.set(source as u32);
+}
+
+/// Send `source` to the interrupt file of `hart`, ignored while the source is inactive.
+pub fn aplic_source_set_target(source: usize, hart: usize) {
+    APLIC.regs().target[source - 1].set(((hart << APLIC_TARGET_HART_SHIFT) | source) as u32);
+}
+
+/// Activate `source` as level triggered, sent to `hart`, if it is still inactive
+pub fn aplic_source_enable(source: usize, hart: usize) {
+    if APLIC.regs().sourcecfg[source - 1].get() == APLIC_SOURCECFG_SM_INACTIVE {
+        aplic_source_configure(source, APLIC_SOURCECFG_SM_LEVEL_HIGH, hart);
+    }
+}
+
+/// Make `source` edge triggered, or level triggered for false, sent to `hart`.
+pub fn aplic_source_set_trigger(source: usize, edge: bool, hart: usize) {
+    let mode = match edge {
+        true => APLIC_SOURCECFG_SM_EDGE_RISE,
+        false => APLIC_SOURCECFG_SM_LEVEL_HIGH,
+    };
+    aplic_source_configure(source, mode, hart);
+}
+
+/// Send `source` again if it is level triggered and its line is still up.
+pub fn aplic_retrigger_level(source: usize) {
+    let regs = APLIC.regs();
+    if regs.sourcecfg[source - 1].get() != APLIC_SOURCECFG_SM_LEVEL_HIGH {
+        return;
+    }
+    // Reads of `in_clrip` return the input of the sources
+    if regs.in_clrip[source / 32].get() & (1 << (source % 32)) != 0 {
+        regs.setipnum.set(source as u32);
+    }
+}
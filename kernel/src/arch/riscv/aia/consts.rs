@@ -0,0 +1,25 @@
+//! The S-level APLIC domain is mapped by `map_kernel_devices` in place of the PLIC, as the first
+//! kernel device. Only its registers up to the interrupt delivery controls are used, the domain
+//! runs in MSI delivery mode.
+use crate::config::KDEV_BASE;
+
+pub const APLIC_S_PPTR: usize = KDEV_BASE;
+
+/// `domaincfg`: interrupts are enabled, and delivered as MSIs
+pub const APLIC_DOMAINCFG_IE: u32 = 1 << 8;
+pub const APLIC_DOMAINCFG_DM: u32 = 1 << 2;
+
+/// `sourcecfg` source modes
+pub const APLIC_SOURCECFG_SM_INACTIVE: u32 = 0;
+pub const APLIC_SOURCECFG_SM_EDGE_RISE: u32 = 4;
+pub const APLIC_SOURCECFG_SM_LEVEL_HIGH: u32 = 6;
+
+/// `target` in MSI delivery mode: the hart index of the interrupt file and the identity sent
+pub const APLIC_TARGET_HART_SHIFT: usize = 18;
+
+/// The registers of the IMSIC interrupt file behind `siselect`. On RV64 the `eip` and `eie`
+/// arrays have only even numbers, each covering 64 identities.
+pub const IMSIC_EIDELIVERY: usize = 0x70;
+pub const IMSIC_EITHRESHOLD: usize = 0x72;
+pub const IMSIC_EIP0: usize = 0x80;
+pub const IMSIC_EIE0: usize = 0xc0;
@@ -0,0 +1,79 @@
+//! The supervisor interrupt file of the IMSIC of the current hart, reached through `siselect` and
+//! `sireg`. Interrupts are claimed through `stopei`, which leaves the identity not pending.
+
+use super::consts::*;
+use crate::config::CONFIG_IMSIC_NUM_IDS;
+use core::arch::asm;
+use sel4_common::arch::sysreg::{sireg, siselect, stopei};
+use sel4_common::sel4_config::CONFIG_MAX_NUM_NODES;
+use sel4_common::utils::cpu_id;
+
+const IMSIC_EIE_WORDS: usize = CONFIG_IMSIC_NUM_IDS / 64;
+
+/// The enabled identities of the hart of each core, see [`imsic_sync`]
+static mut imsic_enabled: [[usize; IMSIC_EIE_WORDS]; CONFIG_MAX_NUM_NODES] =
+    [[0; IMSIC_EIE_WORDS]; CONFIG_MAX_NUM_NODES];
+
+fn imsic_write(reg: usize, value: usize) {
+    unsafe {
+        siselect::write(reg);
+        sireg::write(value);
+    }
+}
+
+/// Disable and drop every identity, signal all of them once enabled and turn on delivery.
+pub fn imsic_init() {
+    imsic_write(IMSIC_EIDELIVERY, 0);
+    for word in 0..IMSIC_EIE_WORDS {
+        imsic_write(IMSIC_EIE0 + 2 * word, 0);
+        imsic_write(IMSIC_EIP0 + 2 * word, 0);
+    }
+    imsic_write(IMSIC_EITHRESHOLD, 0);
+    imsic_write(IMSIC_EIDELIVERY, 1);
+}
+
+/// Enable or disable identity `id` for the hart of `core`, in its interrupt file when running
+/// on it.
+pub fn imsic_set_enabled(core: usize, id: usize, enable: bool) {
+    let (word, bit) = (id / 64, 1 << (id % 64));
+    unsafe {
+        match enable {
+            true => imsic_enabled[core][word] |= bit,
+            false => imsic_enabled[core][word] &= !bit,
+        }
+    }
+    if core != cpu_id() {
+        return;
+    }
+    unsafe {
+        siselect::write(IMSIC_EIE0 + 2 * word);
+        match enable {
+            true => sireg::set_bits(bit),
+            false => sireg::clear_bits(bit),
+        };
+    }
+}
+
+/// Whether identity `id` is enabled for the hart of `core`
+pub fn imsic_is_enabled(core: usize, id: usize) -> bool {
+    unsafe { imsic_enabled[core][id / 64] & (1 << (id % 64)) != 0 }
+}
+
+/// Copy the enables other cores changed to the interrupt file of the current hart, run by every
+/// hart on each interrupt
+#[cfg(feature = "ENABLE_SMP")]
+pub fn imsic_sync() {
+    let core = cpu_id();
+    for word in 0..IMSIC_EIE_WORDS {
+        imsic_write(IMSIC_EIE0 + 2 * word, unsafe { imsic_enabled[core][word] });
+    }
+}
+
+/// Claim the highest pending enabled identity, 0 for none
+pub fn imsic_claim() -> usize {
+    let topei: usize;
+    unsafe {
+        asm!("csrrw {0}, 0x15c, zero", out(reg) topei);
+    }
+    stopei::IDENTITY.get(topei)
+}
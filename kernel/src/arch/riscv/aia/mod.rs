@@ -0,0 +1,128 @@
+//! The RISC-V Advanced Interrupt Architecture, used with the `ENABLE_RISCV_AIA` feature.
+//!
+//! Every external interrupt reaches the kernel as an MSI to the supervisor interrupt file of the
+//! IMSIC of a hart, that of the boot hart `CONFIG_FIRST_HART_ID` unless the IRQ was routed to
+//! another core. The IMSIC identity `id` is the kernel IRQ `AIA_IRQ_BASE + id`. The APLIC runs in
+//! MSI delivery mode and sends wired source `n` as identity `n` to the hart of the core of its
+//! IRQ, the identities from `CONFIG_APLIC_NUM_SOURCES` on are left to devices which write MSIs
+//! themselves, see `RISCVIRQIssueIRQHandlerMSI`, and stay with the boot hart.
+//!
+//! Each hart has its own interrupt file, which can only be changed from that hart, so with
+//! `ENABLE_SMP` the enables set on another core take effect on the next interrupt the hart takes.
+
+pub mod aplic;
+pub mod consts;
+pub mod imsic;
+
+use crate::config::{AIA_IRQ_BASE, CONFIG_APLIC_NUM_SOURCES, INT_STATE_ARRAY_SIZE};
+use crate::interrupt::get_irq_target_core;
+use sel4_common::utils::cpu_id;
+use tock_registers::register_structs;
+use tock_registers::registers::ReadWrite;
+
+register_structs! {
+    /// APLIC domain registers, up to the `target` array.
+    #[allow(non_snake_case)]
+    pub Aplic_Map_Regs {
+        (0x0000 => domaincfg: ReadWrite<u32>),
+        (0x0004 => sourcecfg: [ReadWrite<u32>; 1023]),
+        (0x1000 => _reserved_1),
+        (0x1cdc => setipnum: ReadWrite<u32>),
+        (0x1ce0 => _reserved_2),
+        (0x1d00 => in_clrip: [ReadWrite<u32>; 32]),
+        (0x1d80 => _reserved_3),
+        (0x1edc => setienum: ReadWrite<u32>),
+        (0x1ee0 => _reserved_4),
+        (0x3004 => target: [ReadWrite<u32>; 1023]),
+        (0x4000 => @END),
+    }
+}
+
+/// The IMSIC identity of `irq`, and whether it is a wired APLIC source
+#[inline]
+fn irq_identity(irq: usize) -> (usize, bool) {
+    let id = irq - AIA_IRQ_BASE;
+    (id, id < CONFIG_APLIC_NUM_SOURCES)
+}
+
+/// Whether `irq` is an IMSIC identity
+#[inline]
+pub fn is_aia_irq(irq: usize) -> bool {
+    irq > AIA_IRQ_BASE && irq < INT_STATE_ARRAY_SIZE
+}
+
+/// Whether `irq` is a wired APLIC source, which the kernel can send to any hart
+#[inline]
+pub fn is_wired_irq(irq: usize) -> bool {
+    is_aia_irq(irq) && irq_identity(irq).1
+}
+
+/// The hart whose interrupt file takes the IRQs routed to `core`
+#[inline]
+fn core_hart(core: usize) -> usize {
+    #[cfg(feature = "ENABLE_SMP")]
+    {
+        sel4_common::smp::cpu_index_to_id(core)
+    }
+    #[cfg(not(feature = "ENABLE_SMP"))]
+    {
+        let _ = core;
+        crate::config::CONFIG_FIRST_HART_ID
+    }
+}
+
+/// Set up the interrupt file of the current hart, and the APLIC on the boot core.
+pub fn aia_init_local() {
+    if cpu_id() == 0 {
+        aplic::aplic_init();
+    }
+    imsic::imsic_init();
+}
+
+pub fn irq_enable(irq: usize) {
+    let (id, wired) = irq_identity(irq);
+    let core = get_irq_target_core(irq);
+    if wired {
+        aplic::aplic_source_enable(id, core_hart(core));
+    }
+    imsic::imsic_set_enabled(core, id, true);
+    // An MSI is only sent when a level triggered source goes up, not while it stays up
+    if wired {
+        aplic::aplic_retrigger_level(id);
+    }
+}
+
+/// Disable the IRQ, it stays pending if it was
+pub fn irq_disable(irq: usize) {
+    imsic::imsic_set_enabled(get_irq_target_core(irq), irq_identity(irq).0, false);
+}
+
+/// Make the wired source of `irq` edge triggered, or level triggered for false. MSIs have none.
+pub fn irq_set_trigger(irq: usize, edge: bool) {
+    if let (id, true) = irq_identity(irq) {
+        aplic::aplic_source_set_trigger(id, edge, core_hart(get_irq_target_core(irq)));
+    }
+}
+
+/// Send the wired source of `irq` to the hart of core `to` instead of `from`, its enable moves
+/// along. An edge still pending at `from` is lost, a level is sent again.
+pub fn irq_set_target_core(irq: usize, from: usize, to: usize) {
+    let (id, wired) = irq_identity(irq);
+    if !wired {
+        return;
+    }
+    aplic::aplic_source_set_target(id, core_hart(to));
+    if imsic::imsic_is_enabled(from, id) {
+        imsic::imsic_set_enabled(from, id, false);
+        imsic::imsic_set_enabled(to, id, true);
+        aplic::aplic_retrigger_level(id);
+    }
+}
+
+/// Claim the highest pending external interrupt of the current hart, `irqInvalid` for none.
+pub fn aia_claim() -> usize {
+    match imsic::imsic_claim() {
+        0 => crate::config::irqInvalid,
+        id => AIA_IRQ_BASE + id,
+    }
+}
@@ -4,15 +4,28 @@ use sel4_task::create_idle_thread;
 use sel4_vspace::{kpptr_to_paddr, rust_map_kernel_window};
 
 use crate::{
-    arch::{init_cpu, init_freemem},
+    arch::{cbo_init, init_cpu, init_freemem},
     boot::{
-        bi_finalise, calculate_extra_bi_size_bits, create_untypeds, init_core_state, init_dtb,
-        ksNumCPUs, ndks_boot, paddr_to_pptr_reg, root_server_init,
+        bi_finalise, calculate_extra_bi_size_bits, create_shared_frames, create_untypeds,
+        init_core_state, init_dtb, init_platform_info, ksNumCPUs, ndks_boot, paddr_to_pptr_reg,
+        root_server_init,
     },
     config::{BI_FRAME_SIZE_BITS, USER_TOP},
     ffi::init_plat,
-    structures::{p_region_t, seL4_SlotRegion, v_region_t},
+    kernel::random::random_init,
+    structures::{p_region_t, v_region_t},
 };
+#[cfg(feature = "ENABLE_SMP")]
+use crate::{
+    boot::node_boot_lock,
+    ffi::{clh_lock_acquire, clh_lock_init},
+};
+#[cfg(feature = "ENABLE_SMP")]
+use core::arch::asm;
+#[cfg(feature = "ENABLE_SMP")]
+use sel4_common::{sel4_config::CONFIG_MAX_NUM_NODES, utils::cpu_id};
+#[cfg(feature = "ENABLE_SMP")]
+use sel4_task::{tcb_t, SchedulerAction_ResumeCurrentThread};
 
 pub fn try_init_kernel(
     ui_p_reg_start: usize,
@@ -23,14 +36,24 @@ pub fn try_init_kernel(
     dtb_size: usize,
     ki_boot_end: usize,
 ) -> bool {
+    #[cfg(feature = "ENABLE_BOOT_PROGRESS")]
+    crate::boot::boot_progress(crate::boot::boot_stage_t::Entry);
     sel4_common::logging::init();
     debug!("hello logging");
     debug!("hello logging");
+    log::info!("{}", crate::version::kernel_version());
     let boot_mem_reuse_p_reg = p_region_t {
         start: kpptr_to_paddr(KERNEL_ELF_BASE),
         end: kpptr_to_paddr(ki_boot_end as usize),
     };
     let boot_mem_reuse_reg = paddr_to_pptr_reg(&boot_mem_reuse_p_reg);
+    // The image the elfloader loaded stays reserved, also when the embedded root task runs instead
+    let loaded_ui_p_reg = p_region_t {
+        start: ui_p_reg_start,
+        end: ui_p_reg_end,
+    };
+    #[cfg(feature = "ENABLE_SELFTEST_ROOT_TASK")]
+    let (ui_p_reg_start, ui_p_reg_end, pv_offset, v_entry) = crate::boot::selftest_root_task();
     let ui_p_reg = p_region_t {
         start: ui_p_reg_start,
         end: ui_p_reg_end,
@@ -46,16 +69,31 @@ pub fn try_init_kernel(
     let bi_frame_vptr = ipcbuf_vptr + BIT!(PAGE_BITS);
     let extra_bi_frame_vptr = bi_frame_vptr + BIT!(BI_FRAME_SIZE_BITS);
     rust_map_kernel_window();
+    #[cfg(feature = "ENABLE_BOOT_PROGRESS")]
+    crate::boot::boot_progress(crate::boot::boot_stage_t::KernelWindow);
     init_cpu();
+    #[cfg(feature = "ENABLE_BOOT_PROGRESS")]
+    crate::boot::boot_progress(crate::boot::boot_stage_t::Cpu);
 
     unsafe {
         init_plat();
     }
+    #[cfg(feature = "ENABLE_BOOT_PROGRESS")]
+    crate::boot::boot_progress(crate::boot::boot_stage_t::Platform);
 
     let dtb_p_reg = init_dtb(dtb_size, dtb_phys_addr, &mut extra_bi_size);
     if dtb_p_reg.is_none() {
         return false;
     }
+    init_platform_info(&mut extra_bi_size);
+    #[cfg(feature = "ENABLE_DTB_PLATFORM")]
+    crate::boot::dtb_platform_discover(dtb_phys_addr, dtb_size, &mut extra_bi_size);
+    #[cfg(feature = "ENABLE_BENCHMARK")]
+    crate::boot::cpu_freq_pin(&mut extra_bi_size);
+    #[cfg(feature = "ENABLE_PERSISTENT_LOG")]
+    crate::boot::persistent_log_detect(&mut extra_bi_size);
+    random_init(dtb_phys_addr, dtb_size);
+    cbo_init(dtb_phys_addr, dtb_size);
 
     let extra_bi_size_bits = calculate_extra_bi_size_bits(extra_bi_size);
 
@@ -73,10 +111,19 @@ pub fn try_init_kernel(
         return false;
     }
 
-    if !init_freemem(ui_reg.clone(), dtb_p_reg.unwrap().clone()) {
+    if !init_freemem(
+        paddr_to_pptr_reg(&loaded_ui_p_reg),
+        dtb_p_reg.unwrap().clone(),
+    ) {
         debug!("ERROR: free memory management initialization failed\n");
         return false;
     }
+    #[cfg(feature = "ENABLE_BOOT_PROGRESS")]
+    crate::boot::boot_progress(crate::boot::boot_stage_t::FreeMem);
+    #[cfg(feature = "ENABLE_RISCV_PMP")]
+    if !crate::arch::pmp_shield_kernel(ki_boot_end) {
+        return false;
+    }
 
     if let Some((initial_thread, root_cnode_cap)) = root_server_init(
         it_v_reg,
@@ -89,15 +136,20 @@ pub fn try_init_kernel(
         pv_offset,
         v_entry,
     ) {
+        #[cfg(feature = "ENABLE_BOOT_PROGRESS")]
+        crate::boot::boot_progress(crate::boot::boot_stage_t::RootServer);
         create_idle_thread();
         init_core_state(initial_thread);
         if !create_untypeds(&root_cnode_cap, boot_mem_reuse_reg) {
             debug!("ERROR: could not create untypteds for kernel image boot memory");
+            return false;
         }
         unsafe {
-            (*ndks_boot.bi_frame).sharedFrames = seL4_SlotRegion { start: 0, end: 0 };
+            (*ndks_boot.bi_frame).sharedFrames = create_shared_frames(&root_cnode_cap);
 
             bi_finalise(dtb_size, dtb_phys_addr, extra_bi_size);
+            #[cfg(feature = "ENABLE_PERSISTENT_LOG")]
+            crate::boot::persistent_log_attach();
         }
         // debug!("release_secondary_cores start");
         *ksNumCPUs.lock() = 1;
@@ -110,6 +162,8 @@ pub fn try_init_kernel(
             }
         }
 
+        #[cfg(feature = "ENABLE_BOOT_PROGRESS")]
+        crate::boot::boot_progress(crate::boot::boot_stage_t::User);
         debug!("Booting all finished, dropped to user space");
         debug!("\n");
     } else {
@@ -118,3 +172,28 @@ pub fn try_init_kernel(
 
     true
 }
+
+#[cfg(feature = "ENABLE_SMP")]
+/// Boot the secondary core `_core_id` on hart `_hart_id`, once the primary core released it. The
+/// core takes the big kernel lock before it touches any shared state.
+pub fn try_init_kernel_secondary_core(_hart_id: usize, _core_id: usize) -> bool {
+    while node_boot_lock.lock().eq(&0) {}
+    init_cpu();
+    unsafe { clh_lock_acquire(cpu_id(), false) }
+    *ksNumCPUs.lock() += 1;
+    init_core_state(SchedulerAction_ResumeCurrentThread as *mut tcb_t);
+    unsafe {
+        asm!("fence.i");
+    }
+    true
+}
+
+#[cfg(feature = "ENABLE_SMP")]
+/// Let the secondary cores boot and wait until all of them are up.
+fn release_secondary_cores() {
+    *node_boot_lock.lock() = 1;
+    unsafe {
+        asm!("fence rw, rw");
+    }
+    while ksNumCPUs.lock().ne(&CONFIG_MAX_NUM_NODES) {}
+}
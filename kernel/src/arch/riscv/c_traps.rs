@@ -12,12 +12,18 @@ use crate::{
 use sel4_task::*;
 
 use super::exception::{handleUserLevelFault, handleVMFaultEvent};
+#[cfg(feature = "ENABLE_RISCV_HYP")]
+use crate::config::{
+    RISCVEnvCallFromVS, RISCVInstructionGuestPageFault, RISCVLoadGuestPageFault,
+    RISCVStoreGuestPageFault, RISCVVirtualInstruction,
+};
 use crate::interrupt::handler::handleInterruptEntry;
 
 #[cfg(feature = "ENABLE_SMP")]
 use crate::{
-    deps::{clh_is_self_in_queue, clh_lock_acquire, clh_lock_release},
+    ffi::{clh_is_self_in_queue, clh_lock_release},
     interrupt::getActiveIRQ,
+    smp::ipi::{ipi_lock_acquire, ipi_stop_waiting},
 };
 
 #[cfg(feature = "ENABLE_SMP")]
@@ -25,11 +31,19 @@ use sel4_common::utils::cpu_id;
 
 #[no_mangle]
 pub fn restore_user_context() {
+    #[cfg(feature = "ENABLE_MCS")]
+    sel4_task::sched_context::commit_time();
+    #[cfg(feature = "ENABLE_TICKLESS")]
+    crate::interrupt::timer::timer_program_next();
+    // Enter the guest if the thread has a VCPU, `sret` goes to VS-mode with `hstatus.SPV` set
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    super::vcpu_switch(get_currenct_thread().tcbArch.get_vcpu());
     unsafe {
         // debug!("restore_user_context");
         let cur_thread_reg: usize = get_currenct_thread().tcbArch.raw_ptr();
         #[cfg(feature = "ENABLE_SMP")]
         {
+            ipi_stop_waiting();
             if clh_is_self_in_queue() {
                 clh_lock_release(cpu_id());
             }
@@ -98,16 +112,18 @@ pub fn c_handle_interrupt() {
     // if hart_id() != 0 {
     //     debug!("c_handle_interrupt");
     // }
+    #[cfg(feature = "ENABLE_IRQ_LATENCY")]
+    crate::interrupt::latency::irq_latency_entry();
     #[cfg(feature = "ENABLE_SMP")]
     {
         use crate::config::INTERRUPT_IPI_0;
         if getActiveIRQ() != INTERRUPT_IPI_0 {
-            unsafe {
-                clh_lock_acquire(cpu_id(), true);
-            }
+            ipi_lock_acquire(true);
         }
     }
     // debug!("c_handle_interrupt");
+    #[cfg(feature = "ENABLE_MCS")]
+    sel4_task::sched_context::update_timestamp(super::read_time());
     handleInterruptEntry();
     restore_user_context();
 }
@@ -115,12 +131,12 @@ pub fn c_handle_interrupt() {
 #[no_mangle]
 pub fn c_handle_exception() {
     #[cfg(feature = "ENABLE_SMP")]
-    unsafe {
-        clh_lock_acquire(cpu_id(), false);
-    }
+    ipi_lock_acquire(false);
     // if hart_id() == 0 {
     //     debug!("c_handle_exception");
     // }
+    #[cfg(feature = "ENABLE_MCS")]
+    sel4_task::sched_context::update_timestamp(super::read_time());
 
     let cause = read_scause();
     match cause {
@@ -132,6 +148,14 @@ pub fn c_handle_exception() {
         | RISCVInstructionPageFault => {
             handleVMFaultEvent(cause);
         }
+        #[cfg(feature = "ENABLE_RISCV_HYP")]
+        RISCVInstructionGuestPageFault | RISCVLoadGuestPageFault | RISCVStoreGuestPageFault => {
+            handleVMFaultEvent(cause);
+        }
+        #[cfg(feature = "ENABLE_RISCV_HYP")]
+        RISCVEnvCallFromVS | RISCVVirtualInstruction => {
+            super::exception::handleVCPUFault(cause);
+        }
         _ => {
             handleUserLevelFault(cause, 0);
         }
@@ -142,12 +166,12 @@ pub fn c_handle_exception() {
 #[no_mangle]
 pub fn c_handle_syscall(_cptr: usize, _msgInfo: usize, syscall: usize) {
     #[cfg(feature = "ENABLE_SMP")]
-    unsafe {
-        clh_lock_acquire(cpu_id(), false);
-    }
+    ipi_lock_acquire(false);
     // if hart_id() == 0 {
     //     debug!("c_handle_syscall: syscall: {},", syscall as isize);
     // }
+    #[cfg(feature = "ENABLE_MCS")]
+    sel4_task::sched_context::update_timestamp(super::read_time());
     slowpath(syscall);
     // debug!("c_handle_syscall complete");
 }
@@ -0,0 +1,214 @@
+//! Cache block operations of the Zicbom and Zicboz extensions.
+//!
+//! `cbo_init` reads the extensions from the `riscv,isa` or `riscv,isa-extensions` property of
+//! every cpu node in the DTB, and the block sizes from `riscv,cbom-block-size` and
+//! `riscv,cboz-block-size`. An extension is only used if all harts have it with the same block
+//! size. The firmware has to enable the instructions for S-mode in `menvcfg`, which OpenSBI does
+//! on harts that implement them.
+//!
+//! With Zicboz `clear_memory` zeroes whole cache blocks with `cbo.zero`, which is what makes
+//! retyping fast. With Zicbom the data cache maintenance invocations and benchmark syscalls use
+//! `cbo.clean`, `cbo.inval` and `cbo.flush`. Without the extensions the callers fall back to
+//! plain stores and to `fence rw, rw`, as the data caches are taken to be coherent.
+
+use core::arch::asm;
+
+use log::info;
+use sel4_common::sel4_config::seL4_PageBits;
+use sel4_common::{BIT, MASK};
+use sel4_vspace::{paddr_to_pptr, PTEFlags, PTE};
+
+use crate::boot::{fdt_cstr_eq, fdt_for_each_prop, fdt_read_be32};
+
+/// The block size of Zicbom in bytes, 0 if not all harts have it
+static mut ksCbomBlockSize: usize = 0;
+/// The block size of Zicboz in bytes, 0 if not all harts have it
+static mut ksCbozBlockSize: usize = 0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+/// A cache block management operation of Zicbom, the value is its immediate
+pub enum cbo_op_t {
+    /// Invalidate the block, `cbo.inval`
+    Inval = 0,
+    /// Write the block back if dirty, `cbo.clean`
+    Clean = 1,
+    /// Write the block back and invalidate it, `cbo.flush`
+    Flush = 2,
+}
+
+#[derive(Default)]
+/// The extensions found in the cpu nodes of the DTB so far
+struct cbo_probe_t {
+    harts: usize,
+    cbom_harts: usize,
+    cboz_harts: usize,
+    cbom_block_size: Option<usize>,
+    cboz_block_size: Option<usize>,
+    sizes_match: bool,
+    /// The node the properties below belong to
+    node: usize,
+    node_is_hart: bool,
+    node_cbom: bool,
+    node_cboz: bool,
+}
+
+impl cbo_probe_t {
+    /// Account the node whose properties were seen last.
+    fn end_node(&mut self) {
+        if self.node_is_hart {
+            self.harts += 1;
+            self.cbom_harts += self.node_cbom as usize;
+            self.cboz_harts += self.node_cboz as usize;
+        }
+        self.node_is_hart = false;
+        self.node_cbom = false;
+        self.node_cboz = false;
+    }
+
+    /// The block size of an extension all `with` harts have, 0 if not usable
+    fn usable(&self, with: usize, size: Option<usize>) -> usize {
+        match size {
+            Some(size) if self.harts != 0 && with == self.harts && size.is_power_of_two() => size,
+            _ => 0,
+        }
+    }
+}
+
+/// Take the block size at `value` into `size`, false if it differs from the one there already
+fn merge_block_size(size: &mut Option<usize>, value: usize) -> bool {
+    let value = fdt_read_be32(value) as usize;
+    match size {
+        Some(size) => *size == value,
+        None => {
+            *size = Some(value);
+            true
+        }
+    }
+}
+
+/// Whether the ISA string at `ptr` of `len` bytes, e.g. `rv64imafdc_zicbom`, names `ext`
+fn isa_string_has(ptr: usize, len: usize, ext: &[u8]) -> bool {
+    let isa = unsafe { core::slice::from_raw_parts(ptr as *const u8, len) };
+    let isa = isa.split(|c| *c == 0).next().unwrap_or(&[]);
+    isa.split(|c| *c == b'_')
+        .skip(1)
+        .any(|name| name.eq_ignore_ascii_case(ext))
+}
+
+/// Whether the string list at `ptr` of `len` bytes names `ext`
+fn string_list_has(ptr: usize, len: usize, ext: &[u8]) -> bool {
+    let list = unsafe { core::slice::from_raw_parts(ptr as *const u8, len) };
+    list.split(|c| *c == 0).any(|name| name == ext)
+}
+
+/// Detect Zicbom and Zicboz, called once on the boot core after the DTB has been validated.
+pub fn cbo_init(dtb_phys_addr: usize, dtb_size: usize) {
+    let mut probe = cbo_probe_t {
+        sizes_match: true,
+        ..Default::default()
+    };
+    fdt_for_each_prop(dtb_phys_addr, dtb_size, |node, name, value, len| {
+        if node != probe.node {
+            probe.end_node();
+            probe.node = node;
+        }
+        if fdt_cstr_eq(name, b"riscv,isa") {
+            probe.node_is_hart = true;
+            probe.node_cbom |= isa_string_has(value, len, b"zicbom");
+            probe.node_cboz |= isa_string_has(value, len, b"zicboz");
+        } else if fdt_cstr_eq(name, b"riscv,isa-extensions") {
+            probe.node_is_hart = true;
+            probe.node_cbom |= string_list_has(value, len, b"zicbom");
+            probe.node_cboz |= string_list_has(value, len, b"zicboz");
+        } else if fdt_cstr_eq(name, b"riscv,cbom-block-size") && len == 4 {
+            probe.sizes_match &= merge_block_size(&mut probe.cbom_block_size, value);
+        } else if fdt_cstr_eq(name, b"riscv,cboz-block-size") && len == 4 {
+            probe.sizes_match &= merge_block_size(&mut probe.cboz_block_size, value);
+        }
+    });
+    probe.end_node();
+    if !probe.sizes_match {
+        info!("cache block sizes differ between harts, not using Zicbom/Zicboz");
+        return;
+    }
+    unsafe {
+        ksCbomBlockSize = probe.usable(probe.cbom_harts, probe.cbom_block_size);
+        ksCbozBlockSize = probe.usable(probe.cboz_harts, probe.cboz_block_size);
+        info!(
+            "Zicbom block size {}, Zicboz block size {} (0 for not used)",
+            ksCbomBlockSize, ksCbozBlockSize
+        );
+    }
+}
+
+/// Zero the `BIT!(bits)` bytes at `ptr` with `cbo.zero`.
+///
+/// # Returns
+///
+/// false if Zicboz is not used or the region is not made of whole blocks, and nothing was zeroed
+pub fn cbo_zero(ptr: usize, bits: usize) -> bool {
+    let block = unsafe { ksCbozBlockSize };
+    if block == 0 || BIT!(bits) < block || ptr & (block - 1) != 0 {
+        return false;
+    }
+    let mut addr = ptr;
+    while addr < ptr + BIT!(bits) {
+        unsafe {
+            asm!(".insn i 0x0f, 2, x0, {}, 4", in(reg) addr);
+        }
+        addr += block;
+    }
+    true
+}
+
+/// Apply `op` to the cache blocks of the kernel addresses `[start, end)`.
+///
+/// # Returns
+///
+/// false if Zicbom is not used, and nothing was done
+pub fn cbo_range(op: cbo_op_t, start: usize, end: usize) -> bool {
+    let block = unsafe { ksCbomBlockSize };
+    if block == 0 {
+        return false;
+    }
+    let mut addr = start & !(block - 1);
+    while addr < end {
+        unsafe {
+            match op {
+                cbo_op_t::Inval => asm!(".insn i 0x0f, 2, x0, {}, 0", in(reg) addr),
+                cbo_op_t::Clean => asm!(".insn i 0x0f, 2, x0, {}, 1", in(reg) addr),
+                cbo_op_t::Flush => asm!(".insn i 0x0f, 2, x0, {}, 2", in(reg) addr),
+            }
+        }
+        addr += block;
+    }
+    unsafe {
+        asm!("fence rw, rw");
+    }
+    true
+}
+
+/// Apply `op` to the cache blocks of the user addresses `[start, end)` of the vspace rooted at
+/// `root`, through the kernel window. Pages that are not mapped are skipped.
+///
+/// # Returns
+///
+/// false if Zicbom is not used, and nothing was done
+pub fn cbo_vspace_range(op: cbo_op_t, root: &mut PTE, start: usize, end: usize) -> bool {
+    if unsafe { ksCbomBlockSize } == 0 {
+        return false;
+    }
+    let mut vaddr = start;
+    while vaddr < end {
+        let ret = root.lookup_pt_slot(vaddr);
+        let pte = unsafe { &*ret.ptSlot };
+        let page_end = end.min((vaddr | MASK!(ret.ptBitsLeft)) + 1);
+        if !pte.is_pte_table() && PTEFlags::from_bits_truncate(pte.0).contains(PTEFlags::V) {
+            let kstart =
+                paddr_to_pptr(pte.get_ppn() << seL4_PageBits) + (vaddr & MASK!(ret.ptBitsLeft));
+            cbo_range(op, kstart, kstart + (page_end - vaddr));
+        }
+        vaddr = page_end;
+    }
+    true
+}
@@ -2,14 +2,53 @@ use super::read_stval;
 use crate::config::*;
 use crate::kernel::boot::current_fault;
 use crate::syscall::handle_fault;
+use sel4_common::arch::restart::riscv_instruction_len;
 use sel4_common::fault::seL4_Fault_t;
+use sel4_common::sel4_config::tcbVTable;
 use sel4_common::structures::exception_t;
+use sel4_common::utils::{convert_to_mut_type_ref, convert_to_type_ref};
+use sel4_cspace::interface::CapTag;
 use sel4_task::{activateThread, get_currenct_thread, schedule};
+use sel4_vspace::PTE;
 
+/// Length of the instruction of the current thread which raised the exception `cause`
+///
+/// For an illegal instruction `stval` holds the instruction, if the core reports it. Otherwise the
+/// lowest parcel is read from the user mapping at the restart pc. Falls back to 4 bytes when the
+/// instruction cannot be read.
+fn fault_instruction_len(cause: usize) -> usize {
+    if cause == RISCVInstructionIllegal {
+        let bits = read_stval();
+        if bits != 0 {
+            return riscv_instruction_len(bits as u16);
+        }
+    }
+    let thread = get_currenct_thread();
+    let vspace_root = thread.get_cspace(tcbVTable).cap;
+    if vspace_root.get_cap_type() != CapTag::CapPageTableCap {
+        return 4;
+    }
+    let pc = thread.tcbArch.get_restart_pc();
+    match convert_to_mut_type_ref::<PTE>(vspace_root.get_pt_base_ptr()).lookup_user_text(pc) {
+        Some(ptr) => riscv_instruction_len(*convert_to_type_ref::<u16>(ptr)),
+        None => 4,
+    }
+}
+
+/// Raise a user exception fault for the exception `w_a`.
+///
+/// Illegal instructions and breakpoints may be compressed (RVC) instructions, so their length is
+/// reported in the code word. The thread resumes at the pc of the handler's reply, which is the
+/// restart pc `FaultIP` of the fault message: a handler replying with it unchanged executes the
+/// instruction again, one which skips the instruction replies with `FaultIP` plus the length.
 #[no_mangle]
 pub fn handleUserLevelFault(w_a: usize, w_b: usize) -> exception_t {
+    let mut code = w_b;
+    if w_a == RISCVInstructionIllegal || w_a == RISCVBreakPoint {
+        code = fault_instruction_len(w_a);
+    }
     unsafe {
-        current_fault = seL4_Fault_t::new_user_exeception(w_a, w_b);
+        current_fault = seL4_Fault_t::new_user_exeception(w_a, code);
         handle_fault(get_currenct_thread());
     }
     schedule();
@@ -49,6 +88,48 @@ pub fn handle_vm_fault(type_: usize) -> exception_t {
             }
             exception_t::EXCEPTION_FAULT
         }
+        #[cfg(feature = "ENABLE_RISCV_HYP")]
+        RISCVInstructionGuestPageFault | RISCVLoadGuestPageFault | RISCVStoreGuestPageFault => {
+            unsafe {
+                current_fault = guest_page_fault(addr, type_);
+            }
+            exception_t::EXCEPTION_FAULT
+        }
         _ => panic!("Invalid VM fault type:{}", type_),
     }
 }
+
+/// The fault of a guest page fault `cause` at the guest virtual address `addr`. `htval` holds the
+/// guest physical address shifted right by 2, and `htinst` the transformed load or store if the
+/// core reports it, from which the width of the access is taken.
+#[cfg(feature = "ENABLE_RISCV_HYP")]
+fn guest_page_fault(addr: usize, cause: usize) -> seL4_Fault_t {
+    use sel4_common::arch::sysreg::{htinst, htval};
+    let ipa = (htval::read() << 2) | (addr & 0b11);
+    let inst = htinst::read() & 0xffff_ffff;
+    let width = if cause != RISCVInstructionGuestPageFault && inst & 1 != 0 {
+        1 << ((inst >> 12) & 0b11)
+    } else {
+        0
+    };
+    seL4_Fault_t::new_guest_vm_fault(
+        ipa,
+        inst,
+        width,
+        (cause == RISCVStoreGuestPageFault) as usize,
+    )
+}
+
+/// Entry of an exception of a thread with a VCPU the VMM emulates, an SBI call of the guest or a
+/// virtual instruction. `sepc` still points at the instruction, the VMM moves past it.
+#[cfg(feature = "ENABLE_RISCV_HYP")]
+#[no_mangle]
+pub fn handleVCPUFault(cause: usize) -> exception_t {
+    unsafe {
+        current_fault = seL4_Fault_t::new_vcpu_fault(read_stval(), cause);
+        handle_fault(get_currenct_thread());
+    }
+    schedule();
+    activateThread();
+    exception_t::EXCEPTION_NONE
+}
@@ -1,32 +1,39 @@
+#[cfg(feature = "ENABLE_RISCV_AIA")]
+pub mod aia;
 mod boot;
 mod c_traps;
+mod cbo;
 mod exception;
 mod platform;
+#[cfg(feature = "ENABLE_RISCV_PLIC")]
+pub mod plic;
+#[cfg(feature = "ENABLE_RISCV_PMP")]
+mod pmp;
+#[cfg(feature = "ENABLE_RISCV_HYP")]
+mod vcpu;
 
 pub use boot::try_init_kernel;
+#[cfg(feature = "ENABLE_SMP")]
+pub use boot::try_init_kernel_secondary_core;
 pub use c_traps::restore_user_context;
+pub use cbo::{cbo_init, cbo_op_t, cbo_range, cbo_vspace_range, cbo_zero};
 use core::arch::asm;
 pub use platform::{init_cpu, init_freemem};
+#[cfg(feature = "ENABLE_RISCV_PMP")]
+pub use pmp::pmp_shield_kernel;
+#[cfg(feature = "ENABLE_RISCV_HYP")]
+pub use vcpu::{decode_vcpu_invocation, vcpu_boot_init, vcpu_switch, vcpu_t};
 
-use crate::config::RESET_CYCLES;
-use sel4_common::arch::set_timer;
+use sel4_common::arch::sysreg::{scause, sip, stval};
 
 core::arch::global_asm!(include_str!("restore_fp.S"));
 
 pub fn read_stval() -> usize {
-    let temp: usize;
-    unsafe {
-        asm!("csrr {}, stval",out(reg)temp);
-    }
-    temp
+    stval::read()
 }
 
 pub fn read_sip() -> usize {
-    let temp: usize;
-    unsafe {
-        asm!("csrr {}, sip",out(reg)temp);
-    }
-    temp
+    sip::read()
 }
 
 pub fn read_time() -> usize {
@@ -38,19 +45,5 @@ pub fn read_time() -> usize {
 }
 
 pub fn read_scause() -> usize {
-    let temp: usize;
-    unsafe {
-        asm!("csrr {}, scause",out(reg)temp);
-    }
-    temp
-}
-
-#[no_mangle]
-pub fn resetTimer() {
-    let mut target = read_time() + RESET_CYCLES;
-    set_timer(target);
-    while read_time() > target {
-        target = read_time() + RESET_CYCLES;
-        set_timer(target);
-    }
+    scause::read()
 }
@@ -1,8 +1,5 @@
-use riscv::register::{stvec, utvec::TrapMode};
-use sel4_common::{
-    arch::{config::KERNEL_ELF_BASE, get_time, set_timer},
-    BIT,
-};
+use sel4_common::arch::config::KERNEL_ELF_BASE;
+use sel4_common::arch::sysreg::{sie, stvec};
 use sel4_vspace::activate_kernel_vspace;
 
 use crate::boot::paddr_to_pptr_reg;
@@ -10,10 +7,6 @@ use crate::boot::rust_init_freemem;
 use crate::boot::{avail_p_regs_addr, avail_p_regs_size, res_reg};
 use crate::config::*;
 use crate::structures::*;
-use crate::{
-    config::{RESET_CYCLES, SIE_SEIE, SIE_STIE},
-    interrupt::set_sie_mask,
-};
 use log::debug;
 use sel4_vspace::*;
 
@@ -23,17 +16,23 @@ pub fn init_cpu() {
         fn trap_entry();
     }
     unsafe {
-        stvec::write(trap_entry as usize, TrapMode::Direct);
+        stvec::write(stvec::MODE.set(trap_entry as usize, 0));
     }
+    #[cfg(feature = "ENABLE_RISCV_AIA")]
+    super::aia::aia_init_local();
+    #[cfg(feature = "ENABLE_RISCV_PLIC")]
+    super::plic::plic_init_local();
     #[cfg(feature = "ENABLE_SMP")]
-    {
-        set_sie_mask(BIT!(SIE_SEIE) | BIT!(SIE_STIE) | BIT!(SIE_SSIE));
+    unsafe {
+        sie::set_bits(sie::SEIE.mask() | sie::STIE.mask() | sie::SSIE.mask());
     }
     #[cfg(not(feature = "ENABLE_SMP"))]
-    {
-        set_sie_mask(BIT!(SIE_SEIE) | BIT!(SIE_STIE));
+    unsafe {
+        sie::set_bits(sie::SEIE.mask() | sie::STIE.mask());
     }
-    set_timer(get_time() + RESET_CYCLES);
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    super::vcpu_boot_init();
+    crate::interrupt::timer::resetTimer();
 }
 
 pub fn init_freemem(ui_reg: region_t, dtb_p_reg: p_region_t) -> bool {
@@ -0,0 +1,161 @@
+//! The RISC-V PLIC, used with the `ENABLE_RISCV_PLIC` feature.
+//!
+//! The PLIC is mapped by `map_kernel_devices` as the first kernel device. Source `n` is the kernel
+//! IRQ `PLIC_IRQ_BASE + n` and is enabled in the S-mode context of a single hart, that of the boot
+//! hart `CONFIG_FIRST_HART_ID` unless the IRQ was routed to another core. The enables of every
+//! context are memory mapped, so routing takes effect at once on any core.
+
+#[cfg(all(feature = "ENABLE_RISCV_AIA", feature = "ENABLE_RISCV_PLIC"))]
+compile_error!("ENABLE_RISCV_AIA maps the APLIC in place of the PLIC of ENABLE_RISCV_PLIC");
+
+use crate::config::{
+    irqInvalid, CONFIG_PLIC_NUM_SOURCES, INT_STATE_ARRAY_SIZE, KDEV_BASE, PLIC_IRQ_BASE,
+};
+use crate::interrupt::get_irq_target_core;
+use sel4_common::mmio::mmio_t;
+use sel4_common::utils::cpu_id;
+use tock_registers::interfaces::{Readable, Writeable};
+use tock_registers::register_structs;
+use tock_registers::registers::ReadWrite;
+
+const PLIC_PPTR: usize = KDEV_BASE;
+const PLIC_ENABLE_OFFSET: usize = 0x2000;
+const PLIC_ENABLE_STRIDE: usize = 0x80;
+const PLIC_CONTEXT_OFFSET: usize = 0x20_0000;
+const PLIC_CONTEXT_STRIDE: usize = 0x1000;
+
+const PLIC_ENABLE_WORDS: usize = CONFIG_PLIC_NUM_SOURCES.div_ceil(32);
+
+register_structs! {
+    /// The priority of each source, source 0 has none
+    #[allow(non_snake_case)]
+    pub Plic_Priority_Regs {
+        (0x0000 => priority: [ReadWrite<u32>; 1024]),
+        (0x1000 => @END),
+    }
+}
+
+register_structs! {
+    /// The enables of the sources in one context
+    #[allow(non_snake_case)]
+    pub Plic_Enable_Regs {
+        (0x0000 => enable: [ReadWrite<u32>; 32]),
+        (0x0080 => @END),
+    }
+}
+
+register_structs! {
+    /// The priority threshold and the claim/complete register of one context
+    #[allow(non_snake_case)]
+    pub Plic_Context_Regs {
+        (0x0000 => threshold: ReadWrite<u32>),
+        (0x0004 => claim: ReadWrite<u32>),
+        (0x0008 => @END),
+    }
+}
+
+static PLIC_PRIORITY: mmio_t<Plic_Priority_Regs> = mmio_t::new(PLIC_PPTR);
+
+/// The S-mode context of `hart`, each hart has an M-mode context before it
+#[inline]
+const fn hart_context(hart: usize) -> usize {
+    2 * hart + 1
+}
+
+/// The hart which takes the IRQs routed to `core`
+#[inline]
+fn core_hart(core: usize) -> usize {
+    #[cfg(feature = "ENABLE_SMP")]
+    {
+        sel4_common::smp::cpu_index_to_id(core)
+    }
+    #[cfg(not(feature = "ENABLE_SMP"))]
+    {
+        let _ = core;
+        crate::config::CONFIG_FIRST_HART_ID
+    }
+}
+
+#[inline]
+fn enables(core: usize) -> mmio_t<Plic_Enable_Regs> {
+    let context = hart_context(core_hart(core));
+    mmio_t::new(PLIC_PPTR + PLIC_ENABLE_OFFSET + context * PLIC_ENABLE_STRIDE)
+}
+
+#[inline]
+fn context_regs(core: usize) -> mmio_t<Plic_Context_Regs> {
+    let context = hart_context(core_hart(core));
+    mmio_t::new(PLIC_PPTR + PLIC_CONTEXT_OFFSET + context * PLIC_CONTEXT_STRIDE)
+}
+
+/// Whether `irq` is a PLIC source
+#[inline]
+pub fn is_plic_irq(irq: usize) -> bool {
+    irq > PLIC_IRQ_BASE && irq < INT_STATE_ARRAY_SIZE
+}
+
+/// Disable every source in the context of the current hart and take every priority above 0 in it.
+/// The boot core also gives each source priority 1, the lowest one which is taken.
+pub fn plic_init_local() {
+    let core = cpu_id();
+    if core == 0 {
+        for source in 1..CONFIG_PLIC_NUM_SOURCES {
+            PLIC_PRIORITY.regs().priority[source].set(1);
+        }
+    }
+    let enables = enables(core);
+    for word in 0..PLIC_ENABLE_WORDS {
+        enables.regs().enable[word].set(0);
+    }
+    context_regs(core).regs().threshold.set(0);
+}
+
+fn set_enabled(core: usize, source: usize, enable: bool) {
+    let enables = enables(core);
+    let reg = &enables.regs().enable[source / 32];
+    let bit = 1 << (source % 32);
+    match enable {
+        true => reg.set(reg.get() | bit),
+        false => reg.set(reg.get() & !bit),
+    }
+}
+
+fn is_enabled(core: usize, source: usize) -> bool {
+    enables(core).regs().enable[source / 32].get() & (1 << (source % 32)) != 0
+}
+
+/// Enable the IRQ for the hart of the core it is routed to
+pub fn irq_enable(irq: usize) {
+    set_enabled(get_irq_target_core(irq), irq - PLIC_IRQ_BASE, true);
+}
+
+/// Disable the IRQ, it stays pending if it was
+pub fn irq_disable(irq: usize) {
+    set_enabled(get_irq_target_core(irq), irq - PLIC_IRQ_BASE, false);
+}
+
+/// Move the enable of `irq` from the context of the hart of core `from` to that of `to`. A claim
+/// the hart of `from` has not completed yet holds the source back until it is.
+pub fn irq_set_target_core(irq: usize, from: usize, to: usize) {
+    let source = irq - PLIC_IRQ_BASE;
+    if from != to && is_enabled(from, source) {
+        set_enabled(from, source, false);
+        set_enabled(to, source, true);
+    }
+}
+
+/// Claim the highest pending enabled source of the current hart, `irqInvalid` for none.
+pub fn plic_claim() -> usize {
+    match context_regs(cpu_id()).regs().claim.get() as usize {
+        0 => irqInvalid,
+        source => PLIC_IRQ_BASE + source,
+    }
+}
+
+/// Complete the claim of `irq` by the current hart, the PLIC sends the source again from then on.
+pub fn plic_complete(irq: usize) {
+    context_regs(cpu_id())
+        .regs()
+        .claim
+        .set((irq - PLIC_IRQ_BASE) as u32);
+}
@@ -0,0 +1,122 @@
+//! PMP shield of the kernel image, enabled by the `ENABLE_RISCV_PMP` feature.
+//!
+//! reL4 runs in S-mode and cannot program PMP itself, so the kernel only describes the region
+//! to shield and hands the PMP entries to the platform through `plat_pmp_shield`. A platform
+//! implements the hook with whatever its M-mode firmware offers, e.g. a vendor SBI call or a
+//! firmware domain that excludes the region from every other hart. PMP only checks accesses of
+//! harts; IOPMP units in front of DMA masters take entries of the same format, so a platform can
+//! install the same entries there.
+//!
+//! Only the part of the image the kernel keeps is shielded: the boot part before `ki_boot_end`
+//! is handed to the root server as untyped memory once the kernel is up.
+
+use log::debug;
+use sel4_vspace::kpptr_to_paddr;
+
+use crate::boot::{avail_p_regs_addr, avail_p_regs_size};
+use crate::structures::p_region_t;
+
+/// Match from the address of the previous entry up to this one
+pub const PMP_A_TOR: u8 = 1 << 3;
+/// Match a naturally aligned power of two region
+pub const PMP_A_NAPOT: u8 = 3 << 3;
+
+/// The least PMP granularity, 4 bytes
+const PMP_SHIFT: usize = 2;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+/// A PMP entry: the `pmpcfg` byte and the `pmpaddr` value, entries match in array order
+pub struct pmp_entry_t {
+    pub cfg: u8,
+    pub addr: usize,
+}
+
+/// Platform hook: keep every master but the kernel out of the memory matched by the `count`
+/// entries at `entries`, whose permissions are cleared. Overridden by a strong definition in
+/// the platform code, the default cannot reach the firmware and fails.
+///
+/// # Returns
+///
+/// Whether the entries are in place.
+#[no_mangle]
+#[linkage = "weak"]
+pub extern "C" fn plat_pmp_shield(_entries: *const pmp_entry_t, _count: usize) -> bool {
+    false
+}
+
+/// Encode `reg` as PMP entries with the `pmpcfg` permission bits `perm`: one NAPOT entry if `reg` is a
+/// naturally aligned power of two, a TOR pair otherwise.
+///
+/// # Returns
+///
+/// The entries and how many of them are used.
+pub fn pmp_region_entries(reg: &p_region_t, perm: u8) -> ([pmp_entry_t; 2], usize) {
+    let mut entries = [pmp_entry_t::default(); 2];
+    let size = reg.end - reg.start;
+    if size.is_power_of_two() && size >= 8 && reg.start % size == 0 {
+        entries[0] = pmp_entry_t {
+            cfg: PMP_A_NAPOT | perm,
+            addr: (reg.start >> PMP_SHIFT) | ((size >> 3) - 1),
+        };
+        (entries, 1)
+    } else {
+        entries[0] = pmp_entry_t {
+            cfg: 0,
+            addr: reg.start >> PMP_SHIFT,
+        };
+        entries[1] = pmp_entry_t {
+            cfg: PMP_A_TOR | perm,
+            addr: reg.end >> PMP_SHIFT,
+        };
+        (entries, 2)
+    }
+}
+
+/// Whether `reg` lies in one region of the platform memory map.
+fn in_memory_map(reg: &p_region_t) -> bool {
+    let avail = avail_p_regs_addr as *const p_region_t;
+    unsafe {
+        (0..avail_p_regs_size)
+            .map(|i| *avail.add(i))
+            .any(|known| known.start <= reg.start && reg.end <= known.end)
+    }
+}
+
+/// Shield the kernel image after `ki_boot_end` from every master but the kernel.
+pub fn pmp_shield_kernel(ki_boot_end: usize) -> bool {
+    extern "C" {
+        fn ki_end();
+    }
+    let reg = p_region_t {
+        start: kpptr_to_paddr(ki_boot_end),
+        end: kpptr_to_paddr(ki_end as usize),
+    };
+    if reg.start % (1 << PMP_SHIFT) != 0 || reg.end % (1 << PMP_SHIFT) != 0 {
+        debug!(
+            "ERROR: kernel image [{:#x}..{:#x}] is not PMP aligned",
+            reg.start, reg.end
+        );
+        return false;
+    }
+    if !in_memory_map(&reg) {
+        debug!(
+            "ERROR: kernel image [{:#x}..{:#x}] is not in the memory map",
+            reg.start, reg.end
+        );
+        return false;
+    }
+    let (entries, count) = pmp_region_entries(&reg, 0);
+    if !plat_pmp_shield(entries.as_ptr(), count) {
+        debug!(
+            "ERROR: the platform could not shield the kernel image [{:#x}..{:#x}]",
+            reg.start, reg.end
+        );
+        return false;
+    }
+    debug!(
+        "kernel image [{:#x}..{:#x}] shielded with {} PMP entries",
+        reg.start, reg.end, count
+    );
+    true
+}
@@ -0,0 +1,443 @@
+//! Virtual CPUs of guests on the H extension, enabled by the `ENABLE_RISCV_HYP` feature.
+//!
+//! A VCPU object is bound to a thread with `RISCVVCPUSetTCB`. The thread then runs in VS-mode or
+//! VU-mode on the G-stage table at the start of the VCPU, see `sel4_vspace::stage2`, and its
+//! general purpose registers are those of the guest. The VS CSRs and `hvip` are loaded into the
+//! core when the thread is switched to and saved as soon as another thread runs there, so a VCPU
+//! is only loaded on the core its thread is running on.
+//!
+//! The guest handles its own VS-level interrupts and the exceptions in `hedeleg`. SBI calls,
+//! virtual instruction exceptions and guest page faults go to the fault handler of the thread,
+//! which injects the VS-level interrupts of the devices it emulates with `RISCVVCPUInjectIRQ`.
+
+use sel4_common::arch::sysreg::{
+    hcounteren, hedeleg, hideleg, hstatus, hvip, vsatp, vscause, vsepc, vsie, vsscratch, vsstatus,
+    vstval, vstvec,
+};
+use sel4_common::arch::{ArchReg, MessageLabel};
+use sel4_common::message_info::seL4_MessageInfo_t;
+use sel4_common::sel4_config::{
+    asidLowBits, seL4_IllegalOperation, seL4_InvalidArgument, seL4_InvalidCapability,
+    seL4_TruncatedMessage, CONFIG_MAX_NUM_NODES,
+};
+use sel4_common::structures::{exception_t, seL4_IPCBuffer};
+use sel4_common::utils::{convert_to_mut_type_ref, convert_to_option_mut_type_ref, cpu_id};
+use sel4_common::{BIT, MASK};
+use sel4_cspace::interface::{cap_t, CapTag};
+use sel4_task::{get_currenct_thread, tcb_t, ThreadStateMachine};
+use sel4_vspace::{asid_pool_t, asid_t, gstage_root_t, is_guest_root_of_asid, set_guest_vm_root};
+
+use crate::kernel::boot::{current_syscall_error, get_extra_cap_by_index};
+use crate::syscall::get_syscall_arg;
+
+/// `loaded_core` of a VCPU that is not loaded on any core
+const VCPU_NOT_LOADED: usize = usize::MAX;
+
+/// The exceptions of VS-mode and VU-mode the guest handles: misaligned fetches, breakpoints,
+/// ecalls from VU-mode and VS-stage page faults
+const HEDELEG_GUEST: usize = BIT!(0) | BIT!(3) | BIT!(8) | BIT!(12) | BIT!(13) | BIT!(15);
+
+/// The VS-level software, timer and external interrupts, in `hvip` and `hideleg`
+const HVIP_VS_IRQS: usize = BIT!(2) | BIT!(6) | BIT!(10);
+
+/// The VS CSRs of a guest, indexed by the register number of `RISCVVCPUReadReg` and
+/// `RISCVVCPUWriteReg`
+#[derive(Clone, Copy)]
+enum VCPUReg {
+    VSStatus = 0,
+    VSIE,
+    VSTVec,
+    VSScratch,
+    VSEPC,
+    VSCause,
+    VSTVal,
+    VSATP,
+}
+
+const VCPU_REG_NUM: usize = VCPUReg::VSATP as usize + 1;
+
+impl VCPUReg {
+    fn from_usize(reg: usize) -> Option<Self> {
+        const REGS: [VCPUReg; VCPU_REG_NUM] = [
+            VCPUReg::VSStatus,
+            VCPUReg::VSIE,
+            VCPUReg::VSTVec,
+            VCPUReg::VSScratch,
+            VCPUReg::VSEPC,
+            VCPUReg::VSCause,
+            VCPUReg::VSTVal,
+            VCPUReg::VSATP,
+        ];
+        REGS.get(reg).copied()
+    }
+
+    fn read_hw(self) -> usize {
+        match self {
+            VCPUReg::VSStatus => vsstatus::read(),
+            VCPUReg::VSIE => vsie::read(),
+            VCPUReg::VSTVec => vstvec::read(),
+            VCPUReg::VSScratch => vsscratch::read(),
+            VCPUReg::VSEPC => vsepc::read(),
+            VCPUReg::VSCause => vscause::read(),
+            VCPUReg::VSTVal => vstval::read(),
+            VCPUReg::VSATP => vsatp::read(),
+        }
+    }
+
+    fn write_hw(self, value: usize) {
+        unsafe {
+            match self {
+                VCPUReg::VSStatus => vsstatus::write(value),
+                VCPUReg::VSIE => vsie::write(value),
+                VCPUReg::VSTVec => vstvec::write(value),
+                VCPUReg::VSScratch => vsscratch::write(value),
+                VCPUReg::VSEPC => vsepc::write(value),
+                VCPUReg::VSCause => vscause::write(value),
+                VCPUReg::VSTVal => vstval::write(value),
+                VCPUReg::VSATP => vsatp::write(value),
+            }
+        }
+    }
+}
+
+/// The VCPU loaded on each core, 0 for none
+static mut riscvHSCurVCPU: [usize; CONFIG_MAX_NUM_NODES] = [0; CONFIG_MAX_NUM_NODES];
+
+/// A VCPU object, `seL4_VCPUBits` in size.
+#[repr(C)]
+pub struct vcpu_t {
+    /// The G-stage root of the guest, first for its alignment
+    pub gstage: gstage_root_t,
+    /// The thread the VCPU is bound to, 0 for none
+    pub vcpuTCB: usize,
+    /// The ASID the G-stage root was assigned to, 0 for none
+    pub asid: asid_t,
+    /// The core the VCPU is loaded on, `VCPU_NOT_LOADED` when its state is in `regs` and `hvip`
+    loaded_core: usize,
+    regs: [usize; VCPU_REG_NUM],
+    hvip: usize,
+    /// The `hvip` bits injected or cleared while the VCPU was loaded on another core, to be
+    /// applied by that core
+    hvip_set: usize,
+    hvip_clear: usize,
+}
+
+impl vcpu_t {
+    #[inline]
+    pub fn get_ptr(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Initialise the VCPU, which was just retyped and is zeroed.
+    pub fn init(&mut self) {
+        self.vcpuTCB = 0;
+        self.asid = 0;
+        self.loaded_core = VCPU_NOT_LOADED;
+        self.regs = [0; VCPU_REG_NUM];
+        self.hvip = 0;
+        self.hvip_set = 0;
+        self.hvip_clear = 0;
+    }
+
+    /// The G-stage root, if it is still assigned to its ASID.
+    fn guest_root(&self) -> Option<&gstage_root_t> {
+        is_guest_root_of_asid(&self.gstage, self.asid).then_some(&self.gstage)
+    }
+
+    /// Apply the interrupts injected from another core to `hvip`.
+    fn flush_hvip(&mut self) {
+        unsafe {
+            hvip::clear_bits(self.hvip_clear);
+            hvip::set_bits(self.hvip_set);
+        }
+        self.hvip_set = 0;
+        self.hvip_clear = 0;
+    }
+
+    /// Save the VCPU from the current core.
+    fn save(&mut self) {
+        self.flush_hvip();
+        for (i, reg) in self.regs.iter_mut().enumerate() {
+            *reg = VCPUReg::from_usize(i).unwrap().read_hw();
+        }
+        self.hvip = hvip::read();
+        unsafe {
+            hvip::write(0);
+        }
+        self.loaded_core = VCPU_NOT_LOADED;
+        unsafe {
+            riscvHSCurVCPU[cpu_id()] = 0;
+        }
+    }
+
+    /// Load the VCPU on the current core.
+    fn restore(&mut self) {
+        for (i, reg) in self.regs.iter().enumerate() {
+            VCPUReg::from_usize(i).unwrap().write_hw(*reg);
+        }
+        unsafe {
+            hvip::write(self.hvip);
+        }
+        self.hvip_set = 0;
+        self.hvip_clear = 0;
+        self.loaded_core = cpu_id();
+        unsafe {
+            riscvHSCurVCPU[cpu_id()] = self.get_ptr();
+        }
+    }
+
+    /// Take the VCPU off the core it is loaded on. On another core, which only happens while its
+    /// thread runs there, the state of the guest since the thread was switched to is lost.
+    fn unload(&mut self) {
+        if self.loaded_core == cpu_id() {
+            self.save();
+        } else if self.loaded_core != VCPU_NOT_LOADED {
+            unsafe {
+                riscvHSCurVCPU[self.loaded_core] = 0;
+            }
+            self.loaded_core = VCPU_NOT_LOADED;
+            self.hvip_set = 0;
+            self.hvip_clear = 0;
+        }
+    }
+
+    /// Unbind the VCPU from its thread, if it has one.
+    pub fn dissociate(&mut self) {
+        self.unload();
+        if let Some(tcb) = convert_to_option_mut_type_ref::<tcb_t>(self.vcpuTCB) {
+            tcb.tcbArch.set_vcpu(0);
+        }
+        self.vcpuTCB = 0;
+    }
+
+    /// Bind the VCPU to `tcb`, unbinding both from what they were bound to before.
+    pub fn associate(&mut self, tcb: &mut tcb_t) {
+        if let Some(old) = convert_to_option_mut_type_ref::<vcpu_t>(tcb.tcbArch.get_vcpu()) {
+            old.dissociate();
+        }
+        self.dissociate();
+        self.vcpuTCB = tcb.get_ptr();
+        tcb.tcbArch.set_vcpu(self.get_ptr());
+    }
+
+    /// Assert the `hvip` bits of `mask` if `pending`, deassert them otherwise.
+    fn inject_irq(&mut self, mask: usize, pending: bool) {
+        if self.loaded_core == cpu_id() {
+            unsafe {
+                if pending {
+                    hvip::set_bits(mask);
+                } else {
+                    hvip::clear_bits(mask);
+                }
+            }
+        } else if self.loaded_core != VCPU_NOT_LOADED {
+            // The core applies it when it enters the kernel for the reschedule
+            if pending {
+                self.hvip_set |= mask;
+                self.hvip_clear &= !mask;
+            } else {
+                self.hvip_clear |= mask;
+                self.hvip_set &= !mask;
+            }
+            #[cfg(feature = "ENABLE_SMP")]
+            crate::smp::ipi::ipi_reschedule_mask(BIT!(self.loaded_core));
+        } else if pending {
+            self.hvip |= mask;
+        } else {
+            self.hvip &= !mask;
+        }
+    }
+
+    /// A VS CSR of the guest. A VCPU running on another core is read from its saved state.
+    fn read_reg(&self, reg: VCPUReg) -> usize {
+        if self.loaded_core == cpu_id() {
+            reg.read_hw()
+        } else {
+            self.regs[reg as usize]
+        }
+    }
+
+    /// Write a VS CSR of the guest. A VCPU running on another core is written in its saved state,
+    /// which that core overwrites when it saves the VCPU, so its thread should be stopped first.
+    fn write_reg(&mut self, reg: VCPUReg, value: usize) {
+        if self.loaded_core == cpu_id() {
+            reg.write_hw(value);
+        } else {
+            self.regs[reg as usize] = value;
+        }
+    }
+
+    /// Assign the G-stage root to `asid` of `pool`.
+    pub fn assign_asid(&mut self, pool: &mut asid_pool_t, asid: asid_t) {
+        self.asid = asid;
+        pool.set_vspace_by_index(
+            asid & MASK!(asidLowBits),
+            self.gstage.as_vspace_root() as usize,
+        );
+    }
+}
+
+/// Set up the delegation of guest traps on the current core.
+pub fn vcpu_boot_init() {
+    unsafe {
+        hedeleg::write(HEDELEG_GUEST);
+        hideleg::write(HVIP_VS_IRQS);
+        // cycle, time and instret
+        hcounteren::write(0b111);
+        hvip::write(0);
+    }
+}
+
+/// Load the VCPU at `new`, 0 for none, on the current core on the way to user mode, and return
+/// to the guest with `sret` if there is one.
+pub fn vcpu_switch(new: usize) {
+    let cur = unsafe { riscvHSCurVCPU[cpu_id()] };
+    if new != cur {
+        if let Some(vcpu) = convert_to_option_mut_type_ref::<vcpu_t>(cur) {
+            vcpu.save();
+        }
+    }
+    match convert_to_option_mut_type_ref::<vcpu_t>(new) {
+        Some(vcpu) => {
+            if new == cur {
+                vcpu.flush_hvip();
+            } else {
+                vcpu.restore();
+            }
+            // The ASID may have been deleted since the VCPU was loaded
+            set_guest_vm_root(vcpu.guest_root());
+            unsafe {
+                hstatus::set_bits(hstatus::SPV.mask());
+            }
+        }
+        None => unsafe {
+            hstatus::clear_bits(hstatus::SPV.mask());
+        },
+    }
+}
+
+/// Decode an invocation of the VCPU cap `cap`.
+pub fn decode_vcpu_invocation(
+    label: MessageLabel,
+    length: usize,
+    cap: &cap_t,
+    call: bool,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> exception_t {
+    let vcpu = convert_to_mut_type_ref::<vcpu_t>(cap.get_vcpu_ptr());
+    match label {
+        MessageLabel::RISCVVCPUSetTCB => decode_vcpu_set_tcb(vcpu),
+        MessageLabel::RISCVVCPUInjectIRQ => decode_vcpu_inject_irq(vcpu, length, buffer),
+        MessageLabel::RISCVVCPUReadReg => decode_vcpu_read_reg(vcpu, length, call, buffer),
+        MessageLabel::RISCVVCPUWriteReg => decode_vcpu_write_reg(vcpu, length, buffer),
+        _ => {
+            unsafe {
+                current_syscall_error._type = seL4_IllegalOperation;
+            }
+            exception_t::EXCEPTION_SYSCALL_ERROR
+        }
+    }
+}
+
+fn decode_vcpu_set_tcb(vcpu: &mut vcpu_t) -> exception_t {
+    let Some(tcb_slot) = get_extra_cap_by_index(0) else {
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return exception_t::EXCEPTION_SYSCALL_ERROR;
+    };
+    if tcb_slot.cap.get_cap_type() != CapTag::CapThreadCap {
+        unsafe {
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 1;
+        }
+        return exception_t::EXCEPTION_SYSCALL_ERROR;
+    }
+    get_currenct_thread().set_restart();
+    vcpu.associate(convert_to_mut_type_ref::<tcb_t>(tcb_slot.cap.get_tcb_ptr()));
+    exception_t::EXCEPTION_NONE
+}
+
+fn invalid_argument(arg: usize) -> exception_t {
+    unsafe {
+        current_syscall_error._type = seL4_InvalidArgument;
+        current_syscall_error.invalidArgumentNumber = arg;
+    }
+    exception_t::EXCEPTION_SYSCALL_ERROR
+}
+
+fn truncated_message() -> exception_t {
+    unsafe {
+        current_syscall_error._type = seL4_TruncatedMessage;
+    }
+    exception_t::EXCEPTION_SYSCALL_ERROR
+}
+
+/// The first message register is the VS-level interrupt as numbered in `sip`: 1 for software, 5
+/// for timer and 9 for external interrupts. The second one asserts it if nonzero and deasserts it
+/// otherwise, the guest sees it pending until the VMM does.
+fn decode_vcpu_inject_irq(
+    vcpu: &mut vcpu_t,
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> exception_t {
+    if length < 2 {
+        return truncated_message();
+    }
+    let irq = get_syscall_arg(0, buffer);
+    let pending = get_syscall_arg(1, buffer) != 0;
+    // `hvip` keeps the VS-level interrupts one bit above their `sip` counterparts
+    let mask = if irq < usize::BITS as usize - 1 {
+        BIT!(irq + 1)
+    } else {
+        0
+    };
+    if mask & HVIP_VS_IRQS == 0 {
+        return invalid_argument(0);
+    }
+    get_currenct_thread().set_restart();
+    vcpu.inject_irq(mask, pending);
+    exception_t::EXCEPTION_NONE
+}
+
+fn decode_vcpu_read_reg(
+    vcpu: &mut vcpu_t,
+    length: usize,
+    call: bool,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> exception_t {
+    if length < 1 {
+        return truncated_message();
+    }
+    let Some(reg) = VCPUReg::from_usize(get_syscall_arg(0, buffer)) else {
+        return invalid_argument(0);
+    };
+    let value = vcpu.read_reg(reg);
+    let thread = get_currenct_thread();
+    if call {
+        thread.tcbArch.set_register(ArchReg::Badge, 0);
+        let length = thread.set_mr(0, value);
+        thread.tcbArch.set_register(
+            ArchReg::MsgInfo,
+            seL4_MessageInfo_t::new(0, 0, 0, length).to_word(),
+        );
+    }
+    thread.set_restart();
+    exception_t::EXCEPTION_NONE
+}
+
+fn decode_vcpu_write_reg(
+    vcpu: &mut vcpu_t,
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> exception_t {
+    if length < 2 {
+        return truncated_message();
+    }
+    let Some(reg) = VCPUReg::from_usize(get_syscall_arg(0, buffer)) else {
+        return invalid_argument(0);
+    };
+    get_currenct_thread().set_restart();
+    vcpu.write_reg(reg, get_syscall_arg(1, buffer));
+    exception_t::EXCEPTION_NONE
+}
@@ -0,0 +1,236 @@
+//! Cycle accounting for the IPC paths, enabled by the `ENABLE_BENCHMARK` feature.
+//!
+//! Userland resets the counters with `SysBenchmarkResetLog`, runs its workload and reads them
+//! back with `SysBenchmarkFinalizeLog`, which returns them in the message registers:
+//! - `Msg(0)`, `Msg(1)`: completed `fastpath_call` count and cycles
+//! - `Msg(2)`, `Msg(3)`: endpoint receive count and cycles on the slowpath
+//!
+//! `SysBenchmarkGetEndpointStats` reads the counters of the endpoint named by the cap register,
+//! see `sel4_ipc::ep_stats`:
+//! - `Msg(0)`, `Msg(1)`: `Call` and `Send` count
+//! - `Msg(2)`: largest number of threads queued on the endpoint at once
+//!
+//! All three are zero if the cap is not an endpoint cap.
+//!
+//! `SysBenchmarkGetIdleTime` reads the time the core in the cap register spent in its idle thread,
+//! see `sel4_task::idle_time`, in timer counts:
+//! - `Msg(0)`: idle time since the last `SysBenchmarkResetLog`
+//! - `Msg(1)`: time since the last `SysBenchmarkResetLog`, or since the timer started before the
+//!   first one, so the core was busy for the rest
+//!
+//! Both are zero for a core that does not exist.
+//!
+//! For cold-cache baselines `SysBenchmarkFlushCaches` cleans and invalidates all cache levels by
+//! set/way if the cap register is 0, otherwise the level 1 caches of the cache type in the
+//! message info register: 1 for instruction, 2 for data, 3 for both. `SysBenchmarkFlushCachesRange`
+//! does the same by virtual address for `[Cap, MsgInfo)` of the address space of the caller, with
+//! the cache type in `Msg(0)`; pages that are not mapped are skipped. RISC-V has no cache
+//! maintenance by set/way, so there the first only orders memory accesses and unifies the
+//! instruction stream, and the second flushes the data cache with Zicbom where all harts have it,
+//! see `crate::arch::cbo_init`.
+
+use sel4_common::arch::{get_time, ArchReg};
+use sel4_common::sel4_config::{tcbVTable, CONFIG_MAX_NUM_NODES};
+use sel4_common::structures::exception_t;
+#[cfg(target_arch = "riscv64")]
+use sel4_common::utils::convert_to_mut_type_ref;
+#[cfg(target_arch = "aarch64")]
+use sel4_common::{sel4_config::PAGE_BITS, utils::pageBitsForSize, MASK};
+use sel4_cspace::interface::CapTag;
+use sel4_ipc::ep_stats::{ep_stats_get, ep_stats_reset, ep_stats_t};
+use sel4_task::get_currenct_thread;
+use sel4_task::idle_time::{idle_time_get, idle_time_reset};
+#[cfg(target_arch = "aarch64")]
+use sel4_vspace::{
+    clean_invalidate_d_range, clean_invalidate_l1_by_type, clean_invalidate_l1_caches,
+    invalidate_i_range, paddr_to_pptr, PGDE,
+};
+#[cfg(target_arch = "riscv64")]
+use sel4_vspace::{fence_i, fence_rw, PTE};
+
+#[cfg(target_arch = "riscv64")]
+use crate::arch::{cbo_op_t, cbo_vspace_range};
+use crate::config::USER_TOP;
+
+/// The instruction cache, as a cache type of the flush syscalls
+const CACHE_I: usize = 1;
+/// The data cache, as a cache type of the flush syscalls
+const CACHE_D: usize = 2;
+
+#[derive(Clone, Copy, Debug, Default)]
+/// Accumulated cycles spent in one kernel path
+pub struct benchmark_track_t {
+    /// Number of times the path was taken
+    pub count: usize,
+    /// Cycles spent in the path in total
+    pub cycles: usize,
+}
+
+impl benchmark_track_t {
+    const fn new() -> Self {
+        Self {
+            count: 0,
+            cycles: 0,
+        }
+    }
+
+    #[inline]
+    /// Account one pass through the path which started at `start`
+    pub fn record(&mut self, start: usize) {
+        self.count += 1;
+        self.cycles += timestamp().wrapping_sub(start);
+    }
+}
+
+/// When the log was last reset, in timer counts
+static mut ksBenchmarkResetTime: usize = 0;
+pub static mut ksBenchmarkFastpathCall: benchmark_track_t = benchmark_track_t::new();
+pub static mut ksBenchmarkRecvIPC: benchmark_track_t = benchmark_track_t::new();
+
+#[inline]
+/// Read the cycle counter of the current core
+pub fn timestamp() -> usize {
+    #[cfg(target_arch = "riscv64")]
+    {
+        riscv::register::cycle::read()
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        use aarch64_cpu::registers::{Readable, CNTVCT_EL0};
+        CNTVCT_EL0.get() as usize
+    }
+}
+
+pub fn benchmark_reset_log() {
+    unsafe {
+        ksBenchmarkFastpathCall = benchmark_track_t::new();
+        ksBenchmarkRecvIPC = benchmark_track_t::new();
+        ksBenchmarkResetTime = get_time();
+    }
+    ep_stats_reset();
+    idle_time_reset();
+}
+
+pub fn benchmark_finalize_log() {
+    let thread = get_currenct_thread();
+    let (fastpath, recv) = unsafe { (ksBenchmarkFastpathCall, ksBenchmarkRecvIPC) };
+    thread.tcbArch.set_register(ArchReg::Msg(0), fastpath.count);
+    thread
+        .tcbArch
+        .set_register(ArchReg::Msg(1), fastpath.cycles);
+    thread.tcbArch.set_register(ArchReg::Msg(2), recv.count);
+    thread.tcbArch.set_register(ArchReg::Msg(3), recv.cycles);
+}
+
+pub fn benchmark_get_endpoint_stats() {
+    let thread = get_currenct_thread();
+    let cptr = thread.tcbArch.get_register(ArchReg::Cap);
+    let lu_ret = thread.lookup_slot(cptr);
+    let mut stats = ep_stats_t::default();
+    if lu_ret.status == exception_t::EXCEPTION_NONE {
+        let cap = unsafe { (*lu_ret.slot).cap };
+        if cap.get_cap_type() == CapTag::CapEndpointCap {
+            stats = ep_stats_get(cap.get_ep_ptr());
+        }
+    }
+    thread.tcbArch.set_register(ArchReg::Msg(0), stats.calls);
+    thread.tcbArch.set_register(ArchReg::Msg(1), stats.sends);
+    thread
+        .tcbArch
+        .set_register(ArchReg::Msg(2), stats.queue_high_watermark);
+}
+
+pub fn benchmark_get_idle_time() {
+    let thread = get_currenct_thread();
+    let core = thread.tcbArch.get_register(ArchReg::Cap);
+    let (idle, elapsed) = if core < CONFIG_MAX_NUM_NODES {
+        (
+            idle_time_get(core),
+            get_time().wrapping_sub(unsafe { ksBenchmarkResetTime }),
+        )
+    } else {
+        (0, 0)
+    };
+    thread.tcbArch.set_register(ArchReg::Msg(0), idle);
+    thread.tcbArch.set_register(ArchReg::Msg(1), elapsed);
+}
+
+pub fn benchmark_flush_caches() {
+    let thread = get_currenct_thread();
+    let cache_type = match thread.tcbArch.get_register(ArchReg::Cap) {
+        0 => CACHE_I | CACHE_D,
+        _ => thread.tcbArch.get_register(ArchReg::MsgInfo),
+    };
+    #[cfg(target_arch = "aarch64")]
+    if thread.tcbArch.get_register(ArchReg::Cap) == 0 {
+        clean_invalidate_l1_caches();
+    } else {
+        clean_invalidate_l1_by_type(cache_type);
+    }
+    #[cfg(target_arch = "riscv64")]
+    riscv_flush(cache_type);
+}
+
+pub fn benchmark_flush_caches_range() {
+    let thread = get_currenct_thread();
+    let start = thread.tcbArch.get_register(ArchReg::Cap);
+    let end = thread.tcbArch.get_register(ArchReg::MsgInfo);
+    let cache_type = thread.tcbArch.get_register(ArchReg::Msg(0));
+    if end <= start || end > USER_TOP {
+        return;
+    }
+    let vspace_cap = thread.get_cspace(tcbVTable).cap;
+    #[cfg(target_arch = "aarch64")]
+    {
+        if !vspace_cap.is_valid_native_root() {
+            return;
+        }
+        let vspace_root = PGDE::new_from_pte(vspace_cap.get_pgd_base_ptr());
+        let mut vaddr = start;
+        while vaddr < end {
+            let frame = vspace_root.lookup_frame(vaddr);
+            let page_bits = match frame.valid {
+                true => pageBitsForSize(frame.frameSize),
+                false => PAGE_BITS,
+            };
+            let page_end = end.min((vaddr | MASK!(page_bits)) + 1);
+            if frame.valid {
+                // Through the kernel window, the caches are physically tagged
+                let kstart = paddr_to_pptr(frame.frameBase + (vaddr & MASK!(page_bits)));
+                let kend = kstart + (page_end - vaddr);
+                if cache_type & CACHE_D != 0 {
+                    clean_invalidate_d_range(kstart, kend);
+                }
+                if cache_type & CACHE_I != 0 {
+                    invalidate_i_range(kstart, kend);
+                }
+            }
+            vaddr = page_end;
+        }
+    }
+    #[cfg(target_arch = "riscv64")]
+    {
+        let flushed = cache_type & CACHE_D != 0
+            && vspace_cap.get_cap_type() == CapTag::CapPageTableCap
+            && cbo_vspace_range(
+                cbo_op_t::Flush,
+                convert_to_mut_type_ref::<PTE>(vspace_cap.get_pt_base_ptr()),
+                start,
+                end,
+            );
+        riscv_flush(match flushed {
+            true => cache_type & !CACHE_D,
+            false => cache_type,
+        });
+    }
+}
+
+#[cfg(target_arch = "riscv64")]
+fn riscv_flush(cache_type: usize) {
+    if cache_type & CACHE_D != 0 {
+        fence_rw();
+    }
+    if cache_type & CACHE_I != 0 {
+        fence_i();
+    }
+}
@@ -0,0 +1,33 @@
+//! Physical memory the kernel was booted with, kept for `ENABLE_MEMORY_HOTADD`.
+//!
+//! The available and reserved regions of the platform live in boot memory, which is handed to
+//! the root server once the kernel is up. A copy of them is kept here so that memory added later
+//! can be checked against everything the kernel already owns or gave out as RAM.
+
+use crate::config::MAX_NUM_RESV_REG;
+use crate::structures::p_region_t;
+
+static mut ksBootMemory: [p_region_t; MAX_NUM_RESV_REG] =
+    [p_region_t { start: 0, end: 0 }; MAX_NUM_RESV_REG];
+static mut ksBootMemoryCount: usize = 0;
+
+/// Record `reg` as memory known at boot, empty regions are skipped.
+pub fn boot_memory_record(reg: p_region_t) {
+    if reg.start >= reg.end {
+        return;
+    }
+    unsafe {
+        assert!(ksBootMemoryCount < MAX_NUM_RESV_REG);
+        ksBootMemory[ksBootMemoryCount] = reg;
+        ksBootMemoryCount += 1;
+    }
+}
+
+/// Whether `reg` overlaps memory known at boot.
+pub fn boot_memory_overlaps(reg: &p_region_t) -> bool {
+    unsafe {
+        ksBootMemory[..ksBootMemoryCount]
+            .iter()
+            .any(|known| known.start < reg.end && reg.start < known.end)
+    }
+}
@@ -0,0 +1,55 @@
+//! Pin the CPU frequency at boot for reproducible benchmarks, enabled by `ENABLE_BENCHMARK`.
+//!
+//! Firmware frequency scaling (DVFS) makes cycle counts vary from run to run. The platform layer
+//! may provide `plat_pin_cpu_frequency`, which programs the cores to a fixed frequency, e.g.
+//! through SCMI or the clock registers of the SoC, and returns the frequency it achieved in Hz.
+//! Platforms without DVFS keep the default, which pins nothing and returns 0.
+//!
+//! The achieved frequency is passed to the root server in the extra bootinfo as a
+//! `SEL4_BOOTINFO_HEADER_CPU_FREQ` chunk holding one word, 0 if the frequency was not pinned.
+
+use core::mem::size_of;
+
+use log::info;
+
+use crate::config::SEL4_BOOTINFO_HEADER_CPU_FREQ;
+use crate::structures::seL4_BootInfoHeader;
+
+#[link_section = ".boot.bss"]
+static mut ksCpuFreqHz: usize = 0;
+
+/// Platform hook: pin every core to a fixed frequency, returns the achieved frequency in Hz or 0
+/// if it is not pinned. Overridden by a strong definition in the platform code.
+#[no_mangle]
+#[linkage = "weak"]
+pub extern "C" fn plat_pin_cpu_frequency() -> usize {
+    0
+}
+
+/// Pin the CPU frequency and account for its bootinfo chunk in `extra_bi_size`.
+/// Must run after the platform is initialised.
+pub fn cpu_freq_pin(extra_bi_size: &mut usize) {
+    let hz = plat_pin_cpu_frequency();
+    if hz == 0 {
+        info!("CPU frequency not pinned, benchmark results may vary with DVFS");
+    } else {
+        info!("CPU frequency pinned at {} Hz", hz);
+    }
+    unsafe {
+        ksCpuFreqHz = hz;
+    }
+    *extra_bi_size += size_of::<seL4_BootInfoHeader>() + size_of::<usize>();
+}
+
+/// Write the frequency chunk at `dst` in the extra bootinfo, returns the bytes written.
+pub fn cpu_freq_write_bootinfo(dst: usize) -> usize {
+    let len = size_of::<seL4_BootInfoHeader>() + size_of::<usize>();
+    unsafe {
+        *(dst as *mut seL4_BootInfoHeader) = seL4_BootInfoHeader {
+            id: SEL4_BOOTINFO_HEADER_CPU_FREQ,
+            len,
+        };
+        *((dst + size_of::<seL4_BootInfoHeader>()) as *mut usize) = ksCpuFreqHz;
+    }
+    len
+}
@@ -0,0 +1,372 @@
+//! Discover the platform from the device tree the kernel was booted with, enabled by
+//! `ENABLE_DTB_PLATFORM`.
+//!
+//! Without it the memory of the platform is the one compiled in, see `pRegsToR`. With it
+//! [`dtb_platform_discover`] reads from the DTB:
+//! - the `reg` ranges of the `/memory` nodes, which replace the compiled in memory. Memory below
+//!   `physBase` is left out, it holds the firmware on RISC-V. A DTB without memory nodes keeps
+//!   the compiled in memory.
+//! - the children of `/reserved-memory` and the `/memreserve/` entries, which are cut out of the
+//!   memory. Those marked `no-map` and the `/memreserve/` entries are kept from user space, the
+//!   others are handed out as device untypeds like any memory the kernel does not use as RAM.
+//! - the UART of `/chosen/stdout-path`, a path or an alias of `/aliases`
+//! - the timer frequency, `timebase-frequency` of `/cpus` on RISC-V and `clock-frequency` of the
+//!   timer node on Arm, which is checked against `TIMER_CLOCK_HZ`
+//! - the first interrupt controller with registers
+//!
+//! The device untypeds are created from the gaps between the memory as before, so they follow
+//! the DTB. The UART, the interrupt controller and the reserved memory handed out are passed to
+//! the root server in the extra bootinfo as a `SEL4_BOOTINFO_HEADER_DEVICES` chunk of
+//! `[kind, paddr, size]` word triples, see [`dtb_device_kind_t`], so it can tell which device
+//! untyped holds which device.
+
+use core::mem::size_of;
+use core::ptr::addr_of;
+
+use log::{info, warn};
+use sel4_common::arch::config::{physBase, PADDR_TOP};
+use sel4_common::sel4_config::seL4_PageBits;
+
+use super::fdt::{
+    fdt_cstr_eq, fdt_for_each_mem_rsv, fdt_for_each_node_prop, fdt_for_each_reg, fdt_node_name_is,
+    fdt_read_be32,
+};
+use super::mm::{avail_p_regs_addr, avail_p_regs_size, reserve_region};
+use crate::config::{MAX_NUM_FREEMEM_REG, SEL4_BOOTINFO_HEADER_DEVICES, TIMER_CLOCK_HZ};
+use crate::structures::{p_region_t, seL4_BootInfoHeader};
+use crate::{ROUND_DOWN, ROUND_UP};
+
+/// Most `/reserved-memory` and `/memreserve/` ranges the kernel keeps track of
+const DTB_MAX_RESERVED: usize = 8;
+/// Most ranges of the `SEL4_BOOTINFO_HEADER_DEVICES` chunk
+const DTB_MAX_DEVICES: usize = DTB_MAX_RESERVED + 2;
+/// Longest path or alias of `/chosen/stdout-path` the kernel follows
+const DTB_MAX_PATH: usize = 64;
+
+/// The kind of a range of the `SEL4_BOOTINFO_HEADER_DEVICES` chunk
+#[derive(Clone, Copy)]
+pub enum dtb_device_kind_t {
+    Uart = 1,
+    InterruptController = 2,
+    ReservedMemory = 3,
+}
+
+#[derive(Clone, Copy)]
+struct dtb_reserved_t {
+    region: p_region_t,
+    node: usize,
+    no_map: bool,
+}
+
+const EMPTY: p_region_t = p_region_t { start: 0, end: 0 };
+
+/// The memory the kernel boots with, `avail_p_regs_addr` points here
+#[link_section = ".boot.bss"]
+static mut ksDtbMemory: [p_region_t; MAX_NUM_FREEMEM_REG] = [EMPTY; MAX_NUM_FREEMEM_REG];
+
+#[link_section = ".boot.bss"]
+static mut ksDtbDevices: [(dtb_device_kind_t, p_region_t); DTB_MAX_DEVICES] =
+    [(dtb_device_kind_t::Uart, EMPTY); DTB_MAX_DEVICES];
+
+#[link_section = ".boot.bss"]
+static mut ksDtbNumDevices: usize = 0;
+
+/// Read the platform from the DTB at `dtb_phys_addr`, replace the compiled in memory with it and
+/// account for its bootinfo chunk in `extra_bi_size`. Must run before the free memory is set up.
+pub fn dtb_platform_discover(dtb_phys_addr: usize, dtb_size: usize, extra_bi_size: &mut usize) {
+    let mut memory = [EMPTY; MAX_NUM_FREEMEM_REG];
+    let mut n_memory = 0;
+    let mut reserved = [dtb_reserved_t {
+        region: EMPTY,
+        node: 0,
+        no_map: false,
+    }; DTB_MAX_RESERVED];
+    let mut n_reserved = 0;
+    let mut stdout_path = 0;
+    let mut timer_hz = 0;
+    let mut intc = None;
+    // The node of the last property, its first `reg` range and its flags
+    let (mut node, mut node_reg, mut node_intc, mut node_no_map) = (0, None, false, false);
+
+    fdt_for_each_node_prop(dtb_phys_addr, dtb_size, |prop| {
+        if prop.node != node {
+            (node, node_reg, node_intc, node_no_map) = (prop.node, None, false, false);
+        }
+        let is_reg = fdt_cstr_eq(prop.name, b"reg");
+        if is_reg && prop.depth == 1 && fdt_node_name_is(prop.node_name, b"memory") {
+            fdt_for_each_reg(prop, |start, end| {
+                let start = ROUND_UP!(start.max(physBase), seL4_PageBits);
+                let end = ROUND_DOWN!(end.min(PADDR_TOP), seL4_PageBits);
+                if start < end && !memory_insert(&mut memory, &mut n_memory, start, end) {
+                    warn!(
+                        "DTB memory [{:#x}..{:#x}] dropped, too many ranges",
+                        start, end
+                    );
+                }
+            });
+        } else if prop.depth == 2 && fdt_node_name_is(prop.parent_name, b"reserved-memory") {
+            if is_reg {
+                fdt_for_each_reg(prop, |start, end| {
+                    if n_reserved < DTB_MAX_RESERVED {
+                        reserved[n_reserved] = dtb_reserved_t {
+                            region: p_region_t { start, end },
+                            node,
+                            no_map: node_no_map,
+                        };
+                        n_reserved += 1;
+                    }
+                });
+            } else if fdt_cstr_eq(prop.name, b"no-map") {
+                node_no_map = true;
+                for entry in reserved[..n_reserved].iter_mut() {
+                    entry.no_map |= entry.node == node;
+                }
+            }
+        } else if prop.depth == 1
+            && fdt_node_name_is(prop.node_name, b"chosen")
+            && fdt_cstr_eq(prop.name, b"stdout-path")
+        {
+            stdout_path = prop.value;
+        } else if prop.len == 4 && is_timer_frequency(prop.node_name, prop.name) {
+            timer_hz = fdt_read_be32(prop.value) as usize;
+        }
+        if is_reg && node_reg.is_none() {
+            fdt_for_each_reg(prop, |start, end| {
+                node_reg.get_or_insert(p_region_t { start, end });
+            });
+        }
+        node_intc |= fdt_cstr_eq(prop.name, b"interrupt-controller");
+        if node_intc && intc.is_none() {
+            intc = node_reg;
+        }
+    });
+    fdt_for_each_mem_rsv(dtb_phys_addr, dtb_size, |start, end| {
+        if n_reserved < DTB_MAX_RESERVED {
+            let region = p_region_t { start, end };
+            reserved[n_reserved] = dtb_reserved_t {
+                region,
+                node: 0,
+                no_map: true,
+            };
+            n_reserved += 1;
+        }
+    });
+
+    if n_memory == 0 {
+        // Keep the compiled in memory, the reserved memory is still cut out of it
+        unsafe {
+            n_memory = avail_p_regs_size.min(MAX_NUM_FREEMEM_REG);
+            for i in 0..n_memory {
+                memory[i] = *(avail_p_regs_addr as *const p_region_t).add(i);
+            }
+        }
+    } else {
+        info!("DTB memory: {} ranges", n_memory);
+    }
+    let mut devices = [(dtb_device_kind_t::Uart, EMPTY); DTB_MAX_DEVICES];
+    let mut n_devices = 0;
+    for entry in reserved[..n_reserved].iter() {
+        let region = entry.region;
+        info!(
+            "DTB reserved memory [{:#x}..{:#x}]{}",
+            region.start,
+            region.end,
+            if entry.no_map { ", no-map" } else { "" }
+        );
+        if !memory_cut(&mut memory, &mut n_memory, region) {
+            warn!(
+                "DTB reserved memory [{:#x}..{:#x}] not cut out",
+                region.start, region.end
+            );
+        }
+        if entry.no_map {
+            unsafe { reserve_region(region) };
+        } else {
+            devices[n_devices] = (dtb_device_kind_t::ReservedMemory, region);
+            n_devices += 1;
+        }
+    }
+    if let Some(uart) = stdout_uart(dtb_phys_addr, dtb_size, stdout_path) {
+        info!("DTB UART at [{:#x}..{:#x}]", uart.start, uart.end);
+        devices[n_devices] = (dtb_device_kind_t::Uart, uart);
+        n_devices += 1;
+    }
+    if let Some(intc) = intc {
+        info!(
+            "DTB interrupt controller at [{:#x}..{:#x}]",
+            intc.start, intc.end
+        );
+        devices[n_devices] = (dtb_device_kind_t::InterruptController, intc);
+        n_devices += 1;
+    }
+    if timer_hz != 0 && timer_hz != TIMER_CLOCK_HZ {
+        warn!(
+            "DTB timer frequency is {} Hz, the kernel is built for {} Hz",
+            timer_hz, TIMER_CLOCK_HZ
+        );
+    }
+
+    unsafe {
+        ksDtbMemory = memory;
+        avail_p_regs_addr = addr_of!(ksDtbMemory) as usize;
+        avail_p_regs_size = n_memory;
+        ksDtbDevices = devices;
+        ksDtbNumDevices = n_devices;
+    }
+    *extra_bi_size += size_of::<seL4_BootInfoHeader>() + n_devices * 3 * size_of::<usize>();
+}
+
+/// Write the device chunk at `dst` in the extra bootinfo, returns the bytes written.
+pub fn dtb_platform_write_bootinfo(dst: usize) -> usize {
+    let devices = unsafe { &ksDtbDevices[..ksDtbNumDevices] };
+    let len = size_of::<seL4_BootInfoHeader>() + devices.len() * 3 * size_of::<usize>();
+    unsafe {
+        *(dst as *mut seL4_BootInfoHeader) = seL4_BootInfoHeader {
+            id: SEL4_BOOTINFO_HEADER_DEVICES,
+            len,
+        };
+        let words = (dst + size_of::<seL4_BootInfoHeader>()) as *mut [usize; 3];
+        for (i, (kind, region)) in devices.iter().enumerate() {
+            *words.add(i) = [*kind as usize, region.start, region.end - region.start];
+        }
+    }
+    len
+}
+
+/// Whether the property `name` of the node `node_name` is the frequency of the kernel timer
+#[cfg(target_arch = "riscv64")]
+fn is_timer_frequency(node_name: usize, name: usize) -> bool {
+    (fdt_node_name_is(node_name, b"cpus") || fdt_node_name_is(node_name, b"cpu"))
+        && fdt_cstr_eq(name, b"timebase-frequency")
+}
+
+/// Whether the property `name` of the node `node_name` is the frequency of the kernel timer
+#[cfg(target_arch = "aarch64")]
+fn is_timer_frequency(node_name: usize, name: usize) -> bool {
+    fdt_node_name_is(node_name, b"timer") && fdt_cstr_eq(name, b"clock-frequency")
+}
+
+/// The first `reg` range of the node `/chosen/stdout-path` at the kernel address `path` names.
+fn stdout_uart(dtb_phys_addr: usize, dtb_size: usize, mut path: usize) -> Option<p_region_t> {
+    if path == 0 {
+        return None;
+    }
+    let mut buf = [0; DTB_MAX_PATH];
+    let mut len = read_path(path, &mut buf);
+    if buf[0] != b'/' {
+        // An alias, the property of that name in `/aliases` holds the path
+        let alias = buf;
+        path = 0;
+        fdt_for_each_node_prop(dtb_phys_addr, dtb_size, |prop| {
+            if prop.depth == 1
+                && fdt_node_name_is(prop.node_name, b"aliases")
+                && fdt_cstr_eq(prop.name, &alias[..len])
+            {
+                path = prop.value;
+            }
+        });
+        if path == 0 {
+            return None;
+        }
+        len = read_path(path, &mut buf);
+    }
+    // Node names are only unique among siblings, match the parent as well
+    let name_start = buf[..len].iter().rposition(|c| *c == b'/')? + 1;
+    let parent_start = buf[..name_start - 1]
+        .iter()
+        .rposition(|c| *c == b'/')
+        .map_or(0, |i| i + 1);
+    let (name, parent) = (&buf[name_start..len], &buf[parent_start..name_start - 1]);
+    let mut uart = None;
+    fdt_for_each_node_prop(dtb_phys_addr, dtb_size, |prop| {
+        if uart.is_none()
+            && fdt_cstr_eq(prop.name, b"reg")
+            && fdt_cstr_eq(prop.node_name, name)
+            && (prop.depth == 1 || fdt_cstr_eq(prop.parent_name, parent))
+        {
+            fdt_for_each_reg(prop, |start, end| {
+                uart.get_or_insert(p_region_t { start, end });
+            });
+        }
+    });
+    uart
+}
+
+/// Copy the path at the kernel address `ptr` up to its options after `:` into `buf`, returns its
+/// length.
+fn read_path(ptr: usize, buf: &mut [u8; DTB_MAX_PATH]) -> usize {
+    let mut len = 0;
+    while len < DTB_MAX_PATH {
+        let c = unsafe { *((ptr + len) as *const u8) };
+        if c == 0 || c == b':' {
+            break;
+        }
+        buf[len] = c;
+        len += 1;
+    }
+    len
+}
+
+/// Insert `[start, end)` into the sorted `memory`, false if it is full.
+fn memory_insert(
+    memory: &mut [p_region_t; MAX_NUM_FREEMEM_REG],
+    n: &mut usize,
+    start: usize,
+    end: usize,
+) -> bool {
+    if *n == MAX_NUM_FREEMEM_REG {
+        return false;
+    }
+    let mut i = *n;
+    while i > 0 && memory[i - 1].start > start {
+        memory[i] = memory[i - 1];
+        i -= 1;
+    }
+    memory[i] = p_region_t { start, end };
+    *n += 1;
+    true
+}
+
+/// Remove `hole` from the sorted `memory`, false if a range it splits does not fit.
+fn memory_cut(
+    memory: &mut [p_region_t; MAX_NUM_FREEMEM_REG],
+    n: &mut usize,
+    hole: p_region_t,
+) -> bool {
+    let mut i = 0;
+    while i < *n {
+        let reg = memory[i];
+        if hole.end <= reg.start || hole.start >= reg.end {
+            i += 1;
+        } else if hole.start <= reg.start && hole.end >= reg.end {
+            memory.copy_within(i + 1..*n, i);
+            *n -= 1;
+        } else if hole.start <= reg.start {
+            memory[i].start = ROUND_UP!(hole.end, seL4_PageBits);
+            i += 1;
+        } else if hole.end >= reg.end {
+            memory[i].end = ROUND_DOWN!(hole.start, seL4_PageBits);
+            i += 1;
+        } else {
+            if *n == MAX_NUM_FREEMEM_REG {
+                return false;
+            }
+            memory.copy_within(i..*n, i + 1);
+            *n += 1;
+            memory[i].end = ROUND_DOWN!(hole.start, seL4_PageBits);
+            memory[i + 1].start = ROUND_UP!(hole.end, seL4_PageBits);
+            i += 2;
+        }
+    }
+    // Rounding to pages may have emptied a range
+    let mut i = 0;
+    while i < *n {
+        if memory[i].start >= memory[i].end {
+            memory.copy_within(i + 1..*n, i);
+            *n -= 1;
+        } else {
+            i += 1;
+        }
+    }
+    true
+}
@@ -0,0 +1,207 @@
+//! A minimal walker over the flattened device tree the kernel was booted with.
+//!
+//! The kernel does not parse the DTB beyond reading a few properties at boot; the platform
+//! description itself is generated at build time, unless `ENABLE_DTB_PLATFORM` discovers it from
+//! the DTB. The walker checks the header and stops at the first malformed token instead of
+//! failing, so a missing or broken DTB reads as having no properties.
+//!
+//! The DTB is big-endian whatever the core, so its words are only ever read through
+//! `fdt_read_be32`.
+
+use sel4_vspace::paddr_to_pptr;
+
+const FDT_MAGIC: u32 = 0xd00dfeed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+/// Deepest node the walker follows, it stops at deeper ones
+const FDT_MAX_DEPTH: usize = 16;
+
+/// A property of the DTB, see [`fdt_for_each_node_prop`]
+pub struct fdt_prop_t {
+    /// Numbers the nodes in the order they start
+    pub node: usize,
+    /// Depth of the node, 0 for the root
+    pub depth: usize,
+    /// Kernel address of the NUL terminated name of the node, e.g. `memory@80000000`
+    pub node_name: usize,
+    /// Kernel address of the name of the parent node, 0 for the root
+    pub parent_name: usize,
+    /// Kernel address of the NUL terminated property name
+    pub name: usize,
+    /// Kernel address of the `len` bytes of the value
+    pub value: usize,
+    pub len: usize,
+    /// `#address-cells` of the parent, which the `reg` of the node is encoded with
+    pub address_cells: usize,
+    /// `#size-cells` of the parent
+    pub size_cells: usize,
+}
+
+#[inline]
+/// Read the big-endian 32-bit word at the kernel address `ptr`
+pub fn fdt_read_be32(ptr: usize) -> u32 {
+    u32::from_be(unsafe { core::ptr::read_unaligned(ptr as *const u32) })
+}
+
+/// Whether the NUL terminated string at the kernel address `ptr` is `s`
+pub fn fdt_cstr_eq(ptr: usize, s: &[u8]) -> bool {
+    s.iter()
+        .enumerate()
+        .all(|(i, c)| unsafe { *((ptr + i) as *const u8) } == *c)
+        && unsafe { *((ptr + s.len()) as *const u8) } == 0
+}
+
+/// Read the number of `cells` big-endian words at the kernel address `ptr`, keeping the low 64
+/// bits
+pub fn fdt_read_cells(ptr: usize, cells: usize) -> usize {
+    (0..cells).fold(0, |value, i| {
+        (((value as u64) << 32) | fdt_read_be32(ptr + 4 * i) as u64) as usize
+    })
+}
+
+/// Whether the node name at the kernel address `ptr` is `s`, with or without a unit address
+pub fn fdt_node_name_is(ptr: usize, s: &[u8]) -> bool {
+    s.iter()
+        .enumerate()
+        .all(|(i, c)| unsafe { *((ptr + i) as *const u8) } == *c)
+        && matches!(unsafe { *((ptr + s.len()) as *const u8) }, 0 | b'@')
+}
+
+/// Call `f(node, name, value, len)` for every property of the DTB at `dtb_phys_addr`. `node`
+/// numbers the nodes in the order they start, `name` is the kernel address of the NUL terminated
+/// property name and `value` the one of its `len` bytes.
+pub fn fdt_for_each_prop(
+    dtb_phys_addr: usize,
+    dtb_size: usize,
+    mut f: impl FnMut(usize, usize, usize, usize),
+) {
+    fdt_for_each_node_prop(dtb_phys_addr, dtb_size, |prop| {
+        f(prop.node, prop.name, prop.value, prop.len)
+    });
+}
+
+/// Call `f` for every property of the DTB at `dtb_phys_addr`, with the node it belongs to.
+pub fn fdt_for_each_node_prop(
+    dtb_phys_addr: usize,
+    dtb_size: usize,
+    mut f: impl FnMut(&fdt_prop_t),
+) {
+    if dtb_size < 40 || dtb_phys_addr == 0 {
+        return;
+    }
+    let dtb = paddr_to_pptr(dtb_phys_addr);
+    if fdt_read_be32(dtb) != FDT_MAGIC {
+        return;
+    }
+    let struct_off = fdt_read_be32(dtb + 8) as usize;
+    let strings_off = fdt_read_be32(dtb + 12) as usize;
+    let struct_size = fdt_read_be32(dtb + 36) as usize;
+    if struct_off + struct_size > dtb_size || strings_off > dtb_size {
+        return;
+    }
+    let mut pos = dtb + struct_off;
+    let end = pos + struct_size;
+    let mut node = 0;
+    // The open nodes, with the name and the `#address-cells`/`#size-cells` of each
+    let mut open = 0;
+    let mut names = [0; FDT_MAX_DEPTH];
+    let mut cells = [(2, 1); FDT_MAX_DEPTH];
+    while pos + 4 <= end {
+        let token = fdt_read_be32(pos);
+        pos += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                if open == FDT_MAX_DEPTH {
+                    break;
+                }
+                node += 1;
+                names[open] = pos;
+                cells[open] = (2, 1);
+                open += 1;
+                while pos < end && unsafe { *(pos as *const u8) } != 0 {
+                    pos += 1;
+                }
+                pos = (pos + 4) & !3;
+            }
+            FDT_END_NODE => open = open.saturating_sub(1),
+            FDT_NOP => {}
+            FDT_PROP => {
+                if pos + 8 > end {
+                    break;
+                }
+                let len = fdt_read_be32(pos) as usize;
+                let name_off = fdt_read_be32(pos + 4) as usize;
+                let value = pos + 8;
+                pos = (value + len + 3) & !3;
+                if pos > end || strings_off + name_off >= dtb_size || open == 0 {
+                    break;
+                }
+                let name = dtb + strings_off + name_off;
+                if len == 4 && fdt_cstr_eq(name, b"#address-cells") {
+                    cells[open - 1].0 = fdt_read_be32(value) as usize;
+                } else if len == 4 && fdt_cstr_eq(name, b"#size-cells") {
+                    cells[open - 1].1 = fdt_read_be32(value) as usize;
+                }
+                let (address_cells, size_cells) = match open {
+                    1 => (2, 1),
+                    _ => cells[open - 2],
+                };
+                f(&fdt_prop_t {
+                    node,
+                    depth: open - 1,
+                    node_name: names[open - 1],
+                    parent_name: if open > 1 { names[open - 2] } else { 0 },
+                    name,
+                    value,
+                    len,
+                    address_cells,
+                    size_cells,
+                });
+            }
+            _ => break,
+        }
+    }
+}
+
+/// Call `f(start, end)` for every physical range of the `reg` property `prop`.
+pub fn fdt_for_each_reg(prop: &fdt_prop_t, mut f: impl FnMut(usize, usize)) {
+    let entry = 4 * (prop.address_cells + prop.size_cells);
+    if entry == 0 {
+        return;
+    }
+    for i in 0..prop.len / entry {
+        let value = prop.value + i * entry;
+        let start = fdt_read_cells(value, prop.address_cells);
+        let size = fdt_read_cells(value + 4 * prop.address_cells, prop.size_cells);
+        f(start, start.saturating_add(size));
+    }
+}
+
+/// Call `f(start, end)` for every entry of the `/memreserve/` block of the DTB at
+/// `dtb_phys_addr`.
+pub fn fdt_for_each_mem_rsv(
+    dtb_phys_addr: usize,
+    dtb_size: usize,
+    mut f: impl FnMut(usize, usize),
+) {
+    if dtb_size < 40 || dtb_phys_addr == 0 {
+        return;
+    }
+    let dtb = paddr_to_pptr(dtb_phys_addr);
+    if fdt_read_be32(dtb) != FDT_MAGIC {
+        return;
+    }
+    let mut off = fdt_read_be32(dtb + 16) as usize;
+    while off + 16 <= dtb_size {
+        let start = fdt_read_cells(dtb + off, 2);
+        let size = fdt_read_cells(dtb + off + 8, 2);
+        if start == 0 && size == 0 {
+            break;
+        }
+        f(start, start.saturating_add(size));
+        off += 16;
+    }
+}
@@ -26,7 +26,7 @@ pub fn rust_try_init_kernel(
     dtb_phys_addr: usize,
     dtb_size: usize,
 ) -> bool {
-    try_init_kernel(
+    let result = try_init_kernel(
         ui_p_reg_start,
         ui_p_reg_end,
         pv_offset,
@@ -34,14 +34,18 @@ pub fn rust_try_init_kernel(
         dtb_phys_addr,
         dtb_size,
         ki_boot_end as usize,
-    )
+    );
+    #[cfg(feature = "ENABLE_BOOT_PROGRESS")]
+    if !result {
+        crate::boot::boot_progress_failed();
+    }
+    result
 }
 
 #[cfg(feature = "ENABLE_SMP")]
 #[no_mangle]
 pub fn rust_try_init_kernel_secondary_core(hart_id: usize, core_id: usize) -> bool {
-    use super::try_init_kernel_secondary_core;
-    try_init_kernel_secondary_core(hart_id, core_id)
+    crate::arch::try_init_kernel_secondary_core(hart_id, core_id)
 }
 
 #[no_mangle]
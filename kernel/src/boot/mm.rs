@@ -28,11 +28,25 @@ pub fn rust_init_freemem(
     n_reserved: usize,
     reserved: [region_t; NUM_RESERVED_REGIONS],
 ) -> bool {
+    #[cfg(feature = "ENABLE_PERSISTENT_LOG")]
+    let (n_reserved, reserved) = add_reserved_region(
+        n_reserved,
+        reserved,
+        paddr_to_pptr_reg(&super::persistent_log_region()),
+    );
     if !check_available_memory(n_available, available)
         || !check_reserved_memory(n_reserved, reserved.clone())
     {
         return false;
     }
+    #[cfg(feature = "ENABLE_MEMORY_HOTADD")]
+    for i in 0..n_available {
+        super::boot_memory::boot_memory_record(unsafe { *(available as *const p_region_t).add(i) });
+    }
+    #[cfg(feature = "ENABLE_MEMORY_HOTADD")]
+    for reg in reserved[..n_reserved].iter() {
+        super::boot_memory::boot_memory_record(pptr_to_paddr_reg(*reg));
+    }
 
     unsafe {
         for i in 0..ndks_boot.freemem.len() {
@@ -115,6 +129,26 @@ pub fn rust_init_freemem(
     true
 }
 
+/// Insert `reg` into the sorted `reserved` list, keeping it sorted.
+#[cfg(feature = "ENABLE_PERSISTENT_LOG")]
+fn add_reserved_region(
+    n_reserved: usize,
+    mut reserved: [region_t; NUM_RESERVED_REGIONS],
+    reg: region_t,
+) -> (usize, [region_t; NUM_RESERVED_REGIONS]) {
+    if is_reg_empty(&reg) {
+        return (n_reserved, reserved);
+    }
+    assert!(n_reserved < NUM_RESERVED_REGIONS);
+    let mut i = n_reserved;
+    while i > 0 && reserved[i - 1].start > reg.start {
+        reserved[i] = reserved[i - 1];
+        i -= 1;
+    }
+    reserved[i] = reg;
+    (n_reserved + 1, reserved)
+}
+
 fn check_available_memory(n_available: usize, available: usize) -> bool {
     if n_available == 0 {
         debug!("ERROR: no memory regions available");
@@ -194,61 +228,65 @@ fn insert_region(reg: region_t) -> bool {
 }
 
 pub unsafe fn reserve_region(reg: p_region_t) -> bool {
-    assert!(reg.start <= reg.end);
-    if reg.start == reg.end {
-        return true;
-    }
-
-    let mut i = 0;
-    while i < ndks_boot.resv_count {
-        if ndks_boot.reserved[i].start == reg.end {
-            ndks_boot.reserved[i].start = reg.start;
-            merge_regions();
-            return true;
-        }
-        if ndks_boot.reserved[i].end == reg.start {
-            ndks_boot.reserved[i].end = reg.end;
-            merge_regions();
+    unsafe {
+        assert!(reg.start <= reg.end);
+        if reg.start == reg.end {
             return true;
         }
-        if ndks_boot.reserved[i].start > reg.end {
-            if ndks_boot.resv_count + 1 >= MAX_NUM_RESV_REG {
-                debug!("Can't mark region {:#x}-{:#x} as reserved, try increasing MAX_NUM_RESV_REG (currently {})\n",reg.start,reg.end,MAX_NUM_RESV_REG);
-                return false;
+
+        let mut i = 0;
+        while i < ndks_boot.resv_count {
+            if ndks_boot.reserved[i].start == reg.end {
+                ndks_boot.reserved[i].start = reg.start;
+                merge_regions();
+                return true;
             }
-            let mut j = ndks_boot.resv_count;
-            while j > i {
-                ndks_boot.reserved[j] = ndks_boot.reserved[j - 1];
-                j -= 1;
+            if ndks_boot.reserved[i].end == reg.start {
+                ndks_boot.reserved[i].end = reg.end;
+                merge_regions();
+                return true;
             }
-            ndks_boot.reserved[i] = reg;
-            ndks_boot.resv_count += 1;
-            return true;
+            if ndks_boot.reserved[i].start > reg.end {
+                if ndks_boot.resv_count + 1 >= MAX_NUM_RESV_REG {
+                    debug!("Can't mark region {:#x}-{:#x} as reserved, try increasing MAX_NUM_RESV_REG (currently {})\n",reg.start,reg.end,MAX_NUM_RESV_REG);
+                    return false;
+                }
+                let mut j = ndks_boot.resv_count;
+                while j > i {
+                    ndks_boot.reserved[j] = ndks_boot.reserved[j - 1];
+                    j -= 1;
+                }
+                ndks_boot.reserved[i] = reg;
+                ndks_boot.resv_count += 1;
+                return true;
+            }
+            i += 1;
         }
-        i += 1;
-    }
-    if i + 1 == MAX_NUM_RESV_REG {
-        debug!("Can't mark region 0x{}-0x{} as reserved, try increasing MAX_NUM_RESV_REG (currently {})\n",reg.start,reg.end,MAX_NUM_RESV_REG);
-        return false;
+        if i + 1 == MAX_NUM_RESV_REG {
+            debug!("Can't mark region 0x{}-0x{} as reserved, try increasing MAX_NUM_RESV_REG (currently {})\n",reg.start,reg.end,MAX_NUM_RESV_REG);
+            return false;
+        }
+        ndks_boot.reserved[i] = reg;
+        ndks_boot.resv_count += 1;
+        return true;
     }
-    ndks_boot.reserved[i] = reg;
-    ndks_boot.resv_count += 1;
-    return true;
 }
 
 unsafe fn merge_regions() {
-    let mut i = 1;
-    while i < ndks_boot.resv_count {
-        if ndks_boot.reserved[i - 1].end == ndks_boot.reserved[i].start {
-            ndks_boot.reserved[i - 1].end = ndks_boot.reserved[i].end;
-            let mut j = i + 1;
-            while j < ndks_boot.resv_count {
-                ndks_boot.reserved[j - 1] = ndks_boot.reserved[j];
-                j += 1;
+    unsafe {
+        let mut i = 1;
+        while i < ndks_boot.resv_count {
+            if ndks_boot.reserved[i - 1].end == ndks_boot.reserved[i].start {
+                ndks_boot.reserved[i - 1].end = ndks_boot.reserved[i].end;
+                let mut j = i + 1;
+                while j < ndks_boot.resv_count {
+                    ndks_boot.reserved[j - 1] = ndks_boot.reserved[j];
+                    j += 1;
+                }
+                ndks_boot.resv_count -= 1;
+            } else {
+                i += 1;
             }
-            ndks_boot.resv_count -= 1;
-        } else {
-            i += 1;
         }
     }
 }
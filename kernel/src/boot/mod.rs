@@ -1,6 +1,19 @@
+#[cfg(feature = "ENABLE_MEMORY_HOTADD")]
+mod boot_memory;
+#[cfg(feature = "ENABLE_BENCHMARK")]
+mod cpu_freq;
+#[cfg(feature = "ENABLE_DTB_PLATFORM")]
+mod dtb_platform;
+mod fdt;
 mod interface;
 mod mm;
+#[cfg(feature = "ENABLE_PERSISTENT_LOG")]
+mod persistent_log;
+#[cfg(feature = "ENABLE_BOOT_PROGRESS")]
+mod progress;
 mod root_server;
+#[cfg(feature = "ENABLE_SELFTEST_ROOT_TASK")]
+mod selftest;
 mod untyped;
 mod utils;
 
@@ -16,7 +29,8 @@ use spin::Mutex;
 pub use crate::boot::utils::paddr_to_pptr_reg;
 use crate::config::*;
 use crate::structures::{
-    ndks_boot_t, p_region_t, region_t, seL4_BootInfo, seL4_BootInfoHeader, seL4_SlotRegion,
+    ndks_boot_t, p_region_t, region_t, seL4_BootInfo, seL4_BootInfoHeader, seL4_PlatformInfo,
+    seL4_SlotRegion,
 };
 
 #[cfg(target_arch = "aarch64")]
@@ -26,22 +40,36 @@ pub use root_server::rootserver;
 use sel4_task::*;
 use sel4_vspace::*;
 
-pub use root_server::root_server_init;
+#[cfg(target_arch = "riscv64")]
+pub use fdt::fdt_read_be32;
+pub use fdt::{fdt_cstr_eq, fdt_for_each_prop};
+pub use root_server::{create_shared_frames, root_server_init};
+#[cfg(feature = "ENABLE_SELFTEST_ROOT_TASK")]
+pub use selftest::selftest_root_task;
 pub use untyped::create_untypeds;
 
-#[cfg(feature = "ENABLE_SMP")]
-pub use utils::{provide_cap, write_slot};
-
-#[cfg(feature = "ENABLE_SMP")]
-use crate::ffi::{clh_lock_acquire, clh_lock_init};
+#[cfg(feature = "ENABLE_MEMORY_HOTADD")]
+pub use boot_memory::boot_memory_overlaps;
+#[cfg(feature = "ENABLE_BENCHMARK")]
+pub use cpu_freq::cpu_freq_pin;
+#[cfg(feature = "ENABLE_DTB_PLATFORM")]
+pub use dtb_platform::dtb_platform_discover;
+#[cfg(feature = "ENABLE_PERSISTENT_LOG")]
+pub use persistent_log::{persistent_log_attach, persistent_log_detect, persistent_log_region};
+#[cfg(feature = "ENABLE_BOOT_PROGRESS")]
+pub use progress::{boot_progress, boot_progress_failed, boot_stage_t};
+#[cfg(feature = "ENABLE_MEMORY_HOTADD")]
+pub use utils::pptr_in_kernel_window;
 
 #[cfg(feature = "ENABLE_SMP")]
-use core::arch::asm;
+pub use utils::{provide_cap, write_slot};
 
 #[cfg(feature = "ENABLE_SMP")]
 use sel4_common::utils::cpu_id;
 
+/// The cores which finished booting
 pub static ksNumCPUs: Mutex<usize> = Mutex::new(0);
+/// Set by the primary core once the secondary cores may boot
 #[cfg(feature = "ENABLE_SMP")]
 pub static node_boot_lock: Mutex<usize> = Mutex::new(0);
 
@@ -52,7 +80,7 @@ pub static mut ndks_boot: ndks_boot_t = ndks_boot_t {
     resv_count: 0,
     freemem: [region_t { start: 0, end: 0 }; MAX_NUM_FREEMEM_REG],
     bi_frame: 0 as *mut seL4_BootInfo,
-    slot_pos_cur: seL4_NumInitialCaps,
+    slot_pos_cur: ROOT_CNODE_FIRST_FREE_SLOT,
 };
 
 pub fn calculate_extra_bi_size_bits(size: usize) -> usize {
@@ -101,6 +129,12 @@ pub fn init_dtb(
     Some(dtb_p_reg)
 }
 
+/// Account for the `SEL4_BOOTINFO_HEADER_PLATFORM` chunk in `extra_bi_size`, which every boot
+/// passes to the root server after the DTB.
+pub fn init_platform_info(extra_bi_size: &mut usize) {
+    *extra_bi_size += size_of::<seL4_BootInfoHeader>() + size_of::<seL4_PlatformInfo>();
+}
+
 pub fn init_bootinfo(dtb_size: usize, dtb_phys_addr: usize, extra_bi_size: usize) {
     let mut extra_bi_offset = 0;
     let mut header: seL4_BootInfoHeader = seL4_BootInfoHeader { id: 0, len: 0 };
@@ -121,6 +155,36 @@ pub fn init_bootinfo(dtb_size: usize, dtb_phys_addr: usize, extra_bi_size: usize
             );
             dst.copy_from_slice(src);
         }
+        extra_bi_offset += dtb_size;
+    }
+    header.id = SEL4_BOOTINFO_HEADER_PLATFORM;
+    header.len = size_of::<seL4_BootInfoHeader>() + size_of::<seL4_PlatformInfo>();
+    unsafe {
+        *((rootserver.extra_bi + extra_bi_offset) as *mut seL4_BootInfoHeader) = header.clone();
+        *((rootserver.extra_bi + extra_bi_offset + size_of::<seL4_BootInfoHeader>())
+            as *mut seL4_PlatformInfo) = seL4_PlatformInfo {
+            timerFrequency: TIMER_CLOCK_HZ,
+            maxIRQ,
+            paddrUserDeviceTop: CONFIG_PADDR_USER_DEVICE_TOP,
+        };
+    }
+    extra_bi_offset += header.len;
+    #[cfg(feature = "ENABLE_PERSISTENT_LOG")]
+    {
+        extra_bi_offset += persistent_log::persistent_log_write_bootinfo(
+            unsafe { rootserver.extra_bi } + extra_bi_offset,
+        );
+    }
+    #[cfg(feature = "ENABLE_BENCHMARK")]
+    {
+        extra_bi_offset +=
+            cpu_freq::cpu_freq_write_bootinfo(unsafe { rootserver.extra_bi } + extra_bi_offset);
+    }
+    #[cfg(feature = "ENABLE_DTB_PLATFORM")]
+    {
+        extra_bi_offset += dtb_platform::dtb_platform_write_bootinfo(
+            unsafe { rootserver.extra_bi } + extra_bi_offset,
+        );
     }
     if extra_bi_size > extra_bi_offset {
         header.id = SEL4_BOOTINFO_HEADER_PADDING;
@@ -163,31 +227,3 @@ pub fn init_core_state(scheduler_action: *mut tcb_t) {
         set_current_thread(get_idle_thread());
     }
 }
-
-#[cfg(feature = "ENABLE_SMP")]
-pub fn try_init_kernel_secondary_core(hartid: usize, core_id: usize) -> bool {
-    use core::ops::AddAssign;
-    while node_boot_lock.lock().eq(&0) {}
-    // debug!("start try_init_kernel_secondary_core");
-    init_cpu();
-    debug!("init cpu compl");
-    unsafe { clh_lock_acquire(cpu_id(), false) }
-    ksNumCPUs.lock().add_assign(1);
-    init_core_state(SchedulerAction_ResumeCurrentThread as *mut tcb_t);
-    debug!("init_core_state compl");
-
-    unsafe {
-        asm!("fence.i");
-    }
-    true
-}
-
-#[cfg(feature = "ENABLE_SMP")]
-fn release_secondary_cores() {
-    use sel4_common::sel4_config::CONFIG_MAX_NUM_NODES;
-    *node_boot_lock.lock() = 1;
-    unsafe {
-        asm!("fence rw, rw");
-    }
-    while ksNumCPUs.lock().ne(&CONFIG_MAX_NUM_NODES) {}
-}
@@ -0,0 +1,112 @@
+//! Keep the kernel log ring across warm reboots, enabled by `ENABLE_PERSISTENT_LOG`.
+//!
+//! The console ring buffer is moved to the top of the last available physical memory region,
+//! which is reserved so it is never handed out as untyped memory and therefore never zeroed.
+//! On the next boot the ring header (magic and CRC) is checked; if it is valid, the previous
+//! boot's log is copied into the extra bootinfo as a `SEL4_BOOTINFO_HEADER_PREV_LOG` chunk so a
+//! user-level service can pick it up, e.g. to upload crash context.
+
+use core::mem::size_of;
+
+use log::debug;
+use sel4_common::arch::config::PADDR_TOP;
+use sel4_common::console::{
+    console_ring_t, get_console_ring, get_console_sinks, set_console_ring, set_console_sinks,
+    CONSOLE_SINK_RING,
+};
+use sel4_common::sel4_config::seL4_PageBits;
+use sel4_common::utils::convert_to_mut_type_ref;
+use sel4_vspace::paddr_to_pptr;
+
+use crate::config::SEL4_BOOTINFO_HEADER_PREV_LOG;
+use crate::structures::{p_region_t, seL4_BootInfoHeader};
+use crate::{ROUND_DOWN, ROUND_UP};
+
+use super::mm::{avail_p_regs_addr, avail_p_regs_size};
+
+/// Physical memory kept for the ring, a whole number of pages.
+const PERSISTENT_LOG_SIZE: usize = ROUND_UP!(size_of::<console_ring_t>(), seL4_PageBits);
+
+#[link_section = ".boot.bss"]
+static mut ksPrevLogLen: usize = 0;
+
+/// The physical region holding the ring: the last pages of the last available region that the
+/// kernel window covers. It only depends on the memory layout of the platform, so it is the same
+/// on every boot.
+pub fn persistent_log_region() -> p_region_t {
+    unsafe {
+        if avail_p_regs_size == 0 {
+            return p_region_t { start: 0, end: 0 };
+        }
+        let last = &*(avail_p_regs_addr as *const p_region_t).add(avail_p_regs_size - 1);
+        let end = ROUND_DOWN!(last.end.min(PADDR_TOP), seL4_PageBits);
+        if end < last.start + PERSISTENT_LOG_SIZE {
+            return p_region_t { start: 0, end: 0 };
+        }
+        p_region_t {
+            start: end - PERSISTENT_LOG_SIZE,
+            end,
+        }
+    }
+}
+
+fn persistent_log_ring() -> Option<&'static mut console_ring_t> {
+    let reg = persistent_log_region();
+    if reg.start == reg.end {
+        return None;
+    }
+    Some(convert_to_mut_type_ref::<console_ring_t>(paddr_to_pptr(
+        reg.start,
+    )))
+}
+
+/// Turn on the `ring` console sink, then look for the previous boot's log and account for it
+/// in `extra_bi_size`.
+/// Must run after the kernel window is mapped.
+pub fn persistent_log_detect(extra_bi_size: &mut usize) {
+    set_console_sinks(get_console_sinks() | CONSOLE_SINK_RING);
+    if let Some(ring) = persistent_log_ring() {
+        if ring.is_valid() && ring.used_bytes() != 0 {
+            debug!(
+                "found {} bytes of log from the previous boot",
+                ring.used_bytes()
+            );
+            unsafe {
+                ksPrevLogLen = ring.used_bytes();
+            }
+            *extra_bi_size += size_of::<seL4_BootInfoHeader>() + ring.used_bytes();
+        }
+    }
+}
+
+/// Write the previous boot's log at `dst` in the extra bootinfo, returns the bytes written.
+pub fn persistent_log_write_bootinfo(dst: usize) -> usize {
+    let len = unsafe { ksPrevLogLen };
+    if len == 0 {
+        return 0;
+    }
+    let ring = persistent_log_ring().unwrap();
+    unsafe {
+        *(dst as *mut seL4_BootInfoHeader) = seL4_BootInfoHeader {
+            id: SEL4_BOOTINFO_HEADER_PREV_LOG,
+            len: size_of::<seL4_BootInfoHeader>() + len,
+        };
+        let data = core::slice::from_raw_parts_mut(
+            (dst + size_of::<seL4_BootInfoHeader>()) as *mut u8,
+            len,
+        );
+        ring.copy_to(data);
+    }
+    size_of::<seL4_BootInfoHeader>() + len
+}
+
+/// Start logging into the persistent ring, carrying over what this boot has logged so far.
+/// Must run after the previous log has been copied into the bootinfo.
+pub fn persistent_log_attach() {
+    let Some(ring) = persistent_log_ring() else {
+        debug!("no memory region for the persistent log");
+        return;
+    };
+    ring.copy_from(get_console_ring());
+    set_console_ring(ring);
+}
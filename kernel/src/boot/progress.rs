@@ -0,0 +1,66 @@
+//! Boot progress codes, enabled by the `ENABLE_BOOT_PROGRESS` feature.
+//!
+//! Every boot stage that completes prints one character straight to the console sinks,
+//! bypassing the logger, so a board that hangs before the full console is up still shows how far
+//! it got. A boot that fails prints `!` after the code of the last completed stage.
+//!
+//! The codes are also passed to `plat_boot_progress`, which a platform may override to show them
+//! on LEDs or a POST code port. On AArch64 the UART is only mapped with the kernel window, so
+//! codes before [`boot_stage_t::KernelWindow`] only reach the platform hook.
+
+use sel4_common::console::console_putchar;
+
+/// The code printed when the boot fails
+pub const BOOT_PROGRESS_FAILED: u8 = b'!';
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+/// Boot stages, in boot order, with their progress codes
+pub enum boot_stage_t {
+    /// `try_init_kernel` entered
+    Entry = b'0',
+    /// Kernel window mapped, the kernel runs on its own page tables
+    KernelWindow = b'1',
+    /// Trap vectors, local interrupt controller and timer of the boot core set up
+    Cpu = b'2',
+    /// Platform and global interrupt controller set up
+    Platform = b'3',
+    /// Free memory management initialised
+    FreeMem = b'4',
+    /// ASID pool of the root server created
+    Asid = b'5',
+    /// Root server created
+    RootServer = b'6',
+    /// Boot finished, about to enter user mode
+    User = b'7',
+}
+
+/// Platform hook: show the progress code `code`, e.g. on LEDs. Overridden by a strong
+/// definition in the platform code, the default does nothing.
+#[no_mangle]
+#[linkage = "weak"]
+pub extern "C" fn plat_boot_progress(_code: u8) {}
+
+fn boot_progress_code(code: u8, console: bool) {
+    plat_boot_progress(code);
+    if console {
+        console_putchar(code);
+    }
+}
+
+/// Report that `stage` has completed.
+pub fn boot_progress(stage: boot_stage_t) {
+    boot_progress_code(
+        stage as u8,
+        cfg!(target_arch = "riscv64") || stage >= boot_stage_t::KernelWindow,
+    );
+    if stage == boot_stage_t::User {
+        console_putchar(b'\n');
+    }
+}
+
+/// Report that the boot failed.
+pub fn boot_progress_failed() {
+    boot_progress_code(BOOT_PROGRESS_FAILED, true);
+    console_putchar(b'\n');
+}
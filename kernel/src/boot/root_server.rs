@@ -9,6 +9,8 @@ use crate::structures::{
 use crate::{BIT, ROUND_DOWN};
 use log::debug;
 use sel4_common::arch::{ArchReg, ArchTCB};
+#[cfg(feature = "ENABLE_MCS")]
+use sel4_common::sel4_config::seL4_MinSchedContextBits;
 #[cfg(target_arch = "riscv64")]
 use sel4_common::sel4_config::CONFIG_PT_LEVELS;
 #[cfg(target_arch = "aarch64")]
@@ -41,6 +43,8 @@ pub static mut rootserver: rootserver_mem_t = rootserver_mem_t {
     boot_info: 0,
     extra_bi: 0,
     tcb: 0,
+    #[cfg(feature = "ENABLE_MCS")]
+    sc: 0,
     paging: region_t {
         start: (0),
         end: (0),
@@ -68,10 +72,19 @@ pub fn root_server_init(
     }
 
     create_domain_cap(&root_cnode_cap);
+    #[cfg(feature = "ENABLE_MEMORY_HOTADD")]
+    create_memory_control_cap(&root_cnode_cap);
+    #[cfg(feature = "ENABLE_ARM_SMC")]
+    create_smc_cap(&root_cnode_cap);
     init_irqs(&root_cnode_cap);
     unsafe {
         rust_populate_bi_frame(0, CONFIG_MAX_NUM_NODES, ipcbuf_vptr, extra_bi_size);
     }
+    #[cfg(feature = "ENABLE_MCS")]
+    if !create_sched_control_caps(&root_cnode_cap) {
+        debug!("ERROR: could not create the sched control caps");
+        return None;
+    }
     let it_pd_cap = unsafe { rust_create_it_address_space(&root_cnode_cap, it_v_reg) };
     if it_pd_cap.get_cap_type() == CapTag::CapNullCap {
         debug!("ERROR: address space creation for initial thread failed");
@@ -104,6 +117,8 @@ pub fn root_server_init(
     if !asid_init(root_cnode_cap, it_pd_cap) {
         return None;
     }
+    #[cfg(feature = "ENABLE_BOOT_PROGRESS")]
+    crate::boot::boot_progress(crate::boot::boot_stage_t::Asid);
 
     let initial = unsafe {
         create_initial_thread(
@@ -131,59 +146,102 @@ unsafe fn create_initial_thread(
     ipcbuf_vptr: usize,
     ipcbuf_cap: cap_t,
 ) -> *mut tcb_t {
-    let tcb = convert_to_mut_type_ref::<tcb_t>(rootserver.tcb + TCB_OFFSET);
-    tcb.tcbTimeSlice = CONFIG_TIME_SLICE;
-    tcb.tcbArch = ArchTCB::default();
-
-    let cnode = convert_to_mut_type_ref::<cte_t>(root_cnode_cap.get_cap_ptr());
-    let ipc_buf_slot = cnode.get_offset_slot(seL4_CapInitThreadIPCBuffer);
-    let dc_ret = ipc_buf_slot.derive_cap(&ipcbuf_cap.clone());
-    if dc_ret.status != exception_t::EXCEPTION_NONE {
-        debug!("Failed to derive copy of IPC Buffer\n");
-        return 0 as *mut tcb_t;
-    }
+    unsafe {
+        let tcb = convert_to_mut_type_ref::<tcb_t>(rootserver.tcb + TCB_OFFSET);
+        tcb.tcbTimeSlice = CONFIG_TIME_SLICE;
+        tcb.tcbArch = ArchTCB::default();
+
+        let cnode = convert_to_mut_type_ref::<cte_t>(root_cnode_cap.get_cap_ptr());
+        let ipc_buf_slot = cnode.get_offset_slot(seL4_CapInitThreadIPCBuffer);
+        let dc_ret = ipc_buf_slot.derive_cap(&ipcbuf_cap.clone());
+        if dc_ret.status != exception_t::EXCEPTION_NONE {
+            debug!("Failed to derive copy of IPC Buffer\n");
+            return 0 as *mut tcb_t;
+        }
 
-    cte_insert(
-        root_cnode_cap,
-        cnode.get_offset_slot(seL4_CapInitThreadCNode),
-        tcb.get_cspace_mut_ref(tcbCTable),
-    );
+        cte_insert(
+            root_cnode_cap,
+            cnode.get_offset_slot(seL4_CapInitThreadCNode),
+            tcb.get_cspace_mut_ref(tcbCTable),
+        );
 
-    cte_insert(
-        it_pd_cap,
-        cnode.get_offset_slot(seL4_CapInitThreadVspace),
-        tcb.get_cspace_mut_ref(tcbVTable),
-    );
+        cte_insert(
+            it_pd_cap,
+            cnode.get_offset_slot(seL4_CapInitThreadVspace),
+            tcb.get_cspace_mut_ref(tcbVTable),
+        );
 
-    cte_insert(
-        &dc_ret.cap,
-        cnode.get_offset_slot(seL4_CapInitThreadIPCBuffer),
-        tcb.get_cspace_mut_ref(tcbBuffer),
-    );
+        cte_insert(
+            &dc_ret.cap,
+            cnode.get_offset_slot(seL4_CapInitThreadIPCBuffer),
+            tcb.get_cspace_mut_ref(tcbBuffer),
+        );
 
-    tcb.tcbIPCBuffer = ipcbuf_vptr;
-    tcb.tcbArch.set_register(ArchReg::Cap, bi_frame_vptr);
-    tcb.tcbArch.set_register(ArchReg::NextIP, ui_v_entry);
-    tcb.tcbMCP = seL4_MaxPrio;
-    tcb.tcbPriority = seL4_MaxPrio;
-    set_thread_state(tcb, ThreadState::ThreadStateRunning);
-    tcb.setup_reply_master();
-    unsafe {
+        tcb.set_ipc_buffer(ipcbuf_vptr);
+        tcb.tcbArch.set_register(ArchReg::Cap, bi_frame_vptr);
+        tcb.tcbArch.set_register(ArchReg::NextIP, ui_v_entry);
+        tcb.tcbMCP = seL4_MaxPrio;
+        tcb.tcbPriority = seL4_MaxPrio;
+        tcb.set_running();
+        tcb.setup_reply_master();
         ksCurDomain = ksDomSchedule[ksDomScheduleIdx].domain;
         ksDomainTime = ksDomSchedule[ksDomScheduleIdx].length;
+        #[cfg(feature = "ENABLE_SMP")]
+        {
+            tcb.tcbAffinity = 0;
+        }
+        #[cfg(feature = "ENABLE_MCS")]
+        create_initial_thread_sc(cnode, tcb, rootserver.sc);
+
+        let cap = cap_t::new_thread_cap(tcb.get_ptr());
+        write_slot(
+            cnode.get_offset_slot(seL4_CapInitThreadTCB) as *mut cte_t,
+            cap,
+        );
+        // forget(*tcb);
+        tcb as *mut tcb_t
     }
-    #[cfg(feature = "ENABLE_SMP")]
-    {
-        tcb.tcbAffinity = 0;
-    }
+}
 
-    let cap = cap_t::new_thread_cap(tcb.get_ptr());
+/// Give the initial thread the scheduling context at `sc_ptr`, with a budget of `CONFIG_TIME_SLICE`
+/// ticks every period of the same length, so it runs as it would without one, and provide it in
+/// `seL4_CapInitThreadSC`.
+#[cfg(feature = "ENABLE_MCS")]
+fn create_initial_thread_sc(cnode: &mut cte_t, tcb: &mut tcb_t, sc_ptr: usize) {
+    use sel4_task::sched_context::{sched_context_t, MIN_REFILLS};
+    clear_memory(sc_ptr as *mut u8, seL4_MinSchedContextBits);
+    let sc = convert_to_mut_type_ref::<sched_context_t>(sc_ptr);
+    let budget = CONFIG_TIME_SLICE * RESET_CYCLES;
+    sc.configure(budget, budget, MIN_REFILLS, 0, 0);
+    sc.bind_tcb(tcb);
     write_slot(
-        cnode.get_offset_slot(seL4_CapInitThreadTCB) as *mut cte_t,
-        cap,
+        cnode.get_offset_slot(seL4_CapInitThreadSC) as *mut cte_t,
+        cap_t::new_sched_context_cap(seL4_MinSchedContextBits, sc_ptr),
     );
-    // forget(*tcb);
-    tcb as *mut tcb_t
+}
+
+/// Provide the caps reported in the `sharedFrames` region of the bootinfo.
+/// With `ENABLE_KERNEL_INFO_PAGE` this is a read-only cap to the kernel info page.
+pub fn create_shared_frames(root_cnode_cap: &cap_t) -> seL4_SlotRegion {
+    #[cfg(feature = "ENABLE_KERNEL_INFO_PAGE")]
+    {
+        let start = unsafe { ndks_boot.slot_pos_cur };
+        let pptr = paddr_to_pptr(kpptr_to_paddr(
+            sel4_common::kernel_info::kernel_info_page_addr(),
+        ));
+        let mut cap = create_unmapped_it_frame_cap(pptr, false);
+        cap.set_frame_vm_rights(sel4_common::arch::vm_rights_t::VMReadOnly as usize);
+        if provide_cap(root_cnode_cap, cap) {
+            return seL4_SlotRegion {
+                start,
+                end: start + 1,
+            };
+        }
+        debug!("ERROR: could not provide the kernel info page");
+    }
+    #[cfg(not(feature = "ENABLE_KERNEL_INFO_PAGE"))]
+    let _ = root_cnode_cap;
+    seL4_SlotRegion { start: 0, end: 0 }
 }
 
 // TODO: FIX asid_init for aarch64
@@ -249,54 +307,58 @@ fn create_frame_ui_frames(
 }
 
 unsafe fn root_server_mem_init(it_v_reg: v_region_t, extra_bi_size_bits: usize) {
-    let size = calculate_rootserver_size(it_v_reg, extra_bi_size_bits);
-    let max = rootserver_max_size_bits(extra_bi_size_bits);
-    let mut i = ndks_boot.freemem.len() - 1;
-    /* skip any empty regions */
-    while i != usize::MAX && is_reg_empty(&ndks_boot.freemem[i]) {
-        i -= 1;
-    }
-    while i != usize::MAX && i < ndks_boot.freemem.len() {
-        /* Invariant: both i and (i + 1) are valid indices in ndks_boot.freemem. */
-        assert!(i < (ndks_boot.freemem.len() - 1));
-        /* Invariant; the region at index i is the current candidate.
-         * Invariant: regions 0 up to (i - 1), if any, are additional candidates.
-         * Invariant: region (i + 1) is empty. */
-        assert!(is_reg_empty(&ndks_boot.freemem[i + 1]));
-
-        let empty_index = i + 1;
-        let unaligned_start = ndks_boot.freemem[i].end - size;
-        let start = ROUND_DOWN!(unaligned_start, max);
-
-        /* if unaligned_start didn't underflow, and start fits in the region,
-         * then we've found a region that fits the root server objects. */
-        if unaligned_start <= ndks_boot.freemem[i].end && start >= ndks_boot.freemem[i].start {
-            create_rootserver_objects(start, it_v_reg, extra_bi_size_bits);
-            ndks_boot.freemem[empty_index] = region_t {
-                start: start + size,
-                end: ndks_boot.freemem[i].end,
-            };
-            ndks_boot.freemem[i].end = start;
-            return;
+    unsafe {
+        let size = calculate_rootserver_size(it_v_reg, extra_bi_size_bits);
+        let max = rootserver_max_size_bits(extra_bi_size_bits);
+        let mut i = ndks_boot.freemem.len() - 1;
+        /* skip any empty regions */
+        while i != usize::MAX && is_reg_empty(&ndks_boot.freemem[i]) {
+            i -= 1;
+        }
+        while i != usize::MAX && i < ndks_boot.freemem.len() {
+            /* Invariant: both i and (i + 1) are valid indices in ndks_boot.freemem. */
+            assert!(i < (ndks_boot.freemem.len() - 1));
+            /* Invariant; the region at index i is the current candidate.
+             * Invariant: regions 0 up to (i - 1), if any, are additional candidates.
+             * Invariant: region (i + 1) is empty. */
+            assert!(is_reg_empty(&ndks_boot.freemem[i + 1]));
+
+            let empty_index = i + 1;
+            let unaligned_start = ndks_boot.freemem[i].end - size;
+            let start = ROUND_DOWN!(unaligned_start, max);
+
+            /* if unaligned_start didn't underflow, and start fits in the region,
+             * then we've found a region that fits the root server objects. */
+            if unaligned_start <= ndks_boot.freemem[i].end && start >= ndks_boot.freemem[i].start {
+                create_rootserver_objects(start, it_v_reg, extra_bi_size_bits);
+                ndks_boot.freemem[empty_index] = region_t {
+                    start: start + size,
+                    end: ndks_boot.freemem[i].end,
+                };
+                ndks_boot.freemem[i].end = start;
+                return;
+            }
+            /* Region i isn't big enough, so shuffle it up to slot (i + 1),
+             * which we know is unused. */
+            ndks_boot.freemem[empty_index] = ndks_boot.freemem[i];
+            ndks_boot.freemem[i] = region_t { start: 0, end: 0 };
+            i -= 1;
         }
-        /* Region i isn't big enough, so shuffle it up to slot (i + 1),
-         * which we know is unused. */
-        ndks_boot.freemem[empty_index] = ndks_boot.freemem[i];
-        ndks_boot.freemem[i] = region_t { start: 0, end: 0 };
-        i -= 1;
     }
 }
 
 unsafe fn create_root_cnode() -> cap_t {
-    let cap = cap_t::new_cnode_cap(
-        CONFIG_ROOT_CNODE_SIZE_BITS,
-        wordBits - CONFIG_ROOT_CNODE_SIZE_BITS,
-        0,
-        rootserver.cnode,
-    );
-    let ptr = rootserver.cnode as *mut cte_t;
-    write_slot(ptr.add(seL4_CapInitThreadCNode), cap.clone());
-    cap
+    unsafe {
+        let cap = cap_t::new_cnode_cap(
+            CONFIG_ROOT_CNODE_SIZE_BITS,
+            wordBits - CONFIG_ROOT_CNODE_SIZE_BITS,
+            0,
+            rootserver.cnode,
+        );
+        let ptr = rootserver.cnode as *mut cte_t;
+        write_slot(ptr.add(seL4_CapInitThreadCNode), cap.clone());
+        cap
+    }
 }
 
 fn calculate_rootserver_size(it_v_reg: v_region_t, extra_bi_size_bits: usize) -> usize {
@@ -311,6 +373,10 @@ fn calculate_rootserver_size(it_v_reg: v_region_t, extra_bi_size_bits: usize) ->
         0
     };
     size += BIT!(seL4_VSpaceBits);
+    #[cfg(feature = "ENABLE_MCS")]
+    {
+        size += BIT!(seL4_MinSchedContextBits);
+    }
     return size + arch_get_n_paging(it_v_reg) * BIT!(seL4_PageTableBits);
 }
 
@@ -340,42 +406,52 @@ fn alloc_rootserver_obj(size_bits: usize, n: usize) -> usize {
 
 #[inline]
 unsafe fn it_alloc_paging() -> usize {
-    let allocated = rootserver.paging.start;
-    rootserver.paging.start += BIT!(seL4_PageTableBits);
-    assert!(rootserver.paging.start <= rootserver.paging.end);
-    allocated
+    unsafe {
+        let allocated = rootserver.paging.start;
+        rootserver.paging.start += BIT!(seL4_PageTableBits);
+        assert!(rootserver.paging.start <= rootserver.paging.end);
+        allocated
+    }
 }
 
 unsafe fn maybe_alloc_extra_bi(cmp_size_bits: usize, extra_bi_size_bits: usize) {
-    if extra_bi_size_bits >= cmp_size_bits && rootserver.extra_bi == 0 {
-        rootserver.extra_bi = alloc_rootserver_obj(extra_bi_size_bits, 1);
+    unsafe {
+        if extra_bi_size_bits >= cmp_size_bits && rootserver.extra_bi == 0 {
+            rootserver.extra_bi = alloc_rootserver_obj(extra_bi_size_bits, 1);
+        }
     }
 }
 
 unsafe fn create_rootserver_objects(start: usize, it_v_reg: v_region_t, extra_bi_size_bits: usize) {
-    let cnode_size_bits = CONFIG_ROOT_CNODE_SIZE_BITS + seL4_SlotBits;
-    let max = rootserver_max_size_bits(extra_bi_size_bits);
-
-    let size = calculate_rootserver_size(it_v_reg, extra_bi_size_bits);
-    rootserver_mem.start = start;
-    rootserver_mem.end = start + size;
-    maybe_alloc_extra_bi(max, extra_bi_size_bits);
-
-    rootserver.cnode = alloc_rootserver_obj(cnode_size_bits, 1);
-    maybe_alloc_extra_bi(seL4_VSpaceBits, extra_bi_size_bits);
-    rootserver.vspace = alloc_rootserver_obj(seL4_VSpaceBits, 1);
-
-    maybe_alloc_extra_bi(seL4_PageBits, extra_bi_size_bits);
-    rootserver.asid_pool = alloc_rootserver_obj(seL4_ASIDPoolBits, 1);
-    rootserver.ipc_buf = alloc_rootserver_obj(seL4_PageBits, 1);
-    rootserver.boot_info = alloc_rootserver_obj(BI_FRAME_SIZE_BITS, 1);
-
-    let n = arch_get_n_paging(it_v_reg);
-    rootserver.paging.start = alloc_rootserver_obj(seL4_PageTableBits, n);
-    rootserver.paging.end = rootserver.paging.start + n * BIT!(seL4_PageTableBits);
-    rootserver.tcb = alloc_rootserver_obj(seL4_TCBBits, 1);
+    unsafe {
+        let cnode_size_bits = CONFIG_ROOT_CNODE_SIZE_BITS + seL4_SlotBits;
+        let max = rootserver_max_size_bits(extra_bi_size_bits);
+
+        let size = calculate_rootserver_size(it_v_reg, extra_bi_size_bits);
+        rootserver_mem.start = start;
+        rootserver_mem.end = start + size;
+        maybe_alloc_extra_bi(max, extra_bi_size_bits);
+
+        rootserver.cnode = alloc_rootserver_obj(cnode_size_bits, 1);
+        maybe_alloc_extra_bi(seL4_VSpaceBits, extra_bi_size_bits);
+        rootserver.vspace = alloc_rootserver_obj(seL4_VSpaceBits, 1);
+
+        maybe_alloc_extra_bi(seL4_PageBits, extra_bi_size_bits);
+        rootserver.asid_pool = alloc_rootserver_obj(seL4_ASIDPoolBits, 1);
+        rootserver.ipc_buf = alloc_rootserver_obj(seL4_PageBits, 1);
+        rootserver.boot_info = alloc_rootserver_obj(BI_FRAME_SIZE_BITS, 1);
+
+        let n = arch_get_n_paging(it_v_reg);
+        rootserver.paging.start = alloc_rootserver_obj(seL4_PageTableBits, n);
+        rootserver.paging.end = rootserver.paging.start + n * BIT!(seL4_PageTableBits);
+        rootserver.tcb = alloc_rootserver_obj(seL4_TCBBits, 1);
+        #[cfg(feature = "ENABLE_MCS")]
+        {
+            rootserver.sc = alloc_rootserver_obj(seL4_MinSchedContextBits, 1);
+        }
 
-    assert_eq!(rootserver_mem.start, rootserver_mem.end);
+        assert_eq!(rootserver_mem.start, rootserver_mem.end);
+    }
 }
 
 fn create_domain_cap(root_cnode_cap: &cap_t) {
@@ -393,14 +469,54 @@ fn create_domain_cap(root_cnode_cap: &cap_t) {
     }
 }
 
+#[cfg(feature = "ENABLE_MEMORY_HOTADD")]
+fn create_memory_control_cap(root_cnode_cap: &cap_t) {
+    unsafe {
+        let pos = root_cnode_cap.get_cap_ptr() as *mut cte_t;
+        write_slot(
+            pos.add(seL4_CapMemoryControl),
+            cap_t::new_memory_control_cap(),
+        );
+    }
+}
+
+/// Provide the unbadged SMC cap, which may make any firmware call.
+#[cfg(feature = "ENABLE_ARM_SMC")]
+fn create_smc_cap(root_cnode_cap: &cap_t) {
+    unsafe {
+        let pos = root_cnode_cap.get_cap_ptr() as *mut cte_t;
+        write_slot(pos.add(seL4_CapSMC), cap_t::new_smc_cap(0));
+    }
+}
+
+/// Provide one `SchedControl` cap per core and record them in the bootinfo.
+#[cfg(feature = "ENABLE_MCS")]
+fn create_sched_control_caps(root_cnode_cap: &cap_t) -> bool {
+    unsafe {
+        let start = ndks_boot.slot_pos_cur;
+        for core in 0..CONFIG_MAX_NUM_NODES {
+            if !provide_cap(root_cnode_cap, cap_t::new_sched_control_cap(core)) {
+                return false;
+            }
+        }
+        (*ndks_boot.bi_frame).schedcontrol = seL4_SlotRegion {
+            start,
+            end: ndks_boot.slot_pos_cur,
+        };
+    }
+    true
+}
+
 // TODO: FIX this for aarch64
 fn init_irqs(root_cnode_cap: &cap_t) {
-    for i in 0..maxIRQ + 1 {
+    for i in 0..INT_STATE_ARRAY_SIZE {
         if i != irqInvalid {
             setIRQState(IRQState::IRQInactive, i);
         }
     }
     setIRQState(IRQState::IRQTimer, KERNEL_TIMER_IRQ);
+    #[cfg(all(target_arch = "aarch64", feature = "ENABLE_ARM_HYP"))]
+    setIRQState(IRQState::IRQReserved, INTERRUPT_VGIC_MAINTENANCE);
     #[cfg(feature = "ENABLE_SMP")]
     {
         setIRQState(IRQState::IRQIPI, INTERRUPT_IPI_0);
@@ -414,86 +530,91 @@ fn init_irqs(root_cnode_cap: &cap_t) {
 
 #[cfg(target_arch = "riscv64")]
 unsafe fn rust_create_it_address_space(root_cnode_cap: &cap_t, it_v_reg: v_region_t) -> cap_t {
-    copyGlobalMappings(rootserver.vspace);
-    let lvl1pt_cap = cap_t::new_page_table_cap(IT_ASID, rootserver.vspace, 1, rootserver.vspace);
-    let ptr = root_cnode_cap.get_cap_ptr() as *mut cte_t;
-    let slot_pos_before = ndks_boot.slot_pos_cur;
-    write_slot(ptr.add(seL4_CapInitThreadVspace), lvl1pt_cap.clone());
-    let mut i = 0;
-    while i < CONFIG_PT_LEVELS - 1 {
-        let mut pt_vptr = ROUND_DOWN!(it_v_reg.start, RISCV_GET_LVL_PGSIZE_BITS(i));
-        while pt_vptr < it_v_reg.end {
-            if !provide_cap(
-                root_cnode_cap,
-                create_it_pt_cap(&lvl1pt_cap, it_alloc_paging(), pt_vptr, IT_ASID),
-            ) {
-                return cap_t::new_null_cap();
+    unsafe {
+        copyGlobalMappings(rootserver.vspace);
+        let lvl1pt_cap =
+            cap_t::new_page_table_cap(IT_ASID, rootserver.vspace, 1, rootserver.vspace);
+        let ptr = root_cnode_cap.get_cap_ptr() as *mut cte_t;
+        let slot_pos_before = ndks_boot.slot_pos_cur;
+        write_slot(ptr.add(seL4_CapInitThreadVspace), lvl1pt_cap.clone());
+        let mut i = 0;
+        while i < CONFIG_PT_LEVELS - 1 {
+            let mut pt_vptr = ROUND_DOWN!(it_v_reg.start, RISCV_GET_LVL_PGSIZE_BITS(i));
+            while pt_vptr < it_v_reg.end {
+                if !provide_cap(
+                    root_cnode_cap,
+                    create_it_pt_cap(&lvl1pt_cap, it_alloc_paging(), pt_vptr, IT_ASID),
+                ) {
+                    return cap_t::new_null_cap();
+                }
+                pt_vptr += RISCV_GET_LVL_PGSIZE(i);
             }
-            pt_vptr += RISCV_GET_LVL_PGSIZE(i);
+            i += 1;
         }
-        i += 1;
+        let slot_pos_after = ndks_boot.slot_pos_cur;
+        (*ndks_boot.bi_frame).userImagePaging = seL4_SlotRegion {
+            start: slot_pos_before,
+            end: slot_pos_after,
+        };
+        lvl1pt_cap
     }
-    let slot_pos_after = ndks_boot.slot_pos_cur;
-    (*ndks_boot.bi_frame).userImagePaging = seL4_SlotRegion {
-        start: slot_pos_before,
-        end: slot_pos_after,
-    };
-    lvl1pt_cap
 }
 
 #[cfg(target_arch = "aarch64")]
 unsafe fn rust_create_it_address_space(root_cnode_cap: &cap_t, it_v_reg: v_region_t) -> cap_t {
-    // create the PGD
-    let vspace_cap = cap_t::new_page_global_directory_cap(IT_ASID, rootserver.vspace, 1);
-    let ptr = root_cnode_cap.get_cap_ptr() as *mut cte_t;
-    let slot_pos_before = ndks_boot.slot_pos_cur;
-    write_slot(ptr.add(seL4_CapInitThreadVspace), vspace_cap.clone());
-
-    // Create any PUDs needed for the user land image, should config `PGD_INDEX_OFFSET`, `PUD_INDEX_OFFSET`...
-    let PGD_INDEX_OFFSET = PAGE_BITS + PT_INDEX_BITS * 3;
-    let PUD_INDEX_OFFSET = PAGE_BITS + PT_INDEX_BITS * 2;
-    let PD_INDEX_OFFSET = PAGE_BITS + PT_INDEX_BITS;
-    let mut vptr = ROUND_DOWN!(it_v_reg.start, PGD_INDEX_OFFSET);
-    while vptr < it_v_reg.end {
-        if !provide_cap(
-            root_cnode_cap,
-            create_it_pud_cap(&vspace_cap, it_alloc_paging(), vptr, IT_ASID),
-        ) {
-            return cap_t::new_null_cap();
+    unsafe {
+        // create the PGD
+        let vspace_cap = cap_t::new_page_global_directory_cap(IT_ASID, rootserver.vspace, 1);
+        let ptr = root_cnode_cap.get_cap_ptr() as *mut cte_t;
+        let slot_pos_before = ndks_boot.slot_pos_cur;
+        write_slot(ptr.add(seL4_CapInitThreadVspace), vspace_cap.clone());
+
+        // Create any PUDs needed for the user land image, should config `PGD_INDEX_OFFSET`, `PUD_INDEX_OFFSET`...
+        let PGD_INDEX_OFFSET = PAGE_BITS + PT_INDEX_BITS * 3;
+        let PUD_INDEX_OFFSET = PAGE_BITS + PT_INDEX_BITS * 2;
+        let PD_INDEX_OFFSET = PAGE_BITS + PT_INDEX_BITS;
+        let mut vptr = ROUND_DOWN!(it_v_reg.start, PGD_INDEX_OFFSET);
+        while vptr < it_v_reg.end {
+            if !provide_cap(
+                root_cnode_cap,
+                create_it_pud_cap(&vspace_cap, it_alloc_paging(), vptr, IT_ASID),
+            ) {
+                return cap_t::new_null_cap();
+            }
+            vptr += BIT!(PGD_INDEX_OFFSET);
         }
-        vptr += BIT!(PGD_INDEX_OFFSET);
-    }
 
-    // Create any PDs needed for the user land image
-    vptr = ROUND_DOWN!(it_v_reg.start, PUD_INDEX_OFFSET);
-    while vptr < it_v_reg.end {
-        if !provide_cap(
-            root_cnode_cap,
-            create_it_pd_cap(&vspace_cap, it_alloc_paging(), vptr, IT_ASID),
-        ) {
-            return cap_t::new_null_cap();
+        // Create any PDs needed for the user land image
+        vptr = ROUND_DOWN!(it_v_reg.start, PUD_INDEX_OFFSET);
+        while vptr < it_v_reg.end {
+            if !provide_cap(
+                root_cnode_cap,
+                create_it_pd_cap(&vspace_cap, it_alloc_paging(), vptr, IT_ASID),
+            ) {
+                return cap_t::new_null_cap();
+            }
+            vptr += BIT!(PUD_INDEX_OFFSET);
         }
-        vptr += BIT!(PUD_INDEX_OFFSET);
-    }
 
-    // Create any PTs needed for the user land image
-    vptr = ROUND_DOWN!(it_v_reg.start, PD_INDEX_OFFSET);
-    while vptr < it_v_reg.end {
-        if !provide_cap(
-            root_cnode_cap,
-            create_it_pt_cap(&vspace_cap, it_alloc_paging(), vptr, IT_ASID),
-        ) {
-            return cap_t::new_null_cap();
+        // Create any PTs needed for the user land image
+        vptr = ROUND_DOWN!(it_v_reg.start, PD_INDEX_OFFSET);
+        while vptr < it_v_reg.end {
+            if !provide_cap(
+                root_cnode_cap,
+                create_it_pt_cap(&vspace_cap, it_alloc_paging(), vptr, IT_ASID),
+            ) {
+                return cap_t::new_null_cap();
+            }
+            vptr += BIT!(PD_INDEX_OFFSET);
         }
-        vptr += BIT!(PD_INDEX_OFFSET);
-    }
 
-    let slot_pos_after = ndks_boot.slot_pos_cur;
-    (*ndks_boot.bi_frame).userImagePaging = seL4_SlotRegion {
-        start: slot_pos_before,
-        end: slot_pos_after,
-    };
-    vspace_cap
+        let slot_pos_after = ndks_boot.slot_pos_cur;
+        (*ndks_boot.bi_frame).userImagePaging = seL4_SlotRegion {
+            start: slot_pos_before,
+            end: slot_pos_after,
+        };
+        vspace_cap
+    }
 }
 
 fn init_bi_frame_cap(
@@ -577,9 +698,12 @@ fn rust_create_frames_of_region(
 }
 
 unsafe fn create_bi_frame_cap(root_cnode_cap: &cap_t, pd_cap: &cap_t, vptr: usize) {
-    let cap = create_mapped_it_frame_cap(pd_cap, rootserver.boot_info, vptr, IT_ASID, false, false);
-    let ptr = root_cnode_cap.get_cap_ptr() as *mut cte_t;
-    write_slot(ptr.add(seL4_CapBootInfoFrame), cap);
+    unsafe {
+        let cap =
+            create_mapped_it_frame_cap(pd_cap, rootserver.boot_info, vptr, IT_ASID, false, false);
+        let ptr = root_cnode_cap.get_cap_ptr() as *mut cte_t;
+        write_slot(ptr.add(seL4_CapBootInfoFrame), cap);
+    }
 }
 
 unsafe fn rust_populate_bi_frame(
@@ -588,30 +712,35 @@ unsafe fn rust_populate_bi_frame(
     ipcbuf_vptr: usize,
     extra_bi_size: usize,
 ) {
-    clear_memory(rootserver.boot_info as *mut u8, BI_FRAME_SIZE_BITS);
-    if extra_bi_size != 0 {
-        clear_memory(
-            rootserver.extra_bi as *mut u8,
-            calculate_extra_bi_size_bits(extra_bi_size),
-        );
+    unsafe {
+        clear_memory(rootserver.boot_info as *mut u8, BI_FRAME_SIZE_BITS);
+        if extra_bi_size != 0 {
+            clear_memory(
+                rootserver.extra_bi as *mut u8,
+                calculate_extra_bi_size_bits(extra_bi_size),
+            );
+        }
+        let bi = &mut *(rootserver.boot_info as *mut seL4_BootInfo);
+        bi.nodeID = node_id;
+        bi.numNodes = num_nodes;
+        bi.numIOPTLevels = 0;
+        bi.ipcBuffer = ipcbuf_vptr as *mut seL4_IPCBuffer;
+        bi.initThreadCNodeSizeBits = CONFIG_ROOT_CNODE_SIZE_BITS;
+        bi.initThreadDomain = ksDomSchedule[ksDomScheduleIdx].domain;
+        bi.extraLen = extra_bi_size;
+
+        ndks_boot.bi_frame = bi as *mut seL4_BootInfo;
+        ndks_boot.slot_pos_cur = ROOT_CNODE_FIRST_FREE_SLOT;
     }
-    let bi = &mut *(rootserver.boot_info as *mut seL4_BootInfo);
-    bi.nodeID = node_id;
-    bi.numNodes = num_nodes;
-    bi.numIOPTLevels = 0;
-    bi.ipcBuffer = ipcbuf_vptr as *mut seL4_IPCBuffer;
-    bi.initThreadCNodeSizeBits = CONFIG_ROOT_CNODE_SIZE_BITS;
-    bi.initThreadDomain = ksDomSchedule[ksDomScheduleIdx].domain;
-    bi.extraLen = extra_bi_size;
-
-    ndks_boot.bi_frame = bi as *mut seL4_BootInfo;
-    ndks_boot.slot_pos_cur = seL4_NumInitialCaps;
 }
 
 unsafe fn create_ipcbuf_frame_cap(root_cnode_cap: &cap_t, pd_cap: &cap_t, vptr: usize) -> cap_t {
-    clear_memory(rootserver.ipc_buf as *mut u8, PAGE_BITS);
-    let cap = create_mapped_it_frame_cap(pd_cap, rootserver.ipc_buf, vptr, IT_ASID, false, false);
-    let ptr = root_cnode_cap.get_cap_ptr() as *mut cte_t;
-    write_slot(ptr.add(seL4_CapInitThreadIPCBuffer), cap.clone());
-    return cap;
+    unsafe {
+        clear_memory(rootserver.ipc_buf as *mut u8, PAGE_BITS);
+        let cap =
+            create_mapped_it_frame_cap(pd_cap, rootserver.ipc_buf, vptr, IT_ASID, false, false);
+        let ptr = root_cnode_cap.get_cap_ptr() as *mut cte_t;
+        write_slot(ptr.add(seL4_CapInitThreadIPCBuffer), cap.clone());
+        return cap;
+    }
 }
@@ -0,0 +1,147 @@
+//! A root task built into the kernel image, enabled by the `ENABLE_SELFTEST_ROOT_TASK` feature.
+//!
+//! Bringing the kernel up on a new board should not need a userland first. With the feature the
+//! kernel starts the root task of `arch/<arch>/selftest.S` instead of the image the elfloader
+//! loaded, which stays reserved but unused. The root task checks one kernel service per step and
+//! prints `rel4 selftest: <step> ok`, or `FAILED` and stops:
+//!
+//! - `hello`: the root task runs and can print
+//! - `retype`: an endpoint, a notification, a TCB and a frame from the first untyped in RAM
+//! - `notification`: a signal through a copy minted with a badge, the wait returns the badge
+//! - `ipc`: a second thread calls the root task over the endpoint and checks the reply
+//! - `map`: the frame is mapped, with the page tables it needs, and a write reads back
+//! - `irq`: a handler for [`SELFTEST_IRQ`] is issued, bound to the notification and acked
+//!
+//! `rel4 selftest: all passed` ends a good run, then the root task suspends itself. The output
+//! goes through `seL4_DebugPutChar`, so the kernel has to be built with printing.
+
+use core::mem::offset_of;
+
+use sel4_common::arch::{MessageLabel, ObjectType};
+use sel4_common::sel4_config::{seL4_DeleteFirst, wordBits};
+use sel4_common::structures::seL4_IPCBuffer;
+use sel4_vspace::kpptr_to_paddr;
+
+use crate::config::{
+    seL4_CapIRQControl, seL4_CapInitThreadCNode, seL4_CapInitThreadTCB, seL4_CapInitThreadVspace,
+    SELFTEST_IRQ,
+};
+use crate::structures::{seL4_BootInfo, seL4_SlotRegion, seL4_UntypedDesc};
+#[cfg(not(feature = "ENABLE_MCS"))]
+use crate::syscall::SysReply;
+use crate::syscall::{SysCall, SysRecv, SysSend};
+
+/// Where the root task runs
+const SELFTEST_VBASE: usize = 0x40_0000;
+/// Where the `map` step maps its frame, a page table level away from the root task
+const SELFTEST_MAP_VADDR: usize = 0x4000_0000;
+/// Handled by `handleUnknownSyscall` in the kernel builds with printing
+#[cfg(not(feature = "ENABLE_MCS"))]
+const SysDebugPutChar: isize = -9;
+#[cfg(feature = "ENABLE_MCS")]
+const SysDebugPutChar: isize = -12;
+/// The reply of the `ipc` step, there is no SysReply with reply objects
+#[cfg(feature = "ENABLE_MCS")]
+const SysReply: isize = SysSend;
+/// `seL4_AllRights` as a word of `seL4_CapRights_t`
+const ALL_RIGHTS: usize = 0b1111;
+/// `seL4_ReadWrite` as a word of `seL4_CapRights_t`
+const READ_WRITE: usize = 0b0011;
+/// The retype the `ipc` step needs for the reply of the root task, 0 without reply objects
+#[cfg(feature = "ENABLE_MCS")]
+const REPLY_OBJECT: usize = ObjectType::ReplyObject as usize;
+#[cfg(not(feature = "ENABLE_MCS"))]
+const REPLY_OBJECT: usize = 0;
+
+macro_rules! selftest_asm {
+    ($file:literal, $($arch:tt)*) => {
+        core::arch::global_asm!(
+            include_str!($file),
+            sys_call = const SysCall,
+            sys_send = const SysSend,
+            sys_recv = const SysRecv,
+            sys_reply = const SysReply,
+            sys_debug_put_char = const SysDebugPutChar,
+            cap_init_tcb = const seL4_CapInitThreadTCB,
+            cap_init_cnode = const seL4_CapInitThreadCNode,
+            cap_init_vspace = const seL4_CapInitThreadVspace,
+            cap_irq_control = const seL4_CapIRQControl,
+            bi_ipc_buffer = const offset_of!(seL4_BootInfo, ipcBuffer),
+            bi_empty_start = const offset_of!(seL4_BootInfo, empty)
+                + offset_of!(seL4_SlotRegion, start),
+            bi_untyped_start = const offset_of!(seL4_BootInfo, untyped)
+                + offset_of!(seL4_SlotRegion, start),
+            bi_untyped_end = const offset_of!(seL4_BootInfo, untyped)
+                + offset_of!(seL4_SlotRegion, end),
+            bi_untyped_list = const offset_of!(seL4_BootInfo, untypedList),
+            ut_size_bits = const offset_of!(seL4_UntypedDesc, sizeBits),
+            ut_is_device = const offset_of!(seL4_UntypedDesc, isDevice),
+            ut_desc_size = const core::mem::size_of::<seL4_UntypedDesc>(),
+            ipc_msg = const offset_of!(seL4_IPCBuffer, msg),
+            ipc_caps = const offset_of!(seL4_IPCBuffer, caps_or_badges),
+            untyped_retype = const MessageLabel::UntypedRetype as usize,
+            cnode_mint = const MessageLabel::CNodeMint as usize,
+            tcb_configure = const MessageLabel::TCBConfigure as usize,
+            tcb_write_registers = const MessageLabel::TCBWriteRegisters as usize,
+            tcb_suspend = const MessageLabel::TCBSuspend as usize,
+            irq_issue_handler = const MessageLabel::IRQIssueIRQHandler as usize,
+            irq_set_handler = const MessageLabel::IRQSetIRQHandler as usize,
+            irq_ack = const MessageLabel::IRQAckIRQ as usize,
+            endpoint_object = const ObjectType::EndpointObject as usize,
+            notification_object = const ObjectType::NotificationObject as usize,
+            tcb_object = const ObjectType::TCBObject as usize,
+            reply_object = const REPLY_OBJECT,
+            word_bits = const wordBits,
+            all_rights = const ALL_RIGHTS,
+            read_write = const READ_WRITE,
+            delete_first = const seL4_DeleteFirst,
+            map_vaddr = const SELFTEST_MAP_VADDR,
+            irq = const SELFTEST_IRQ,
+            $($arch)*
+        );
+    };
+}
+
+#[cfg(target_arch = "riscv64")]
+selftest_asm!(
+    "../arch/riscv/selftest.S",
+    page_object = const ObjectType::NormalPageObject as usize,
+    page_table_object = const ObjectType::PageTableObject as usize,
+    page_table_map = const MessageLabel::RISCVPageTableMap as usize,
+    page_map = const MessageLabel::RISCVPageMap as usize,
+);
+
+#[cfg(target_arch = "aarch64")]
+selftest_asm!(
+    "../arch/aarch64/selftest.S",
+    page_object = const ObjectType::seL4_ARM_SmallPageObject as usize,
+    page_upper_directory_object = const ObjectType::seL4_ARM_PageUpperDirectoryObject as usize,
+    page_directory_object = const ObjectType::seL4_ARM_PageDirectoryObject as usize,
+    page_table_object = const ObjectType::seL4_ARM_PageTableObject as usize,
+    page_upper_directory_map = const MessageLabel::ARMPageUpperDirectoryMap as usize,
+    page_directory_map = const MessageLabel::ARMPageDirectoryMap as usize,
+    page_table_map = const MessageLabel::ARMPageTableMap as usize,
+    page_map = const MessageLabel::ARMPageMap as usize,
+    // seL4_ARM_Default_VMAttributes: cacheable and parity enabled
+    page_attr = const 0b11,
+);
+
+extern "C" {
+    fn selftest_start();
+    fn selftest_entry();
+    fn selftest_end();
+}
+
+/// The embedded root task in the terms of `try_init_kernel`: the start and end of its physical
+/// region, the offset of its physical addresses from the virtual ones and its entry point.
+pub fn selftest_root_task() -> (usize, usize, isize, usize) {
+    let start = selftest_start as usize;
+    let p_start = kpptr_to_paddr(start);
+    let p_end = kpptr_to_paddr(selftest_end as usize);
+    (
+        p_start,
+        p_end,
+        p_start as isize - SELFTEST_VBASE as isize,
+        SELFTEST_VBASE + (selftest_entry as usize - start),
+    )
+}
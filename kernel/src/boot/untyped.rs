@@ -6,8 +6,9 @@ use crate::{
 
 use crate::{BIT, IS_ALIGNED, MASK};
 use log::debug;
+use sel4_common::arch::config::PTR_FIELD_BITS;
 use sel4_common::sel4_config::{seL4_MaxUntypedBits, seL4_MinUntypedBits};
-use sel4_common::utils::MAX_FREE_INDEX;
+use sel4_common::utils::{ptr_range_fits_field, MAX_FREE_INDEX};
 use sel4_cspace::interface::*;
 use sel4_vspace::*;
 
@@ -86,8 +87,8 @@ fn create_untypeds_for_region(
 ) -> bool {
     while !is_reg_empty(&reg) {
         let mut size_bits = seL4_WordBits - 1 - (reg.end - reg.start).leading_zeros() as usize;
-        if size_bits > seL4_MaxUntypedBits {
-            size_bits = seL4_MaxUntypedBits;
+        if size_bits > CONFIG_MAX_BOOT_UNTYPED_BITS {
+            size_bits = CONFIG_MAX_BOOT_UNTYPED_BITS;
         }
         if reg.start != 0 {
             let align_bits = reg.start.trailing_zeros() as usize;
@@ -131,6 +132,14 @@ fn provide_untyped_cap(
         return false;
     }
 
+    if !ptr_range_fits_field(pptr, pptr.wrapping_add(BIT!(size_bits))) {
+        debug!(
+            "ERROR: untyped at {:#x} (size {}) does not fit the {}-bit cap pointer field",
+            pptr, size_bits, PTR_FIELD_BITS
+        );
+        return false;
+    }
+
     if !device_memory && !pptr_in_kernel_window(pptr) {
         debug!(
             "Kernel init: Non-device untyped pptr {:#x} outside kernel window",
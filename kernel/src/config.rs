@@ -32,8 +32,37 @@ pub const CONFIG_PADDR_USER_DEVICE_TOP: usize = 549755813888;
 #[cfg(target_arch = "aarch64")]
 pub const CONFIG_PADDR_USER_DEVICE_TOP: usize = 17592186044416;
 
+// Device untypeds up to CONFIG_PADDR_USER_DEVICE_TOP are kernel pointers in the cap bitfields
+const _: () = assert!(sel4_common::utils::ptr_range_fits_field(
+    sel4_common::arch::config::PPTR_BASE_OFFSET,
+    CONFIG_PADDR_USER_DEVICE_TOP.wrapping_add(sel4_common::arch::config::PPTR_BASE_OFFSET)
+));
+
+// The platform layout of `build.rs`, which the linker script was generated from, has to match the
+// kernel window of sel4_common
+include!(concat!(env!("OUT_DIR"), "/platform.rs"));
+const _: () = assert!(
+    PLATFORM_LOAD_PADDR == sel4_common::arch::config::KERNEL_ELF_PADDR_BASE,
+    "the load address of the platform in layout.rs differs from KERNEL_ELF_PADDR_BASE"
+);
+const _: () = assert!(
+    PLATFORM_LOAD_VADDR == sel4_common::arch::config::KERNEL_ELF_BASE,
+    "the kernel window of the platform in layout.rs differs from KERNEL_ELF_BASE"
+);
+const _: () = assert!(
+    PLATFORM_CHECKED_NUM_NODES == sel4_common::sel4_config::CONFIG_MAX_NUM_NODES,
+    "layout.rs checked the kernel stacks of another number of cores than CONFIG_MAX_NUM_NODES"
+);
+const _: () = assert!(
+    PLATFORM_CHECKED_STACK_BITS == sel4_common::sel4_config::CONFIG_KERNEL_STACK_BITS,
+    "layout.rs checked kernel stacks of another size than CONFIG_KERNEL_STACK_BITS"
+);
+
 pub const MAX_NUM_FREEMEM_REG: usize = 16;
+#[cfg(not(feature = "ENABLE_PERSISTENT_LOG"))]
 pub const NUM_RESERVED_REGIONS: usize = 3;
+#[cfg(feature = "ENABLE_PERSISTENT_LOG")]
+pub const NUM_RESERVED_REGIONS: usize = 4;
 pub const MAX_NUM_RESV_REG: usize = MAX_NUM_FREEMEM_REG + NUM_RESERVED_REGIONS;
 
 pub const CONFIG_ROOT_CNODE_SIZE_BITS: usize = 13;
@@ -56,21 +85,20 @@ pub const seL4_CapInitThreadIPCBuffer: usize = 10;
 pub const seL4_CapDomain: usize = 11;
 pub const seL4_CapSMMUSIDControl: usize = 12;
 pub const seL4_CapSMMUCBControl: usize = 13;
-pub const seL4_NumInitialCaps: usize = 14;
-
-pub const SIP_SSIP: usize = 1;
-pub const SIP_MSIP: usize = 3;
-pub const SIP_STIP: usize = 5;
-pub const SIP_MTIP: usize = 7;
-pub const SIP_SEIP: usize = 9;
-pub const SIP_MEIP: usize = 11;
-
-pub const SIE_SSIE: usize = 1;
-pub const SIE_MSIE: usize = 3;
-pub const SIE_STIE: usize = 5;
-pub const SIE_MTIE: usize = 7;
-pub const SIE_SEIE: usize = 9;
-pub const SIE_MEIE: usize = 11;
+/// The scheduling context of the initial thread, see `ENABLE_MCS`.
+#[cfg(feature = "ENABLE_MCS")]
+pub const seL4_CapInitThreadSC: usize = 14;
+/// The SMC cap, see `ENABLE_ARM_SMC`.
+#[cfg(feature = "ENABLE_ARM_SMC")]
+pub const seL4_CapSMC: usize = 15;
+pub const seL4_NumInitialCaps: usize = 16;
+/// reL4 extension: the memory control cap, see `ENABLE_MEMORY_HOTADD`. It takes the first slot
+/// after the initial caps of libsel4, so the empty region in the bootinfo starts after it.
+#[cfg(feature = "ENABLE_MEMORY_HOTADD")]
+pub const seL4_CapMemoryControl: usize = seL4_NumInitialCaps;
+/// The first slot of the root CNode the kernel fills with the caps it reports in the bootinfo.
+pub const ROOT_CNODE_FIRST_FREE_SLOT: usize =
+    seL4_NumInitialCaps + cfg!(feature = "ENABLE_MEMORY_HOTADD") as usize;
 
 pub const seL4_MsgLengthBits: usize = 7;
 
@@ -85,6 +113,12 @@ pub const RISCVEnvCall: usize = 8;
 pub const RISCVInstructionPageFault: usize = 12;
 pub const RISCVLoadPageFault: usize = 13;
 pub const RISCVStorePageFault: usize = 15;
+/// The causes of a guest trap, see `ENABLE_RISCV_HYP`
+pub const RISCVEnvCallFromVS: usize = 10;
+pub const RISCVInstructionGuestPageFault: usize = 20;
+pub const RISCVLoadGuestPageFault: usize = 21;
+pub const RISCVVirtualInstruction: usize = 22;
+pub const RISCVStoreGuestPageFault: usize = 23;
 pub const RISCVSupervisorTimer: usize = 9223372036854775813;
 
 pub const thread_control_update_priority: usize = 0x1;
@@ -115,6 +149,10 @@ pub const KERNEL_TIMER_IRQ: usize = 1;
 #[cfg(all(not(feature = "ENABLE_SMP"), target_arch = "aarch64"))]
 pub const KERNEL_TIMER_IRQ: usize = 27;
 
+/// The PPI the virtual GIC interface raises for maintenance, see `ENABLE_ARM_HYP`
+#[cfg(all(target_arch = "aarch64", feature = "ENABLE_ARM_HYP"))]
+pub const INTERRUPT_VGIC_MAINTENANCE: usize = 25;
+
 #[cfg(target_arch = "riscv64")]
 pub const maxIRQ: usize = KERNEL_TIMER_IRQ;
 
@@ -123,17 +161,121 @@ pub const maxIRQ: usize = 159;
 
 pub const irqInvalid: usize = 0;
 
+/// The LPIs `ENABLE_GIC_ITS` hands out, kernel IRQs from `LPI_IRQ_BASE` on
+#[cfg(feature = "ENABLE_GIC_ITS")]
+pub const CONFIG_MAX_NUM_LPIS: usize = 64;
+#[cfg(feature = "ENABLE_GIC_ITS")]
+pub const LPI_IRQ_BASE: usize = maxIRQ + 1;
+/// The ITS devices which can have MSIs mapped at the same time, see `ENABLE_GIC_ITS`
+#[cfg(feature = "ENABLE_GIC_ITS")]
+pub const CONFIG_MAX_NUM_ITS_DEVICES: usize = 16;
+
+/// The sources of the APLIC of `ENABLE_RISCV_AIA`, 1 to `CONFIG_APLIC_NUM_SOURCES - 1`
+#[cfg(feature = "ENABLE_RISCV_AIA")]
+pub const CONFIG_APLIC_NUM_SOURCES: usize = 96;
+/// The interrupt identities of an IMSIC interrupt file, identity 0 is never signalled. Those below
+/// `CONFIG_APLIC_NUM_SOURCES` carry the APLIC source of the same number, the others are MSIs.
+#[cfg(feature = "ENABLE_RISCV_AIA")]
+pub const CONFIG_IMSIC_NUM_IDS: usize = 128;
+/// The kernel IRQ of IMSIC identity 0, see `ENABLE_RISCV_AIA`
+#[cfg(feature = "ENABLE_RISCV_AIA")]
+pub const AIA_IRQ_BASE: usize = maxIRQ + 1;
+/// The sources of the PLIC of `ENABLE_RISCV_PLIC`, 1 to `CONFIG_PLIC_NUM_SOURCES - 1`
+#[cfg(feature = "ENABLE_RISCV_PLIC")]
+pub const CONFIG_PLIC_NUM_SOURCES: usize = 96;
+/// The kernel IRQ of PLIC source 0, see `ENABLE_RISCV_PLIC`
+#[cfg(feature = "ENABLE_RISCV_PLIC")]
+pub const PLIC_IRQ_BASE: usize = maxIRQ + 1;
+/// The hart of the boot core, which takes every external interrupt not routed to another core
+#[cfg(any(feature = "ENABLE_RISCV_AIA", feature = "ENABLE_RISCV_PLIC"))]
+pub const CONFIG_FIRST_HART_ID: usize = 0;
+
+/// The number of kernel IRQs, the wired interrupts followed by the LPIs of `ENABLE_GIC_ITS`, the
+/// IMSIC identities of `ENABLE_RISCV_AIA` or the PLIC sources of `ENABLE_RISCV_PLIC`
+#[cfg(feature = "ENABLE_GIC_ITS")]
+pub const INT_STATE_ARRAY_SIZE: usize = maxIRQ + 1 + CONFIG_MAX_NUM_LPIS;
+#[cfg(feature = "ENABLE_RISCV_AIA")]
+pub const INT_STATE_ARRAY_SIZE: usize = AIA_IRQ_BASE + CONFIG_IMSIC_NUM_IDS;
+#[cfg(feature = "ENABLE_RISCV_PLIC")]
+pub const INT_STATE_ARRAY_SIZE: usize = PLIC_IRQ_BASE + CONFIG_PLIC_NUM_SOURCES;
+#[cfg(not(any(
+    feature = "ENABLE_GIC_ITS",
+    feature = "ENABLE_RISCV_AIA",
+    feature = "ENABLE_RISCV_PLIC"
+)))]
+pub const INT_STATE_ARRAY_SIZE: usize = maxIRQ + 1;
+
+/// The interrupt the `irq` step of the embedded root task binds, `irqInvalid` to skip the step.
+/// RISC-V has none to spare below `maxIRQ`.
+#[cfg(all(feature = "ENABLE_SELFTEST_ROOT_TASK", target_arch = "riscv64"))]
+pub const SELFTEST_IRQ: usize = irqInvalid;
+/// The interrupt the `irq` step of the embedded root task binds, the first virtio-mmio
+/// interrupt of qemu-arm-virt.
+#[cfg(all(feature = "ENABLE_SELFTEST_ROOT_TASK", target_arch = "aarch64"))]
+pub const SELFTEST_IRQ: usize = 48;
+
 pub const SEL4_BOOTINFO_HEADER_FDT: usize = 6;
 pub const SEL4_BOOTINFO_HEADER_PADDING: usize = 0;
+/// reL4 extension: the kernel log of the previous boot, see `ENABLE_PERSISTENT_LOG`.
+pub const SEL4_BOOTINFO_HEADER_PREV_LOG: usize = 0x100;
+/// reL4 extension: the pinned CPU frequency in Hz, see `ENABLE_BENCHMARK`.
+pub const SEL4_BOOTINFO_HEADER_CPU_FREQ: usize = 0x101;
+/// reL4 extension: the devices found in the DTB, see `ENABLE_DTB_PLATFORM`.
+pub const SEL4_BOOTINFO_HEADER_DEVICES: usize = 0x102;
+/// reL4 extension: the `seL4_PlatformInfo` of the platform the kernel was built for.
+pub const SEL4_BOOTINFO_HEADER_PLATFORM: usize = 0x103;
 pub const CONFIG_MAX_NUM_BOOTINFO_UNTYPED_CAPS: usize = 230;
 
+// Largest untyped handed to the root server at boot. Larger memory regions are split into
+// untypeds of at most this size, each aligned to its size. Platforms with a lot of RAM lower it
+// in `layout.rs` so user level allocators track fewer, more uniform blocks, at the cost of more
+// bootinfo entries.
+pub const CONFIG_MAX_BOOT_UNTYPED_BITS: usize = PLATFORM_MAX_BOOT_UNTYPED_BITS;
+const _: () = assert!(
+    CONFIG_MAX_BOOT_UNTYPED_BITS >= sel4_common::sel4_config::seL4_MinUntypedBits
+        && CONFIG_MAX_BOOT_UNTYPED_BITS <= sel4_common::sel4_config::seL4_MaxUntypedBits,
+    "the max_boot_untyped_bits of the platform in layout.rs is not a valid untyped size"
+);
+
 pub const seL4_MaxPrio: usize = 255;
 
+#[cfg(target_arch = "riscv64")]
 pub const TIMER_CLOCK_HZ: usize = 10000000;
+/// The frequency of the generic timer, as in `resetTimer`
+#[cfg(target_arch = "aarch64")]
+pub const TIMER_CLOCK_HZ: usize = 62500000;
 pub const MS_IN_S: usize = 1000;
+/// The period of the timer tick in timer cycles, see `crate::interrupt::timer::resetTimer`
+#[cfg(target_arch = "riscv64")]
 pub const RESET_CYCLES: usize = (TIMER_CLOCK_HZ / MS_IN_S) * 2;
+#[cfg(target_arch = "aarch64")]
+pub const RESET_CYCLES: usize = (TIMER_CLOCK_HZ / MS_IN_S) * 10;
+
+/// Length of a timer tick in microseconds, how far a scheduling context may overrun its budget,
+/// see `ENABLE_MCS`
+#[cfg(all(feature = "ENABLE_MCS", target_arch = "riscv64"))]
+pub const CONFIG_TIMER_TICK_US: usize = RESET_CYCLES / (TIMER_CLOCK_HZ / US_IN_S);
+#[cfg(all(feature = "ENABLE_MCS", target_arch = "aarch64"))]
+pub const CONFIG_TIMER_TICK_US: usize = 10 * US_IN_MS;
+#[cfg(feature = "ENABLE_MCS")]
+pub const US_IN_MS: usize = 1000;
+#[cfg(feature = "ENABLE_MCS")]
+pub const US_IN_S: usize = US_IN_MS * MS_IN_S;
+/// Longest period of a scheduling context, one hour like in seL4
+#[cfg(feature = "ENABLE_MCS")]
+pub const MAX_PERIOD_US: usize = 60 * 60 * US_IN_S;
+/// Shortest budget of a scheduling context, one tick
+#[cfg(feature = "ENABLE_MCS")]
+pub const MIN_BUDGET_US: usize = CONFIG_TIMER_TICK_US;
 
 pub const seL4_MinPrio: usize = 0;
 
 pub const CONFIG_MAX_NUM_WORK_UNITS_PER_PREEMPTION: usize = 100;
 pub const CONFIG_RETYPE_FAN_OUT_LIMIT: usize = 256;
+/// Largest radix of a CNode MemoryControlRelocateCNode moves, its slots are walked without a
+/// preemption point like the fan out of a retype
+#[cfg(feature = "ENABLE_OBJECT_RELOCATE")]
+pub const CONFIG_RELOCATE_CNODE_MAX_RADIX: usize = 8;
+/// Number of emulated MMIO regions of all vspaces, see `ENABLE_MMIO_EMULATION`
+#[cfg(all(target_arch = "aarch64", feature = "ENABLE_MMIO_EMULATION"))]
+pub const CONFIG_MAX_MMIO_REGIONS: usize = 16;
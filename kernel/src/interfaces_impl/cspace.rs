@@ -1,14 +1,20 @@
 use crate::config::CONFIG_MAX_NUM_WORK_UNITS_PER_PREEMPTION;
 use crate::ffi::tcbDebugRemove;
-use crate::interrupt::{deletingIRQHandler, isIRQPending, setIRQState, IRQState};
+use crate::interrupt::{deletedIRQHandler, deletingIRQHandler, isIRQPending};
 use crate::kernel::boot::current_lookup_fault;
 use crate::syscall::safe_unbind_notification;
 use sel4_common::sel4_config::{tcbCNodeEntries, tcbCTable, tcbVTable};
 use sel4_common::structures::exception_t;
-use sel4_common::utils::convert_to_mut_type_ref;
+#[cfg(feature = "ENABLE_MCS")]
+use sel4_common::utils::convert_to_option_mut_type_ref;
+use sel4_common::utils::{convert_to_checked_mut_type_ref, convert_to_mut_type_ref};
 use sel4_cspace::compatibility::{ZombieType_ZombieTCB, Zombie_new};
 use sel4_cspace::interface::{cap_t, finaliseCap_ret, CapTag};
 use sel4_ipc::{endpoint_t, notification_t, Transfer};
+#[cfg(feature = "ENABLE_MCS")]
+use sel4_task::sched_context::sched_context_t;
+#[cfg(feature = "ENABLE_MCS")]
+use sel4_task::ThreadState;
 use sel4_task::{get_currenct_thread, ksWorkUnitsCompleted, tcb_t};
 #[cfg(target_arch = "riscv64")]
 use sel4_vspace::find_vspace_for_asid;
@@ -45,6 +51,8 @@ pub fn Arch_finaliseCap(cap: &cap_t, final_: bool) -> finaliseCap_ret {
                 {
                     deleteASID(asid, pte as *mut PTE);
                 } else {
+                    #[cfg(feature = "ENABLE_SHARED_PT")]
+                    sel4_vspace::shared_pt_remove_all(pte);
                     convert_to_mut_type_ref::<PTE>(pte)
                         .unmap_page_table(asid, cap.get_pt_mapped_address());
                 }
@@ -61,6 +69,16 @@ pub fn Arch_finaliseCap(cap: &cap_t, final_: bool) -> finaliseCap_ret {
                 deleteASIDPool(cap.get_asid_base(), cap.get_asid_pool() as *mut asid_pool_t);
             }
         }
+        #[cfg(feature = "ENABLE_RISCV_HYP")]
+        CapTag::CapVCPUCap => {
+            if final_ {
+                let vcpu = convert_to_mut_type_ref::<crate::arch::vcpu_t>(cap.get_vcpu_ptr());
+                vcpu.dissociate();
+                if sel4_vspace::is_guest_root_of_asid(&vcpu.gstage, vcpu.asid) {
+                    deleteASID(vcpu.asid, vcpu.gstage.as_vspace_root());
+                }
+            }
+        }
         _ => {}
     }
     fc_ret.remainder = cap_t::new_null_cap();
@@ -89,7 +107,11 @@ pub fn Arch_finaliseCap(cap: &cap_t, final_: bool) -> finaliseCap_ret {
         }
         CapTag::CapPageGlobalDirectoryCap => {
             if final_ && cap.get_pgd_is_mapped() == 1 {
-                deleteASID(cap.get_pgd_is_mapped(), cap.get_pgd_base_ptr() as _);
+                deleteASID(cap.get_pgd_mapped_asid(), cap.get_pgd_base_ptr() as _);
+            }
+            #[cfg(feature = "ENABLE_MMIO_EMULATION")]
+            if final_ {
+                crate::arch::mmio_regions_clear(cap.get_pgd_base_ptr());
             }
         }
         CapTag::CapPageUpperDirectoryCap => {
@@ -120,6 +142,14 @@ pub fn Arch_finaliseCap(cap: &cap_t, final_: bool) -> finaliseCap_ret {
             }
         }
         CapTag::CapASIDControlCap => {}
+        #[cfg(feature = "ENABLE_ARM_HYP")]
+        CapTag::CapVCPUCap => {
+            if final_ {
+                convert_to_mut_type_ref::<crate::arch::vcpu_t>(cap.get_vcpu_ptr()).dissociate();
+            }
+        }
+        #[cfg(feature = "ENABLE_ARM_SMC")]
+        CapTag::CapSMCCap => {}
         _ => unimplemented!("finaliseCap: {:?}", cap.get_cap_type()),
     }
     fc_ret.remainder = cap_t::new_null_cap();
@@ -144,7 +174,11 @@ pub fn finaliseCap(cap: &cap_t, _final: bool, _exposed: bool) -> finaliseCap_ret
         CapTag::CapEndpointCap => {
             if _final {
                 // cancelAllIPC(cap.get_ep_ptr() as *mut endpoint_t);
-                convert_to_mut_type_ref::<endpoint_t>(cap.get_ep_ptr()).cancel_all_ipc()
+                convert_to_checked_mut_type_ref::<endpoint_t>(cap.get_ep_ptr()).cancel_all_ipc();
+                #[cfg(feature = "ENABLE_BENCHMARK")]
+                sel4_ipc::ep_stats::ep_stats_clear(cap.get_ep_ptr());
+                #[cfg(feature = "ENABLE_OBJECT_GEN")]
+                sel4_common::object_gen::object_gen_kill(cap.get_ep_ptr());
             }
             fc_ret.remainder = cap_t::new_null_cap();
             fc_ret.cleanupInfo = cap_t::new_null_cap();
@@ -152,15 +186,47 @@ pub fn finaliseCap(cap: &cap_t, _final: bool, _exposed: bool) -> finaliseCap_ret
         }
         CapTag::CapNotificationCap => {
             if _final {
-                let ntfn = convert_to_mut_type_ref::<notification_t>(cap.get_nf_ptr());
+                let ntfn = convert_to_checked_mut_type_ref::<notification_t>(cap.get_nf_ptr());
                 ntfn.safe_unbind_tcb();
                 ntfn.cacncel_all_signal();
+                #[cfg(feature = "ENABLE_OBJECT_GEN")]
+                sel4_common::object_gen::object_gen_kill(cap.get_nf_ptr());
             }
             fc_ret.remainder = cap_t::new_null_cap();
             fc_ret.cleanupInfo = cap_t::new_null_cap();
             return fc_ret;
         }
-        CapTag::CapReplyCap | CapTag::CapNullCap | CapTag::CapDomainCap => {
+        #[cfg(feature = "ENABLE_MCS")]
+        CapTag::CapSchedContextCap => {
+            if _final {
+                let sc = convert_to_mut_type_ref::<sched_context_t>(cap.get_sc_ptr());
+                sel4_ipc::sched_context_unbind_reply(sc);
+                sc.unbind_tcb();
+            }
+            fc_ret.remainder = cap_t::new_null_cap();
+            fc_ret.cleanupInfo = cap_t::new_null_cap();
+            return fc_ret;
+        }
+        CapTag::CapReplyCap => {
+            #[cfg(feature = "ENABLE_MCS")]
+            if _final {
+                let reply = convert_to_mut_type_ref::<sel4_ipc::reply_t>(cap.get_reply_ptr());
+                if let Some(tcb) = convert_to_option_mut_type_ref::<tcb_t>(reply.replyTCB) {
+                    match tcb.get_state() {
+                        ThreadState::ThreadStateBlockedOnReply => reply.remove(tcb),
+                        ThreadState::ThreadStateBlockedOnReceive => tcb.cancel_ipc(),
+                        _ => {}
+                    }
+                }
+            }
+            fc_ret.remainder = cap_t::new_null_cap();
+            fc_ret.cleanupInfo = cap_t::new_null_cap();
+            return fc_ret;
+        }
+        CapTag::CapNullCap
+        | CapTag::CapDomainCap
+        | CapTag::CapMemoryControlCap
+        | CapTag::CapSchedControlCap => {
             fc_ret.remainder = cap_t::new_null_cap();
             fc_ret.cleanupInfo = cap_t::new_null_cap();
             return fc_ret;
@@ -175,6 +241,8 @@ pub fn finaliseCap(cap: &cap_t, _final: bool, _exposed: bool) -> finaliseCap_ret
     match cap.get_cap_type() {
         CapTag::CapCNodeCap => {
             return if _final {
+                #[cfg(feature = "ENABLE_OBJECT_GEN")]
+                sel4_common::object_gen::object_gen_kill(cap.get_cnode_ptr());
                 fc_ret.remainder = Zombie_new(
                     1usize << cap.get_cnode_radix(),
                     cap.get_cnode_radix(),
@@ -190,18 +258,29 @@ pub fn finaliseCap(cap: &cap_t, _final: bool, _exposed: bool) -> finaliseCap_ret
         }
         CapTag::CapThreadCap => {
             if _final {
-                let tcb = convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr());
+                let tcb = convert_to_checked_mut_type_ref::<tcb_t>(cap.get_tcb_ptr());
                 #[cfg(feature = "ENABLE_SMP")]
                 unsafe {
                     crate::ffi::remoteTCBStall(tcb)
                 };
                 let cte_ptr = tcb.get_cspace_mut_ref(tcbCTable);
                 safe_unbind_notification(tcb);
+                #[cfg(feature = "ENABLE_MCS")]
+                if tcb.tcbSchedContext != 0 {
+                    convert_to_mut_type_ref::<sched_context_t>(tcb.tcbSchedContext).unbind_tcb();
+                }
+                #[cfg(any(feature = "ENABLE_ARM_HYP", feature = "ENABLE_RISCV_HYP"))]
+                if tcb.tcbArch.get_vcpu() != 0 {
+                    convert_to_mut_type_ref::<crate::arch::vcpu_t>(tcb.tcbArch.get_vcpu())
+                        .dissociate();
+                }
                 tcb.cancel_ipc();
                 tcb.suspend();
                 unsafe {
                     tcbDebugRemove(tcb as *mut tcb_t);
                 }
+                #[cfg(feature = "ENABLE_OBJECT_GEN")]
+                sel4_common::object_gen::object_gen_kill(cap.get_tcb_ptr());
                 fc_ret.remainder =
                     Zombie_new(tcbCNodeEntries, ZombieType_ZombieTCB, cte_ptr.get_ptr());
                 fc_ret.cleanupInfo = cap_t::new_null_cap();
@@ -236,11 +315,18 @@ pub fn finaliseCap(cap: &cap_t, _final: bool, _exposed: bool) -> finaliseCap_ret
 #[no_mangle]
 pub fn post_cap_deletion(cap: &cap_t) {
     if cap.get_cap_type() == CapTag::CapIrqHandlerCap {
-        let irq = cap.get_irq_handler();
-        setIRQState(IRQState::IRQInactive, irq);
+        deletedIRQHandler(cap.get_irq_handler());
     }
 }
 
+/// Count one unit of a long running operation, and every `CONFIG_MAX_NUM_WORK_UNITS_PER_PREEMPTION`
+/// units check whether it has to stop for a pending interrupt or, with MCS, an exhausted budget.
+///
+/// On `EXCEPTION_PREEMTED` the caller returns at once and the invoking thread stays in
+/// `Restart`, so the invocation runs again once the interrupt is handled. Callers therefore keep
+/// their progress in the objects they work on rather than on the stack: revoke in the children
+/// already deleted, Zombie deletion in the Zombie cap left in the slot, untyped reset in the free
+/// index and CancelBadgedSends in the endpoint queue.
 #[no_mangle]
 pub fn preemptionPoint() -> exception_t {
     unsafe {
@@ -251,6 +337,14 @@ pub fn preemptionPoint() -> exception_t {
             if isIRQPending() {
                 return exception_t::EXCEPTION_PREEMTED;
             }
+            // A long operation is charged as it goes and stops once the budget is gone
+            #[cfg(feature = "ENABLE_MCS")]
+            {
+                sel4_task::sched_context::update_timestamp(crate::arch::read_time());
+                if !sel4_task::sched_context::check_budget() {
+                    return exception_t::EXCEPTION_PREEMTED;
+                }
+            }
         }
         exception_t::EXCEPTION_NONE
     }
@@ -1,12 +1,12 @@
-use crate::arch::resetTimer;
-use crate::config::{irqInvalid, maxIRQ};
+use crate::config::{irqInvalid, INT_STATE_ARRAY_SIZE};
 use crate::interrupt::*;
 use core::intrinsics::unlikely;
 use log::debug;
 use sel4_common::structures::exception_t;
+use sel4_common::utils::convert_to_checked_mut_type_ref;
 use sel4_cspace::interface::CapTag;
 use sel4_ipc::notification_t;
-use sel4_task::{activateThread, schedule, timerTick};
+use sel4_task::{activateThread, schedule};
 
 #[no_mangle]
 pub fn handleInterruptEntry() -> exception_t {
@@ -18,6 +18,8 @@ pub fn handleInterruptEntry() -> exception_t {
         debug!("Spurious interrupt!");
         debug!("Superior IRQ!! SIP {:#x}\n", read_sip());
     }
+    #[cfg(feature = "ENABLE_IRQ_LATENCY")]
+    crate::interrupt::latency::irq_latency_exit();
 
     schedule();
     activateThread();
@@ -26,10 +28,13 @@ pub fn handleInterruptEntry() -> exception_t {
 
 #[no_mangle]
 pub fn handleInterrupt(irq: usize) {
-    if unlikely(irq > maxIRQ) {
+    #[cfg(feature = "ENABLE_KERNEL_INFO_PAGE")]
+    sel4_common::kernel_info::kernel_info_count_irq();
+    if unlikely(irq >= INT_STATE_ARRAY_SIZE) {
         debug!(
             "Received IRQ {}, which is above the platforms maxIRQ of {}\n",
-            irq, maxIRQ
+            irq,
+            INT_STATE_ARRAY_SIZE - 1
         );
         mask_interrupt(true, irq);
         ackInterrupt(irq);
@@ -48,19 +53,29 @@ pub fn handleInterrupt(irq: usize) {
             if handler_cap.get_cap_type() == CapTag::CapNotificationCap
                 && handler_cap.get_nf_can_send() != 0
             {
-                let nf = convert_to_mut_type_ref::<notification_t>(handler_cap.get_nf_ptr());
+                let nf =
+                    convert_to_checked_mut_type_ref::<notification_t>(handler_cap.get_nf_ptr());
                 nf.send_signal(handler_cap.get_nf_badge());
+                #[cfg(feature = "ENABLE_IRQ_LATENCY")]
+                crate::interrupt::latency::irq_latency_deliver();
             }
+            // A level triggered device keeps the line up until its driver served it, the IRQ
+            // stays masked until the driver acknowledges it with IRQAckIRQ
+            mask_interrupt(true, irq);
         }
-        IRQState::IRQTimer => {
-            timerTick();
-            resetTimer();
-        }
+        IRQState::IRQTimer => timer::timer_interrupt(),
         #[cfg(feature = "ENABLE_SMP")]
         IRQState::IRQIPI => {
+            crate::smp::ipi::ipi_handle(irq);
             unsafe { crate::ffi::handleIPI(irq, true) };
         }
         IRQState::IRQReserved => {
+            #[cfg(all(target_arch = "aarch64", feature = "ENABLE_ARM_HYP"))]
+            if irq == crate::config::INTERRUPT_VGIC_MAINTENANCE {
+                crate::arch::vgic_maintenance();
+                ackInterrupt(irq);
+                return;
+            }
             debug!("Received unhandled reserved IRQ: {}\n", irq);
         }
     }
@@ -0,0 +1,104 @@
+//! Interrupt latency self-measurement, enabled by the `ENABLE_IRQ_LATENCY` feature.
+//!
+//! The kernel reads the timer counter first thing in `c_handle_interrupt`, before it waits for the
+//! kernel lock, and again when it signals the notification of the IRQ handler cap, which is when
+//! the driver thread can be woken. The difference, in timer counts, is counted in a histogram of
+//! power of two buckets: bucket `i` counts latencies in `[2^i, 2^(i+1))`, bucket 0 also counts 0
+//! and the last bucket everything longer. IRQs the kernel finds at a preemption point did not go
+//! through the vector and are not counted, nor are the timer and IPIs, which no driver waits for.
+//!
+//! `SysDebugIrqLatency` returns the histogram in the message words of the caller, like
+//! `SysDebugVersion`, and clears it if the cap register is non-zero:
+//! - word 0: number of latencies counted
+//! - word 1: their sum
+//! - word 2: the longest
+//! - words 3 on: the [`IRQ_LATENCY_BUCKETS`] buckets
+//!
+//! The message info holds the number of words returned, all of them need an IPC buffer.
+
+use crate::arch::read_time;
+use sel4_common::arch::ArchReg;
+use sel4_common::message_info::seL4_MessageInfo_t;
+use sel4_common::sel4_config::CONFIG_MAX_NUM_NODES;
+use sel4_common::utils::cpu_id;
+use sel4_task::get_currenct_thread;
+
+/// Number of buckets of the histogram
+pub const IRQ_LATENCY_BUCKETS: usize = 16;
+
+#[derive(Clone, Copy)]
+struct irq_latency_t {
+    count: usize,
+    sum: usize,
+    max: usize,
+    buckets: [usize; IRQ_LATENCY_BUCKETS],
+}
+
+impl irq_latency_t {
+    const fn new() -> Self {
+        Self {
+            count: 0,
+            sum: 0,
+            max: 0,
+            buckets: [0; IRQ_LATENCY_BUCKETS],
+        }
+    }
+
+    fn record(&mut self, latency: usize) {
+        let bucket = (usize::BITS - latency.leading_zeros()).saturating_sub(1) as usize;
+        self.count += 1;
+        self.sum = self.sum.saturating_add(latency);
+        self.max = self.max.max(latency);
+        self.buckets[bucket.min(IRQ_LATENCY_BUCKETS - 1)] += 1;
+    }
+}
+
+/// The histogram of all cores, updated under the kernel lock
+static mut ksIrqLatency: irq_latency_t = irq_latency_t::new();
+
+/// Vector entry time of the interrupt every core handles, 0 for none
+static mut ksIrqEntryTime: [usize; CONFIG_MAX_NUM_NODES] = [0; CONFIG_MAX_NUM_NODES];
+
+/// Stamp the vector entry of an interrupt on the current core.
+#[inline]
+pub fn irq_latency_entry() {
+    // A stamp of 0 would read as none
+    unsafe { ksIrqEntryTime[cpu_id()] = read_time().max(1) };
+}
+
+/// Forget the stamp of the current core once its interrupt is handled.
+#[inline]
+pub fn irq_latency_exit() {
+    unsafe { ksIrqEntryTime[cpu_id()] = 0 };
+}
+
+/// Count the latency of the interrupt of the current core, which is delivered to its driver.
+#[inline]
+pub fn irq_latency_deliver() {
+    let start = unsafe { core::mem::replace(&mut ksIrqEntryTime[cpu_id()], 0) };
+    if start != 0 {
+        let latency = read_time().wrapping_sub(start);
+        unsafe { ksIrqLatency.record(latency) };
+    }
+}
+
+/// Handle `SysDebugIrqLatency` for the current thread.
+pub fn handle_debug_irq_latency() {
+    let thread = get_currenct_thread();
+    let latency = unsafe { ksIrqLatency };
+    let words = [latency.count, latency.sum, latency.max];
+    let mut length = 0;
+    for word in words.into_iter().chain(latency.buckets) {
+        if thread.set_mr(length, word) == length {
+            break;
+        }
+        length += 1;
+    }
+    if thread.tcbArch.get_register(ArchReg::Cap) != 0 {
+        unsafe { ksIrqLatency = irq_latency_t::new() };
+    }
+    thread.tcbArch.set_register(
+        ArchReg::MsgInfo,
+        seL4_MessageInfo_t::new(0, 0, 0, length).to_word(),
+    );
+}
@@ -1,9 +1,10 @@
 pub mod handler;
+#[cfg(feature = "ENABLE_IRQ_LATENCY")]
+pub mod latency;
+pub mod timer;
 
-#[cfg(target_arch="riscv64")]
-use crate::BIT;
 #[cfg(target_arch = "riscv64")]
-use core::arch::asm;
+use sel4_common::arch::sysreg::{sie, sip};
 use sel4_common::sel4_config::CONFIG_MAX_NUM_NODES;
 #[cfg(target_arch = "aarch64")]
 use sel4_common::utils::global_ops;
@@ -17,10 +18,36 @@ use crate::{arch::read_sip, config::*};
 use crate::ffi::{ipi_clear_irq, ipi_get_irq};
 
 #[no_mangle]
-pub static mut intStateIRQTable: [usize; maxIRQ + 1] = [0; maxIRQ + 1];
+pub static mut intStateIRQTable: [usize; INT_STATE_ARRAY_SIZE] = [0; INT_STATE_ARRAY_SIZE];
 
 pub static mut intStateIRQNode: pptr_t = 0;
 
+/// The core each IRQ is routed to, see [`set_irq_target_core`]
+static mut intStateIRQCore: [usize; INT_STATE_ARRAY_SIZE] = [0; INT_STATE_ARRAY_SIZE];
+
+/// The handler slots of the LPIs, which have none in `intStateIRQNode`
+#[cfg(feature = "ENABLE_GIC_ITS")]
+static mut lpiHandlerSlots: [cte_t; CONFIG_MAX_NUM_LPIS] = [EMPTY_SLOT; CONFIG_MAX_NUM_LPIS];
+
+/// The handler slots of the IMSIC identities, which have none in `intStateIRQNode`
+#[cfg(feature = "ENABLE_RISCV_AIA")]
+static mut aiaHandlerSlots: [cte_t; CONFIG_IMSIC_NUM_IDS] = [EMPTY_SLOT; CONFIG_IMSIC_NUM_IDS];
+
+/// The handler slots of the PLIC sources, which have none in `intStateIRQNode`
+#[cfg(feature = "ENABLE_RISCV_PLIC")]
+static mut plicHandlerSlots: [cte_t; CONFIG_PLIC_NUM_SOURCES] =
+    [EMPTY_SLOT; CONFIG_PLIC_NUM_SOURCES];
+
+#[cfg(any(
+    feature = "ENABLE_GIC_ITS",
+    feature = "ENABLE_RISCV_AIA",
+    feature = "ENABLE_RISCV_PLIC"
+))]
+const EMPTY_SLOT: cte_t = cte_t {
+    cap: sel4_cspace::interface::cap_t { words: [0; 2] },
+    cteMDBNode: sel4_cspace::interface::mdb_node_t { words: [0; 2] },
+};
+
 #[no_mangle]
 // #[link_section = ".boot.bss"]
 pub static mut active_irq: [usize; CONFIG_MAX_NUM_NODES] = [0; CONFIG_MAX_NUM_NODES];
@@ -52,6 +79,18 @@ pub fn get_irq_state(irq: usize) -> IRQState {
 
 #[inline]
 pub fn get_irq_handler_slot(irq: usize) -> &'static mut cte_t {
+    #[cfg(feature = "ENABLE_GIC_ITS")]
+    if irq >= LPI_IRQ_BASE {
+        return unsafe { &mut (*core::ptr::addr_of_mut!(lpiHandlerSlots))[irq - LPI_IRQ_BASE] };
+    }
+    #[cfg(feature = "ENABLE_RISCV_AIA")]
+    if irq >= AIA_IRQ_BASE {
+        return unsafe { &mut (*core::ptr::addr_of_mut!(aiaHandlerSlots))[irq - AIA_IRQ_BASE] };
+    }
+    #[cfg(feature = "ENABLE_RISCV_PLIC")]
+    if irq >= PLIC_IRQ_BASE {
+        return unsafe { &mut (*core::ptr::addr_of_mut!(plicHandlerSlots))[irq - PLIC_IRQ_BASE] };
+    }
     unsafe { convert_to_mut_type_ref::<cte_t>(intStateIRQNode).get_offset_slot(irq) }
 }
 
@@ -82,53 +121,61 @@ pub extern "C" fn intStateIRQNodeToR(ptr: *mut usize) {
     }
 }
 
+/// The last handler cap of `irq` is gone, after `deletingIRQHandler` dropped its notification:
+/// mask it and hand it back to IRQControl.
 #[no_mangle]
 pub fn deletedIRQHandler(irq: usize) {
     setIRQState(IRQState::IRQInactive, irq);
-}
-#[inline]
-#[cfg(target_arch = "riscv64")]
-pub fn set_sie_mask(_mask_high: usize) {
-    unsafe {
-        let _temp: usize;
-        asm!("csrrs {0},sie,{1}",out(reg)_temp,in(reg)_mask_high);
+    #[cfg(feature = "ENABLE_GIC_ITS")]
+    if irq >= LPI_IRQ_BASE {
+        crate::arch::arm_gic::gic_v3::its::its_unmap_lpi(irq);
     }
 }
-#[inline]
-#[cfg(target_arch = "riscv64")]
-pub fn clear_sie_mask(_mask_low: usize) {
-    unsafe {
-        let _temp: usize;
-        asm!("csrrc {0},sie,{1}",out(reg)_temp,in(reg)_mask_low);
-    }
-}
-
+/// Mask or unmask `irq` at the interrupt controller. On riscv64 only the timer, the IMSIC
+/// identities of `ENABLE_RISCV_AIA` and the PLIC sources of `ENABLE_RISCV_PLIC` can be, external
+/// interrupts stay enabled in `sie`.
 #[inline]
 pub fn mask_interrupt(disable: bool, irq: usize) {
     #[cfg(target_arch = "riscv64")]
     if irq == KERNEL_TIMER_IRQ {
+        unsafe {
+            if disable {
+                sie::clear_bits(sie::STIE.mask());
+            } else {
+                sie::set_bits(sie::STIE.mask());
+            }
+        }
+    }
+    #[cfg(feature = "ENABLE_RISCV_AIA")]
+    if crate::arch::aia::is_aia_irq(irq) {
         if disable {
-            clear_sie_mask(BIT!(SIE_STIE));
+            crate::arch::aia::irq_disable(irq);
         } else {
-            set_sie_mask(BIT!(SIE_STIE));
+            crate::arch::aia::irq_enable(irq);
+        }
+    }
+    #[cfg(feature = "ENABLE_RISCV_PLIC")]
+    if crate::arch::plic::is_plic_irq(irq) {
+        if disable {
+            crate::arch::plic::irq_disable(irq);
+        } else {
+            crate::arch::plic::irq_enable(irq);
         }
     }
     #[cfg(target_arch = "aarch64")]
-    {
-        if irq == KERNEL_TIMER_IRQ {
-            if disable {
-                log::info!("disable interrupt: {}", irq);
-            } else {
-                crate::arch::arm_gic::gic_v2::irq_enable(irq);
-            }
+    if irq < INT_STATE_ARRAY_SIZE {
+        if disable {
+            crate::arch::arm_gic::gic::irq_disable(irq);
+        } else {
+            crate::arch::arm_gic::gic::irq_enable(irq);
         }
     }
 }
 
 #[cfg(target_arch = "riscv64")]
 pub fn isIRQPending() -> bool {
-    let sip = read_sip();
-    if (sip & (BIT!(SIP_STIP) | BIT!(SIP_SEIP))) != 0 {
+    let pending = read_sip();
+    if (pending & (sip::STIP.mask() | sip::SEIP.mask())) != 0 {
         true
     } else {
         false
@@ -145,10 +192,15 @@ pub fn ackInterrupt(irq: usize) {
     unsafe {
         active_irq[cpu_id()] = irqInvalid;
     }
+    #[cfg(all(target_arch = "aarch64", feature = "ENABLE_ARM_HYP"))]
+    if irq == crate::config::INTERRUPT_VGIC_MAINTENANCE {
+        crate::arch::arm_gic::gic::ack_irq(irq);
+        return;
+    }
     if irq == KERNEL_TIMER_IRQ {
         #[cfg(target_arch = "aarch64")]
         {
-            crate::arch::arm_gic::gic_v2::gic_v2::ack_irq(irq);
+            crate::arch::arm_gic::gic::ack_irq(irq);
             global_ops!(active_irq[cpu_id()] = 0);
         }
         return;
@@ -161,11 +213,22 @@ pub fn ackInterrupt(irq: usize) {
             }
         }
     }
+    // Until the end of interrupt the GIC holds back every interrupt of the same priority
+    #[cfg(target_arch = "aarch64")]
+    if irq < INT_STATE_ARRAY_SIZE {
+        crate::arch::arm_gic::gic::ack_irq(irq);
+    }
+    // Until the claim is completed the PLIC holds the source back
+    #[cfg(feature = "ENABLE_RISCV_PLIC")]
+    if crate::arch::plic::is_plic_irq(irq) {
+        crate::arch::plic::plic_complete(irq);
+    }
 }
 
+/// Whether `irq` is in use, by a handler cap or by the kernel, so IRQControl may not issue it.
 #[inline]
 pub fn is_irq_active(irq: usize) -> bool {
-    get_irq_state(irq) == IRQState::IRQInactive
+    get_irq_state(irq) != IRQState::IRQInactive
 }
 
 #[no_mangle]
@@ -173,6 +236,92 @@ pub fn isIRQActive(_irq: usize) -> bool {
     panic!("should not be invoked!")
 }
 
+/// Whether `irq` is wired through the interrupt controller and can be routed to another core.
+/// Only GIC SPIs, the wired APLIC sources of `ENABLE_RISCV_AIA` and the PLIC sources of
+/// `ENABLE_RISCV_PLIC` can; PPIs and SGIs are banked per core, and MSIs go to the interrupt file
+/// the device writes to.
+#[inline]
+pub fn is_irq_routable(irq: usize) -> bool {
+    #[cfg(target_arch = "aarch64")]
+    {
+        irq >= crate::arch::arm_gic::gic::SPI_START && irq <= maxIRQ
+    }
+    #[cfg(feature = "ENABLE_RISCV_AIA")]
+    {
+        crate::arch::aia::is_wired_irq(irq)
+    }
+    #[cfg(feature = "ENABLE_RISCV_PLIC")]
+    {
+        crate::arch::plic::is_plic_irq(irq)
+    }
+    #[cfg(all(
+        target_arch = "riscv64",
+        not(any(feature = "ENABLE_RISCV_AIA", feature = "ENABLE_RISCV_PLIC"))
+    ))]
+    {
+        let _ = irq;
+        false
+    }
+}
+
+/// The core `irq` is routed to, the boot core unless it was changed
+#[inline]
+pub fn get_irq_target_core(irq: usize) -> usize {
+    unsafe { intStateIRQCore[irq] }
+}
+
+/// Route `irq` to `core`, which must be routable, see [`is_irq_routable`].
+#[inline]
+pub fn set_irq_target_core(irq: usize, core: usize) {
+    #[cfg(any(feature = "ENABLE_RISCV_AIA", feature = "ENABLE_RISCV_PLIC"))]
+    let from = get_irq_target_core(irq);
+    unsafe {
+        intStateIRQCore[irq] = core;
+    }
+    #[cfg(target_arch = "aarch64")]
+    crate::arch::arm_gic::gic::irq_set_target_core(irq, core);
+    #[cfg(feature = "ENABLE_RISCV_AIA")]
+    crate::arch::aia::irq_set_target_core(irq, from, core);
+    #[cfg(feature = "ENABLE_RISCV_PLIC")]
+    crate::arch::plic::irq_set_target_core(irq, from, core);
+}
+
+/// Make `irq` edge triggered, or level triggered for false. On riscv64 only APLIC sources of
+/// `ENABLE_RISCV_AIA` have a trigger, for the others, PLIC sources included, it is left as it is.
+#[inline]
+pub fn set_irq_trigger(irq: usize, edge: bool) {
+    #[cfg(target_arch = "aarch64")]
+    crate::arch::arm_gic::gic::irq_set_trigger(irq, edge);
+    #[cfg(feature = "ENABLE_RISCV_AIA")]
+    if crate::arch::aia::is_aia_irq(irq) {
+        crate::arch::aia::irq_set_trigger(irq, edge);
+    }
+    #[cfg(all(target_arch = "riscv64", not(feature = "ENABLE_RISCV_AIA")))]
+    let _ = (irq, edge);
+}
+
+/// The external interrupt the current hart takes, claimed from the IMSIC with `ENABLE_RISCV_AIA`
+/// or from the PLIC with `ENABLE_RISCV_PLIC`. Without either it is `irqInvalid`.
+#[cfg(target_arch = "riscv64")]
+#[inline]
+fn claim_external_irq() -> usize {
+    #[cfg(feature = "ENABLE_RISCV_AIA")]
+    return crate::arch::aia::aia_claim();
+    #[cfg(feature = "ENABLE_RISCV_PLIC")]
+    return crate::arch::plic::plic_claim();
+    #[cfg(not(any(feature = "ENABLE_RISCV_AIA", feature = "ENABLE_RISCV_PLIC")))]
+    irqInvalid
+}
+
+/// Map the MSI `event` of the ITS device `device` to the LPI of `irq`, see `ENABLE_GIC_ITS`.
+#[inline]
+pub fn set_irq_msi(irq: usize, device: usize, event: usize) {
+    #[cfg(feature = "ENABLE_GIC_ITS")]
+    crate::arch::arm_gic::gic_v3::its::its_map_lpi(irq, device, event);
+    #[cfg(not(feature = "ENABLE_GIC_ITS"))]
+    let _ = (irq, device, event);
+}
+
 #[cfg(target_arch = "riscv64")]
 #[inline]
 #[no_mangle]
@@ -181,26 +330,31 @@ pub fn getActiveIRQ() -> usize {
     if IS_IRQ_VALID(irq) {
         return irq;
     }
-    let sip = read_sip();
+    #[cfg(all(feature = "ENABLE_SMP", feature = "ENABLE_RISCV_AIA"))]
+    crate::arch::aia::imsic::imsic_sync();
+    let pending = read_sip();
     #[cfg(feature = "ENABLE_SMP")]
     {
-        use sel4_common::sbi::clear_ipi;
-        if (sip & BIT!(SIP_SEIP)) != 0 {
-            irq = 0;
-        } else if (sip & BIT!(SIP_SSIP)) != 0 {
+        use sel4_common::arch::clear_ipi;
+        if (pending & sip::SEIP.mask()) != 0 {
+            irq = claim_external_irq();
+        } else if (pending & sip::SSIP.mask()) != 0 {
             clear_ipi();
             irq = unsafe { ipi_get_irq() };
+            if irq == irqInvalid {
+                irq = crate::smp::ipi::ipi_pending_irq();
+            }
             // debug!("irq: {}", irq);
-        } else if (sip & BIT!(SIP_STIP)) != 0 {
+        } else if (pending & sip::STIP.mask()) != 0 {
             irq = KERNEL_TIMER_IRQ;
         } else {
             irq = irqInvalid;
         }
     }
     #[cfg(not(feature = "ENABLE_SMP"))]
-    if (sip & BIT!(SIP_SEIP)) != 0 {
-        irq = 0;
-    } else if (sip & BIT!(SIP_STIP)) != 0 {
+    if (pending & sip::SEIP.mask()) != 0 {
+        irq = claim_external_irq();
+    } else if (pending & sip::STIP.mask()) != 0 {
         irq = KERNEL_TIMER_IRQ;
     } else {
         irq = irqInvalid;
@@ -227,7 +381,7 @@ pub fn getActiveIRQ() -> usize {
             irq = irqInvalid;
         }
     */
-    use crate::arch::arm_gic::gic_v2::{consts::IRQ_MASK, gic_v2::gic_int_ack};
+    use crate::arch::arm_gic::gic::{gic_int_ack, IRQ_MASK};
 
     if !IS_IRQ_VALID(global_ops!(active_irq[cpu_id()])) {
         global_ops!(active_irq[cpu_id()] = gic_int_ack());
@@ -241,5 +395,5 @@ pub fn getActiveIRQ() -> usize {
 }
 
 pub const fn IS_IRQ_VALID(x: usize) -> bool {
-    (x <= maxIRQ) && (x != irqInvalid)
+    (x < INT_STATE_ARRAY_SIZE) && (x != irqInvalid)
 }
@@ -0,0 +1,82 @@
+//! The timer interrupt of the kernel, programmed through `sel4_common::timer::KernelTimer`.
+//!
+//! By default every core takes a tick every `RESET_CYCLES`, which runs the round robin of
+//! `timerTick` and, with `ENABLE_MCS`, enforces budgets and releases refills. With
+//! `ENABLE_TICKLESS` the kernel instead programs the timer on every return to user space for the
+//! next time it has work, see [`timer_program_next`]: the budget expiry of the current thread,
+//! the next release of the release queue and, only while the current thread runs without a
+//! scheduling context, the next tick of its time slice. A core which idles or runs threads with
+//! scheduling contexts is not woken up by ticks. The tick count of the kernel info page then
+//! counts the ticks which are taken.
+
+use crate::config::RESET_CYCLES;
+use sel4_common::arch::timer::kernel_timer_t;
+use sel4_common::timer::KernelTimer;
+use sel4_task::timerTick;
+
+#[cfg(feature = "ENABLE_TICKLESS")]
+use sel4_common::{sel4_config::CONFIG_MAX_NUM_NODES, utils::cpu_id};
+#[cfg(feature = "ENABLE_TICKLESS")]
+use sel4_task::{get_currenct_thread, get_idle_thread, sched_context};
+
+/// Time of the next tick of every core, 0 while the core does not tick, see `ENABLE_TICKLESS`
+#[cfg(feature = "ENABLE_TICKLESS")]
+static mut ksNextTick: [usize; CONFIG_MAX_NUM_NODES] = [0; CONFIG_MAX_NUM_NODES];
+
+/// Program the next periodic tick of the current core.
+#[no_mangle]
+pub fn resetTimer() {
+    let mut target = kernel_timer_t::now() + RESET_CYCLES;
+    kernel_timer_t::set_deadline(target);
+    // The deadline may have passed before it was set, e.g. in a slow simulator
+    while kernel_timer_t::now() > target {
+        target = kernel_timer_t::now() + RESET_CYCLES;
+        kernel_timer_t::set_deadline(target);
+    }
+}
+
+/// Handle the interrupt of the kernel timer, `IRQTimer`.
+pub fn timer_interrupt() {
+    #[cfg(feature = "ENABLE_KERNEL_INFO_PAGE")]
+    sel4_task::idle_time::idle_time_tick();
+    #[cfg(not(feature = "ENABLE_TICKLESS"))]
+    {
+        #[cfg(feature = "ENABLE_KERNEL_INFO_PAGE")]
+        sel4_common::kernel_info::kernel_info_count_tick();
+        timerTick();
+        resetTimer();
+    }
+    // The next deadline is programmed on the way out
+    #[cfg(feature = "ENABLE_TICKLESS")]
+    {
+        let now = sched_context::current_time();
+        let next_tick = unsafe { &mut ksNextTick[cpu_id()] };
+        if *next_tick != 0 && now >= *next_tick {
+            *next_tick = now + RESET_CYCLES;
+            #[cfg(feature = "ENABLE_KERNEL_INFO_PAGE")]
+            sel4_common::kernel_info::kernel_info_count_tick();
+            timerTick();
+        } else {
+            sched_context::sched_context_tick(get_currenct_thread());
+        }
+    }
+}
+
+/// Program the timer of the current core for the next time the kernel has work, called on every
+/// return to user space after `commit_time`.
+#[cfg(feature = "ENABLE_TICKLESS")]
+pub fn timer_program_next() {
+    let mut deadline = sched_context::next_event();
+    let current = get_currenct_thread();
+    let next_tick = unsafe { &mut ksNextTick[cpu_id()] };
+    if current.tcbSchedContext == 0 && current.get_ptr() != get_idle_thread().get_ptr() {
+        // The time slice of the thread is counted in ticks
+        if *next_tick == 0 {
+            *next_tick = sched_context::current_time() + RESET_CYCLES;
+        }
+        deadline = deadline.min(*next_tick);
+    } else {
+        *next_tick = 0;
+    }
+    kernel_timer_t::set_deadline(deadline);
+}
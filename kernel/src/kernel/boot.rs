@@ -37,8 +37,15 @@ pub static mut current_extra_caps: extra_caps_t = extra_caps_t {
     excaprefs: [0; seL4_MsgMaxExtraCaps],
 };
 
+/// The slot of the extra cap `index` of the current invocation, none if fewer were sent.
+///
+/// The list ends at the first empty entry, the entries behind it are left from earlier syscalls.
 #[inline]
 pub fn get_extra_cap_by_index(index: usize) -> Option<&'static mut cte_t> {
     assert!(index < seL4_MsgMaxExtraCaps);
-    unsafe { convert_to_option_mut_type_ref::<cte_t>(current_extra_caps.excaprefs[index] as usize) }
+    let excaprefs = unsafe { current_extra_caps.excaprefs };
+    if excaprefs[..index].contains(&0) {
+        return None;
+    }
+    convert_to_option_mut_type_ref::<cte_t>(excaprefs[index] as usize)
 }
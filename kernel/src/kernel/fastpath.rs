@@ -5,12 +5,14 @@ use crate::{
     syscall::{slowpath, SysCall, SysReplyRecv},
 };
 use core::intrinsics::{likely, unlikely};
-use sel4_common::arch::msgRegister;
+use sel4_common::arch::{msgRegister, msgRegisterNum};
 use sel4_common::{
     fault::*,
     message_info::*,
     sel4_config::*,
-    utils::{convert_to_mut_type_ref, convert_to_option_mut_type_ref},
+    utils::{
+        convert_to_checked_mut_type_ref, convert_to_mut_type_ref, convert_to_option_mut_type_ref,
+    },
 };
 use sel4_cspace::interface::*;
 use sel4_ipc::*;
@@ -82,9 +84,21 @@ pub fn switchToThread_fp(thread: *mut tcb_t, vroot: *mut PTE, stored_hw_asid: PT
         setVSpaceRoot(pptr_to_paddr(vroot as usize), asid);
         #[cfg(target_arch = "aarch64")]
         setCurrentUserVSpaceRoot(ttbr_new(asid, pptr_to_paddr(vroot as usize)));
+        #[cfg(all(target_arch = "aarch64", feature = "ENABLE_ARM_MPAM"))]
+        (*thread).tcbArch.load_mpam();
         // panic!("switchToThread_fp");
         // ksCurThread = thread as usize;
+        #[cfg(feature = "ENABLE_KERNEL_INFO_PAGE")]
+        sel4_common::kernel_info::kernel_info_count_context_switch();
+        // The fastpath does not read the time on entry, the caller is charged up to here
+        #[cfg(feature = "ENABLE_MCS")]
+        {
+            sel4_task::sched_context::update_timestamp(crate::arch::read_time());
+            sel4_task::sched_context::commit_time();
+        }
         set_current_thread(&*thread);
+        #[cfg(feature = "ENABLE_TICKLESS")]
+        crate::interrupt::timer::timer_program_next();
     }
 }
 
@@ -109,7 +123,7 @@ pub fn isValidVTableRoot_fp(cap: &cap_t) -> bool {
 #[inline]
 #[no_mangle]
 pub fn fastpath_mi_check(msgInfo: usize) -> bool {
-    (msgInfo & MASK!(seL4_MsgLengthBits + seL4_MsgExtraCapBits)) > 4
+    (msgInfo & MASK!(seL4_MsgLengthBits + seL4_MsgExtraCapBits)) > msgRegisterNum
 }
 
 #[inline]
@@ -135,6 +149,11 @@ pub fn fastpath_copy_mrs(length: usize, src: &mut tcb_t, dest: &mut tcb_t) {
 #[inline]
 #[no_mangle]
 pub fn fastpath_call(cptr: usize, msgInfo: usize) {
+    // Reply objects and scheduling context donation are handled by the slowpath only
+    #[cfg(feature = "ENABLE_MCS")]
+    slowpath(SysCall as usize);
+    #[cfg(feature = "ENABLE_BENCHMARK")]
+    let start = crate::benchmark::timestamp();
     let current = get_currenct_thread();
     let mut info = seL4_MessageInfo_t::from_word(msgInfo);
     let length = info.get_length();
@@ -142,19 +161,24 @@ pub fn fastpath_call(cptr: usize, msgInfo: usize) {
     if fastpath_mi_check(msgInfo) || current.tcbFault.get_fault_type() != FaultType::NullFault {
         slowpath(SysCall as usize);
     }
+    #[cfg(feature = "ENABLE_SYSCALL_TRACE")]
+    if unlikely(current.tcbSyscallTrace) {
+        slowpath(SysCall as usize);
+    }
     let ep_cap = lookup_fp(&current.get_cspace(tcbCTable).cap, cptr);
     if unlikely(
         !(ep_cap.get_cap_type() == CapTag::CapEndpointCap) || (ep_cap.get_ep_can_send() == 0),
     ) {
         slowpath(SysCall as usize);
     }
-    let ep = convert_to_mut_type_ref::<endpoint_t>(ep_cap.get_ep_ptr());
+    let ep = convert_to_checked_mut_type_ref::<endpoint_t>(ep_cap.get_ep_ptr());
 
     if unlikely(ep.get_state() != EPState::Recv) {
         slowpath(SysCall as usize);
     }
 
     let dest = convert_to_mut_type_ref::<tcb_t>(ep.get_queue_head());
+    dest.prefetch_ipc_buffer();
     let new_vtable = dest.get_cspace(tcbVTable).cap;
 
     if unlikely(!isValidVTableRoot_fp(&new_vtable)) {
@@ -182,6 +206,8 @@ pub fn fastpath_call(cptr: usize, msgInfo: usize) {
         ep.set_queue_tail(0);
         ep.set_state(EPState::Idle as usize);
     }
+    #[cfg(feature = "ENABLE_BENCHMARK")]
+    sel4_ipc::ep_stats::ep_stats_dequeued(ep.get_ptr());
 
     current.tcbState.words[0] = ThreadState::ThreadStateBlockedOnReply as usize;
 
@@ -198,13 +224,37 @@ pub fn fastpath_call(cptr: usize, msgInfo: usize) {
         1,
     );
     fastpath_copy_mrs(length, current, dest);
+    #[cfg(feature = "ENABLE_IPC_TRACE")]
+    sel4_ipc::ipc_trace::ipc_trace_record(
+        ep.get_ptr(),
+        current.get_ptr(),
+        dest.get_ptr(),
+        ep_cap.get_ep_badge(),
+        &info,
+    );
+    #[cfg(feature = "ENABLE_KERNEL_INFO_PAGE")]
+    sel4_common::kernel_info::kernel_info_count_ipc();
+    #[cfg(feature = "ENABLE_BENCHMARK")]
+    sel4_ipc::ep_stats::ep_stats_count_send(ep.get_ptr(), true);
     dest.tcbState.words[0] = ThreadState::ThreadStateRunning as usize;
+    #[cfg(feature = "ENABLE_CALL_DONATION")]
+    sel4_task::donate_timeslice(current, dest);
+    #[cfg(feature = "ENABLE_SCHED_TRACE")]
+    {
+        use sel4_task::sched_trace::*;
+        sched_trace_cause(sched_trace_cause_t::Wake);
+        sched_trace_record(current.get_ptr(), dest.get_ptr());
+    }
     let cap_pd = new_vtable.get_pt_base_ptr() as *mut PTE;
     let stored_hw_asid: PTE = PTE(new_vtable.get_pt_mapped_asid());
     switchToThread_fp(dest as *mut tcb_t, cap_pd, stored_hw_asid);
     info.set_caps_unwrapped(0);
     let msgInfo1 = info.to_word();
     let badge = ep_cap.get_ep_badge();
+    #[cfg(feature = "ENABLE_BENCHMARK")]
+    unsafe {
+        crate::benchmark::ksBenchmarkFastpathCall.record(start)
+    };
     unsafe {
         fastpath_restore(badge, msgInfo1, get_currenct_thread());
     }
@@ -213,6 +263,8 @@ pub fn fastpath_call(cptr: usize, msgInfo: usize) {
 #[inline]
 #[no_mangle]
 pub fn fastpath_reply_recv(cptr: usize, msgInfo: usize) {
+    #[cfg(feature = "ENABLE_MCS")]
+    slowpath(SysReplyRecv as usize);
     // debug!("enter fastpath_reply_recv");
     let current = get_currenct_thread();
     let mut info = seL4_MessageInfo_t::from_word(msgInfo);
@@ -222,6 +274,10 @@ pub fn fastpath_reply_recv(cptr: usize, msgInfo: usize) {
     if fastpath_mi_check(msgInfo) || fault_type != FaultType::NullFault {
         slowpath(SysReplyRecv as usize);
     }
+    #[cfg(feature = "ENABLE_SYSCALL_TRACE")]
+    if unlikely(current.tcbSyscallTrace) {
+        slowpath(SysReplyRecv as usize);
+    }
 
     let ep_cap = lookup_fp(&current.get_cspace(tcbCTable).cap, cptr);
 
@@ -237,7 +293,7 @@ pub fn fastpath_reply_recv(cptr: usize, msgInfo: usize) {
         }
     }
 
-    let ep = convert_to_mut_type_ref::<endpoint_t>(ep_cap.get_ep_ptr());
+    let ep = convert_to_checked_mut_type_ref::<endpoint_t>(ep_cap.get_ep_ptr());
     if unlikely(ep.get_state() == EPState::Send) {
         slowpath(SysReplyRecv as usize);
     }
@@ -287,6 +343,8 @@ pub fn fastpath_reply_recv(cptr: usize, msgInfo: usize) {
         get_currenct_thread().get_ptr(),
         EPState_Recv,
     );
+    #[cfg(feature = "ENABLE_BENCHMARK")]
+    sel4_ipc::ep_stats::ep_stats_queued(ep.get_ptr());
 
     unsafe {
         let node = convert_to_mut_type_ref::<cte_t>(caller_slot.cteMDBNode.get_prev());
@@ -294,8 +352,20 @@ pub fn fastpath_reply_recv(cptr: usize, msgInfo: usize) {
         caller_slot.cap = cap_t::new_null_cap();
         caller_slot.cteMDBNode = mdb_node_t::new(0, 0, 0, 0);
         fastpath_copy_mrs(length, current, caller);
+        #[cfg(feature = "ENABLE_IPC_TRACE")]
+        sel4_ipc::ipc_trace::ipc_trace_record(0, current.get_ptr(), caller.get_ptr(), 0, &info);
+        #[cfg(feature = "ENABLE_KERNEL_INFO_PAGE")]
+        sel4_common::kernel_info::kernel_info_count_ipc();
 
         caller.tcbState.words[0] = ThreadState::ThreadStateRunning as usize;
+        #[cfg(feature = "ENABLE_CALL_DONATION")]
+        sel4_task::return_timeslice(current, caller);
+        #[cfg(feature = "ENABLE_SCHED_TRACE")]
+        {
+            use sel4_task::sched_trace::*;
+            sched_trace_cause(sched_trace_cause_t::Wake);
+            sched_trace_record(current.get_ptr(), caller.get_ptr());
+        }
         let cap_pd = new_vtable.get_pt_base_ptr() as *mut PTE;
         let stored_hw_asid: PTE = PTE(new_vtable.get_pt_mapped_asid());
         switchToThread_fp(caller, cap_pd, stored_hw_asid);
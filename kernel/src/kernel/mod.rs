@@ -1,3 +1,4 @@
 pub mod boot;
 pub mod fastpath;
 pub mod fault;
+pub mod random;
@@ -0,0 +1,124 @@
+//! Kernel-internal pseudo random number source.
+//!
+//! Each core owns a xoshiro256** state. The boot core mixes every entropy source available at
+//! boot (the `RNDR` instruction on aarch64 when implemented, the `rng-seed`/`kaslr-seed`
+//! properties the firmware left in the DTB, and the cycle counter) into a boot seed, from which
+//! every core derives its own state the first time it asks for a number.
+//!
+//! The output is meant for hardening features inside the kernel (stack canaries, hash seeds,
+//! randomised layouts) and is never exposed to user space.
+
+use sel4_common::sel4_config::CONFIG_MAX_NUM_NODES;
+use sel4_common::utils::cpu_id;
+
+use crate::arch::read_time;
+use crate::boot::{fdt_cstr_eq, fdt_for_each_prop};
+
+static mut ksRandomBootSeed: u64 = 0;
+static mut ksRandomState: [[u64; 4]; CONFIG_MAX_NUM_NODES] = [[0; 4]; CONFIG_MAX_NUM_NODES];
+
+/// splitmix64 step, used to spread a single seed word over a whole state.
+#[inline]
+fn splitmix64(x: &mut u64) -> u64 {
+    *x = x.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[inline]
+fn mix(seed: u64, value: u64) -> u64 {
+    let mut x = seed ^ value;
+    splitmix64(&mut x)
+}
+
+/// Read the architectural random number register if the cpu implements one.
+#[cfg(target_arch = "aarch64")]
+fn arch_random() -> Option<u64> {
+    use core::arch::asm;
+    let isar0: u64;
+    unsafe { asm!("mrs {}, id_aa64isar0_el1", out(reg) isar0) };
+    // ID_AA64ISAR0_EL1.RNDR, bits [63:60]
+    if isar0 >> 60 == 0 {
+        return None;
+    }
+    let value: u64;
+    let failed: u64;
+    unsafe {
+        asm!(
+            "mrs {0}, s3_3_c2_c4_0",
+            "cset {1}, eq",
+            out(reg) value,
+            out(reg) failed,
+        )
+    };
+    if failed != 0 {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// The `seed` CSR of Zkr traps on harts without it and cannot be probed safely from S-mode.
+#[cfg(target_arch = "riscv64")]
+fn arch_random() -> Option<u64> {
+    None
+}
+
+/// Fold the `rng-seed` and `kaslr-seed` properties of the flattened device tree into `seed`.
+fn dtb_entropy(mut seed: u64, dtb_phys_addr: usize, dtb_size: usize) -> u64 {
+    fdt_for_each_prop(dtb_phys_addr, dtb_size, |_, name, value, len| {
+        if fdt_cstr_eq(name, b"rng-seed") || fdt_cstr_eq(name, b"kaslr-seed") {
+            for i in 0..len {
+                seed = mix(seed, unsafe { *((value + i) as *const u8) } as u64);
+            }
+        }
+    });
+    seed
+}
+
+/// Collect the boot entropy, called once on the boot core after the DTB has been validated.
+pub fn random_init(dtb_phys_addr: usize, dtb_size: usize) {
+    let mut seed = mix(0, read_time() as u64);
+    if let Some(value) = arch_random() {
+        seed = mix(seed, value);
+    }
+    seed = dtb_entropy(seed, dtb_phys_addr, dtb_size);
+    seed = mix(seed, read_time() as u64);
+    unsafe {
+        ksRandomBootSeed = seed;
+    }
+}
+
+/// Get the next 64 random bits of the current core.
+#[allow(unused)]
+pub fn random_u64() -> u64 {
+    let state = unsafe { &mut ksRandomState[cpu_id()] };
+    if *state == [0; 4] {
+        let mut x = unsafe { mix(ksRandomBootSeed, cpu_id() as u64) };
+        if let Some(value) = arch_random() {
+            x = mix(x, value);
+        }
+        x = mix(x, read_time() as u64);
+        for word in state.iter_mut() {
+            *word = splitmix64(&mut x);
+        }
+    }
+    let result = state[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+    let t = state[1] << 17;
+    state[2] ^= state[0];
+    state[3] ^= state[1];
+    state[1] ^= state[2];
+    state[0] ^= state[3];
+    state[2] ^= t;
+    state[3] = state[3].rotate_left(45);
+    result
+}
+
+/// Get a random word of the current core.
+#[allow(unused)]
+#[inline]
+pub fn random_usize() -> usize {
+    random_u64() as usize
+}
@@ -2,10 +2,13 @@ use log::error;
 
 use core::panic::PanicInfo;
 use sel4_common::arch::shutdown;
+#[cfg(feature = "ENABLE_COMPACT_LOG")]
+use sel4_common::kprintln;
 
 /// Print `[ERROR 0] rel4_kernel: PANICED` if panic is detected
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    #[cfg(not(feature = "ENABLE_COMPACT_LOG"))]
     if let Some(location) = info.location() {
         error!(
             "Panicked at {}:{} {}",
@@ -16,6 +19,25 @@ fn panic(info: &PanicInfo) -> ! {
     } else {
         error!("[kernel] Panicked: {}", info.message().unwrap());
     }
+    // Only messages without arguments are printed without `core::fmt`, see `ENABLE_COMPACT_LOG`
+    #[cfg(feature = "ENABLE_COMPACT_LOG")]
+    {
+        let message = info
+            .message()
+            .and_then(|message| message.as_str())
+            .unwrap_or("<formatted message>");
+        match info.location() {
+            Some(location) => kprintln!(
+                "Panicked at ",
+                location.file(),
+                ":",
+                location.line(),
+                " ",
+                message
+            ),
+            None => kprintln!("[kernel] Panicked: ", message),
+        }
+    }
     error!("rel4_kernel: PANICED");
     shutdown()
 }
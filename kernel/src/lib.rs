@@ -8,6 +8,7 @@
 #![allow(non_snake_case)]
 #![allow(non_camel_case_types)]
 #![allow(non_upper_case_globals)]
+#![deny(unsafe_op_in_unsafe_fn)]
 #![feature(alloc_error_handler)]
 #![feature(asm_const)]
 #![feature(panic_info_message)]
@@ -19,14 +20,19 @@ use sel4_common::arch::shutdown;
 mod config;
 // mod console;
 mod arch;
+#[cfg(feature = "ENABLE_BENCHMARK")]
+mod benchmark;
 mod boot;
 mod interrupt;
 mod kernel;
 mod lang_items;
 mod object;
+#[cfg(feature = "ENABLE_SMP")]
+mod smp;
 mod structures;
 mod syscall;
 mod utils;
+mod version;
 
 mod compatibility;
 mod ffi;
@@ -1,3 +1,7 @@
+pub mod retype;
+#[cfg(feature = "ENABLE_REVOKE_NOTIFY")]
+pub mod revoke_notify;
+
 use crate::structures::lookupCapAndSlot_ret_t;
 use crate::syscall::handle_fault;
 use sel4_common::arch::MessageLabel;
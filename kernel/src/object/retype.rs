@@ -0,0 +1,92 @@
+//! Per-type initialisation of retyped objects, and poisoning of revoked untyped memory.
+//!
+//! Retype only hands out memory that was zeroed by the reset of its untyped, `construct_object`
+//! then puts every object in the state its type starts in, instead of relying on zero being that
+//! state. In debug builds `poison_revoked_untyped` fills the memory of an untyped whose children
+//! were all revoked with `UNTYPED_POISON`, and `construct_object` checks that none of it is left,
+//! so objects that are still used after they were revoked, or retyped without a reset, show up as
+//! the pattern instead of as plausible zeroes.
+
+use sel4_common::arch::ObjectType;
+use sel4_common::utils::convert_to_mut_type_ref;
+use sel4_ipc::{endpoint_t, notification_t};
+use sel4_vspace::pptr_t;
+
+#[cfg(debug_assertions)]
+use crate::syscall::FREE_INDEX_TO_OFFSET;
+#[cfg(debug_assertions)]
+use sel4_common::sel4_config::CONFIG_MAX_NUM_NODES;
+#[cfg(debug_assertions)]
+use sel4_common::BIT;
+#[cfg(debug_assertions)]
+use sel4_cspace::interface::{cap_t, CapTag};
+
+/// The word revoked untyped memory is filled with in debug builds
+#[cfg(debug_assertions)]
+pub const UNTYPED_POISON: usize = 0xdead_5eed_dead_5eed;
+
+/// Initialise the object of `obj_type` at `region_base` of `2^object_size` bytes, which was just
+/// retyped. TCBs are initialised by `create_object`, which also needs their offset in the object.
+pub fn construct_object(obj_type: ObjectType, region_base: pptr_t, object_size: usize) {
+    #[cfg(debug_assertions)]
+    check_unpoisoned(region_base, object_size);
+    #[cfg(not(debug_assertions))]
+    let _ = object_size;
+    match obj_type {
+        ObjectType::EndpointObject => convert_to_mut_type_ref::<endpoint_t>(region_base).init(),
+        ObjectType::NotificationObject => {
+            convert_to_mut_type_ref::<notification_t>(region_base).init()
+        }
+        #[cfg(feature = "ENABLE_MCS")]
+        ObjectType::ReplyObject => convert_to_mut_type_ref::<sel4_ipc::reply_t>(region_base).init(),
+        #[cfg(feature = "ENABLE_ARM_HYP")]
+        ObjectType::seL4_ARM_VCPUObject => {
+            convert_to_mut_type_ref::<crate::arch::vcpu_t>(region_base).init()
+        }
+        #[cfg(feature = "ENABLE_RISCV_HYP")]
+        ObjectType::RISCV_VCPUObject => {
+            convert_to_mut_type_ref::<crate::arch::vcpu_t>(region_base).init()
+        }
+        _ => {}
+    }
+}
+
+/// Panic if the first or the last word of the `2^size_bits` bytes at `ptr` is still poisoned.
+#[cfg(debug_assertions)]
+fn check_unpoisoned(ptr: pptr_t, size_bits: usize) {
+    let last = ptr + BIT!(size_bits) - core::mem::size_of::<usize>();
+    for word in [ptr, last] {
+        assert!(
+            unsafe { *(word as *const usize) } != UNTYPED_POISON,
+            "retyped object at {:#x} overlaps revoked untyped memory",
+            ptr
+        );
+    }
+}
+
+/// Fill the used part of the untyped `cap`, whose children were all revoked, with
+/// `UNTYPED_POISON`. It is zeroed again by the reset of the next retype. Memory that holds the
+/// TCB of a current thread is left alone, as the kernel still reads its state on the way out.
+#[cfg(debug_assertions)]
+pub fn poison_revoked_untyped(cap: &cap_t) {
+    if cap.get_cap_type() != CapTag::CapUntypedCap || cap.get_untyped_is_device() != 0 {
+        return;
+    }
+    let base = cap.get_untyped_ptr();
+    let end = base + FREE_INDEX_TO_OFFSET(cap.get_untyped_free_index());
+    for core in 0..CONFIG_MAX_NUM_NODES {
+        #[cfg(feature = "ENABLE_SMP")]
+        let current = unsafe { sel4_task::ksSMP[core].ksCurThread };
+        #[cfg(not(feature = "ENABLE_SMP"))]
+        let current = {
+            let _ = core;
+            unsafe { sel4_task::ksCurThread }
+        };
+        if (base..end).contains(&current) {
+            return;
+        }
+    }
+    unsafe {
+        core::slice::from_raw_parts_mut(base as *mut usize, (end - base) / 8).fill(UNTYPED_POISON);
+    }
+}
@@ -0,0 +1,41 @@
+//! Notification of completed revocations, enabled by the `ENABLE_REVOKE_NOTIFY` feature.
+//!
+//! A user-level resource manager registers a notification with `DomainSetRevokeNotification`.
+//! Whenever a CNodeRevoke of a cap to a physical object completes, the kernel signals it with its
+//! badge ORed with `BIT!(tag)`, `tag` being the `CapTag` of the revoked cap, so the manager learns
+//! which classes of objects may have been reclaimed. The registered cap is kept in a kernel slot as
+//! a child of the cap it was taken from, so revoking that cap or any of its ancestors clears the
+//! registration.
+
+use sel4_common::{utils::convert_to_checked_mut_type_ref, BIT};
+use sel4_cspace::interface::{cap_t, cte_insert, cte_t, mdb_node_t, CapTag};
+use sel4_ipc::notification_t;
+
+/// The slot holding the registered notification cap, a null cap while none is registered.
+static mut ksRevokeNotificationSlot: cte_t = cte_t {
+    cap: cap_t { words: [0; 2] },
+    cteMDBNode: mdb_node_t { words: [0; 2] },
+};
+
+/// Register the notification cap `cap`, derived from the cap in `src_slot`, replacing the
+/// previous one.
+pub fn set_revoke_notification(cap: &cap_t, src_slot: &mut cte_t) {
+    unsafe {
+        let slot = &mut *core::ptr::addr_of_mut!(ksRevokeNotificationSlot);
+        slot.delete_one();
+        cte_insert(cap, src_slot, slot);
+    }
+}
+
+/// Signal the registered notification once the revocation of `cap` has completed.
+pub fn revoke_notify(cap: &cap_t) {
+    if !cap.get_cap_is_physical() {
+        return;
+    }
+    let ntfn_cap = unsafe { ksRevokeNotificationSlot.cap };
+    if ntfn_cap.get_cap_type() != CapTag::CapNotificationCap || ntfn_cap.get_nf_can_send() == 0 {
+        return;
+    }
+    let nf = convert_to_checked_mut_type_ref::<notification_t>(ntfn_cap.get_nf_ptr());
+    nf.send_signal(ntfn_cap.get_nf_badge() | BIT!(cap.get_cap_type() as usize));
+}
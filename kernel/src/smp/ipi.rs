@@ -0,0 +1,258 @@
+//! Inter-processor interrupts with call/ack semantics.
+//!
+//! A core posts a call for other cores with [`ipi_remote_call`] and raises `INTERRUPT_IPI_0` on
+//! them, a GIC SGI on aarch64 and an SBI IPI on riscv64. The call is taken without the big kernel
+//! lock, so the caller can keep holding the lock and wait until every target acknowledged it. A
+//! target that is spinning for the lock is not waited for, it runs the pending calls as soon as it
+//! got the lock, before it touches any user state.
+//!
+//! Reschedule requests are not waited for. They are raised with `INTERRUPT_IPI_1` and handled with
+//! the lock held, like the other interrupts.
+//!
+//! There is no remote VCPU call: an interrupt injected into a VCPU loaded on another core is
+//! written to the GIC there on the way back to user mode, after a reschedule request. Stalling a
+//! remote TCB is still done by `remoteTCBStall` of the C kernel, as it needs to be serviced inside
+//! the lock spin loop.
+
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use sel4_common::sel4_config::CONFIG_MAX_NUM_NODES;
+use sel4_common::utils::cpu_id;
+use sel4_common::{BIT, MASK};
+use sel4_task::rescheduleRequired;
+
+use crate::boot::ksNumCPUs;
+#[cfg(target_arch = "riscv64")]
+use crate::config::irqInvalid;
+use crate::config::{INTERRUPT_IPI_0, INTERRUPT_IPI_1};
+use crate::ffi::clh_lock_acquire;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+/// A call to another core, the value is its bit in the pending mask
+pub enum ipi_remote_call_t {
+    /// Invalidate all the TLB entries of the core
+    InvalidateTLB = 0,
+    /// Invalidate the TLB entries of the ASID given as the argument
+    InvalidateTLBByASID = 1,
+    /// Choose a new thread, see [`ipi_reschedule_mask`]
+    Reschedule = 2,
+}
+
+/// The calls taken by `INTERRUPT_IPI_0`
+const REMOTE_CALLS: usize = BIT!(ipi_remote_call_t::InvalidateTLB as usize)
+    | BIT!(ipi_remote_call_t::InvalidateTLBByASID as usize);
+
+/// The IPI state of a core
+struct ipi_state_t {
+    /// The calls posted for the core
+    pending: AtomicUsize,
+    /// The argument of `InvalidateTLBByASID`
+    asid: AtomicUsize,
+    /// The number of calls posted for the core
+    requested: AtomicUsize,
+    /// The value of `requested` up to which the core ran the calls
+    done: AtomicUsize,
+    /// Whether the core is spinning for the big kernel lock
+    waiting_for_lock: AtomicBool,
+}
+
+impl ipi_state_t {
+    const INIT: Self = Self {
+        pending: AtomicUsize::new(0),
+        asid: AtomicUsize::new(0),
+        requested: AtomicUsize::new(0),
+        done: AtomicUsize::new(0),
+        waiting_for_lock: AtomicBool::new(false),
+    };
+}
+
+static ksIPIState: [ipi_state_t; CONFIG_MAX_NUM_NODES] = [ipi_state_t::INIT; CONFIG_MAX_NUM_NODES];
+
+/// The booted cores of `mask` other than the current one
+#[inline]
+fn remote_mask(mask: usize) -> usize {
+    mask & MASK!(*ksNumCPUs.lock()) & !BIT!(cpu_id())
+}
+
+/// Raise `irq` on the cores in `mask`.
+fn ipi_send_mask(irq: usize, mask: usize) {
+    #[cfg(target_arch = "aarch64")]
+    crate::arch::arm_gic::gic::send_sgi(irq, mask);
+    #[cfg(target_arch = "riscv64")]
+    {
+        let _ = irq;
+        let mut hart_mask = 0;
+        for core in 0..CONFIG_MAX_NUM_NODES {
+            if mask & BIT!(core) != 0 {
+                hart_mask |= BIT!(sel4_common::smp::cpu_index_to_id(core));
+            }
+        }
+        sel4_common::arch::send_ipi(hart_mask);
+    }
+}
+
+/// Post `call` with `arg` for the other cores in `mask` and wait until each of them ran it or is
+/// spinning for the big kernel lock. Called with the lock held.
+pub fn ipi_remote_call(call: ipi_remote_call_t, arg: usize, mask: usize) {
+    let mask = remote_mask(mask);
+    if mask == 0 {
+        return;
+    }
+    let mut seqs = [0; CONFIG_MAX_NUM_NODES];
+    for core in (0..CONFIG_MAX_NUM_NODES).filter(|core| mask & BIT!(*core) != 0) {
+        let state = &ksIPIState[core];
+        let mut bit = BIT!(call as usize);
+        if call == ipi_remote_call_t::InvalidateTLBByASID {
+            let by_asid = BIT!(ipi_remote_call_t::InvalidateTLBByASID as usize);
+            // Two different ASIDs do not fit in one slot, fall back to the whole TLB
+            if state.pending.load(Ordering::Acquire) & by_asid != 0
+                && state.asid.load(Ordering::Relaxed) != arg
+            {
+                bit = BIT!(ipi_remote_call_t::InvalidateTLB as usize);
+            } else {
+                state.asid.store(arg, Ordering::Relaxed);
+            }
+        }
+        state.pending.fetch_or(bit, Ordering::Release);
+        seqs[core] = state.requested.fetch_add(1, Ordering::AcqRel) + 1;
+    }
+    ipi_send_mask(INTERRUPT_IPI_0, mask);
+    for core in (0..CONFIG_MAX_NUM_NODES).filter(|core| mask & BIT!(*core) != 0) {
+        let state = &ksIPIState[core];
+        while state.done.load(Ordering::Acquire) < seqs[core]
+            && !state.waiting_for_lock.load(Ordering::Acquire)
+        {
+            spin_loop();
+        }
+    }
+}
+
+/// Invalidate the TLB entries of `asid` on the other cores in `mask`, called by the vspace code
+/// after it invalidated them locally.
+#[no_mangle]
+pub fn ipi_remote_invalidate_tlb_by_asid(asid: usize, mask: usize) {
+    ipi_remote_call(ipi_remote_call_t::InvalidateTLBByASID, asid, mask);
+}
+
+/// Invalidate the whole TLB of the other cores in `mask`.
+#[no_mangle]
+pub fn ipi_remote_invalidate_tlb(mask: usize) {
+    ipi_remote_call(ipi_remote_call_t::InvalidateTLB, 0, mask);
+}
+
+/// Make the cores in `mask` choose a new thread, called at the end of `schedule`.
+#[no_mangle]
+pub fn ipi_reschedule_mask(mask: usize) {
+    let mask = remote_mask(mask);
+    if mask == 0 {
+        return;
+    }
+    for core in (0..CONFIG_MAX_NUM_NODES).filter(|core| mask & BIT!(*core) != 0) {
+        ksIPIState[core].pending.fetch_or(
+            BIT!(ipi_remote_call_t::Reschedule as usize),
+            Ordering::Release,
+        );
+    }
+    ipi_send_mask(INTERRUPT_IPI_1, mask);
+}
+
+/// Run the calls posted for the current core and acknowledge them.
+fn ipi_handle_remote_call() {
+    let state = &ksIPIState[cpu_id()];
+    let seq = state.requested.load(Ordering::Acquire);
+    let pending = state.pending.fetch_and(!REMOTE_CALLS, Ordering::AcqRel);
+    let asid = state.asid.load(Ordering::Relaxed);
+    if pending & BIT!(ipi_remote_call_t::InvalidateTLB as usize) != 0 {
+        invalidate_local_tlb();
+    } else if pending & BIT!(ipi_remote_call_t::InvalidateTLBByASID as usize) != 0 {
+        invalidate_local_tlb_asid(asid);
+    }
+    state.done.store(seq, Ordering::Release);
+}
+
+#[cfg(target_arch = "aarch64")]
+#[inline]
+fn invalidate_local_tlb() {
+    sel4_vspace::invalidate_local_tlb();
+}
+
+#[cfg(target_arch = "aarch64")]
+#[inline]
+fn invalidate_local_tlb_asid(asid: usize) {
+    sel4_vspace::invalidate_local_tlb_asid(asid);
+}
+
+#[cfg(target_arch = "riscv64")]
+#[inline]
+fn invalidate_local_tlb() {
+    unsafe {
+        core::arch::asm!("sfence.vma");
+    }
+}
+
+#[cfg(target_arch = "riscv64")]
+#[inline]
+fn invalidate_local_tlb_asid(asid: usize) {
+    unsafe {
+        core::arch::asm!("sfence.vma x0, {}", in(reg) asid);
+    }
+}
+
+/// Handle the IPI `irq` on the current core. The calls are run for either IPI, as a riscv64 hart
+/// has only one software interrupt for both.
+pub fn ipi_handle(irq: usize) {
+    ipi_handle_remote_call();
+    let reschedule = BIT!(ipi_remote_call_t::Reschedule as usize);
+    if irq == INTERRUPT_IPI_1 {
+        let pending = ksIPIState[cpu_id()]
+            .pending
+            .fetch_and(!reschedule, Ordering::AcqRel);
+        if pending & reschedule != 0 {
+            rescheduleRequired();
+        }
+    } else {
+        // The software interrupt was cleared when the IPI was taken, raise it again to get the
+        // reschedule request handled with the lock held
+        #[cfg(target_arch = "riscv64")]
+        if ksIPIState[cpu_id()].pending.load(Ordering::Acquire) & reschedule != 0 {
+            ipi_send_mask(INTERRUPT_IPI_1, BIT!(cpu_id()));
+        }
+    }
+}
+
+/// The IPI to take for the calls posted for the current core, `irqInvalid` for none.
+#[cfg(target_arch = "riscv64")]
+pub fn ipi_pending_irq() -> usize {
+    let pending = ksIPIState[cpu_id()].pending.load(Ordering::Acquire);
+    if pending & REMOTE_CALLS != 0 {
+        INTERRUPT_IPI_0
+    } else if pending & BIT!(ipi_remote_call_t::Reschedule as usize) != 0 {
+        INTERRUPT_IPI_1
+    } else {
+        irqInvalid
+    }
+}
+
+/// Take the big kernel lock on entry. The remote calls that were posted while the current core
+/// was spinning for it are run before the kernel goes on.
+pub fn ipi_lock_acquire(irq_path: bool) {
+    ksIPIState[cpu_id()]
+        .waiting_for_lock
+        .store(true, Ordering::Release);
+    unsafe {
+        clh_lock_acquire(cpu_id(), irq_path);
+    }
+    ipi_stop_waiting();
+}
+
+/// Stop being waited for as a core spinning for the lock, and run the calls posted meanwhile.
+/// Also called on the way back to user mode, which the C stall path jumps to with the lock taken.
+pub fn ipi_stop_waiting() {
+    let state = &ksIPIState[cpu_id()];
+    if state.waiting_for_lock.swap(false, Ordering::AcqRel)
+        || state.pending.load(Ordering::Acquire) & REMOTE_CALLS != 0
+    {
+        ipi_handle_remote_call();
+    }
+}
@@ -0,0 +1,3 @@
+//! Cooperation between the cores, enabled by the `ENABLE_SMP` feature.
+
+pub mod ipi;
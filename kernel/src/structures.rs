@@ -67,10 +67,25 @@ pub struct seL4_BootInfo {
     pub extraBIPages: seL4_SlotRegion,
     pub initThreadCNodeSizeBits: usize,
     pub initThreadDomain: usize,
+    /// One `SchedControl` cap per core, see `ENABLE_MCS`
+    #[cfg(feature = "ENABLE_MCS")]
+    pub schedcontrol: seL4_SlotRegion,
     pub untyped: seL4_SlotRegion,
     pub untypedList: [seL4_UntypedDesc; CONFIG_MAX_NUM_BOOTINFO_UNTYPED_CAPS],
 }
 
+/// The payload of the `SEL4_BOOTINFO_HEADER_PLATFORM` chunk of the extra bootinfo
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct seL4_PlatformInfo {
+    /// Frequency of the kernel timer in Hz, which scheduling context times are counted in
+    pub timerFrequency: usize,
+    /// The last interrupt an IRQ handler cap can be issued for
+    pub maxIRQ: usize,
+    /// End of the physical address space the device untypeds cover
+    pub paddrUserDeviceTop: usize,
+}
+
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct ndks_boot_t {
@@ -91,6 +106,9 @@ pub struct rootserver_mem_t {
     pub boot_info: usize,
     pub extra_bi: usize,
     pub tcb: usize,
+    /// The scheduling context of the initial thread, see `ENABLE_MCS`
+    #[cfg(feature = "ENABLE_MCS")]
+    pub sc: usize,
     pub paging: region_t,
 }
 
@@ -0,0 +1,162 @@
+//! In-kernel test callbacks reachable through `SysDebugRun`.
+//!
+//! This mirrors `seL4_DebugRun` in debug builds, but instead of jumping to a
+//! user supplied function pointer, userland selects one of the functions
+//! registered in [`DEBUG_RUN_TABLE`] by id and passes a single word argument.
+//! The word returned by the callback is written back to the caller.
+//!
+//! Register convention:
+//! - `ArchReg::Cap`: id of the registered function
+//! - `ArchReg::MsgInfo`: user argument
+//! - `ArchReg::Badge`: return value, `usize::MAX` if the id is not registered
+
+use log::debug;
+use sel4_common::arch::ArchReg;
+use sel4_task::{get_currenct_thread, tcb_queue_t, tcb_t};
+use sel4_vspace::PTE;
+
+/// Signature of a function that can be invoked through `SysDebugRun`.
+pub type debug_run_fn = fn(arg: usize) -> usize;
+
+pub const DebugRunEPQueue: usize = 0;
+pub const DebugRunPTEEncode: usize = 1;
+pub const DebugRunInvocationErrors: usize = 2;
+pub const DebugRunMessageRegisters: usize = 3;
+pub const DebugRunIPCBuffers: usize = 4;
+pub const DebugRunAffinityReset: usize = 5;
+
+/// Registered in-kernel test functions, indexed by id.
+static DEBUG_RUN_TABLE: [debug_run_fn; 6] = [
+    debug_run_ep_queue,
+    debug_run_pte_encode,
+    debug_run_invocation_errors,
+    debug_run_message_registers,
+    debug_run_ipc_buffers,
+    debug_run_affinity_reset,
+];
+
+/// Number of scratch TCBs used by the endpoint queue test.
+const DEBUG_RUN_TCB_NUM: usize = 3;
+
+/// Scratch TCBs which are never scheduled, only linked into a local queue.
+static mut debug_run_tcbs: [tcb_t; DEBUG_RUN_TCB_NUM] = unsafe { core::mem::zeroed() };
+
+/// The scratch TCBs, only one test runs at a time
+fn scratch_tcbs() -> &'static mut [tcb_t; DEBUG_RUN_TCB_NUM] {
+    unsafe { &mut debug_run_tcbs }
+}
+
+/// Handle `SysDebugRun` for the current thread.
+pub fn handle_debug_run() {
+    let thread = get_currenct_thread();
+    let id = thread.tcbArch.get_register(ArchReg::Cap);
+    let arg = thread.tcbArch.get_register(ArchReg::MsgInfo);
+    let ret = match DEBUG_RUN_TABLE.get(id) {
+        Some(func) => func(arg),
+        None => {
+            debug!("SysDebugRun: no function registered with id {}", id);
+            usize::MAX
+        }
+    };
+    thread.tcbArch.set_register(ArchReg::Badge, ret);
+}
+
+/// Append all scratch TCBs to an endpoint queue, dequeue the one at
+/// `arg % DEBUG_RUN_TCB_NUM`, and walk the queue from head to tail.
+///
+/// # Returns
+///
+/// The order of the remaining TCBs, one nibble per TCB holding `index + 1`,
+/// first TCB in the lowest nibble. For `arg == 1` the expected value is `0x31`.
+fn debug_run_ep_queue(arg: usize) -> usize {
+    let tcbs = scratch_tcbs();
+    let mut queue = tcb_queue_t::default();
+    for tcb in tcbs.iter_mut() {
+        queue.ep_append(tcb);
+    }
+    queue.ep_dequeue(&mut tcbs[arg % DEBUG_RUN_TCB_NUM]);
+
+    let mut ret = 0;
+    let mut shift = 0;
+    let mut ptr = queue.head;
+    while ptr != 0 {
+        let index = tcbs.iter().position(|tcb| tcb.get_ptr() == ptr).unwrap();
+        ret |= (index + 1) << shift;
+        shift += 4;
+        ptr = tcbs[index].tcbEPNext;
+    }
+    ret
+}
+
+/// Encode a next-level page table entry pointing at the physical address `arg`.
+///
+/// # Returns
+///
+/// The raw PTE word.
+fn debug_run_pte_encode(arg: usize) -> usize {
+    PTE::pte_next_table(arg, false).0
+}
+
+/// Run the invocation error-path suite, see `super::invocation_errors`.
+///
+/// # Returns
+///
+/// The number of failed cases, 0 if the decoders report every error as expected.
+fn debug_run_invocation_errors(_arg: usize) -> usize {
+    super::invocation_errors::run_invocation_error_cases()
+}
+
+/// Check that message words travel in registers, see `super::message_registers`. `arg` seeds
+/// the message words.
+///
+/// # Returns
+///
+/// The bits of the failed checks, 0 if the message register ABI holds.
+fn debug_run_message_registers(arg: usize) -> usize {
+    super::message_registers::run_message_register_checks(arg)
+}
+
+/// Check that transfers reach both IPC buffers through their kernel alias, see
+/// `super::ipc_buffers`. `arg` seeds the message words.
+///
+/// # Returns
+///
+/// The bits of the failed checks, 0 if every transfer used the right buffers.
+fn debug_run_ipc_buffers(arg: usize) -> usize {
+    super::ipc_buffers::run_ipc_buffer_checks(arg)
+}
+
+/// Pin a scratch TCB to the core `arg` and reset its affinity with `AFFINITY_ANY_CORE`, see
+/// `sel4_task::balance`.
+///
+/// # Returns
+///
+/// The bits of the failed checks: bit 0 if the mask is not cleared, bit 1 if a core may still
+/// not pull the TCB. `usize::MAX` without `ENABLE_SMP_LOAD_BALANCE`, like an unregistered id.
+fn debug_run_affinity_reset(arg: usize) -> usize {
+    #[cfg(all(feature = "ENABLE_SMP_LOAD_BALANCE", not(feature = "ENABLE_MCS")))]
+    {
+        use sel4_common::sel4_config::CONFIG_MAX_NUM_NODES;
+        use sel4_task::balance::{can_migrate_to, AFFINITY_ANY_CORE};
+
+        let tcb = &mut scratch_tcbs()[0];
+        tcb.tcbAffinityMask = sel4_common::BIT!(arg % CONFIG_MAX_NUM_NODES);
+        super::invocation::invoke_tcb::invoke_tcb_set_affinity(tcb, AFFINITY_ANY_CORE);
+        let mut failed = 0;
+        if tcb.tcbAffinityMask != 0 {
+            failed |= 1;
+        }
+        if !(0..CONFIG_MAX_NUM_NODES).all(|cpu| can_migrate_to(tcb, cpu)) {
+            failed |= 2;
+        }
+        failed
+    }
+    #[cfg(not(all(feature = "ENABLE_SMP_LOAD_BALANCE", not(feature = "ENABLE_MCS"))))]
+    {
+        debug!(
+            "SysDebugRun: affinity reset needs ENABLE_SMP_LOAD_BALANCE, arg {}",
+            arg
+        );
+        usize::MAX
+    }
+}
@@ -1,12 +1,10 @@
-use crate::arch::set_vm_root_for_flush;
 use crate::config::{seL4_ASIDPoolBits, USER_TOP};
 use crate::kernel::boot::{current_extra_caps, get_extra_cap_by_index};
 use crate::syscall::invocation::decode::current_syscall_error;
-use crate::syscall::ThreadState;
-use crate::syscall::{current_lookup_fault, get_syscall_arg, set_thread_state, unlikely};
-use crate::syscall::{ensure_empty_slot, get_currenct_thread, lookup_slot_for_cnode_op};
+use crate::syscall::{current_lookup_fault, get_syscall_arg, unlikely};
+use crate::syscall::{ensure_empty_slot, lookup_slot_for_cnode_op};
 use log::debug;
-use sel4_common::arch::maskVMRights;
+use sel4_common::arch::{maskVMRights, vm_rights_t};
 use sel4_common::cap_rights::seL4_CapRights_t;
 use sel4_common::fault::lookup_fault_t;
 use sel4_common::sel4_config::{
@@ -19,7 +17,7 @@ use sel4_common::sel4_config::{
     PD_INDEX_OFFSET,
 };
 use sel4_common::utils::{
-    convert_to_mut_type_ref, global_ops, pageBitsForSize, ptr_to_mut, ptr_to_ref, MAX_FREE_INDEX,
+    convert_to_mut_type_ref, global_ops, pageBitsForSize, ptr_to_mut, ptr_to_ref,
 };
 use sel4_common::{
     arch::MessageLabel,
@@ -27,22 +25,23 @@ use sel4_common::{
     MASK,
 };
 use sel4_common::{BIT, IS_ALIGNED};
-use sel4_cspace::interface::{cap_t, cte_insert, cte_t, CapTag};
+use sel4_cspace::interface::{cap_t, cte_t, CapTag};
 
 use sel4_vspace::{
-    asid_map_t, asid_pool_t, asid_t, find_vspace_for_asid, get_asid_pool_by_index,
-    makeUser3rdLevel, make_user_1st_level, make_user_2nd_level, paddr_t, pptr_to_paddr,
-    set_asid_pool_by_index, vm_attributes_t, vptr_t, PDE, PGDE, PTE, PUDE,
+    asid_pool_t, asid_t, find_vspace_for_asid, get_asid_pool_by_index, makeUser3rdLevel,
+    make_user_1st_level, make_user_2nd_level, pptr_to_paddr, vm_attributes_t, PDE, PGDE, PTE, PUDE,
 };
 
-use crate::syscall::invocation::invoke_mmu_op::{
-    invoke_huge_page_map, invoke_large_page_map, invoke_page_get_address, invoke_page_table_unmap,
-    invoke_page_unmap, invoke_small_page_map,
+#[cfg(feature = "ENABLE_SMP")]
+use crate::syscall::invocation::decode::decode_irq_invocation::decode_irq_issue_trigger_core;
+use crate::syscall::invocation::{
+    invoke_mmu_op::{invoke_mmu, mmu_invocation_t},
+    perform_invocation,
 };
 use crate::{
     config::maxIRQ,
     interrupt::is_irq_active,
-    syscall::{invocation::invoke_irq::invoke_irq_control, lookupSlotForCNodeOp},
+    syscall::{invocation::invoke_irq::irq_control_invocation_t, lookupSlotForCNodeOp},
 };
 
 pub fn decode_mmu_invocation(
@@ -52,7 +51,11 @@ pub fn decode_mmu_invocation(
     call: bool,
     buffer: Option<&seL4_IPCBuffer>,
 ) -> exception_t {
-    match slot.cap.get_cap_type() {
+    let decoded = match slot.cap.get_cap_type() {
+        #[cfg(feature = "ENABLE_MMIO_EMULATION")]
+        CapTag::CapPageGlobalDirectoryCap if label == MessageLabel::ARMVSpaceEmulateMMIO => {
+            return crate::arch::decode_vspace_emulate_mmio(length, &slot.cap, buffer)
+        }
         CapTag::CapPageGlobalDirectoryCap => {
             decode_vspace_root_invocation(label, length, slot, buffer)
         }
@@ -66,10 +69,19 @@ pub fn decode_mmu_invocation(
         CapTag::CapFrameCap => decode_frame_invocation(label, length, slot, call, buffer),
         CapTag::CapASIDControlCap => decode_asid_control(label, length, buffer),
         CapTag::CapASIDPoolCap => decode_asid_pool(label, slot),
+        #[cfg(feature = "ENABLE_ARM_HYP")]
+        CapTag::CapVCPUCap => {
+            return crate::arch::decode_vcpu_invocation(label, length, &slot.cap, buffer)
+        }
+        #[cfg(feature = "ENABLE_ARM_SMC")]
+        CapTag::CapSMCCap => {
+            return crate::arch::decode_arm_smc_invocation(label, length, &slot.cap, call, buffer)
+        }
         _ => {
             panic!("Invalid arch cap type");
         }
-    }
+    };
+    perform_invocation(decoded, |inv| invoke_mmu(inv, slot))
 }
 
 fn decode_page_table_invocation(
@@ -77,7 +89,7 @@ fn decode_page_table_invocation(
     length: usize,
     cte: &mut cte_t,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<mmu_invocation_t, exception_t> {
     /*
         if (invLabel == ARMPageTableUnmap) {
             if (unlikely(!isFinalCapability(cte))) {
@@ -90,23 +102,21 @@ fn decode_page_table_invocation(
     */
 
     if label == MessageLabel::ARMPageTableUnmap {
-        log::warn!("Need to check is FinalCapability here");
-        get_currenct_thread().set_state(ThreadState::ThreadStateRestart);
-        unimplemented!("performPageTableInvocationUnmap");
+        return decode_page_table_unmap(cte);
     }
 
     if unlikely(label != MessageLabel::ARMPageTableMap) {
         global_ops!(current_syscall_error._type = seL4_IllegalOperation);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     if unlikely(length < 2 || global_ops!(current_extra_caps.excaprefs[0] == 0)) {
         global_ops!(current_syscall_error._type = seL4_TruncatedMessage);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     if unlikely(cte.cap.get_pt_is_mapped() == 1) {
         global_ops!(current_syscall_error._type = seL4_InvalidCapability);
         global_ops!(current_syscall_error.invalidArgumentNumber = 0);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let vaddr = get_syscall_arg(0, buffer) & !(MASK!(PD_INDEX_OFFSET));
@@ -116,16 +126,16 @@ fn decode_page_table_invocation(
     if unlikely(!vspace_root_cap.is_valid_native_root()) {
         global_ops!(current_syscall_error._type = seL4_InvalidCapability);
         global_ops!(current_syscall_error.invalidCapNumber = 1);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let vspace_root = vspace_root_cap.get_pgd_base_ptr();
     let asid = vspace_root_cap.get_pgd_mapped_asid();
 
-    if unlikely(vaddr > USER_TOP) {
+    if unlikely(vaddr > vspace_top(asid)) {
         global_ops!(current_syscall_error._type = seL4_InvalidArgument);
         global_ops!(current_syscall_error.invalidArgumentNumber = 0);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let find_ret = find_vspace_for_asid(asid);
@@ -133,12 +143,12 @@ fn decode_page_table_invocation(
     if unlikely(find_ret.status != exception_t::EXCEPTION_NONE) {
         global_ops!(current_syscall_error._type = seL4_FailedLookup);
         global_ops!(current_syscall_error.failedLookupWasSource = 0);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     if unlikely(find_ret.vspace_root.unwrap() as usize != vspace_root) {
         global_ops!(current_syscall_error._type = seL4_InvalidCapability);
         global_ops!(current_syscall_error.invalidCapNumber = 1);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let pd_slot = PGDE::new_from_pte(vspace_root).lookup_pd_slot(vaddr);
@@ -146,45 +156,39 @@ fn decode_page_table_invocation(
     if pd_slot.status != exception_t::EXCEPTION_NONE {
         global_ops!(current_syscall_error._type = seL4_FailedLookup);
         global_ops!(current_syscall_error.failedLookupWasSource = 0);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     if unlikely(
         ptr_to_ref(pd_slot.pdSlot).get_present() || ptr_to_ref(pd_slot.pdSlot).is_larger_page(),
     ) {
         global_ops!(current_syscall_error._type = seL4_DeleteFirst);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
-
-    let pde = PDE::new_page(pptr_to_paddr(cte.cap.get_pt_base_ptr()), 0x3);
-    cte.cap.set_pt_is_mapped(1);
-    cte.cap.set_pt_mapped_asid(asid);
-    cte.cap.set_pt_mapped_address(vaddr);
-    get_currenct_thread().set_state(ThreadState::ThreadStateRestart);
-
-    *ptr_to_mut(pd_slot.pdSlot) = pde;
-    log::warn!("Need to clean D-Cache using cleanByVA_PoU");
-    exception_t::EXCEPTION_NONE
+    Ok(mmu_invocation_t::PageTableMap {
+        pd_slot: ptr_to_mut(pd_slot.pdSlot),
+        asid,
+        vaddr,
+    })
 }
 
 fn decode_page_clean_invocation(
     label: MessageLabel,
     length: usize,
     cte: &mut cte_t,
-    _call: bool,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<mmu_invocation_t, exception_t> {
     if length < 2 {
         log::error!("[User] Page Flush: Truncated message.");
         global_ops!(current_syscall_error._type = seL4_TruncatedMessage);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     if unlikely(cte.cap.get_frame_mapped_asid() == 0) {
         log::error!("[User] Page Flush: Frame is not mapped.");
         global_ops!(current_syscall_error._type = seL4_IllegalOperation);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
-    let _vaddr = cte.cap.get_frame_mapped_address();
+    let vaddr = cte.cap.get_frame_mapped_address();
     let asid = cte.cap.get_frame_mapped_asid();
     let find_ret = find_vspace_for_asid(asid);
 
@@ -192,7 +196,7 @@ fn decode_page_clean_invocation(
         log::error!("[User] Page Flush: No PGD for ASID");
         global_ops!(current_syscall_error._type = seL4_FailedLookup);
         global_ops!(current_syscall_error.failedLookupWasSource = 0);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let start = get_syscall_arg(0, buffer);
@@ -202,7 +206,7 @@ fn decode_page_clean_invocation(
         log::error!("[User] Page Flush: Invalid range");
         global_ops!(current_syscall_error._type = seL4_InvalidArgument);
         global_ops!(current_syscall_error.invalidArgumentNumber = 1);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let page_size = BIT!(pageBitsForSize(cte.cap.get_frame_size()));
@@ -210,44 +214,19 @@ fn decode_page_clean_invocation(
         log::error!("[User] Page Flush: Requested range not inside page");
         global_ops!(current_syscall_error._type = seL4_InvalidArgument);
         global_ops!(current_syscall_error.invalidArgumentNumber = 0);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
-    let _pstart = pptr_to_paddr(cte.cap.get_frame_base_ptr() + start);
-    get_currenct_thread().set_state(ThreadState::ThreadStateRestart);
-
-    if start < end {
-        let root_switched = set_vm_root_for_flush(find_ret.vspace_root.unwrap() as _, asid);
-        log::warn!(
-            "need to flush cache for decode_page_clean_invocation label: {:?}",
-            label
-        );
-        if root_switched {
-            get_currenct_thread()
-                .set_vm_root()
-                .expect("can't set vm root for decode_page_clean_invocation");
-        }
-    }
-    exception_t::EXCEPTION_NONE
-
-    /*
-        static exception_t performPageFlush(int invLabel, vspace_root_t *vspaceRoot, asid_t asid,
-                                    vptr_t start, vptr_t end, paddr_t pstart)
-        {
-            bool_t root_switched;
-                if (start < end) {
-                    root_switched = setVMRootForFlush(vspaceRoot, asid);
-                    doFlush(invLabel, start, end, pstart);
-                    if (root_switched) {
-                        setVMRoot(NODE_STATE(ksCurThread));
-                    }
-                }
-            return EXCEPTION_NONE;
-        }
-    */
     /*
         return performPageFlush(invLabel, find_ret.vspace_root, asid, vaddr + start, vaddr + end - 1,
                                 pstart);
     */
+    Ok(mmu_invocation_t::Flush {
+        label,
+        vspace: find_ret.vspace_root.unwrap() as usize,
+        asid,
+        start: vaddr + start,
+        end: vaddr + end,
+    })
 }
 
 fn decode_frame_invocation(
@@ -256,29 +235,23 @@ fn decode_frame_invocation(
     frame_slot: &mut cte_t,
     call: bool,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<mmu_invocation_t, exception_t> {
     match label {
         MessageLabel::ARMPageMap => decode_frame_map(length, frame_slot, buffer),
-        MessageLabel::ARMPageUnmap => {
-            set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-            invoke_page_unmap(frame_slot)
-        }
+        MessageLabel::ARMPageUnmap => Ok(mmu_invocation_t::PageUnmap),
         MessageLabel::ARMPageClean_Data
         | MessageLabel::ARMPageInvalidate_Data
         | MessageLabel::ARMPageCleanInvalidate_Data
         | MessageLabel::ARMPageUnify_Instruction => {
-            decode_page_clean_invocation(label, length, frame_slot, call, buffer)
-        }
-        MessageLabel::ARMPageGetAddress => {
-            set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-            invoke_page_get_address(frame_slot.cap.get_frame_base_ptr(), call)
+            decode_page_clean_invocation(label, length, frame_slot, buffer)
         }
+        MessageLabel::ARMPageGetAddress => Ok(mmu_invocation_t::PageGetAddress { call }),
         _ => {
             debug!("invalid operation label:{:?}", label);
             unsafe {
                 current_syscall_error._type = seL4_IllegalOperation;
             }
-            exception_t::EXCEPTION_SYSCALL_ERROR
+            Err(exception_t::EXCEPTION_SYSCALL_ERROR)
         }
     }
 }
@@ -287,10 +260,10 @@ fn decode_asid_control(
     label: MessageLabel,
     length: usize,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<mmu_invocation_t, exception_t> {
     if unlikely(label != MessageLabel::ARMASIDControlMakePool) {
         global_ops!(current_syscall_error._type = seL4_IllegalOperation);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     if unlikely(
         length < 2
@@ -298,7 +271,7 @@ fn decode_asid_control(
             || global_ops!(current_extra_caps.excaprefs[1] == 0),
     ) {
         global_ops!(current_syscall_error._type = seL4_TruncatedMessage);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let index = get_syscall_arg(0, buffer);
     let depth = get_syscall_arg(1, buffer);
@@ -317,7 +290,7 @@ fn decode_asid_control(
     if unlikely(i == nASIDPools) {
         /* If no unallocated pool is found */
         global_ops!(current_syscall_error._type = seL4_DeleteFirst);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let asid_base = i << asidLowBits;
     if unlikely(
@@ -327,57 +300,52 @@ fn decode_asid_control(
     ) {
         global_ops!(current_syscall_error._type = seL4_InvalidCapability);
         global_ops!(current_syscall_error.invalidCapNumber = 0);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let status = parent_slot.ensure_no_children();
     if unlikely(status != exception_t::EXCEPTION_NONE) {
-        return status;
+        return Err(status);
     }
     let frame = untyped.get_cap_ptr();
     let lu_ret = lookup_slot_for_cnode_op(false, &root, index, depth);
     if unlikely(lu_ret.status != exception_t::EXCEPTION_NONE) {
-        return lu_ret.status;
+        return Err(lu_ret.status);
     }
     let dest_slot = ptr_to_mut(lu_ret.slot);
     let status = ensure_empty_slot(dest_slot);
     if unlikely(status != exception_t::EXCEPTION_NONE) {
-        return status;
+        return Err(status);
     }
-    get_currenct_thread().set_state(ThreadState::ThreadStateRestart);
-    parent_slot
-        .cap
-        .set_untyped_free_index(MAX_FREE_INDEX(parent_slot.cap.get_untyped_block_size()));
-    unsafe {
-        core::slice::from_raw_parts_mut(frame as *mut u8, BIT!(seL4_ASIDPoolBits)).fill(0);
-    }
-    cte_insert(
-        &cap_t::new_asid_pool_cap(asid_base, frame),
+    Ok(mmu_invocation_t::ASIDControl {
+        frame,
         parent_slot,
         dest_slot,
-    );
-    assert!(asid_base & MASK!(asidLowBits) == 0);
-    set_asid_pool_by_index(asid_base >> asidLowBits, frame);
-    exception_t::EXCEPTION_NONE
+        asid_base,
+    })
 }
 
-fn decode_asid_pool(label: MessageLabel, cte: &mut cte_t) -> exception_t {
-    if unlikely(label != MessageLabel::ARMASIDPoolAssign) {
+fn decode_asid_pool(label: MessageLabel, cte: &mut cte_t) -> Result<mmu_invocation_t, exception_t> {
+    let guest = label == MessageLabel::ARMASIDPoolAssignVM;
+    if unlikely(
+        label != MessageLabel::ARMASIDPoolAssign && !(guest && cfg!(feature = "ENABLE_ARM_HYP")),
+    ) {
         global_ops!(current_syscall_error._type = seL4_IllegalOperation);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     if unlikely(global_ops!(current_extra_caps.excaprefs[0] == 0)) {
         global_ops!(current_syscall_error._type = seL4_TruncatedMessage);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
-    let vspace_cap_slot = global_ops!(current_extra_caps.excaprefs[0]);
-    let vspace_cap = convert_to_mut_type_ref::<cap_t>(vspace_cap_slot);
+    let vspace_slot =
+        convert_to_mut_type_ref::<cte_t>(global_ops!(current_extra_caps.excaprefs[0]));
+    let vspace_cap = vspace_slot.cap;
 
     if unlikely(!vspace_cap.is_vtable_root() || vspace_cap.get_pgd_is_mapped() == 1) {
         log::debug!("is not a valid vtable root");
         global_ops!(current_syscall_error._type = seL4_InvalidCapability);
         global_ops!(current_syscall_error.invalidArgumentNumber = 1);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let pool = get_asid_pool_by_index(cte.cap.get_asid_base() >> asidLowBits);
 
@@ -387,16 +355,16 @@ fn decode_asid_pool(label: MessageLabel, cte: &mut cte_t) -> exception_t {
         unsafe {
             current_lookup_fault = lookup_fault_t::new_root_invalid();
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     if unlikely(pool != cte.cap.get_asid_pool()) {
         global_ops!(current_syscall_error._type = seL4_InvalidCapability);
         global_ops!(current_syscall_error.invalidCapNumber = 0);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
-    let mut asid = cte.cap.get_asid_base();
+    let asid = cte.cap.get_asid_base();
 
     let pool = convert_to_mut_type_ref::<asid_pool_t>(pool);
     let mut i = 0;
@@ -410,31 +378,81 @@ fn decode_asid_pool(label: MessageLabel, cte: &mut cte_t) -> exception_t {
         unsafe {
             current_syscall_error._type = seL4_DeleteFirst;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+
+    Ok(mmu_invocation_t::ASIDPoolAssign {
+        asid: asid + i,
+        pool,
+        vspace_slot,
+        guest,
+    })
+}
+
+/// The highest address that can be mapped in the vspace of `asid`, the IPA space for a guest.
+#[inline]
+fn vspace_top(asid: asid_t) -> usize {
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    if sel4_vspace::is_guest_asid(asid) {
+        return sel4_vspace::guest_ipa_top();
     }
+    let _ = asid;
+    USER_TOP
+}
 
-    asid += i;
+/// The leaf descriptors mapping a frame into the vspace of `asid`, stage-2 ones for a guest.
+fn make_1st_level(
+    asid: asid_t,
+    base: usize,
+    vm_rights: vm_rights_t,
+    attr: vm_attributes_t,
+) -> PUDE {
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    if sel4_vspace::is_guest_asid(asid) {
+        return sel4_vspace::make_guest_1st_level(base, vm_rights, attr);
+    }
+    let _ = asid;
+    make_user_1st_level(base, vm_rights, attr)
+}
 
-    get_currenct_thread().set_state(ThreadState::ThreadStateRestart);
-    vspace_cap.set_pgd_mapped_asid(asid);
-    vspace_cap.set_pgd_is_mapped(1);
-    let asid_map = asid_map_t::new_vspace(vspace_cap.get_pgd_base_ptr());
-    pool[asid & MASK!(asidLowBits)] = asid_map;
-    exception_t::EXCEPTION_NONE
+fn make_2nd_level(asid: asid_t, base: usize, vm_rights: vm_rights_t, attr: vm_attributes_t) -> PDE {
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    if sel4_vspace::is_guest_asid(asid) {
+        return sel4_vspace::make_guest_2nd_level(base, vm_rights, attr);
+    }
+    let _ = asid;
+    make_user_2nd_level(base, vm_rights, attr)
+}
+
+fn make_3rd_level(asid: asid_t, base: usize, vm_rights: vm_rights_t, attr: vm_attributes_t) -> PTE {
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    if sel4_vspace::is_guest_asid(asid) {
+        return sel4_vspace::make_guest_3rd_level(base, vm_rights, attr);
+    }
+    let _ = asid;
+    makeUser3rdLevel(base, vm_rights, attr)
 }
 
 fn decode_frame_map(
     length: usize,
     frame_slot: &mut cte_t,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<mmu_invocation_t, exception_t> {
     if length < 3 || get_extra_cap_by_index(0).is_none() {
         debug!("ARMPageMap: Truncated message.");
         global_ops!(current_syscall_error._type = seL4_TruncatedMessage);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let vaddr = get_syscall_arg(0, buffer);
-    let attr = vm_attributes_t::from_word(get_syscall_arg(2, buffer));
+    let mut attr = vm_attributes_t::from_user_word(get_syscall_arg(2, buffer));
+    if frame_slot.cap.is_device_frame() {
+        if !attr.get_armExecuteNever() {
+            debug!("ARMPageMap: Device frames must be mapped ExecuteNever.");
+            global_ops!(current_syscall_error._type = seL4_IllegalOperation);
+            return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+        }
+        attr = attr.for_device_frame();
+    }
     let vspace_root_cap = get_extra_cap_by_index(0).unwrap().cap;
     let frame_size = frame_slot.cap.get_frame_size();
     let frame_vm_rights = unsafe { core::mem::transmute(frame_slot.cap.get_frame_vm_rights()) };
@@ -445,7 +463,7 @@ fn decode_frame_map(
     if unlikely(!vspace_root_cap.is_valid_native_root()) {
         global_ops!(current_syscall_error._type = seL4_InvalidCapability);
         global_ops!(current_syscall_error.invalidCapNumber = 1);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let vspace_root = vspace_root_cap.get_pgd_base_ptr();
     let asid = vspace_root_cap.get_pgd_mapped_asid();
@@ -453,18 +471,18 @@ fn decode_frame_map(
     if unlikely(find_ret.status != exception_t::EXCEPTION_NONE) {
         global_ops!(current_syscall_error._type = seL4_FailedLookup);
         global_ops!(current_syscall_error.failedLookupWasSource = 0);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     if unlikely(find_ret.vspace_root.unwrap() as usize != vspace_root) {
         global_ops!(current_syscall_error._type = seL4_InvalidCapability);
         global_ops!(current_syscall_error.invalidCapNumber = 1);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     if unlikely(!IS_ALIGNED!(vaddr, pageBitsForSize(frame_size))) {
         // global_var!(current_syscall_error)._type = seL4_AlignmentError;
         // Use unsafe here will cause the _type error.
         global_ops!(current_syscall_error._type = seL4_AlignmentError);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let frame_asid = frame_slot.cap.get_frame_mapped_asid();
     if frame_asid != asidInvalid {
@@ -472,23 +490,20 @@ fn decode_frame_map(
             log::error!("[User] ARMPageMap: Attempting to remap a frame that does not belong to the passed address space");
             global_ops!(current_syscall_error._type = seL4_InvalidCapability);
             global_ops!(current_syscall_error.invalidArgumentNumber = 0);
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
+            return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
         } else if frame_slot.cap.get_frame_mapped_address() != vaddr {
             log::error!("[User] ARMPageMap: Attempting to map frame into multiple addresses");
             global_ops!(current_syscall_error._type = seL4_InvalidArgument);
             global_ops!(current_syscall_error.invalidArgumentNumber = 2);
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
+            return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
         }
     } else {
-        if unlikely(vaddr + BIT!(pageBitsForSize(frame_size)) - 1 > USER_TOP) {
+        if unlikely(vaddr + BIT!(pageBitsForSize(frame_size)) - 1 > vspace_top(asid)) {
             global_ops!(current_syscall_error._type = seL4_InvalidArgument);
             global_ops!(current_syscall_error.invalidArgumentNumber = 0);
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
+            return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
         }
     }
-    // TODO: copy cap in the here. Not write slot when the address is not need to write.
-    // frame_slot.cap.set_frame_mapped_asid(asid);
-    // frame_slot.cap.set_frame_mapped_address(vaddr);
 
     let vspace_root = PGDE::new_from_pte(vspace_root);
     let base = pptr_to_paddr(frame_slot.cap.get_frame_base_ptr());
@@ -500,17 +515,14 @@ fn decode_frame_map(
                     current_syscall_error._type = seL4_FailedLookup;
                     current_syscall_error.failedLookupWasSource = 0;
                 }
-                return exception_t::EXCEPTION_SYSCALL_ERROR;
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
             }
-            set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-            let ptSlot = convert_to_mut_type_ref::<PTE>(lu_ret.ptSlot as usize);
-            invoke_small_page_map(
+            Ok(mmu_invocation_t::SmallPageMap {
                 vaddr,
                 asid,
-                frame_slot,
-                makeUser3rdLevel(base, vm_rights, attr),
-                ptSlot,
-            )
+                pte: make_3rd_level(asid, base, vm_rights, attr),
+                pt_slot: convert_to_mut_type_ref::<PTE>(lu_ret.ptSlot as usize),
+            })
         }
         ARM_Large_Page => {
             let lu_ret = vspace_root.lookup_pd_slot(vaddr);
@@ -519,17 +531,14 @@ fn decode_frame_map(
                     current_syscall_error._type = seL4_FailedLookup;
                     current_syscall_error.failedLookupWasSource = 0;
                 }
-                return exception_t::EXCEPTION_SYSCALL_ERROR;
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
             }
-            set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-            let pdSlot = convert_to_mut_type_ref::<PDE>(lu_ret.pdSlot as usize);
-            invoke_large_page_map(
+            Ok(mmu_invocation_t::LargePageMap {
                 vaddr,
                 asid,
-                frame_slot,
-                make_user_2nd_level(base, vm_rights, attr),
-                pdSlot,
-            )
+                pde: make_2nd_level(asid, base, vm_rights, attr),
+                pd_slot: convert_to_mut_type_ref::<PDE>(lu_ret.pdSlot as usize),
+            })
         }
         ARM_Huge_Page => {
             let lu_ret = vspace_root.lookup_pud_slot(vaddr);
@@ -538,152 +547,27 @@ fn decode_frame_map(
                     current_syscall_error._type = seL4_FailedLookup;
                     current_syscall_error.failedLookupWasSource = 0;
                 }
-                return exception_t::EXCEPTION_SYSCALL_ERROR;
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
             }
-            set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-            let pudSlot = convert_to_mut_type_ref::<PUDE>(lu_ret.pudSlot as usize);
-            invoke_huge_page_map(
+            Ok(mmu_invocation_t::HugePageMap {
                 vaddr,
                 asid,
-                frame_slot,
-                make_user_1st_level(base, vm_rights, attr),
-                pudSlot,
-            )
+                pude: make_1st_level(asid, base, vm_rights, attr),
+                pud_slot: convert_to_mut_type_ref::<PUDE>(lu_ret.pudSlot as usize),
+            })
         }
-        _ => exception_t::EXCEPTION_SYSCALL_ERROR,
-    }
-    // if length < 3 || get_extra_cap_by_index(0).is_none() {
-    //     debug!("ARMPageMap: Truncated message.");
-    //     unsafe {
-    //         current_syscall_error._type = seL4_TruncatedMessage;
-    //     }
-    //     return exception_t::EXCEPTION_SYSCALL_ERROR;
-    // }
-    // let vaddr = get_syscall_arg(0, buffer);
-    // log::debug!("map frame: {:#x?}  frame: {:#x?}", frame_slot.cap.get_frame_mapped_address(), vaddr);
-    // let attr = vm_attributes_t::from_word(get_syscall_arg(2, buffer));
-    // let lvl1pt_cap = get_extra_cap_by_index(0).unwrap().cap;
-    // let frame_size = frame_slot.cap.get_frame_size();
-    // let frame_vm_rights = unsafe { core::mem::transmute(frame_slot.cap.get_frame_vm_rights()) };
-    // let vm_rights = maskVMRights(
-    //     frame_vm_rights,
-    //     seL4_CapRights_t::from_word(get_syscall_arg(1, buffer)),
-    // );
-    // let (vspace_root, asid) = match get_vspace(&lvl1pt_cap) {
-    //     Some(v) => v,
-    //     _ => return exception_t::EXCEPTION_SYSCALL_ERROR,
-    // };
-    // if unlikely(!checkVPAlignment(frame_size, vaddr)) {
-    //     unsafe {
-    //         current_syscall_error._type = seL4_AlignmentError;
-    //     }
-    //     return exception_t::EXCEPTION_SYSCALL_ERROR;
-    // }
-    // let frame_asid = frame_slot.cap.get_frame_mapped_asid();
-    // log::debug!("frame_asid: {:?}", frame_asid);
-    // if frame_asid != asidInvalid {
-    //     if frame_asid != asid {
-    //         debug!("ARMPageMap: Attempting to remap a frame that does not belong to the passed address space");
-    //         unsafe {
-    //             current_syscall_error._type = seL4_InvalidCapability;
-    //             current_syscall_error.invalidArgumentNumber = 0;
-    //         }
-    //         return exception_t::EXCEPTION_SYSCALL_ERROR;
-    //     }
-    //     if frame_slot.cap.get_frame_mapped_address() != vaddr {
-    //         debug!("ARMPageMap: attempting to map frame into multiple addresses");
-    //         unsafe {
-    //             current_syscall_error._type = seL4_InvalidArgument;
-    //             current_syscall_error.invalidArgumentNumber = 2;
-    //         }
-    //         return exception_t::EXCEPTION_SYSCALL_ERROR;
-    //     }
-    // } else {
-    //     let vtop = vaddr + BIT!(pageBitsForSize(frame_size)) - 1;
-    //     if unlikely(vtop >= USER_TOP) {
-    //         unsafe {
-    //             current_syscall_error._type = seL4_InvalidArgument;
-    //             current_syscall_error.invalidArgumentNumber = 0;
-    //         }
-    //         return exception_t::EXCEPTION_SYSCALL_ERROR;
-    //     }
-    // }
-
-    // // frame_slot.cap.set_frame_mapped_address(vaddr);
-    // // frame_slot.cap.set_frame_mapped_asid(asid);
-
-    // let base = pptr_to_paddr(frame_slot.cap.get_frame_base_ptr());
-    // if frame_size == ARM_Small_Page {
-    //     let lu_ret = vspace_root.lookup_pt_slot(vaddr);
-    //     if lu_ret.status != exception_t::EXCEPTION_NONE {
-    //         unsafe {
-    //             current_syscall_error._type = seL4_FailedLookup;
-    //             current_syscall_error.failedLookupWasSource = 0;
-    //         }
-    //         return exception_t::EXCEPTION_SYSCALL_ERROR;
-    //     }
-    //     set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-    //     let ptSlot = convert_to_mut_type_ref::<PTE>(lu_ret.ptSlot as usize);
-    //     invoke_small_page_map(
-    //         vaddr,
-    //         asid,
-    //         frame_slot,
-    //         makeUser3rdLevel(base, vm_rights, attr),
-    //         ptSlot,
-    //     )
-    // } else if frame_size == ARM_Large_Page {
-    //     let lu_ret = vspace_root.lookup_pd_slot(vaddr);
-    //     if lu_ret.status != exception_t::EXCEPTION_NONE {
-    //         unsafe {
-    //             current_syscall_error._type = seL4_FailedLookup;
-    //             current_syscall_error.failedLookupWasSource = 0;
-    //         }
-    //         return exception_t::EXCEPTION_SYSCALL_ERROR;
-    //     }
-    //     set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-    //     let pdSlot = convert_to_mut_type_ref::<PDE>(lu_ret.pdSlot as usize);
-    //     invoke_large_page_map(
-    //         vaddr,
-    //         asid,
-    //         frame_slot,
-    //         make_user_2nd_level(base, vm_rights, attr),
-    //         pdSlot,
-    //     )
-    // } else if frame_size == ARM_Huge_Page {
-    //     let lu_ret = vspace_root.lookup_pud_slot(vaddr);
-    //     if lu_ret.status != exception_t::EXCEPTION_NONE {
-    //         unsafe {
-    //             current_syscall_error._type = seL4_FailedLookup;
-    //             current_syscall_error.failedLookupWasSource = 0;
-    //         }
-    //         return exception_t::EXCEPTION_SYSCALL_ERROR;
-    //     }
-    //     set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-    //     let pudSlot = convert_to_mut_type_ref::<PUDE>(lu_ret.pudSlot as usize);
-    //     invoke_huge_page_map(
-    //         vaddr,
-    //         asid,
-    //         frame_slot,
-    //         make_user_1st_level(base, vm_rights, attr),
-    //         pudSlot,
-    //     )
-    // } else {
-    //     return exception_t::EXCEPTION_SYSCALL_ERROR;
-    // }
+        _ => Err(exception_t::EXCEPTION_SYSCALL_ERROR),
+    }
 }
 
-#[allow(unused)]
-fn decode_page_table_unmap(pt_cte: &mut cte_t) -> exception_t {
+fn decode_page_table_unmap(pt_cte: &mut cte_t) -> Result<mmu_invocation_t, exception_t> {
     if !pt_cte.is_final_cap() {
-        debug!("RISCVPageTableUnmap: cannot unmap if more than once cap exists");
+        debug!("ARMPageTableUnmap: cannot unmap if more than once cap exists");
         global_ops!(current_syscall_error._type = seL4_RevokeFirst);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
-    let cap = &mut pt_cte.cap;
     // todo: in riscv here exists some more code ,but I don't know what it means and cannot find it in sel4,need check
-    set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-
-    return invoke_page_table_unmap(cap);
+    Ok(mmu_invocation_t::PageTableUnmap)
 }
 
 // FIXED check pgd_is_mapped
@@ -729,7 +613,7 @@ fn decode_vspace_root_invocation(
     length: usize,
     cte: &mut cte_t,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<mmu_invocation_t, exception_t> {
     match label {
         MessageLabel::ARMVSpaceClean_Data
         | MessageLabel::ARMVSpaceInvalidate_Data
@@ -739,8 +623,8 @@ fn decode_vspace_root_invocation(
                 debug!("VSpaceRoot Flush: Truncated message.");
                 unsafe {
                     current_syscall_error._type = seL4_TruncatedMessage;
-                    return exception_t::EXCEPTION_SYSCALL_ERROR;
                 }
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
             }
             let start = get_syscall_arg(0, buffer);
             let end = get_syscall_arg(1, buffer);
@@ -749,20 +633,20 @@ fn decode_vspace_root_invocation(
                 unsafe {
                     current_syscall_error._type = seL4_InvalidArgument;
                     current_syscall_error.invalidArgumentNumber = 1;
-                    return exception_t::EXCEPTION_SYSCALL_ERROR;
                 }
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
             }
             if end > USER_TOP {
                 debug!("VSpaceRoot Flush: Exceed the user addressable region.");
                 unsafe { current_syscall_error._type = seL4_IllegalOperation };
-                return exception_t::EXCEPTION_SYSCALL_ERROR;
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
             }
             if !cte.cap.is_valid_native_root() {
                 unsafe {
                     current_syscall_error._type = seL4_InvalidCapability;
                     current_syscall_error.invalidCapNumber = 0
                 };
-                return exception_t::EXCEPTION_SYSCALL_ERROR;
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
             }
             let vspace_root = PGDE::new_from_pte(cte.cap.get_pgd_base_ptr());
             let asid = cte.cap.get_asid_base();
@@ -772,8 +656,8 @@ fn decode_vspace_root_invocation(
                 unsafe {
                     current_syscall_error._type = seL4_FailedLookup;
                     current_syscall_error.failedLookupWasSource = 0;
-                    return exception_t::EXCEPTION_SYSCALL_ERROR;
                 }
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
             }
             if find_ret.vspace_root.unwrap() as usize != vspace_root.get_ptr() {
                 debug!("VSpaceRoot Flush: Invalid VSpace Cap");
@@ -781,12 +665,19 @@ fn decode_vspace_root_invocation(
                     current_syscall_error._type = seL4_InvalidCapability;
                     current_syscall_error.invalidCapNumber = 0;
                 }
-                return exception_t::EXCEPTION_SYSCALL_ERROR;
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
             }
+            let vspace = find_ret.vspace_root.unwrap() as usize;
             let resolve_ret = vspace_root.lookup_frame(start);
             if !resolve_ret.valid {
-                get_currenct_thread().set_state(ThreadState::ThreadStateRestart);
-                return exception_t::EXCEPTION_NONE;
+                // Nothing is mapped at start, the flush is empty
+                return Ok(mmu_invocation_t::Flush {
+                    label,
+                    vspace,
+                    asid,
+                    start,
+                    end: start,
+                });
             }
             let page_base_start = start & !MASK!(pageBitsForSize(resolve_ret.frameSize));
             let page_base_end = (end - 1) & !MASK!(pageBitsForSize(resolve_ret.frameSize));
@@ -797,55 +688,29 @@ fn decode_vspace_root_invocation(
                     current_syscall_error.rangeErrorMax =
                         page_base_start + MASK!(pageBitsForSize(resolve_ret.frameSize));
                 }
-                return exception_t::EXCEPTION_SYSCALL_ERROR;
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
             }
-            let pstart =
-                resolve_ret.frameBase + start & MASK!(pageBitsForSize(resolve_ret.frameSize));
-            get_currenct_thread().set_state(ThreadState::ThreadStateRestart);
-            return decode_vspace_flush_invocation(
+            Ok(mmu_invocation_t::Flush {
                 label,
-                find_ret.vspace_root.unwrap() as usize,
+                vspace,
                 asid,
                 start,
                 end,
-                paddr_t::from(pstart),
-            );
+            })
         }
         _ => {
             unsafe { current_syscall_error._type = seL4_IllegalOperation };
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
+            Err(exception_t::EXCEPTION_SYSCALL_ERROR)
         }
     }
 }
 
-fn decode_vspace_flush_invocation(
-    label: MessageLabel,
-    vspace: usize,
-    asid: asid_t,
-    start: vptr_t,
-    end: vptr_t,
-    _pstart: paddr_t,
-) -> exception_t {
-    if start < end {
-        let _root_switched = set_vm_root_for_flush(vspace, asid);
-        log::warn!(
-            "need to flush cache for decode_page_clean_invocation label: {:?}",
-            label
-        );
-        todo!();
-        // if root_switched {
-        // 	set_vm_root(vspace);
-        // }
-    }
-    exception_t::EXCEPTION_NONE
-}
-
 fn decode_page_upper_directory_invocation(
     label: MessageLabel,
     length: usize,
     cte: &mut cte_t,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<mmu_invocation_t, exception_t> {
     /*
         lookupPGDSlot_ret_t pgdSlot;
         findVSpaceForASID_ret_t find_ret;
@@ -859,25 +724,28 @@ fn decode_page_upper_directory_invocation(
         }
     */
     if label == MessageLabel::ARMPageUpperDirectoryUnmap {
-        log::warn!("Need to check is FinalCapability here");
-        get_currenct_thread().set_state(ThreadState::ThreadStateRestart);
-        unimplemented!("performUpperPageDirectoryInvocationUnmap");
+        if unlikely(!cte.is_final_cap()) {
+            debug!("ARMPageUpperDirectoryUnmap: cannot unmap if more than once cap exists");
+            global_ops!(current_syscall_error._type = seL4_RevokeFirst);
+            return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+        }
+        return Ok(mmu_invocation_t::PageUpperDirectoryUnmap);
     }
 
     // Return SYSCALL_ERROR if message is not ARMPageUpperDirectoryUnmap
     if unlikely(label != MessageLabel::ARMPageUpperDirectoryMap) {
         global_ops!(current_syscall_error._type = seL4_IllegalOperation);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     if unlikely(length < 2 || unsafe { current_extra_caps.excaprefs[0] == 0 }) {
         global_ops!(current_syscall_error._type = seL4_TruncatedMessage);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     if unlikely(cte.cap.get_pud_is_mapped() == 1) {
         global_ops!(current_syscall_error._type = seL4_InvalidCapability);
         global_ops!(current_syscall_error.invalidCapNumber = 0);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let vaddr = get_syscall_arg(0, buffer) & (!MASK!(PGD_INDEX_OFFSET));
     let pgd_cap = convert_to_mut_type_ref::<cap_t>(global_ops!(current_extra_caps.excaprefs[0]));
@@ -885,16 +753,16 @@ fn decode_page_upper_directory_invocation(
     if unlikely(!pgd_cap.is_valid_native_root()) {
         global_ops!(current_syscall_error._type = seL4_InvalidCapability);
         global_ops!(current_syscall_error.invalidCapNumber = 1);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let pgd = pgd_cap.get_pgd_base_ptr();
     let asid = pgd_cap.get_pgd_mapped_asid();
 
-    if unlikely(vaddr > USER_TOP) {
+    if unlikely(vaddr > vspace_top(asid)) {
         global_ops!(current_syscall_error._type = seL4_InvalidArgument);
         global_ops!(current_syscall_error.failedLookupWasSource = 0);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let find_ret = find_vspace_for_asid(asid);
@@ -902,13 +770,13 @@ fn decode_page_upper_directory_invocation(
     if unlikely(find_ret.status != exception_t::EXCEPTION_NONE) {
         global_ops!(current_syscall_error._type = seL4_FailedLookup);
         global_ops!(current_syscall_error.failedLookupWasSource = 0);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     // vspace_root is Some(_) when Exception is NONE
     if unlikely(find_ret.vspace_root.unwrap() as usize != pgd) {
         global_ops!(current_syscall_error._type = seL4_InvalidCapability);
         global_ops!(current_syscall_error.invalidCapNumber = 1);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     // Ensure that pgd is aligned 4K.
     assert!(pgd & MASK!(PAGE_BITS) == 0);
@@ -917,25 +785,21 @@ fn decode_page_upper_directory_invocation(
 
     if unlikely(ptr_to_ref(pgd_slot.pgdSlot).get_present()) {
         global_ops!(current_syscall_error._type = seL4_DeleteFirst);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
-    // TODO: make 0x3 in a pagetable-specific position
-    let pgde = PGDE::new_page(pptr_to_paddr(cte.cap.get_pud_base_ptr()), 0x3);
-    cte.cap.set_pud_is_mapped(1);
-    cte.cap.set_pud_mapped_asid(asid);
-    cte.cap.set_pud_mapped_address(vaddr);
-
-    get_currenct_thread().set_state(ThreadState::ThreadStateRestart);
-    *ptr_to_mut(pgd_slot.pgdSlot) = pgde;
-    log::warn!("Need to clean D-Cache using cleanByVA_PoU");
-    exception_t::EXCEPTION_NONE
+    Ok(mmu_invocation_t::PageUpperDirectoryMap {
+        pgd_slot: ptr_to_mut(pgd_slot.pgdSlot),
+        asid,
+        vaddr,
+    })
 }
+
 fn decode_page_directory_invocation(
     label: MessageLabel,
     length: usize,
     cte: &mut cte_t,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<mmu_invocation_t, exception_t> {
     /*
         if (invLabel == ARMPageDirectoryUnmap) {
             if (unlikely(!isFinalCapability(cte))) {
@@ -946,25 +810,28 @@ fn decode_page_directory_invocation(
             return performPageDirectoryInvocationUnmap(cap, cte);
         }
     */
-    // Call performPageDirectoryInvocationUnmap if message is unmap
+    // Unmap with performPageDirectoryInvocationUnmap if message is unmap
     if label == MessageLabel::ARMPageDirectoryUnmap {
-        log::warn!("Need to check is FinalCapability here");
-        get_currenct_thread().set_state(ThreadState::ThreadStateRestart);
-        unimplemented!("performPageDirectoryInvocationUnmap");
+        if unlikely(!cte.is_final_cap()) {
+            debug!("ARMPageDirectoryUnmap: cannot unmap if more than once cap exists");
+            global_ops!(current_syscall_error._type = seL4_RevokeFirst);
+            return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+        }
+        return Ok(mmu_invocation_t::PageDirectoryUnmap);
     }
     // Return SYSCALL_ERROR if message is not ARMPageDirectoryUnmap
     if unlikely(label != MessageLabel::ARMPageDirectoryMap) {
         global_ops!(current_syscall_error._type = seL4_IllegalOperation);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     if unlikely(length < 2 || global_ops!(current_extra_caps.excaprefs[0] == 0)) {
         global_ops!(current_syscall_error._type = seL4_TruncatedMessage);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     if unlikely(cte.cap.get_pd_is_mapped() == 1) {
         global_ops!(current_syscall_error._type = seL4_InvalidCapability);
         global_ops!(current_syscall_error.invalidCapNumber = 0);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let vaddr = get_syscall_arg(0, buffer) & (!MASK!(PUD_INDEX_OFFSET));
@@ -974,16 +841,16 @@ fn decode_page_directory_invocation(
     if unlikely(!vspace_root_cap.is_valid_native_root()) {
         global_ops!(current_syscall_error._type = seL4_InvalidCapability);
         global_ops!(current_syscall_error.invalidCapNumber = 1);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let vspace_root = vspace_root_cap.get_pgd_base_ptr();
     let asid = vspace_root_cap.get_pgd_mapped_asid();
 
-    if unlikely(vaddr > USER_TOP) {
+    if unlikely(vaddr > vspace_top(asid)) {
         global_ops!(current_syscall_error._type = seL4_InvalidArgument);
         global_ops!(current_syscall_error.failedLookupWasSource = 0);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let find_ret = find_vspace_for_asid(asid);
@@ -991,12 +858,12 @@ fn decode_page_directory_invocation(
     if unlikely(find_ret.status != exception_t::EXCEPTION_NONE) {
         global_ops!(current_syscall_error._type = seL4_FailedLookup);
         global_ops!(current_syscall_error.failedLookupWasSource = 0);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     if unlikely(find_ret.vspace_root.unwrap() as usize != vspace_root) {
         global_ops!(current_syscall_error._type = seL4_InvalidCapability);
         global_ops!(current_syscall_error.invalidCapNumber = 1);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let pud_slot = PGDE::new_from_pte(vspace_root).lookup_pud_slot(vaddr);
@@ -1004,23 +871,19 @@ fn decode_page_directory_invocation(
     if pud_slot.status != exception_t::EXCEPTION_NONE {
         global_ops!(current_syscall_error._type = seL4_FailedLookup);
         global_ops!(current_syscall_error.failedLookupWasSource = 0);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     if unlikely(
         ptr_to_ref(pud_slot.pudSlot).get_present() || ptr_to_ref(pud_slot.pudSlot).is_1g_page(),
     ) {
         global_ops!(current_syscall_error._type = seL4_DeleteFirst);
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
-    }
-    // TODO: make 0x3 in a pagetable-specific position
-    let pude = PUDE::new_page(pptr_to_paddr(cte.cap.get_pd_base_ptr()), 0x3);
-    cte.cap.set_pd_is_mapped(1);
-    cte.cap.set_pd_mapped_asid(asid);
-    cte.cap.set_pd_mapped_address(vaddr);
-    get_currenct_thread().set_state(ThreadState::ThreadStateRestart);
-    *ptr_to_mut(pud_slot.pudSlot) = pude;
-    log::warn!("Need to clean D-Cache using cleanByVA_PoU");
-    exception_t::EXCEPTION_NONE
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    Ok(mmu_invocation_t::PageDirectoryMap {
+        pud_slot: ptr_to_mut(pud_slot.pudSlot),
+        asid,
+        vaddr,
+    })
 }
 
 pub(crate) fn check_irq(irq: usize) -> exception_t {
@@ -1042,47 +905,159 @@ pub(crate) fn check_irq(irq: usize) -> exception_t {
 pub fn arch_decode_irq_control_invocation(
     label: MessageLabel,
     length: usize,
-    src_slot: &mut cte_t,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<irq_control_invocation_t, exception_t> {
+    #[cfg(feature = "ENABLE_GIC_ITS")]
+    if label == MessageLabel::ARMIRQIssueIRQHandlerMSI {
+        return decode_irq_issue_msi(length, buffer);
+    }
+    #[cfg(feature = "ENABLE_SMP")]
+    if label == MessageLabel::ARMIRQIssueIRQHandlerTriggerCore {
+        return decode_irq_issue_trigger_core(length, buffer);
+    }
     if label == MessageLabel::ARMIRQIssueIRQHandlerTrigger {
-        if length < 4 || get_extra_cap_by_index(0).is_none() {
-            unsafe {
-                current_syscall_error._type = seL4_TruncatedMessage;
-            }
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
+        decode_irq_issue_trigger(length, buffer)
+    } else {
+        unsafe {
+            current_syscall_error._type = seL4_IllegalOperation;
         }
-        let irq = get_syscall_arg(0, buffer);
-        let _trigger = get_syscall_arg(1, buffer) != 0;
-        let index = get_syscall_arg(2, buffer);
-        let depth = get_syscall_arg(3, buffer);
-        let cnode_cap = get_extra_cap_by_index(0).unwrap().cap;
-        let status = check_irq(irq);
-        if status != exception_t::EXCEPTION_NONE {
-            return status;
+        Err(exception_t::EXCEPTION_SYSCALL_ERROR)
+    }
+}
+
+/// Decode ARMIRQIssueIRQHandlerTrigger: the IRQ, edge triggered for a non-zero second argument,
+/// and the slot for the handler cap by index and depth in the CNode of extra cap 0.
+pub(crate) fn decode_irq_issue_trigger(
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<irq_control_invocation_t, exception_t> {
+    if length < 4 || get_extra_cap_by_index(0).is_none() {
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
         }
-        if is_irq_active(irq) {
-            unsafe {
-                current_syscall_error._type = seL4_RevokeFirst;
-            }
-            debug!("Rejecting request for IRQ {}. Already active.", irq);
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let irq = get_syscall_arg(0, buffer);
+    let trigger = get_syscall_arg(1, buffer) != 0;
+    let index = get_syscall_arg(2, buffer);
+    let depth = get_syscall_arg(3, buffer);
+    let cnode_cap = get_extra_cap_by_index(0).unwrap().cap;
+    let status = check_irq(irq);
+    if status != exception_t::EXCEPTION_NONE {
+        return Err(status);
+    }
+    if is_irq_active(irq) {
+        unsafe {
+            current_syscall_error._type = seL4_RevokeFirst;
         }
-        let lu_ret = lookupSlotForCNodeOp(false, &cnode_cap, index, depth);
-        if lu_ret.status != exception_t::EXCEPTION_NONE {
-            debug!("Target slot for new IRQ Handler cap invalid: IRQ {}.", irq);
-            return lu_ret.status;
+        debug!("Rejecting request for IRQ {}. Already active.", irq);
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let lu_ret = lookupSlotForCNodeOp(false, &cnode_cap, index, depth);
+    if lu_ret.status != exception_t::EXCEPTION_NONE {
+        debug!("Target slot for new IRQ Handler cap invalid: IRQ {}.", irq);
+        return Err(lu_ret.status);
+    }
+    let dest_slot = convert_to_mut_type_ref::<cte_t>(lu_ret.slot as usize);
+    if dest_slot.cap.get_cap_type() != CapTag::CapNullCap {
+        unsafe {
+            current_syscall_error._type = seL4_DeleteFirst;
         }
-        set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-        invoke_irq_control(
-            irq,
-            convert_to_mut_type_ref::<cte_t>(lu_ret.slot as usize),
-            src_slot,
-        )
-    } else {
+        debug!("Target slot for new IRQ Handler cap not empty");
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    Ok(irq_control_invocation_t {
+        irq,
+        trigger: Some(trigger),
+        msi: None,
+        core: None,
+        dest_slot,
+    })
+}
+
+/// Decode ARMIRQIssueIRQHandlerMSI: the slot for the handler cap by index and depth in the CNode
+/// of extra cap 0, the ITS device ID and event ID of the MSI, and the LPI to map it to.
+#[cfg(feature = "ENABLE_GIC_ITS")]
+fn decode_irq_issue_msi(
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<irq_control_invocation_t, exception_t> {
+    use crate::arch::arm_gic::gic_v3::its::{
+        its_device_available, its_event_mapped, its_max_devices, its_ready, ITS_DEVICE_EVENT_BITS,
+    };
+    use crate::config::{CONFIG_MAX_NUM_LPIS, LPI_IRQ_BASE};
+    use sel4_common::sel4_config::seL4_NotEnoughMemory;
+
+    if length < 5 || get_extra_cap_by_index(0).is_none() {
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let index = get_syscall_arg(0, buffer);
+    let depth = get_syscall_arg(1, buffer);
+    let device = get_syscall_arg(2, buffer);
+    let event = get_syscall_arg(3, buffer);
+    let lpi = get_syscall_arg(4, buffer);
+    let cnode_cap = get_extra_cap_by_index(0).unwrap().cap;
+    if !its_ready() {
+        debug!("IRQIssueIRQHandlerMSI: the GIC has no ITS the kernel can use.");
         unsafe {
             current_syscall_error._type = seL4_IllegalOperation;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let ranges = [
+        (device, its_max_devices()),
+        (event, BIT!(ITS_DEVICE_EVENT_BITS)),
+        (lpi, CONFIG_MAX_NUM_LPIS),
+    ];
+    if let Some(&(value, limit)) = ranges.iter().find(|(value, limit)| value >= limit) {
+        debug!(
+            "IRQIssueIRQHandlerMSI: {} is out of range [0..{}).",
+            value, limit
+        );
+        unsafe {
+            current_syscall_error._type = seL4_RangeError;
+            current_syscall_error.rangeErrorMin = 0;
+            current_syscall_error.rangeErrorMax = limit - 1;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let irq = LPI_IRQ_BASE + lpi;
+    if is_irq_active(irq) || its_event_mapped(device, event) {
+        unsafe {
+            current_syscall_error._type = seL4_RevokeFirst;
+        }
+        debug!("Rejecting request for LPI {}. Already active.", lpi);
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    if !its_device_available(device) {
+        debug!("IRQIssueIRQHandlerMSI: no free ITS device slot.");
+        unsafe {
+            current_syscall_error._type = seL4_NotEnoughMemory;
+            current_syscall_error.memoryLeft = 0;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
+    let lu_ret = lookupSlotForCNodeOp(false, &cnode_cap, index, depth);
+    if lu_ret.status != exception_t::EXCEPTION_NONE {
+        debug!("Target slot for new IRQ Handler cap invalid: LPI {}.", lpi);
+        return Err(lu_ret.status);
+    }
+    let dest_slot = convert_to_mut_type_ref::<cte_t>(lu_ret.slot as usize);
+    if dest_slot.cap.get_cap_type() != CapTag::CapNullCap {
+        unsafe {
+            current_syscall_error._type = seL4_DeleteFirst;
+        }
+        debug!("Target slot for new IRQ Handler cap not empty");
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    Ok(irq_control_invocation_t {
+        irq,
+        trigger: None,
+        msi: Some((device, event)),
+        core: None,
+        dest_slot,
+    })
 }
@@ -10,16 +10,16 @@ use sel4_common::arch::MessageLabel;
 use sel4_common::fault::lookup_fault_t;
 use sel4_common::sel4_config::{
     asidInvalid, asidLowBits, nASIDPools, seL4_AlignmentError, seL4_DeleteFirst, seL4_FailedLookup,
-    seL4_IllegalOperation, seL4_InvalidArgument, seL4_InvalidCapability, seL4_PageBits,
-    seL4_RevokeFirst, seL4_TruncatedMessage,
+    seL4_IllegalOperation, seL4_InvalidArgument, seL4_InvalidCapability, seL4_NotEnoughMemory,
+    seL4_PageBits, seL4_RevokeFirst, seL4_TruncatedMessage,
 };
 use sel4_common::structures::{exception_t, seL4_IPCBuffer};
 use sel4_common::utils::{convert_to_mut_type_ref, pageBitsForSize};
 use sel4_common::{BIT, MASK};
 use sel4_cspace::interface::{cap_t, cte_t, CapTag};
-use sel4_task::{get_currenct_thread, set_thread_state, ThreadState};
 use sel4_vspace::{
-    checkVPAlignment, find_vspace_for_asid, get_asid_pool_by_index, vm_attributes_t, PTE,
+    checkVPAlignment, find_vspace_for_asid, get_asid_pool_by_index, vm_attributes_t,
+    vspace_promote_check, vspace_promoted_slot, PTE, RISCV_GET_LVL_PGSIZE_BITS,
 };
 
 use crate::{
@@ -27,20 +27,32 @@ use crate::{
     kernel::boot::{current_lookup_fault, current_syscall_error, get_extra_cap_by_index},
     syscall::{
         get_syscall_arg,
-        invocation::invoke_mmu_op::{
-            invoke_asid_control, invoke_asid_pool, invoke_page_get_address, invoke_page_map,
-            invoke_page_table_map, invoke_page_table_unmap, invoke_page_unmap,
+        invocation::{
+            invoke_mmu_op::{invoke_mmu, mmu_invocation_t},
+            perform_invocation,
         },
         lookup_slot_for_cnode_op,
     },
 };
 
+#[cfg(feature = "ENABLE_RISCV_HYP")]
+use crate::arch::vcpu_t;
 use sel4_common::sel4_config::seL4_RangeError;
-
+#[cfg(feature = "ENABLE_SHARED_PT")]
+use sel4_vspace::{shared_pt_contains, shared_pt_count, shared_pt_has_room, shared_pt_level_bits};
+
+#[cfg(not(any(feature = "ENABLE_RISCV_AIA", feature = "ENABLE_RISCV_PLIC")))]
+use crate::config::maxIRQ;
+#[cfg(feature = "ENABLE_RISCV_AIA")]
+use crate::config::{AIA_IRQ_BASE, CONFIG_APLIC_NUM_SOURCES};
+#[cfg(feature = "ENABLE_RISCV_PLIC")]
+use crate::config::{CONFIG_PLIC_NUM_SOURCES, PLIC_IRQ_BASE};
+#[cfg(feature = "ENABLE_SMP")]
+use crate::syscall::invocation::decode::decode_irq_invocation::decode_irq_issue_trigger_core;
 use crate::{
-    config::{irqInvalid, maxIRQ},
+    config::irqInvalid,
     interrupt::is_irq_active,
-    syscall::{invocation::invoke_irq::invoke_irq_control, lookupSlotForCNodeOp},
+    syscall::{invocation::invoke_irq::irq_control_invocation_t, lookupSlotForCNodeOp},
 };
 
 pub fn decode_mmu_invocation(
@@ -50,15 +62,20 @@ pub fn decode_mmu_invocation(
     call: bool,
     buffer: Option<&seL4_IPCBuffer>,
 ) -> exception_t {
-    match slot.cap.get_cap_type() {
+    let decoded = match slot.cap.get_cap_type() {
         CapTag::CapPageTableCap => decode_page_table_invocation(label, length, slot, buffer),
         CapTag::CapFrameCap => decode_frame_invocation(label, length, slot, call, buffer),
         CapTag::CapASIDControlCap => decode_asid_control(label, length, buffer),
         CapTag::CapASIDPoolCap => decode_asid_pool(label, slot),
+        #[cfg(feature = "ENABLE_RISCV_HYP")]
+        CapTag::CapVCPUCap => {
+            return crate::arch::decode_vcpu_invocation(label, length, &slot.cap, call, buffer)
+        }
         _ => {
             panic!("Invalid arch cap type");
         }
-    }
+    };
+    perform_invocation(decoded, |inv| invoke_mmu(inv, slot))
 }
 
 fn decode_page_table_invocation(
@@ -66,17 +83,28 @@ fn decode_page_table_invocation(
     length: usize,
     cte: &mut cte_t,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<mmu_invocation_t, exception_t> {
     match label {
         MessageLabel::RISCVPageTableUnmap => decode_page_table_unmap(cte),
 
         MessageLabel::RISCVPageTableMap => decode_page_table_map(length, cte, buffer),
+        MessageLabel::RISCVVSpaceClean_Data
+        | MessageLabel::RISCVVSpaceInvalidate_Data
+        | MessageLabel::RISCVVSpaceCleanInvalidate_Data
+        | MessageLabel::RISCVVSpaceUnify_Instruction => {
+            decode_vspace_flush(label, length, cte, buffer)
+        }
+        MessageLabel::RISCVVSpacePromote => decode_vspace_promote(length, cte, buffer),
+        #[cfg(feature = "ENABLE_SHARED_PT")]
+        MessageLabel::RISCVPageTableMapShared | MessageLabel::RISCVPageTableUnmapShared => {
+            decode_page_table_shared(label, length, cte, buffer)
+        }
         _ => {
             debug!("RISCVPageTable: Illegal Operation");
             unsafe {
                 current_syscall_error._type = seL4_IllegalOperation;
             }
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
+            Err(exception_t::EXCEPTION_SYSCALL_ERROR)
         }
     }
 }
@@ -87,23 +115,204 @@ fn decode_frame_invocation(
     frame_slot: &mut cte_t,
     call: bool,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<mmu_invocation_t, exception_t> {
     match label {
         MessageLabel::RISCVPageMap => decode_frame_map(length, frame_slot, buffer),
-        MessageLabel::RISCVPageUnmap => {
-            set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-            invoke_page_unmap(frame_slot)
-        }
-        MessageLabel::RISCVPageGetAddress => {
-            set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-            invoke_page_get_address(frame_slot.cap.get_frame_base_ptr(), call)
+        MessageLabel::RISCVPageUnmap => Ok(mmu_invocation_t::PageUnmap),
+        MessageLabel::RISCVPageGetAddress => Ok(mmu_invocation_t::PageGetAddress { call }),
+        MessageLabel::RISCVPageClean_Data
+        | MessageLabel::RISCVPageInvalidate_Data
+        | MessageLabel::RISCVPageCleanInvalidate_Data
+        | MessageLabel::RISCVPageUnify_Instruction => {
+            decode_page_flush(label, length, frame_slot, buffer)
         }
         _ => {
             debug!("invalid operation label:{:?}", label);
             unsafe {
                 current_syscall_error._type = seL4_IllegalOperation;
             }
-            exception_t::EXCEPTION_SYSCALL_ERROR
+            Err(exception_t::EXCEPTION_SYSCALL_ERROR)
+        }
+    }
+}
+
+fn decode_page_flush(
+    label: MessageLabel,
+    length: usize,
+    frame_slot: &mut cte_t,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<mmu_invocation_t, exception_t> {
+    if unlikely(length < 2) {
+        debug!("RISCVPageFlush: Truncated message.");
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let asid = frame_slot.cap.get_frame_mapped_asid();
+    if unlikely(asid == asidInvalid) {
+        debug!("RISCVPageFlush: Frame is not mapped.");
+        unsafe {
+            current_syscall_error._type = seL4_IllegalOperation;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let find_ret = find_vspace_for_asid(asid);
+    if unlikely(find_ret.status != exception_t::EXCEPTION_NONE) {
+        debug!("RISCVPageFlush: No PageTable for ASID");
+        unsafe {
+            current_lookup_fault = find_ret.lookup_fault.unwrap();
+            current_syscall_error._type = seL4_FailedLookup;
+            current_syscall_error.failedLookupWasSource = 0;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let start = get_syscall_arg(0, buffer);
+    let end = get_syscall_arg(1, buffer);
+    if end <= start {
+        debug!("RISCVPageFlush: Invalid range");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidArgument;
+            current_syscall_error.invalidArgumentNumber = 1;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let page_size = BIT!(pageBitsForSize(frame_slot.cap.get_frame_size()));
+    if start >= page_size || end > page_size {
+        debug!("RISCVPageFlush: Requested range not inside page");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidArgument;
+            current_syscall_error.invalidArgumentNumber = 0;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    Ok(mmu_invocation_t::PageFlush { label, start, end })
+}
+
+fn decode_vspace_flush(
+    label: MessageLabel,
+    length: usize,
+    cte: &mut cte_t,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<mmu_invocation_t, exception_t> {
+    if unlikely(length < 2) {
+        debug!("RISCVVSpaceFlush: Truncated message.");
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let start = get_syscall_arg(0, buffer);
+    let end = get_syscall_arg(1, buffer);
+    if end <= start {
+        debug!("RISCVVSpaceFlush: Invalid range.");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidArgument;
+            current_syscall_error.invalidArgumentNumber = 1;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    if end > USER_TOP {
+        debug!("RISCVVSpaceFlush: Exceed the user addressable region.");
+        unsafe {
+            current_syscall_error._type = seL4_IllegalOperation;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let cap = cte.cap;
+    if unlikely(cap.get_pt_is_mapped() == 0) {
+        debug!("RISCVVSpaceFlush: Invalid VSpace Cap");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 0;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let find_ret = find_vspace_for_asid(cap.get_pt_mapped_asid());
+    if find_ret.status != exception_t::EXCEPTION_NONE {
+        debug!("RISCVVSpaceFlush: No VSpace for ASID");
+        unsafe {
+            current_lookup_fault = find_ret.lookup_fault.unwrap();
+            current_syscall_error._type = seL4_FailedLookup;
+            current_syscall_error.failedLookupWasSource = 0;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    if find_ret.vspace_root.unwrap() as usize != cap.get_pt_base_ptr() {
+        debug!("RISCVVSpaceFlush: Invalid VSpace Cap");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 0;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    Ok(mmu_invocation_t::VSpaceFlush { label, start, end })
+}
+
+fn decode_vspace_promote(
+    length: usize,
+    cte: &mut cte_t,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<mmu_invocation_t, exception_t> {
+    if unlikely(length < 1) {
+        debug!("RISCVVSpacePromote: Truncated message.");
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let vaddr = get_syscall_arg(0, buffer);
+    if unlikely(vaddr >= USER_TOP) {
+        debug!("RISCVVSpacePromote: Virtual address cannot be in kernel window.");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidArgument;
+            current_syscall_error.invalidArgumentNumber = 0;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let cap = cte.cap;
+    let asid = cap.get_pt_mapped_asid();
+    let find_ret = find_vspace_for_asid(asid);
+    if unlikely(
+        cap.get_pt_is_mapped() == 0
+            || find_ret.status != exception_t::EXCEPTION_NONE
+            || find_ret.vspace_root.unwrap() as usize != cap.get_pt_base_ptr(),
+    ) {
+        debug!("RISCVVSpacePromote: Invalid VSpace Cap");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 0;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let root = convert_to_mut_type_ref::<PTE>(cap.get_pt_base_ptr());
+    match vspace_promote_check(root, vaddr) {
+        Ok(promotion) => Ok(mmu_invocation_t::VSpacePromote { promotion, asid }),
+        Err(exception_t::EXCEPTION_LOOKUP_FAULT) => {
+            debug!("RISCVVSpacePromote: Region is not mapped through a PageTable");
+            unsafe {
+                current_lookup_fault =
+                    lookup_fault_t::new_missing_cap(RISCV_GET_LVL_PGSIZE_BITS(1));
+                current_syscall_error._type = seL4_FailedLookup;
+                current_syscall_error.failedLookupWasSource = 0;
+            }
+            Err(exception_t::EXCEPTION_SYSCALL_ERROR)
+        }
+        Err(exception_t::EXCEPTION_SYSCALL_ERROR) => {
+            debug!("RISCVVSpacePromote: Region is not contiguous with identical attributes");
+            unsafe {
+                current_syscall_error._type = seL4_InvalidArgument;
+                current_syscall_error.invalidArgumentNumber = 0;
+            }
+            Err(exception_t::EXCEPTION_SYSCALL_ERROR)
+        }
+        Err(_) => {
+            debug!("RISCVVSpacePromote: Too many promoted regions");
+            unsafe {
+                current_syscall_error._type = seL4_NotEnoughMemory;
+                current_syscall_error.memoryLeft = 0;
+            }
+            Err(exception_t::EXCEPTION_SYSCALL_ERROR)
         }
     }
 }
@@ -112,12 +321,12 @@ fn decode_asid_control(
     label: MessageLabel,
     length: usize,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<mmu_invocation_t, exception_t> {
     if label != MessageLabel::RISCVASIDControlMakePool {
         unsafe {
             current_syscall_error._type = seL4_IllegalOperation;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     if unlikely(
@@ -126,7 +335,7 @@ fn decode_asid_control(
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let index = get_syscall_arg(0, buffer);
     let depth = get_syscall_arg(1, buffer);
@@ -135,7 +344,7 @@ fn decode_asid_control(
     let root = get_extra_cap_by_index(1).unwrap().cap;
 
     let mut i = 0;
-    while get_asid_pool_by_index(i).is_some() {
+    while i < nASIDPools && get_asid_pool_by_index(i).is_some() {
         i += 1;
     }
 
@@ -143,7 +352,7 @@ fn decode_asid_control(
         unsafe {
             current_syscall_error._type = seL4_DeleteFirst;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let asid_base = i << asidLowBits;
@@ -155,7 +364,7 @@ fn decode_asid_control(
             current_syscall_error._type = seL4_InvalidCapability;
             current_syscall_error.invalidCapNumber = 1;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let status = parent_slot.ensure_no_children();
@@ -163,13 +372,13 @@ fn decode_asid_control(
         unsafe {
             current_syscall_error._type = seL4_RevokeFirst;
         }
-        return status;
+        return Err(status);
     }
 
     let frame = untyped_cap.get_untyped_ptr();
     let lu_ret = lookup_slot_for_cnode_op(false, &root, index, depth);
     if lu_ret.status != exception_t::EXCEPTION_NONE {
-        return lu_ret.status;
+        return Err(lu_ret.status);
     }
 
     let dest_slot = convert_to_mut_type_ref::<cte_t>(lu_ret.slot as usize);
@@ -178,182 +387,206 @@ fn decode_asid_control(
         unsafe {
             current_syscall_error._type = seL4_DeleteFirst;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
-    set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-    invoke_asid_control(frame, dest_slot, parent_slot, asid_base)
+    Ok(mmu_invocation_t::ASIDControl {
+        frame,
+        parent_slot,
+        dest_slot,
+        asid_base,
+    })
 }
 
-fn decode_asid_pool(label: MessageLabel, cte: &mut cte_t) -> exception_t {
-    // debug!("in cap_asid_pool_cap");
+fn decode_asid_pool(label: MessageLabel, cte: &mut cte_t) -> Result<mmu_invocation_t, exception_t> {
     if label != MessageLabel::RISCVASIDPoolAssign {
         unsafe {
             current_syscall_error._type = seL4_IllegalOperation;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     if unlikely(get_extra_cap_by_index(0).is_none()) {
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let vspace_slot = get_extra_cap_by_index(0).unwrap();
     let vspace_cap = vspace_slot.cap;
 
+    // A VCPU takes the place of the vspace root to have its G-stage root assigned
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    let is_vcpu = vspace_cap.get_cap_type() == CapTag::CapVCPUCap
+        && convert_to_mut_type_ref::<vcpu_t>(vspace_cap.get_vcpu_ptr()).asid == asidInvalid;
+    #[cfg(not(feature = "ENABLE_RISCV_HYP"))]
+    let is_vcpu = false;
     if unlikely(
-        vspace_cap.get_cap_type() != CapTag::CapPageTableCap || vspace_cap.get_pt_is_mapped() != 0,
+        !is_vcpu
+            && (vspace_cap.get_cap_type() != CapTag::CapPageTableCap
+                || vspace_cap.get_pt_is_mapped() != 0),
     ) {
         debug!("RISCVASIDPool: Invalid vspace root.");
         unsafe {
             current_syscall_error._type = seL4_InvalidCapability;
             current_syscall_error.invalidCapNumber = 1;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let asid = cte.cap.get_asid_base();
-    if let Some(pool) = get_asid_pool_by_index(asid >> asidLowBits) {
-        if pool.get_ptr() != cte.cap.get_asid_pool() {
+    let pool = match get_asid_pool_by_index(asid >> asidLowBits) {
+        Some(pool) => pool,
+        None => {
             unsafe {
-                current_syscall_error._type = seL4_InvalidCapability;
-                current_syscall_error.invalidCapNumber = 0;
+                current_syscall_error._type = seL4_FailedLookup;
+                current_syscall_error.failedLookupWasSource = 0;
+                current_lookup_fault = lookup_fault_t::new_root_invalid();
             }
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
+            return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
         }
-
-        let mut i = 0;
-        while i < BIT!(asidLowBits) && (asid + i == 0 || pool.get_vspace_by_index(i).is_some()) {
-            i += 1;
+    };
+    if pool.get_ptr() != cte.cap.get_asid_pool() {
+        unsafe {
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 0;
         }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
 
-        if i == BIT!(asidLowBits) {
-            unsafe {
-                current_syscall_error._type = seL4_DeleteFirst;
-            }
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
-        }
+    let mut i = 0;
+    while i < BIT!(asidLowBits) && (asid + i == 0 || pool.get_vspace_by_index(i).is_some()) {
+        i += 1;
+    }
 
-        set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-        // performASIDPoolInvocation(asid + i, pool as *mut asid_pool_t, vspace_slot as *mut cte_t)
-        invoke_asid_pool(asid + i, pool, vspace_slot)
-    } else {
+    if i == BIT!(asidLowBits) {
         unsafe {
-            current_syscall_error._type = seL4_FailedLookup;
-            current_syscall_error.failedLookupWasSource = 0;
-            current_lookup_fault = lookup_fault_t::new_root_invalid();
+            current_syscall_error._type = seL4_DeleteFirst;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
+
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    if is_vcpu {
+        return Ok(mmu_invocation_t::ASIDPoolAssignVCPU {
+            asid: asid + i,
+            pool,
+            vcpu: convert_to_mut_type_ref::<vcpu_t>(vspace_cap.get_vcpu_ptr()),
+        });
+    }
+    Ok(mmu_invocation_t::ASIDPoolAssign {
+        asid: asid + i,
+        pool,
+        vspace_slot,
+    })
 }
 
 fn decode_frame_map(
     length: usize,
     frame_slot: &mut cte_t,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<mmu_invocation_t, exception_t> {
     if length < 3 || get_extra_cap_by_index(0).is_none() {
         debug!("RISCVPageMap: Truncated message.");
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let vaddr = get_syscall_arg(0, buffer);
     let w_rights_mask = get_syscall_arg(1, buffer);
-    let attr = vm_attributes_t::from_word(get_syscall_arg(2, buffer));
+    let mut attr = vm_attributes_t::from_word(get_syscall_arg(2, buffer));
+    if frame_slot.cap.is_device_frame() {
+        attr = attr.for_device_frame();
+    }
     let lvl1pt_cap = get_extra_cap_by_index(0).unwrap().cap;
-    if let Some((lvl1pt, asid)) = get_vspace(&lvl1pt_cap) {
-        let frame_size = frame_slot.cap.get_frame_size();
-        let vtop = vaddr + BIT!(pageBitsForSize(frame_size)) - 1;
-        if unlikely(vtop >= USER_TOP) {
-            unsafe {
-                current_syscall_error._type = seL4_InvalidArgument;
-                current_syscall_error.invalidCapNumber = 0;
-            }
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
+    let (lvl1pt, asid) = get_vspace(&lvl1pt_cap).ok_or(exception_t::EXCEPTION_SYSCALL_ERROR)?;
+    let frame_size = frame_slot.cap.get_frame_size();
+    let vtop = vaddr + BIT!(pageBitsForSize(frame_size)) - 1;
+    if unlikely(vtop >= USER_TOP) {
+        unsafe {
+            current_syscall_error._type = seL4_InvalidArgument;
+            current_syscall_error.invalidCapNumber = 0;
         }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
 
-        if unlikely(!checkVPAlignment(frame_size, vaddr)) {
-            unsafe {
-                current_syscall_error._type = seL4_AlignmentError;
-            }
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
+    if unlikely(!checkVPAlignment(frame_size, vaddr)) {
+        unsafe {
+            current_syscall_error._type = seL4_AlignmentError;
         }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
 
-        let lu_ret = lvl1pt.lookup_pt_slot(vaddr);
-        if lu_ret.ptBitsLeft != pageBitsForSize(frame_size) {
-            unsafe {
-                current_lookup_fault = lookup_fault_t::new_missing_cap(lu_ret.ptBitsLeft);
-                current_syscall_error._type = seL4_FailedLookup;
-                current_syscall_error.failedLookupWasSource = false as usize;
-            }
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
+    let mut lu_ret = lvl1pt.lookup_pt_slot(vaddr);
+    if let Some(slot) = vspace_promoted_slot(asid, vaddr) {
+        // invoke_page_map demotes the region, check the 4K slot it maps then
+        lu_ret.ptSlot = slot;
+        lu_ret.ptBitsLeft = seL4_PageBits;
+    }
+    if lu_ret.ptBitsLeft != pageBitsForSize(frame_size) {
+        unsafe {
+            current_lookup_fault = lookup_fault_t::new_missing_cap(lu_ret.ptBitsLeft);
+            current_syscall_error._type = seL4_FailedLookup;
+            current_syscall_error.failedLookupWasSource = false as usize;
         }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
 
-        let pt_slot = convert_to_mut_type_ref::<PTE>(lu_ret.ptSlot as usize);
-        let frame_asid = frame_slot.cap.get_frame_mapped_asid();
-        if frame_asid != asidInvalid {
-            if frame_asid != asid {
-                debug!("RISCVPageMap: Attempting to remap a frame that does not belong to the passed address space");
-                unsafe {
-                    current_syscall_error._type = seL4_InvalidCapability;
-                    current_syscall_error.invalidCapNumber = 1;
-                }
-                return exception_t::EXCEPTION_SYSCALL_ERROR;
+    let pt_slot = convert_to_mut_type_ref::<PTE>(lu_ret.ptSlot as usize);
+    let frame_asid = frame_slot.cap.get_frame_mapped_asid();
+    if frame_asid != asidInvalid {
+        if frame_asid != asid {
+            debug!("RISCVPageMap: Attempting to remap a frame that does not belong to the passed address space");
+            unsafe {
+                current_syscall_error._type = seL4_InvalidCapability;
+                current_syscall_error.invalidCapNumber = 1;
             }
+            return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+        }
 
-            if frame_slot.cap.get_frame_mapped_address() != vaddr {
-                debug!("RISCVPageMap: attempting to map frame into multiple addresses");
-                unsafe {
-                    current_syscall_error._type = seL4_InvalidArgument;
-                    current_syscall_error.invalidArgumentNumber = 0;
-                }
-                return exception_t::EXCEPTION_SYSCALL_ERROR;
+        if frame_slot.cap.get_frame_mapped_address() != vaddr {
+            debug!("RISCVPageMap: attempting to map frame into multiple addresses");
+            unsafe {
+                current_syscall_error._type = seL4_InvalidArgument;
+                current_syscall_error.invalidArgumentNumber = 0;
             }
+            return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+        }
 
-            if pt_slot.is_pte_table() {
-                debug!("RISCVPageMap: no mapping to remap.");
-                unsafe {
-                    current_syscall_error._type = seL4_DeleteFirst;
-                }
-                return exception_t::EXCEPTION_SYSCALL_ERROR;
-            }
-        } else {
-            if pt_slot.get_valid() != 0 {
-                debug!("Virtual address already mapped");
-                unsafe {
-                    current_syscall_error._type = seL4_DeleteFirst;
-                }
-                return exception_t::EXCEPTION_SYSCALL_ERROR;
+        if pt_slot.is_pte_table() {
+            debug!("RISCVPageMap: no mapping to remap.");
+            unsafe {
+                current_syscall_error._type = seL4_DeleteFirst;
             }
+            return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
         }
-        invoke_page_map(
-            &mut frame_slot.cap.clone(),
-            w_rights_mask,
-            vaddr,
-            asid,
-            attr,
-            pt_slot,
-            frame_slot,
-        )
-    } else {
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+    } else if pt_slot.get_valid() != 0 {
+        debug!("Virtual address already mapped");
+        unsafe {
+            current_syscall_error._type = seL4_DeleteFirst;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
+    Ok(mmu_invocation_t::PageMap {
+        w_rights_mask,
+        vaddr,
+        asid,
+        attr,
+        pt_slot,
+    })
 }
 
-fn decode_page_table_unmap(pt_cte: &mut cte_t) -> exception_t {
+fn decode_page_table_unmap(pt_cte: &mut cte_t) -> Result<mmu_invocation_t, exception_t> {
     if !pt_cte.is_final_cap() {
         debug!("RISCVPageTableUnmap: cannot unmap if more than once cap exists");
         unsafe {
             current_syscall_error._type = seL4_RevokeFirst;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
-    let cap = &mut pt_cte.cap;
+    let cap = &pt_cte.cap;
     if cap.get_pt_is_mapped() != 0 {
         let asid = cap.get_pt_mapped_asid();
         let find_ret = find_vspace_for_asid(asid);
@@ -365,37 +598,43 @@ fn decode_page_table_unmap(pt_cte: &mut cte_t) -> exception_t {
             unsafe {
                 current_syscall_error._type = seL4_RevokeFirst;
             }
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
-        } else {
+            return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+        } else if let Some(lookup_fault) = find_ret.lookup_fault {
             unsafe {
-                current_lookup_fault = find_ret.lookup_fault.unwrap();
+                current_lookup_fault = lookup_fault;
+            }
+        }
+        #[cfg(feature = "ENABLE_SHARED_PT")]
+        if shared_pt_count(pte_ptr as usize) != 0 {
+            debug!("RISCVPageTableUnmap: PageTable is still shared with other vspaces");
+            unsafe {
+                current_syscall_error._type = seL4_DeleteFirst;
             }
+            return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
         }
     }
-    set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-    return invoke_page_table_unmap(cap);
+    Ok(mmu_invocation_t::PageTableUnmap)
 }
 
 fn decode_page_table_map(
     length: usize,
     pt_cte: &mut cte_t,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<mmu_invocation_t, exception_t> {
     if unlikely(length < 2 || get_extra_cap_by_index(0).is_none()) {
         debug!("RISCVPageTableMap: truncated message");
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
-    let cap = &mut pt_cte.cap;
-    if unlikely(cap.get_pt_is_mapped() != 0) {
+    if unlikely(pt_cte.cap.get_pt_is_mapped() != 0) {
         debug!("RISCVPageTable: PageTable is already mapped.");
         unsafe {
             current_syscall_error._type = seL4_InvalidCapability;
             current_syscall_error.invalidCapNumber = 0;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let vaddr = get_syscall_arg(0, buffer);
@@ -405,42 +644,146 @@ fn decode_page_table_map(
             current_syscall_error._type = seL4_InvalidArgument;
             current_syscall_error.invalidCapNumber = 0;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let lvl1pt_cap = get_extra_cap_by_index(0).unwrap().cap;
+    let (lvl1pt, asid) = get_vspace(&lvl1pt_cap).ok_or(exception_t::EXCEPTION_SYSCALL_ERROR)?;
+    let lu_ret = lvl1pt.lookup_pt_slot(vaddr);
+    let pt_slot = convert_to_mut_type_ref::<PTE>(lu_ret.ptSlot as usize);
+    if lu_ret.ptBitsLeft == seL4_PageBits || pt_slot.get_valid() != 0 {
+        debug!("RISCVPageTableMap: All objects mapped at this address");
+        unsafe {
+            current_syscall_error._type = seL4_DeleteFirst;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    Ok(mmu_invocation_t::PageTableMap {
+        pt_slot,
+        asid,
+        vaddr: vaddr & !MASK!(lu_ret.ptBitsLeft),
+    })
+}
 
-    if let Some((lvl1pt, asid)) = get_vspace(&lvl1pt_cap) {
-        let lu_ret = lvl1pt.lookup_pt_slot(vaddr);
-        let lu_slot = convert_to_mut_type_ref::<PTE>(lu_ret.ptSlot as usize);
-        #[cfg(target_arch = "riscv64")]
-        if lu_ret.ptBitsLeft == seL4_PageBits || lu_slot.get_valid() != 0 {
-            debug!("RISCVPageTableMap: All objects mapped at this address");
+/// Link an already mapped page table into, or out of, a second vspace.
+#[cfg(feature = "ENABLE_SHARED_PT")]
+fn decode_page_table_shared(
+    label: MessageLabel,
+    length: usize,
+    pt_cte: &mut cte_t,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<mmu_invocation_t, exception_t> {
+    if unlikely(length < 1 || get_extra_cap_by_index(0).is_none()) {
+        debug!("RISCVPageTableShared: truncated message");
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let cap = pt_cte.cap;
+    let table = cap.get_pt_base_ptr();
+    let find_ret = find_vspace_for_asid(cap.get_pt_mapped_asid());
+    let level_bits = if cap.get_pt_is_mapped() != 0
+        && find_ret.status == exception_t::EXCEPTION_NONE
+        && find_ret.vspace_root.unwrap() as usize != table
+    {
+        let root = convert_to_mut_type_ref::<PTE>(find_ret.vspace_root.unwrap() as usize);
+        shared_pt_level_bits(root, cap.get_pt_mapped_address(), table)
+    } else {
+        None
+    };
+    let level_bits = match level_bits {
+        Some(bits) => bits,
+        None => {
+            debug!("RISCVPageTableShared: PageTable must be mapped and not a vspace root.");
             unsafe {
-                current_syscall_error._type = seL4_DeleteFirst;
+                current_syscall_error._type = seL4_InvalidCapability;
+                current_syscall_error.invalidCapNumber = 0;
             }
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
+            return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
         }
-        set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-        return invoke_page_table_map(cap, lu_slot, asid, vaddr & !MASK!(lu_ret.ptBitsLeft));
-    } else {
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+    };
+
+    let vaddr = get_syscall_arg(0, buffer) & !MASK!(level_bits);
+    if unlikely(vaddr >= USER_TOP) {
+        debug!("RISCVPageTableShared: Virtual address cannot be in kernel window.");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidArgument;
+            current_syscall_error.invalidArgumentNumber = 0;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
-}
+    let lvl1pt_cap = get_extra_cap_by_index(0).unwrap().cap;
+    let (lvl1pt, asid) = get_vspace(&lvl1pt_cap).ok_or(exception_t::EXCEPTION_SYSCALL_ERROR)?;
 
-fn get_vspace(lvl1pt_cap: &cap_t) -> Option<(&mut PTE, usize)> {
-    if lvl1pt_cap.get_cap_type() != CapTag::CapPageTableCap
-        || lvl1pt_cap.get_pt_is_mapped() == asidInvalid
-    {
-        debug!("RISCVMMUInvocation: Invalid top-level PageTable.");
+    if label == MessageLabel::RISCVPageTableUnmapShared {
+        if !shared_pt_contains(table, asid, vaddr) {
+            debug!("RISCVPageTableUnmapShared: PageTable is not shared at this address");
+            unsafe {
+                current_syscall_error._type = seL4_InvalidArgument;
+                current_syscall_error.invalidArgumentNumber = 0;
+            }
+            return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+        }
+        return Ok(mmu_invocation_t::PageTableUnmapShared { asid, vaddr });
+    }
+
+    let lu_ret = lvl1pt.lookup_pt_slot(vaddr);
+    let pt_slot = convert_to_mut_type_ref::<PTE>(lu_ret.ptSlot as usize);
+    if lu_ret.ptBitsLeft > level_bits {
+        debug!("RISCVPageTableMapShared: Missing intermediate PageTable");
         unsafe {
-            current_syscall_error._type = seL4_InvalidCapability;
-            current_syscall_error.invalidCapNumber = 1;
+            current_lookup_fault = lookup_fault_t::new_missing_cap(lu_ret.ptBitsLeft);
+            current_syscall_error._type = seL4_FailedLookup;
+            current_syscall_error.failedLookupWasSource = 0;
         }
-        return None;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    if lu_ret.ptBitsLeft < level_bits || pt_slot.get_valid() != 0 {
+        debug!("RISCVPageTableMapShared: All objects mapped at this address");
+        unsafe {
+            current_syscall_error._type = seL4_DeleteFirst;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
+    if !shared_pt_has_room() {
+        debug!("RISCVPageTableMapShared: Too many shared mappings");
+        unsafe {
+            current_syscall_error._type = seL4_NotEnoughMemory;
+            current_syscall_error.memoryLeft = 0;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    Ok(mmu_invocation_t::PageTableMapShared {
+        pt_slot,
+        asid,
+        vaddr,
+    })
+}
 
-    let lvl1pt = convert_to_mut_type_ref::<PTE>(lvl1pt_cap.get_pt_base_ptr());
-    let asid = lvl1pt_cap.get_pt_mapped_asid();
+/// The vspace root of `lvl1pt_cap` and its ASID. The cap of a VCPU stands for its G-stage root.
+fn get_vspace(lvl1pt_cap: &cap_t) -> Option<(&mut PTE, usize)> {
+    let (lvl1pt, asid) = match lvl1pt_cap.get_cap_type() {
+        CapTag::CapPageTableCap if lvl1pt_cap.get_pt_is_mapped() != asidInvalid => (
+            convert_to_mut_type_ref::<PTE>(lvl1pt_cap.get_pt_base_ptr()),
+            lvl1pt_cap.get_pt_mapped_asid(),
+        ),
+        #[cfg(feature = "ENABLE_RISCV_HYP")]
+        CapTag::CapVCPUCap => {
+            let vcpu = convert_to_mut_type_ref::<vcpu_t>(lvl1pt_cap.get_vcpu_ptr());
+            (
+                convert_to_mut_type_ref::<PTE>(vcpu.gstage.as_vspace_root() as usize),
+                vcpu.asid,
+            )
+        }
+        _ => {
+            debug!("RISCVMMUInvocation: Invalid top-level PageTable.");
+            unsafe {
+                current_syscall_error._type = seL4_InvalidCapability;
+                current_syscall_error.invalidCapNumber = 1;
+            }
+            return None;
+        }
+    };
 
     let find_ret = find_vspace_for_asid(asid);
     if find_ret.status != exception_t::EXCEPTION_NONE {
@@ -464,12 +807,29 @@ fn get_vspace(lvl1pt_cap: &cap_t) -> Option<(&mut PTE, usize)> {
     Some((lvl1pt, asid))
 }
 
+/// The highest IRQ IRQControl issues by number, with `ENABLE_RISCV_AIA` the last wired APLIC
+/// source, its MSIs are issued by RISCVIRQIssueIRQHandlerMSI, and with `ENABLE_RISCV_PLIC` the
+/// last PLIC source.
+#[cfg(feature = "ENABLE_RISCV_AIA")]
+const MAX_ISSUE_IRQ: usize = AIA_IRQ_BASE + CONFIG_APLIC_NUM_SOURCES - 1;
+#[cfg(feature = "ENABLE_RISCV_PLIC")]
+const MAX_ISSUE_IRQ: usize = PLIC_IRQ_BASE + CONFIG_PLIC_NUM_SOURCES - 1;
+#[cfg(not(any(feature = "ENABLE_RISCV_AIA", feature = "ENABLE_RISCV_PLIC")))]
+const MAX_ISSUE_IRQ: usize = maxIRQ;
+
 pub(crate) fn check_irq(irq: usize) -> exception_t {
-    if irq > maxIRQ || irq == irqInvalid {
+    // IMSIC identity 0 and PLIC source 0 are never signalled
+    #[cfg(feature = "ENABLE_RISCV_AIA")]
+    let never_signalled = irq == AIA_IRQ_BASE;
+    #[cfg(feature = "ENABLE_RISCV_PLIC")]
+    let never_signalled = irq == PLIC_IRQ_BASE;
+    #[cfg(not(any(feature = "ENABLE_RISCV_AIA", feature = "ENABLE_RISCV_PLIC")))]
+    let never_signalled = false;
+    if irq > MAX_ISSUE_IRQ || irq == irqInvalid || never_signalled {
         unsafe {
             current_syscall_error._type = seL4_RangeError;
             current_syscall_error.rangeErrorMin = 1;
-            current_syscall_error.rangeErrorMax = maxIRQ;
+            current_syscall_error.rangeErrorMax = MAX_ISSUE_IRQ;
             debug!(
                 "Rejecting request for IRQ {}. IRQ is out of range [1..maxIRQ].",
                 irq
@@ -483,47 +843,133 @@ pub(crate) fn check_irq(irq: usize) -> exception_t {
 pub fn arch_decode_irq_control_invocation(
     label: MessageLabel,
     length: usize,
-    src_slot: &mut cte_t,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<irq_control_invocation_t, exception_t> {
+    #[cfg(feature = "ENABLE_RISCV_AIA")]
+    if label == MessageLabel::RISCVIRQIssueIRQHandlerMSI {
+        return decode_irq_issue_msi(length, buffer);
+    }
+    #[cfg(feature = "ENABLE_SMP")]
+    if label == MessageLabel::RISCVIRQIssueIRQHandlerTriggerCore {
+        return decode_irq_issue_trigger_core(length, buffer);
+    }
     if label == MessageLabel::RISCVIRQIssueIRQHandlerTrigger {
-        if length < 4 || get_extra_cap_by_index(0).is_none() {
-            unsafe {
-                current_syscall_error._type = seL4_TruncatedMessage;
-            }
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
+        decode_irq_issue_trigger(length, buffer)
+    } else {
+        unsafe {
+            current_syscall_error._type = seL4_IllegalOperation;
         }
-        let irq = get_syscall_arg(0, buffer);
-        let _trigger = get_syscall_arg(1, buffer) != 0;
-        let index = get_syscall_arg(2, buffer);
-        let depth = get_syscall_arg(3, buffer);
-        let cnode_cap = get_extra_cap_by_index(0).unwrap().cap;
-        let status = check_irq(irq);
-        if status != exception_t::EXCEPTION_NONE {
-            return status;
+        Err(exception_t::EXCEPTION_SYSCALL_ERROR)
+    }
+}
+
+/// Decode RISCVIRQIssueIRQHandlerTrigger: the IRQ, edge triggered for a non-zero second argument,
+/// and the slot for the handler cap by index and depth in the CNode of extra cap 0.
+pub(crate) fn decode_irq_issue_trigger(
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<irq_control_invocation_t, exception_t> {
+    if length < 4 || get_extra_cap_by_index(0).is_none() {
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
         }
-        if is_irq_active(irq) {
-            unsafe {
-                current_syscall_error._type = seL4_RevokeFirst;
-            }
-            debug!("Rejecting request for IRQ {}. Already active.", irq);
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let irq = get_syscall_arg(0, buffer);
+    let trigger = get_syscall_arg(1, buffer) != 0;
+    let index = get_syscall_arg(2, buffer);
+    let depth = get_syscall_arg(3, buffer);
+    let cnode_cap = get_extra_cap_by_index(0).unwrap().cap;
+    let status = check_irq(irq);
+    if status != exception_t::EXCEPTION_NONE {
+        return Err(status);
+    }
+    if is_irq_active(irq) {
+        unsafe {
+            current_syscall_error._type = seL4_RevokeFirst;
         }
-        let lu_ret = lookupSlotForCNodeOp(false, &cnode_cap, index, depth);
-        if lu_ret.status != exception_t::EXCEPTION_NONE {
-            debug!("Target slot for new IRQ Handler cap invalid: IRQ {}.", irq);
-            return lu_ret.status;
-        }
-        set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-        invoke_irq_control(
-            irq,
-            convert_to_mut_type_ref::<cte_t>(lu_ret.slot as usize),
-            src_slot,
-        )
-    } else {
+        debug!("Rejecting request for IRQ {}. Already active.", irq);
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let lu_ret = lookupSlotForCNodeOp(false, &cnode_cap, index, depth);
+    if lu_ret.status != exception_t::EXCEPTION_NONE {
+        debug!("Target slot for new IRQ Handler cap invalid: IRQ {}.", irq);
+        return Err(lu_ret.status);
+    }
+    let dest_slot = convert_to_mut_type_ref::<cte_t>(lu_ret.slot as usize);
+    if dest_slot.cap.get_cap_type() != CapTag::CapNullCap {
         unsafe {
-            current_syscall_error._type = seL4_IllegalOperation;
+            current_syscall_error._type = seL4_DeleteFirst;
+        }
+        debug!("Target slot for new IRQ Handler cap not empty");
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    Ok(irq_control_invocation_t {
+        irq,
+        trigger: Some(trigger),
+        msi: None,
+        core: None,
+        dest_slot,
+    })
+}
+
+/// Decode RISCVIRQIssueIRQHandlerMSI: the slot for the handler cap by index and depth in the CNode
+/// of extra cap 0, and the IMSIC identity devices write to the interrupt file of the boot hart.
+#[cfg(feature = "ENABLE_RISCV_AIA")]
+fn decode_irq_issue_msi(
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<irq_control_invocation_t, exception_t> {
+    use crate::config::CONFIG_IMSIC_NUM_IDS;
+
+    if length < 3 || get_extra_cap_by_index(0).is_none() {
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let index = get_syscall_arg(0, buffer);
+    let depth = get_syscall_arg(1, buffer);
+    let id = get_syscall_arg(2, buffer);
+    let cnode_cap = get_extra_cap_by_index(0).unwrap().cap;
+    if id < CONFIG_APLIC_NUM_SOURCES || id >= CONFIG_IMSIC_NUM_IDS {
+        debug!(
+            "IRQIssueIRQHandlerMSI: {} is out of range [{}..{}).",
+            id, CONFIG_APLIC_NUM_SOURCES, CONFIG_IMSIC_NUM_IDS
+        );
+        unsafe {
+            current_syscall_error._type = seL4_RangeError;
+            current_syscall_error.rangeErrorMin = CONFIG_APLIC_NUM_SOURCES;
+            current_syscall_error.rangeErrorMax = CONFIG_IMSIC_NUM_IDS - 1;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let irq = AIA_IRQ_BASE + id;
+    if is_irq_active(irq) {
+        unsafe {
+            current_syscall_error._type = seL4_RevokeFirst;
+        }
+        debug!("Rejecting request for MSI {}. Already active.", id);
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let lu_ret = lookupSlotForCNodeOp(false, &cnode_cap, index, depth);
+    if lu_ret.status != exception_t::EXCEPTION_NONE {
+        debug!("Target slot for new IRQ Handler cap invalid: MSI {}.", id);
+        return Err(lu_ret.status);
+    }
+    let dest_slot = convert_to_mut_type_ref::<cte_t>(lu_ret.slot as usize);
+    if dest_slot.cap.get_cap_type() != CapTag::CapNullCap {
+        unsafe {
+            current_syscall_error._type = seL4_DeleteFirst;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        debug!("Target slot for new IRQ Handler cap not empty");
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
+    Ok(irq_control_invocation_t {
+        irq,
+        trigger: None,
+        msi: None,
+        core: None,
+        dest_slot,
+    })
 }
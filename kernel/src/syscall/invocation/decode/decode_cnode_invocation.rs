@@ -13,7 +13,11 @@ use sel4_cspace::interface::{cap_t, cte_t, CapTag};
 
 use crate::{
     kernel::boot::{current_lookup_fault, current_syscall_error, get_extra_cap_by_index},
-    syscall::{get_syscall_arg, invocation::invoke_cnode::*, lookup_slot_for_cnode_op},
+    syscall::{
+        get_syscall_arg,
+        invocation::{invoke_cnode::*, perform_invocation},
+        lookup_slot_for_cnode_op, mask_cap_rights,
+    },
 };
 
 pub fn decode_cnode_invocation(
@@ -22,12 +26,25 @@ pub fn decode_cnode_invocation(
     cap: &cap_t,
     buffer: Option<&seL4_IPCBuffer>,
 ) -> exception_t {
-    if invLabel < MessageLabel::CNodeRevoke || invLabel > MessageLabel::CNodeSaveCaller {
+    perform_invocation(decode_cnode(invLabel, length, cap, buffer), invoke_cnode)
+}
+
+fn decode_cnode(
+    invLabel: MessageLabel,
+    length: usize,
+    cap: &cap_t,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<cnode_invocation_t, exception_t> {
+    #[cfg(not(feature = "ENABLE_MCS"))]
+    let last_label = MessageLabel::CNodeSaveCaller;
+    #[cfg(feature = "ENABLE_MCS")]
+    let last_label = MessageLabel::CNodeRotate;
+    if invLabel < MessageLabel::CNodeRevoke || invLabel > last_label {
         debug!("CNodeCap: Illegal Operation attempted.");
         unsafe {
             current_syscall_error._type = seL4_IllegalOperation;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     if length < 2 {
@@ -35,7 +52,7 @@ pub fn decode_cnode_invocation(
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let index = get_syscall_arg(0, buffer);
     let w_bits = get_syscall_arg(1, buffer);
@@ -43,7 +60,7 @@ pub fn decode_cnode_invocation(
 
     if lu_ret.status != exception_t::EXCEPTION_NONE {
         debug!("CNode operation: Target slot invalid.");
-        return lu_ret.status;
+        return Err(lu_ret.status);
     }
     let dest_slot = convert_to_mut_type_ref::<cte_t>(lu_ret.slot as usize);
     match invLabel {
@@ -51,12 +68,32 @@ pub fn decode_cnode_invocation(
         | MessageLabel::CNodeMint
         | MessageLabel::CNodeMove
         | MessageLabel::CNodeMutate => {
-            return decode_cnode_invoke_with_two_slot(invLabel, dest_slot, length, buffer);
+            decode_cnode_invoke_with_two_slot(invLabel, dest_slot, length, buffer)
+        }
+        MessageLabel::CNodeRevoke => Ok(cnode_invocation_t::Revoke(dest_slot)),
+        MessageLabel::CNodeDelete => Ok(cnode_invocation_t::Delete(dest_slot)),
+        #[cfg(not(feature = "ENABLE_MCS"))]
+        MessageLabel::CNodeSaveCaller => {
+            if dest_slot.cap.get_cap_type() != CapTag::CapNullCap {
+                debug!("CNode SaveCaller: Destination slot not empty.");
+                unsafe {
+                    current_syscall_error._type = seL4_DeleteFirst;
+                }
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+            }
+            Ok(cnode_invocation_t::SaveCaller(dest_slot))
+        }
+        MessageLabel::CNodeCancelBadgedSends => {
+            let dest_cap = dest_slot.cap;
+            if !hasCancelSendRight(&dest_cap) {
+                debug!("CNode CancelBadgedSends: Target cap invalid.");
+                unsafe {
+                    current_syscall_error._type = seL4_IllegalOperation;
+                }
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+            }
+            Ok(cnode_invocation_t::CancelBadgedSends(dest_cap))
         }
-        MessageLabel::CNodeRevoke => invoke_cnode_revoke(dest_slot),
-        MessageLabel::CNodeDelete => invoke_cnode_delete(dest_slot),
-        MessageLabel::CNodeSaveCaller => invoke_cnode_save_caller(dest_slot),
-        MessageLabel::CNodeCancelBadgedSends => invoke_cnode_cancel_badged_sends(dest_slot),
         MessageLabel::CNodeRotate => decode_cnode_rotate(dest_slot, length, buffer),
         _ => panic!("invalid invlabel: {:?}", invLabel),
     }
@@ -64,16 +101,16 @@ pub fn decode_cnode_invocation(
 
 fn decode_cnode_invoke_with_two_slot(
     label: MessageLabel,
-    dest_slot: &mut cte_t,
+    dest_slot: &'static mut cte_t,
     length: usize,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<cnode_invocation_t, exception_t> {
     if length < 4 || get_extra_cap_by_index(0).is_none() {
         debug!("CNode Copy/Mint/Move/Mutate: Truncated message.");
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let src_index = get_syscall_arg(2, buffer);
@@ -84,13 +121,13 @@ fn decode_cnode_invoke_with_two_slot(
         unsafe {
             current_syscall_error._type = seL4_DeleteFirst;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let lu_ret = lookup_slot_for_cnode_op(true, &src_root, src_index, src_depth);
     if lu_ret.status != exception_t::EXCEPTION_NONE {
         debug!("CNode Copy/Mint/Move/Mutate: Invalid source slot.");
-        return lu_ret.status;
+        return Err(lu_ret.status);
     }
     let src_slot = convert_to_mut_type_ref::<cte_t>(lu_ret.slot as usize);
     if src_slot.cap.get_cap_type() == CapTag::CapNullCap {
@@ -99,49 +136,72 @@ fn decode_cnode_invoke_with_two_slot(
             current_syscall_error.failedLookupWasSource = 1;
             current_lookup_fault = lookup_fault_t::new_missing_cap(src_depth);
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     match label {
-        MessageLabel::CNodeCopy => {
-            if length < 5 {
-                debug!("Truncated message for CNode Copy operation.");
+        MessageLabel::CNodeCopy | MessageLabel::CNodeMint => {
+            let (min_length, op) = if label == MessageLabel::CNodeCopy {
+                (5, "Copy")
+            } else {
+                (6, "Mint")
+            };
+            if length < min_length {
+                debug!("Truncated message for CNode {} operation.", op);
                 unsafe {
                     current_syscall_error._type = seL4_TruncatedMessage;
                 }
-                return exception_t::EXCEPTION_SYSCALL_ERROR;
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
             }
             let cap_right = seL4_CapRights_t::from_word(get_syscall_arg(4, buffer));
-            return invoke_cnode_copy(src_slot, dest_slot, cap_right);
-        }
-
-        MessageLabel::CNodeMint => {
-            if length < 6 {
-                debug!("Truncated message for CNode Mint operation.");
+            let mut new_cap = mask_cap_rights(cap_right, &src_slot.cap);
+            if label == MessageLabel::CNodeMint {
+                new_cap = new_cap.update_data(false, get_syscall_arg(5, buffer));
+            }
+            let dc_ret = src_slot.derive_cap(&new_cap);
+            if dc_ret.status != exception_t::EXCEPTION_NONE {
+                debug!("Error deriving cap for CNode {} operation.", op);
+                return Err(dc_ret.status);
+            }
+            if dc_ret.cap.get_cap_type() == CapTag::CapNullCap {
+                debug!("CNode {}: {} cap would be invalid.", op, op);
                 unsafe {
-                    current_syscall_error._type = seL4_TruncatedMessage;
+                    current_syscall_error._type = seL4_IllegalOperation;
                 }
-                return exception_t::EXCEPTION_SYSCALL_ERROR;
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
             }
-            let cap_right = seL4_CapRights_t::from_word(get_syscall_arg(4, buffer));
-            let cap_data = get_syscall_arg(5, buffer);
-            return invoke_cnode_mint(src_slot, dest_slot, cap_right, cap_data);
+            Ok(cnode_invocation_t::Insert {
+                cap: dc_ret.cap,
+                src_slot,
+                dest_slot,
+            })
         }
 
-        MessageLabel::CNodeMove => {
-            return invoke_cnode_move(src_slot, dest_slot);
-        }
-
-        MessageLabel::CNodeMutate => {
-            if length < 5 {
-                debug!("Truncated message for CNode Mutate operation.");
+        MessageLabel::CNodeMove | MessageLabel::CNodeMutate => {
+            let new_cap = if label == MessageLabel::CNodeMove {
+                src_slot.cap
+            } else {
+                if length < 5 {
+                    debug!("Truncated message for CNode Mutate operation.");
+                    unsafe {
+                        current_syscall_error._type = seL4_TruncatedMessage;
+                    }
+                    return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+                }
+                src_slot.cap.update_data(true, get_syscall_arg(4, buffer))
+            };
+            if new_cap.get_cap_type() == CapTag::CapNullCap {
+                debug!("CNode Copy/Mint/Move/Mutate: Mutated cap would be invalid.");
                 unsafe {
-                    current_syscall_error._type = seL4_TruncatedMessage;
+                    current_syscall_error._type = seL4_IllegalOperation;
                 }
-                return exception_t::EXCEPTION_SYSCALL_ERROR;
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
             }
-            let cap_data = get_syscall_arg(4, buffer);
-            return invoke_cnode_mutate(src_slot, dest_slot, cap_data);
+            Ok(cnode_invocation_t::Move {
+                cap: new_cap,
+                src_slot,
+                dest_slot,
+            })
         }
         _ => {
             panic!("invalid invLabel:{:?}", label);
@@ -150,16 +210,16 @@ fn decode_cnode_invoke_with_two_slot(
 }
 
 fn decode_cnode_rotate(
-    dest_slot: &mut cte_t,
+    dest_slot: &'static mut cte_t,
     length: usize,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<cnode_invocation_t, exception_t> {
     if length < 8 || get_extra_cap_by_index(0).is_none() || get_extra_cap_by_index(1).is_none() {
         debug!("CNode Rotate: Target cap invalid.");
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let pivot_new_data = get_syscall_arg(2, buffer);
@@ -174,13 +234,13 @@ fn decode_cnode_rotate(
 
     let lu_ret = lookup_slot_for_cnode_op(true, &src_root, src_idnex, src_depth);
     if lu_ret.status != exception_t::EXCEPTION_NONE {
-        return lu_ret.status;
+        return Err(lu_ret.status);
     }
     let src_slot = convert_to_mut_type_ref::<cte_t>(lu_ret.slot as usize);
 
     let lu_ret = lookup_slot_for_cnode_op(true, &pivot_root, pivot_index, pivot_depth);
     if lu_ret.status != exception_t::EXCEPTION_NONE {
-        return lu_ret.status;
+        return Err(lu_ret.status);
     }
     let pivot_slot = convert_to_mut_type_ref::<cte_t>(lu_ret.slot as usize);
 
@@ -189,15 +249,15 @@ fn decode_cnode_rotate(
         unsafe {
             current_syscall_error._type = seL4_IllegalOperation;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
-    if src_slot.get_ptr() != dest_slot.get_ptr() {
-        if dest_slot.cap.get_cap_type() != CapTag::CapNullCap {
-            unsafe {
-                current_syscall_error._type = seL4_DeleteFirst;
-            }
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
+    if src_slot.get_ptr() != dest_slot.get_ptr()
+        && dest_slot.cap.get_cap_type() != CapTag::CapNullCap
+    {
+        unsafe {
+            current_syscall_error._type = seL4_DeleteFirst;
         }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     if src_slot.cap.get_cap_type() == CapTag::CapNullCap {
@@ -206,7 +266,7 @@ fn decode_cnode_rotate(
             current_syscall_error.failedLookupWasSource = 1;
             current_lookup_fault = lookup_fault_t::new_missing_cap(src_depth);
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     if pivot_slot.cap.get_cap_type() == CapTag::CapNullCap {
@@ -215,13 +275,44 @@ fn decode_cnode_rotate(
             current_syscall_error.failedLookupWasSource = 0;
             current_lookup_fault = lookup_fault_t::new_missing_cap(pivot_depth);
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+
+    let src_cap = src_slot.cap.update_data(true, src_new_data);
+    let pivot_cap = pivot_slot.cap.update_data(true, pivot_new_data);
+
+    if src_cap.get_cap_type() == CapTag::CapNullCap {
+        debug!("CNode Rotate: Source cap invalid");
+        unsafe {
+            current_syscall_error._type = seL4_IllegalOperation;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
-    return invoke_cnode_rotate(
+
+    if pivot_cap.get_cap_type() == CapTag::CapNullCap {
+        debug!("CNode Rotate: Pivot cap invalid");
+        unsafe {
+            current_syscall_error._type = seL4_IllegalOperation;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    Ok(cnode_invocation_t::Rotate {
+        src_cap,
+        pivot_cap,
         src_slot,
         pivot_slot,
         dest_slot,
-        src_new_data,
-        pivot_new_data,
-    );
+    })
+}
+
+fn hasCancelSendRight(cap: &cap_t) -> bool {
+    match cap.get_cap_type() {
+        CapTag::CapEndpointCap => {
+            cap.get_ep_can_send() != 0
+                && cap.get_ep_can_receive() != 0
+                && cap.get_ep_can_grant() != 0
+                && cap.get_ep_can_grant_reply() != 0
+        }
+        _ => false,
+    }
 }
@@ -5,14 +5,14 @@ use sel4_common::{
     arch::MessageLabel,
     sel4_config::*,
     structures::{exception_t, seL4_IPCBuffer},
-    utils::convert_to_mut_type_ref,
+    utils::convert_to_checked_mut_type_ref,
 };
-use sel4_cspace::interface::CapTag;
-use sel4_task::{get_currenct_thread, set_thread_state, tcb_t, ThreadState};
+use sel4_cspace::interface::{cap_t, cte_t, CapTag};
+use sel4_task::tcb_t;
 
 use crate::{
     kernel::boot::{current_syscall_error, get_extra_cap_by_index},
-    syscall::get_syscall_arg,
+    syscall::{get_syscall_arg, invocation::perform_invocation},
 };
 
 pub fn decode_domain_invocation(
@@ -20,18 +20,50 @@ pub fn decode_domain_invocation(
     length: usize,
     buffer: Option<&seL4_IPCBuffer>,
 ) -> exception_t {
+    #[cfg(all(target_arch = "aarch64", feature = "ENABLE_ARM_MPAM"))]
+    if invLabel == MessageLabel::ARMDomainSetMPAMPartID {
+        return perform_invocation(decode_mpam_partid(length, buffer), |(thread, partid)| {
+            thread.tcbArch.set_mpam_partid(partid);
+            if thread.is_current() {
+                thread.tcbArch.load_mpam();
+            }
+            exception_t::EXCEPTION_NONE
+        });
+    }
+    #[cfg(feature = "ENABLE_REVOKE_NOTIFY")]
+    if invLabel == MessageLabel::DomainSetRevokeNotification {
+        return perform_invocation(decode_revoke_notification(), |(cap, src_slot)| {
+            crate::object::revoke_notify::set_revoke_notification(&cap, src_slot);
+            exception_t::EXCEPTION_NONE
+        });
+    }
+    perform_invocation(
+        decode_domain(invLabel, length, buffer),
+        |(thread, domain)| {
+            thread.set_domain(domain);
+            exception_t::EXCEPTION_NONE
+        },
+    )
+}
+
+/// Decode DomainSetSet into the thread to move and its new domain.
+fn decode_domain(
+    invLabel: MessageLabel,
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<(&'static mut tcb_t, usize), exception_t> {
     if invLabel != MessageLabel::DomainSetSet {
         unsafe {
             current_syscall_error._type = seL4_IllegalOperation;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     if length == 0 {
         debug!("Domain Configure: Truncated message.");
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let domain = get_syscall_arg(0, buffer);
     if domain >= 1 {
@@ -40,14 +72,14 @@ pub fn decode_domain_invocation(
             current_syscall_error._type = seL4_InvalidArgument;
             current_syscall_error.invalidArgumentNumber = 0;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     if get_extra_cap_by_index(0).is_none() {
         debug!("Domain Configure: Truncated message.");
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let thread_cap = get_extra_cap_by_index(0).unwrap().cap;
     if unlikely(thread_cap.get_cap_type() != CapTag::CapThreadCap) {
@@ -56,10 +88,85 @@ pub fn decode_domain_invocation(
             current_syscall_error._type = seL4_InvalidArgument;
             current_syscall_error.invalidArgumentNumber = 1;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
-    set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-    convert_to_mut_type_ref::<tcb_t>(thread_cap.get_tcb_ptr()).set_domain(domain);
-    exception_t::EXCEPTION_NONE
+    Ok((
+        convert_to_checked_mut_type_ref::<tcb_t>(thread_cap.get_tcb_ptr()),
+        domain,
+    ))
+}
+
+/// Decode ARMDomainSetMPAMPartID into the thread to tag and its new PARTID.
+///
+/// The domain cap is the authority over the partitioning of the system in time, and MPAM
+/// partitions the caches and the memory bandwidth, so it is the authority over PARTIDs as well.
+#[cfg(all(target_arch = "aarch64", feature = "ENABLE_ARM_MPAM"))]
+fn decode_mpam_partid(
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<(&'static mut tcb_t, usize), exception_t> {
+    let Some(partid_max) = sel4_common::arch::mpam_partid_max() else {
+        debug!("Domain SetMPAMPartID: MPAM is not available.");
+        unsafe {
+            current_syscall_error._type = seL4_IllegalOperation;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    };
+    if length == 0 || get_extra_cap_by_index(0).is_none() {
+        debug!("Domain SetMPAMPartID: Truncated message.");
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let partid = get_syscall_arg(0, buffer);
+    if partid > partid_max {
+        debug!(
+            "Domain SetMPAMPartID: PARTID {} out of range (> {}).",
+            partid, partid_max
+        );
+        unsafe {
+            current_syscall_error._type = seL4_RangeError;
+            current_syscall_error.rangeErrorMin = 0;
+            current_syscall_error.rangeErrorMax = partid_max;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let thread_cap = get_extra_cap_by_index(0).unwrap().cap;
+    if unlikely(thread_cap.get_cap_type() != CapTag::CapThreadCap) {
+        debug!("Domain SetMPAMPartID: thread cap required.");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidArgument;
+            current_syscall_error.invalidArgumentNumber = 1;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+
+    Ok((
+        convert_to_checked_mut_type_ref::<tcb_t>(thread_cap.get_tcb_ptr()),
+        partid,
+    ))
+}
+
+/// Decode DomainSetRevokeNotification into the notification cap to register and its slot.
+#[cfg(feature = "ENABLE_REVOKE_NOTIFY")]
+fn decode_revoke_notification() -> Result<(cap_t, &'static mut cte_t), exception_t> {
+    let Some(src_slot) = get_extra_cap_by_index(0) else {
+        debug!("Domain SetRevokeNotification: Truncated message.");
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    };
+    let cap = src_slot.cap;
+    if unlikely(cap.get_cap_type() != CapTag::CapNotificationCap || cap.get_nf_can_send() == 0) {
+        debug!("Domain SetRevokeNotification: notification cap with send right required.");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 1;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    Ok((cap, src_slot))
 }
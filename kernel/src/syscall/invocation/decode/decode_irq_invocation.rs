@@ -6,14 +6,17 @@ use sel4_common::{
     utils::convert_to_mut_type_ref,
 };
 use sel4_cspace::interface::{cte_t, CapTag};
-use sel4_task::{get_currenct_thread, set_thread_state, ThreadState};
 
+#[cfg(feature = "ENABLE_SMP")]
+use super::arch::decode_irq_issue_trigger;
 use super::arch::{arch_decode_irq_control_invocation, check_irq};
-use crate::syscall::invocation::invoke_irq::{invoke_clear_irq_handler, invoke_set_irq_handler};
+use crate::syscall::invocation::invoke_irq::{
+    invoke_irq_control, invoke_irq_handler, irq_control_invocation_t, irq_handler_invocation_t,
+};
 use crate::{
-    interrupt::is_irq_active,
+    interrupt::{is_irq_active, is_irq_routable},
     kernel::boot::{current_syscall_error, get_extra_cap_by_index},
-    syscall::{get_syscall_arg, invocation::invoke_irq::invoke_irq_control, lookupSlotForCNodeOp},
+    syscall::{get_syscall_arg, invocation::perform_invocation, lookupSlotForCNodeOp},
 };
 
 pub fn decode_irq_control_invocation(
@@ -22,66 +25,128 @@ pub fn decode_irq_control_invocation(
     src_slot: &mut cte_t,
     buffer: Option<&seL4_IPCBuffer>,
 ) -> exception_t {
-    if label == MessageLabel::IRQIssueIRQHandler {
-        if length < 3 || get_extra_cap_by_index(0).is_none() {
-            unsafe {
-                current_syscall_error._type = seL4_TruncatedMessage;
-            }
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
+    let decoded = if label == MessageLabel::IRQIssueIRQHandler {
+        decode_irq_control(length, buffer)
+    } else {
+        arch_decode_irq_control_invocation(label, length, buffer)
+    };
+    perform_invocation(decoded, |invocation| {
+        invoke_irq_control(invocation, src_slot)
+    })
+}
+
+fn decode_irq_control(
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<irq_control_invocation_t, exception_t> {
+    if length < 3 || get_extra_cap_by_index(0).is_none() {
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
         }
-        let irq = get_syscall_arg(0, buffer);
-        let index = get_syscall_arg(1, buffer);
-        let depth = get_syscall_arg(2, buffer);
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let irq = get_syscall_arg(0, buffer);
+    let index = get_syscall_arg(1, buffer);
+    let depth = get_syscall_arg(2, buffer);
 
-        let cnode_cap = get_extra_cap_by_index(0).unwrap().cap;
-        let status = check_irq(irq);
-        if status != exception_t::EXCEPTION_NONE {
-            return status;
+    let cnode_cap = get_extra_cap_by_index(0).unwrap().cap;
+    let status = check_irq(irq);
+    if status != exception_t::EXCEPTION_NONE {
+        return Err(status);
+    }
+    if is_irq_active(irq) {
+        unsafe {
+            current_syscall_error._type = seL4_RevokeFirst;
         }
-        if is_irq_active(irq) {
-            unsafe {
-                current_syscall_error._type = seL4_RevokeFirst;
-            }
-            debug!("Rejecting request for IRQ {}. Already active.", irq);
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
+        debug!("Rejecting request for IRQ {}. Already active.", irq);
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let lu_ret = lookupSlotForCNodeOp(false, &cnode_cap, index, depth);
+    if lu_ret.status != exception_t::EXCEPTION_NONE {
+        debug!("Target slot for new IRQ Handler cap invalid: IRQ {}.", irq);
+        return Err(lu_ret.status);
+    }
+    let dest_slot = convert_to_mut_type_ref::<cte_t>(lu_ret.slot as usize);
+    if dest_slot.cap.get_cap_type() != CapTag::CapNullCap {
+        unsafe {
+            current_syscall_error._type = seL4_DeleteFirst;
         }
-        let lu_ret = lookupSlotForCNodeOp(false, &cnode_cap, index, depth);
-        if lu_ret.status != exception_t::EXCEPTION_NONE {
-            debug!("Target slot for new IRQ Handler cap invalid: IRQ {}.", irq);
-            return lu_ret.status;
+        debug!("Target slot for new IRQ Handler cap not empty");
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    Ok(irq_control_invocation_t {
+        irq,
+        trigger: None,
+        msi: None,
+        core: None,
+        dest_slot,
+    })
+}
+
+/// Decode the IRQIssueIRQHandlerTriggerCore of the architecture: its IRQIssueIRQHandlerTrigger
+/// and the core to route the IRQ to, as with IRQSetCore.
+#[cfg(feature = "ENABLE_SMP")]
+pub fn decode_irq_issue_trigger_core(
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<irq_control_invocation_t, exception_t> {
+    if length < 5 || get_extra_cap_by_index(0).is_none() {
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
         }
-        let dest_slot = convert_to_mut_type_ref::<cte_t>(lu_ret.slot as usize);
-        if dest_slot.cap.get_cap_type() != CapTag::CapNullCap {
-            unsafe {
-                current_syscall_error._type = seL4_DeleteFirst;
-            }
-            debug!("Target slot for new IRQ Handler cap not empty");
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let core = get_syscall_arg(4, buffer);
+    if core >= *crate::boot::ksNumCPUs.lock() {
+        debug!("Target core {} is invalid.", core);
+        unsafe {
+            current_syscall_error._type = seL4_InvalidArgument;
+            current_syscall_error.invalidArgumentNumber = 4;
         }
-        set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-        invoke_irq_control(
-            irq,
-            convert_to_mut_type_ref::<cte_t>(lu_ret.slot as usize),
-            src_slot,
-        )
-    } else {
-        arch_decode_irq_control_invocation(label, length, src_slot, buffer)
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
+    let mut invocation = decode_irq_issue_trigger(length, buffer)?;
+    if !is_irq_routable(invocation.irq) {
+        debug!(
+            "IRQIssueIRQHandlerTriggerCore: IRQ {} cannot be routed to another core.",
+            invocation.irq
+        );
+        unsafe {
+            current_syscall_error._type = seL4_IllegalOperation;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    invocation.core = Some(core);
+    Ok(invocation)
 }
 
-pub fn decode_irq_handler_invocation(label: MessageLabel, irq: usize) -> exception_t {
-    return match label {
-        MessageLabel::IRQAckIRQ => {
-            set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-            exception_t::EXCEPTION_NONE
-        }
+pub fn decode_irq_handler_invocation(
+    label: MessageLabel,
+    length: usize,
+    irq: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> exception_t {
+    perform_invocation(
+        decode_irq_handler(label, length, irq, buffer),
+        |invocation| invoke_irq_handler(irq, invocation),
+    )
+}
+
+fn decode_irq_handler(
+    label: MessageLabel,
+    length: usize,
+    irq: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<irq_handler_invocation_t, exception_t> {
+    match label {
+        MessageLabel::IRQAckIRQ => Ok(irq_handler_invocation_t::Ack),
 
         MessageLabel::IRQSetIRQHandler => {
             if get_extra_cap_by_index(0).is_none() {
                 unsafe {
                     current_syscall_error._type = seL4_TruncatedMessage;
                 }
-                return exception_t::EXCEPTION_SYSCALL_ERROR;
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
             }
             let slot = get_extra_cap_by_index(0).unwrap();
             let ntfn_cap = slot.cap;
@@ -92,23 +157,47 @@ pub fn decode_irq_handler_invocation(label: MessageLabel, irq: usize) -> excepti
                     current_syscall_error._type = seL4_InvalidCapability;
                     current_syscall_error.invalidCapNumber = 0;
                 }
-                return exception_t::EXCEPTION_SYSCALL_ERROR;
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
             }
-            set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-            invoke_set_irq_handler(irq, &ntfn_cap, slot);
-            exception_t::EXCEPTION_NONE
+            Ok(irq_handler_invocation_t::SetHandler {
+                cap: ntfn_cap,
+                slot,
+            })
         }
-        MessageLabel::IRQClearIRQHandler => {
-            set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-            invoke_clear_irq_handler(irq);
-            exception_t::EXCEPTION_NONE
+        MessageLabel::IRQClearIRQHandler => Ok(irq_handler_invocation_t::Clear),
+        MessageLabel::IRQSetCore => {
+            if length < 1 {
+                unsafe {
+                    current_syscall_error._type = seL4_TruncatedMessage;
+                }
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+            }
+            let core = get_syscall_arg(0, buffer);
+            let num_cpus = *crate::boot::ksNumCPUs.lock();
+            if core >= num_cpus {
+                debug!("IRQSetCore: core {} out of range.", core);
+                unsafe {
+                    current_syscall_error._type = seL4_RangeError;
+                    current_syscall_error.rangeErrorMin = 0;
+                    current_syscall_error.rangeErrorMax = num_cpus - 1;
+                }
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+            }
+            if !is_irq_routable(irq) {
+                debug!("IRQSetCore: IRQ {} cannot be routed to another core.", irq);
+                unsafe {
+                    current_syscall_error._type = seL4_IllegalOperation;
+                }
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+            }
+            Ok(irq_handler_invocation_t::SetCore(core))
         }
         _ => {
             debug!("IRQHandler: Illegal operation.");
             unsafe {
                 current_syscall_error._type = seL4_IllegalOperation;
             }
-            exception_t::EXCEPTION_SYSCALL_ERROR
+            Err(exception_t::EXCEPTION_SYSCALL_ERROR)
         }
-    };
+    }
 }
@@ -0,0 +1,224 @@
+use log::debug;
+use sel4_common::{
+    arch::MessageLabel,
+    sel4_config::*,
+    structures::{exception_t, seL4_IPCBuffer},
+    utils::convert_to_mut_type_ref,
+    BIT,
+};
+use sel4_cspace::interface::{cte_t, CapTag};
+use sel4_vspace::pptr_to_paddr;
+
+use crate::boot::{boot_memory_overlaps, pptr_in_kernel_window};
+#[cfg(feature = "ENABLE_OBJECT_RELOCATE")]
+use crate::config::CONFIG_RELOCATE_CNODE_MAX_RADIX;
+use crate::structures::p_region_t;
+use crate::syscall::invocation::invoke_memory_control::{
+    invoke_memory_control, memory_control_invocation_t,
+};
+#[cfg(feature = "ENABLE_OBJECT_RELOCATE")]
+use crate::syscall::{alignUp, GET_FREE_REF};
+use crate::{
+    kernel::boot::{current_syscall_error, get_extra_cap_by_index},
+    syscall::{get_syscall_arg, invocation::perform_invocation, lookupSlotForCNodeOp},
+};
+
+pub fn decode_memory_control_invocation(
+    label: MessageLabel,
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> exception_t {
+    perform_invocation(
+        decode_memory_control(label, length, buffer),
+        invoke_memory_control,
+    )
+}
+
+fn decode_memory_control(
+    label: MessageLabel,
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<memory_control_invocation_t, exception_t> {
+    match label {
+        MessageLabel::MemoryControlAddUntyped => decode_add_untyped(length, buffer),
+        #[cfg(feature = "ENABLE_OBJECT_RELOCATE")]
+        MessageLabel::MemoryControlRelocateCNode => decode_relocate_cnode(),
+        _ => {
+            unsafe {
+                current_syscall_error._type = seL4_IllegalOperation;
+            }
+            Err(exception_t::EXCEPTION_SYSCALL_ERROR)
+        }
+    }
+}
+
+/// Decode MemoryControlAddUntyped: extra cap 0 is a device untyped without children covering
+/// the added RAM, the new untyped goes to the slot `index`, `depth` of the CNode in extra cap 1.
+///
+/// The memory must lie in the kernel window and must not overlap any region the kernel was
+/// booted with, so RAM it already manages can never be handed out twice.
+fn decode_add_untyped(
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<memory_control_invocation_t, exception_t> {
+    if length < 2 || get_extra_cap_by_index(0).is_none() || get_extra_cap_by_index(1).is_none() {
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let index = get_syscall_arg(0, buffer);
+    let depth = get_syscall_arg(1, buffer);
+    let parent_slot = get_extra_cap_by_index(0).unwrap();
+    let root = get_extra_cap_by_index(1).unwrap().cap;
+
+    let untyped_cap = parent_slot.cap;
+    if untyped_cap.get_cap_type() != CapTag::CapUntypedCap
+        || untyped_cap.get_untyped_is_device() == 0
+    {
+        debug!("MemoryControlAddUntyped: a device untyped is required.");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 1;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let status = parent_slot.ensure_no_children();
+    if status != exception_t::EXCEPTION_NONE {
+        unsafe {
+            current_syscall_error._type = seL4_RevokeFirst;
+        }
+        return Err(status);
+    }
+
+    let start = untyped_cap.get_untyped_ptr();
+    let last = start + (BIT!(untyped_cap.get_untyped_block_size()) - 1);
+    if !pptr_in_kernel_window(start) || !pptr_in_kernel_window(last) {
+        debug!(
+            "MemoryControlAddUntyped: [{:#x}..{:#x}] is outside the kernel window.",
+            start, last
+        );
+        unsafe {
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 1;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let reg = p_region_t {
+        start: pptr_to_paddr(start),
+        end: pptr_to_paddr(last) + 1,
+    };
+    if boot_memory_overlaps(&reg) {
+        debug!(
+            "MemoryControlAddUntyped: [{:#x}..{:#x}] overlaps memory known at boot.",
+            reg.start, reg.end
+        );
+        unsafe {
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 1;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+
+    let lu_ret = lookupSlotForCNodeOp(false, &root, index, depth);
+    if lu_ret.status != exception_t::EXCEPTION_NONE {
+        debug!("MemoryControlAddUntyped: target slot invalid.");
+        return Err(lu_ret.status);
+    }
+    let dest_slot = convert_to_mut_type_ref::<cte_t>(lu_ret.slot as usize);
+    if dest_slot.cap.get_cap_type() != CapTag::CapNullCap {
+        unsafe {
+            current_syscall_error._type = seL4_DeleteFirst;
+        }
+        debug!("MemoryControlAddUntyped: target slot not empty.");
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    Ok(memory_control_invocation_t::AddUntyped {
+        parent_slot,
+        dest_slot,
+    })
+}
+
+/// Decode MemoryControlRelocateCNode: the CNode extra cap 0 refers to moves to the free memory
+/// of the RAM untyped in extra cap 1, aligned to its size like a retype.
+///
+/// The CNode must not be in the middle of a delete, which leaves a zombie cap in it. Its radix is
+/// bounded by `CONFIG_RELOCATE_CNODE_MAX_RADIX`, as the move is not preemptible.
+#[cfg(feature = "ENABLE_OBJECT_RELOCATE")]
+fn decode_relocate_cnode() -> Result<memory_control_invocation_t, exception_t> {
+    let (Some(cnode_slot), Some(untyped_slot)) =
+        (get_extra_cap_by_index(0), get_extra_cap_by_index(1))
+    else {
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    };
+    let cnode_cap = cnode_slot.cap;
+    if cnode_cap.get_cap_type() != CapTag::CapCNodeCap
+        || cnode_cap.get_cnode_radix() > CONFIG_RELOCATE_CNODE_MAX_RADIX
+    {
+        debug!(
+            "MemoryControlRelocateCNode: a CNode cap of radix {} at most is required.",
+            CONFIG_RELOCATE_CNODE_MAX_RADIX
+        );
+        unsafe {
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 1;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let untyped_cap = untyped_slot.cap;
+    if untyped_cap.get_cap_type() != CapTag::CapUntypedCap
+        || untyped_cap.get_untyped_is_device() != 0
+    {
+        debug!("MemoryControlRelocateCNode: a RAM untyped is required.");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 2;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+
+    let radix = cnode_cap.get_cnode_radix();
+    let old_ptr = cnode_cap.get_cnode_ptr();
+    let cnode = convert_to_mut_type_ref::<cte_t>(old_ptr);
+    if (0..BIT!(radix)).any(|i| cnode.get_offset_slot(i).cap.get_cap_type() == CapTag::CapZombieCap)
+    {
+        debug!("MemoryControlRelocateCNode: the CNode is being deleted.");
+        unsafe {
+            current_syscall_error._type = seL4_IllegalOperation;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+
+    let size_bits = radix + seL4_SlotBits;
+    let base = untyped_cap.get_untyped_ptr();
+    let end = base + BIT!(untyped_cap.get_untyped_block_size());
+    let free_ref = GET_FREE_REF(base, untyped_cap.get_untyped_free_index());
+    let new_ptr = alignUp(free_ref, size_bits);
+    if new_ptr > end || end - new_ptr < BIT!(size_bits) {
+        debug!(
+            "MemoryControlRelocateCNode: {} bytes needed, {} bytes available.",
+            BIT!(size_bits),
+            end - free_ref
+        );
+        unsafe {
+            current_syscall_error._type = seL4_NotEnoughMemory;
+            current_syscall_error.memoryLeft = end - free_ref;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    if new_ptr < old_ptr + BIT!(size_bits) && old_ptr < new_ptr + BIT!(size_bits) {
+        debug!("MemoryControlRelocateCNode: the new place overlaps the CNode.");
+        unsafe {
+            current_syscall_error._type = seL4_IllegalOperation;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    Ok(memory_control_invocation_t::RelocateCNode {
+        cnode_slot,
+        untyped_slot,
+        new_ptr,
+    })
+}
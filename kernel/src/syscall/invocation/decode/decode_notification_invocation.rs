@@ -0,0 +1,125 @@
+//! NotificationWaitIfEqual: an experimental extension that is NOT part of seL4.
+//!
+//! The invoking thread passes a frame cap (extra cap 0), a word offset into that
+//! frame (MR0) and an expected value (MR1). The kernel reads the word and only
+//! waits on the notification if it still equals the expected value, otherwise it
+//! returns immediately. Since the check and the block happen inside the kernel,
+//! a user-level mutex built over a notification can no longer miss a wakeup
+//! between reading its lock word and calling `seL4_Wait`.
+//!
+//! The check is not atomic with respect to user space: on SMP a thread on another
+//! core can store to the word right after the kernel read it. The guarantee is
+//! only that no signal is lost, and it holds as long as wakers store to the word
+//! before they signal the notification: that signal takes the kernel lock, so it
+//! is handled after this thread is already waiting.
+//!
+//! Must be invoked with a send (`seL4_Send`/`seL4_NBSend`), not a call, so the
+//! badge delivered by the notification is not overwritten by a kernel reply.
+
+use core::intrinsics::unlikely;
+
+use log::debug;
+use sel4_common::{
+    sel4_config::*,
+    structures::{exception_t, seL4_IPCBuffer},
+    utils::{convert_to_checked_mut_type_ref, pageBitsForSize},
+};
+use sel4_cspace::interface::{cap_t, CapTag};
+use sel4_ipc::notification_t;
+use sel4_task::get_currenct_thread;
+
+use crate::{
+    kernel::boot::{current_syscall_error, get_extra_cap_by_index},
+    syscall::{get_syscall_arg, invocation::perform_invocation},
+};
+
+pub fn decode_notification_wait_if_equal(
+    length: usize,
+    cap: &cap_t,
+    block: bool,
+    call: bool,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> exception_t {
+    perform_invocation(
+        decode_wait_if_equal(length, cap, call, buffer),
+        |(ntfn, word_ptr, expected)| {
+            // Other cores can still write the word, but no signal can be handled
+            // until this thread is queued, see the module documentation.
+            let word = unsafe { core::ptr::read_volatile(word_ptr as *const usize) };
+            if word == expected {
+                ntfn.receive_signal(get_currenct_thread(), block);
+            }
+            exception_t::EXCEPTION_NONE
+        },
+    )
+}
+
+/// Decode into the notification, the address of the word to compare and the expected value.
+fn decode_wait_if_equal(
+    length: usize,
+    cap: &cap_t,
+    call: bool,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<(&'static mut notification_t, usize, usize), exception_t> {
+    let ntfn = convert_to_checked_mut_type_ref::<notification_t>(cap.get_nf_ptr());
+    let bound_tcb_ptr = ntfn.get_bound_tcb();
+    if unlikely(
+        cap.get_nf_can_receive() == 0
+            || (bound_tcb_ptr != 0 && bound_tcb_ptr != get_currenct_thread().get_ptr()),
+    ) {
+        debug!("NotificationWaitIfEqual: Cannot wait on this notification.");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 0;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    if unlikely(call) {
+        debug!("NotificationWaitIfEqual: Must be invoked with a send.");
+        unsafe {
+            current_syscall_error._type = seL4_IllegalOperation;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    if unlikely(length < 2 || get_extra_cap_by_index(0).is_none()) {
+        debug!("NotificationWaitIfEqual: Truncated message.");
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let offset = get_syscall_arg(0, buffer);
+    let expected = get_syscall_arg(1, buffer);
+    let frame_cap = get_extra_cap_by_index(0).unwrap().cap;
+    if unlikely(
+        frame_cap.get_cap_type() != CapTag::CapFrameCap
+            || frame_cap.get_frame_mapped_asid() == asidInvalid
+            || frame_cap.get_frame_is_device() != 0,
+    ) {
+        debug!("NotificationWaitIfEqual: Expected a mapped, non-device frame cap.");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 1;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    if unlikely(offset % core::mem::size_of::<usize>() != 0) {
+        debug!("NotificationWaitIfEqual: Offset is not word aligned.");
+        unsafe {
+            current_syscall_error._type = seL4_AlignmentError;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let page_size = 1usize << pageBitsForSize(frame_cap.get_frame_size());
+    if unlikely(offset >= page_size) {
+        debug!("NotificationWaitIfEqual: Offset is outside of the frame.");
+        unsafe {
+            current_syscall_error._type = seL4_RangeError;
+            current_syscall_error.rangeErrorMin = 0;
+            current_syscall_error.rangeErrorMax = page_size - 1;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+
+    Ok((ntfn, frame_cap.get_frame_base_ptr() + offset, expected))
+}
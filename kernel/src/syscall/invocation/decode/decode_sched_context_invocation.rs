@@ -0,0 +1,193 @@
+use log::debug;
+use sel4_common::{
+    arch::MessageLabel,
+    sel4_config::*,
+    structures::{exception_t, seL4_IPCBuffer},
+    utils::convert_to_mut_type_ref,
+};
+use sel4_cspace::interface::{cap_t, CapTag};
+use sel4_task::sched_context::{refill_absolute_max, sched_context_t, MIN_REFILLS};
+use sel4_task::tcb_t;
+
+use crate::config::{MAX_PERIOD_US, MIN_BUDGET_US, MS_IN_S, TIMER_CLOCK_HZ, US_IN_MS};
+use crate::syscall::invocation::invoke_sched_context::{
+    invoke_sched_context, sched_context_invocation_t,
+};
+use crate::{
+    kernel::boot::{current_syscall_error, get_extra_cap_by_index},
+    syscall::{get_syscall_arg, invocation::perform_invocation},
+};
+
+pub fn decode_sched_control_invocation(
+    label: MessageLabel,
+    length: usize,
+    cap: &cap_t,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> exception_t {
+    perform_invocation(
+        decode_sched_control(label, length, cap, buffer),
+        invoke_sched_context,
+    )
+}
+
+pub fn decode_sched_context_invocation(
+    label: MessageLabel,
+    cap: &cap_t,
+    call: bool,
+) -> exception_t {
+    perform_invocation(decode_sched_context(label, cap, call), invoke_sched_context)
+}
+
+/// Microseconds to cycles of the timer counter.
+#[inline]
+fn us_to_cycles(us: usize) -> usize {
+    us * (TIMER_CLOCK_HZ / MS_IN_S) / US_IN_MS
+}
+
+/// Decode SchedControlConfigureFlags: budget and period in microseconds, the number of extra
+/// refills, the badge and the flags, extra cap 0 is the scheduling context.
+///
+/// The flags are accepted but do not change anything, every scheduling context is accounted as a
+/// sporadic server.
+fn decode_sched_control(
+    label: MessageLabel,
+    length: usize,
+    cap: &cap_t,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<sched_context_invocation_t, exception_t> {
+    if label != MessageLabel::SchedControlConfigureFlags {
+        debug!("SchedControl: Illegal operation attempted.");
+        unsafe {
+            current_syscall_error._type = seL4_IllegalOperation;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    if length < 5 || get_extra_cap_by_index(0).is_none() {
+        debug!("SchedControl_ConfigureFlags: Truncated message.");
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let sc_cap = get_extra_cap_by_index(0).unwrap().cap;
+    if sc_cap.get_cap_type() != CapTag::CapSchedContextCap {
+        debug!("SchedControl_ConfigureFlags: a scheduling context cap is required.");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 1;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let budget_us = get_syscall_arg(0, buffer);
+    let period_us = get_syscall_arg(1, buffer);
+    let extra_refills = get_syscall_arg(2, buffer);
+    let badge = get_syscall_arg(3, buffer);
+
+    if !(MIN_BUDGET_US..=MAX_PERIOD_US).contains(&budget_us) {
+        debug!("SchedControl_ConfigureFlags: budget out of range.");
+        unsafe {
+            current_syscall_error._type = seL4_RangeError;
+            current_syscall_error.rangeErrorMin = MIN_BUDGET_US;
+            current_syscall_error.rangeErrorMax = MAX_PERIOD_US;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    if !(budget_us..=MAX_PERIOD_US).contains(&period_us) {
+        debug!("SchedControl_ConfigureFlags: period out of range.");
+        unsafe {
+            current_syscall_error._type = seL4_RangeError;
+            current_syscall_error.rangeErrorMin = budget_us;
+            current_syscall_error.rangeErrorMax = MAX_PERIOD_US;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let max_refills = refill_absolute_max(sc_cap.get_sc_size_bits());
+    if extra_refills > max_refills - MIN_REFILLS {
+        debug!("SchedControl_ConfigureFlags: too many extra refills.");
+        unsafe {
+            current_syscall_error._type = seL4_RangeError;
+            current_syscall_error.rangeErrorMin = 0;
+            current_syscall_error.rangeErrorMax = max_refills - MIN_REFILLS;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+
+    Ok(sched_context_invocation_t::Configure {
+        sc: convert_to_mut_type_ref::<sched_context_t>(sc_cap.get_sc_ptr()),
+        budget: us_to_cycles(budget_us),
+        period: us_to_cycles(period_us),
+        max_refills: extra_refills + MIN_REFILLS,
+        badge,
+        core: cap.get_sched_control_core(),
+    })
+}
+
+/// Decode SchedContextBind, SchedContextUnbind, SchedContextUnbindObject and
+/// SchedContextConsumed. Bind takes the thread in extra cap 0, neither the thread nor the
+/// scheduling context may be bound already; UnbindObject takes the thread bound to it. Consumed
+/// replies with the time consumed in microseconds, the overruns and the deadline misses.
+///
+/// SchedContextYieldTo is not supported and fails as an illegal operation.
+fn decode_sched_context(
+    label: MessageLabel,
+    cap: &cap_t,
+    call: bool,
+) -> Result<sched_context_invocation_t, exception_t> {
+    let sc = convert_to_mut_type_ref::<sched_context_t>(cap.get_sc_ptr());
+    match label {
+        MessageLabel::SchedContextBind => {
+            let Some(slot) = get_extra_cap_by_index(0) else {
+                debug!("SchedContext_Bind: Truncated message.");
+                unsafe {
+                    current_syscall_error._type = seL4_TruncatedMessage;
+                }
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+            };
+            if slot.cap.get_cap_type() != CapTag::CapThreadCap {
+                debug!("SchedContext_Bind: a thread cap is required.");
+                unsafe {
+                    current_syscall_error._type = seL4_InvalidCapability;
+                    current_syscall_error.invalidCapNumber = 1;
+                }
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+            }
+            let tcb = convert_to_mut_type_ref::<tcb_t>(slot.cap.get_tcb_ptr());
+            if sc.scTcb != 0 || tcb.tcbSchedContext != 0 {
+                debug!("SchedContext_Bind: already bound.");
+                unsafe {
+                    current_syscall_error._type = seL4_IllegalOperation;
+                }
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+            }
+            Ok(sched_context_invocation_t::Bind { sc, tcb })
+        }
+        MessageLabel::SchedContextUnbind => Ok(sched_context_invocation_t::Unbind { sc }),
+        MessageLabel::SchedContextUnbindObject => {
+            let Some(slot) = get_extra_cap_by_index(0) else {
+                debug!("SchedContext_UnbindObject: Truncated message.");
+                unsafe {
+                    current_syscall_error._type = seL4_TruncatedMessage;
+                }
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+            };
+            // Notifications cannot be bound to a scheduling context, so only a thread can be
+            if slot.cap.get_cap_type() != CapTag::CapThreadCap || sc.scTcb != slot.cap.get_tcb_ptr()
+            {
+                debug!("SchedContext_UnbindObject: object not bound.");
+                unsafe {
+                    current_syscall_error._type = seL4_IllegalOperation;
+                }
+                return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+            }
+            Ok(sched_context_invocation_t::UnbindTcb { sc })
+        }
+        MessageLabel::SchedContextConsumed => Ok(sched_context_invocation_t::Consumed { sc, call }),
+        _ => {
+            debug!("SchedContext: Illegal operation attempted.");
+            unsafe {
+                current_syscall_error._type = seL4_IllegalOperation;
+            }
+            Err(exception_t::EXCEPTION_SYSCALL_ERROR)
+        }
+    }
+}
@@ -5,21 +5,31 @@
 
 use log::debug;
 use sel4_common::arch::MessageLabel;
-use sel4_common::arch::{frameRegNum, gpRegNum};
+use sel4_common::arch::{frameRegNum, gpRegNum, vm_rights_t};
+use sel4_common::fault::{
+    lookup_fault_guard_mismatch, lookup_fault_t, seL4_Fault_CapFault, seL4_Fault_NullFault,
+    seL4_Fault_UnknownSyscall, seL4_Fault_UserException, seL4_Fault_VMFault,
+};
 use sel4_common::sel4_config::{
-    seL4_IllegalOperation, seL4_InvalidCapability, seL4_RangeError, seL4_TruncatedMessage,
-    tcbCTable, tcbVTable,
+    seL4_FailedLookup, seL4_IllegalOperation, seL4_InvalidArgument, seL4_InvalidCapability,
+    seL4_RangeError, seL4_TruncatedMessage, tcbCTable, tcbVTable,
 };
 use sel4_common::structures::{exception_t, seL4_IPCBuffer};
-use sel4_common::utils::convert_to_mut_type_ref;
+use sel4_common::utils::{
+    convert_to_checked_mut_type_ref, convert_to_mut_type_ref, pageBitsForSize,
+};
 use sel4_common::BIT;
 use sel4_cspace::interface::{cap_t, cte_t, CapTag};
 use sel4_ipc::notification_t;
-use sel4_task::{get_currenct_thread, set_thread_state, tcb_t, ThreadState};
+use sel4_task::{tcb_t, ThreadState};
+use sel4_vspace::find_vspace_for_asid;
 
 use crate::{
-    kernel::boot::{current_syscall_error, get_extra_cap_by_index},
-    syscall::utils::{check_ipc_buffer_vaild, check_prio, get_syscall_arg},
+    kernel::boot::{current_lookup_fault, current_syscall_error, get_extra_cap_by_index},
+    syscall::{
+        invocation::perform_invocation,
+        utils::{check_ipc_buffer_vaild, check_prio, get_syscall_arg},
+    },
 };
 
 #[cfg(target_arch = "riscv64")]
@@ -36,7 +46,6 @@ pub const CopyRegisters_transferFrame: usize = 2;
 pub const CopyRegisters_transferInteger: usize = 3;
 pub const ReadRegisters_suspend: usize = 0;
 
-#[cfg(feature = "ENABLE_SMP")]
 #[no_mangle]
 pub fn decode_tcb_invocation(
     invLabel: MessageLabel,
@@ -46,90 +55,69 @@ pub fn decode_tcb_invocation(
     call: bool,
     buffer: Option<&seL4_IPCBuffer>,
 ) -> exception_t {
+    #[cfg(feature = "ENABLE_SMP")]
     unsafe {
-        remoteTCBStall(convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr()));
-    }
-    match invLabel {
-        MessageLabel::TCBReadRegisters => decode_read_registers(cap, length, call, buffer),
-        MessageLabel::TCBWriteRegisters => decode_write_registers(cap, length, buffer),
-        MessageLabel::TCBCopyRegisters => decode_copy_registers(cap, length, buffer),
-        MessageLabel::TCBSuspend => {
-            set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-            invoke_tcb_suspend(convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr()))
-        }
-        MessageLabel::TCBResume => {
-            set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-            invoke_tcb_resume(convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr()))
-        }
-        MessageLabel::TCBConfigure => decode_tcb_configure(cap, length, slot, buffer),
-        MessageLabel::TCBSetPriority => decode_set_priority(cap, length, buffer),
-        MessageLabel::TCBSetMCPriority => decode_set_mc_priority(cap, length, buffer),
-        MessageLabel::TCBSetSchedParams => decode_set_sched_params(cap, length, buffer),
-        MessageLabel::TCBSetIPCBuffer => decode_set_ipc_buffer(cap, length, slot, buffer),
-        MessageLabel::TCBSetSpace => decode_set_space(cap, length, slot, buffer),
-        MessageLabel::TCBBindNotification => decode_bind_notification(cap),
-        MessageLabel::TCBUnbindNotification => decode_unbind_notification(cap),
-        MessageLabel::TCBSetAffinity => decode_set_affinity(cap, length, buffer),
-        MessageLabel::TCBSetTLSBase => decode_set_tls_base(cap, length, buffer),
-        _ => unsafe {
-            debug!("TCB: Illegal operation invLabel :{:?}", invLabel);
-            current_syscall_error._type = seL4_IllegalOperation;
-            exception_t::EXCEPTION_SYSCALL_ERROR
-        },
+        remoteTCBStall(convert_to_checked_mut_type_ref::<tcb_t>(cap.get_tcb_ptr()));
     }
+    perform_invocation(
+        decode_tcb(invLabel, length, cap, call, buffer),
+        |invocation| invoke_tcb(invocation, slot, buffer),
+    )
 }
 
-#[cfg(not(feature = "ENABLE_SMP"))]
-#[no_mangle]
-pub fn decode_tcb_invocation(
+fn decode_tcb(
     invLabel: MessageLabel,
     length: usize,
     cap: &cap_t,
-    slot: &mut cte_t,
     call: bool,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<tcb_invocation_t, exception_t> {
+    let thread = convert_to_checked_mut_type_ref::<tcb_t>(cap.get_tcb_ptr());
     match invLabel {
-        MessageLabel::TCBReadRegisters => decode_read_registers(cap, length, call, buffer),
-        MessageLabel::TCBWriteRegisters => decode_write_registers(cap, length, buffer),
-        MessageLabel::TCBCopyRegisters => decode_copy_registers(cap, length, buffer),
-        MessageLabel::TCBSuspend => {
-            set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-            invoke_tcb_suspend(convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr()))
-        }
-        MessageLabel::TCBResume => {
-            set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-            invoke_tcb_resume(convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr()))
-        }
-        MessageLabel::TCBConfigure => decode_tcb_configure(cap, length, slot, buffer),
-        MessageLabel::TCBSetPriority => decode_set_priority(cap, length, buffer),
-        MessageLabel::TCBSetMCPriority => decode_set_mc_priority(cap, length, buffer),
-        MessageLabel::TCBSetSchedParams => decode_set_sched_params(cap, length, buffer),
-        MessageLabel::TCBSetIPCBuffer => decode_set_ipc_buffer(cap, length, slot, buffer),
-        MessageLabel::TCBSetSpace => decode_set_space(cap, length, slot, buffer),
-        MessageLabel::TCBBindNotification => decode_bind_notification(cap),
-        MessageLabel::TCBUnbindNotification => decode_unbind_notification(cap),
-        MessageLabel::TCBSetTLSBase => decode_set_tls_base(cap, length, buffer),
+        MessageLabel::TCBReadRegisters => decode_read_registers(thread, length, call, buffer),
+        MessageLabel::TCBWriteRegisters => decode_write_registers(thread, length, buffer),
+        MessageLabel::TCBCopyRegisters => decode_copy_registers(thread, length, buffer),
+        MessageLabel::TCBSuspend => Ok(tcb_invocation_t::Suspend(thread)),
+        MessageLabel::TCBResume => Ok(tcb_invocation_t::Resume(thread)),
+        MessageLabel::TCBConfigure => decode_tcb_configure(thread, length, buffer),
+        MessageLabel::TCBSetPriority => decode_set_priority(thread, length, buffer),
+        MessageLabel::TCBSetMCPriority => decode_set_mc_priority(thread, length, buffer),
+        MessageLabel::TCBSetSchedParams => decode_set_sched_params(thread, length, buffer),
+        MessageLabel::TCBSetIPCBuffer => decode_set_ipc_buffer(thread, length, buffer),
+        MessageLabel::TCBSetSpace => decode_set_space(thread, length, buffer),
+        MessageLabel::TCBBindNotification => decode_bind_notification(thread),
+        MessageLabel::TCBUnbindNotification => decode_unbind_notification(thread),
+        #[cfg(all(feature = "ENABLE_SMP", not(feature = "ENABLE_MCS")))]
+        MessageLabel::TCBSetAffinity => decode_set_affinity(thread, length, buffer),
+        MessageLabel::TCBSetTLSBase => decode_set_tls_base(thread, length, buffer),
+        #[cfg(feature = "ENABLE_MCS")]
+        MessageLabel::TCBSetTimeoutEndpoint => decode_set_timeout_endpoint(thread, length, buffer),
+        #[cfg(feature = "ENABLE_THREAD_ID")]
+        MessageLabel::TCBSetThreadID => decode_set_thread_id(thread, length, buffer),
+        #[cfg(feature = "ENABLE_CPU_LIMIT")]
+        MessageLabel::TCBSetCPULimit => decode_set_cpu_limit(thread, length, buffer),
+        MessageLabel::TCBCheckpointSave => decode_checkpoint(thread, true, call),
+        MessageLabel::TCBCheckpointRestore => decode_checkpoint(thread, false, call),
         _ => unsafe {
             debug!("TCB: Illegal operation invLabel :{:?}", invLabel);
             current_syscall_error._type = seL4_IllegalOperation;
-            exception_t::EXCEPTION_SYSCALL_ERROR
+            Err(exception_t::EXCEPTION_SYSCALL_ERROR)
         },
     }
 }
 
 fn decode_read_registers(
-    cap: &cap_t,
+    thread: &'static mut tcb_t,
     length: usize,
     call: bool,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<tcb_invocation_t, exception_t> {
     if length < 2 {
-        debug!("TCB CopyRegisters: Truncated message.");
+        debug!("TCB ReadRegisters: Truncated message.");
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let flags = get_syscall_arg(0, buffer);
     let n = get_syscall_arg(1, buffer);
@@ -142,32 +130,35 @@ fn decode_read_registers(
             current_syscall_error._type = seL4_RangeError;
             current_syscall_error.rangeErrorMin = 1;
             current_syscall_error.rangeErrorMax = frameRegNum + gpRegNum;
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
         }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
-    let thread = convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr());
     if thread.is_current() {
         debug!("TCB ReadRegisters: Attempted to read our own registers.");
         unsafe {
             current_syscall_error._type = seL4_IllegalOperation;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
-    set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-    invoke_tcb_read_registers(thread, flags & BIT!(ReadRegisters_suspend), n, 0, call)
+    Ok(tcb_invocation_t::ReadRegisters {
+        src: thread,
+        suspend_source: flags & BIT!(ReadRegisters_suspend),
+        n,
+        call,
+    })
 }
 
 fn decode_write_registers(
-    cap: &cap_t,
+    thread: &'static mut tcb_t,
     length: usize,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<tcb_invocation_t, exception_t> {
     if length < 2 {
+        debug!("TCB WriteRegisters: Truncated message.");
         unsafe {
-            debug!("TCB CopyRegisters: Truncated message.");
             current_syscall_error._type = seL4_TruncatedMessage;
-            return exception_t::EXCEPTION_SYSCALL_ERROR;
         }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let flags = get_syscall_arg(0, buffer);
     let w = get_syscall_arg(1, buffer);
@@ -181,262 +172,204 @@ fn decode_write_registers(
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
-    let thread = convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr());
     if thread.is_current() {
         debug!("TCB WriteRegisters: Attempted to write our own registers.");
         unsafe {
             current_syscall_error._type = seL4_IllegalOperation;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
-    set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-    invoke_tcb_write_registers(thread, flags & BIT!(0), w, 0, buffer)
+    Ok(tcb_invocation_t::WriteRegisters {
+        dest: thread,
+        resume_target: flags & BIT!(0),
+        n: w,
+    })
 }
 
 fn decode_copy_registers(
-    cap: &cap_t,
-    _length: usize,
+    thread: &'static mut tcb_t,
+    length: usize,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<tcb_invocation_t, exception_t> {
+    if length < 1 || get_extra_cap_by_index(0).is_none() {
+        debug!("TCB CopyRegisters: Truncated message.");
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
     let flags = get_syscall_arg(0, buffer);
 
     let source_cap = get_extra_cap_by_index(0).unwrap().cap;
-
-    if cap.get_cap_type() != CapTag::CapThreadCap {
-        debug!("TCB CopyRegisters: Truncated message.");
+    if source_cap.get_cap_type() != CapTag::CapThreadCap {
+        debug!("TCB CopyRegisters: Invalid source TCB.");
         unsafe {
-            current_syscall_error._type = seL4_TruncatedMessage;
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 1;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
-    let src_tcb = convert_to_mut_type_ref::<tcb_t>(source_cap.get_tcb_ptr());
-    return invoke_tcb_copy_registers(
-        convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr()),
-        src_tcb,
-        flags & BIT!(CopyRegisters_suspendSource),
-        flags & BIT!(CopyRegisters_resumeTarget),
-        flags & BIT!(CopyRegisters_transferFrame),
-        flags & BIT!(CopyRegisters_transferInteger),
-        0,
-    );
+    Ok(tcb_invocation_t::CopyRegisters {
+        dest: thread,
+        src: convert_to_checked_mut_type_ref::<tcb_t>(source_cap.get_tcb_ptr()),
+        suspend_source: flags & BIT!(CopyRegisters_suspendSource),
+        resume_target: flags & BIT!(CopyRegisters_resumeTarget),
+        transfer_frame: flags & BIT!(CopyRegisters_transferFrame),
+        transfer_integer: flags & BIT!(CopyRegisters_transferInteger),
+    })
 }
 
 fn decode_tcb_configure(
-    target_thread_cap: &cap_t,
+    target_thread: &'static mut tcb_t,
     msg_length: usize,
-    target_thread_slot: &mut cte_t,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<tcb_invocation_t, exception_t> {
     if msg_length < 4
         || get_extra_cap_by_index(0).is_none()
         || get_extra_cap_by_index(1).is_none()
         || get_extra_cap_by_index(2).is_none()
     {
-        debug!("TCB CopyRegisters: Truncated message.");
+        debug!("TCB Configure: Truncated message.");
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let fault_ep = get_syscall_arg(0, buffer);
     let croot_data = get_syscall_arg(1, buffer);
     let vroot_data = get_syscall_arg(2, buffer);
     let new_buffer_addr = get_syscall_arg(3, buffer);
-    let croot_slot = get_extra_cap_by_index(0).unwrap();
-    let mut croot_cap = croot_slot.cap;
-    let vroot_slot = get_extra_cap_by_index(1).unwrap();
-    let mut vroot_cap = vroot_slot.cap;
 
-    let (buffer_slot, buffer_cap) = {
-        let mut cap = get_extra_cap_by_index(2).unwrap().cap;
-        let mut buffer_slot_inner = if new_buffer_addr == 0 {
+    let ipc_buffer = {
+        let buffer_slot = get_extra_cap_by_index(2).unwrap();
+        let mut cap = buffer_slot.cap;
+        let slot = if new_buffer_addr == 0 {
             None
         } else {
-            get_extra_cap_by_index(2)
-        };
-        if let Some(buffer_slot) = buffer_slot_inner.as_deref_mut() {
             let dc_ret = buffer_slot.derive_cap(&cap);
             if dc_ret.status != exception_t::EXCEPTION_NONE {
                 unsafe {
                     current_syscall_error._type = seL4_IllegalOperation;
                 }
-                return dc_ret.status;
+                return Err(dc_ret.status);
             }
             cap = dc_ret.cap;
             let status = check_ipc_buffer_vaild(new_buffer_addr, &cap);
             if status != exception_t::EXCEPTION_NONE {
-                return status;
+                return Err(status);
             }
+            Some(buffer_slot)
+        };
+        tcb_ipc_buffer_t {
+            addr: new_buffer_addr,
+            cap,
+            slot,
         }
-        (buffer_slot_inner, cap)
     };
-    let target_thread = convert_to_mut_type_ref::<tcb_t>(target_thread_cap.get_tcb_ptr());
-    if target_thread.get_cspace(tcbCTable).is_long_running_delete()
-        || target_thread.get_cspace(tcbVTable).is_long_running_delete()
-    {
-        debug!("TCB Configure: CSpace or VSpace currently being deleted.");
-        unsafe {
-            current_syscall_error._type = seL4_IllegalOperation;
-        }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
-    }
+    let space = decode_space(target_thread, fault_ep, croot_data, vroot_data)?;
 
-    match decode_set_space_args(croot_data, croot_cap, croot_slot) {
-        Ok(cap) => croot_cap = cap,
-        Err(status) => return status,
-    }
-    if croot_cap.get_cap_type() != CapTag::CapCNodeCap {
-        debug!("TCB Configure: CSpace cap is invalid.");
-        unsafe {
-            current_syscall_error._type = seL4_IllegalOperation;
-        }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
-    }
-    match decode_set_space_args(vroot_data, vroot_cap, vroot_slot) {
-        Ok(cap) => vroot_cap = cap,
-        Err(status) => return status,
-    }
-    #[cfg(target_arch = "riscv64")]
-    if !is_valid_vtable_root(&vroot_cap) {
-        unsafe {
-            current_syscall_error._type = seL4_IllegalOperation;
-        }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
-    }
-    #[cfg(target_arch = "aarch64")]
-    if !vroot_cap.is_valid_vtable_root() {
+    Ok(tcb_invocation_t::ThreadControl {
+        target: target_thread,
+        space: Some(space),
+        ipc_buffer: Some(ipc_buffer),
+    })
+}
+
+/// The authority TCB of SetPriority, SetMCPriority and SetSchedParams, the first extra cap
+fn decode_auth_tcb(name: &str) -> Result<&'static mut tcb_t, exception_t> {
+    let auth_cap = get_extra_cap_by_index(0).unwrap().cap;
+    if auth_cap.get_cap_type() != CapTag::CapThreadCap {
+        debug!("{}: authority cap not a TCB.", name);
         unsafe {
-            current_syscall_error._type = seL4_IllegalOperation;
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 1;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
-
-    set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-    let status = invoke_tcb_set_space(
-        target_thread,
-        target_thread_slot,
-        fault_ep,
-        croot_cap,
-        croot_slot,
-        vroot_cap,
-        vroot_slot,
-    );
-    if status != exception_t::EXCEPTION_NONE {
-        return status;
-    }
-
-    invoke_tcb_set_ipc_buffer(
-        target_thread,
-        target_thread_slot,
-        new_buffer_addr,
-        buffer_cap,
-        buffer_slot,
-    )
+    Ok(convert_to_checked_mut_type_ref::<tcb_t>(
+        auth_cap.get_tcb_ptr(),
+    ))
 }
 
-fn decode_set_priority(cap: &cap_t, length: usize, buffer: Option<&seL4_IPCBuffer>) -> exception_t {
+fn decode_set_priority(
+    thread: &'static mut tcb_t,
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<tcb_invocation_t, exception_t> {
     if length < 1 || get_extra_cap_by_index(0).is_none() {
         debug!("TCB SetPriority: Truncated message.");
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let new_prio = get_syscall_arg(0, buffer);
-    let auth_cap = get_extra_cap_by_index(0).unwrap().cap;
-    if auth_cap.get_cap_type() != CapTag::CapThreadCap {
-        debug!("Set priority: authority cap not a TCB.");
-        unsafe {
-            current_syscall_error._type = seL4_InvalidCapability;
-            current_syscall_error.invalidCapNumber = 1;
-        }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
-    }
-    let auth_tcb = convert_to_mut_type_ref::<tcb_t>(auth_cap.get_tcb_ptr());
+    let auth_tcb = decode_auth_tcb("Set priority")?;
     let status = check_prio(new_prio, auth_tcb);
     if status != exception_t::EXCEPTION_NONE {
-        return status;
+        return Err(status);
     }
-    set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-    invoke_tcb_set_priority(
-        convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr()),
-        new_prio,
-    )
+    Ok(tcb_invocation_t::SetPriority {
+        target: thread,
+        prio: new_prio,
+    })
 }
 
 fn decode_set_mc_priority(
-    cap: &cap_t,
+    thread: &'static mut tcb_t,
     length: usize,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<tcb_invocation_t, exception_t> {
     if length < 1 || get_extra_cap_by_index(0).is_none() {
         debug!("TCB SetMCPPriority: Truncated message.");
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let new_mcp = get_syscall_arg(0, buffer);
-    let auth_cap = get_extra_cap_by_index(0).unwrap().cap;
-    if auth_cap.get_cap_type() != CapTag::CapThreadCap {
-        debug!("SetMCPriority: authority cap not a TCB.");
-        unsafe {
-            current_syscall_error._type = seL4_InvalidCapability;
-            current_syscall_error.invalidCapNumber = 1;
-        }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
-    }
-
-    let auth_tcb = convert_to_mut_type_ref::<tcb_t>(auth_cap.get_tcb_ptr());
+    let auth_tcb = decode_auth_tcb("SetMCPriority")?;
     let status = check_prio(new_mcp, auth_tcb);
     if status != exception_t::EXCEPTION_NONE {
         debug!(
             "TCB SetMCPriority: Requested maximum controlled priority {} too high (max {}).",
             new_mcp, auth_tcb.tcbMCP
         );
-        return status;
+        return Err(status);
     }
-    set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-    invoke_tcb_set_mcp(convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr()), new_mcp)
+    Ok(tcb_invocation_t::SetMCPriority {
+        target: thread,
+        mcp: new_mcp,
+    })
 }
 
 fn decode_set_sched_params(
-    cap: &cap_t,
+    thread: &'static mut tcb_t,
     length: usize,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
-    if length < 2 || get_extra_cap_by_index(0).is_some() {
+) -> Result<tcb_invocation_t, exception_t> {
+    if length < 2 || get_extra_cap_by_index(0).is_none() {
         debug!("TCB SetSchedParams: Truncated message.");
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let new_mcp = get_syscall_arg(0, buffer);
     let new_prio = get_syscall_arg(1, buffer);
-    let auth_cap = get_extra_cap_by_index(0).unwrap().cap;
-    if auth_cap.get_cap_type() != CapTag::CapThreadCap {
-        debug!("SetSchedParams: authority cap not a TCB.");
-        unsafe {
-            current_syscall_error._type = seL4_InvalidCapability;
-            current_syscall_error.invalidCapNumber = 1;
-        }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
-    }
-
-    let auth_tcb = convert_to_mut_type_ref::<tcb_t>(auth_cap.get_tcb_ptr());
+    let auth_tcb = decode_auth_tcb("SetSchedParams")?;
     let status = check_prio(new_mcp, auth_tcb);
     if status != exception_t::EXCEPTION_NONE {
         debug!(
             "TCB SetSchedParams: Requested maximum controlled priority {} too high (max {}).",
             new_mcp, auth_tcb.tcbMCP
         );
-        return status;
+        return Err(status);
     }
     let status = check_prio(new_prio, auth_tcb);
     if status != exception_t::EXCEPTION_NONE {
@@ -444,32 +377,35 @@ fn decode_set_sched_params(
             "TCB SetSchedParams: Requested priority {} too high (max {}).",
             new_prio, auth_tcb.tcbMCP
         );
-        return status;
+        return Err(status);
     }
-
-    set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-    let target = convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr());
-    invoke_tcb_set_mcp(target, new_mcp);
-    invoke_tcb_set_priority(target, new_prio)
+    Ok(tcb_invocation_t::SetSchedParams {
+        target: thread,
+        mcp: new_mcp,
+        prio: new_prio,
+    })
 }
 
 fn decode_set_ipc_buffer(
-    cap: &cap_t,
+    thread: &'static mut tcb_t,
     length: usize,
-    slot: &mut cte_t,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<tcb_invocation_t, exception_t> {
     if length < 1 || get_extra_cap_by_index(0).is_none() {
         debug!("TCB SetIPCBuffer: Truncated message.");
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let buffer_addr = get_syscall_arg(0, buffer);
-    let (buffer_slot, buffer_cap) = if buffer_addr == 0 {
-        (None, cap_t::new_null_cap())
+    let ipc_buffer = if buffer_addr == 0 {
+        tcb_ipc_buffer_t {
+            addr: 0,
+            cap: cap_t::new_null_cap(),
+            slot: None,
+        }
     } else {
         let slot = get_extra_cap_by_index(0).unwrap();
         let cap = slot.cap;
@@ -478,46 +414,59 @@ fn decode_set_ipc_buffer(
             unsafe {
                 current_syscall_error._type = seL4_IllegalOperation;
             }
-            return dc_ret.status;
+            return Err(dc_ret.status);
         }
         let status = check_ipc_buffer_vaild(buffer_addr, &dc_ret.cap);
         if status != exception_t::EXCEPTION_NONE {
-            return status;
+            return Err(status);
+        }
+        tcb_ipc_buffer_t {
+            addr: buffer_addr,
+            cap: dc_ret.cap,
+            slot: Some(slot),
         }
-        (Some(slot), dc_ret.cap)
     };
 
-    set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-    invoke_tcb_set_ipc_buffer(
-        convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr()),
-        slot,
-        buffer_addr,
-        buffer_cap,
-        buffer_slot,
-    )
+    Ok(tcb_invocation_t::ThreadControl {
+        target: thread,
+        space: None,
+        ipc_buffer: Some(ipc_buffer),
+    })
 }
 
 fn decode_set_space(
-    cap: &cap_t,
+    thread: &'static mut tcb_t,
     length: usize,
-    slot: &mut cte_t,
     buffer: Option<&seL4_IPCBuffer>,
-) -> exception_t {
+) -> Result<tcb_invocation_t, exception_t> {
     if length < 3 || get_extra_cap_by_index(0).is_none() || get_extra_cap_by_index(1).is_none() {
         debug!("TCB SetSpace: Truncated message.");
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let fault_ep = get_syscall_arg(0, buffer);
     let croot_data = get_syscall_arg(1, buffer);
     let vroot_data = get_syscall_arg(2, buffer);
+    let space = decode_space(thread, fault_ep, croot_data, vroot_data)?;
+
+    Ok(tcb_invocation_t::ThreadControl {
+        target: thread,
+        space: Some(space),
+        ipc_buffer: None,
+    })
+}
+
+/// The new roots of Configure and SetSpace, the first two extra caps, derived and checked
+fn decode_space(
+    target_thread: &mut tcb_t,
+    fault_ep: usize,
+    croot_data: usize,
+    vroot_data: usize,
+) -> Result<tcb_space_t, exception_t> {
     let croot_slot = get_extra_cap_by_index(0).unwrap();
-    let mut croot_cap = croot_slot.cap;
     let vroot_slot = get_extra_cap_by_index(1).unwrap();
-    let mut vroot_cap = vroot_slot.cap;
-    let target_thread = convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr());
     if target_thread.get_cspace(tcbCTable).is_long_running_delete()
         || target_thread.get_cspace(tcbVTable).is_long_running_delete()
     {
@@ -525,68 +474,47 @@ fn decode_set_space(
         unsafe {
             current_syscall_error._type = seL4_IllegalOperation;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
-    match decode_set_space_args(croot_data, croot_cap, croot_slot) {
-        Ok(cap) => croot_cap = cap,
-        Err(status) => return status,
-    }
+    let croot_cap = decode_set_space_args(croot_data, croot_slot.cap, croot_slot)?;
     if croot_cap.get_cap_type() != CapTag::CapCNodeCap {
         debug!("TCB Configure: CSpace cap is invalid.");
         unsafe {
             current_syscall_error._type = seL4_IllegalOperation;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
-    }
-
-    match decode_set_space_args(vroot_data, vroot_cap, vroot_slot) {
-        Ok(cap) => vroot_cap = cap,
-        Err(status) => return status,
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
-    #[cfg(target_arch = "riscv64")]
-    if !is_valid_vtable_root(&vroot_cap) {
-        unsafe {
-            current_syscall_error._type = seL4_IllegalOperation;
-        }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
-    }
-    #[cfg(target_arch = "aarch64")]
-    if !vroot_cap.is_valid_vtable_root() {
-        unsafe {
-            current_syscall_error._type = seL4_IllegalOperation;
-        }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+    let vroot_cap = decode_set_space_args(vroot_data, vroot_slot.cap, vroot_slot)?;
+    let status = check_vspace_root(&vroot_cap);
+    if status != exception_t::EXCEPTION_NONE {
+        return Err(status);
     }
 
-    set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-    invoke_tcb_set_space(
-        target_thread,
-        slot,
+    Ok(tcb_space_t {
         fault_ep,
         croot_cap,
         croot_slot,
         vroot_cap,
         vroot_slot,
-    )
+    })
 }
 
-fn decode_bind_notification(cap: &cap_t) -> exception_t {
+fn decode_bind_notification(tcb: &'static mut tcb_t) -> Result<tcb_invocation_t, exception_t> {
     if get_extra_cap_by_index(0).is_none() {
         debug!("TCB BindNotification: Truncated message.");
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
-    let tcb = convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr());
     if tcb.tcbBoundNotification != 0 {
         debug!("TCB BindNotification: TCB already has a bound notification.");
         unsafe {
             current_syscall_error._type = seL4_IllegalOperation;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let ntfn_cap = get_extra_cap_by_index(0).unwrap().cap;
@@ -595,17 +523,17 @@ fn decode_bind_notification(cap: &cap_t) -> exception_t {
         unsafe {
             current_syscall_error._type = seL4_IllegalOperation;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
-    let ntfn = convert_to_mut_type_ref::<notification_t>(ntfn_cap.get_nf_ptr());
+    let ntfn = convert_to_checked_mut_type_ref::<notification_t>(ntfn_cap.get_nf_ptr());
 
     if ntfn_cap.get_nf_can_receive() == 0 {
         debug!("TCB BindNotification: Insufficient access rights");
         unsafe {
             current_syscall_error._type = seL4_IllegalOperation;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     if ntfn.get_queue_head() != 0 || ntfn.get_queue_tail() != 0 {
@@ -613,62 +541,177 @@ fn decode_bind_notification(cap: &cap_t) -> exception_t {
         unsafe {
             current_syscall_error._type = seL4_IllegalOperation;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
-    set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-    invoke_tcb_bind_notification(tcb, ntfn)
+    Ok(tcb_invocation_t::BindNotification { tcb, ntfn })
 }
 
-fn decode_unbind_notification(cap: &cap_t) -> exception_t {
-    let tcb = convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr());
+fn decode_unbind_notification(tcb: &'static mut tcb_t) -> Result<tcb_invocation_t, exception_t> {
     if tcb.tcbBoundNotification == 0 {
         debug!("TCB BindNotification: TCB already has no bound Notification.");
         unsafe {
             current_syscall_error._type = seL4_IllegalOperation;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
-    set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-    invoke_tcb_unbind_notification(tcb)
+    Ok(tcb_invocation_t::UnbindNotification(tcb))
 }
 
-#[cfg(feature = "ENABLE_SMP")]
-fn decode_set_affinity(cap: &cap_t, length: usize, buffer: Option<&seL4_IPCBuffer>) -> exception_t {
-    use sel4_common::sel4_config::CONFIG_MAX_NUM_NODES;
-
+#[cfg(all(feature = "ENABLE_SMP", not(feature = "ENABLE_MCS")))]
+fn decode_set_affinity(
+    tcb: &'static mut tcb_t,
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<tcb_invocation_t, exception_t> {
     if length < 1 {
         debug!("TCB SetAffinity: Truncated message.");
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let affinity = get_syscall_arg(0, buffer);
-    if affinity > CONFIG_MAX_NUM_NODES {
+    #[cfg(feature = "ENABLE_SMP_LOAD_BALANCE")]
+    if affinity == sel4_task::balance::AFFINITY_ANY_CORE {
+        return Ok(tcb_invocation_t::SetAffinity { tcb, affinity });
+    }
+    if affinity >= *crate::boot::ksNumCPUs.lock() {
         debug!("TCB SetAffinity: Requested CPU does not exist.");
         unsafe {
             current_syscall_error._type = seL4_IllegalOperation;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
-    set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-    let tcb = convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr());
-    invoke_tcb_set_affinity(tcb, affinity)
+    Ok(tcb_invocation_t::SetAffinity { tcb, affinity })
 }
 
-fn decode_set_tls_base(cap: &cap_t, length: usize, buffer: Option<&seL4_IPCBuffer>) -> exception_t {
+/// The single word argument of SetTLSBase and the other setters which take one, `name` for the
+/// log
+fn decode_word_arg(
+    name: &str,
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<usize, exception_t> {
     if length < 1 {
-        debug!("TCB SetTLSBase: Truncated message.");
+        debug!("TCB {}: Truncated message.", name);
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
-    let base = get_syscall_arg(0, buffer);
-    set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-    invoke_tcb_set_tls_base(convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr()), base)
+    Ok(get_syscall_arg(0, buffer))
+}
+
+fn decode_set_tls_base(
+    tcb: &'static mut tcb_t,
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<tcb_invocation_t, exception_t> {
+    let base = decode_word_arg("SetTLSBase", length, buffer)?;
+    Ok(tcb_invocation_t::SetTLSBase { tcb, base })
+}
+
+#[cfg(feature = "ENABLE_THREAD_ID")]
+/// Decode TCBSetThreadID: the ID the thread reads back without a syscall, see `ENABLE_THREAD_ID`.
+fn decode_set_thread_id(
+    tcb: &'static mut tcb_t,
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<tcb_invocation_t, exception_t> {
+    let id = decode_word_arg("SetThreadID", length, buffer)?;
+    Ok(tcb_invocation_t::SetThreadID { tcb, id })
+}
+
+#[cfg(feature = "ENABLE_CPU_LIMIT")]
+/// Decode TCBSetCPULimit: the timer ticks the thread may still run for before it raises a timeout
+/// fault, 0 for no limit. See `ENABLE_CPU_LIMIT`.
+fn decode_set_cpu_limit(
+    tcb: &'static mut tcb_t,
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<tcb_invocation_t, exception_t> {
+    let ticks = decode_word_arg("SetCPULimit", length, buffer)?;
+    Ok(tcb_invocation_t::SetCPULimit { tcb, ticks })
+}
+
+#[cfg(feature = "ENABLE_MCS")]
+/// Decode TCBSetTimeoutEndpoint: the CPtr of the timeout handler in the CSpace of the thread, 0
+/// for none. The cap is looked up only when a timeout fault is raised, like the fault handler.
+fn decode_set_timeout_endpoint(
+    tcb: &'static mut tcb_t,
+    length: usize,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<tcb_invocation_t, exception_t> {
+    let timeout_ep = decode_word_arg("SetTimeoutEndpoint", length, buffer)?;
+    Ok(tcb_invocation_t::SetTimeoutEndpoint { tcb, timeout_ep })
+}
+
+fn decode_checkpoint(
+    thread: &'static mut tcb_t,
+    save: bool,
+    call: bool,
+) -> Result<tcb_invocation_t, exception_t> {
+    if get_extra_cap_by_index(0).is_none() {
+        debug!("TCB Checkpoint: Truncated message.");
+        unsafe {
+            current_syscall_error._type = seL4_TruncatedMessage;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let frame_cap = get_extra_cap_by_index(0).unwrap().cap;
+    if frame_cap.get_cap_type() != CapTag::CapFrameCap
+        || frame_cap.get_frame_is_device() != 0
+        || BIT!(pageBitsForSize(frame_cap.get_frame_size()))
+            < core::mem::size_of::<tcb_checkpoint_t>()
+        || (save && frame_cap.get_frame_vm_rights() != vm_rights_t::VMReadWrite as usize)
+    {
+        debug!("TCB Checkpoint: Invalid frame cap.");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidCapability;
+            current_syscall_error.invalidCapNumber = 1;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    if thread.is_current() || thread.get_state() != ThreadState::ThreadStateInactive {
+        debug!("TCB Checkpoint: Thread must be suspended.");
+        unsafe {
+            current_syscall_error._type = seL4_IllegalOperation;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    let record = convert_to_mut_type_ref::<tcb_checkpoint_t>(frame_cap.get_frame_base_ptr());
+    if save {
+        return Ok(tcb_invocation_t::CheckpointSave {
+            src: thread,
+            record,
+            call,
+        });
+    }
+    let fault_type = record.fault.get_type();
+    if record.magic != TCB_CHECKPOINT_MAGIC
+        || !matches!(
+            fault_type,
+            seL4_Fault_NullFault
+                | seL4_Fault_CapFault
+                | seL4_Fault_UnknownSyscall
+                | seL4_Fault_UserException
+                | seL4_Fault_VMFault
+        )
+        || record.lookup_failure.get_type() > lookup_fault_guard_mismatch
+    {
+        debug!("TCB CheckpointRestore: Invalid checkpoint record.");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidArgument;
+            current_syscall_error.invalidArgumentNumber = 0;
+        }
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    Ok(tcb_invocation_t::CheckpointRestore {
+        dest: thread,
+        record,
+    })
 }
 
 #[inline]
@@ -691,3 +734,52 @@ fn decode_set_space_args(
     ret_root_cap = dc_ret.cap;
     return Ok(ret_root_cap);
 }
+
+/// Check that `vroot_cap` can become the vspace of a thread: a vspace root assigned to an ASID,
+/// which the ASID table still maps to it. A root keeps its ASID when the ASID pool is deleted, such
+/// a root is refused here, so the thread is not left to run without an address space.
+fn check_vspace_root(vroot_cap: &cap_t) -> exception_t {
+    #[cfg(target_arch = "riscv64")]
+    let (valid, root, asid) = (
+        is_valid_vtable_root(vroot_cap),
+        vroot_cap.get_pt_base_ptr(),
+        vroot_cap.get_pt_mapped_asid(),
+    );
+    #[cfg(target_arch = "aarch64")]
+    let (valid, root, asid) = (
+        vroot_cap.is_valid_vtable_root(),
+        vroot_cap.get_pgd_base_ptr(),
+        vroot_cap.get_pgd_mapped_asid(),
+    );
+    if !valid {
+        unsafe {
+            current_syscall_error._type = seL4_IllegalOperation;
+        }
+        return exception_t::EXCEPTION_SYSCALL_ERROR;
+    }
+    let find_ret = find_vspace_for_asid(asid);
+    match find_ret.vspace_root {
+        Some(found) if find_ret.status == exception_t::EXCEPTION_NONE && found as usize == root => {
+            exception_t::EXCEPTION_NONE
+        }
+        Some(_) => {
+            debug!("TCB: the ASID of the VSpace cap was reassigned.");
+            unsafe {
+                current_syscall_error._type = seL4_InvalidCapability;
+                current_syscall_error.invalidCapNumber = 2;
+            }
+            exception_t::EXCEPTION_SYSCALL_ERROR
+        }
+        None => {
+            debug!("TCB: the ASID of the VSpace cap is no longer assigned.");
+            unsafe {
+                current_syscall_error._type = seL4_FailedLookup;
+                current_syscall_error.failedLookupWasSource = 0;
+                current_lookup_fault = find_ret
+                    .lookup_fault
+                    .unwrap_or(lookup_fault_t::new_root_invalid());
+            }
+            exception_t::EXCEPTION_SYSCALL_ERROR
+        }
+    }
+}
@@ -8,14 +8,17 @@ use sel4_common::{
     utils::convert_to_mut_type_ref,
 };
 use sel4_cspace::interface::{cap_t, cte_t, CapTag};
-use sel4_task::{get_currenct_thread, set_thread_state, ThreadState};
 
 use crate::syscall::{alignUp, FREE_INDEX_TO_OFFSET, GET_FREE_REF};
 use crate::{
     config::CONFIG_RETYPE_FAN_OUT_LIMIT,
     kernel::boot::{current_lookup_fault, current_syscall_error, get_extra_cap_by_index},
     syscall::{
-        get_syscall_arg, invocation::invoke_untyped::invoke_untyped_retype,
+        get_syscall_arg,
+        invocation::{
+            invoke_untyped::{invoke_untyped, untyped_invocation_t},
+            perform_invocation,
+        },
         lookup_slot_for_cnode_op,
     },
 };
@@ -25,14 +28,31 @@ pub fn decode_untyed_invocation(
     length: usize,
     slot: &mut cte_t,
     cap: &cap_t,
+    call: bool,
     buffer: Option<&seL4_IPCBuffer>,
 ) -> exception_t {
+    perform_invocation(
+        decode_untyped(inv_label, length, slot, cap, buffer),
+        |invocation| invoke_untyped(slot, invocation, call),
+    )
+}
+
+fn decode_untyped(
+    inv_label: MessageLabel,
+    length: usize,
+    slot: &cte_t,
+    cap: &cap_t,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> Result<untyped_invocation_t, exception_t> {
+    if inv_label == MessageLabel::UntypedDescribe {
+        return Ok(untyped_invocation_t::Describe);
+    }
     if inv_label != MessageLabel::UntypedRetype {
         debug!("Untyped cap: Illegal operation attempted.");
         unsafe {
             current_syscall_error._type = seL4_IllegalOperation;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     if length < 6 || get_extra_cap_by_index(0).is_none() {
@@ -40,7 +60,7 @@ pub fn decode_untyed_invocation(
         unsafe {
             current_syscall_error._type = seL4_TruncatedMessage;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let op_new_type = ObjectType::from_usize(get_syscall_arg(0, buffer));
@@ -53,7 +73,7 @@ pub fn decode_untyed_invocation(
             current_syscall_error._type = seL4_InvalidArgument;
             current_syscall_error.invalidArgumentNumber = 0;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let new_type = op_new_type.unwrap();
     let user_obj_size = get_syscall_arg(1, buffer);
@@ -72,22 +92,22 @@ pub fn decode_untyed_invocation(
             current_syscall_error.rangeErrorMin = 0;
             current_syscall_error.rangeErrorMax = seL4_MaxUntypedBits;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
     let status = check_object_type(new_type, user_obj_size);
     if status != exception_t::EXCEPTION_NONE {
-        return status;
+        return Err(status);
     }
     let mut node_cap = cap_t::default();
     let status = get_target_cnode(node_index, node_depth, &mut node_cap);
     if status != exception_t::EXCEPTION_NONE {
-        return status;
+        return Err(status);
     }
 
     let status = check_cnode_slot(&node_cap, node_offset, node_window);
     if status != exception_t::EXCEPTION_NONE {
-        return status;
+        return Err(status);
     }
 
     let status = slot.ensure_no_children();
@@ -119,32 +139,30 @@ pub fn decode_untyed_invocation(
             current_syscall_error._type = seL4_NotEnoughMemory;
             current_syscall_error.memoryLeft = untyped_free_bytes;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
 
-    let device_mem = cap.get_frame_is_device() != 0;
+    let device_mem = cap.get_untyped_is_device() != 0;
     if device_mem && new_type.is_arch_type() && new_type != ObjectType::UnytpedObject {
         debug!("Untyped Retype: Creating kernel objects with device untyped");
         unsafe {
             current_syscall_error._type = seL4_InvalidArgument;
             current_syscall_error.invalidArgumentNumber = 1;
         }
-        return exception_t::EXCEPTION_SYSCALL_ERROR;
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
     }
     let aligned_free_ref = alignUp(free_ref, obj_size);
 
-    set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-    invoke_untyped_retype(
-        slot,
+    Ok(untyped_invocation_t::Retype {
         reset,
-        aligned_free_ref,
+        retype_base: aligned_free_ref,
         new_type,
-        user_obj_size,
-        convert_to_mut_type_ref::<cte_t>(node_cap.get_cnode_ptr()),
-        node_offset,
-        node_window,
-        device_mem as usize,
-    )
+        user_size: user_obj_size,
+        dest_cnode: convert_to_mut_type_ref::<cte_t>(node_cap.get_cnode_ptr()),
+        dest_offset: node_offset,
+        dest_length: node_window,
+        device_mem: device_mem as usize,
+    })
 }
 
 #[inline]
@@ -158,6 +176,16 @@ fn check_object_type(new_type: ObjectType, user_obj_size: usize) -> exception_t
         return exception_t::EXCEPTION_SYSCALL_ERROR;
     }
 
+    #[cfg(feature = "ENABLE_MCS")]
+    if new_type == ObjectType::SchedContextObject && user_obj_size < seL4_MinSchedContextBits {
+        debug!("Untyped Retype: Requested SchedContext size too small.");
+        unsafe {
+            current_syscall_error._type = seL4_InvalidArgument;
+            current_syscall_error.invalidArgumentNumber = 1;
+        }
+        return exception_t::EXCEPTION_SYSCALL_ERROR;
+    }
+
     if new_type == ObjectType::UnytpedObject && user_obj_size < seL4_MinUntypedBits {
         debug!("Untyped Retype: Requested UntypedItem size too small.");
         unsafe {
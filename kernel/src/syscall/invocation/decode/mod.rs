@@ -1,6 +1,12 @@
 mod decode_cnode_invocation;
 mod decode_domain_invocation;
 pub mod decode_irq_invocation;
+#[cfg(feature = "ENABLE_MEMORY_HOTADD")]
+mod decode_memory_control_invocation;
+#[cfg(feature = "ENABLE_NTFN_WAIT_IF_EQUAL")]
+mod decode_notification_invocation;
+#[cfg(feature = "ENABLE_MCS")]
+mod decode_sched_context_invocation;
 
 pub mod arch;
 mod decode_tcb_invocation;
@@ -13,11 +19,11 @@ use sel4_common::{
     arch::MessageLabel,
     sel4_config::seL4_InvalidCapability,
     structures::{exception_t, seL4_IPCBuffer},
-    utils::convert_to_mut_type_ref,
+    utils::{convert_to_checked_mut_type_ref, convert_to_mut_type_ref},
 };
 use sel4_cspace::interface::{cap_t, cte_t, CapTag};
 use sel4_ipc::{endpoint_t, notification_t, Transfer};
-use sel4_task::{get_currenct_thread, set_thread_state, tcb_t, ThreadState};
+use sel4_task::{get_currenct_thread, tcb_t, ThreadStateMachine};
 
 use crate::kernel::boot::current_syscall_error;
 use crate::syscall::invocation::decode::decode_irq_invocation::decode_irq_handler_invocation;
@@ -66,8 +72,8 @@ pub fn decode_invocation(
                 }
                 return exception_t::EXCEPTION_SYSCALL_ERROR;
             }
-            set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-            convert_to_mut_type_ref::<endpoint_t>(cap.get_ep_ptr()).send_ipc(
+            get_currenct_thread().set_restart();
+            convert_to_checked_mut_type_ref::<endpoint_t>(cap.get_ep_ptr()).send_ipc(
                 get_currenct_thread(),
                 block,
                 call,
@@ -79,6 +85,12 @@ pub fn decode_invocation(
         }
 
         CapTag::CapNotificationCap => {
+            #[cfg(feature = "ENABLE_NTFN_WAIT_IF_EQUAL")]
+            if label == MessageLabel::NotificationWaitIfEqual {
+                return decode_notification_invocation::decode_notification_wait_if_equal(
+                    length, cap, block, call, buffer,
+                );
+            }
             if unlikely(cap.get_nf_can_send() == 0) {
                 debug!(
                     "Attempted to invoke a read-only notification cap {}.",
@@ -90,12 +102,21 @@ pub fn decode_invocation(
                 }
                 return exception_t::EXCEPTION_SYSCALL_ERROR;
             }
-            set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
-            convert_to_mut_type_ref::<notification_t>(cap.get_nf_ptr())
+            get_currenct_thread().set_restart();
+            convert_to_checked_mut_type_ref::<notification_t>(cap.get_nf_ptr())
                 .send_signal(cap.get_nf_badge());
             exception_t::EXCEPTION_NONE
         }
 
+        #[cfg(feature = "ENABLE_MCS")]
+        CapTag::CapReplyCap => {
+            get_currenct_thread().set_restart();
+            convert_to_mut_type_ref::<sel4_ipc::reply_t>(cap.get_reply_ptr())
+                .do_reply(get_currenct_thread(), cap.get_reply_can_grant() != 0);
+            exception_t::EXCEPTION_NONE
+        }
+
+        #[cfg(not(feature = "ENABLE_MCS"))]
         CapTag::CapReplyCap => {
             if unlikely(cap.get_reply_master() != 0) {
                 debug!("Attempted to invoke an invalid reply cap {}.", cap_index);
@@ -105,7 +126,7 @@ pub fn decode_invocation(
                     return exception_t::EXCEPTION_SYSCALL_ERROR;
                 }
             }
-            set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
+            get_currenct_thread().set_restart();
             get_currenct_thread().do_reply(
                 convert_to_mut_type_ref::<tcb_t>(cap.get_reply_tcb_ptr()),
                 slot,
@@ -116,9 +137,27 @@ pub fn decode_invocation(
         CapTag::CapThreadCap => decode_tcb_invocation(label, length, cap, slot, call, buffer),
         CapTag::CapDomainCap => decode_domain_invocation(label, length, buffer),
         CapTag::CapCNodeCap => decode_cnode_invocation(label, length, cap, buffer),
-        CapTag::CapUntypedCap => decode_untyed_invocation(label, length, slot, cap, buffer),
+        CapTag::CapUntypedCap => decode_untyed_invocation(label, length, slot, cap, call, buffer),
         CapTag::CapIrqControlCap => decode_irq_control_invocation(label, length, slot, buffer),
-        CapTag::CapIrqHandlerCap => decode_irq_handler_invocation(label, cap.get_irq_handler()),
+        CapTag::CapIrqHandlerCap => {
+            decode_irq_handler_invocation(label, length, cap.get_irq_handler(), buffer)
+        }
+        #[cfg(feature = "ENABLE_MEMORY_HOTADD")]
+        CapTag::CapMemoryControlCap => {
+            decode_memory_control_invocation::decode_memory_control_invocation(
+                label, length, buffer,
+            )
+        }
+        #[cfg(feature = "ENABLE_MCS")]
+        CapTag::CapSchedControlCap => {
+            decode_sched_context_invocation::decode_sched_control_invocation(
+                label, length, cap, buffer,
+            )
+        }
+        #[cfg(feature = "ENABLE_MCS")]
+        CapTag::CapSchedContextCap => {
+            decode_sched_context_invocation::decode_sched_context_invocation(label, cap, call)
+        }
         _ => decode_mmu_invocation(label, length, slot, call, buffer),
     }
 }
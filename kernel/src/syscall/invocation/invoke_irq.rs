@@ -1,26 +1,70 @@
 use sel4_common::structures::exception_t;
 use sel4_cspace::interface::{cap_t, cte_insert, cte_t};
 
-use crate::interrupt::{get_irq_handler_slot, set_irq_state, IRQState};
+use crate::interrupt::{
+    get_irq_handler_slot, mask_interrupt, set_irq_msi, set_irq_state, set_irq_target_core,
+    set_irq_trigger, IRQState,
+};
+
+/// An IRQControl invocation which passed its decode checks: issue a handler cap for `irq`.
+pub struct irq_control_invocation_t {
+    pub irq: usize,
+    /// Edge triggered for true, level triggered for false, as configured already for none
+    pub trigger: Option<bool>,
+    /// The ITS device and event IDs of the MSI to map to the LPI `irq`, see `ENABLE_GIC_ITS`
+    pub msi: Option<(usize, usize)>,
+    /// The core to route the IRQ to, by IRQIssueIRQHandlerTriggerCore
+    pub core: Option<usize>,
+    pub dest_slot: &'static mut cte_t,
+}
+
+/// An IRQHandler invocation which passed its decode checks, see `decode_irq_handler`.
+pub enum irq_handler_invocation_t {
+    /// Unmask the IRQ, which is masked from its delivery until the driver served it
+    Ack,
+    /// Deliver the IRQ to the notification `cap`, copied from `slot`
+    SetHandler {
+        cap: cap_t,
+        slot: &'static mut cte_t,
+    },
+    Clear,
+    /// Route the IRQ to the given core
+    SetCore(usize),
+}
 
 pub fn invoke_irq_control(
-    irq: usize,
-    handler_slot: &mut cte_t,
+    invocation: irq_control_invocation_t,
     control_slot: &mut cte_t,
 ) -> exception_t {
+    let irq = invocation.irq;
+    if let Some(edge) = invocation.trigger {
+        set_irq_trigger(irq, edge);
+    }
+    if let Some((device, event)) = invocation.msi {
+        set_irq_msi(irq, device, event);
+    }
+    if let Some(core) = invocation.core {
+        set_irq_target_core(irq, core);
+    }
     set_irq_state(IRQState::IRQSignal, irq);
-    cte_insert(&cap_t::new_irq_handler_cap(irq), control_slot, handler_slot);
+    cte_insert(
+        &cap_t::new_irq_handler_cap(irq),
+        control_slot,
+        invocation.dest_slot,
+    );
     exception_t::EXCEPTION_NONE
 }
 
-#[inline]
-pub fn invoke_set_irq_handler(irq: usize, cap: &cap_t, slot: &mut cte_t) {
-    let irq_slot = get_irq_handler_slot(irq);
-    irq_slot.delete_one();
-    cte_insert(cap, slot, irq_slot);
-}
-
-#[inline]
-pub fn invoke_clear_irq_handler(irq: usize) {
-    get_irq_handler_slot(irq).delete_one();
+pub fn invoke_irq_handler(irq: usize, invocation: irq_handler_invocation_t) -> exception_t {
+    match invocation {
+        irq_handler_invocation_t::Ack => mask_interrupt(false, irq),
+        irq_handler_invocation_t::SetHandler { cap, slot } => {
+            let irq_slot = get_irq_handler_slot(irq);
+            irq_slot.delete_one();
+            cte_insert(&cap, slot, irq_slot);
+        }
+        irq_handler_invocation_t::Clear => get_irq_handler_slot(irq).delete_one(),
+        irq_handler_invocation_t::SetCore(core) => set_irq_target_core(irq, core),
+    }
+    exception_t::EXCEPTION_NONE
 }
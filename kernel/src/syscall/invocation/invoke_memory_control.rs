@@ -0,0 +1,162 @@
+#[cfg(feature = "ENABLE_OBJECT_RELOCATE")]
+use crate::syscall::GET_FREE_INDEX;
+#[cfg(feature = "ENABLE_OBJECT_RELOCATE")]
+use sel4_common::{sel4_config::seL4_SlotBits, utils::convert_to_mut_type_ref, BIT};
+use sel4_common::{structures::exception_t, utils::MAX_FREE_INDEX};
+use sel4_cspace::interface::{cap_t, cte_insert, cte_t};
+#[cfg(feature = "ENABLE_OBJECT_RELOCATE")]
+use sel4_cspace::interface::{cspace_modified, CapTag};
+
+/// A MemoryControl invocation which passed its decode checks, see `decode_memory_control`.
+pub enum memory_control_invocation_t {
+    /// Turn the device untyped in `parent_slot` into RAM, inserted as its child in `dest_slot`.
+    AddUntyped {
+        parent_slot: &'static mut cte_t,
+        dest_slot: &'static mut cte_t,
+    },
+    /// Move the CNode of the cap in `cnode_slot` to `new_ptr` in the untyped of `untyped_slot`.
+    #[cfg(feature = "ENABLE_OBJECT_RELOCATE")]
+    RelocateCNode {
+        cnode_slot: &'static mut cte_t,
+        untyped_slot: &'static mut cte_t,
+        new_ptr: usize,
+    },
+}
+
+pub fn invoke_memory_control(invocation: memory_control_invocation_t) -> exception_t {
+    match invocation {
+        memory_control_invocation_t::AddUntyped {
+            parent_slot,
+            dest_slot,
+        } => invoke_add_untyped(parent_slot, dest_slot),
+        #[cfg(feature = "ENABLE_OBJECT_RELOCATE")]
+        memory_control_invocation_t::RelocateCNode {
+            cnode_slot,
+            untyped_slot,
+            new_ptr,
+        } => invoke_relocate_cnode(cnode_slot, untyped_slot, new_ptr),
+    }
+}
+
+/// Insert an untyped over the memory of the device untyped in `parent_slot` and mark the device
+/// untyped as full, so the memory is only ever handed out once.
+///
+/// The new untyped starts full as well: its first retype resets it, which clears memory the
+/// kernel has never seen. Revoking the device untyped removes it again.
+fn invoke_add_untyped(parent_slot: &mut cte_t, dest_slot: &mut cte_t) -> exception_t {
+    let size_bits = parent_slot.cap.get_untyped_block_size();
+    let ptr = parent_slot.cap.get_untyped_ptr();
+    parent_slot
+        .cap
+        .set_untyped_free_index(MAX_FREE_INDEX(size_bits));
+    cte_insert(
+        &cap_t::new_untyped_cap(MAX_FREE_INDEX(size_bits), 0, size_bits, ptr),
+        parent_slot,
+        dest_slot,
+    );
+    exception_t::EXCEPTION_NONE
+}
+
+/// Copy the CNode of the cap in `cnode_slot` to `new_ptr`, taken from the untyped in
+/// `untyped_slot`, and clear the old copy.
+///
+/// The MDB links of the copied slots and of their neighbours are moved along. The caps of the
+/// CNode are adjacent in the MDB like for `is_final_cap`; they are pointed at the copy and moved
+/// behind the untyped as its children, so the old untyped can be revoked and reused without
+/// deleting the CNode. With `ENABLE_OBJECT_GEN` the copy is stamped with a new generation.
+#[cfg(feature = "ENABLE_OBJECT_RELOCATE")]
+fn invoke_relocate_cnode(
+    cnode_slot: &mut cte_t,
+    untyped_slot: &mut cte_t,
+    new_ptr: usize,
+) -> exception_t {
+    let radix = cnode_slot.cap.get_cnode_radix();
+    let old_ptr = cnode_slot.cap.get_cnode_ptr();
+    let size = BIT!(radix + seL4_SlotBits);
+    let relocate = |ptr: usize| match (old_ptr..old_ptr + size).contains(&ptr) {
+        true => ptr - old_ptr + new_ptr,
+        false => ptr,
+    };
+    let is_moved = |ptr: usize| (new_ptr..new_ptr + size).contains(&ptr);
+
+    // The untyped may live in the CNode itself, update it before the copy
+    let base = untyped_slot.cap.get_untyped_ptr();
+    untyped_slot
+        .cap
+        .set_untyped_free_index(GET_FREE_INDEX(base, new_ptr + size));
+    let (cnode_slot, untyped_slot) = (
+        relocate(cnode_slot.get_ptr()),
+        relocate(untyped_slot.get_ptr()),
+    );
+    unsafe { core::ptr::copy_nonoverlapping(old_ptr as *const u8, new_ptr as *mut u8, size) };
+
+    let cnode = convert_to_mut_type_ref::<cte_t>(new_ptr);
+    for i in 0..BIT!(radix) {
+        let mdb = &mut cnode.get_offset_slot(i).cteMDBNode;
+        mdb.set_prev(relocate(mdb.get_prev()));
+        mdb.set_next(relocate(mdb.get_next()));
+    }
+    for i in 0..BIT!(radix) {
+        let slot = cnode.get_offset_slot(i);
+        let (prev, next) = (slot.cteMDBNode.get_prev(), slot.cteMDBNode.get_next());
+        if prev != 0 && !is_moved(prev) {
+            let prev = convert_to_mut_type_ref::<cte_t>(prev);
+            prev.cteMDBNode.set_next(slot.get_ptr());
+        }
+        if next != 0 && !is_moved(next) {
+            let next = convert_to_mut_type_ref::<cte_t>(next);
+            next.cteMDBNode.set_prev(slot.get_ptr());
+        }
+    }
+    unsafe { core::ptr::write_bytes(old_ptr as *mut u8, 0, size) };
+
+    // Point the caps of the CNode at the copy, from the first to the last
+    let refers_to_cnode = |slot: usize| {
+        let cap = &convert_to_mut_type_ref::<cte_t>(slot).cap;
+        cap.get_cap_type() == CapTag::CapCNodeCap && cap.get_cnode_ptr() == old_ptr
+    };
+    let mut first = convert_to_mut_type_ref::<cte_t>(cnode_slot);
+    while first.cteMDBNode.get_prev() != 0 && refers_to_cnode(first.cteMDBNode.get_prev()) {
+        first = convert_to_mut_type_ref::<cte_t>(first.cteMDBNode.get_prev());
+    }
+    let mut last = convert_to_mut_type_ref::<cte_t>(first.get_ptr());
+    loop {
+        last.cap.set_cnode_ptr(new_ptr);
+        let next = last.cteMDBNode.get_next();
+        if next == 0 || !refers_to_cnode(next) {
+            break;
+        }
+        last = convert_to_mut_type_ref::<cte_t>(next);
+    }
+
+    // Cut them out of the MDB and insert them behind the untyped
+    let (before, after) = (first.cteMDBNode.get_prev(), last.cteMDBNode.get_next());
+    if before != 0 {
+        convert_to_mut_type_ref::<cte_t>(before)
+            .cteMDBNode
+            .set_next(after);
+    }
+    if after != 0 {
+        convert_to_mut_type_ref::<cte_t>(after)
+            .cteMDBNode
+            .set_prev(before);
+    }
+    let untyped = convert_to_mut_type_ref::<cte_t>(untyped_slot);
+    let untyped_next = untyped.cteMDBNode.get_next();
+    first.cteMDBNode.set_prev(untyped.get_ptr());
+    last.cteMDBNode.set_next(untyped_next);
+    if untyped_next != 0 {
+        convert_to_mut_type_ref::<cte_t>(untyped_next)
+            .cteMDBNode
+            .set_prev(last.get_ptr());
+    }
+    untyped.cteMDBNode.set_next(first.get_ptr());
+    cspace_modified();
+
+    #[cfg(feature = "ENABLE_OBJECT_GEN")]
+    {
+        sel4_common::object_gen::object_gen_kill(old_ptr);
+        sel4_common::object_gen::object_gen_stamp(new_ptr);
+    }
+    exception_t::EXCEPTION_NONE
+}
@@ -1,32 +1,337 @@
 #[cfg(target_arch = "aarch64")]
 use core::arch::asm;
-use sel4_common::arch::ArchReg;
+use sel4_common::arch::{ArchReg, MessageLabel};
 #[cfg(target_arch = "aarch64")]
 use sel4_common::BIT;
 #[cfg(target_arch = "riscv64")]
+use sel4_common::{arch::maskVMRights, cap_rights::seL4_CapRights_t};
 use sel4_common::{
-    arch::maskVMRights,
-    cap_rights::seL4_CapRights_t,
-    utils::{pageBitsForSize, MAX_FREE_INDEX},
+    message_info::seL4_MessageInfo_t,
+    sel4_config::*,
+    structures::exception_t,
+    utils::{convert_to_mut_type_ref, MAX_FREE_INDEX},
     MASK,
 };
-use sel4_common::{
-    message_info::seL4_MessageInfo_t, sel4_config::*, structures::exception_t,
-    utils::convert_to_mut_type_ref,
+use sel4_cspace::interface::{cap_t, cte_insert, cte_t};
+use sel4_task::{get_currenct_thread, ThreadStateMachine};
+#[cfg(target_arch = "aarch64")]
+use sel4_vspace::{
+    asid_map_t, invalidate_tlb_by_asid_va, unmap_page_directory, unmap_page_upper_directory, PDE,
+    PGDE, PUDE,
+};
+use sel4_vspace::{
+    asid_pool_t, pptr_t, pptr_to_paddr, set_asid_pool_by_index, unmapPage, unmap_page_table, PTE,
 };
-#[cfg(target_arch = "riscv64")]
-use sel4_cspace::interface::cte_insert;
-use sel4_cspace::interface::{cap_t, cte_t};
-use sel4_task::{get_currenct_thread, set_thread_state, ThreadState};
-use sel4_vspace::{asid_pool_t, pptr_to_paddr, unmapPage, unmap_page_table, PTE};
 #[cfg(target_arch = "riscv64")]
 use sel4_vspace::{
-    copyGlobalMappings, pptr_t, set_asid_pool_by_index, sfence, vm_attributes_t, PTEFlags,
+    copyGlobalMappings, fence_i, fence_rw, sfence, vm_attributes_t, vspace_promote,
+    vspace_promotion_t, PTEFlags,
 };
+#[cfg(feature = "ENABLE_SHARED_PT")]
+use sel4_vspace::{shared_pt_add, shared_pt_remove};
+
+#[cfg(target_arch = "aarch64")]
+use crate::arch::set_vm_root_for_flush;
+#[cfg(feature = "ENABLE_RISCV_HYP")]
+use crate::arch::vcpu_t;
+#[cfg(target_arch = "riscv64")]
+use crate::arch::{cbo_op_t, cbo_range, cbo_vspace_range};
+use crate::{config::seL4_ASIDPoolBits, kernel::boot::current_lookup_fault, utils::clear_memory};
+
+/// An MMU invocation which passed its decode checks, see `decode_mmu_invocation`. The operations
+/// on the invoked cap get its slot from `invoke_mmu`.
+#[cfg(target_arch = "riscv64")]
+pub enum mmu_invocation_t {
+    PageTableUnmap,
+    /// Link the invoked page table into `pt_slot`, which covers `vaddr` in the vspace of `asid`
+    PageTableMap {
+        pt_slot: &'static mut PTE,
+        asid: usize,
+        vaddr: usize,
+    },
+    /// Link the invoked page table into a second vspace as well, see `ENABLE_SHARED_PT`
+    #[cfg(feature = "ENABLE_SHARED_PT")]
+    PageTableMapShared {
+        pt_slot: &'static mut PTE,
+        asid: usize,
+        vaddr: usize,
+    },
+    #[cfg(feature = "ENABLE_SHARED_PT")]
+    PageTableUnmapShared {
+        asid: usize,
+        vaddr: usize,
+    },
+    /// Flush `start..end` of the invoked vspace as `label` asks
+    VSpaceFlush {
+        label: MessageLabel,
+        start: usize,
+        end: usize,
+    },
+    VSpacePromote {
+        promotion: vspace_promotion_t,
+        asid: usize,
+    },
+    PageMap {
+        w_rights_mask: usize,
+        vaddr: usize,
+        asid: usize,
+        attr: vm_attributes_t,
+        pt_slot: &'static mut PTE,
+    },
+    PageUnmap,
+    PageGetAddress {
+        call: bool,
+    },
+    /// Flush `start..end` of the invoked frame as `label` asks
+    PageFlush {
+        label: MessageLabel,
+        start: usize,
+        end: usize,
+    },
+    ASIDControl {
+        frame: pptr_t,
+        parent_slot: &'static mut cte_t,
+        dest_slot: &'static mut cte_t,
+        asid_base: usize,
+    },
+    ASIDPoolAssign {
+        asid: usize,
+        pool: &'static mut asid_pool_t,
+        vspace_slot: &'static mut cte_t,
+    },
+    /// Assign the ASID to the G-stage root of a VCPU, see `ENABLE_RISCV_HYP`
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    ASIDPoolAssignVCPU {
+        asid: usize,
+        pool: &'static mut asid_pool_t,
+        vcpu: &'static mut vcpu_t,
+    },
+}
+
+#[cfg(target_arch = "riscv64")]
+pub fn invoke_mmu(invocation: mmu_invocation_t, slot: &mut cte_t) -> exception_t {
+    match invocation {
+        mmu_invocation_t::PageTableUnmap => invoke_page_table_unmap(&mut slot.cap),
+        mmu_invocation_t::PageTableMap {
+            pt_slot,
+            asid,
+            vaddr,
+        } => invoke_page_table_map(&mut slot.cap, pt_slot, asid, vaddr),
+        #[cfg(feature = "ENABLE_SHARED_PT")]
+        mmu_invocation_t::PageTableMapShared {
+            pt_slot,
+            asid,
+            vaddr,
+        } => {
+            let table = slot.cap.get_pt_base_ptr();
+            assert!(shared_pt_add(table, asid, vaddr));
+            *pt_slot = PTE::new(pptr_to_paddr(table) >> seL4_PageBits, PTEFlags::V);
+            sfence();
+            exception_t::EXCEPTION_NONE
+        }
+        #[cfg(feature = "ENABLE_SHARED_PT")]
+        mmu_invocation_t::PageTableUnmapShared { asid, vaddr } => {
+            shared_pt_remove(slot.cap.get_pt_base_ptr(), asid, vaddr);
+            exception_t::EXCEPTION_NONE
+        }
+        mmu_invocation_t::VSpaceFlush { label, start, end } => {
+            let root = convert_to_mut_type_ref::<PTE>(slot.cap.get_pt_base_ptr());
+            perform_flush(label, |op| cbo_vspace_range(op, root, start, end));
+            exception_t::EXCEPTION_NONE
+        }
+        mmu_invocation_t::VSpacePromote { promotion, asid } => {
+            vspace_promote(promotion, asid);
+            exception_t::EXCEPTION_NONE
+        }
+        mmu_invocation_t::PageMap {
+            w_rights_mask,
+            vaddr,
+            asid,
+            attr,
+            pt_slot,
+        } => invoke_page_map(w_rights_mask, vaddr, asid, attr, pt_slot, slot),
+        mmu_invocation_t::PageUnmap => invoke_page_unmap(slot),
+        mmu_invocation_t::PageGetAddress { call } => {
+            invoke_page_get_address(slot.cap.get_frame_base_ptr(), call)
+        }
+        mmu_invocation_t::PageFlush { label, start, end } => {
+            let base = slot.cap.get_frame_base_ptr();
+            perform_flush(label, |op| cbo_range(op, base + start, base + end));
+            exception_t::EXCEPTION_NONE
+        }
+        mmu_invocation_t::ASIDControl {
+            frame,
+            parent_slot,
+            dest_slot,
+            asid_base,
+        } => invoke_asid_control(frame, dest_slot, parent_slot, asid_base),
+        mmu_invocation_t::ASIDPoolAssign {
+            asid,
+            pool,
+            vspace_slot,
+        } => invoke_asid_pool(asid, pool, vspace_slot),
+        #[cfg(feature = "ENABLE_RISCV_HYP")]
+        mmu_invocation_t::ASIDPoolAssignVCPU { asid, pool, vcpu } => {
+            vcpu.assign_asid(pool, asid);
+            exception_t::EXCEPTION_NONE
+        }
+    }
+}
+
+/// Unifying the instruction stream is a `fence.i`. The data cache labels `flush` with the Zicbom
+/// operation they name, if all harts have the extension, see `crate::arch::cbo_init`. Otherwise
+/// data caches are taken to be coherent and the labels only order memory accesses.
+#[cfg(target_arch = "riscv64")]
+fn perform_flush(label: MessageLabel, flush: impl FnOnce(cbo_op_t) -> bool) {
+    let op = match label {
+        MessageLabel::RISCVPageClean_Data | MessageLabel::RISCVVSpaceClean_Data => cbo_op_t::Clean,
+        MessageLabel::RISCVPageInvalidate_Data | MessageLabel::RISCVVSpaceInvalidate_Data => {
+            cbo_op_t::Inval
+        }
+        MessageLabel::RISCVPageCleanInvalidate_Data
+        | MessageLabel::RISCVVSpaceCleanInvalidate_Data => cbo_op_t::Flush,
+        _ => return fence_i(),
+    };
+    if !flush(op) {
+        fence_rw();
+    }
+}
+
+/// An MMU invocation which passed its decode checks, see `decode_mmu_invocation`. The operations
+/// on the invoked cap get its slot from `invoke_mmu`.
 #[cfg(target_arch = "aarch64")]
-use sel4_vspace::{invalidate_tlb_by_asid_va, PDE, PUDE};
+pub enum mmu_invocation_t {
+    PageUpperDirectoryUnmap,
+    PageDirectoryUnmap,
+    PageTableUnmap,
+    /// Link the invoked table into the slot covering `vaddr` in the vspace of `asid`
+    PageUpperDirectoryMap {
+        pgd_slot: &'static mut PGDE,
+        asid: usize,
+        vaddr: usize,
+    },
+    PageDirectoryMap {
+        pud_slot: &'static mut PUDE,
+        asid: usize,
+        vaddr: usize,
+    },
+    PageTableMap {
+        pd_slot: &'static mut PDE,
+        asid: usize,
+        vaddr: usize,
+    },
+    /// Write the entry mapping the invoked frame at `vaddr` in the vspace of `asid`
+    HugePageMap {
+        vaddr: usize,
+        asid: usize,
+        pude: PUDE,
+        pud_slot: &'static mut PUDE,
+    },
+    LargePageMap {
+        vaddr: usize,
+        asid: usize,
+        pde: PDE,
+        pd_slot: &'static mut PDE,
+    },
+    SmallPageMap {
+        vaddr: usize,
+        asid: usize,
+        pte: PTE,
+        pt_slot: &'static mut PTE,
+    },
+    PageUnmap,
+    PageGetAddress {
+        call: bool,
+    },
+    /// Flush `start..end` of `vspace`, the vspace of `asid`, as `label` asks
+    Flush {
+        label: MessageLabel,
+        vspace: usize,
+        asid: usize,
+        start: usize,
+        end: usize,
+    },
+    ASIDControl {
+        frame: pptr_t,
+        parent_slot: &'static mut cte_t,
+        dest_slot: &'static mut cte_t,
+        asid_base: usize,
+    },
+    /// Assign the ASID to a vspace, a guest IPA space for `guest`
+    ASIDPoolAssign {
+        asid: usize,
+        pool: &'static mut asid_pool_t,
+        vspace_slot: &'static mut cte_t,
+        guest: bool,
+    },
+}
 
-use crate::{kernel::boot::current_lookup_fault, utils::clear_memory};
+#[cfg(target_arch = "aarch64")]
+pub fn invoke_mmu(invocation: mmu_invocation_t, slot: &mut cte_t) -> exception_t {
+    match invocation {
+        mmu_invocation_t::PageUpperDirectoryUnmap => {
+            invoke_page_upper_directory_unmap(&mut slot.cap)
+        }
+        mmu_invocation_t::PageDirectoryUnmap => invoke_page_directory_unmap(&mut slot.cap),
+        mmu_invocation_t::PageTableUnmap => invoke_page_table_unmap(&mut slot.cap),
+        mmu_invocation_t::PageUpperDirectoryMap {
+            pgd_slot,
+            asid,
+            vaddr,
+        } => invoke_page_upper_directory_map(&mut slot.cap, pgd_slot, asid, vaddr),
+        mmu_invocation_t::PageDirectoryMap {
+            pud_slot,
+            asid,
+            vaddr,
+        } => invoke_page_directory_map(&mut slot.cap, pud_slot, asid, vaddr),
+        mmu_invocation_t::PageTableMap {
+            pd_slot,
+            asid,
+            vaddr,
+        } => invoke_page_table_map(&mut slot.cap, pd_slot, asid, vaddr),
+        mmu_invocation_t::HugePageMap {
+            vaddr,
+            asid,
+            pude,
+            pud_slot,
+        } => invoke_huge_page_map(vaddr, asid, slot, pude, pud_slot),
+        mmu_invocation_t::LargePageMap {
+            vaddr,
+            asid,
+            pde,
+            pd_slot,
+        } => invoke_large_page_map(vaddr, asid, slot, pde, pd_slot),
+        mmu_invocation_t::SmallPageMap {
+            vaddr,
+            asid,
+            pte,
+            pt_slot,
+        } => invoke_small_page_map(vaddr, asid, slot, pte, pt_slot),
+        mmu_invocation_t::PageUnmap => invoke_page_unmap(slot),
+        mmu_invocation_t::PageGetAddress { call } => {
+            invoke_page_get_address(slot.cap.get_frame_base_ptr(), call)
+        }
+        mmu_invocation_t::Flush {
+            label,
+            vspace,
+            asid,
+            start,
+            end,
+        } => invoke_flush(label, vspace, asid, start, end),
+        mmu_invocation_t::ASIDControl {
+            frame,
+            parent_slot,
+            dest_slot,
+            asid_base,
+        } => invoke_asid_control(frame, dest_slot, parent_slot, asid_base),
+        mmu_invocation_t::ASIDPoolAssign {
+            asid,
+            pool,
+            vspace_slot,
+            guest,
+        } => invoke_asid_pool(asid, pool, vspace_slot, guest),
+    }
+}
 
 pub fn invoke_page_table_unmap(cap: &mut cap_t) -> exception_t {
     if cap.get_pt_is_mapped() != 0 {
@@ -37,6 +342,28 @@ pub fn invoke_page_table_unmap(cap: &mut cap_t) -> exception_t {
     cap.set_pt_is_mapped(0);
     exception_t::EXCEPTION_NONE
 }
+
+#[cfg(target_arch = "aarch64")]
+pub fn invoke_page_directory_unmap(cap: &mut cap_t) -> exception_t {
+    if cap.get_pd_is_mapped() != 0 {
+        let pd = convert_to_mut_type_ref::<PDE>(cap.get_pd_base_ptr());
+        unmap_page_directory(cap.get_pd_mapped_asid(), cap.get_pd_mapped_address(), pd);
+        clear_memory(pd.get_mut_ptr() as *mut u8, seL4_PageDirBits)
+    }
+    cap.set_pd_is_mapped(0);
+    exception_t::EXCEPTION_NONE
+}
+
+#[cfg(target_arch = "aarch64")]
+pub fn invoke_page_upper_directory_unmap(cap: &mut cap_t) -> exception_t {
+    if cap.get_pud_is_mapped() != 0 {
+        let pud = convert_to_mut_type_ref::<PUDE>(cap.get_pud_base_ptr());
+        unmap_page_upper_directory(cap.get_pud_mapped_asid(), cap.get_pud_mapped_address(), pud);
+        clear_memory(pud.get_mut_ptr() as *mut u8, seL4_PUDBits)
+    }
+    cap.set_pud_is_mapped(0);
+    exception_t::EXCEPTION_NONE
+}
 #[cfg(target_arch = "riscv64")]
 pub fn invoke_page_table_map(
     pt_cap: &mut cap_t,
@@ -53,7 +380,52 @@ pub fn invoke_page_table_map(
     sfence();
     exception_t::EXCEPTION_NONE
 }
-#[allow(unused)]
+#[cfg(target_arch = "aarch64")]
+pub fn invoke_page_upper_directory_map(
+    pud_cap: &mut cap_t,
+    pgd_slot: &mut PGDE,
+    asid: usize,
+    vaddr: usize,
+) -> exception_t {
+    let paddr = pptr_to_paddr(pud_cap.get_pud_base_ptr());
+    // TODO: make 0x3 in a pagetable-specific position
+    *pgd_slot = PGDE::new_page(paddr, 0x3);
+    pud_cap.set_pud_is_mapped(1);
+    pud_cap.set_pud_mapped_asid(asid);
+    pud_cap.set_pud_mapped_address(vaddr);
+    unsafe {
+        asm!(
+            "dc cvau, {}",
+            "dmb sy",
+            in(reg) pgd_slot,
+        );
+    }
+    exception_t::EXCEPTION_NONE
+}
+
+#[cfg(target_arch = "aarch64")]
+pub fn invoke_page_directory_map(
+    pd_cap: &mut cap_t,
+    pud_slot: &mut PUDE,
+    asid: usize,
+    vaddr: usize,
+) -> exception_t {
+    let paddr = pptr_to_paddr(pd_cap.get_pd_base_ptr());
+    // TODO: make 0x3 in a pagetable-specific position
+    *pud_slot = PUDE::new_page(paddr, 0x3);
+    pd_cap.set_pd_is_mapped(1);
+    pd_cap.set_pd_mapped_asid(asid);
+    pd_cap.set_pd_mapped_address(vaddr);
+    unsafe {
+        asm!(
+            "dc cvau, {}",
+            "dmb sy",
+            in(reg) pud_slot,
+        );
+    }
+    exception_t::EXCEPTION_NONE
+}
+
 #[cfg(target_arch = "aarch64")]
 pub fn invoke_page_table_map(
     pt_cap: &mut cap_t,
@@ -87,12 +459,12 @@ pub fn invoke_page_get_address(vbase_ptr: usize, call: bool) -> exception_t {
             seL4_MessageInfo_t::new(0, 0, 0, length).to_word(),
         );
     }
-    set_thread_state(thread, ThreadState::ThreadStateRestart);
+    thread.set_restart();
     exception_t::EXCEPTION_NONE
 }
 
 pub fn invoke_page_unmap(frame_slot: &mut cte_t) -> exception_t {
-    if frame_slot.cap.get_pt_mapped_asid() != asidInvalid {
+    if frame_slot.cap.get_frame_mapped_asid() != asidInvalid {
         match unmapPage(
             frame_slot.cap.get_frame_size(),
             frame_slot.cap.get_frame_mapped_asid(),
@@ -107,13 +479,12 @@ pub fn invoke_page_unmap(frame_slot: &mut cte_t) -> exception_t {
         }
     }
     frame_slot.cap.set_frame_mapped_address(0);
-    frame_slot.cap.set_pt_mapped_asid(asidInvalid);
+    frame_slot.cap.set_frame_mapped_asid(asidInvalid);
     exception_t::EXCEPTION_NONE
 }
 
 #[cfg(target_arch = "riscv64")]
 pub fn invoke_page_map(
-    _frame_cap: &mut cap_t,
     w_rights_mask: usize,
     vaddr: usize,
     asid: usize,
@@ -132,7 +503,9 @@ pub fn invoke_page_map(
     let pte = PTE::make_user_pte(frame_addr, executable, vm_rights);
     #[cfg(target_arch = "aarch64")]
     let pte = PTE::make_user_pte(frame_addr, vm_rights, attr, frame_slot.cap.get_frame_size());
-    set_thread_state(get_currenct_thread(), ThreadState::ThreadStateRestart);
+    // Relink the table of a promoted region before changing one of its 4K mappings
+    #[cfg(target_arch = "riscv64")]
+    sel4_vspace::vspace_demote(asid, vaddr);
     pt_slot.update(pte);
     exception_t::EXCEPTION_NONE
 }
@@ -214,7 +587,6 @@ pub fn invoke_small_page_map(
     exception_t::EXCEPTION_NONE
 }
 
-#[cfg(target_arch = "riscv64")]
 pub fn invoke_asid_control(
     frame_ptr: pptr_t,
     slot: &mut cte_t,
@@ -224,7 +596,7 @@ pub fn invoke_asid_control(
     parent_slot
         .cap
         .set_untyped_free_index(MAX_FREE_INDEX(parent_slot.cap.get_untyped_block_size()));
-    clear_memory(frame_ptr as *mut u8, pageBitsForSize(RISCV_4K_Page));
+    clear_memory(frame_ptr as *mut u8, seL4_ASIDPoolBits);
     cte_insert(
         &cap_t::new_asid_pool_cap(asid_base, frame_ptr),
         parent_slot,
@@ -251,12 +623,51 @@ pub fn invoke_asid_pool(
     exception_t::EXCEPTION_NONE
 }
 
-#[allow(unused)]
 #[cfg(target_arch = "aarch64")]
 pub fn invoke_asid_pool(
     asid: usize,
     pool: &mut asid_pool_t,
     vspace_slot: &mut cte_t,
+    guest: bool,
 ) -> exception_t {
-    todo!()
+    vspace_slot.cap.set_pgd_mapped_asid(asid);
+    vspace_slot.cap.set_pgd_is_mapped(1);
+    let asid_map = asid_map_t::new_vspace(vspace_slot.cap.get_pgd_base_ptr(), 0, 0, guest as usize);
+    pool[asid & MASK!(asidLowBits)] = asid_map;
+    exception_t::EXCEPTION_NONE
+}
+
+/*
+    static exception_t performPageFlush(int invLabel, vspace_root_t *vspaceRoot, asid_t asid,
+                                vptr_t start, vptr_t end, paddr_t pstart)
+    {
+        bool_t root_switched;
+            if (start < end) {
+                root_switched = setVMRootForFlush(vspaceRoot, asid);
+                doFlush(invLabel, start, end, pstart);
+                if (root_switched) {
+                    setVMRoot(NODE_STATE(ksCurThread));
+                }
+            }
+        return EXCEPTION_NONE;
+    }
+*/
+#[cfg(target_arch = "aarch64")]
+pub fn invoke_flush(
+    label: MessageLabel,
+    vspace: usize,
+    asid: usize,
+    start: usize,
+    end: usize,
+) -> exception_t {
+    if start < end {
+        let root_switched = set_vm_root_for_flush(vspace, asid);
+        log::warn!("need to flush cache for invoke_flush label: {:?}", label);
+        if root_switched {
+            get_currenct_thread()
+                .set_vm_root()
+                .expect("can't set vm root for invoke_flush");
+        }
+    }
+    exception_t::EXCEPTION_NONE
 }
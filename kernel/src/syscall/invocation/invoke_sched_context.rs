@@ -0,0 +1,71 @@
+use sel4_common::arch::ArchReg;
+use sel4_common::message_info::seL4_MessageInfo_t;
+use sel4_common::structures::exception_t;
+use sel4_task::sched_context::sched_context_t;
+use sel4_task::{get_currenct_thread, tcb_t, ThreadStateMachine};
+
+use crate::config::{MS_IN_S, TIMER_CLOCK_HZ, US_IN_MS};
+
+/// A SchedControl or SchedContext invocation which passed its decode checks. Budget and period
+/// are in cycles of the timer counter.
+pub enum sched_context_invocation_t {
+    Configure {
+        sc: &'static mut sched_context_t,
+        budget: usize,
+        period: usize,
+        max_refills: usize,
+        badge: usize,
+        core: usize,
+    },
+    Bind {
+        sc: &'static mut sched_context_t,
+        tcb: &'static mut tcb_t,
+    },
+    Unbind {
+        sc: &'static mut sched_context_t,
+    },
+    /// Unbind only the thread, by SchedContextUnbindObject
+    UnbindTcb {
+        sc: &'static mut sched_context_t,
+    },
+    Consumed {
+        sc: &'static mut sched_context_t,
+        call: bool,
+    },
+}
+
+pub fn invoke_sched_context(invocation: sched_context_invocation_t) -> exception_t {
+    match invocation {
+        sched_context_invocation_t::Configure {
+            sc,
+            budget,
+            period,
+            max_refills,
+            badge,
+            core,
+        } => sc.configure(budget, period, max_refills, badge, core),
+        sched_context_invocation_t::Bind { sc, tcb } => sc.bind_tcb(tcb),
+        sched_context_invocation_t::Unbind { sc } => {
+            sel4_ipc::sched_context_unbind_reply(sc);
+            sc.unbind_tcb()
+        }
+        sched_context_invocation_t::UnbindTcb { sc } => sc.unbind_tcb(),
+        sched_context_invocation_t::Consumed { sc, call } => {
+            let consumed = sc.take_consumed() * US_IN_MS / (TIMER_CLOCK_HZ / MS_IN_S);
+            let (overruns, deadline_misses) = sc.take_misses();
+            if call {
+                let thread = get_currenct_thread();
+                thread.tcbArch.set_register(ArchReg::Badge, 0);
+                thread.set_mr(0, consumed);
+                thread.set_mr(1, overruns);
+                let length = thread.set_mr(2, deadline_misses);
+                thread.tcbArch.set_register(
+                    ArchReg::MsgInfo,
+                    seL4_MessageInfo_t::new(0, 0, 0, length).to_word(),
+                );
+                thread.set_running();
+            }
+        }
+    }
+    exception_t::EXCEPTION_NONE
+}
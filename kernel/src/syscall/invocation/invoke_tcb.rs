@@ -1,15 +1,212 @@
 use sel4_common::arch::*;
 use sel4_common::{
+    fault::{lookup_fault_t, seL4_Fault_t},
     message_info::seL4_MessageInfo_t,
     sel4_config::{tcbBuffer, tcbCTable, tcbVTable},
     structures::{exception_t, seL4_IPCBuffer},
+    utils::convert_to_mut_type_ref,
 };
 use sel4_cspace::interface::{cap_t, cte_insert, cte_t, same_object_as};
-use sel4_ipc::{notification_t, Transfer};
-use sel4_task::{get_currenct_thread, rescheduleRequired, set_thread_state, tcb_t, ThreadState};
+use sel4_ipc::{notification_t, NtfnState, Transfer};
+use sel4_task::{get_currenct_thread, rescheduleRequired, tcb_t, ThreadStateMachine};
 
 use crate::syscall::{do_bind_notification, safe_unbind_notification, utils::get_syscall_arg};
 
+/// A TCB invocation which passed its decode checks, see `decode_tcb`.
+pub enum tcb_invocation_t {
+    ReadRegisters {
+        src: &'static mut tcb_t,
+        suspend_source: usize,
+        n: usize,
+        call: bool,
+    },
+    /// Write `n` registers, read from the message of the caller
+    WriteRegisters {
+        dest: &'static mut tcb_t,
+        resume_target: usize,
+        n: usize,
+    },
+    CopyRegisters {
+        dest: &'static mut tcb_t,
+        src: &'static mut tcb_t,
+        suspend_source: usize,
+        resume_target: usize,
+        transfer_frame: usize,
+        transfer_integer: usize,
+    },
+    Suspend(&'static mut tcb_t),
+    Resume(&'static mut tcb_t),
+    /// Configure, SetSpace or SetIPCBuffer: replace the roots and the fault handler, the IPC
+    /// buffer, or both, in that order
+    ThreadControl {
+        target: &'static mut tcb_t,
+        space: Option<tcb_space_t>,
+        ipc_buffer: Option<tcb_ipc_buffer_t>,
+    },
+    SetPriority {
+        target: &'static mut tcb_t,
+        prio: usize,
+    },
+    SetMCPriority {
+        target: &'static mut tcb_t,
+        mcp: usize,
+    },
+    SetSchedParams {
+        target: &'static mut tcb_t,
+        mcp: usize,
+        prio: usize,
+    },
+    BindNotification {
+        tcb: &'static mut tcb_t,
+        ntfn: &'static mut notification_t,
+    },
+    UnbindNotification(&'static mut tcb_t),
+    #[cfg(all(feature = "ENABLE_SMP", not(feature = "ENABLE_MCS")))]
+    SetAffinity {
+        tcb: &'static mut tcb_t,
+        affinity: usize,
+    },
+    SetTLSBase {
+        tcb: &'static mut tcb_t,
+        base: usize,
+    },
+    #[cfg(feature = "ENABLE_MCS")]
+    SetTimeoutEndpoint {
+        tcb: &'static mut tcb_t,
+        timeout_ep: usize,
+    },
+    #[cfg(feature = "ENABLE_THREAD_ID")]
+    SetThreadID {
+        tcb: &'static mut tcb_t,
+        id: usize,
+    },
+    #[cfg(feature = "ENABLE_CPU_LIMIT")]
+    SetCPULimit {
+        tcb: &'static mut tcb_t,
+        ticks: usize,
+    },
+    CheckpointSave {
+        src: &'static mut tcb_t,
+        record: &'static mut tcb_checkpoint_t,
+        call: bool,
+    },
+    CheckpointRestore {
+        dest: &'static mut tcb_t,
+        record: &'static tcb_checkpoint_t,
+    },
+}
+
+/// The new fault handler and roots of a ThreadControl invocation, the roots derived already
+pub struct tcb_space_t {
+    pub fault_ep: usize,
+    pub croot_cap: cap_t,
+    pub croot_slot: &'static mut cte_t,
+    pub vroot_cap: cap_t,
+    pub vroot_slot: &'static mut cte_t,
+}
+
+/// The new IPC buffer of a ThreadControl invocation, without a frame for address 0
+pub struct tcb_ipc_buffer_t {
+    pub addr: usize,
+    pub cap: cap_t,
+    pub slot: Option<&'static mut cte_t>,
+}
+
+/// Perform `invocation` on a TCB invoked through the cap in `slot`. WriteRegisters reads the
+/// registers from `buffer`.
+pub fn invoke_tcb(
+    invocation: tcb_invocation_t,
+    slot: &mut cte_t,
+    buffer: Option<&seL4_IPCBuffer>,
+) -> exception_t {
+    match invocation {
+        tcb_invocation_t::ReadRegisters {
+            src,
+            suspend_source,
+            n,
+            call,
+        } => invoke_tcb_read_registers(src, suspend_source, n, 0, call),
+        tcb_invocation_t::WriteRegisters {
+            dest,
+            resume_target,
+            n,
+        } => invoke_tcb_write_registers(dest, resume_target, n, 0, buffer),
+        tcb_invocation_t::CopyRegisters {
+            dest,
+            src,
+            suspend_source,
+            resume_target,
+            transfer_frame,
+            transfer_integer,
+        } => invoke_tcb_copy_registers(
+            dest,
+            src,
+            suspend_source,
+            resume_target,
+            transfer_frame,
+            transfer_integer,
+            0,
+        ),
+        tcb_invocation_t::Suspend(thread) => invoke_tcb_suspend(thread),
+        tcb_invocation_t::Resume(thread) => invoke_tcb_resume(thread),
+        tcb_invocation_t::ThreadControl {
+            target,
+            space,
+            ipc_buffer,
+        } => {
+            if let Some(space) = space {
+                let status = invoke_tcb_set_space(
+                    target,
+                    slot,
+                    space.fault_ep,
+                    space.croot_cap,
+                    space.croot_slot,
+                    space.vroot_cap,
+                    space.vroot_slot,
+                );
+                if status != exception_t::EXCEPTION_NONE {
+                    return status;
+                }
+            }
+            match ipc_buffer {
+                Some(ipc_buffer) => invoke_tcb_set_ipc_buffer(
+                    target,
+                    slot,
+                    ipc_buffer.addr,
+                    ipc_buffer.cap,
+                    ipc_buffer.slot,
+                ),
+                None => exception_t::EXCEPTION_NONE,
+            }
+        }
+        tcb_invocation_t::SetPriority { target, prio } => invoke_tcb_set_priority(target, prio),
+        tcb_invocation_t::SetMCPriority { target, mcp } => invoke_tcb_set_mcp(target, mcp),
+        tcb_invocation_t::SetSchedParams { target, mcp, prio } => {
+            invoke_tcb_set_mcp(target, mcp);
+            invoke_tcb_set_priority(target, prio)
+        }
+        tcb_invocation_t::BindNotification { tcb, ntfn } => invoke_tcb_bind_notification(tcb, ntfn),
+        tcb_invocation_t::UnbindNotification(tcb) => invoke_tcb_unbind_notification(tcb),
+        #[cfg(all(feature = "ENABLE_SMP", not(feature = "ENABLE_MCS")))]
+        tcb_invocation_t::SetAffinity { tcb, affinity } => invoke_tcb_set_affinity(tcb, affinity),
+        tcb_invocation_t::SetTLSBase { tcb, base } => invoke_tcb_set_tls_base(tcb, base),
+        #[cfg(feature = "ENABLE_MCS")]
+        tcb_invocation_t::SetTimeoutEndpoint { tcb, timeout_ep } => {
+            invoke_tcb_set_timeout_endpoint(tcb, timeout_ep)
+        }
+        #[cfg(feature = "ENABLE_THREAD_ID")]
+        tcb_invocation_t::SetThreadID { tcb, id } => invoke_tcb_set_thread_id(tcb, id),
+        #[cfg(feature = "ENABLE_CPU_LIMIT")]
+        tcb_invocation_t::SetCPULimit { tcb, ticks } => invoke_tcb_set_cpu_limit(tcb, ticks),
+        tcb_invocation_t::CheckpointSave { src, record, call } => {
+            invoke_tcb_checkpoint_save(src, record, call)
+        }
+        tcb_invocation_t::CheckpointRestore { dest, record } => {
+            invoke_tcb_checkpoint_restore(dest, record)
+        }
+    }
+}
+
 pub fn invoke_tcb_read_registers(
     src: &mut tcb_t,
     suspend_source: usize,
@@ -63,7 +260,7 @@ pub fn invoke_tcb_read_registers(
             seL4_MessageInfo_t::new(0, 0, 0, i + j).to_word(),
         );
     }
-    set_thread_state(thread, ThreadState::ThreadStateRunning);
+    thread.set_running();
     exception_t::EXCEPTION_NONE
 }
 
@@ -81,18 +278,17 @@ pub fn invoke_tcb_write_registers(
     let mut i = 0;
     while i < frameRegNum && i < n {
         dest.tcbArch
-            .set_register(ArchReg::Frame(i), get_syscall_arg(i + 2, buffer));
+            .set_user_register(ArchReg::Frame(i), get_syscall_arg(i + 2, buffer));
         i += 1;
     }
     i = 0;
     while i < gpRegNum && i + frameRegNum < n {
         dest.tcbArch
-            .set_register(ArchReg::GP(i), get_syscall_arg(i + frameRegNum + 2, buffer));
+            .set_user_register(ArchReg::GP(i), get_syscall_arg(i + frameRegNum + 2, buffer));
         i += 1;
     }
 
-    dest.tcbArch
-        .set_register(ArchReg::NextIP, dest.tcbArch.get_register(ArchReg::FaultIP));
+    dest.tcbArch.rewind_to_restart_pc();
 
     if resumeTarget != 0 {
         // cancel_ipc(dest);
@@ -161,9 +357,27 @@ pub fn invoke_tcb_set_mcp(target: &mut tcb_t, mcp: usize) -> exception_t {
 #[inline]
 pub fn invoke_tcb_set_priority(target: &mut tcb_t, prio: usize) -> exception_t {
     target.set_priority(prio);
+    #[cfg(feature = "ENABLE_ORDERED_IPC")]
+    reorder_blocked(target);
     exception_t::EXCEPTION_NONE
 }
 
+/// Keep the queue `target` is blocked in ordered after a change of its priority.
+#[cfg(feature = "ENABLE_ORDERED_IPC")]
+fn reorder_blocked(target: &mut tcb_t) {
+    let object = target.tcbState.get_blocking_object();
+    match target.get_state() {
+        sel4_task::ThreadState::ThreadStateBlockedOnSend
+        | sel4_task::ThreadState::ThreadStateBlockedOnReceive => {
+            convert_to_mut_type_ref::<sel4_ipc::endpoint_t>(object).reorder(target)
+        }
+        sel4_task::ThreadState::ThreadStateBlockedOnNotification => {
+            convert_to_mut_type_ref::<notification_t>(object).reorder(target)
+        }
+        _ => {}
+    }
+}
+
 pub fn invoke_tcb_set_space(
     target: &mut tcb_t,
     slot: &mut cte_t,
@@ -210,7 +424,7 @@ pub fn invoke_tcb_set_ipc_buffer(
     if status != exception_t::EXCEPTION_NONE {
         return status;
     }
-    target.tcbIPCBuffer = buffer_addr;
+    target.set_ipc_buffer(buffer_addr);
     if let Some(buffer_src_slot) = buffer_src_slot {
         if same_object_as(&buffer_cap, &buffer_src_slot.cap)
             && same_object_as(&target_cap, &slot.cap)
@@ -245,14 +459,130 @@ pub fn invoke_tcb_set_tls_base(thread: &mut tcb_t, base: usize) -> exception_t {
     exception_t::EXCEPTION_NONE
 }
 
-#[cfg(feature = "ENABLE_SMP")]
+#[cfg(feature = "ENABLE_THREAD_ID")]
+pub fn invoke_tcb_set_thread_id(thread: &mut tcb_t, id: usize) -> exception_t {
+    thread.tcbArch.set_thread_id(id);
+    if thread.is_current() {
+        rescheduleRequired();
+    }
+    exception_t::EXCEPTION_NONE
+}
+
+/// Arm the CPU time limit of `thread` to fire after `ticks` more timer ticks, or disarm it for 0.
+#[cfg(feature = "ENABLE_CPU_LIMIT")]
+pub fn invoke_tcb_set_cpu_limit(thread: &mut tcb_t, ticks: usize) -> exception_t {
+    thread.tcbCPULimit = match ticks {
+        0 => 0,
+        _ => thread.tcbCPUTime.saturating_add(ticks),
+    };
+    exception_t::EXCEPTION_NONE
+}
+
+#[cfg(feature = "ENABLE_MCS")]
+pub fn invoke_tcb_set_timeout_endpoint(thread: &mut tcb_t, timeout_ep: usize) -> exception_t {
+    thread.tcbTimeoutHandler = timeout_ep;
+    exception_t::EXCEPTION_NONE
+}
+
+pub const TCB_CHECKPOINT_MAGIC: usize = 0x7463_625f_636b_7074;
+
+/// The layout written into the caller's frame by TCBCheckpointSave and read
+/// back by TCBCheckpointRestore.
+#[repr(C)]
+#[derive(Clone)]
+pub struct tcb_checkpoint_t {
+    pub magic: usize,
+    pub frame_regs: [usize; frameRegNum],
+    pub gp_regs: [usize; gpRegNum],
+    pub tls_base: usize,
+    #[cfg(target_arch = "aarch64")]
+    pub fpu: FPUState,
+    pub fault: seL4_Fault_t,
+    pub lookup_failure: lookup_fault_t,
+    pub has_pending_badge: usize,
+    pub pending_badge: usize,
+}
+
+pub fn invoke_tcb_checkpoint_save(
+    src: &mut tcb_t,
+    record: &mut tcb_checkpoint_t,
+    call: bool,
+) -> exception_t {
+    record.magic = TCB_CHECKPOINT_MAGIC;
+    for i in 0..frameRegNum {
+        record.frame_regs[i] = src.tcbArch.get_register(ArchReg::Frame(i));
+    }
+    for i in 0..gpRegNum {
+        record.gp_regs[i] = src.tcbArch.get_register(ArchReg::GP(i));
+    }
+    record.tls_base = src.tcbArch.get_register(ArchReg::TlsBase);
+    #[cfg(target_arch = "aarch64")]
+    {
+        record.fpu = src.tcbArch.get_fpu_state().clone();
+    }
+    record.fault = src.tcbFault;
+    record.lookup_failure = src.tcbLookupFailure;
+    record.has_pending_badge = 0;
+    record.pending_badge = 0;
+    if src.tcbBoundNotification != 0 {
+        let ntfn = convert_to_mut_type_ref::<notification_t>(src.tcbBoundNotification);
+        if ntfn.get_state() == NtfnState::Active {
+            record.has_pending_badge = 1;
+            record.pending_badge = ntfn.get_msg_identifier();
+        }
+    }
+    let thread = get_currenct_thread();
+    if call {
+        thread.tcbArch.set_register(ArchReg::Badge, 0);
+        let length = thread.set_mr(0, core::mem::size_of::<tcb_checkpoint_t>());
+        thread.tcbArch.set_register(
+            ArchReg::MsgInfo,
+            seL4_MessageInfo_t::new(0, 0, 0, length).to_word(),
+        );
+    }
+    thread.set_running();
+    exception_t::EXCEPTION_NONE
+}
+
+pub fn invoke_tcb_checkpoint_restore(dest: &mut tcb_t, record: &tcb_checkpoint_t) -> exception_t {
+    for i in 0..frameRegNum {
+        dest.tcbArch
+            .set_user_register(ArchReg::Frame(i), record.frame_regs[i]);
+    }
+    for i in 0..gpRegNum {
+        dest.tcbArch
+            .set_user_register(ArchReg::GP(i), record.gp_regs[i]);
+    }
+    dest.tcbArch.rewind_to_restart_pc();
+    dest.tcbArch.set_register(ArchReg::TlsBase, record.tls_base);
+    #[cfg(target_arch = "aarch64")]
+    dest.tcbArch.set_fpu_state(&record.fpu);
+    dest.tcbFault = record.fault;
+    dest.tcbLookupFailure = record.lookup_failure;
+    if record.has_pending_badge != 0 && dest.tcbBoundNotification != 0 {
+        convert_to_mut_type_ref::<notification_t>(dest.tcbBoundNotification)
+            .send_signal(record.pending_badge);
+    }
+    exception_t::EXCEPTION_NONE
+}
+
+#[cfg(all(feature = "ENABLE_SMP", not(feature = "ENABLE_MCS")))]
 #[inline]
-pub fn invoke_tcb_set_affinity(thread: &mut tcb_t, affinitiy: usize) -> exception_t {
+/// Move `thread` to the core `affinity`. A thread that becomes runnable there makes that core
+/// reschedule with an IPI if it should preempt what the core runs, see `tcb_t::sched_append`.
+/// `AFFINITY_ANY_CORE` leaves the thread where it is and lets any core pull it again.
+pub fn invoke_tcb_set_affinity(thread: &mut tcb_t, affinity: usize) -> exception_t {
+    #[cfg(feature = "ENABLE_SMP_LOAD_BALANCE")]
+    if affinity == sel4_task::balance::AFFINITY_ANY_CORE {
+        thread.tcbAffinityMask = 0;
+        return exception_t::EXCEPTION_NONE;
+    }
     thread.sched_dequeue();
-    unsafe {
-        crate::ffi::migrateTCB(thread, affinitiy);
+    thread.migrate(affinity);
+    #[cfg(feature = "ENABLE_SMP_LOAD_BALANCE")]
+    {
+        thread.tcbAffinityMask = sel4_common::BIT!(affinity);
     }
-    // debug!("tcb migrate: {}", thread.tcbAffinity);
     if thread.is_runnable() {
         thread.sched_append();
     }
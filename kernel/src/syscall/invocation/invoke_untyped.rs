@@ -1,13 +1,18 @@
 use crate::ffi::tcbDebugAppend;
+use crate::object::retype::construct_object;
 use crate::syscall::{
     FREE_INDEX_TO_OFFSET, GET_FREE_INDEX, GET_OFFSET_FREE_PTR, OFFSET_TO_FREE_IDNEX,
 };
-use sel4_common::arch::{vm_rights_t, ObjectType};
+use sel4_common::arch::{vm_rights_t, ArchReg, ObjectType};
+use sel4_common::message_info::seL4_MessageInfo_t;
 use sel4_common::{
     sel4_config::*, structures::exception_t, utils::convert_to_mut_type_ref, BIT, ROUND_DOWN,
 };
+use sel4_cspace::deps::preemptionPoint;
+#[cfg(feature = "ENABLE_OBJECT_GEN")]
+use sel4_cspace::interface::CapTag;
 use sel4_cspace::interface::{cap_t, cte_t, insert_new_cap};
-use sel4_task::{get_current_domain, tcb_t};
+use sel4_task::{get_currenct_thread, get_current_domain, tcb_t, ThreadStateMachine};
 use sel4_vspace::pptr_t;
 
 use crate::utils::*;
@@ -25,12 +30,23 @@ fn create_new_objects(
     // debug!("create_new_object: {:?}", obj_type);
     let object_size = obj_type.get_object_size(user_size);
     for i in 0..dest_length {
+        construct_object(obj_type, region_base + (i << object_size), object_size);
         let cap = create_object(
             obj_type,
             region_base + (i << object_size),
             user_size,
             device_mem,
         );
+        #[cfg(feature = "ENABLE_OBJECT_GEN")]
+        if matches!(
+            cap.get_cap_type(),
+            CapTag::CapThreadCap
+                | CapTag::CapEndpointCap
+                | CapTag::CapNotificationCap
+                | CapTag::CapCNodeCap
+        ) {
+            sel4_common::object_gen::object_gen_stamp(cap.get_cap_ptr());
+        }
         insert_new_cap(parent, dest_cnode.get_offset_slot(dest_offset + i), &cap);
     }
 }
@@ -62,6 +78,11 @@ fn create_object(
 
         ObjectType::UnytpedObject => cap_t::new_untyped_cap(0, device_mem, user_size, region_base),
 
+        #[cfg(feature = "ENABLE_MCS")]
+        ObjectType::SchedContextObject => cap_t::new_sched_context_cap(user_size, region_base),
+        #[cfg(feature = "ENABLE_MCS")]
+        ObjectType::ReplyObject => cap_t::new_reply_cap(1, 0, region_base),
+
         ObjectType::PageTableObject => cap_t::new_page_table_cap(asidInvalid, region_base, 0, 0),
 
         ObjectType::NormalPageObject | ObjectType::GigaPageObject | ObjectType::MegaPageObject => {
@@ -74,6 +95,8 @@ fn create_object(
                 0,
             )
         }
+        #[cfg(feature = "ENABLE_RISCV_HYP")]
+        ObjectType::RISCV_VCPUObject => cap_t::new_vcpu_cap(region_base),
     }
 }
 #[cfg(target_arch = "aarch64")]
@@ -107,6 +130,11 @@ fn create_object(
         ObjectType::NotificationObject => cap_t::new_notification_cap(0, 1, 1, region_base),
         ObjectType::EndpointObject => cap_t::new_endpoint_cap(0, 1, 1, 1, 1, region_base),
         ObjectType::UnytpedObject => cap_t::new_untyped_cap(0, device_mem, user_size, region_base),
+
+        #[cfg(feature = "ENABLE_MCS")]
+        ObjectType::SchedContextObject => cap_t::new_sched_context_cap(user_size, region_base),
+        #[cfg(feature = "ENABLE_MCS")]
+        ObjectType::ReplyObject => cap_t::new_reply_cap(1, 0, region_base),
         // TODO: remove ARCH related object to arch-specific module.
         ObjectType::seL4_ARM_PageUpperDirectoryObject => {
             cap_t::new_page_upper_directory_cap(asidInvalid, region_base, 0, 0)
@@ -132,6 +160,8 @@ fn create_object(
             asidInvalid,
             region_base,
         ),
+        #[cfg(feature = "ENABLE_ARM_HYP")]
+        ObjectType::seL4_ARM_VCPUObject => cap_t::new_vcpu_cap(region_base),
         _ => {
             unimplemented!(
                 "create object: {:?} region: {:#x} - {:#x}",
@@ -182,31 +212,85 @@ pub fn reset_untyped_cap(srcSlot: &mut cte_t) -> exception_t {
     let region_base = prev_cap.get_untyped_ptr();
     let chunk = CONFIG_RESET_CHUNK_BITS;
     let offset = FREE_INDEX_TO_OFFSET(prev_cap.get_untyped_free_index());
-    let device_mem = prev_cap.get_frame_is_device();
+    let device_mem = prev_cap.get_untyped_is_device();
     if offset == 0 {
         return exception_t::EXCEPTION_NONE;
     }
+    #[cfg(feature = "ENABLE_OBJECT_GEN")]
+    sel4_common::object_gen::object_gen_forget(region_base, offset);
 
-    if device_mem != 0 && block_size < chunk {
-        if device_mem != 0 {
+    if device_mem != 0 || block_size < chunk {
+        if device_mem == 0 {
             clear_memory(region_base as *mut u8, block_size);
         }
         prev_cap.set_untyped_free_index(0);
     } else {
+        // Clear from the top down one chunk at a time, so that a preempted reset keeps everything
+        // above the free index zeroed and resumes where it stopped.
         let mut offset: isize = ROUND_DOWN!(offset - 1, chunk) as isize;
         while offset != -(BIT!(chunk) as isize) {
             clear_memory(
                 GET_OFFSET_FREE_PTR(region_base, offset as usize) as *mut u8,
                 chunk,
             );
+            prev_cap.set_untyped_free_index(OFFSET_TO_FREE_IDNEX(offset as usize));
+            let status = unsafe { preemptionPoint() };
+            if status != exception_t::EXCEPTION_NONE {
+                return status;
+            }
             offset -= BIT!(chunk) as isize;
         }
-        prev_cap.set_untyped_free_index(OFFSET_TO_FREE_IDNEX(offset as usize));
     }
     exception_t::EXCEPTION_NONE
 }
 
-pub fn invoke_untyped_retype(
+/// An Untyped invocation which passed its decode checks, see `decode_untyped`.
+pub enum untyped_invocation_t {
+    Retype {
+        /// Whether the untyped has no children and is reset before retyping
+        reset: bool,
+        retype_base: pptr_t,
+        new_type: ObjectType,
+        user_size: usize,
+        dest_cnode: &'static mut cte_t,
+        dest_offset: usize,
+        dest_length: usize,
+        device_mem: usize,
+    },
+    Describe,
+}
+
+pub fn invoke_untyped(
+    slot: &mut cte_t,
+    invocation: untyped_invocation_t,
+    call: bool,
+) -> exception_t {
+    match invocation {
+        untyped_invocation_t::Retype {
+            reset,
+            retype_base,
+            new_type,
+            user_size,
+            dest_cnode,
+            dest_offset,
+            dest_length,
+            device_mem,
+        } => invoke_untyped_retype(
+            slot,
+            reset,
+            retype_base,
+            new_type,
+            user_size,
+            dest_cnode,
+            dest_offset,
+            dest_length,
+            device_mem,
+        ),
+        untyped_invocation_t::Describe => invoke_untyped_describe(slot, call),
+    }
+}
+
+fn invoke_untyped_retype(
     src_slot: &mut cte_t,
     reset: bool,
     retype_base: pptr_t,
@@ -241,3 +325,21 @@ pub fn invoke_untyped_retype(
     );
     exception_t::EXCEPTION_NONE
 }
+
+/// Reply with the watermark of the untyped in `slot`: the byte offset of the first free byte in
+/// `msg[0]`, and whether the untyped currently has children in `msg[1]`.
+fn invoke_untyped_describe(slot: &mut cte_t, call: bool) -> exception_t {
+    let thread = get_currenct_thread();
+    if call {
+        let has_children = slot.ensure_no_children() != exception_t::EXCEPTION_NONE;
+        thread.tcbArch.set_register(ArchReg::Badge, 0);
+        thread.set_mr(0, FREE_INDEX_TO_OFFSET(slot.cap.get_untyped_free_index()));
+        let length = thread.set_mr(1, has_children as usize);
+        thread.tcbArch.set_register(
+            ArchReg::MsgInfo,
+            seL4_MessageInfo_t::new(0, 0, 0, length).to_word(),
+        );
+        thread.set_running();
+    }
+    exception_t::EXCEPTION_NONE
+}
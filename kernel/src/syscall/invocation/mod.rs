@@ -1,8 +1,12 @@
 pub mod decode;
 mod invoke_cnode;
 pub mod invoke_irq;
+#[cfg(feature = "ENABLE_MEMORY_HOTADD")]
+mod invoke_memory_control;
 mod invoke_mmu_op;
-mod invoke_tcb;
+#[cfg(feature = "ENABLE_MCS")]
+mod invoke_sched_context;
+pub(crate) mod invoke_tcb;
 mod invoke_untyped;
 
 use core::intrinsics::unlikely;
@@ -10,7 +14,7 @@ use core::intrinsics::unlikely;
 use log::debug;
 use sel4_common::arch::{msgRegisterNum, ArchReg};
 use sel4_common::{fault::seL4_Fault_t, message_info::seL4_MessageInfo_t, structures::exception_t};
-use sel4_task::{get_currenct_thread, set_thread_state, ThreadState};
+use sel4_task::{get_currenct_thread, ThreadState, ThreadStateMachine};
 
 use crate::kernel::boot::current_fault;
 use crate::syscall::invocation::decode::decode_invocation;
@@ -18,11 +22,10 @@ use crate::syscall::syscall_reply::{reply_error_from_kernel, reply_success_from_
 use crate::syscall::{handle_fault, lookup_extra_caps_with_buf};
 
 #[no_mangle]
-pub fn handleInvocation(isCall: bool, isBlocking: bool) -> exception_t {
+pub fn handleInvocation(isCall: bool, isBlocking: bool, cptr: usize) -> exception_t {
     let thread = get_currenct_thread();
     let info =
         seL4_MessageInfo_t::from_word_security(thread.tcbArch.get_register(ArchReg::MsgInfo));
-    let cptr = thread.tcbArch.get_register(ArchReg::Cap);
     let lu_ret = thread.lookup_slot(cptr);
     if unlikely(lu_ret.status != exception_t::EXCEPTION_NONE) {
         debug!("Invocation of invalid cap {:#x}.", cptr);
@@ -76,7 +79,25 @@ pub fn handleInvocation(isCall: bool, isBlocking: bool) -> exception_t {
         if isCall {
             reply_success_from_kernel(thread);
         }
-        set_thread_state(thread, ThreadState::ThreadStateRunning);
+        thread.set_running();
     }
     return exception_t::EXCEPTION_NONE;
 }
+
+/// Apply an invocation which passed its decode checks.
+///
+/// Decoding is split in two, as in seL4: a `decode_*` function only reads the message and the
+/// caps it names, and either fails with a syscall error leaving the kernel untouched, or returns
+/// a typed invocation. The caller is then restarted and `invoke` applies the invocation.
+pub fn perform_invocation<T>(
+    decoded: Result<T, exception_t>,
+    invoke: impl FnOnce(T) -> exception_t,
+) -> exception_t {
+    match decoded {
+        Ok(invocation) => {
+            get_currenct_thread().set_restart();
+            invoke(invocation)
+        }
+        Err(status) => status,
+    }
+}
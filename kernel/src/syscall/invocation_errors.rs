@@ -0,0 +1,348 @@
+//! Error paths of the invocation decoders, run in the kernel through `SysDebugRun`.
+//!
+//! Every case invokes one cap with one class of invalid argument, replies with the error the
+//! decoder leaves in `current_syscall_error` and checks the reply user space would receive: the
+//! label and length of the message info and the error message registers. The cases are generated
+//! by [`invocation_error_cases`] from a table with one row per invocation and error class, so a
+//! new decoder only needs new rows. The rows of the MMU decoders are in a table of their own for
+//! each architecture.
+//!
+//! The caps are built on the fly and point at a scratch TCB or at nothing. Every case fails
+//! before the decoder touches the object, so nothing is modified. The message registers, the
+//! message info, the badge, the extra caps and the syscall error of the caller are saved and
+//! restored around the run.
+
+use log::debug;
+use sel4_common::arch::{frameRegNum, gpRegNum, msgRegisterNum, ArchReg, MessageLabel};
+use sel4_common::message_info::seL4_MessageInfo_t;
+use sel4_common::sel4_config::{
+    seL4_IllegalOperation, seL4_InvalidArgument, seL4_InvalidCapability, seL4_MsgMaxExtraCaps,
+    seL4_RangeError, seL4_TruncatedMessage, wordBits,
+};
+use sel4_common::structures::exception_t;
+use sel4_cspace::interface::{cap_t, cte_t};
+use sel4_task::{get_currenct_thread, tcb_t};
+
+#[cfg(target_arch = "riscv64")]
+use crate::config::USER_TOP;
+use crate::kernel::boot::{current_extra_caps, current_syscall_error};
+use crate::structures::{extra_caps_t, syscall_error_t};
+
+use super::invocation::decode::decode_invocation;
+use super::syscall_reply::reply_error_from_kernel;
+
+/// One invocation of a cap with invalid arguments and the error reply it is expected to get
+struct invocation_error_case_t {
+    name: &'static str,
+    label: MessageLabel,
+    cap: fn() -> cap_t,
+    extra_caps: &'static [fn() -> cap_t],
+    args: &'static [usize],
+    error: usize,
+    mrs: &'static [usize],
+}
+
+/// Generate a table of cases, one row per invocation and error class:
+///
+/// `name: label, cap + extra_cap.., [args] => error_type [reply message registers];`
+///
+/// The caps are given by the functions which build them, the extra caps are optional.
+macro_rules! invocation_error_cases {
+    ($(
+        $name:ident: $label:ident, $cap:ident $(+ $extra:ident)*, [$($arg:expr),*]
+            => $err:ident [$($mr:expr),*];
+    )*) => {
+        &[$(
+            invocation_error_case_t {
+                name: stringify!($name),
+                label: MessageLabel::$label,
+                cap: $cap,
+                extra_caps: &[$($extra),*],
+                args: &[$($arg),*],
+                error: $err,
+                mrs: &[$($mr),*],
+            },
+        )*]
+    };
+}
+
+const NO_SYSCALL_ERROR: syscall_error_t = syscall_error_t {
+    invalidArgumentNumber: 0,
+    invalidCapNumber: 0,
+    rangeErrorMax: 0,
+    rangeErrorMin: 0,
+    memoryLeft: 0,
+    failedLookupWasSource: 0,
+    _type: 0,
+};
+
+/// Scratch TCB the thread caps point at, never scheduled
+static mut invocation_error_tcb: tcb_t = unsafe { core::mem::zeroed() };
+
+/// Scratch slots which hold the extra caps of a case
+static mut invocation_error_extra_slots: [cte_t; seL4_MsgMaxExtraCaps] =
+    unsafe { core::mem::zeroed() };
+
+fn null_cap() -> cap_t {
+    cap_t::new_null_cap()
+}
+
+fn endpoint_cap() -> cap_t {
+    cap_t::new_endpoint_cap(0, 1, 1, 1, 1, 0)
+}
+
+fn read_only_endpoint_cap() -> cap_t {
+    cap_t::new_endpoint_cap(0, 0, 0, 0, 1, 0)
+}
+
+fn read_only_notification_cap() -> cap_t {
+    cap_t::new_notification_cap(0, 1, 0, 0)
+}
+
+fn reply_master_cap() -> cap_t {
+    cap_t::new_reply_cap(0, 1, 0)
+}
+
+fn cnode_cap() -> cap_t {
+    cap_t::new_cnode_cap(1, 0, 0, 0)
+}
+
+fn thread_cap() -> cap_t {
+    cap_t::new_thread_cap(unsafe { &invocation_error_tcb } as *const tcb_t as usize)
+}
+
+fn untyped_cap() -> cap_t {
+    cap_t::new_untyped_cap(0, 0, 12, 0)
+}
+
+fn domain_cap() -> cap_t {
+    cap_t::new_domain_cap()
+}
+
+fn irq_control_cap() -> cap_t {
+    cap_t::new_irq_control_cap()
+}
+
+fn irq_handler_cap() -> cap_t {
+    cap_t::new_irq_handler_cap(0)
+}
+
+/// A frame which is not mapped, the fields line up to 0 on every architecture
+fn frame_cap() -> cap_t {
+    cap_t::new_frame_cap(0, 0, 0, 0, 0, 0)
+}
+
+fn page_table_cap() -> cap_t {
+    cap_t::new_page_table_cap(0, 0, 0, 0)
+}
+
+fn mapped_page_table_cap() -> cap_t {
+    cap_t::new_page_table_cap(0, 0, 1, 0)
+}
+
+#[cfg(target_arch = "aarch64")]
+fn vspace_cap() -> cap_t {
+    cap_t::new_page_global_directory_cap(0, 0, 0)
+}
+
+fn asid_control_cap() -> cap_t {
+    cap_t::new_asid_control_cap()
+}
+
+fn asid_pool_cap() -> cap_t {
+    cap_t::new_asid_pool_cap(0, 0)
+}
+
+static INVOCATION_ERROR_CASES: &[invocation_error_case_t] = invocation_error_cases! {
+    null_cap_invoked: CNodeRevoke, null_cap, [] => seL4_InvalidCapability [0];
+    read_only_endpoint_send: InvalidInvocation, read_only_endpoint_cap, []
+        => seL4_InvalidCapability [0];
+    read_only_notification_signal: InvalidInvocation, read_only_notification_cap, []
+        => seL4_InvalidCapability [0];
+    reply_master_invoked: InvalidInvocation, reply_master_cap, [] => seL4_InvalidCapability [0];
+
+    cnode_illegal_label: UntypedRetype, cnode_cap, [0, 1] => seL4_IllegalOperation [];
+    cnode_truncated: CNodeRevoke, cnode_cap, [0] => seL4_TruncatedMessage [];
+    cnode_depth_zero: CNodeRevoke, cnode_cap, [0, 0] => seL4_RangeError [1, wordBits];
+    cnode_depth_too_large: CNodeRevoke, cnode_cap, [0, wordBits + 1]
+        => seL4_RangeError [1, wordBits];
+
+    tcb_illegal_label: CNodeRevoke, thread_cap, [] => seL4_IllegalOperation [];
+    tcb_read_registers_truncated: TCBReadRegisters, thread_cap, [0] => seL4_TruncatedMessage [];
+    tcb_read_registers_none: TCBReadRegisters, thread_cap, [0, 0]
+        => seL4_RangeError [1, frameRegNum + gpRegNum];
+    tcb_read_registers_too_many: TCBReadRegisters, thread_cap, [0, frameRegNum + gpRegNum + 1]
+        => seL4_RangeError [1, frameRegNum + gpRegNum];
+    tcb_write_registers_truncated: TCBWriteRegisters, thread_cap, [0] => seL4_TruncatedMessage [];
+    tcb_set_priority_truncated: TCBSetPriority, thread_cap, [] => seL4_TruncatedMessage [];
+    tcb_set_tls_base_truncated: TCBSetTLSBase, thread_cap, [] => seL4_TruncatedMessage [];
+    tcb_bind_notification_truncated: TCBBindNotification, thread_cap, []
+        => seL4_TruncatedMessage [];
+    tcb_bind_notification_not_a_notification: TCBBindNotification, thread_cap + endpoint_cap, []
+        => seL4_IllegalOperation [];
+    tcb_unbind_notification_unbound: TCBUnbindNotification, thread_cap, []
+        => seL4_IllegalOperation [];
+
+    untyped_illegal_label: CNodeRevoke, untyped_cap, [] => seL4_IllegalOperation [];
+    untyped_retype_truncated: UntypedRetype, untyped_cap, [0, 0, 0, 0]
+        => seL4_TruncatedMessage [];
+
+    domain_illegal_label: CNodeRevoke, domain_cap, [] => seL4_IllegalOperation [];
+    domain_set_truncated: DomainSetSet, domain_cap, [] => seL4_TruncatedMessage [];
+
+    irq_issue_handler_truncated: IRQIssueIRQHandler, irq_control_cap, [0, 0]
+        => seL4_TruncatedMessage [];
+    irq_handler_illegal_label: CNodeRevoke, irq_handler_cap, [] => seL4_IllegalOperation [];
+    irq_handler_set_truncated: IRQSetIRQHandler, irq_handler_cap, [] => seL4_TruncatedMessage [];
+    irq_handler_set_not_a_notification: IRQSetIRQHandler, irq_handler_cap + endpoint_cap, []
+        => seL4_InvalidCapability [0];
+    irq_handler_set_read_only_notification: IRQSetIRQHandler,
+        irq_handler_cap + read_only_notification_cap, [] => seL4_InvalidCapability [0];
+};
+
+#[cfg(target_arch = "riscv64")]
+static ARCH_INVOCATION_ERROR_CASES: &[invocation_error_case_t] = invocation_error_cases! {
+    vspace_flush_truncated: RISCVVSpaceClean_Data, page_table_cap, [0]
+        => seL4_TruncatedMessage [];
+    vspace_flush_empty_range: RISCVVSpaceClean_Data, page_table_cap, [1, 1]
+        => seL4_InvalidArgument [1];
+    vspace_promote_kernel_window: RISCVVSpacePromote, page_table_cap, [USER_TOP]
+        => seL4_InvalidArgument [0];
+
+    page_table_illegal_label: CNodeRevoke, page_table_cap, [] => seL4_IllegalOperation [];
+    page_table_map_truncated: RISCVPageTableMap, page_table_cap, [0, 0]
+        => seL4_TruncatedMessage [];
+    page_table_map_mapped: RISCVPageTableMap, mapped_page_table_cap + page_table_cap, [0, 0]
+        => seL4_InvalidCapability [0];
+
+    page_illegal_label: CNodeRevoke, frame_cap, [] => seL4_IllegalOperation [];
+    page_map_truncated: RISCVPageMap, frame_cap + page_table_cap, [0, 0]
+        => seL4_TruncatedMessage [];
+    page_clean_unmapped: RISCVPageClean_Data, frame_cap, [0, 1] => seL4_IllegalOperation [];
+
+    asid_control_illegal_label: CNodeRevoke, asid_control_cap, [] => seL4_IllegalOperation [];
+    asid_control_make_pool_truncated: RISCVASIDControlMakePool, asid_control_cap, [0, 0]
+        => seL4_TruncatedMessage [];
+    asid_pool_illegal_label: CNodeRevoke, asid_pool_cap, [] => seL4_IllegalOperation [];
+    asid_pool_assign_truncated: RISCVASIDPoolAssign, asid_pool_cap, []
+        => seL4_TruncatedMessage [];
+};
+
+#[cfg(target_arch = "aarch64")]
+static ARCH_INVOCATION_ERROR_CASES: &[invocation_error_case_t] = invocation_error_cases! {
+    vspace_illegal_label: CNodeRevoke, vspace_cap, [] => seL4_IllegalOperation [];
+    vspace_flush_truncated: ARMVSpaceClean_Data, vspace_cap, [0] => seL4_TruncatedMessage [];
+    vspace_flush_empty_range: ARMVSpaceClean_Data, vspace_cap, [1, 1]
+        => seL4_InvalidArgument [1];
+
+    page_table_illegal_label: CNodeRevoke, page_table_cap, [] => seL4_IllegalOperation [];
+    page_table_map_truncated: ARMPageTableMap, page_table_cap, [0, 0]
+        => seL4_TruncatedMessage [];
+    page_table_map_mapped: ARMPageTableMap, mapped_page_table_cap + vspace_cap, [0, 0]
+        => seL4_InvalidCapability [0];
+
+    page_illegal_label: CNodeRevoke, frame_cap, [] => seL4_IllegalOperation [];
+    page_map_truncated: ARMPageMap, frame_cap + vspace_cap, [0, 0] => seL4_TruncatedMessage [];
+    page_clean_unmapped: ARMPageClean_Data, frame_cap, [0, 1] => seL4_IllegalOperation [];
+
+    asid_control_illegal_label: CNodeRevoke, asid_control_cap, [] => seL4_IllegalOperation [];
+    asid_control_make_pool_truncated: ARMASIDControlMakePool, asid_control_cap, [0, 0]
+        => seL4_TruncatedMessage [];
+    asid_pool_illegal_label: CNodeRevoke, asid_pool_cap, [] => seL4_IllegalOperation [];
+    asid_pool_assign_truncated: ARMASIDPoolAssign, asid_pool_cap, []
+        => seL4_TruncatedMessage [];
+};
+
+/// Run one case, returns whether the reply carried the expected error.
+///
+/// The arguments are passed in the message registers only, so a case takes at most
+/// `msgRegisterNum` of them.
+fn run_case(case: &invocation_error_case_t) -> bool {
+    let thread = get_currenct_thread();
+    for (i, arg) in case.args.iter().enumerate() {
+        thread.tcbArch.set_register(ArchReg::Msg(i), *arg);
+    }
+    unsafe {
+        current_syscall_error = NO_SYSCALL_ERROR;
+        current_extra_caps = extra_caps_t {
+            excaprefs: [0; seL4_MsgMaxExtraCaps],
+        };
+        for (i, extra_cap) in case.extra_caps.iter().enumerate() {
+            invocation_error_extra_slots[i].cap = extra_cap();
+            current_extra_caps.excaprefs[i] =
+                &invocation_error_extra_slots[i] as *const cte_t as usize;
+        }
+    }
+    let cap = (case.cap)();
+    let mut slot = cte_t::default();
+    slot.cap = cap;
+    let status = decode_invocation(
+        case.label,
+        case.args.len(),
+        &mut slot,
+        &cap,
+        0,
+        true,
+        false,
+        None,
+    );
+    if status != exception_t::EXCEPTION_SYSCALL_ERROR {
+        debug!(
+            "invocation error case {} failed: status {:?}",
+            case.name, status
+        );
+        return false;
+    }
+
+    reply_error_from_kernel(thread);
+    let info = seL4_MessageInfo_t::from_word(thread.tcbArch.get_register(ArchReg::MsgInfo));
+    let passed = info.get_usize_label() == case.error
+        && info.get_length() == case.mrs.len()
+        && case
+            .mrs
+            .iter()
+            .enumerate()
+            .all(|(i, mr)| thread.tcbArch.get_register(ArchReg::Msg(i)) == *mr);
+    if !passed {
+        debug!(
+            "invocation error case {} failed: reply label {} length {}",
+            case.name,
+            info.get_usize_label(),
+            info.get_length()
+        );
+    }
+    passed
+}
+
+/// Run every case.
+///
+/// # Returns
+///
+/// The number of cases that did not get the expected error reply, 0 if all passed.
+pub fn run_invocation_error_cases() -> usize {
+    let thread = get_currenct_thread();
+    let mut saved_msgs = [0; msgRegisterNum];
+    for (i, msg) in saved_msgs.iter_mut().enumerate() {
+        *msg = thread.tcbArch.get_register(ArchReg::Msg(i));
+    }
+    let saved_info = thread.tcbArch.get_register(ArchReg::MsgInfo);
+    let saved_badge = thread.tcbArch.get_register(ArchReg::Badge);
+    let (saved_error, saved_extra_caps) = unsafe { (current_syscall_error, current_extra_caps) };
+
+    let failed = INVOCATION_ERROR_CASES
+        .iter()
+        .chain(ARCH_INVOCATION_ERROR_CASES)
+        .filter(|case| !run_case(case))
+        .count();
+
+    for (i, msg) in saved_msgs.iter().enumerate() {
+        thread.tcbArch.set_register(ArchReg::Msg(i), *msg);
+    }
+    thread.tcbArch.set_register(ArchReg::MsgInfo, saved_info);
+    thread.tcbArch.set_register(ArchReg::Badge, saved_badge);
+    unsafe {
+        current_syscall_error = saved_error;
+        current_extra_caps = saved_extra_caps;
+    }
+    failed
+}
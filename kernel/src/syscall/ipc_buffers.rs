@@ -0,0 +1,193 @@
+//! Checks of the IPC buffer access of the transfer code, run in the kernel through `SysDebugRun`.
+//!
+//! The transfer code reaches the IPC buffers of both threads through the kernel alias of the
+//! frame in their buffer slot, never through a user address. The checks give the two scratch
+//! threads of `super::message_registers` buffer frames mapped in different vspaces, at different
+//! user addresses, and change those mappings between the lookup and the transfer. The frames are
+//! kernel pages, only the frame caps claim they are mapped, so nothing is ever mapped or unmapped.
+
+use sel4_common::arch::{msgRegisterNum, vm_rights_t};
+use sel4_common::sel4_config::{seL4_MsgMaxLength, seL4_PageBits, tcbBuffer};
+use sel4_common::structures::seL4_IPCBuffer;
+use sel4_common::{BIT, MASK};
+use sel4_cspace::interface::cap_t;
+use sel4_task::tcb_t;
+
+use super::message_registers::scratch_threads;
+
+/// A kernel page standing in for a user frame
+#[repr(align(4096))]
+struct scratch_page_t {
+    data: [u8; BIT!(seL4_PageBits)],
+}
+
+static mut ipc_buffer_pages: [scratch_page_t; 2] = unsafe { core::mem::zeroed() };
+
+/// A transfer between threads in different vspaces lands in the receiver's buffer
+pub const IPCBufferCrossVSpace: usize = 1 << 0;
+/// Unmapping and remapping the buffer frames before the transfer does not move the buffers
+pub const IPCBufferRemapped: usize = 1 << 1;
+/// A receiver needs a writable buffer, a sender may have a read-only one
+pub const IPCBufferRights: usize = 1 << 2;
+/// A device frame is never used as buffer
+pub const IPCBufferDevice: usize = 1 << 3;
+/// Deleting the buffer cap of a thread drops its buffer
+pub const IPCBufferDeleted: usize = 1 << 4;
+
+/// User address of the sender buffer, in ASID 1
+const SENDER_BUFFER_VPTR: usize = 0x10000;
+/// User address of the receiver buffer, in ASID 2, not at the start of its frame
+const RECEIVER_BUFFER_VPTR: usize = 0x20400;
+
+#[cfg(target_arch = "riscv64")]
+fn frame_cap(page: usize, asid: usize, vptr: usize, rights: vm_rights_t, device: usize) -> cap_t {
+    use sel4_common::sel4_config::RISCV_4K_Page;
+    cap_t::new_frame_cap(asid, page, RISCV_4K_Page, rights as usize, device, vptr)
+}
+
+#[cfg(target_arch = "aarch64")]
+fn frame_cap(page: usize, asid: usize, vptr: usize, rights: vm_rights_t, device: usize) -> cap_t {
+    use sel4_common::sel4_config::ARM_Small_Page;
+    cap_t::new_frame_cap(device, rights as usize, vptr, ARM_Small_Page, asid, page)
+}
+
+fn page(i: usize) -> usize {
+    unsafe { ipc_buffer_pages[i].data.as_ptr() as usize }
+}
+
+/// Give `thread` the buffer `frame`, mapped at `vptr` of `asid`.
+fn set_buffer(thread: &mut tcb_t, frame: usize, asid: usize, vptr: usize, rights: vm_rights_t) {
+    thread.get_cspace_mut_ref(tcbBuffer).cap =
+        frame_cap(frame, asid, vptr & !MASK!(seL4_PageBits), rights, 0);
+    thread.set_ipc_buffer(vptr);
+}
+
+/// Zero the scratch threads and pages, and give the threads buffers in different vspaces.
+fn scratch_transfer() -> (&'static mut tcb_t, &'static mut tcb_t) {
+    unsafe {
+        ipc_buffer_pages = core::mem::zeroed();
+    }
+    let (sender, receiver) = scratch_threads();
+    set_buffer(
+        sender,
+        page(0),
+        1,
+        SENDER_BUFFER_VPTR,
+        vm_rights_t::VMReadWrite,
+    );
+    set_buffer(
+        receiver,
+        page(1),
+        2,
+        RECEIVER_BUFFER_VPTR,
+        vm_rights_t::VMReadWrite,
+    );
+    (sender, receiver)
+}
+
+/// The message word `i` of a run seeded with `seed`
+fn pattern(seed: usize, i: usize) -> usize {
+    seed.wrapping_mul(131).wrapping_add(i + 1)
+}
+
+/// Write the words past the message registers into the sender buffer and transfer them.
+fn transfer(sender: &mut tcb_t, receiver: &mut tcb_t, seed: usize) -> bool {
+    if let Some(buffer) = sender.lookup_mut_ipc_buffer(false) {
+        for i in msgRegisterNum..seL4_MsgMaxLength {
+            buffer.msg[i] = pattern(seed, i);
+        }
+    }
+    sender.copy_mrs(receiver, seL4_MsgMaxLength) == seL4_MsgMaxLength
+}
+
+/// Whether the words arrived at `RECEIVER_BUFFER_VPTR`'s offset in the receiver page.
+fn buffer_received(seed: usize) -> bool {
+    let buffer = unsafe {
+        &*((page(1) + (RECEIVER_BUFFER_VPTR & MASK!(seL4_PageBits))) as *const seL4_IPCBuffer)
+    };
+    (msgRegisterNum..seL4_MsgMaxLength).all(|i| buffer.msg[i] == pattern(seed, i))
+}
+
+fn buffer_ptr(buffer: Option<&seL4_IPCBuffer>) -> usize {
+    buffer.map_or(0, |buffer| buffer as *const seL4_IPCBuffer as usize)
+}
+
+/// Run every check with message words derived from `seed`.
+///
+/// # Returns
+///
+/// The `IPCBuffer*` bits of the checks that failed, 0 if all passed.
+pub fn run_ipc_buffer_checks(seed: usize) -> usize {
+    let mut failed = 0;
+
+    let (sender, receiver) = scratch_transfer();
+    if !transfer(sender, receiver, seed) || !buffer_received(seed) {
+        failed |= IPCBufferCrossVSpace;
+    }
+
+    let (sender, receiver) = scratch_transfer();
+    let sender_alias = buffer_ptr(sender.lookup_ipc_buffer(false));
+    let receiver_alias = buffer_ptr(receiver.lookup_ipc_buffer(true));
+    sender
+        .get_cspace_mut_ref(tcbBuffer)
+        .cap
+        .set_frame_mapped_asid(0);
+    sender
+        .get_cspace_mut_ref(tcbBuffer)
+        .cap
+        .set_frame_mapped_address(0);
+    receiver
+        .get_cspace_mut_ref(tcbBuffer)
+        .cap
+        .set_frame_mapped_address(0x30000);
+    if !transfer(sender, receiver, seed)
+        || !buffer_received(seed)
+        || buffer_ptr(sender.lookup_ipc_buffer(false)) != sender_alias
+        || buffer_ptr(receiver.lookup_ipc_buffer(true)) != receiver_alias
+    {
+        failed |= IPCBufferRemapped;
+    }
+
+    let (sender, receiver) = scratch_transfer();
+    set_buffer(
+        sender,
+        page(0),
+        1,
+        SENDER_BUFFER_VPTR,
+        vm_rights_t::VMReadOnly,
+    );
+    set_buffer(
+        receiver,
+        page(1),
+        2,
+        RECEIVER_BUFFER_VPTR,
+        vm_rights_t::VMReadOnly,
+    );
+    if sender.lookup_ipc_buffer(false).is_none()
+        || receiver.lookup_mut_ipc_buffer(true).is_some()
+        || sender.copy_mrs(receiver, seL4_MsgMaxLength) != msgRegisterNum
+    {
+        failed |= IPCBufferRights;
+    }
+
+    let (sender, receiver) = scratch_transfer();
+    receiver
+        .get_cspace_mut_ref(tcbBuffer)
+        .cap
+        .set_frame_is_device(1);
+    if receiver.lookup_ipc_buffer(false).is_some()
+        || sender.copy_mrs(receiver, seL4_MsgMaxLength) != msgRegisterNum
+    {
+        failed |= IPCBufferDevice;
+    }
+
+    let (sender, receiver) = scratch_transfer();
+    receiver.get_cspace_mut_ref(tcbBuffer).cap = cap_t::new_null_cap();
+    if receiver.lookup_ipc_buffer(false).is_some()
+        || sender.copy_mrs(receiver, seL4_MsgMaxLength) != msgRegisterNum
+    {
+        failed |= IPCBufferDeleted;
+    }
+
+    failed
+}
@@ -0,0 +1,110 @@
+//! Conformance checks of the message register ABI, run in the kernel through `SysDebugRun`.
+//!
+//! The seL4 ABI passes the first `msgRegisterNum` message words of Send, Call and Reply in CPU
+//! registers (`a2`-`a5` on RISC-V, `x2`-`x5` on AArch64) and only the rest in the IPC buffer.
+//! The checks run the transfer helpers of the slowpath and the fastpath between two scratch
+//! threads without an IPC buffer, so every word that arrives must have travelled in registers.
+//!
+//! The scratch threads live in TCB blocks of their own, with null caps in the TCB CNode, and are
+//! never scheduled.
+
+use sel4_common::arch::{msgRegisterNum, ArchReg};
+use sel4_common::sel4_config::{seL4_MsgMaxLength, seL4_TCBBits, TCB_OFFSET};
+use sel4_common::utils::convert_to_mut_type_ref;
+use sel4_common::BIT;
+use sel4_task::tcb_t;
+
+use crate::kernel::fastpath::fastpath_copy_mrs;
+
+/// A zeroed TCB object, the CNode slots of the thread followed by its `tcb_t`
+#[repr(align(2048))]
+struct scratch_tcb_block_t {
+    data: [u8; BIT!(seL4_TCBBits)],
+}
+
+static mut scratch_tcb_blocks: [scratch_tcb_block_t; 2] = unsafe { core::mem::zeroed() };
+
+/// `copy_mrs` of a register-sized message delivers every word in the receiver registers
+pub const MessageRegistersCopy: usize = 1 << 0;
+/// `copy_mrs` of a longer message without IPC buffers still delivers the register words
+pub const MessageRegistersCopyLong: usize = 1 << 1;
+/// `set_mr` writes the first words to registers and needs a buffer for the rest
+pub const MessageRegistersSetMR: usize = 1 << 2;
+/// `fastpath_copy_mrs` delivers the same registers as `copy_mrs`
+pub const MessageRegistersFastpath: usize = 1 << 3;
+
+/// Zero the scratch TCB blocks and return the sender and the receiver, also used by
+/// `super::ipc_buffers`.
+pub(super) fn scratch_threads() -> (&'static mut tcb_t, &'static mut tcb_t) {
+    unsafe {
+        core::ptr::write_bytes(core::ptr::addr_of_mut!(scratch_tcb_blocks), 0, 1);
+        (
+            convert_to_mut_type_ref::<tcb_t>(
+                scratch_tcb_blocks[0].data.as_ptr() as usize + TCB_OFFSET,
+            ),
+            convert_to_mut_type_ref::<tcb_t>(
+                scratch_tcb_blocks[1].data.as_ptr() as usize + TCB_OFFSET,
+            ),
+        )
+    }
+}
+
+/// The message word `i` of a run seeded with `seed`
+fn pattern(seed: usize, i: usize) -> usize {
+    seed.wrapping_mul(31).wrapping_add(i + 1)
+}
+
+fn fill_message(sender: &mut tcb_t, seed: usize) {
+    for i in 0..msgRegisterNum {
+        sender
+            .tcbArch
+            .set_register(ArchReg::Msg(i), pattern(seed, i));
+    }
+}
+
+fn message_received(receiver: &tcb_t, seed: usize) -> bool {
+    (0..msgRegisterNum).all(|i| receiver.tcbArch.get_register(ArchReg::Msg(i)) == pattern(seed, i))
+}
+
+/// Run every check with message words derived from `seed`.
+///
+/// # Returns
+///
+/// The `MessageRegisters*` bits of the checks that failed, 0 if all passed.
+pub fn run_message_register_checks(seed: usize) -> usize {
+    let mut failed = 0;
+
+    let (sender, receiver) = scratch_threads();
+    fill_message(sender, seed);
+    if sender.copy_mrs(receiver, msgRegisterNum) != msgRegisterNum
+        || !message_received(receiver, seed)
+    {
+        failed |= MessageRegistersCopy;
+    }
+
+    let (sender, receiver) = scratch_threads();
+    fill_message(sender, seed);
+    if sender.copy_mrs(receiver, seL4_MsgMaxLength) != msgRegisterNum
+        || !message_received(receiver, seed)
+    {
+        failed |= MessageRegistersCopyLong;
+    }
+
+    let (_, receiver) = scratch_threads();
+    let in_registers = (0..msgRegisterNum).all(|i| {
+        receiver.set_mr(i, pattern(seed, i)) == i + 1
+            && receiver.tcbArch.get_register(ArchReg::Msg(i)) == pattern(seed, i)
+    });
+    if !in_registers || receiver.set_mr(msgRegisterNum, seed) != msgRegisterNum {
+        failed |= MessageRegistersSetMR;
+    }
+
+    let (sender, receiver) = scratch_threads();
+    fill_message(sender, seed);
+    fastpath_copy_mrs(msgRegisterNum, sender, receiver);
+    if !message_received(receiver, seed) {
+        failed |= MessageRegistersFastpath;
+    }
+
+    failed
+}
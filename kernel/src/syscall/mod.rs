@@ -1,28 +1,110 @@
+#[cfg(feature = "ENABLE_DEBUG_RUN")]
+pub mod debug_run;
 pub mod invocation;
+#[cfg(feature = "ENABLE_DEBUG_RUN")]
+mod invocation_errors;
+#[cfg(feature = "ENABLE_DEBUG_RUN")]
+mod ipc_buffers;
+#[cfg(feature = "ENABLE_DEBUG_RUN")]
+mod message_registers;
+#[cfg(feature = "ENABLE_STATE_DUMP")]
+pub mod snapshot;
 pub mod syscall_reply;
+#[cfg(feature = "ENABLE_SYSCALL_TRACE")]
+pub mod syscall_trace;
 pub mod utils;
 
 use core::intrinsics::unlikely;
 use sel4_common::arch::ArchReg;
 use sel4_common::fault::{lookup_fault_t, seL4_Fault_t, FaultType};
 use sel4_common::ffi_call;
+#[cfg(not(feature = "ENABLE_MCS"))]
 use sel4_common::sel4_config::tcbCaller;
 
 pub const SysCall: isize = -1;
 pub const SysReplyRecv: isize = -2;
+#[cfg(not(feature = "ENABLE_MCS"))]
 pub const SysSend: isize = -3;
+#[cfg(not(feature = "ENABLE_MCS"))]
 pub const SysNBSend: isize = -4;
+#[cfg(not(feature = "ENABLE_MCS"))]
 pub const SysRecv: isize = -5;
+#[cfg(not(feature = "ENABLE_MCS"))]
 pub const SysReply: isize = -6;
+#[cfg(not(feature = "ENABLE_MCS"))]
 pub const SysYield: isize = -7;
 pub const SysNBRecv: isize = -8;
+/// Send to the cap in `ArchReg::NBSendRecvDest` without blocking, then receive
+#[cfg(feature = "ENABLE_MCS")]
+pub const SysNBSendRecv: isize = -3;
+/// Send to the cap in `ArchReg::Reply` without blocking, then wait without a reply object
+#[cfg(feature = "ENABLE_MCS")]
+pub const SysNBSendWait: isize = -4;
+#[cfg(feature = "ENABLE_MCS")]
+pub const SysSend: isize = -5;
+#[cfg(feature = "ENABLE_MCS")]
+pub const SysNBSend: isize = -6;
+#[cfg(feature = "ENABLE_MCS")]
+pub const SysRecv: isize = -7;
+/// Receive without a reply object
+#[cfg(feature = "ENABLE_MCS")]
+pub const SysWait: isize = -9;
+#[cfg(feature = "ENABLE_MCS")]
+pub const SysNBWait: isize = -10;
+#[cfg(feature = "ENABLE_MCS")]
+pub const SysYield: isize = -11;
+/// The lowest number of the syscalls `handleSyscall` handles, the highest is `SysCall`
+#[cfg(not(feature = "ENABLE_MCS"))]
+const SysMin: isize = SysNBRecv;
+#[cfg(feature = "ENABLE_MCS")]
+const SysMin: isize = SysYield;
+#[cfg(feature = "ENABLE_STATE_DUMP")]
+pub const SysDebugSnapshot: isize = -13;
+#[cfg(feature = "ENABLE_DEBUG_RUN")]
+pub const SysDebugRun: isize = -15;
+#[cfg(feature = "ENABLE_BENCHMARK")]
+pub const SysBenchmarkFlushCaches: isize = -16;
+#[cfg(feature = "ENABLE_BENCHMARK")]
+pub const SysBenchmarkResetLog: isize = -17;
+#[cfg(feature = "ENABLE_BENCHMARK")]
+pub const SysBenchmarkFinalizeLog: isize = -18;
+#[cfg(feature = "ENABLE_BENCHMARK")]
+pub const SysBenchmarkGetEndpointStats: isize = -19;
+/// Flush the caches by virtual address range, see `crate::benchmark`.
+#[cfg(feature = "ENABLE_BENCHMARK")]
+pub const SysBenchmarkFlushCachesRange: isize = -22;
+/// Read the idle time of a core, see `crate::benchmark`.
+#[cfg(feature = "ENABLE_BENCHMARK")]
+pub const SysBenchmarkGetIdleTime: isize = -25;
+/// Switch IPC tracing on if the cap register is non-zero, off otherwise. The previous state is
+/// returned in the badge register, see `sel4_ipc::ipc_trace`.
+#[cfg(feature = "ENABLE_IPC_TRACE")]
+pub const SysDebugIpcTrace: isize = -20;
+/// Switch scheduler tracing on or off like `SysDebugIpcTrace`, see `sel4_task::sched_trace`.
+#[cfg(feature = "ENABLE_SCHED_TRACE")]
+pub const SysDebugSchedTrace: isize = -23;
+/// Switch syscall tracing of the thread of a TCB cap, see `syscall_trace`.
+#[cfg(feature = "ENABLE_SYSCALL_TRACE")]
+pub const SysDebugSyscallTrace: isize = -26;
+/// Read the interrupt latency histogram, see `crate::interrupt::latency`.
+#[cfg(feature = "ENABLE_IRQ_LATENCY")]
+pub const SysDebugIrqLatency: isize = -27;
+/// Read the build metadata of the kernel, see `crate::version`.
+#[cfg(feature = "ENABLE_DEBUG_VERSION")]
+pub const SysDebugVersion: isize = -21;
+/// Read the features the kernel was built with as a bitmask, see `crate::version`.
+#[cfg(feature = "ENABLE_KERNEL_FEATURES")]
+pub const SysKernelFeatures: isize = -24;
 use sel4_common::structures::exception_t;
-use sel4_common::utils::{convert_to_mut_type_ref, ptr_to_mut};
+use sel4_common::utils::{convert_to_checked_mut_type_ref, convert_to_mut_type_ref, ptr_to_mut};
 use sel4_cspace::interface::CapTag;
+#[cfg(feature = "ENABLE_MCS")]
+use sel4_ipc::reply_t;
 use sel4_ipc::{endpoint_t, notification_t, Transfer};
+#[cfg(feature = "ENABLE_MCS")]
+use sel4_task::sched_context::sched_context_t;
 use sel4_task::{
-    activateThread, get_currenct_thread, rescheduleRequired, schedule, set_thread_state, tcb_t,
-    ThreadState,
+    activateThread, get_currenct_thread, rescheduleRequired, schedule, tcb_t, ThreadStateMachine,
 };
 pub use utils::*;
 
@@ -35,7 +117,101 @@ use self::invocation::handleInvocation;
 
 #[no_mangle]
 pub fn slowpath(syscall: usize) {
-    if (syscall as isize) < -8 || (syscall as isize) > -1 {
+    #[cfg(feature = "ENABLE_DEBUG_RUN")]
+    if syscall as isize == SysDebugRun {
+        debug_run::handle_debug_run();
+        restore_user_context();
+        return;
+    }
+    #[cfg(feature = "ENABLE_STATE_DUMP")]
+    if syscall as isize == SysDebugSnapshot {
+        snapshot::handle_debug_snapshot();
+        restore_user_context();
+        return;
+    }
+    #[cfg(feature = "ENABLE_BENCHMARK")]
+    if syscall as isize == SysBenchmarkResetLog || syscall as isize == SysBenchmarkFinalizeLog {
+        if syscall as isize == SysBenchmarkResetLog {
+            crate::benchmark::benchmark_reset_log();
+        } else {
+            crate::benchmark::benchmark_finalize_log();
+        }
+        restore_user_context();
+        return;
+    }
+    #[cfg(feature = "ENABLE_BENCHMARK")]
+    if syscall as isize == SysBenchmarkGetEndpointStats {
+        crate::benchmark::benchmark_get_endpoint_stats();
+        restore_user_context();
+        return;
+    }
+    #[cfg(feature = "ENABLE_BENCHMARK")]
+    if syscall as isize == SysBenchmarkGetIdleTime {
+        crate::benchmark::benchmark_get_idle_time();
+        restore_user_context();
+        return;
+    }
+    #[cfg(feature = "ENABLE_BENCHMARK")]
+    if syscall as isize == SysBenchmarkFlushCaches {
+        crate::benchmark::benchmark_flush_caches();
+        restore_user_context();
+        return;
+    }
+    #[cfg(feature = "ENABLE_BENCHMARK")]
+    if syscall as isize == SysBenchmarkFlushCachesRange {
+        crate::benchmark::benchmark_flush_caches_range();
+        restore_user_context();
+        return;
+    }
+    #[cfg(feature = "ENABLE_IPC_TRACE")]
+    if syscall as isize == SysDebugIpcTrace {
+        let thread = get_currenct_thread();
+        let was_enabled = sel4_ipc::ipc_trace::ipc_trace_set_enabled(
+            thread.tcbArch.get_register(ArchReg::Cap) != 0,
+        );
+        thread
+            .tcbArch
+            .set_register(ArchReg::Badge, was_enabled as usize);
+        restore_user_context();
+        return;
+    }
+    #[cfg(feature = "ENABLE_SCHED_TRACE")]
+    if syscall as isize == SysDebugSchedTrace {
+        let thread = get_currenct_thread();
+        let was_enabled = sel4_task::sched_trace::sched_trace_set_enabled(
+            thread.tcbArch.get_register(ArchReg::Cap) != 0,
+        );
+        thread
+            .tcbArch
+            .set_register(ArchReg::Badge, was_enabled as usize);
+        restore_user_context();
+        return;
+    }
+    #[cfg(feature = "ENABLE_SYSCALL_TRACE")]
+    if syscall as isize == SysDebugSyscallTrace {
+        syscall_trace::handle_debug_syscall_trace();
+        restore_user_context();
+        return;
+    }
+    #[cfg(feature = "ENABLE_IRQ_LATENCY")]
+    if syscall as isize == SysDebugIrqLatency {
+        crate::interrupt::latency::handle_debug_irq_latency();
+        restore_user_context();
+        return;
+    }
+    #[cfg(feature = "ENABLE_DEBUG_VERSION")]
+    if syscall as isize == SysDebugVersion {
+        crate::version::handle_debug_version();
+        restore_user_context();
+        return;
+    }
+    #[cfg(feature = "ENABLE_KERNEL_FEATURES")]
+    if syscall as isize == SysKernelFeatures {
+        crate::version::handle_kernel_features();
+        restore_user_context();
+        return;
+    }
+    if (syscall as isize) < SysMin || (syscall as isize) > SysCall {
         // using ffi_call! macro to call c function
         ffi_call!(handleUnknownSyscall(id: usize => syscall));
     } else {
@@ -47,12 +223,15 @@ pub fn slowpath(syscall: usize) {
 #[no_mangle]
 pub fn handleSyscall(_syscall: usize) -> exception_t {
     let syscall: isize = _syscall as isize;
+    #[cfg(feature = "ENABLE_SYSCALL_TRACE")]
+    syscall_trace::syscall_trace_record(syscall);
     // if hart_id() == 0 {
     //     debug!("handle syscall: {}", syscall);
     // }
+    let cptr = get_currenct_thread().tcbArch.get_register(ArchReg::Cap);
     match syscall {
         SysSend => {
-            let ret = handleInvocation(false, true);
+            let ret = handleInvocation(false, true, cptr);
 
             if unlikely(ret != exception_t::EXCEPTION_NONE) {
                 let irq = getActiveIRQ();
@@ -62,7 +241,7 @@ pub fn handleSyscall(_syscall: usize) -> exception_t {
             }
         }
         SysNBSend => {
-            let ret = handleInvocation(false, false);
+            let ret = handleInvocation(false, false, cptr);
             if unlikely(ret != exception_t::EXCEPTION_NONE) {
                 let irq = getActiveIRQ();
                 if irq != irqInvalid {
@@ -71,7 +250,7 @@ pub fn handleSyscall(_syscall: usize) -> exception_t {
             }
         }
         SysCall => {
-            let ret = handleInvocation(true, true);
+            let ret = handleInvocation(true, true, cptr);
             if unlikely(ret != exception_t::EXCEPTION_NONE) {
                 let irq = getActiveIRQ();
                 if irq != irqInvalid {
@@ -79,15 +258,60 @@ pub fn handleSyscall(_syscall: usize) -> exception_t {
                 }
             }
         }
+        #[cfg(not(feature = "ENABLE_MCS"))]
         SysRecv => {
             handle_recv(true);
         }
+        #[cfg(not(feature = "ENABLE_MCS"))]
         SysReply => handle_reply(),
+        #[cfg(not(feature = "ENABLE_MCS"))]
         SysReplyRecv => {
             handle_reply();
             handle_recv(true);
         }
+        #[cfg(not(feature = "ENABLE_MCS"))]
         SysNBRecv => handle_recv(false),
+        #[cfg(feature = "ENABLE_MCS")]
+        SysRecv => handle_recv(true, true),
+        #[cfg(feature = "ENABLE_MCS")]
+        SysReplyRecv => {
+            handle_reply();
+            handle_recv(true, true);
+        }
+        #[cfg(feature = "ENABLE_MCS")]
+        SysNBSendRecv => {
+            let dest = get_currenct_thread()
+                .tcbArch
+                .get_register(ArchReg::NBSendRecvDest);
+            let ret = handleInvocation(false, false, dest);
+            if unlikely(ret != exception_t::EXCEPTION_NONE) {
+                let irq = getActiveIRQ();
+                if irq != irqInvalid {
+                    handleInterrupt(irq);
+                }
+            } else {
+                handle_recv(true, true);
+            }
+        }
+        #[cfg(feature = "ENABLE_MCS")]
+        SysNBSendWait => {
+            let dest = get_currenct_thread().tcbArch.get_register(ArchReg::Reply);
+            let ret = handleInvocation(false, false, dest);
+            if unlikely(ret != exception_t::EXCEPTION_NONE) {
+                let irq = getActiveIRQ();
+                if irq != irqInvalid {
+                    handleInterrupt(irq);
+                }
+            } else {
+                handle_recv(true, false);
+            }
+        }
+        #[cfg(feature = "ENABLE_MCS")]
+        SysNBRecv => handle_recv(false, true),
+        #[cfg(feature = "ENABLE_MCS")]
+        SysWait => handle_recv(true, false),
+        #[cfg(feature = "ENABLE_MCS")]
+        SysNBWait => handle_recv(false, false),
         SysYield => handle_yield(),
         _ => panic!("Invalid syscall"),
     }
@@ -113,7 +337,7 @@ fn send_fault_ipc(thread: &mut tcb_t) -> exception_t {
         if thread.tcbFault.get_fault_type() == FaultType::CapFault {
             thread.tcbLookupFailure = origin_lookup_fault;
         }
-        convert_to_mut_type_ref::<endpoint_t>(handler_cap.get_ep_ptr()).send_ipc(
+        convert_to_checked_mut_type_ref::<endpoint_t>(handler_cap.get_ep_ptr()).send_ipc(
             thread,
             true,
             true,
@@ -134,10 +358,99 @@ fn send_fault_ipc(thread: &mut tcb_t) -> exception_t {
 #[inline]
 pub fn handle_fault(thread: &mut tcb_t) {
     if send_fault_ipc(thread) != exception_t::EXCEPTION_NONE {
-        set_thread_state(thread, ThreadState::ThreadStateInactive);
+        thread.set_inactive();
+    }
+}
+
+#[cfg(feature = "ENABLE_MCS")]
+#[no_mangle]
+/// Send a timeout fault for `thread`, whose scheduling context ran out of budget, to the endpoint
+/// set by `TCBSetTimeoutEndpoint`. Called by `sel4_task::sched_context::check_budget`.
+///
+/// # Returns
+///
+/// Whether the fault was sent. Without a timeout handler, or if the CPtr does not name an
+/// endpoint cap with grant or grant reply rights, the thread raises no fault.
+pub fn handleTimeout(thread: &mut tcb_t) -> bool {
+    if thread.tcbTimeoutHandler == 0 {
+        return false;
+    }
+    let lu_ret = thread.lookup_slot(thread.tcbTimeoutHandler);
+    if lu_ret.status != exception_t::EXCEPTION_NONE {
+        return false;
+    }
+    let handler_cap = &ptr_to_mut(lu_ret.slot).cap;
+    if handler_cap.get_cap_type() != CapTag::CapEndpointCap
+        || (handler_cap.get_ep_can_grant() == 0 && handler_cap.get_ep_can_grant_reply() == 0)
+    {
+        return false;
+    }
+    let sc = convert_to_mut_type_ref::<sched_context_t>(thread.tcbSchedContext);
+    thread.tcbFault = seL4_Fault_t::new_timeout_fault(sc.scBadge);
+    convert_to_checked_mut_type_ref::<endpoint_t>(handler_cap.get_ep_ptr()).send_ipc(
+        thread,
+        true,
+        true,
+        handler_cap.get_ep_can_grant() != 0,
+        handler_cap.get_ep_badge(),
+        true,
+    );
+    true
+}
+
+#[cfg(feature = "ENABLE_CPU_LIMIT")]
+#[no_mangle]
+/// Send a timeout fault for `thread`, which ran for the CPU time set by `TCBSetCPULimit`, to its
+/// fault handler. Called by `sel4_task::timerTick`. Without a usable fault handler the thread is
+/// left inactive, as for any other fault.
+pub fn handleCPULimit(thread: &mut tcb_t) {
+    unsafe {
+        current_fault = seL4_Fault_t::new_timeout_fault(0);
     }
+    handle_fault(thread);
 }
 
+#[cfg(feature = "ENABLE_MCS")]
+/// The reply object named by the cap in the reply register of `thread`, none for a null cap.
+/// Any other cap raises a cap fault.
+fn lookup_reply(thread: &mut tcb_t) -> Result<Option<&'static mut reply_t>, exception_t> {
+    let reply_cptr = thread.tcbArch.get_register(ArchReg::Reply);
+    let lu_ret = thread.lookup_slot(reply_cptr);
+    if lu_ret.status == exception_t::EXCEPTION_NONE {
+        let reply_cap = unsafe { (*lu_ret.slot).cap };
+        match reply_cap.get_cap_type() {
+            CapTag::CapNullCap => return Ok(None),
+            CapTag::CapReplyCap => {
+                return Ok(Some(convert_to_mut_type_ref::<reply_t>(
+                    reply_cap.get_reply_ptr(),
+                )))
+            }
+            _ => {}
+        }
+    }
+    unsafe {
+        current_lookup_fault = lookup_fault_t::new_missing_cap(0);
+        current_fault = seL4_Fault_t::new_cap_fault(reply_cptr, 1);
+    }
+    Err(exception_t::EXCEPTION_FAULT)
+}
+
+#[cfg(feature = "ENABLE_MCS")]
+fn handle_reply() {
+    let current_thread = get_currenct_thread();
+    let reply_cptr = current_thread.tcbArch.get_register(ArchReg::Reply);
+    let lu_ret = current_thread.lookup_slot(reply_cptr);
+    if lu_ret.status != exception_t::EXCEPTION_NONE {
+        return;
+    }
+    let reply_cap = unsafe { (*lu_ret.slot).cap };
+    if reply_cap.get_cap_type() == CapTag::CapReplyCap {
+        convert_to_mut_type_ref::<reply_t>(reply_cap.get_reply_ptr())
+            .do_reply(current_thread, reply_cap.get_reply_can_grant() != 0);
+    }
+}
+
+#[cfg(not(feature = "ENABLE_MCS"))]
 fn handle_reply() {
     let current_thread = get_currenct_thread();
     let caller_slot = current_thread.get_cspace_mut_ref(tcbCaller);
@@ -151,7 +464,9 @@ fn handle_reply() {
     }
 }
 
-fn handle_recv(block: bool) {
+/// Receive on the endpoint or notification of the cap register. With `ENABLE_MCS`, a caller is
+/// only accepted with `can_reply`, through the reply object of `ArchReg::Reply`.
+fn handle_recv(block: bool, #[cfg(feature = "ENABLE_MCS")] can_reply: bool) {
     let current_thread = get_currenct_thread();
     let ep_cptr = current_thread.tcbArch.get_register(ArchReg::Cap);
     let lu_ret = current_thread.lookup_slot(ep_cptr);
@@ -171,16 +486,31 @@ fn handle_recv(block: bool) {
                 }
                 return handle_fault(current_thread);
             }
+            #[cfg(feature = "ENABLE_MCS")]
+            let reply = match can_reply.then(|| lookup_reply(current_thread)) {
+                None => None,
+                Some(Ok(reply)) => reply,
+                Some(Err(_)) => return handle_fault(current_thread),
+            };
+            #[cfg(not(feature = "ENABLE_MCS"))]
             current_thread.delete_caller_cap();
-            convert_to_mut_type_ref::<endpoint_t>(ipc_cap.get_ep_ptr()).receive_ipc(
+            #[cfg(feature = "ENABLE_BENCHMARK")]
+            let start = crate::benchmark::timestamp();
+            convert_to_checked_mut_type_ref::<endpoint_t>(ipc_cap.get_ep_ptr()).receive_ipc(
                 current_thread,
                 block,
                 ipc_cap.get_ep_can_grant() != 0,
+                #[cfg(feature = "ENABLE_MCS")]
+                reply,
             );
+            #[cfg(feature = "ENABLE_BENCHMARK")]
+            unsafe {
+                crate::benchmark::ksBenchmarkRecvIPC.record(start)
+            };
         }
 
         CapTag::CapNotificationCap => {
-            let ntfn = convert_to_mut_type_ref::<notification_t>(ipc_cap.get_nf_ptr());
+            let ntfn = convert_to_checked_mut_type_ref::<notification_t>(ipc_cap.get_nf_ptr());
             let bound_tcb_ptr = ntfn.get_bound_tcb();
             if unlikely(
                 ipc_cap.get_nf_can_receive() == 0
@@ -207,5 +537,7 @@ fn handle_recv(block: bool) {
 fn handle_yield() {
     get_currenct_thread().sched_dequeue();
     get_currenct_thread().sched_append();
+    #[cfg(feature = "ENABLE_SCHED_TRACE")]
+    sel4_task::sched_trace::sched_trace_cause(sel4_task::sched_trace::sched_trace_cause_t::Yield);
     rescheduleRequired();
 }
@@ -0,0 +1,313 @@
+//! `SysDebugSnapshot`: dump the kernel objects reachable from the caller's root CNode.
+//!
+//! The dump is a single CBOR item (RFC 8949), printed to the kernel console as hex lines between
+//! `SNAPSHOT BEGIN` and `SNAPSHOT END` markers. Its layout is described by
+//! `kernel/snapshot.cddl`, which host tools use to decode the dump and compare it with the
+//! abstract model. Every slot holding a cap is emitted once; the object a cap points to is
+//! described only the first time it is reached, later caps refer to it by `ptr`.
+//!
+//! The walk runs on the kernel stack, so it keeps its state in statics and descends into CNodes
+//! and TCBs with an explicit work stack instead of recursing.
+
+use sel4_common::kfmt::hex_pad_t;
+use sel4_common::sel4_config::{tcbCNodeEntries, tcbCTable};
+use sel4_common::utils::convert_to_mut_type_ref;
+use sel4_common::{kprint, kprintln};
+use sel4_cspace::interface::{cap_t, cte_t, CapTag};
+use sel4_ipc::{endpoint_t, notification_t};
+use sel4_task::{get_currenct_thread, tcb_t};
+
+/// Version of the format, bumped on any incompatible change to `snapshot.cddl`.
+const SNAPSHOT_VERSION: usize = 1;
+
+/// Maximum number of distinct objects described in one dump.
+const SNAPSHOT_MAX_OBJECTS: usize = 256;
+
+/// Objects already described, keyed by `(cap type, ptr)`.
+static mut snapshot_visited: [(usize, usize); SNAPSHOT_MAX_OBJECTS] =
+    [(0, 0); SNAPSHOT_MAX_OBJECTS];
+
+/// Slot arrays still being dumped, innermost last, as `(next slot, slots left)`. Only a newly
+/// visited object pushes an entry, so the depth is bounded by `SNAPSHOT_MAX_OBJECTS`.
+static mut snapshot_stack: [(usize, usize); SNAPSHOT_MAX_OBJECTS] = [(0, 0); SNAPSHOT_MAX_OBJECTS];
+
+const CBOR_UINT: u8 = 0;
+const CBOR_TEXT: u8 = 3;
+const CBOR_ARRAY: u8 = 4;
+const CBOR_MAP: u8 = 5;
+const CBOR_SIMPLE: u8 = 7;
+const CBOR_INDEFINITE: u8 = 31;
+const CBOR_FALSE: u8 = 20;
+const CBOR_TRUE: u8 = 21;
+const CBOR_NULL: u8 = 22;
+const CBOR_BREAK: u8 = 0xff;
+
+/// Streaming CBOR encoder which prints its output as hex, 32 bytes per line.
+struct cbor_writer_t {
+    line: [u8; 32],
+    len: usize,
+}
+
+impl cbor_writer_t {
+    const fn new() -> Self {
+        Self {
+            line: [0; 32],
+            len: 0,
+        }
+    }
+
+    fn byte(&mut self, b: u8) {
+        self.line[self.len] = b;
+        self.len += 1;
+        if self.len == self.line.len() {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        for b in &self.line[..self.len] {
+            kprint!(hex_pad_t(*b as usize, 2));
+        }
+        if self.len != 0 {
+            kprintln!();
+        }
+        self.len = 0;
+    }
+
+    fn head(&mut self, major: u8, value: usize) {
+        let major = major << 5;
+        match value {
+            0..=23 => self.byte(major | value as u8),
+            24..=0xff => {
+                self.byte(major | 24);
+                self.byte(value as u8);
+            }
+            0x100..=0xffff => {
+                self.byte(major | 25);
+                (value as u16)
+                    .to_be_bytes()
+                    .iter()
+                    .for_each(|b| self.byte(*b));
+            }
+            0x10000..=0xffff_ffff => {
+                self.byte(major | 26);
+                (value as u32)
+                    .to_be_bytes()
+                    .iter()
+                    .for_each(|b| self.byte(*b));
+            }
+            _ => {
+                self.byte(major | 27);
+                (value as u64)
+                    .to_be_bytes()
+                    .iter()
+                    .for_each(|b| self.byte(*b));
+            }
+        }
+    }
+
+    fn uint(&mut self, value: usize) {
+        self.head(CBOR_UINT, value);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.byte(CBOR_SIMPLE << 5 | if value { CBOR_TRUE } else { CBOR_FALSE });
+    }
+
+    fn null(&mut self) {
+        self.byte(CBOR_SIMPLE << 5 | CBOR_NULL);
+    }
+
+    fn text(&mut self, s: &str) {
+        self.head(CBOR_TEXT, s.len());
+        s.bytes().for_each(|b| self.byte(b));
+    }
+
+    fn array(&mut self, len: usize) {
+        self.head(CBOR_ARRAY, len);
+    }
+
+    fn map(&mut self, len: usize) {
+        self.head(CBOR_MAP, len);
+    }
+
+    fn indefinite(&mut self, major: u8) {
+        self.byte(major << 5 | CBOR_INDEFINITE);
+    }
+
+    fn end(&mut self) {
+        self.byte(CBOR_BREAK);
+    }
+
+    fn field(&mut self, key: &str, value: usize) {
+        self.text(key);
+        self.uint(value);
+    }
+}
+
+struct snapshot_t {
+    writer: cbor_writer_t,
+    visited: &'static mut [(usize, usize); SNAPSHOT_MAX_OBJECTS],
+    visited_num: usize,
+    stack: &'static mut [(usize, usize); SNAPSHOT_MAX_OBJECTS],
+    stack_num: usize,
+    truncated: bool,
+}
+
+impl snapshot_t {
+    /// Mark the object at `ptr` as visited, returns false if it was already described.
+    ///
+    /// Objects are keyed by cap type as well, since an untyped and its first child share `ptr`.
+    fn visit(&mut self, cap_type: usize, ptr: usize) -> bool {
+        if self.visited[..self.visited_num].contains(&(cap_type, ptr)) {
+            return false;
+        }
+        if self.visited_num == SNAPSHOT_MAX_OBJECTS {
+            self.truncated = true;
+            return false;
+        }
+        self.visited[self.visited_num] = (cap_type, ptr);
+        self.visited_num += 1;
+        true
+    }
+
+    /// Dump `root` and every slot reachable from it, depth first.
+    fn dump_tree(&mut self, root: &cte_t) {
+        self.dump_slot(root);
+        while self.stack_num != 0 {
+            let (next, left) = &mut self.stack[self.stack_num - 1];
+            if *left == 0 {
+                self.stack_num -= 1;
+                self.writer.end();
+                continue;
+            }
+            let slot = convert_to_mut_type_ref::<cte_t>(*next);
+            *next += core::mem::size_of::<cte_t>();
+            *left -= 1;
+            self.dump_slot(slot);
+        }
+    }
+
+    /// Open the indefinite `slots` array of the object being described, its `num` slots starting
+    /// at `first` are emitted by `dump_tree` before the array is closed.
+    fn push_slots(&mut self, first: usize, num: usize) {
+        self.writer.text("slots");
+        self.writer.indefinite(CBOR_ARRAY);
+        self.stack[self.stack_num] = (first, num);
+        self.stack_num += 1;
+    }
+
+    fn dump_slot(&mut self, slot: &cte_t) {
+        let cap = &slot.cap;
+        if cap.get_cap_type() == CapTag::CapNullCap {
+            return;
+        }
+        let ptr = cap.get_cap_ptr();
+        let describe = ptr != 0 && self.visit(cap.get_cap_type() as usize, ptr);
+        let w = &mut self.writer;
+        w.map(if describe { 4 } else { 3 });
+        w.field("slot", slot.get_ptr());
+        w.field("cap", cap.get_cap_type() as usize);
+        w.text("words");
+        w.array(cap.words.len());
+        cap.words.iter().for_each(|word| w.uint(*word));
+        if describe {
+            w.text("obj");
+            self.dump_object(cap);
+        }
+    }
+
+    fn dump_object(&mut self, cap: &cap_t) {
+        let w = &mut self.writer;
+        match cap.get_cap_type() {
+            CapTag::CapUntypedCap => {
+                w.map(4);
+                w.field("ptr", cap.get_untyped_ptr());
+                w.field("size_bits", cap.get_untyped_block_size());
+                w.field("free_index", cap.get_untyped_free_index());
+                w.text("device");
+                w.bool(cap.get_untyped_is_device() != 0);
+            }
+            CapTag::CapEndpointCap => {
+                let ep = convert_to_mut_type_ref::<endpoint_t>(cap.get_ep_ptr());
+                w.map(4);
+                w.field("ptr", ep.get_ptr());
+                w.field("state", ep.get_usize_state());
+                w.field("head", ep.get_queue_head());
+                w.field("tail", ep.get_queue_tail());
+            }
+            CapTag::CapNotificationCap => {
+                let ntfn = convert_to_mut_type_ref::<notification_t>(cap.get_nf_ptr());
+                w.map(6);
+                w.field("ptr", ntfn.get_ptr());
+                w.field("state", ntfn.get_usize_state());
+                w.field("head", ntfn.get_queue_head());
+                w.field("tail", ntfn.get_queue_tail());
+                w.field("bound_tcb", ntfn.get_bound_tcb());
+                w.field("msg", ntfn.get_msg_identifier());
+            }
+            CapTag::CapThreadCap => {
+                let tcb = convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr());
+                w.map(9);
+                w.field("ptr", tcb.get_ptr());
+                w.field("state", tcb.tcbState.get_ts_type());
+                w.field("prio", tcb.tcbPriority);
+                w.field("mcp", tcb.tcbMCP);
+                w.field("domain", tcb.domain);
+                w.field("fault_handler", tcb.tcbFaultHandler);
+                w.field("ipc_buffer", tcb.tcbIPCBuffer);
+                w.field("bound_ntfn", tcb.tcbBoundNotification);
+                self.push_slots(
+                    tcb.get_cspace(tcbCTable).get_ptr(),
+                    tcbCNodeEntries - tcbCTable,
+                );
+            }
+            CapTag::CapCNodeCap => {
+                w.map(5);
+                w.field("ptr", cap.get_cnode_ptr());
+                w.field("radix", cap.get_cnode_radix());
+                w.field("guard_size", cap.get_cnode_guard_size());
+                w.field("guard", cap.get_cnode_guard());
+                self.push_slots(cap.get_cnode_ptr(), 1usize << cap.get_cnode_radix());
+            }
+            _ => {
+                w.map(1);
+                w.field("ptr", cap.get_cap_ptr());
+            }
+        }
+    }
+}
+
+/// Handle `SysDebugSnapshot` for the current thread.
+pub fn handle_debug_snapshot() {
+    let mut snapshot = snapshot_t {
+        writer: cbor_writer_t::new(),
+        visited: unsafe { &mut snapshot_visited },
+        visited_num: 0,
+        stack: unsafe { &mut snapshot_stack },
+        stack_num: 0,
+        truncated: false,
+    };
+    kprintln!("SNAPSHOT BEGIN");
+    let w = &mut snapshot.writer;
+    w.indefinite(CBOR_MAP);
+    w.field("version", SNAPSHOT_VERSION);
+    w.text("arch");
+    #[cfg(target_arch = "aarch64")]
+    w.text("aarch64");
+    #[cfg(target_arch = "riscv64")]
+    w.text("riscv64");
+    w.text("root");
+    let root = get_currenct_thread().get_cspace(tcbCTable);
+    if root.cap.get_cap_type() == CapTag::CapNullCap {
+        w.null();
+    } else {
+        snapshot.dump_tree(root);
+    }
+    let w = &mut snapshot.writer;
+    w.text("truncated");
+    w.bool(snapshot.truncated);
+    w.end();
+    w.flush();
+    kprintln!("SNAPSHOT END");
+}
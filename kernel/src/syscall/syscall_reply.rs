@@ -28,26 +28,28 @@ pub fn reply_success_from_kernel(thread: &mut tcb_t) {
 // TODO: Remove this attribute to improve security.
 #[allow(static_mut_ref)]
 pub unsafe fn set_mrs_for_syscall_error(thread: &mut tcb_t) -> usize {
-    match current_syscall_error._type {
-        seL4_InvalidArgument => thread.set_mr(0, current_syscall_error.invalidArgumentNumber),
-        seL4_InvalidCapability => thread.set_mr(0, current_syscall_error.invalidCapNumber),
-        seL4_RangeError => {
-            thread.set_mr(0, current_syscall_error.rangeErrorMin);
-            thread.set_mr(1, current_syscall_error.rangeErrorMax)
-        }
-        seL4_FailedLookup => {
-            let flag = current_syscall_error.failedLookupWasSource == 1;
-            thread.set_mr(0, flag as usize);
-            return thread.set_lookup_fault_mrs(1, &current_lookup_fault);
-        }
-        seL4_IllegalOperation
-        | seL4_AlignmentError
-        | seL4_TruncatedMessage
-        | seL4_DeleteFirst
-        | seL4_RevokeFirst => 0,
-        seL4_NotEnoughMemory => thread.set_mr(0, current_syscall_error.memoryLeft),
-        _ => {
-            panic!("invalid syscall error")
+    unsafe {
+        match current_syscall_error._type {
+            seL4_InvalidArgument => thread.set_mr(0, current_syscall_error.invalidArgumentNumber),
+            seL4_InvalidCapability => thread.set_mr(0, current_syscall_error.invalidCapNumber),
+            seL4_RangeError => {
+                thread.set_mr(0, current_syscall_error.rangeErrorMin);
+                thread.set_mr(1, current_syscall_error.rangeErrorMax)
+            }
+            seL4_FailedLookup => {
+                let flag = current_syscall_error.failedLookupWasSource == 1;
+                thread.set_mr(0, flag as usize);
+                return thread.set_lookup_fault_mrs(1, &current_lookup_fault);
+            }
+            seL4_IllegalOperation
+            | seL4_AlignmentError
+            | seL4_TruncatedMessage
+            | seL4_DeleteFirst
+            | seL4_RevokeFirst => 0,
+            seL4_NotEnoughMemory => thread.set_mr(0, current_syscall_error.memoryLeft),
+            _ => {
+                panic!("invalid syscall error")
+            }
         }
     }
 }
@@ -0,0 +1,128 @@
+//! Per-thread syscall tracing, enabled by the `ENABLE_SYSCALL_TRACE` feature.
+//!
+//! Every syscall of a thread whose `tcbSyscallTrace` is set writes one line to the kernel log, so
+//! it ends up in the console ring like any other kernel message:
+//!
+//! ```text
+//! syscall 0x... Call cptr=0x... cap=CapTCBCap label=TCBSetPriority len=N
+//! ```
+//!
+//! The address is the TCB of the caller. The cap and label are given for `Send`, `NBSend` and
+//! `Call`, and the label is decoded only for caps of kernel objects, it is a raw number for IPC
+//! caps. A traced thread always takes the slowpath, so none of its syscalls are missed.
+//!
+//! `SysDebugSyscallTrace` switches tracing of the thread of the TCB cap in the cap register, on if
+//! the message info register is non-zero and off otherwise. The previous state is returned in the
+//! badge register, 0 if the cap is not a TCB cap.
+
+use log::{debug, info};
+use sel4_common::arch::{ArchReg, MessageLabel};
+use sel4_common::message_info::seL4_MessageInfo_t;
+use sel4_common::structures::exception_t;
+use sel4_common::utils::convert_to_mut_type_ref;
+use sel4_cspace::interface::{cap_t, CapTag};
+use sel4_task::{get_currenct_thread, tcb_t};
+
+#[cfg(not(feature = "ENABLE_MCS"))]
+use super::SysReply;
+use super::{SysCall, SysNBRecv, SysNBSend, SysRecv, SysReplyRecv, SysSend, SysYield};
+#[cfg(feature = "ENABLE_MCS")]
+use super::{SysNBSendRecv, SysNBSendWait, SysNBWait, SysWait};
+
+/// Handle `SysDebugSyscallTrace` for the current thread.
+pub fn handle_debug_syscall_trace() {
+    let thread = get_currenct_thread();
+    let cptr = thread.tcbArch.get_register(ArchReg::Cap);
+    let enable = thread.tcbArch.get_register(ArchReg::MsgInfo) != 0;
+    let lu_ret = thread.lookup_slot(cptr);
+    let mut was_enabled = false;
+    let cap = match lu_ret.status {
+        exception_t::EXCEPTION_NONE => unsafe { (*lu_ret.slot).cap },
+        _ => cap_t::new_null_cap(),
+    };
+    if cap.get_cap_type() == CapTag::CapThreadCap {
+        let target = convert_to_mut_type_ref::<tcb_t>(cap.get_tcb_ptr());
+        was_enabled = target.tcbSyscallTrace;
+        target.tcbSyscallTrace = enable;
+    } else {
+        debug!("DebugSyscallTrace: cap {:#x} is not a TCB cap.", cptr);
+    }
+    thread
+        .tcbArch
+        .set_register(ArchReg::Badge, was_enabled as usize);
+}
+
+/// Trace the syscall `syscall` of the current thread if tracing is on for it.
+#[inline]
+pub fn syscall_trace_record(syscall: isize) {
+    let thread = get_currenct_thread();
+    if !thread.tcbSyscallTrace {
+        return;
+    }
+    let name = match syscall {
+        SysCall => "Call",
+        SysReplyRecv => "ReplyRecv",
+        SysSend => "Send",
+        SysNBSend => "NBSend",
+        SysRecv => "Recv",
+        #[cfg(not(feature = "ENABLE_MCS"))]
+        SysReply => "Reply",
+        SysYield => "Yield",
+        SysNBRecv => "NBRecv",
+        #[cfg(feature = "ENABLE_MCS")]
+        SysNBSendRecv => "NBSendRecv",
+        #[cfg(feature = "ENABLE_MCS")]
+        SysNBSendWait => "NBSendWait",
+        #[cfg(feature = "ENABLE_MCS")]
+        SysWait => "Wait",
+        #[cfg(feature = "ENABLE_MCS")]
+        SysNBWait => "NBWait",
+        _ => "Unknown",
+    };
+    let cptr = thread.tcbArch.get_register(ArchReg::Cap);
+    if !matches!(syscall, SysSend | SysNBSend | SysCall) {
+        info!("syscall {:#x} {} cptr={:#x}", thread.get_ptr(), name, cptr);
+        return;
+    }
+    let info =
+        seL4_MessageInfo_t::from_word_security(thread.tcbArch.get_register(ArchReg::MsgInfo));
+    let lu_ret = thread.lookup_slot(cptr);
+    if lu_ret.status != exception_t::EXCEPTION_NONE {
+        info!(
+            "syscall {:#x} {} cptr={:#x} cap=invalid label={:#x} len={}",
+            thread.get_ptr(),
+            name,
+            cptr,
+            info.get_usize_label(),
+            info.get_length()
+        );
+        return;
+    }
+    let cap_type = unsafe { (*lu_ret.slot).cap.get_cap_type() };
+    let label = info.get_usize_label();
+    let is_ipc = matches!(
+        cap_type,
+        CapTag::CapEndpointCap | CapTag::CapNotificationCap | CapTag::CapReplyCap
+    );
+    if is_ipc || MessageLabel::from_usize(label).is_none() {
+        info!(
+            "syscall {:#x} {} cptr={:#x} cap={:?} label={:#x} len={}",
+            thread.get_ptr(),
+            name,
+            cptr,
+            cap_type,
+            label,
+            info.get_length()
+        );
+    } else {
+        info!(
+            "syscall {:#x} {} cptr={:#x} cap={:?} label={:?} len={}",
+            thread.get_ptr(),
+            name,
+            cptr,
+            cap_type,
+            info.get_label(),
+            info.get_length()
+        );
+    }
+}
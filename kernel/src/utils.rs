@@ -2,6 +2,14 @@ use crate::BIT;
 
 #[inline]
 pub fn clear_memory(ptr: *mut u8, bits: usize) {
+    #[cfg(target_arch = "riscv64")]
+    if crate::arch::cbo_zero(ptr as usize, bits) {
+        return;
+    }
+    #[cfg(target_arch = "aarch64")]
+    if crate::arch::zva_zero(ptr as usize, bits) {
+        return;
+    }
     unsafe {
         core::slice::from_raw_parts_mut(ptr, BIT!(bits)).fill(0);
     }
@@ -0,0 +1,147 @@
+//! Build metadata of the kernel image.
+//!
+//! `build.rs` collects the git revision, the build time in seconds since the epoch and the
+//! enabled cargo features. They are kept as one NUL terminated line in the `.rel4_version`
+//! section, so they can be read from an image with
+//! `objcopy -O binary --only-section=.rel4_version`, and are printed in the boot banner.
+//! With `ENABLE_DEBUG_VERSION` userland reads them with `SysDebugVersion`.
+//!
+//! With `ENABLE_KERNEL_FEATURES` the features userland has to adapt to are also reported as a
+//! bitmask by `SysKernelFeatures`, so a library can pick its calling conventions at run time.
+//! Without it the syscall number is unknown and raises an unknown syscall fault, as in seL4, so a
+//! library which probes for the mask has to handle that fault.
+
+/// The metadata line, without the terminating NUL
+const KERNEL_VERSION: &str = concat!(
+    "reL4 ",
+    env!("CARGO_PKG_VERSION"),
+    " git ",
+    env!("REL4_GIT_HASH"),
+    " built ",
+    env!("REL4_BUILD_TIME"),
+    " features [",
+    env!("REL4_FEATURES"),
+    "]"
+);
+
+const fn version_bytes<const N: usize>(s: &str) -> [u8; N] {
+    let bytes = s.as_bytes();
+    let mut ret = [0; N];
+    let mut i = 0;
+    while i < bytes.len() {
+        ret[i] = bytes[i];
+        i += 1;
+    }
+    ret
+}
+
+#[used]
+#[no_mangle]
+#[link_section = ".rel4_version"]
+pub static ksKernelVersion: [u8; KERNEL_VERSION.len() + 1] = version_bytes(KERNEL_VERSION);
+
+/// The build metadata line
+pub fn kernel_version() -> &'static str {
+    KERNEL_VERSION
+}
+
+/// Handle `SysDebugVersion` for the current thread.
+///
+/// The metadata line is returned in the message words of the caller, packed little endian and
+/// cut where the words run out, i.e. at the message registers without an IPC buffer and at
+/// `seL4_MsgMaxLength` words with one. The message info holds the number of words and the badge
+/// register the number of bytes returned.
+#[cfg(feature = "ENABLE_DEBUG_VERSION")]
+pub fn handle_debug_version() {
+    use core::mem::size_of;
+    use sel4_common::arch::ArchReg;
+    use sel4_common::message_info::seL4_MessageInfo_t;
+    use sel4_common::sel4_config::seL4_MsgMaxLength;
+    use sel4_task::get_currenct_thread;
+
+    let thread = get_currenct_thread();
+    let mut words = 0;
+    for chunk in KERNEL_VERSION
+        .as_bytes()
+        .chunks(size_of::<usize>())
+        .take(seL4_MsgMaxLength)
+    {
+        let mut word = [0; size_of::<usize>()];
+        word[..chunk.len()].copy_from_slice(chunk);
+        if thread.set_mr(words, usize::from_le_bytes(word)) == words {
+            break;
+        }
+        words += 1;
+    }
+    let bytes = KERNEL_VERSION.len().min(words * size_of::<usize>());
+    thread.tcbArch.set_register(
+        ArchReg::MsgInfo,
+        seL4_MessageInfo_t::new(0, 0, 0, words).to_word(),
+    );
+    thread.tcbArch.set_register(ArchReg::Badge, bytes);
+}
+
+/// Bits of the mask returned by `SysKernelFeatures`. They are never reused, a bit a kernel does
+/// not know about reads as 0.
+#[cfg(feature = "ENABLE_KERNEL_FEATURES")]
+pub const KERNEL_FEATURE_MCS: usize = 1 << 0;
+#[cfg(feature = "ENABLE_KERNEL_FEATURES")]
+pub const KERNEL_FEATURE_SMP: usize = 1 << 1;
+/// `ENABLE_ARM_HYP` or `ENABLE_RISCV_HYP`, threads may be bound to VCPUs
+#[cfg(feature = "ENABLE_KERNEL_FEATURES")]
+pub const KERNEL_FEATURE_HYP: usize = 1 << 2;
+/// A debug build, with the kernel assertions
+#[cfg(feature = "ENABLE_KERNEL_FEATURES")]
+pub const KERNEL_FEATURE_DEBUG: usize = 1 << 3;
+#[cfg(feature = "ENABLE_KERNEL_FEATURES")]
+pub const KERNEL_FEATURE_BENCHMARK: usize = 1 << 4;
+/// IPC may take the fastpath, always set as it is not configurable
+#[cfg(feature = "ENABLE_KERNEL_FEATURES")]
+pub const KERNEL_FEATURE_FASTPATH: usize = 1 << 5;
+/// Frames above the large page size can be mapped, always set as it is not configurable
+#[cfg(feature = "ENABLE_KERNEL_FEATURES")]
+pub const KERNEL_FEATURE_HUGE_PAGES: usize = 1 << 6;
+
+/// The features the kernel was built with, see `KERNEL_FEATURE_MCS` and the following bits.
+#[cfg(feature = "ENABLE_KERNEL_FEATURES")]
+pub const fn kernel_features() -> usize {
+    let mut features = KERNEL_FEATURE_FASTPATH | KERNEL_FEATURE_HUGE_PAGES;
+    if cfg!(feature = "ENABLE_MCS") {
+        features |= KERNEL_FEATURE_MCS;
+    }
+    if cfg!(feature = "ENABLE_SMP") {
+        features |= KERNEL_FEATURE_SMP;
+    }
+    if cfg!(any(
+        feature = "ENABLE_ARM_HYP",
+        feature = "ENABLE_RISCV_HYP"
+    )) {
+        features |= KERNEL_FEATURE_HYP;
+    }
+    if cfg!(debug_assertions) {
+        features |= KERNEL_FEATURE_DEBUG;
+    }
+    if cfg!(feature = "ENABLE_BENCHMARK") {
+        features |= KERNEL_FEATURE_BENCHMARK;
+    }
+    features
+}
+
+/// Handle `SysKernelFeatures` for the current thread.
+///
+/// The mask is returned in the badge register, the message info holds no words.
+#[cfg(feature = "ENABLE_KERNEL_FEATURES")]
+pub fn handle_kernel_features() {
+    use sel4_common::arch::ArchReg;
+    use sel4_common::message_info::seL4_MessageInfo_t;
+    use sel4_task::get_currenct_thread;
+
+    let thread = get_currenct_thread();
+    thread.tcbArch.set_register(
+        ArchReg::MsgInfo,
+        seL4_MessageInfo_t::new(0, 0, 0, 0).to_word(),
+    );
+    thread
+        .tcbArch
+        .set_register(ArchReg::Badge, kernel_features());
+}
@@ -1,21 +1,6 @@
-use core::arch::asm;
-
+use super::sysreg::{tpidr_el0, tpidrro_el0};
 use super::{CONTEXT_REG_NUM, ELR_EL1, SPSR_EL1, TLS_BASE, TPIDRRO_EL0, TPIDR_EL0};
 
-/// Get value from the system register
-/// TODO: Move this macro into a proper place
-macro_rules! mrs {
-    ($reg: literal) => {
-        {
-            let value: usize;
-            unsafe {
-                core::arch::asm!(concat!("mrs {0}, ", $reg), out(reg) value);
-            }
-            value
-        }
-    };
-}
-
 #[repr(C)]
 #[derive(Debug, Clone)]
 pub struct FPUState {
@@ -29,6 +14,12 @@ pub struct FPUState {
 pub struct ArchTCB {
     pub(in crate::arch) registers: [usize; CONTEXT_REG_NUM],
     pub(in crate::arch) fpu: FPUState,
+    /// The PARTID of the thread, see `ENABLE_ARM_MPAM`
+    #[cfg(feature = "ENABLE_ARM_MPAM")]
+    pub(in crate::arch) mpam_partid: usize,
+    /// The VCPU bound to the thread, 0 for none, see `ENABLE_ARM_HYP`
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    pub(in crate::arch) vcpu: usize,
 }
 
 /// Implements the Default for the `ArchTCB`
@@ -43,27 +34,140 @@ impl Default for ArchTCB {
                 fpsr: 0,
                 fpcr: 0,
             },
+            #[cfg(feature = "ENABLE_ARM_MPAM")]
+            mpam_partid: 0,
+            #[cfg(feature = "ENABLE_ARM_HYP")]
+            vcpu: 0,
         }
     }
 }
 impl ArchTCB {
-    /// Config the registers fot the idle thread.
-    pub fn config_idle_thread(&mut self, idle_thread: usize) {
+    /// Config the registers fot the idle thread of `_core`. It runs in EL1 on `SP_EL1`, which is
+    /// the kernel stack of the core already.
+    pub fn config_idle_thread(&mut self, idle_thread: usize, _core: usize) {
         self.registers[ELR_EL1] = idle_thread;
         self.registers[SPSR_EL1] = (1 << 6) | 5 | (1 << 8);
     }
 
+    /// Sanitise the value `v` user mode gives for the register at `index`.
+    ///
+    /// This is `sanitiseRegister`. SPSR_EL1 keeps the NZCV flags only and returns to EL0 with
+    /// FIQs and SErrors masked. A thread with a VCPU bound may also return to EL1, see
+    /// `ENABLE_ARM_HYP`.
+    pub(in crate::arch) fn sanitise_register(&self, index: usize, v: usize) -> usize {
+        if index != SPSR_EL1 {
+            return v;
+        }
+        #[cfg(feature = "ENABLE_ARM_HYP")]
+        if self.vcpu != 0 && matches!(v & 0x1f, 0 | 4 | 5) {
+            return v;
+        }
+        (v & 0xf000_0000) | (1 << 6) | (1 << 8)
+    }
+
+    /// Get the FPU state saved in the TCB
+    pub fn get_fpu_state(&self) -> &FPUState {
+        &self.fpu
+    }
+
+    /// Overwrite the FPU state saved in the TCB
+    pub fn set_fpu_state(&mut self, fpu: &FPUState) {
+        self.fpu = fpu.clone();
+    }
+
+    /// Publish the IPC buffer address `buffer` to user mode.
+    ///
+    /// This is `Arch_setTCBIPCBuffer`. User mode finds its IPC buffer in TPIDRRO_EL0, which it
+    /// can read but not write, while TPIDR_EL0 stays free for the TLS base of the thread. With
+    /// `ENABLE_THREAD_ID` TPIDRRO_EL0 holds the thread ID instead, and user mode has to keep the
+    /// address of its IPC buffer itself, e.g. in a thread local variable.
+    #[inline]
+    pub fn set_tcb_ipc_buffer(&mut self, _buffer: usize) {
+        #[cfg(not(feature = "ENABLE_THREAD_ID"))]
+        {
+            self.registers[TPIDRRO_EL0] = _buffer;
+        }
+    }
+
+    /// Publish the thread ID `id` to user mode in TPIDRRO_EL0, see `ENABLE_THREAD_ID`.
+    #[cfg(feature = "ENABLE_THREAD_ID")]
+    #[inline]
+    pub fn set_thread_id(&mut self, id: usize) {
+        self.registers[TPIDRRO_EL0] = id;
+    }
+
+    /// Set the PARTID the accesses of the thread are tagged with, see `ENABLE_ARM_MPAM`.
+    #[cfg(feature = "ENABLE_ARM_MPAM")]
+    #[inline]
+    pub fn set_mpam_partid(&mut self, partid: usize) {
+        self.mpam_partid = partid;
+    }
+
+    /// Tag the accesses of EL0 and EL1 with the PARTID of the thread, on a switch to it.
+    #[cfg(feature = "ENABLE_ARM_MPAM")]
+    #[inline]
+    pub fn load_mpam(&self) {
+        use super::sysreg::{mpam0_el1, mpam1_el1};
+        if super::mpam_partid_max().is_none() {
+            return;
+        }
+        let value = mpam0_el1::PARTID_D.set(
+            mpam0_el1::PARTID_I.set(0, self.mpam_partid),
+            self.mpam_partid,
+        );
+        unsafe {
+            mpam0_el1::write(value);
+            mpam1_el1::write(value);
+        }
+    }
+
+    /// The VCPU bound to the thread, 0 for none, see `ENABLE_ARM_HYP`.
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    #[inline]
+    pub fn get_vcpu(&self) -> usize {
+        self.vcpu
+    }
+
+    /// Bind the VCPU at `vcpu` to the thread, 0 to unbind it.
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    #[inline]
+    pub fn set_vcpu(&mut self, vcpu: usize) {
+        self.vcpu = vcpu;
+    }
+
+    /// The value of the general purpose register Xn of the thread, 0 for XZR, see
+    /// `ENABLE_MMIO_EMULATION`.
+    #[cfg(feature = "ENABLE_MMIO_EMULATION")]
+    #[inline]
+    pub fn get_gp_register(&self, n: usize) -> usize {
+        if n < 31 {
+            self.registers[n]
+        } else {
+            0
+        }
+    }
+
+    /// Set the general purpose register Xn of the thread, writes to XZR are dropped, see
+    /// `ENABLE_MMIO_EMULATION`.
+    #[cfg(feature = "ENABLE_MMIO_EMULATION")]
+    #[inline]
+    pub fn set_gp_register(&mut self, n: usize, value: usize) {
+        if n < 31 {
+            self.registers[n] = value;
+        }
+    }
+
     /// Save TLS(Thread local Storage) registers
     #[inline]
     pub fn save_thread_local(&mut self) {
-        self.registers[TPIDR_EL0] = mrs!("tpidr_el0");
-        self.registers[TPIDRRO_EL0] = mrs!("tpidrro_el0");
+        self.registers[TPIDR_EL0] = tpidr_el0::read();
+        self.registers[TPIDRRO_EL0] = tpidrro_el0::read();
     }
     #[inline]
     pub fn load_thread_local(&mut self) {
         unsafe {
-            asm!("msr tpidr_el0,{}", in(reg) self.registers[TPIDR_EL0]);
-            asm!("msr tpidrro_el0,{}", in(reg) self.registers[TPIDRRO_EL0]);
+            tpidr_el0::write(self.registers[TPIDR_EL0]);
+            tpidrro_el0::write(self.registers[TPIDRRO_EL0]);
         }
     }
 }
@@ -9,3 +9,10 @@ pub const PPTR_BASE: usize = 0xffffff8000000000;
 pub const PADDR_BASE: usize = 0x0;
 pub const PPTR_BASE_OFFSET: usize = PPTR_BASE - PADDR_BASE;
 pub const PADDR_TOP: usize = PPTR_TOP - PPTR_BASE_OFFSET;
+
+/// Width of the sign-extended pointer fields in the cap, notification and other kernel
+/// object bitfields, enough for a 48-bit kernel virtual address space
+pub const PTR_FIELD_BITS: usize = 48;
+
+const _: () = assert!(crate::utils::ptr_range_fits_field(PPTR_BASE, PPTR_TOP));
+const _: () = assert!(crate::utils::ptr_fits_field(KERNEL_ELF_BASE));
@@ -1,5 +1,9 @@
 #[derive(Eq, PartialEq, Debug, Clone, Copy, PartialOrd, Ord)]
 /// The label of a message.
+///
+/// The labels up to `nArchInvocationLabels` have the values libsel4 gives them for the same
+/// configuration. Labels which are not part of seL4 follow in their own range, which starts at
+/// `UntypedDescribe` whatever the configuration.
 #[repr(C)]
 pub enum MessageLabel {
     InvalidInvocation = 0,
@@ -11,13 +15,15 @@ pub enum MessageLabel {
     TCBSetPriority,
     TCBSetMCPriority,
     TCBSetSchedParams,
+    #[cfg(feature = "ENABLE_MCS")]
+    TCBSetTimeoutEndpoint,
     TCBSetIPCBuffer,
     TCBSetSpace,
     TCBSuspend,
     TCBResume,
     TCBBindNotification,
     TCBUnbindNotification,
-    #[cfg(feature = "ENABLE_SMP")]
+    #[cfg(all(feature = "ENABLE_SMP", not(feature = "ENABLE_MCS")))]
     TCBSetAffinity,
     TCBSetTLSBase,
     CNodeRevoke,
@@ -28,16 +34,31 @@ pub enum MessageLabel {
     CNodeMove,
     CNodeMutate,
     CNodeRotate,
+    #[cfg(not(feature = "ENABLE_MCS"))]
     CNodeSaveCaller,
     IRQIssueIRQHandler,
     IRQAckIRQ,
     IRQSetIRQHandler,
     IRQClearIRQHandler,
     DomainSetSet,
+    #[cfg(feature = "ENABLE_MCS")]
+    SchedControlConfigureFlags,
+    #[cfg(feature = "ENABLE_MCS")]
+    SchedContextBind,
+    #[cfg(feature = "ENABLE_MCS")]
+    SchedContextUnbind,
+    #[cfg(feature = "ENABLE_MCS")]
+    SchedContextUnbindObject,
+    #[cfg(feature = "ENABLE_MCS")]
+    SchedContextConsumed,
+    #[cfg(feature = "ENABLE_MCS")]
+    SchedContextYieldTo,
     ARMVSpaceClean_Data,
     ARMVSpaceInvalidate_Data,
     ARMVSpaceCleanInvalidate_Data,
     ARMVSpaceUnify_Instruction,
+    #[cfg(feature = "ENABLE_ARM_SMC")]
+    ARMSMCCall,
     ARMPageUpperDirectoryMap,
     ARMPageUpperDirectoryUnmap,
     ARMPageDirectoryMap,
@@ -53,6 +74,152 @@ pub enum MessageLabel {
     ARMPageGetAddress,
     ARMASIDControlMakePool,
     ARMASIDPoolAssign,
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    ARMVCPUSetTCB,
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    ARMVCPUInjectIRQ,
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    ARMVCPUReadReg,
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    ARMVCPUWriteReg,
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    ARMVCPUAckVPPI,
     ARMIRQIssueIRQHandlerTrigger,
+    /// Only accepted with `ENABLE_SMP`. seL4 has it only then, but no seL4 label follows it.
+    ARMIRQIssueIRQHandlerTriggerCore,
     nArchInvocationLabels,
+    /// Non-seL4 extension, the first label of the extension range.
+    UntypedDescribe = 0x1000,
+    /// Non-seL4 extension, see `ENABLE_NTFN_WAIT_IF_EQUAL`.
+    NotificationWaitIfEqual,
+    /// Non-seL4 extension.
+    TCBCheckpointSave,
+    /// Non-seL4 extension.
+    TCBCheckpointRestore,
+    /// Non-seL4 extension.
+    IRQSetCore,
+    /// Non-seL4 extension, see `ENABLE_MEMORY_HOTADD`.
+    MemoryControlAddUntyped,
+    /// Non-seL4 extension, see `ENABLE_THREAD_ID`.
+    TCBSetThreadID,
+    /// Non-seL4 extension, see `ENABLE_REVOKE_NOTIFY`.
+    DomainSetRevokeNotification,
+    /// Non-seL4 extension, see `ENABLE_CPU_LIMIT`.
+    TCBSetCPULimit,
+    /// Non-seL4 extension, see `ENABLE_OBJECT_RELOCATE`.
+    MemoryControlRelocateCNode,
+    /// Non-seL4 extension, see `ENABLE_ARM_MPAM`.
+    ARMDomainSetMPAMPartID,
+    /// Non-seL4 extension, see `ENABLE_MMIO_EMULATION`.
+    ARMVSpaceEmulateMMIO,
+    /// Non-seL4 extension, see `ENABLE_ARM_HYP`.
+    ARMASIDPoolAssignVM,
+    /// Non-seL4 extension, see `ENABLE_GIC_ITS`.
+    ARMIRQIssueIRQHandlerMSI,
+    /// One past the last label of the extension range.
+    nExtensionInvocationLabels,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MessageLabel::{self, *};
+
+    fn check(labels: &[(MessageLabel, usize)]) {
+        for (label, value) in labels {
+            assert_eq!(*label as usize, *value, "{:?}", label);
+        }
+    }
+
+    /// The values of `sel4/invocation.h`, `sel4/sel4_arch/invocation.h` and
+    /// `sel4/arch/invocation.h` that libsel4 generates for the configuration under test.
+    #[test]
+    fn sel4_label_values_test() {
+        let mcs = cfg!(feature = "ENABLE_MCS") as usize;
+        let smp = (cfg!(feature = "ENABLE_SMP") && mcs == 0) as usize;
+        check(&[
+            (InvalidInvocation, 0),
+            (UntypedRetype, 1),
+            (TCBReadRegisters, 2),
+            (TCBWriteRegisters, 3),
+            (TCBCopyRegisters, 4),
+            (TCBConfigure, 5),
+            (TCBSetPriority, 6),
+            (TCBSetMCPriority, 7),
+            (TCBSetSchedParams, 8),
+            (TCBSetIPCBuffer, 9 + mcs),
+            (TCBSetSpace, 10 + mcs),
+            (TCBSuspend, 11 + mcs),
+            (TCBResume, 12 + mcs),
+            (TCBBindNotification, 13 + mcs),
+            (TCBUnbindNotification, 14 + mcs),
+            (TCBSetTLSBase, 15 + mcs + smp),
+            (CNodeRevoke, 16 + mcs + smp),
+            (CNodeDelete, 17 + mcs + smp),
+            (CNodeCancelBadgedSends, 18 + mcs + smp),
+            (CNodeCopy, 19 + mcs + smp),
+            (CNodeMint, 20 + mcs + smp),
+            (CNodeMove, 21 + mcs + smp),
+            (CNodeMutate, 22 + mcs + smp),
+            (CNodeRotate, 23 + mcs + smp),
+            (IRQIssueIRQHandler, 25 + smp),
+            (IRQAckIRQ, 26 + smp),
+            (IRQSetIRQHandler, 27 + smp),
+            (IRQClearIRQHandler, 28 + smp),
+            (DomainSetSet, 29 + smp),
+        ]);
+        #[cfg(not(feature = "ENABLE_MCS"))]
+        check(&[(CNodeSaveCaller, 24 + smp)]);
+        #[cfg(all(feature = "ENABLE_SMP", not(feature = "ENABLE_MCS")))]
+        check(&[(TCBSetAffinity, 15)]);
+        #[cfg(feature = "ENABLE_MCS")]
+        check(&[
+            (TCBSetTimeoutEndpoint, 9),
+            (SchedControlConfigureFlags, 30),
+            (SchedContextBind, 31),
+            (SchedContextUnbind, 32),
+            (SchedContextUnbindObject, 33),
+            (SchedContextConsumed, 34),
+            (SchedContextYieldTo, 35),
+        ]);
+
+        let arch = 30 + smp + 6 * mcs;
+        let smc = cfg!(feature = "ENABLE_ARM_SMC") as usize;
+        check(&[
+            (ARMVSpaceClean_Data, arch),
+            (ARMVSpaceInvalidate_Data, arch + 1),
+            (ARMVSpaceCleanInvalidate_Data, arch + 2),
+            (ARMVSpaceUnify_Instruction, arch + 3),
+            (ARMPageUpperDirectoryMap, arch + 4 + smc),
+            (ARMPageUpperDirectoryUnmap, arch + 5 + smc),
+            (ARMPageDirectoryMap, arch + 6 + smc),
+            (ARMPageDirectoryUnmap, arch + 7 + smc),
+            (ARMPageTableMap, arch + 8 + smc),
+            (ARMPageTableUnmap, arch + 9 + smc),
+            (ARMPageMap, arch + 10 + smc),
+            (ARMPageUnmap, arch + 11 + smc),
+            (ARMPageClean_Data, arch + 12 + smc),
+            (ARMPageInvalidate_Data, arch + 13 + smc),
+            (ARMPageCleanInvalidate_Data, arch + 14 + smc),
+            (ARMPageUnify_Instruction, arch + 15 + smc),
+            (ARMPageGetAddress, arch + 16 + smc),
+            (ARMASIDControlMakePool, arch + 17 + smc),
+            (ARMASIDPoolAssign, arch + 18 + smc),
+        ]);
+        #[cfg(feature = "ENABLE_ARM_SMC")]
+        check(&[(ARMSMCCall, arch + 4)]);
+        #[cfg(feature = "ENABLE_ARM_HYP")]
+        check(&[
+            (ARMVCPUSetTCB, arch + 19 + smc),
+            (ARMVCPUInjectIRQ, arch + 20 + smc),
+            (ARMVCPUReadReg, arch + 21 + smc),
+            (ARMVCPUWriteReg, arch + 22 + smc),
+            (ARMVCPUAckVPPI, arch + 23 + smc),
+        ]);
+        let hyp = 5 * cfg!(feature = "ENABLE_ARM_HYP") as usize;
+        check(&[
+            (ARMIRQIssueIRQHandlerTrigger, arch + 19 + smc + hyp),
+            (ARMIRQIssueIRQHandlerTriggerCore, arch + 20 + smc + hyp),
+        ]);
+        check(&[(UntypedDescribe, 0x1000)]);
+    }
 }
@@ -2,11 +2,17 @@
 mod arch_tcb;
 pub mod config;
 mod message_info;
+#[cfg(feature = "ENABLE_ARM_MPAM")]
+mod mpam;
 mod object;
 mod registers;
+pub mod sysreg;
+pub mod timer;
 mod vm_rights;
-pub use arch_tcb::ArchTCB;
+pub use arch_tcb::{ArchTCB, FPUState};
 pub use message_info::*;
+#[cfg(feature = "ENABLE_ARM_MPAM")]
+pub use mpam::{mpam_init, mpam_partid_max};
 pub use object::*;
 pub use registers::*;
 pub use vm_rights::*;
@@ -85,8 +91,10 @@ fn psci_call(func: u32, arg0: usize, arg1: usize, arg2: usize) -> Result<(), Psc
     }
 }
 
+/// Raise the timer interrupt once the virtual count reaches `timer`, see
+/// `crate::timer::KernelTimer`
 pub fn set_timer(timer: usize) {
-    todo!("set_timer")
+    <timer::kernel_timer_t as crate::timer::KernelTimer>::set_deadline(timer);
 }
 
 pub fn clear_ipi() {
@@ -106,6 +114,11 @@ pub fn remote_sfence_vma(hart_mask: usize, start: usize, size: usize) {
     todo!("remote_sfence_vma");
 }
 
+/// Read the virtual count of the generic timer
 pub fn get_time() -> usize {
-    todo!("get_time")
+    let time: usize;
+    unsafe {
+        core::arch::asm!("mrs {}, cntvct_el0", out(reg) time);
+    }
+    time
 }
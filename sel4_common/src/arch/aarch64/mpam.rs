@@ -0,0 +1,36 @@
+//! Memory partitioning (MPAM), enabled by the `ENABLE_ARM_MPAM` feature.
+//!
+//! Every thread carries a PARTID which tags its memory accesses, at EL0 and in the kernel on its
+//! behalf, so the MSCs of the system can partition cache capacity and memory bandwidth between
+//! the PARTIDs. The kernel only switches the PARTID with the thread, the MSCs are programmed by
+//! a user-level resource manager.
+
+use super::sysreg::{id_aa64pfr0_el1, id_aa64pfr1_el1, mpam1_el1, mpamidr_el1};
+
+/// Largest PARTID of the system, `None` if MPAM is absent or disabled by the firmware
+static mut ksMPAMPartIDMax: Option<usize> = None;
+
+/// Probe MPAM on the boot core, must run before any thread is switched to.
+pub fn mpam_init() {
+    if id_aa64pfr0_el1::get(id_aa64pfr0_el1::MPAM) == 0
+        && id_aa64pfr1_el1::get(id_aa64pfr1_el1::MPAM_frac) == 0
+    {
+        log::debug!("MPAM: not implemented");
+        return;
+    }
+    if mpam1_el1::get(mpam1_el1::MPAMEN) == 0 {
+        log::debug!("MPAM: disabled by the firmware");
+        return;
+    }
+    let max = mpamidr_el1::get(mpamidr_el1::PARTID_MAX);
+    log::debug!("MPAM: PARTIDs 0..={}", max);
+    unsafe {
+        ksMPAMPartIDMax = Some(max);
+    }
+}
+
+/// The largest PARTID a thread can be given, `None` if MPAM cannot be used.
+#[inline]
+pub fn mpam_partid_max() -> Option<usize> {
+    unsafe { ksMPAMPartIDMax }
+}
@@ -1,3 +1,5 @@
+#[cfg(feature = "ENABLE_ARM_HYP")]
+use crate::sel4_config::seL4_VCPUBits;
 use crate::sel4_config::{
     seL4_PGDBits, seL4_PUDBits, seL4_PageDirBits, seL4_PageTableBits, ARMHugePageBits,
     ARMLargePageBits, ARMSmallPageBits, ARM_Huge_Page, ARM_Large_Page, ARM_Small_Page,
@@ -11,13 +13,21 @@ pub enum ObjectType {
     EndpointObject = 2,
     NotificationObject = 3,
     CapTableObject = 4,
-    seL4_ARM_HugePageObject = 5,
-    seL4_ARM_PageUpperDirectoryObject = 6,
-    seL4_ARM_PageGlobalDirectoryObject = 7,
-    seL4_ARM_SmallPageObject = 8,
-    seL4_ARM_LargePageObject = 9,
-    seL4_ARM_PageTableObject = 10,
-    seL4_ARM_PageDirectoryObject = 11,
+    /// MCS, see `ENABLE_MCS`. The object types that follow move up by one like in seL4.
+    #[cfg(feature = "ENABLE_MCS")]
+    SchedContextObject = 5,
+    #[cfg(feature = "ENABLE_MCS")]
+    ReplyObject,
+    seL4_ARM_HugePageObject,
+    seL4_ARM_PageUpperDirectoryObject,
+    seL4_ARM_PageGlobalDirectoryObject,
+    seL4_ARM_SmallPageObject,
+    seL4_ARM_LargePageObject,
+    seL4_ARM_PageTableObject,
+    seL4_ARM_PageDirectoryObject,
+    /// The virtual CPU of a guest, see `ENABLE_ARM_HYP`
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    seL4_ARM_VCPUObject,
 }
 
 impl ObjectType {
@@ -30,6 +40,8 @@ impl ObjectType {
             Self::seL4_ARM_PageUpperDirectoryObject => seL4_PUDBits,
             Self::seL4_ARM_PageDirectoryObject => seL4_PageDirBits,
             Self::seL4_ARM_PageGlobalDirectoryObject => seL4_PGDBits,
+            #[cfg(feature = "ENABLE_ARM_HYP")]
+            Self::seL4_ARM_VCPUObject => seL4_VCPUBits,
             _ => panic!("unsupported object type:{}", *self as usize),
         }
     }
@@ -55,6 +67,10 @@ impl ObjectType {
     ///
     /// true if the object type is an architecture-specific type, false otherwise.
     pub fn is_arch_type(self) -> bool {
+        #[cfg(feature = "ENABLE_ARM_HYP")]
+        if self == Self::seL4_ARM_VCPUObject {
+            return true;
+        }
         matches!(
             self,
             Self::seL4_ARM_HugePageObject
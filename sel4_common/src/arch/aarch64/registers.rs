@@ -53,7 +53,11 @@ pub(super) const FaultIP: usize = 34;
 pub(super) const TPIDR_EL0: usize = 35;
 //     TLS_BASE                    = TPIDR_EL0,
 pub(super) const TLS_BASE: usize = TPIDR_EL0;
-/// user readonly thread ID register.
+/// user readonly thread ID register, holds the address of the IPC buffer, or the thread ID with
+/// `ENABLE_THREAD_ID`.
+///
+/// Together with [`TLS_BASE`] this is switched by `save_thread_local`/`load_thread_local` of
+/// [`super::ArchTCB`] on every kernel entry and exit.
 pub(super) const TPIDRRO_EL0: usize = 36;
 // pub const n_contextRegisters: usize = 37;
 // This is n_context registers
@@ -89,6 +93,10 @@ impl ArchReg {
             ArchReg::Frame(i) => frameRegisters[*i],
             ArchReg::GP(i) => gpRegisters[*i],
             ArchReg::FaultMessage(id, index) => fault_messages[*id][*index],
+            #[cfg(feature = "ENABLE_MCS")]
+            ArchReg::Reply => 6,
+            #[cfg(feature = "ENABLE_MCS")]
+            ArchReg::NBSendRecvDest => 8,
         }
     }
 }
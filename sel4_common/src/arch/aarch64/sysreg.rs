@@ -0,0 +1,310 @@
+//! The EL1 system registers the kernel touches, see the Arm ARM, chapter D19.
+
+use crate::sysreg::define_sysreg;
+
+define_sysreg! {
+    /// System control register
+    sctlr_el1, read "mrs {0}, sctlr_el1", write "msr sctlr_el1, {0}" => {
+        /// The MMU is enabled
+        M, 0, 1;
+        /// Alignment check
+        A, 1, 1;
+        /// Data accesses are cacheable
+        C, 2, 1;
+        /// SP alignment check at EL1
+        SA, 3, 1;
+        /// SP alignment check at EL0
+        SA0, 4, 1;
+        /// Instruction accesses are cacheable
+        I, 12, 1;
+        /// Writable memory is never executable
+        WXN, 19, 1;
+        /// EL1 data accesses are big endian
+        EE, 25, 1;
+    }
+}
+
+define_sysreg! {
+    /// Translation control register
+    tcr_el1, read "mrs {0}, tcr_el1", write "msr tcr_el1, {0}" => {
+        /// Size offset of the TTBR0 region, the region is 2^(64 - T0SZ) bytes
+        T0SZ, 0, 6;
+        /// Walks through TTBR0 are disabled
+        EPD0, 7, 1;
+        /// Inner cacheability of TTBR0 walks
+        IRGN0, 8, 2;
+        /// Outer cacheability of TTBR0 walks
+        ORGN0, 10, 2;
+        /// Shareability of TTBR0 walks
+        SH0, 12, 2;
+        /// Granule of TTBR0, 0 for 4KiB
+        TG0, 14, 2;
+        /// Size offset of the TTBR1 region
+        T1SZ, 16, 6;
+        /// The ASID is taken from TTBR1 instead of TTBR0
+        A1, 22, 1;
+        /// Walks through TTBR1 are disabled
+        EPD1, 23, 1;
+        /// Inner cacheability of TTBR1 walks
+        IRGN1, 24, 2;
+        /// Outer cacheability of TTBR1 walks
+        ORGN1, 26, 2;
+        /// Shareability of TTBR1 walks
+        SH1, 28, 2;
+        /// Granule of TTBR1, 2 for 4KiB
+        TG1, 30, 2;
+        /// Intermediate physical address size
+        IPS, 32, 3;
+        /// ASIDs are 16 bits
+        AS, 36, 1;
+    }
+}
+
+define_sysreg! {
+    /// Memory attribute indirection register, indexed by the AttrIndx of a descriptor
+    mair_el1, read "mrs {0}, mair_el1", write "msr mair_el1, {0}" => {
+        ATTR0, 0, 8;
+        ATTR1, 8, 8;
+        ATTR2, 16, 8;
+        ATTR3, 24, 8;
+        ATTR4, 32, 8;
+        ATTR5, 40, 8;
+        ATTR6, 48, 8;
+        ATTR7, 56, 8;
+    }
+}
+
+define_sysreg! {
+    /// Architectural feature access control register
+    cpacr_el1, read "mrs {0}, cpacr_el1", write "msr cpacr_el1, {0}" => {
+        /// SVE trapping, 3 for no trapping
+        ZEN, 16, 2;
+        /// FP and SIMD trapping, 3 for no trapping
+        FPEN, 20, 2;
+    }
+}
+
+define_sysreg! {
+    /// Exception syndrome register
+    esr_el1, read "mrs {0}, esr_el1", write "msr esr_el1, {0}" => {
+        /// Syndrome of the exception class
+        ISS, 0, 25;
+        /// Data abort: the access was a write
+        WNR, 6, 1;
+        /// Data abort: the register transferred by the access is 64 bits wide
+        SF, 15, 1;
+        /// Data abort: the register transferred by the access
+        SRT, 16, 5;
+        /// Data abort: the load sign extends the value
+        SSE, 21, 1;
+        /// Data abort: log2 of the size of the access in bytes
+        SAS, 22, 2;
+        /// Data abort: WNR, SRT and SAS are valid
+        ISV, 24, 1;
+        /// The trapped instruction was 32 bits
+        IL, 25, 1;
+        /// Exception class
+        EC, 26, 6;
+    }
+}
+
+define_sysreg! {
+    /// Fault address register
+    far_el1, read "mrs {0}, far_el1", write "msr far_el1, {0}" => {}
+}
+
+define_sysreg! {
+    /// Physical address register, the result of an address translation instruction
+    par_el1, read "mrs {0}, par_el1", write "msr par_el1, {0}" => {
+        /// The translation failed
+        F, 0, 1;
+        /// Bits 12..48 of the output address
+        PA, 12, 36;
+    }
+}
+
+define_sysreg! {
+    /// Hypervisor IPA fault address register, set by stage-2 aborts
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    hpfar_el2, read "mrs {0}, hpfar_el2", write "msr hpfar_el2, {0}" => {
+        /// Bits 12..52 of the faulting IPA
+        FIPA, 4, 40;
+    }
+}
+
+define_sysreg! {
+    /// Interrupt mask bits
+    daif, read "mrs {0}, daif", write "msr daif, {0}" => {
+        /// FIQs are masked
+        F, 6, 1;
+        /// IRQs are masked
+        I, 7, 1;
+        /// SErrors are masked
+        A, 8, 1;
+        /// Debug exceptions are masked
+        D, 9, 1;
+    }
+}
+
+define_sysreg! {
+    /// EL0 read/write thread ID register, the user TLS base
+    tpidr_el0, read "mrs {0}, tpidr_el0", write "msr tpidr_el0, {0}" => {}
+}
+
+define_sysreg! {
+    /// EL0 read-only thread ID register
+    tpidrro_el0, read "mrs {0}, tpidrro_el0", write "msr tpidrro_el0, {0}" => {}
+}
+
+// The MPAM registers are named by their encodings, older assemblers do not know them. The ID
+// registers are read-only, their `write` is never called.
+
+define_sysreg! {
+    /// AArch64 processor feature register 0
+    id_aa64pfr0_el1, read "mrs {0}, S3_0_C0_C4_0", write "msr S3_0_C0_C4_0, {0}" => {
+        /// Major version of the MPAM extension, 0 if absent
+        MPAM, 40, 4;
+    }
+}
+
+define_sysreg! {
+    /// AArch64 processor feature register 1
+    id_aa64pfr1_el1, read "mrs {0}, S3_0_C0_C4_1", write "msr S3_0_C0_C4_1, {0}" => {
+        /// Minor version of the MPAM extension
+        MPAM_frac, 16, 4;
+    }
+}
+
+define_sysreg! {
+    /// MPAM ID register
+    mpamidr_el1, read "mrs {0}, S3_0_C10_C4_4", write "msr S3_0_C10_C4_4, {0}" => {
+        /// Largest PARTID supported
+        PARTID_MAX, 0, 16;
+    }
+}
+
+define_sysreg! {
+    /// MPAM0 register, the partition of EL0 accesses
+    mpam0_el1, read "mrs {0}, S3_0_C10_C5_1", write "msr S3_0_C10_C5_1, {0}" => {
+        /// PARTID of instruction accesses
+        PARTID_I, 0, 16;
+        /// PARTID of data accesses
+        PARTID_D, 16, 16;
+    }
+}
+
+define_sysreg! {
+    /// MPAM1 register, the partition of EL1 accesses
+    mpam1_el1, read "mrs {0}, S3_0_C10_C5_0", write "msr S3_0_C10_C5_0, {0}" => {
+        /// PARTID of instruction accesses
+        PARTID_I, 0, 16;
+        /// PARTID of data accesses
+        PARTID_D, 16, 16;
+        /// MPAM is enabled by EL3, read-only at EL1
+        MPAMEN, 63, 1;
+    }
+}
+
+define_sysreg! {
+    /// Multiprocessor affinity register, read-only
+    mpidr_el1, read "mrs {0}, S3_0_C0_C0_5", write "msr S3_0_C0_C0_5, {0}" => {
+        AFF0, 0, 8;
+        AFF1, 8, 8;
+        AFF2, 16, 8;
+        AFF3, 32, 8;
+    }
+}
+
+// The GICv3 CPU interface registers are named by their encodings as well. The acknowledge
+// register is read-only, the end of interrupt and SGI registers are write-only.
+
+define_sysreg! {
+    /// Interrupt controller system register enable register
+    icc_sre_el1, read "mrs {0}, S3_0_C12_C12_5", write "msr S3_0_C12_C12_5, {0}" => {
+        /// The CPU interface is accessed through the system registers instead of memory
+        SRE, 0, 1;
+    }
+}
+
+define_sysreg! {
+    /// Interrupt controller priority mask register
+    icc_pmr_el1, read "mrs {0}, S3_0_C4_C6_0", write "msr S3_0_C4_C6_0, {0}" => {
+        /// Only interrupts of a higher priority, a lower value, are signalled
+        PRIORITY, 0, 8;
+    }
+}
+
+define_sysreg! {
+    /// Interrupt controller binary point register 1
+    icc_bpr1_el1, read "mrs {0}, S3_0_C12_C12_3", write "msr S3_0_C12_C12_3, {0}" => {
+        /// The priority bits below the binary point form the subpriority, which does not preempt
+        BINARY_POINT, 0, 3;
+    }
+}
+
+define_sysreg! {
+    /// Interrupt controller control register
+    icc_ctlr_el1, read "mrs {0}, S3_0_C12_C12_4", write "msr S3_0_C12_C12_4, {0}" => {
+        /// A write to the end of interrupt register only drops the priority, it does not
+        /// deactivate the interrupt
+        EOIMODE, 1, 1;
+    }
+}
+
+define_sysreg! {
+    /// Interrupt controller group 1 enable register
+    icc_igrpen1_el1, read "mrs {0}, S3_0_C12_C12_7", write "msr S3_0_C12_C12_7, {0}" => {
+        /// Group 1 interrupts are signalled
+        ENABLE, 0, 1;
+    }
+}
+
+define_sysreg! {
+    /// Interrupt controller acknowledge register 1
+    icc_iar1_el1, read "mrs {0}, S3_0_C12_C12_0", write "msr S3_0_C12_C12_0, {0}" => {
+        /// The acknowledged interrupt, 1023 if none is pending
+        INTID, 0, 24;
+    }
+}
+
+define_sysreg! {
+    /// Interrupt controller end of interrupt register 1
+    icc_eoir1_el1, read "mrs {0}, S3_0_C12_C12_1", write "msr S3_0_C12_C12_1, {0}" => {
+        INTID, 0, 24;
+    }
+}
+
+define_sysreg! {
+    /// Interrupt controller software generated interrupt group 1 register
+    icc_sgi1r_el1, read "mrs {0}, S3_0_C12_C11_5", write "msr S3_0_C12_C11_5, {0}" => {
+        /// The cores of the cluster to signal, by `AFF0` modulo 16
+        TARGET_LIST, 0, 16;
+        AFF1, 16, 8;
+        /// The interrupt, 0 to 15
+        INTID, 24, 4;
+        AFF2, 32, 8;
+        /// Signal all cores but the current one instead of the target list
+        IRM, 40, 1;
+        /// The range of the target list, `AFF0` divided by 16
+        RS, 44, 4;
+        AFF3, 48, 8;
+    }
+}
+
+define_sysreg! {
+    /// Counter-timer virtual timer control register
+    cntv_ctl_el0, read "mrs {0}, cntv_ctl_el0", write "msr cntv_ctl_el0, {0}" => {
+        /// The timer is enabled
+        ENABLE, 0, 1;
+        /// The timer condition does not raise the interrupt
+        IMASK, 1, 1;
+        /// The timer condition is met, read only
+        ISTATUS, 2, 1;
+    }
+}
+
+define_sysreg! {
+    /// Counter-timer virtual timer compare value register, the condition is met once the virtual
+    /// count reaches it
+    cntv_cval_el0, read "mrs {0}, cntv_cval_el0", write "msr cntv_cval_el0, {0}" => {}
+}
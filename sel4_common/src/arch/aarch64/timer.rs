@@ -0,0 +1,27 @@
+//! The timer of aarch64, see `crate::timer`.
+
+use super::sysreg::{cntv_ctl_el0, cntv_cval_el0};
+use crate::timer::KernelTimer;
+
+/// The virtual timer of the generic timer, which is free for the kernel at EL1. The kernel enables
+/// its PPI, `INTERRUPT_VTIMER_EVENT`, at boot.
+pub struct generic_timer_t;
+
+impl KernelTimer for generic_timer_t {
+    #[inline]
+    fn now() -> usize {
+        super::get_time()
+    }
+
+    #[inline]
+    fn set_deadline(deadline: usize) {
+        // The compare value is absolute and unsigned, the largest one is never reached
+        unsafe {
+            cntv_cval_el0::write(deadline);
+            cntv_ctl_el0::write(cntv_ctl_el0::ENABLE.val(1));
+        }
+    }
+}
+
+/// The timer the kernel is built with
+pub type kernel_timer_t = generic_timer_t;
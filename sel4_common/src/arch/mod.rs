@@ -10,6 +10,8 @@ mod aarch64;
 #[cfg(any(target_arch = "aarch64", test))]
 pub use aarch64::*;
 
+pub mod restart;
+
 /// ArchTCB Common part
 impl ArchTCB {
     /// Set the register of the TCB
@@ -21,6 +23,16 @@ impl ArchTCB {
         self.registers[reg.to_index()] = w;
     }
 
+    /// Set the register of the TCB to a value that comes from user mode.
+    ///
+    /// This is `setRegister(thread, reg, sanitiseRegister(reg, w, archInfo))`, the value is
+    /// sanitised first so a thread cannot leave user mode through its saved status register.
+    #[inline]
+    pub fn set_user_register(&mut self, reg: ArchReg, w: usize) {
+        let index = reg.to_index();
+        self.registers[index] = self.sanitise_register(index, w);
+    }
+
     /// Get the register value of the TCB
     /// # Arguments
     /// * `reg` - The register index.
@@ -66,4 +78,10 @@ pub enum ArchReg {
     GP(usize),
     /// Fault Message Reg, (id, index)
     FaultMessage(usize, usize),
+    /// The reply cap of Recv, Reply and ReplyRecv, see `ENABLE_MCS`
+    #[cfg(feature = "ENABLE_MCS")]
+    Reply,
+    /// The destination of the send phase of NBSendRecv, see `ENABLE_MCS`
+    #[cfg(feature = "ENABLE_MCS")]
+    NBSendRecvDest,
 }
@@ -0,0 +1,113 @@
+//! Where a thread resumes after a syscall, fault or restart.
+//!
+//! Two registers of [`ArchTCB`] describe it. `FaultIP` is the restart pc, the address of the
+//! syscall or faulting instruction. `NextIP` is the address user mode resumes at, which the trap
+//! entry sets past the syscall instruction, [`SYSCALL_INSTRUCTION_LEN`] bytes after `FaultIP`.
+//! A thread in `ThreadStateRestart` executes its syscall again, so it resumes at the restart pc,
+//! see [`ArchTCB::rewind_to_restart_pc`]. A thread which is suspended while running keeps its
+//! resume address as restart pc, see [`ArchTCB::update_restart_pc`]. A fault handler which
+//! emulates the faulting instruction skips it with [`ArchTCB::advance_past_restart_pc`], using the
+//! length decoded by [`riscv_instruction_len`] or [`arm_instruction_len`].
+
+use super::{ArchReg, ArchTCB};
+
+/// Length of the syscall instruction, `ecall` on riscv and `svc` on aarch64. Neither has a
+/// compressed form.
+pub const SYSCALL_INSTRUCTION_LEN: usize = 4;
+
+/// Length of the riscv instruction whose lowest 16 bits are `parcel`.
+///
+/// Only the standard lengths exist in user code: 16 bits for the compressed (RVC) instructions,
+/// whose two lowest bits are not `0b11`, and 32 bits for all others.
+#[inline]
+pub const fn riscv_instruction_len(parcel: u16) -> usize {
+    if parcel & 0b11 != 0b11 {
+        2
+    } else {
+        4
+    }
+}
+
+/// Length of the arm instruction which trapped with the syndrome `esr`.
+///
+/// The IL bit is set for 32-bit instructions: all A64 and A32 instructions, and the 32-bit T32
+/// ones. It is clear for 16-bit T32 (Thumb) instructions.
+#[inline]
+pub const fn arm_instruction_len(esr: usize) -> usize {
+    if esr & (1 << 25) != 0 {
+        4
+    } else {
+        2
+    }
+}
+
+impl ArchTCB {
+    /// The restart pc, the address of the syscall or faulting instruction
+    #[inline]
+    pub fn get_restart_pc(&self) -> usize {
+        self.get_register(ArchReg::FaultIP)
+    }
+
+    /// Resume at the restart pc, executing the syscall or faulting instruction again
+    #[inline]
+    pub fn rewind_to_restart_pc(&mut self) {
+        self.set_register(ArchReg::NextIP, self.get_restart_pc());
+    }
+
+    /// Resume past the instruction at the restart pc, which is `len` bytes long
+    #[inline]
+    pub fn advance_past_restart_pc(&mut self, len: usize) {
+        self.set_register(ArchReg::NextIP, self.get_restart_pc() + len);
+    }
+
+    /// Make the resume address the restart pc, for a thread which stops while running
+    #[inline]
+    pub fn update_restart_pc(&mut self) {
+        self.set_register(ArchReg::FaultIP, self.get_register(ArchReg::NextIP));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn riscv_instruction_len_test() {
+        // ecall
+        assert_eq!(riscv_instruction_len(0x0073), SYSCALL_INSTRUCTION_LEN);
+        // c.ebreak, ebreak shares its low parcel with ecall
+        assert_eq!(riscv_instruction_len(0x9002), 2);
+        // c.nop, c.lw and c.j, one per compressed quadrant
+        assert_eq!(riscv_instruction_len(0x0001), 2);
+        assert_eq!(riscv_instruction_len(0x4000), 2);
+        assert_eq!(riscv_instruction_len(0xa001), 2);
+        // the all-zero parcel is a defined illegal compressed instruction
+        assert_eq!(riscv_instruction_len(0x0000), 2);
+    }
+
+    #[test]
+    fn arm_instruction_len_test() {
+        // svc #0 from AArch64, EC 0x15 with IL set
+        assert_eq!(arm_instruction_len(0x5600_0000), SYSCALL_INSTRUCTION_LEN);
+        // svc from 16-bit Thumb, EC 0x11 with IL clear
+        assert_eq!(arm_instruction_len(0x4400_0000), 2);
+        // undefined A32 instruction, EC 0 with IL set
+        assert_eq!(arm_instruction_len(0x0200_0000), 4);
+    }
+
+    #[test]
+    fn restart_pc_test() {
+        let mut tcb = ArchTCB::default();
+        tcb.set_register(ArchReg::FaultIP, 0x1000);
+        tcb.set_register(ArchReg::NextIP, 0x1000 + SYSCALL_INSTRUCTION_LEN);
+
+        tcb.rewind_to_restart_pc();
+        assert_eq!(tcb.get_register(ArchReg::NextIP), 0x1000);
+
+        tcb.advance_past_restart_pc(riscv_instruction_len(0x9002));
+        assert_eq!(tcb.get_register(ArchReg::NextIP), 0x1002);
+
+        tcb.update_restart_pc();
+        assert_eq!(tcb.get_restart_pc(), 0x1002);
+    }
+}
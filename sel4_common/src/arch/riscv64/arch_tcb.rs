@@ -1,32 +1,75 @@
-use crate::ffi::kernel_stack_alloc;
+use crate::ffi::kernel_stack_top;
 // use crate::idle_thread;
+use super::sysreg::sstatus;
 use super::{fault_messages, msgRegister, NextIP};
 use super::{sp, CONTEXT_REG_NUM, SSTATUS, SSTATUS_SPIE, SSTATUS_SPP};
-use crate::sel4_config::CONFIG_KERNEL_STACK_BITS;
-use crate::BIT;
 
 /// This is `arch_tcb_t` in the sel4_c_impl.
 #[repr(C)]
 #[derive(Debug, PartialEq, Clone)]
 pub struct ArchTCB {
     pub(in crate::arch) registers: [usize; CONTEXT_REG_NUM],
+    /// The VCPU bound to the thread, 0 for none, see `ENABLE_RISCV_HYP`
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    pub(in crate::arch) vcpu: usize,
 }
 
 impl Default for ArchTCB {
     fn default() -> Self {
         let mut registers = [0; CONTEXT_REG_NUM];
-        registers[SSTATUS] = 0x00040020;
-        Self { registers }
+        registers[SSTATUS] = sstatus::SUM.mask() | sstatus::SPIE.mask();
+        Self {
+            registers,
+            #[cfg(feature = "ENABLE_RISCV_HYP")]
+            vcpu: 0,
+        }
     }
 }
 
 impl ArchTCB {
-    /// Config the registers fot the idle thread.
-    pub fn config_idle_thread(&mut self, idle_thread: usize) {
+    /// Publish the IPC buffer address `buffer` to user mode.
+    ///
+    /// This is `Arch_setTCBIPCBuffer`. riscv has no spare register for it: user mode keeps the
+    /// address in a thread local variable found through the TLS base in `tp`, so there is nothing
+    /// to do.
+    #[inline]
+    pub fn set_tcb_ipc_buffer(&mut self, _buffer: usize) {}
+
+    /// The VCPU bound to the thread, 0 for none, see `ENABLE_RISCV_HYP`.
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    #[inline]
+    pub fn get_vcpu(&self) -> usize {
+        self.vcpu
+    }
+
+    /// Bind the VCPU at `vcpu` to the thread, 0 to unbind it.
+    ///
+    /// `SPP` selects the mode `sret` returns to. A thread with a VCPU starts in VS-mode, the
+    /// kernel mode of its guest, and a thread without one must never return to anything but
+    /// U-mode, where it would run in HS-mode instead.
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    pub fn set_vcpu(&mut self, vcpu: usize) {
+        self.vcpu = vcpu;
+        self.registers[SSTATUS] = sstatus::SPP.set(self.registers[SSTATUS], (vcpu != 0) as usize);
+    }
+
+    /// Sanitise the value `v` user mode gives for the register at `index`.
+    ///
+    /// This is `sanitiseRegister`. `sstatus` is not among the frame and GP registers user mode
+    /// may write, but if it ever is, `SPP` stays what the kernel set, so the thread returns to the
+    /// mode it runs in now.
+    pub(in crate::arch) fn sanitise_register(&self, index: usize, v: usize) -> usize {
+        if index != SSTATUS {
+            return v;
+        }
+        sstatus::SPP.set(v, sstatus::SPP.get(self.registers[SSTATUS]))
+    }
+
+    /// Config the registers fot the idle thread of `core`, which runs on the kernel stack of the
+    /// core.
+    pub fn config_idle_thread(&mut self, idle_thread: usize, core: usize) {
         self.registers[NextIP] = idle_thread;
         self.registers[SSTATUS] = SSTATUS_SPP | SSTATUS_SPIE;
-        self.registers[sp] =
-            unsafe { &kernel_stack_alloc.data[0][BIT!(CONFIG_KERNEL_STACK_BITS) - 1] as *const u8 }
-                as usize;
+        self.registers[sp] = kernel_stack_top(core);
     }
 }
@@ -1,5 +1,9 @@
 #[derive(Eq, PartialEq, Debug, Clone, Copy, PartialOrd, Ord)]
 /// The label of a message.
+///
+/// The labels up to `nArchInvocationLabels` have the values libsel4 gives them for the same
+/// configuration. Labels which are not part of seL4 follow in their own range, which starts at
+/// `UntypedDescribe` whatever the configuration.
 #[repr(C)]
 pub enum MessageLabel {
     InvalidInvocation = 0,
@@ -11,13 +15,15 @@ pub enum MessageLabel {
     TCBSetPriority,
     TCBSetMCPriority,
     TCBSetSchedParams,
+    #[cfg(feature = "ENABLE_MCS")]
+    TCBSetTimeoutEndpoint,
     TCBSetIPCBuffer,
     TCBSetSpace,
     TCBSuspend,
     TCBResume,
     TCBBindNotification,
     TCBUnbindNotification,
-    #[cfg(feature = "ENABLE_SMP")]
+    #[cfg(all(feature = "ENABLE_SMP", not(feature = "ENABLE_MCS")))]
     TCBSetAffinity,
     TCBSetTLSBase,
     CNodeRevoke,
@@ -28,12 +34,25 @@ pub enum MessageLabel {
     CNodeMove,
     CNodeMutate,
     CNodeRotate,
+    #[cfg(not(feature = "ENABLE_MCS"))]
     CNodeSaveCaller,
     IRQIssueIRQHandler,
     IRQAckIRQ,
     IRQSetIRQHandler,
     IRQClearIRQHandler,
     DomainSetSet,
+    #[cfg(feature = "ENABLE_MCS")]
+    SchedControlConfigureFlags,
+    #[cfg(feature = "ENABLE_MCS")]
+    SchedContextBind,
+    #[cfg(feature = "ENABLE_MCS")]
+    SchedContextUnbind,
+    #[cfg(feature = "ENABLE_MCS")]
+    SchedContextUnbindObject,
+    #[cfg(feature = "ENABLE_MCS")]
+    SchedContextConsumed,
+    #[cfg(feature = "ENABLE_MCS")]
+    SchedContextYieldTo,
     RISCVPageTableMap,
     RISCVPageTableUnmap,
     RISCVPageMap,
@@ -43,4 +62,127 @@ pub enum MessageLabel {
     RISCVASIDPoolAssign,
     RISCVIRQIssueIRQHandlerTrigger,
     nArchInvocationLabels,
+    /// Non-seL4 extension, the first label of the extension range.
+    UntypedDescribe = 0x1000,
+    /// Non-seL4 extension, see `ENABLE_NTFN_WAIT_IF_EQUAL`.
+    NotificationWaitIfEqual,
+    /// Non-seL4 extension.
+    TCBCheckpointSave,
+    /// Non-seL4 extension.
+    TCBCheckpointRestore,
+    /// Non-seL4 extension.
+    IRQSetCore,
+    /// Non-seL4 extension, see `ENABLE_MEMORY_HOTADD`.
+    MemoryControlAddUntyped,
+    /// Non-seL4 extension, see `ENABLE_THREAD_ID`. Kept for the numbering, riscv64 does not
+    /// support it.
+    TCBSetThreadID,
+    /// Non-seL4 extension, see `ENABLE_REVOKE_NOTIFY`.
+    DomainSetRevokeNotification,
+    /// Non-seL4 extension, see `ENABLE_CPU_LIMIT`.
+    TCBSetCPULimit,
+    /// Non-seL4 extension, see `ENABLE_OBJECT_RELOCATE`.
+    MemoryControlRelocateCNode,
+    /// Non-seL4 extension.
+    RISCVPageClean_Data,
+    RISCVPageInvalidate_Data,
+    RISCVPageCleanInvalidate_Data,
+    RISCVPageUnify_Instruction,
+    RISCVVSpaceClean_Data,
+    RISCVVSpaceInvalidate_Data,
+    RISCVVSpaceCleanInvalidate_Data,
+    RISCVVSpaceUnify_Instruction,
+    /// Non-seL4 extension.
+    RISCVPageTableMapShared,
+    RISCVPageTableUnmapShared,
+    /// Non-seL4 extension.
+    RISCVVSpacePromote,
+    /// Non-seL4 extension, see `ENABLE_RISCV_HYP`.
+    RISCVVCPUSetTCB,
+    RISCVVCPUInjectIRQ,
+    RISCVVCPUReadReg,
+    RISCVVCPUWriteReg,
+    /// Non-seL4 extension, see `ENABLE_RISCV_AIA`.
+    RISCVIRQIssueIRQHandlerMSI,
+    /// Non-seL4 extension, only accepted with `ENABLE_SMP`.
+    RISCVIRQIssueIRQHandlerTriggerCore,
+    /// One past the last label of the extension range.
+    nExtensionInvocationLabels,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MessageLabel::{self, *};
+
+    fn check(labels: &[(MessageLabel, usize)]) {
+        for (label, value) in labels {
+            assert_eq!(*label as usize, *value, "{:?}", label);
+        }
+    }
+
+    /// The values of `sel4/invocation.h` and `sel4/arch/invocation.h` that libsel4
+    /// generates for the configuration under test.
+    #[test]
+    fn sel4_label_values_test() {
+        let mcs = cfg!(feature = "ENABLE_MCS") as usize;
+        let smp = (cfg!(feature = "ENABLE_SMP") && mcs == 0) as usize;
+        check(&[
+            (InvalidInvocation, 0),
+            (UntypedRetype, 1),
+            (TCBReadRegisters, 2),
+            (TCBWriteRegisters, 3),
+            (TCBCopyRegisters, 4),
+            (TCBConfigure, 5),
+            (TCBSetPriority, 6),
+            (TCBSetMCPriority, 7),
+            (TCBSetSchedParams, 8),
+            (TCBSetIPCBuffer, 9 + mcs),
+            (TCBSetSpace, 10 + mcs),
+            (TCBSuspend, 11 + mcs),
+            (TCBResume, 12 + mcs),
+            (TCBBindNotification, 13 + mcs),
+            (TCBUnbindNotification, 14 + mcs),
+            (TCBSetTLSBase, 15 + mcs + smp),
+            (CNodeRevoke, 16 + mcs + smp),
+            (CNodeDelete, 17 + mcs + smp),
+            (CNodeCancelBadgedSends, 18 + mcs + smp),
+            (CNodeCopy, 19 + mcs + smp),
+            (CNodeMint, 20 + mcs + smp),
+            (CNodeMove, 21 + mcs + smp),
+            (CNodeMutate, 22 + mcs + smp),
+            (CNodeRotate, 23 + mcs + smp),
+            (IRQIssueIRQHandler, 25 + smp),
+            (IRQAckIRQ, 26 + smp),
+            (IRQSetIRQHandler, 27 + smp),
+            (IRQClearIRQHandler, 28 + smp),
+            (DomainSetSet, 29 + smp),
+        ]);
+        #[cfg(not(feature = "ENABLE_MCS"))]
+        check(&[(CNodeSaveCaller, 24 + smp)]);
+        #[cfg(all(feature = "ENABLE_SMP", not(feature = "ENABLE_MCS")))]
+        check(&[(TCBSetAffinity, 15)]);
+        #[cfg(feature = "ENABLE_MCS")]
+        check(&[
+            (TCBSetTimeoutEndpoint, 9),
+            (SchedControlConfigureFlags, 30),
+            (SchedContextBind, 31),
+            (SchedContextUnbind, 32),
+            (SchedContextUnbindObject, 33),
+            (SchedContextConsumed, 34),
+            (SchedContextYieldTo, 35),
+        ]);
+
+        let arch = 30 + smp + 6 * mcs;
+        check(&[
+            (RISCVPageTableMap, arch),
+            (RISCVPageTableUnmap, arch + 1),
+            (RISCVPageMap, arch + 2),
+            (RISCVPageUnmap, arch + 3),
+            (RISCVPageGetAddress, arch + 4),
+            (RISCVASIDControlMakePool, arch + 5),
+            (RISCVASIDPoolAssign, arch + 6),
+            (RISCVIRQIssueIRQHandlerTrigger, arch + 7),
+        ]);
+        check(&[(UntypedDescribe, 0x1000)]);
+    }
 }
@@ -6,6 +6,8 @@ pub mod config;
 mod message_info;
 mod object;
 mod registers;
+pub mod sysreg;
+pub mod timer;
 mod vm_rights;
 pub use arch_tcb::ArchTCB;
 pub use message_info::*;
@@ -19,9 +21,15 @@ const SBI_CONSOLE_PUTCHAR: usize = 1;
 const SBI_CONSOLE_GETCHAR: usize = 2;
 
 const SBI_CLEAR_IPI: usize = 3;
+const SBI_SEND_IPI: usize = 4;
 const SBI_REMOTE_SFENCE_VMA: usize = 6;
 const SBI_SHUTDOWN: usize = 8;
 const SYSCALL_WRITE: usize = 64;
+/// The RFENCE extension of SBI v0.2, the legacy calls have no fence for guests
+#[cfg(feature = "ENABLE_RISCV_HYP")]
+const SBI_EXT_RFENCE: usize = 0x52464e43;
+#[cfg(feature = "ENABLE_RISCV_HYP")]
+const SBI_RFENCE_REMOTE_HFENCE_GVMA: usize = 3;
 
 #[no_mangle]
 pub fn sbi_call(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
@@ -38,13 +46,20 @@ pub fn sbi_call(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
     ret
 }
 
+/// Raise the timer interrupt once the time reaches `timer`, see `crate::timer::KernelTimer`
 pub fn set_timer(timer: usize) {
-    sbi_call(SBI_SET_TIMER, timer, 0, 0);
+    <timer::kernel_timer_t as crate::timer::KernelTimer>::set_deadline(timer);
 }
 
 pub fn clear_ipi() {
     sbi_call(SBI_CLEAR_IPI, 0, 0, 0);
 }
+
+/// Raise a supervisor software interrupt on the harts in `hart_mask`
+pub fn send_ipi(hart_mask: usize) {
+    let virt_addr_hart_mask = (&hart_mask) as *const usize as usize;
+    sbi_call(SBI_SEND_IPI, virt_addr_hart_mask, 0, 0);
+}
 pub fn shutdown() -> ! {
     sbi_call(SBI_SHUTDOWN, 0, 0, 0);
     panic!("It should shutdown!");
@@ -59,6 +74,22 @@ pub fn remote_sfence_vma(hart_mask: usize, start: usize, size: usize) {
     sbi_call(SBI_REMOTE_SFENCE_VMA, virt_addr_hart_mask, 0, 0);
 }
 
+/// Drop the G-stage TLB entries of all VMIDs on the harts in `hart_mask`, see `ENABLE_RISCV_HYP`
+#[cfg(feature = "ENABLE_RISCV_HYP")]
+pub fn remote_hfence_gvma(hart_mask: usize) {
+    unsafe {
+        core::arch::asm!(
+        "ecall",
+        inlateout("x10") hart_mask => _,
+        inlateout("x11") 0usize => _,
+        in("x12") 0usize,
+        in("x13") 0usize,
+        in("x16") SBI_RFENCE_REMOTE_HFENCE_GVMA,
+        in("x17") SBI_EXT_RFENCE,
+        );
+    }
+}
+
 pub fn get_time() -> usize {
     time::read()
 }
@@ -1,3 +1,5 @@
+#[cfg(feature = "ENABLE_RISCV_HYP")]
+use crate::sel4_config::seL4_VCPUBits;
 use crate::sel4_config::{
     seL4_HugePageBits, seL4_LargePageBits, seL4_PageBits, RISCV_4K_Page, RISCV_Giga_Page,
     RISCV_Mega_Page,
@@ -11,11 +13,19 @@ pub enum ObjectType {
     EndpointObject = 2,
     NotificationObject = 3,
     CapTableObject = 4,
+    /// MCS, see `ENABLE_MCS`. The object types that follow move up by one like in seL4.
+    #[cfg(feature = "ENABLE_MCS")]
+    SchedContextObject = 5,
+    #[cfg(feature = "ENABLE_MCS")]
+    ReplyObject,
     // RISCV relevant object
-    GigaPageObject = 5,
-    NormalPageObject = 6,
-    MegaPageObject = 7,
-    PageTableObject = 8,
+    GigaPageObject,
+    NormalPageObject,
+    MegaPageObject,
+    PageTableObject,
+    /// The virtual CPU of a guest, see `ENABLE_RISCV_HYP`
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    RISCV_VCPUObject,
 }
 
 impl ObjectType {
@@ -25,6 +35,8 @@ impl ObjectType {
             ObjectType::NormalPageObject => seL4_PageBits,
             ObjectType::MegaPageObject => seL4_LargePageBits,
             ObjectType::PageTableObject => seL4_PageBits,
+            #[cfg(feature = "ENABLE_RISCV_HYP")]
+            ObjectType::RISCV_VCPUObject => seL4_VCPUBits,
             _ => panic!("unsupported cap type:{}", (*self) as usize),
         }
     }
@@ -51,6 +63,10 @@ impl ObjectType {
     ///
     /// true if the object type is an architecture-specific type, false otherwise.
     pub fn is_arch_type(self) -> bool {
+        #[cfg(feature = "ENABLE_RISCV_HYP")]
+        if self == Self::RISCV_VCPUObject {
+            return true;
+        }
         matches!(
             self,
             Self::GigaPageObject | Self::NormalPageObject | Self::MegaPageObject
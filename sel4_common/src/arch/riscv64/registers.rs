@@ -1,10 +1,13 @@
 //! This module contains constants representing register indices and values used in the kernel.
 
+use super::sysreg::sstatus;
 use crate::arch::ArchReg;
 pub(super) const ra: usize = 0;
 pub(super) const sp: usize = 1;
 // const gp: usize = 2;
 // const tp: usize = 3;
+/// `tp`, the TLS base. It is switched with the other general purpose registers, and user mode
+/// finds its IPC buffer through a thread local variable.
 pub(super) const TLS_BASE: usize = 3;
 // const t0: usize = 4;
 // const t1: usize = 5;
@@ -49,8 +52,8 @@ pub(super) const CONTEXT_REG_NUM: usize = 35;
 pub const msgRegisterNum: usize = 4;
 pub const msgRegister: [usize; msgRegisterNum] = [11, 12, 13, 14];
 
-pub const SSTATUS_SPIE: usize = 0x00000020;
-pub const SSTATUS_SPP: usize = 0x00000100;
+pub const SSTATUS_SPIE: usize = sstatus::SPIE.mask();
+pub const SSTATUS_SPP: usize = sstatus::SPP.mask();
 
 pub const n_syscallMessage: usize = 10;
 pub const n_exceptionMessage: usize = 2;
@@ -82,6 +85,10 @@ impl ArchReg {
             ArchReg::Frame(i) => frameRegisters[*i],
             ArchReg::GP(i) => gpRegisters[*i],
             ArchReg::FaultMessage(id, index) => fault_messages[*id][*index],
+            #[cfg(feature = "ENABLE_MCS")]
+            ArchReg::Reply => 15,
+            #[cfg(feature = "ENABLE_MCS")]
+            ArchReg::NBSendRecvDest => 4,
         }
     }
 }
@@ -0,0 +1,240 @@
+//! The supervisor CSRs the kernel touches, see the RISC-V privileged spec, chapter 10, and with
+//! `ENABLE_RISCV_HYP` the hypervisor and virtual supervisor CSRs of chapter 18.
+
+use crate::sysreg::define_sysreg;
+
+define_sysreg! {
+    /// Supervisor status register
+    sstatus, read "csrr {0}, sstatus", write "csrw sstatus, {0}",
+        set "csrrs {0}, sstatus, {1}", clear "csrrc {0}, sstatus, {1}" => {
+        /// Interrupts are enabled in S-mode
+        SIE, 1, 1;
+        /// SIE before the last trap, restored by `sret`
+        SPIE, 5, 1;
+        /// Privilege before the last trap, 1 for S-mode
+        SPP, 8, 1;
+        /// State of the floating point unit
+        FS, 13, 2;
+        /// S-mode may access U-mode pages
+        SUM, 18, 1;
+        /// Loads from executable-only pages succeed
+        MXR, 19, 1;
+    }
+}
+
+define_sysreg! {
+    /// Supervisor interrupt enable register
+    sie, read "csrr {0}, sie", write "csrw sie, {0}",
+        set "csrrs {0}, sie, {1}", clear "csrrc {0}, sie, {1}" => {
+        /// Software interrupt, the IPIs of SMP
+        SSIE, 1, 1;
+        /// Timer interrupt
+        STIE, 5, 1;
+        /// External interrupt, from the PLIC or the IMSIC of `ENABLE_RISCV_AIA`
+        SEIE, 9, 1;
+    }
+}
+
+define_sysreg! {
+    /// Supervisor interrupt pending register, same layout as [`sie`]
+    sip, read "csrr {0}, sip", write "csrw sip, {0}",
+        set "csrrs {0}, sip, {1}", clear "csrrc {0}, sip, {1}" => {
+        /// Software interrupt pending
+        SSIP, 1, 1;
+        /// Timer interrupt pending
+        STIP, 5, 1;
+        /// External interrupt pending
+        SEIP, 9, 1;
+    }
+}
+
+define_sysreg! {
+    /// Supervisor trap vector base address register
+    stvec, read "csrr {0}, stvec", write "csrw stvec, {0}" => {
+        /// 0 traps to BASE, 1 vectors interrupts to BASE + 4 * cause
+        MODE, 0, 2;
+        /// Trap vector address, 4 byte aligned
+        BASE, 2, 62;
+    }
+}
+
+define_sysreg! {
+    /// Supervisor address translation and protection register
+    satp, read "csrr {0}, satp", write "csrw satp, {0}" => {
+        /// Physical page number of the root page table
+        PPN, 0, 44;
+        /// Address space identifier
+        ASID, 44, 16;
+        /// Translation scheme, 8 for Sv39
+        MODE, 60, 4;
+    }
+}
+
+define_sysreg! {
+    /// Supervisor trap cause register
+    scause, read "csrr {0}, scause", write "csrw scause, {0}" => {
+        /// Exception or interrupt code
+        CODE, 0, 63;
+        /// The trap was an interrupt
+        INTERRUPT, 63, 1;
+    }
+}
+
+define_sysreg! {
+    /// Supervisor trap value register, the faulting address or instruction
+    stval, read "csrr {0}, stval", write "csrw stval, {0}" => {}
+}
+
+define_sysreg! {
+    /// Supervisor scratch register, the kernel stack of the current core
+    sscratch, read "csrr {0}, sscratch", write "csrw sscratch, {0}" => {}
+}
+
+define_sysreg! {
+    /// Supervisor timer compare register of the Sstc extension, see `ENABLE_RISCV_SSTC`. The
+    /// timer interrupt is pending while `time` is at least its value.
+    #[cfg(feature = "ENABLE_RISCV_SSTC")]
+    stimecmp, read "csrr {0}, 0x14d", write "csrw 0x14d, {0}" => {}
+}
+
+define_sysreg! {
+    /// Hypervisor status register, see `ENABLE_RISCV_HYP`
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    hstatus, read "csrr {0}, hstatus", write "csrw hstatus, {0}",
+        set "csrrs {0}, hstatus, {1}", clear "csrrc {0}, hstatus, {1}" => {
+        /// The trap came from a guest, `sret` returns to it
+        SPV, 7, 1;
+        /// Privilege of the guest before the last trap, 1 for VS-mode
+        SPVP, 8, 1;
+        /// The guest external interrupt line the guest sees as its external interrupt
+        VGEIN, 12, 6;
+    }
+}
+
+define_sysreg! {
+    /// Hypervisor exception delegation register, the exceptions of a guest it handles itself
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    hedeleg, read "csrr {0}, hedeleg", write "csrw hedeleg, {0}" => {}
+}
+
+define_sysreg! {
+    /// Hypervisor interrupt delegation register, the VS-level interrupts a guest handles itself
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    hideleg, read "csrr {0}, hideleg", write "csrw hideleg, {0}" => {}
+}
+
+define_sysreg! {
+    /// Hypervisor virtual interrupt pending register, the interrupts injected into the guest
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    hvip, read "csrr {0}, hvip", write "csrw hvip, {0}",
+        set "csrrs {0}, hvip, {1}", clear "csrrc {0}, hvip, {1}" => {
+        /// VS-level software interrupt
+        VSSIP, 2, 1;
+        /// VS-level timer interrupt
+        VSTIP, 6, 1;
+        /// VS-level external interrupt
+        VSEIP, 10, 1;
+    }
+}
+
+define_sysreg! {
+    /// Hypervisor counter enable register, the counters a guest may read
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    hcounteren, read "csrr {0}, hcounteren", write "csrw hcounteren, {0}" => {}
+}
+
+define_sysreg! {
+    /// Hypervisor guest address translation and protection register
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    hgatp, read "csrr {0}, hgatp", write "csrw hgatp, {0}" => {
+        /// Physical page number of the root G-stage table
+        PPN, 0, 44;
+        /// Virtual machine identifier
+        VMID, 44, 14;
+        /// Translation scheme, 8 for Sv39x4
+        MODE, 60, 4;
+    }
+}
+
+define_sysreg! {
+    /// Hypervisor trap value register, the guest physical address of a guest page fault >> 2
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    htval, read "csrr {0}, htval", write "csrw htval, {0}" => {}
+}
+
+define_sysreg! {
+    /// Hypervisor trap instruction register, the transformed instruction of a guest trap, if the
+    /// core reports it
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    htinst, read "csrr {0}, htinst", write "csrw htinst, {0}" => {}
+}
+
+define_sysreg! {
+    /// Virtual supervisor status register, the `s*` register of a guest
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    vsstatus, read "csrr {0}, vsstatus", write "csrw vsstatus, {0}" => {}
+}
+
+define_sysreg! {
+    /// Virtual supervisor interrupt enable register, the `s*` register of a guest
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    vsie, read "csrr {0}, vsie", write "csrw vsie, {0}" => {}
+}
+
+define_sysreg! {
+    /// Virtual supervisor trap vector base address register, the `s*` register of a guest
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    vstvec, read "csrr {0}, vstvec", write "csrw vstvec, {0}" => {}
+}
+
+define_sysreg! {
+    /// Virtual supervisor scratch register, the `s*` register of a guest
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    vsscratch, read "csrr {0}, vsscratch", write "csrw vsscratch, {0}" => {}
+}
+
+define_sysreg! {
+    /// Virtual supervisor exception program counter, the `s*` register of a guest
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    vsepc, read "csrr {0}, vsepc", write "csrw vsepc, {0}" => {}
+}
+
+define_sysreg! {
+    /// Virtual supervisor trap cause register, the `s*` register of a guest
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    vscause, read "csrr {0}, vscause", write "csrw vscause, {0}" => {}
+}
+
+define_sysreg! {
+    /// Virtual supervisor trap value register, the `s*` register of a guest
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    vstval, read "csrr {0}, vstval", write "csrw vstval, {0}" => {}
+}
+
+define_sysreg! {
+    /// Virtual supervisor address translation and protection register, the `s*` register of a guest
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    vsatp, read "csrr {0}, vsatp", write "csrw vsatp, {0}" => {}
+}
+
+define_sysreg! {
+    /// Supervisor indirect register select, the register of the IMSIC interrupt file [`sireg`]
+    /// reaches, see `ENABLE_RISCV_AIA`. By number, assemblers without Smaia do not know its name.
+    siselect, read "csrr {0}, 0x150", write "csrw 0x150, {0}" => {}
+}
+
+define_sysreg! {
+    /// Supervisor indirect register alias, the register [`siselect`] selects
+    sireg, read "csrr {0}, 0x151", write "csrw 0x151, {0}",
+        set "csrrs {0}, 0x151, {1}", clear "csrrc {0}, 0x151, {1}" => {}
+}
+
+define_sysreg! {
+    /// Supervisor top external interrupt of the IMSIC interrupt file, a write claims it
+    stopei, read "csrr {0}, 0x15c", write "csrw 0x15c, {0}" => {
+        /// Priority of the interrupt, equal to its identity
+        PRIORITY, 0, 11;
+        /// Identity of the highest priority pending and enabled interrupt, 0 for none
+        IDENTITY, 16, 11;
+    }
+}
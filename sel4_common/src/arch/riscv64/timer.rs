@@ -0,0 +1,45 @@
+//! The timers of riscv, see `crate::timer`.
+
+use super::{sbi_call, SBI_SET_TIMER};
+use crate::timer::KernelTimer;
+
+/// The timer of the SBI, every deadline is a call into the firmware
+pub struct sbi_timer_t;
+
+impl KernelTimer for sbi_timer_t {
+    #[inline]
+    fn now() -> usize {
+        super::get_time()
+    }
+
+    #[inline]
+    fn set_deadline(deadline: usize) {
+        // The SBI clears the pending timer interrupt and takes `u64::MAX` as no deadline
+        sbi_call(SBI_SET_TIMER, deadline, 0, 0);
+    }
+}
+
+/// The timer of the Sstc extension, the kernel writes the deadline to `stimecmp` itself. The
+/// firmware has to let S-mode use it with `menvcfg.STCE`, which OpenSBI does on harts with Sstc.
+#[cfg(feature = "ENABLE_RISCV_SSTC")]
+pub struct sstc_timer_t;
+
+#[cfg(feature = "ENABLE_RISCV_SSTC")]
+impl KernelTimer for sstc_timer_t {
+    #[inline]
+    fn now() -> usize {
+        super::get_time()
+    }
+
+    #[inline]
+    fn set_deadline(deadline: usize) {
+        // The interrupt is pending while `time >= stimecmp`, a later value clears it
+        unsafe { super::sysreg::stimecmp::write(deadline) };
+    }
+}
+
+/// The timer the kernel is built with
+#[cfg(not(feature = "ENABLE_RISCV_SSTC"))]
+pub type kernel_timer_t = sbi_timer_t;
+#[cfg(feature = "ENABLE_RISCV_SSTC")]
+pub type kernel_timer_t = sstc_timer_t;
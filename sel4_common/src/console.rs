@@ -1,16 +1,227 @@
-//! SBI console driver, for text output
+//! Kernel console output.
+//!
+//! Output goes to every sink in the active sink list. The list defaults to the UART and can be
+//! picked at build time with the `CONSOLE` environment variable (a comma separated list of
+//! `uart`, `semihosting`, `ring` and `null`), or changed at boot with [`set_console_sinks`].
+//!
+//! - `uart`: the platform serial driver from `driver_collect`.
+//! - `semihosting`: the debugger/QEMU semihosting `SYS_WRITEC` call. Only select this when a
+//!   semihosting host is attached, otherwise the trap instruction faults.
+//! - `ring`: the RAM ring buffer [`ksConsoleRing`], which can be read post-mortem from a
+//!   debugger through the symbol of the same name. Boot code may move the ring somewhere else
+//!   with [`set_console_ring`], e.g. to memory that survives a warm reboot.
+//! - `null` (an empty list): all output is dropped.
 
 use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use driver_collect::default_serial;
 use driver_collect::SerialDriver;
 
+use crate::BIT;
+
+pub const CONSOLE_SINK_UART: usize = BIT!(0);
+pub const CONSOLE_SINK_SEMIHOSTING: usize = BIT!(1);
+pub const CONSOLE_SINK_RING: usize = BIT!(2);
+
+/// Size of the data area of [`ksConsoleRing`], must be a power of two.
+pub const CONSOLE_RING_SIZE: usize = BIT!(14);
+
+static ksConsoleSinks: AtomicUsize = AtomicUsize::new(CONSOLE_SINK_UART);
+
+/// Marks a [`console_ring_t`] whose header has been initialised.
+pub const CONSOLE_RING_MAGIC: usize = 0x7265_6c34_6c6f_6721;
+
+/// RAM ring buffer console sink. `head` counts every byte ever written, so the oldest byte
+/// still in the buffer is at `head - CONSOLE_RING_SIZE` when `head > CONSOLE_RING_SIZE`.
+/// `crc` covers `magic` and `head` so a reader can tell a live ring from random memory.
+#[repr(C)]
+pub struct console_ring_t {
+    pub magic: usize,
+    pub head: usize,
+    pub crc: usize,
+    pub data: [u8; CONSOLE_RING_SIZE],
+}
+
+impl console_ring_t {
+    fn header_crc(&self) -> usize {
+        let mut crc = crc32(!0, &self.magic.to_le_bytes());
+        crc = crc32(crc, &self.head.to_le_bytes());
+        !crc as usize
+    }
+
+    /// Whether the header was written by [`console_ring_t::reset`] and the ring since.
+    pub fn is_valid(&self) -> bool {
+        self.magic == CONSOLE_RING_MAGIC && self.crc == self.header_crc()
+    }
+
+    /// Empty the ring and write a valid header.
+    pub fn reset(&mut self) {
+        self.magic = CONSOLE_RING_MAGIC;
+        self.head = 0;
+        self.crc = self.header_crc();
+    }
+
+    /// Number of bytes held in the ring.
+    pub fn used_bytes(&self) -> usize {
+        self.head.min(CONSOLE_RING_SIZE)
+    }
+
+    /// Copy the contents of the ring, oldest byte first, into `dst`. Returns the bytes copied.
+    pub fn copy_to(&self, dst: &mut [u8]) -> usize {
+        let n = self.used_bytes().min(dst.len());
+        let start = self.head.wrapping_sub(n);
+        for (i, b) in dst[..n].iter_mut().enumerate() {
+            *b = self.data[start.wrapping_add(i) & (CONSOLE_RING_SIZE - 1)];
+        }
+        n
+    }
+
+    /// Make this ring a copy of `other`, with a valid header.
+    pub fn copy_from(&mut self, other: &console_ring_t) {
+        self.magic = CONSOLE_RING_MAGIC;
+        self.head = other.head;
+        self.data.copy_from_slice(&other.data);
+        self.crc = self.header_crc();
+    }
+
+    fn push(&mut self, c: u8) {
+        self.data[self.head & (CONSOLE_RING_SIZE - 1)] = c;
+        self.head = self.head.wrapping_add(1);
+        self.crc = self.header_crc();
+    }
+}
+
+/// Bitwise CRC-32 (IEEE), only used for the small ring header.
+fn crc32(mut crc: u32, bytes: &[u8]) -> u32 {
+    for b in bytes {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+#[no_mangle]
+pub static mut ksConsoleRing: console_ring_t = console_ring_t {
+    magic: 0,
+    head: 0,
+    crc: 0,
+    data: [0; CONSOLE_RING_SIZE],
+};
+
+/// The ring the `ring` sink writes to, `0` means [`ksConsoleRing`].
+static ksConsoleRingPtr: AtomicUsize = AtomicUsize::new(0);
+
+/// Parse a comma separated sink list, unknown names are ignored.
+fn parse_console_sinks(list: &str) -> usize {
+    let mut sinks = 0;
+    for name in list.split(',') {
+        sinks |= match name.trim() {
+            "uart" => CONSOLE_SINK_UART,
+            "semihosting" => CONSOLE_SINK_SEMIHOSTING,
+            "ring" => CONSOLE_SINK_RING,
+            _ => 0,
+        };
+    }
+    sinks
+}
+
+/// Select the sinks from the build time `CONSOLE` setting, if any.
+pub fn init() {
+    if let Some(list) = option_env!("CONSOLE") {
+        set_console_sinks(parse_console_sinks(list));
+    }
+}
+
+/// Replace the active sink list, `0` disables console output.
+pub fn set_console_sinks(sinks: usize) {
+    ksConsoleSinks.store(sinks, Ordering::Relaxed);
+}
+
+pub fn get_console_sinks() -> usize {
+    ksConsoleSinks.load(Ordering::Relaxed)
+}
+
+#[cfg(target_arch = "riscv64")]
+fn semihosting_putchar(c: u8) {
+    const SYS_WRITEC: usize = 0x03;
+    unsafe {
+        core::arch::asm!(
+            ".option push",
+            ".option norvc",
+            "slli x0, x0, 0x1f",
+            "ebreak",
+            "srai x0, x0, 7",
+            ".option pop",
+            inlateout("a0") SYS_WRITEC => _,
+            in("a1") &c as *const u8,
+        );
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn semihosting_putchar(c: u8) {
+    const SYS_WRITEC: usize = 0x03;
+    unsafe {
+        core::arch::asm!(
+            "hlt #0xf000",
+            inlateout("x0") SYS_WRITEC => _,
+            in("x1") &c as *const u8,
+        );
+    }
+}
+
+/// The ring buffer currently used by the `ring` sink.
+pub fn get_console_ring() -> &'static mut console_ring_t {
+    let ptr = ksConsoleRingPtr.load(Ordering::Relaxed);
+    unsafe {
+        if ptr == 0 {
+            let ring = &mut *core::ptr::addr_of_mut!(ksConsoleRing);
+            if ring.magic != CONSOLE_RING_MAGIC {
+                ring.reset();
+            }
+            ring
+        } else {
+            &mut *(ptr as *mut console_ring_t)
+        }
+    }
+}
+
+/// Make the `ring` sink write to `ring` from now on. The caller initialises its header.
+pub fn set_console_ring(ring: &'static mut console_ring_t) {
+    ksConsoleRingPtr.store(ring as *mut console_ring_t as usize, Ordering::Relaxed);
+}
+
+fn ring_putchar(c: u8) {
+    get_console_ring().push(c);
+}
+
+/// Write a byte to every active sink.
+pub fn console_putchar(c: u8) {
+    let sinks = get_console_sinks();
+    if sinks & CONSOLE_SINK_UART != 0 {
+        default_serial().putchar(c);
+    }
+    if sinks & CONSOLE_SINK_SEMIHOSTING != 0 {
+        semihosting_putchar(c);
+    }
+    if sinks & CONSOLE_SINK_RING != 0 {
+        ring_putchar(c);
+    }
+}
+
 struct Stdout;
 
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            default_serial().putchar(c as _);
+        for c in s.bytes() {
+            console_putchar(c);
         }
         Ok(())
     }
@@ -1,14 +1,18 @@
 //! This module defines fault types and related constants for the seL4 microkernel.
 //! It provides bitfield definitions for different fault types, such as NullFault, CapFault,
-//! UnknownSyscall, UserException, and VMFault.
+//! UnknownSyscall, UserException, VMFault, with `ENABLE_MCS` or `ENABLE_CPU_LIMIT` Timeout and,
+//! with `ENABLE_ARM_HYP`, VGICMaintenance, GuestVMFault and VCPUFault. With `ENABLE_RISCV_HYP`
+//! riscv64 raises the last two as well, with `ENABLE_MMIO_EMULATION` aarch64 raises MMIOFault.
 //!
 //! The `FaultType` enum represents the different fault types, and the `seL4_Fault_t` struct
 //! provides methods to get the fault type.
 //!
 //! The module also defines constants for specific fault types, such as `seL4_Fault_NullFault`,
-//! `seL4_Fault_CapFault`, `seL4_Fault_UnknownSyscall`, `seL4_Fault_UserException`, and `seL4_Fault_VMFault`.
+//! `seL4_Fault_CapFault`, `seL4_Fault_UnknownSyscall`, `seL4_Fault_UserException`, `seL4_Fault_VMFault` and `seL4_Fault_Timeout`.
 //!
-//! Additionally, it defines constants for specific fields in the `seL4_VMFault_Msg` and `seL4_CapFault_Msg` structs.
+//! Additionally, it defines constants for specific fields in the `seL4_VMFault_Msg`, `seL4_CapFault_Msg`,
+//! `seL4_Timeout_Msg`, `seL4_VGICMaintenance_Msg`, `seL4_GuestVMFault_Msg`, `seL4_VCPUFault_Msg` and
+//! `seL4_MMIOFault_Msg` structs.
 //!
 //! The `LookupFaultType` enum represents different types of lookup faults, such as InvalidRoot,
 //! MissingCap, DepthMismatch, and GuardMismatch. The `lookup_fault_t` struct provides methods
@@ -38,6 +42,19 @@ plus_define_bitfield! {
             address, vm_fault_get_address, vm_fault_set_address, 1, 0, 64, 0, false,
             fsr, vm_fault_get_fsr, vm_fault_set_fsr, 0, 27, 5, 0, false,
             instruction_fault, vm_fault_get_instruction_fault, vm_fault_set_instruction_fault, 0, 19, 1, 0, false
+        },
+        new_timeout_fault, seL4_Fault_Timeout => {
+            badge, timeout_get_badge, timeout_set_badge, 1, 0, 64, 0, false
+        },
+        new_guest_vm_fault, seL4_Fault_GuestVMFault => {
+            ipa, guest_vm_fault_get_ipa, guest_vm_fault_set_ipa, 1, 0, 64, 0, false,
+            iss, guest_vm_fault_get_iss, guest_vm_fault_set_iss, 0, 32, 32, 0, false,
+            width, guest_vm_fault_get_width, guest_vm_fault_set_width, 0, 8, 4, 0, false,
+            write, guest_vm_fault_get_write, guest_vm_fault_set_write, 0, 4, 1, 0, false
+        },
+        new_vcpu_fault, seL4_Fault_VCPUFault => {
+            data, vcpu_fault_get_data, vcpu_fault_set_data, 1, 0, 64, 0, false,
+            hsr, vcpu_fault_get_hsr, vcpu_fault_set_hsr, 0, 32, 32, 0, false
         }
     }
 }
@@ -64,17 +81,76 @@ plus_define_bitfield! {
             address, vm_fault_get_address, vm_fault_set_address, 1, 0, 64, 0, false,
             fsr, vm_fault_get_fsr, vm_fault_set_fsr, 0, 32, 32, 0, false,
             instruction_fault, vm_fault_get_instruction_fault, vm_fault_set_instruction_fault, 0, 31, 1, 0, false
+        },
+        new_timeout_fault, seL4_Fault_Timeout => {
+            badge, timeout_get_badge, timeout_set_badge, 1, 0, 64, 0, false
+        },
+        new_vgic_maintenance_fault, seL4_Fault_VGICMaintenance => {
+            idx, vgic_maintenance_get_idx, vgic_maintenance_set_idx, 0, 5, 6, 0, false,
+            idx_valid, vgic_maintenance_get_idx_valid, vgic_maintenance_set_idx_valid, 0, 4, 1, 0, false
+        },
+        new_guest_vm_fault, seL4_Fault_GuestVMFault => {
+            ipa, guest_vm_fault_get_ipa, guest_vm_fault_set_ipa, 1, 0, 64, 0, false,
+            iss, guest_vm_fault_get_iss, guest_vm_fault_set_iss, 0, 32, 25, 0, false,
+            width, guest_vm_fault_get_width, guest_vm_fault_set_width, 0, 8, 4, 0, false,
+            write, guest_vm_fault_get_write, guest_vm_fault_set_write, 0, 4, 1, 0, false
+        },
+        new_vcpu_fault, seL4_Fault_VCPUFault => {
+            hsr, vcpu_fault_get_hsr, vcpu_fault_set_hsr, 0, 32, 32, 0, false
+        },
+        new_mmio_fault, seL4_Fault_MMIOFault => {
+            address, mmio_fault_get_address, mmio_fault_set_address, 1, 0, 64, 0, false,
+            data, mmio_fault_get_data, mmio_fault_set_data, 2, 0, 64, 0, false,
+            width, mmio_fault_get_width, mmio_fault_set_width, 0, 4, 4, 0, false,
+            write, mmio_fault_get_write, mmio_fault_set_write, 0, 8, 1, 0, false,
+            sign_extend, mmio_fault_get_sign_extend, mmio_fault_set_sign_extend, 0, 9, 1, 0, false,
+            sixty_four, mmio_fault_get_sixty_four, mmio_fault_set_sixty_four, 0, 10, 1, 0, false,
+            register, mmio_fault_get_register, mmio_fault_set_register, 0, 11, 5, 0, false
         }
     }
 }
 
+/// The timeout fault libsel4 numbers before the architecture faults with `ENABLE_MCS`
+const MCS_FAULTS: isize = cfg!(feature = "ENABLE_MCS") as isize;
+
+/// The fault types, numbered as libsel4 numbers them for the same configuration. With
+/// `ENABLE_MCS` Timeout is 5 and the architecture faults move up by one, the fault types which
+/// are not part of seL4 follow the last of them, VPPIEvent.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum FaultType {
     NullFault = 0,
     CapFault = 1,
     UnknownSyscall = 2,
     UserException = 3,
-    VMFault = 5,
+    /// Raised when the budget of a scheduling context runs out, or with `ENABLE_CPU_LIMIT` when
+    /// a thread ran for the timer ticks set by `TCBSetCPULimit`
+    #[cfg(feature = "ENABLE_MCS")]
+    Timeout = 5,
+    VMFault = 5 + MCS_FAULTS,
+    /// Raised only on aarch64 with `ENABLE_ARM_HYP`, when a guest completed a virtual interrupt
+    /// that asked for a maintenance interrupt
+    VGICMaintenance = 6 + MCS_FAULTS,
+    /// Raised only with `ENABLE_ARM_HYP` or `ENABLE_RISCV_HYP`, for an exception of a thread with a
+    /// VCPU the kernel does not handle itself, like an SMC or an SBI call. It gives the syndrome,
+    /// on riscv64 `scause` and `stval`, for the VMM to emulate it
+    VCPUFault = 7 + MCS_FAULTS,
+    /// The number of `seL4_Fault_VPPIEvent` of libsel4 on aarch64, which reL4 never raises. It is
+    /// kept free so the fault types after it do not take it
+    VPPIEvent = 8 + MCS_FAULTS,
+    /// Not in seL4, raised only with `ENABLE_ARM_HYP` or `ENABLE_RISCV_HYP`, instead of `VMFault`
+    /// for a data abort or guest page fault of a thread with a VCPU. It gives the IPA and the
+    /// decoded access, for MMIO emulation. On riscv64 the ISS is the transformed instruction from
+    /// `htinst`
+    GuestVMFault = 9 + MCS_FAULTS,
+    /// Not in seL4, raised only on aarch64 with `ENABLE_MMIO_EMULATION`, instead of `VMFault` for
+    /// a data abort in an emulated MMIO region. It goes to the endpoint of the region and gives
+    /// the decoded access, the reply gives the data of a load and resumes the thread after the
+    /// instruction
+    MMIOFault = 10 + MCS_FAULTS,
+    /// Not in seL4 without `ENABLE_MCS`, raised only with `ENABLE_CPU_LIMIT` when a thread ran
+    /// for the timer ticks set by `TCBSetCPULimit`
+    #[cfg(not(feature = "ENABLE_MCS"))]
+    Timeout = 11,
 }
 
 impl seL4_Fault_t {
@@ -88,13 +164,31 @@ pub const seL4_Fault_CapFault: usize = FaultType::CapFault as usize;
 pub const seL4_Fault_UnknownSyscall: usize = FaultType::UnknownSyscall as usize;
 pub const seL4_Fault_UserException: usize = FaultType::UserException as usize;
 pub const seL4_Fault_VMFault: usize = FaultType::VMFault as usize;
+pub const seL4_Fault_Timeout: usize = FaultType::Timeout as usize;
+pub const seL4_Fault_VGICMaintenance: usize = FaultType::VGICMaintenance as usize;
+pub const seL4_Fault_GuestVMFault: usize = FaultType::GuestVMFault as usize;
+pub const seL4_Fault_VCPUFault: usize = FaultType::VCPUFault as usize;
+pub const seL4_Fault_MMIOFault: usize = FaultType::MMIOFault as usize;
 
 //seL4_VMFault_Msg
 pub const seL4_VMFault_IP: usize = 0;
 pub const seL4_VMFault_Addr: usize = 1;
 pub const seL4_VMFault_PrefetchFault: usize = 2;
 pub const seL4_VMFault_FSR: usize = 3;
+#[cfg(target_arch = "riscv64")]
 pub const seL4_VMFault_Length: usize = 4;
+// aarch64 appends the access of a data abort as decoded from the syndrome, not in seL4. The width
+// is in bytes, 0 if the syndrome is not valid and the pager has to decode the instruction itself
+#[cfg(target_arch = "aarch64")]
+pub const seL4_VMFault_Width: usize = 4;
+#[cfg(target_arch = "aarch64")]
+pub const seL4_VMFault_SignExtend: usize = 5;
+#[cfg(target_arch = "aarch64")]
+pub const seL4_VMFault_Register: usize = 6;
+#[cfg(target_arch = "aarch64")]
+pub const seL4_VMFault_Write: usize = 7;
+#[cfg(target_arch = "aarch64")]
+pub const seL4_VMFault_Length: usize = 8;
 
 pub const seL4_CapFault_IP: usize = 0;
 pub const seL4_CapFault_Addr: usize = 1;
@@ -105,6 +199,41 @@ pub const seL4_CapFault_DepthMismatch_BitsFound: usize = 5;
 pub const seL4_CapFault_GuardMismatch_GuardFound: usize = seL4_CapFault_DepthMismatch_BitsFound;
 pub const seL4_CapFault_GuardMismatch_BitsFound: usize = 6;
 
+//seL4_Timeout_Msg
+pub const seL4_Timeout_Data: usize = 0;
+pub const seL4_Timeout_Consumed: usize = 1;
+pub const seL4_Timeout_Length: usize = 2;
+
+//seL4_VGICMaintenance_Msg
+pub const seL4_VGICMaintenance_IDX: usize = 0;
+pub const seL4_VGICMaintenance_Length: usize = 1;
+
+//seL4_GuestVMFault_Msg, not in seL4. The width is in bytes, 0 if the syndrome is not valid
+pub const seL4_GuestVMFault_IP: usize = 0;
+pub const seL4_GuestVMFault_IPA: usize = 1;
+pub const seL4_GuestVMFault_ISS: usize = 2;
+pub const seL4_GuestVMFault_Width: usize = 3;
+pub const seL4_GuestVMFault_Write: usize = 4;
+pub const seL4_GuestVMFault_Length: usize = 5;
+
+//seL4_VCPUFault_Msg, riscv64 adds the trap value
+pub const seL4_VCPUFault_HSR: usize = 0;
+#[cfg(target_arch = "aarch64")]
+pub const seL4_VCPUFault_Length: usize = 1;
+#[cfg(target_arch = "riscv64")]
+pub const seL4_VCPUFault_Data: usize = 1;
+#[cfg(target_arch = "riscv64")]
+pub const seL4_VCPUFault_Length: usize = 2;
+
+//seL4_MMIOFault_Msg, not in seL4. The data is the value stored by a write, 0 for a read. The reply
+//to a read carries the value loaded in its first message register
+pub const seL4_MMIOFault_IP: usize = 0;
+pub const seL4_MMIOFault_Addr: usize = 1;
+pub const seL4_MMIOFault_Width: usize = 2;
+pub const seL4_MMIOFault_Write: usize = 3;
+pub const seL4_MMIOFault_Data: usize = 4;
+pub const seL4_MMIOFault_Length: usize = 5;
+
 // lookup_fault
 #[derive(PartialEq, Eq, Debug)]
 pub enum LookupFaultType {
@@ -142,3 +271,36 @@ impl lookup_fault_t {
         unsafe { core::mem::transmute::<u8, LookupFaultType>(self.get_type() as u8) }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::FaultType::{self, *};
+
+    fn check(faults: &[(FaultType, usize)]) {
+        for (fault, value) in faults {
+            assert_eq!(*fault as usize, *value, "{:?}", fault);
+        }
+    }
+
+    /// The values of `seL4_FaultType` that libsel4 generates for the configuration under test.
+    #[test]
+    fn sel4_fault_type_values_test() {
+        let mcs = cfg!(feature = "ENABLE_MCS") as usize;
+        check(&[
+            (NullFault, 0),
+            (CapFault, 1),
+            (UnknownSyscall, 2),
+            (UserException, 3),
+            (VMFault, 5 + mcs),
+            (VGICMaintenance, 6 + mcs),
+            (VCPUFault, 7 + mcs),
+            (VPPIEvent, 8 + mcs),
+        ]);
+        // Not in seL4, after VPPIEvent, the last fault type of libsel4
+        check(&[(GuestVMFault, 9 + mcs), (MMIOFault, 10 + mcs)]);
+        #[cfg(not(feature = "ENABLE_MCS"))]
+        check(&[(Timeout, 11)]);
+        #[cfg(feature = "ENABLE_MCS")]
+        check(&[(Timeout, 5)]);
+    }
+}
@@ -8,6 +8,14 @@ pub struct kernel_stack_alloc_data {
 pub static mut kernel_stack_alloc: kernel_stack_alloc_data = kernel_stack_alloc_data {
     data: [[0_u8; BIT!(CONFIG_KERNEL_STACK_BITS)]; CONFIG_MAX_NUM_NODES],
 };
+
+/// The top of the kernel stack of `core`, every core has its own stack in `kernel_stack_alloc`.
+#[inline]
+pub fn kernel_stack_top(core: usize) -> usize {
+    assert!(core < CONFIG_MAX_NUM_NODES);
+    unsafe { kernel_stack_alloc.data[core].as_ptr() as usize + BIT!(CONFIG_KERNEL_STACK_BITS) }
+}
+
 #[cfg(feature = "ENABLE_SMP")]
 /// This function is used to map the core.
 extern "C" {
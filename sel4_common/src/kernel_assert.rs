@@ -0,0 +1,28 @@
+//! Numbered kernel invariant assertions.
+//!
+//! `kernel_assert!(id, cond)` panics with `KERNEL ASSERT <id>`, the condition and its location
+//! when `cond` is false in a debug build. In a release build the condition is still type checked
+//! but never evaluated, so it costs nothing. An id names one assertion, grep for
+//! `kernel_assert!(<id>,` to find it. Ids are grouped by module:
+//!
+//! | Range | Module                 |
+//! |-------|------------------------|
+//! | 1xxx  | IPC (`sel4_ipc`)       |
+//! | 2xxx  | scheduler (`sel4_task`)|
+//! | 3xxx  | cspace (`sel4_cspace`) |
+
+#[macro_export]
+/// Check a kernel invariant in debug builds, see the docs of the `kernel_assert` module.
+macro_rules! kernel_assert {
+    ($id: literal, $cond: expr) => {
+        if cfg!(debug_assertions) && !$cond {
+            panic!(
+                "KERNEL ASSERT {}: `{}` failed at {}:{}",
+                $id,
+                stringify!($cond),
+                file!(),
+                line!()
+            );
+        }
+    };
+}
@@ -0,0 +1,76 @@
+//! Kernel info page, enabled by `ENABLE_KERNEL_INFO_PAGE`.
+//!
+//! A single page of monotonically increasing event counters that the kernel bumps as it runs.
+//! At boot the root task receives a read-only frame cap to this page (reported through
+//! `sharedFrames` in the bootinfo), so a monitoring agent can map it and read coarse health
+//! metrics without making any syscalls. Counters wrap on overflow and are only ever written by
+//! the kernel.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::sel4_config::CONFIG_MAX_NUM_NODES;
+
+/// Bumped whenever the layout of [`kernel_info_page_t`] changes.
+pub const KERNEL_INFO_PAGE_VERSION: usize = 2;
+
+/// The layout user level sees when it maps the kernel info page.
+#[repr(C, align(4096))]
+pub struct kernel_info_page_t {
+    pub version: usize,
+    /// Switches to a different thread, through the scheduler or the fastpath.
+    pub context_switches: AtomicUsize,
+    /// IPC message transfers, including fault and reply messages.
+    pub ipc_count: AtomicUsize,
+    /// Interrupts handled by the kernel, including timer interrupts.
+    pub irq_count: AtomicUsize,
+    /// Kernel timer ticks.
+    pub ticks: AtomicUsize,
+    /// Time each core spent in its idle thread, in timer counts, up to its last timer tick or
+    /// switch away from idle. `SysBenchmarkResetLog` clears it. Added in version 2.
+    pub idle_time: [AtomicUsize; CONFIG_MAX_NUM_NODES],
+}
+
+#[no_mangle]
+pub static ksKernelInfoPage: kernel_info_page_t = kernel_info_page_t {
+    version: KERNEL_INFO_PAGE_VERSION,
+    context_switches: AtomicUsize::new(0),
+    ipc_count: AtomicUsize::new(0),
+    irq_count: AtomicUsize::new(0),
+    ticks: AtomicUsize::new(0),
+    idle_time: {
+        const INIT: AtomicUsize = AtomicUsize::new(0);
+        [INIT; CONFIG_MAX_NUM_NODES]
+    },
+};
+
+#[inline]
+pub fn kernel_info_count_context_switch() {
+    ksKernelInfoPage
+        .context_switches
+        .fetch_add(1, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn kernel_info_count_ipc() {
+    ksKernelInfoPage.ipc_count.fetch_add(1, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn kernel_info_count_irq() {
+    ksKernelInfoPage.irq_count.fetch_add(1, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn kernel_info_count_tick() {
+    ksKernelInfoPage.ticks.fetch_add(1, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn kernel_info_set_idle_time(core: usize, time: usize) {
+    ksKernelInfoPage.idle_time[core].store(time, Ordering::Relaxed);
+}
+
+/// Kernel virtual address of the kernel info page.
+pub fn kernel_info_page_addr() -> usize {
+    &ksKernelInfoPage as *const kernel_info_page_t as usize
+}
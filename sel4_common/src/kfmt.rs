@@ -0,0 +1,189 @@
+//! A heapless formatter for the kernel console, which does not use `core::fmt`.
+//!
+//! [`kprint!`] and [`kprintln!`] take a list of values and write each to the console in turn,
+//! strings as they are, integers in decimal and [`hex_t`] or [`hex_pad_t`] in hexadecimal:
+//!
+//! ```ignore
+//! kprintln!("Panicked at ", location.file(), ":", location.line());
+//! kprint!("cap ", hex_t(cptr), " byte ", hex_pad_t(b as usize, 2));
+//! ```
+//!
+//! The logger and the panic handler use it with the `ENABLE_COMPACT_LOG` feature, for images that
+//! must be small, to keep the `core::fmt` machinery out of those paths. Messages with arguments
+//! are then reduced to the file and line they were logged at, so the default build formats them
+//! in full with `core::fmt`, as `print!` and `println!` always do.
+
+use crate::console::console_putchar;
+
+/// A value [`kprint!`] can write.
+pub trait console_fmt_t {
+    fn console_put(&self);
+}
+
+/// `value` in hexadecimal with a `0x` prefix
+pub struct hex_t(pub usize);
+
+/// `.0` in hexadecimal without prefix, zero padded to `.1` digits
+pub struct hex_pad_t(pub usize, pub usize);
+
+/// The decimal digits of `value`, at the end of `buf`.
+pub fn format_dec(mut value: u64, buf: &mut [u8; 20]) -> &[u8] {
+    let mut start = buf.len();
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            return &buf[start..];
+        }
+    }
+}
+
+/// The hexadecimal digits of `value`, at least `width` of them, at the end of `buf`.
+pub fn format_hex(mut value: u64, width: usize, buf: &mut [u8; 16]) -> &[u8] {
+    let mut start = buf.len();
+    while start > 0 && (value != 0 || start == buf.len() || buf.len() - start < width) {
+        start -= 1;
+        buf[start] = b"0123456789abcdef"[(value & 0xf) as usize];
+        value >>= 4;
+    }
+    &buf[start..]
+}
+
+fn put_bytes(bytes: &[u8]) {
+    for &c in bytes {
+        console_putchar(c);
+    }
+}
+
+impl console_fmt_t for str {
+    fn console_put(&self) {
+        put_bytes(self.as_bytes());
+    }
+}
+
+impl console_fmt_t for bool {
+    fn console_put(&self) {
+        put_bytes(if *self { b"true" } else { b"false" });
+    }
+}
+
+impl<T: console_fmt_t + ?Sized> console_fmt_t for &T {
+    fn console_put(&self) {
+        (**self).console_put();
+    }
+}
+
+macro_rules! impl_unsigned {
+    ($($t:ty),*) => {$(
+        impl console_fmt_t for $t {
+            fn console_put(&self) {
+                put_bytes(format_dec(*self as u64, &mut [0; 20]));
+            }
+        }
+    )*};
+}
+
+macro_rules! impl_signed {
+    ($($t:ty),*) => {$(
+        impl console_fmt_t for $t {
+            fn console_put(&self) {
+                if *self < 0 {
+                    console_putchar(b'-');
+                }
+                put_bytes(format_dec(self.unsigned_abs() as u64, &mut [0; 20]));
+            }
+        }
+    )*};
+}
+
+impl_unsigned!(u8, u16, u32, u64, usize);
+impl_signed!(i8, i16, i32, i64, isize);
+
+impl console_fmt_t for hex_t {
+    fn console_put(&self) {
+        put_bytes(b"0x");
+        put_bytes(format_hex(self.0 as u64, 1, &mut [0; 16]));
+    }
+}
+
+impl console_fmt_t for hex_pad_t {
+    fn console_put(&self) {
+        put_bytes(format_hex(self.0 as u64, self.1, &mut [0; 16]));
+    }
+}
+
+/// Write each value to the console, see the module docs.
+#[macro_export]
+macro_rules! kprint {
+    ($($item:expr),* $(,)?) => {{
+        $( $crate::kfmt::console_fmt_t::console_put(&$item); )*
+    }};
+}
+
+/// [`kprint!`] followed by a newline.
+#[macro_export]
+macro_rules! kprintln {
+    ($($item:expr),* $(,)?) => {{
+        $crate::kprint!($($item),*);
+        $crate::console::console_putchar(b'\n');
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_dec_test() {
+        assert_eq!(format_dec(0, &mut [0; 20]), b"0");
+        assert_eq!(format_dec(1907, &mut [0; 20]), b"1907");
+        assert_eq!(format_dec(u64::MAX, &mut [0; 20]), b"18446744073709551615");
+    }
+
+    #[test]
+    fn format_hex_test() {
+        assert_eq!(format_hex(0, 1, &mut [0; 16]), b"0");
+        assert_eq!(format_hex(0xbeef, 1, &mut [0; 16]), b"beef");
+        assert_eq!(format_hex(0xa, 2, &mut [0; 16]), b"0a");
+        assert_eq!(format_hex(u64::MAX, 1, &mut [0; 16]), b"ffffffffffffffff");
+    }
+}
+
+#[cfg(kani)]
+mod verification {
+    use super::*;
+
+    /// Parse the digits of `text` in `radix` back into a value, none if one is not a digit.
+    fn parse(text: &[u8], radix: u32) -> Option<u64> {
+        let mut value: u64 = 0;
+        for &digit in text {
+            let digit = (digit as char).to_digit(radix)?;
+            value = value.checked_mul(radix as u64)?.checked_add(digit as u64)?;
+        }
+        Some(value)
+    }
+
+    #[kani::proof]
+    #[kani::unwind(21)]
+    fn format_dec_proof() {
+        let value: u64 = kani::any();
+        let mut buf = [0; 20];
+        let text = format_dec(value, &mut buf);
+        assert!(!text.is_empty());
+        assert!(text.len() == 1 || text[0] != b'0');
+        assert_eq!(parse(text, 10), Some(value));
+    }
+
+    #[kani::proof]
+    #[kani::unwind(17)]
+    fn format_hex_proof() {
+        let value: u64 = kani::any();
+        let width: usize = kani::any();
+        let mut buf = [0; 16];
+        let text = format_hex(value, width, &mut buf);
+        assert!(text.len() >= width.clamp(1, 16));
+        assert!(text.len() <= width.max(1) || text[0] != b'0');
+        assert_eq!(parse(text, 16), Some(value));
+    }
+}
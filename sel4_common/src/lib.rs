@@ -7,18 +7,40 @@
 #![allow(non_upper_case_globals)]
 #![allow(clippy::enum_clike_unportable_variant)]
 #![allow(clippy::not_unsafe_ptr_arg_deref)]
+#![deny(unsafe_op_in_unsafe_fn)]
+
+// Caps, page table entries and the other bitfields are read as little-endian words by user level
+// and the hardware, nothing converts them for a big-endian core.
+#[cfg(target_endian = "big")]
+compile_error!("rel4 only supports little-endian cores");
+
+// The thread ID has to live where user mode can read but not write it. aarch64 has TPIDRRO_EL0,
+// riscv64 has no such register and the IPC buffer is writable by the thread, so it is unsupported.
+#[cfg(all(feature = "ENABLE_THREAD_ID", not(target_arch = "aarch64")))]
+compile_error!("ENABLE_THREAD_ID is only supported on aarch64");
+
 pub mod arch;
 pub mod cap_rights;
 pub mod console;
 pub mod fault;
 pub mod ffi;
+pub mod kernel_assert;
+#[cfg(feature = "ENABLE_KERNEL_INFO_PAGE")]
+pub mod kernel_info;
+pub mod kfmt;
 pub mod logging;
 pub mod message_info;
+pub mod mmio;
 pub mod object;
+#[cfg(feature = "ENABLE_OBJECT_GEN")]
+pub mod object_gen;
+pub mod object_ref;
 pub mod sel4_config;
 #[cfg(feature = "ENABLE_SMP")]
 pub mod smp;
 pub mod structures;
+pub mod sysreg;
+pub mod timer;
 pub mod utils;
 pub mod vm_rights;
 
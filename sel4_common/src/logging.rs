@@ -9,7 +9,10 @@
 use log::{self, Level, LevelFilter, Log, Metadata, Record};
 
 use super::utils::cpu_id;
+#[cfg(not(feature = "ENABLE_COMPACT_LOG"))]
 use crate::println;
+#[cfg(feature = "ENABLE_COMPACT_LOG")]
+use crate::{kprint, kprintln};
 
 use spin::Mutex;
 
@@ -32,6 +35,7 @@ impl Log for SimpleLogger {
             Level::Debug => 32, // Green
             Level::Trace => 90, // BrightBlack
         };
+        #[cfg(not(feature = "ENABLE_COMPACT_LOG"))]
         println!(
             "\u{1B}[{}m[{:>5} {}] {}\u{1B}[0m",
             color,
@@ -39,6 +43,28 @@ impl Log for SimpleLogger {
             cpu_id(),
             record.args(),
         );
+        // A compact image leaves out `core::fmt`, so only messages without arguments can be
+        // printed, the others are replaced by where they were logged
+        #[cfg(feature = "ENABLE_COMPACT_LOG")]
+        {
+            let level = match record.level() {
+                Level::Error => "ERROR",
+                Level::Warn => " WARN",
+                Level::Info => " INFO",
+                Level::Debug => "DEBUG",
+                Level::Trace => "TRACE",
+            };
+            kprint!("\u{1B}[", color, "m[", level, " ", cpu_id(), "] ");
+            match record.args().as_str() {
+                Some(message) => kprint!(message),
+                None => kprint!(
+                    record.file().unwrap_or("?"),
+                    ":",
+                    record.line().unwrap_or(0)
+                ),
+            }
+            kprintln!("\u{1B}[0m");
+        }
     }
     fn flush(&self) {
         let _lock = LOG_MUTEX.lock();
@@ -46,6 +72,7 @@ impl Log for SimpleLogger {
 }
 
 pub fn init() {
+    crate::console::init();
     static LOGGER: SimpleLogger = SimpleLogger;
     log::set_logger(&LOGGER).unwrap();
     log::set_max_level(match option_env!("LOG") {
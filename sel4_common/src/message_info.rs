@@ -56,9 +56,69 @@ impl seL4_MessageInfo_t {
         self.words[0]
     }
 
-    /// Gets the label of the message.
+    /// Gets the label of the message, `InvalidInvocation` if the kernel does not know it.
     #[inline]
     pub fn get_label(&self) -> MessageLabel {
-        unsafe { core::mem::transmute::<u32, MessageLabel>(self.get_usize_label() as u32) }
+        MessageLabel::from_usize(self.get_usize_label()).unwrap_or(MessageLabel::InvalidInvocation)
+    }
+}
+
+impl MessageLabel {
+    /// Converts a raw label, `None` if it lies outside both the seL4 and the extension range.
+    #[inline]
+    pub fn from_usize(label: usize) -> Option<Self> {
+        let extensions = MessageLabel::UntypedDescribe as usize
+            ..MessageLabel::nExtensionInvocationLabels as usize;
+        if label < MessageLabel::nArchInvocationLabels as usize || extensions.contains(&label) {
+            Some(unsafe { core::mem::transmute::<u32, MessageLabel>(label as u32) })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sel4_config::seL4_MsgMaxExtraCaps;
+
+    #[test]
+    fn message_info_word_test() {
+        // seL4_MessageInfo_new(0x1234, 5, 3, 120) of libsel4
+        let info = seL4_MessageInfo_t::new(0x1234, 5, 3, 120);
+        assert_eq!(info.to_word(), 0x123_4bf8);
+
+        let info = seL4_MessageInfo_t::from_word(0x123_4bf8);
+        assert_eq!(info.get_usize_label(), 0x1234);
+        assert_eq!(info.get_caps_unwrapped(), 5);
+        assert_eq!(info.get_extra_caps(), 3);
+        assert_eq!(info.get_length(), 120);
+    }
+
+    #[test]
+    fn message_info_label_test() {
+        let info = seL4_MessageInfo_t::new(MessageLabel::UntypedRetype as usize, 0, 0, 0);
+        assert_eq!(info.get_label(), MessageLabel::UntypedRetype);
+        let info = seL4_MessageInfo_t::new(MessageLabel::UntypedDescribe as usize, 0, 0, 0);
+        assert_eq!(info.get_label(), MessageLabel::UntypedDescribe);
+        // Between the seL4 and the extension range, and past the extension range
+        let info = seL4_MessageInfo_t::new(MessageLabel::nArchInvocationLabels as usize, 0, 0, 0);
+        assert_eq!(info.get_label(), MessageLabel::InvalidInvocation);
+        let label = MessageLabel::nExtensionInvocationLabels as usize;
+        assert_eq!(MessageLabel::from_usize(label), None);
+    }
+
+    #[test]
+    fn message_info_truncation_test() {
+        // The length field holds up to 127, more than the buffer has words for
+        let info = seL4_MessageInfo_t::from_word(0x7f);
+        assert_eq!(info.get_length(), 0x7f);
+        let info = seL4_MessageInfo_t::from_word_security(0x7f);
+        assert_eq!(info.get_length(), seL4_MsgMaxLength);
+        assert_eq!(info.get_extra_caps(), 0);
+
+        // Every extra cap count the field can hold is valid
+        let info = seL4_MessageInfo_t::from_word_security(0x3 << 7);
+        assert_eq!(info.get_extra_caps(), seL4_MsgMaxExtraCaps);
     }
 }
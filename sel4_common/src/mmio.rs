@@ -0,0 +1,33 @@
+//! Device registers at fixed kernel addresses, the one place that turns such an address into a
+//! reference to registers.
+//!
+//! A driver describes the registers of a device frame with `tock_registers::register_structs!`
+//! and keeps a static [`mmio_t`] of the frame, which `map_kernel_devices` maps before the driver
+//! first runs. Every access goes through the register types, which read and write volatile.
+
+use core::ptr::NonNull;
+
+/// The registers `T` of a device frame at a kernel virtual address
+pub struct mmio_t<T> {
+    base: NonNull<T>,
+}
+
+// The registers are shared by all cores, the drivers serialise their accesses like those of the
+// other kernel state
+unsafe impl<T> Send for mmio_t<T> {}
+unsafe impl<T> Sync for mmio_t<T> {}
+
+impl<T> mmio_t<T> {
+    /// The registers at `base`, the kernel address the frame of a device with the layout `T` is
+    /// mapped at.
+    pub const fn new(base: usize) -> Self {
+        assert!(base != 0 && base % core::mem::align_of::<T>() == 0);
+        Self {
+            base: unsafe { NonNull::new_unchecked(base as *mut T) },
+        }
+    }
+
+    pub const fn regs(&self) -> &T {
+        unsafe { self.base.as_ref() }
+    }
+}
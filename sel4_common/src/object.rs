@@ -7,12 +7,19 @@ use crate::arch::ObjectType;
 
 use super::sel4_config::*;
 
-#[cfg(target_arch = "riscv64")]
+#[cfg(all(target_arch = "riscv64", not(feature = "ENABLE_RISCV_HYP")))]
 pub const seL4_ObjectTypeCount: usize = ObjectType::PageTableObject as usize + 1;
+#[cfg(all(target_arch = "riscv64", feature = "ENABLE_RISCV_HYP"))]
+pub const seL4_ObjectTypeCount: usize = ObjectType::RISCV_VCPUObject as usize + 1;
 // FIXED: Need to add 1 to cover all possible object types
-#[cfg(any(target_arch = "aarch64", test))]
+#[cfg(all(any(target_arch = "aarch64", test), not(feature = "ENABLE_ARM_HYP")))]
 pub const seL4_ObjectTypeCount: usize = ObjectType::seL4_ARM_PageDirectoryObject as usize + 1;
+#[cfg(all(any(target_arch = "aarch64", test), feature = "ENABLE_ARM_HYP"))]
+pub const seL4_ObjectTypeCount: usize = ObjectType::seL4_ARM_VCPUObject as usize + 1;
+#[cfg(not(feature = "ENABLE_MCS"))]
 pub const seL4_NonArchObjectTypeCount: usize = ObjectType::CapTableObject as usize + 1;
+#[cfg(feature = "ENABLE_MCS")]
+pub const seL4_NonArchObjectTypeCount: usize = ObjectType::ReplyObject as usize + 1;
 
 impl ObjectType {
     /// Returns the size of the object based on its type.
@@ -34,6 +41,10 @@ impl ObjectType {
             ObjectType::EndpointObject => seL4_EndpointBits,
             ObjectType::NotificationObject => seL4_NotificationBits,
             ObjectType::CapTableObject => seL4_SlotBits + user_object_size,
+            #[cfg(feature = "ENABLE_MCS")]
+            ObjectType::SchedContextObject => user_object_size,
+            #[cfg(feature = "ENABLE_MCS")]
+            ObjectType::ReplyObject => seL4_ReplyBits,
             _ => panic!("unsupported cap type:{}", (*self) as usize),
         }
     }
@@ -0,0 +1,179 @@
+//! Per-object generation tags for catching use-after-revoke, enabled by `ENABLE_OBJECT_GEN`.
+//!
+//! Every kernel object created by retype is stamped with a fresh generation and marked live; when
+//! its final cap is finalised the object is marked dead. [`convert_to_checked_mut_type_ref`]
+//! panics on a dead object, so a stale pointer is caught on first use instead of surfacing later
+//! as corruption. Code that keeps raw object pointers around can also save the generation from
+//! [`object_gen_get`] and compare it before use, which catches an object that has been recreated
+//! at the same address.
+//!
+//! Objects which were never stamped (e.g. those created at boot) are not tracked.
+//!
+//! The table is open addressed with linear probing, but an object is only ever placed within
+//! [`OBJECT_GEN_MAX_PROBE`] entries of its home, so a lookup on the fastpath touches a bounded
+//! number of entries. Entries are freed when the untyped memory holding their object is reset,
+//! see [`object_gen_forget`]; when the probe window of a new object is full, the oldest dead entry
+//! in it is reused, which only loses the use-after-revoke check for that dead object.
+
+use crate::object_ref::convert_to_mut_type_ref;
+
+/// Number of objects that can be tracked at the same time.
+const OBJECT_GEN_TABLE_SIZE: usize = 4096;
+
+/// Maximum distance of an entry from its home index.
+const OBJECT_GEN_MAX_PROBE: usize = 16;
+
+#[derive(Clone, Copy)]
+struct object_gen_entry_t {
+    ptr: usize,
+    generation: usize,
+    live: bool,
+}
+
+const EMPTY_ENTRY: object_gen_entry_t = object_gen_entry_t {
+    ptr: 0,
+    generation: 0,
+    live: false,
+};
+
+static mut ksObjectGenTable: [object_gen_entry_t; OBJECT_GEN_TABLE_SIZE] =
+    [EMPTY_ENTRY; OBJECT_GEN_TABLE_SIZE];
+static mut ksObjectGenCounter: usize = 0;
+
+fn object_gen_home(ptr: usize) -> usize {
+    (ptr >> 4) % OBJECT_GEN_TABLE_SIZE
+}
+
+/// Find the entry of `ptr`, or the entry where it would be inserted: the first empty one, or
+/// failing that the dead one with the oldest generation in the probe window.
+fn object_gen_find(ptr: usize) -> Option<&'static mut object_gen_entry_t> {
+    let table = unsafe { &mut ksObjectGenTable };
+    let home = object_gen_home(ptr);
+    let mut oldest_dead: Option<usize> = None;
+    for i in 0..OBJECT_GEN_MAX_PROBE {
+        let index = (home + i) % OBJECT_GEN_TABLE_SIZE;
+        let entry = &table[index];
+        if entry.ptr == ptr || entry.ptr == 0 {
+            return Some(&mut table[index]);
+        }
+        let older = match oldest_dead {
+            Some(j) => entry.generation < table[j].generation,
+            None => true,
+        };
+        if !entry.live && older {
+            oldest_dead = Some(index);
+        }
+    }
+    oldest_dead.map(|index| &mut table[index])
+}
+
+/// Free the entry at `index`, moving later entries of the same cluster back so that every entry
+/// stays reachable from its home.
+fn object_gen_remove(table: &mut [object_gen_entry_t; OBJECT_GEN_TABLE_SIZE], index: usize) {
+    let mut hole = index;
+    let mut next = (hole + 1) % OBJECT_GEN_TABLE_SIZE;
+    while next != hole && table[next].ptr != 0 {
+        let home = object_gen_home(table[next].ptr);
+        // Move the entry into the hole unless its home lies after the hole
+        let distance = (next + OBJECT_GEN_TABLE_SIZE - home) % OBJECT_GEN_TABLE_SIZE;
+        let gap = (next + OBJECT_GEN_TABLE_SIZE - hole) % OBJECT_GEN_TABLE_SIZE;
+        if distance >= gap {
+            table[hole] = table[next];
+            hole = next;
+        }
+        next = (next + 1) % OBJECT_GEN_TABLE_SIZE;
+    }
+    table[hole] = EMPTY_ENTRY;
+}
+
+/// Forget every object in `[base, base + size)`, called when that untyped memory is reset and
+/// can be retyped again.
+pub fn object_gen_forget(base: usize, size: usize) {
+    let table = unsafe { &mut ksObjectGenTable };
+    let mut index = 0;
+    while index < OBJECT_GEN_TABLE_SIZE {
+        let ptr = table[index].ptr;
+        if ptr != 0 && ptr >= base && ptr - base < size {
+            // Check the same index again, an entry may have moved into it
+            object_gen_remove(table, index);
+        } else {
+            index += 1;
+        }
+    }
+}
+
+/// Stamp the object at `ptr` with a new generation, called when it is created by retype.
+pub fn object_gen_stamp(ptr: usize) {
+    match object_gen_find(ptr) {
+        Some(entry) => unsafe {
+            ksObjectGenCounter += 1;
+            *entry = object_gen_entry_t {
+                ptr,
+                generation: ksObjectGenCounter,
+                live: true,
+            };
+        },
+        None => log::warn!("object_gen: probe window full, {:#x} is not tracked", ptr),
+    }
+}
+
+/// Mark the object at `ptr` as dead, called when its final cap is finalised.
+pub fn object_gen_kill(ptr: usize) {
+    if let Some(entry) = object_gen_find(ptr) {
+        if entry.ptr == ptr {
+            entry.live = false;
+        }
+    }
+}
+
+/// Get the generation of the live object at `ptr`, `None` if it is dead or not tracked.
+pub fn object_gen_get(ptr: usize) -> Option<usize> {
+    match object_gen_find(ptr) {
+        Some(entry) if entry.ptr == ptr && entry.live => Some(entry.generation),
+        _ => None,
+    }
+}
+
+/// Like [`convert_to_mut_type_ref`], but panics if `addr` points to an object that was revoked.
+#[inline]
+pub fn convert_to_checked_mut_type_ref<T>(addr: usize) -> &'static mut T {
+    if let Some(entry) = object_gen_find(addr) {
+        if entry.ptr == addr && !entry.live {
+            panic!(
+                "use of revoked object {:#x} (generation {})",
+                addr, entry.generation
+            );
+        }
+    }
+    convert_to_mut_type_ref::<T>(addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn object_gen_table_test() {
+        // Objects sharing a home index fill its probe window
+        let ptr = |i: usize| 0x1000_0000 + ((i * OBJECT_GEN_TABLE_SIZE) << 4);
+        for i in 0..OBJECT_GEN_MAX_PROBE {
+            object_gen_stamp(ptr(i));
+        }
+        object_gen_stamp(ptr(OBJECT_GEN_MAX_PROBE));
+        assert_eq!(object_gen_get(ptr(OBJECT_GEN_MAX_PROBE)), None);
+
+        // A dead entry is reused once the window is full
+        object_gen_kill(ptr(3));
+        object_gen_stamp(ptr(OBJECT_GEN_MAX_PROBE));
+        assert!(object_gen_get(ptr(OBJECT_GEN_MAX_PROBE)).is_some());
+        assert_eq!(object_gen_get(ptr(3)), None);
+
+        // Freeing a range keeps the rest of the cluster reachable
+        object_gen_forget(ptr(0), ptr(8) - ptr(0));
+        for i in 8..=OBJECT_GEN_MAX_PROBE {
+            assert!(object_gen_get(ptr(i)).is_some());
+        }
+        object_gen_stamp(ptr(0));
+        assert!(object_gen_get(ptr(0)).is_some());
+    }
+}
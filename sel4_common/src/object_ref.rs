@@ -0,0 +1,102 @@
+//! Conversions from object pointers to references, the one place that turns the addresses kept
+//! in caps and kernel objects into references.
+//!
+//! Kernel objects live in memory retyped from untypeds and point to each other by address, e.g.
+//! the bound TCB and the queue of a notification. No object owns another, so the references
+//! handed out here are `'static` and it is up to the caller that the object is still there: the
+//! pointer was read from a cap or from an object that is kept consistent with its caps, like the
+//! queue of an endpoint. The crates wrap these conversions in typed accessors next to the field
+//! they read, like `notification_t::bound_tcb`, instead of converting the raw fields themselves.
+//!
+//! The conversions assert the pointer is not null, except the `_unsafe` one and the slice. The
+//! `checked` ones also catch a revoked object with `ENABLE_OBJECT_GEN`, see the `object_gen`
+//! module.
+
+use core::slice;
+
+#[inline]
+pub fn convert_ref_type_to_usize<T>(addr: &mut T) -> usize {
+    addr as *mut T as usize
+}
+
+#[inline]
+pub fn convert_to_type_ref<T>(addr: usize) -> &'static T {
+    assert_ne!(addr, 0);
+    unsafe { &*(addr as *mut T) }
+}
+
+#[inline]
+pub fn convert_to_mut_type_ref<T>(addr: usize) -> &'static mut T {
+    assert_ne!(addr, 0);
+    unsafe { &mut *(addr as *mut T) }
+}
+
+/// Convert an object pointer taken from a cap to a mutable reference.
+///
+/// With `ENABLE_OBJECT_GEN` this also checks the object has not been revoked.
+#[cfg(not(feature = "ENABLE_OBJECT_GEN"))]
+#[inline]
+pub fn convert_to_checked_mut_type_ref<T>(addr: usize) -> &'static mut T {
+    convert_to_mut_type_ref::<T>(addr)
+}
+
+#[cfg(feature = "ENABLE_OBJECT_GEN")]
+pub use crate::object_gen::convert_to_checked_mut_type_ref;
+
+#[inline]
+pub fn convert_to_mut_type_ptr<T>(addr: usize) -> *mut T {
+    assert_ne!(addr, 0);
+    addr as *mut T
+}
+
+#[inline]
+pub fn convert_to_mut_type_ref_unsafe<T>(addr: usize) -> &'static mut T {
+    unsafe { &mut *(addr as *mut T) }
+}
+
+#[inline]
+pub fn convert_to_option_type_ref<T>(addr: usize) -> Option<&'static T> {
+    if addr == 0 {
+        return None;
+    }
+    Some(convert_to_type_ref::<T>(addr))
+}
+
+#[inline]
+pub fn convert_to_option_mut_type_ref<T>(addr: usize) -> Option<&'static mut T> {
+    if addr == 0 {
+        return None;
+    }
+    Some(convert_to_mut_type_ref::<T>(addr))
+}
+
+/// Get the slice through passed arguments
+///
+/// addr: The address of the slice
+/// len: The length of the slice
+#[inline]
+pub fn convert_to_mut_slice<T>(addr: usize, len: usize) -> &'static mut [T] {
+    unsafe { slice::from_raw_parts_mut(addr as _, len) }
+}
+
+/// Convert a ptr to a reference
+#[inline]
+pub fn ptr_to_ref<T>(ptr: *const T) -> &'static T {
+    unsafe { ptr.as_ref().unwrap() }
+}
+
+/// Convert a ptr to a mutable reference
+#[inline]
+pub fn ptr_to_mut<T>(ptr: *mut T) -> &'static mut T {
+    unsafe { ptr.as_mut().unwrap() }
+}
+
+/// Like [`convert_to_option_mut_type_ref`], but checks the object has not been revoked, see
+/// [`convert_to_checked_mut_type_ref`].
+#[inline]
+pub fn convert_to_option_checked_mut_type_ref<T>(addr: usize) -> Option<&'static mut T> {
+    if addr == 0 {
+        return None;
+    }
+    Some(convert_to_checked_mut_type_ref::<T>(addr))
+}
@@ -6,9 +6,15 @@ pub const wordBits: usize = BIT!(wordRadix);
 pub const seL4_EndpointBits: usize = 4;
 pub const seL4_NotificationBits: usize = 4;
 pub const seL4_SlotBits: usize = 5;
+#[cfg(not(feature = "ENABLE_MCS"))]
 pub const seL4_ReplyBits: usize = 4;
+/// A reply object, see `ENABLE_MCS`
+#[cfg(feature = "ENABLE_MCS")]
+pub const seL4_ReplyBits: usize = 5;
 pub const seL4_MinUntypedBits: usize = 4;
 pub const seL4_MaxUntypedBits: usize = 38;
+/// Smallest scheduling context, a header and at least two refills, see `ENABLE_MCS`
+pub const seL4_MinSchedContextBits: usize = 7;
 
 // page table relevant
 pub const PT_SIZE_BITS: usize = 12;
@@ -42,6 +48,13 @@ pub const seL4_PageTableBits: usize = 12;
 pub const seL4_PageDirBits: usize = 12;
 pub const seL4_PUDBits: usize = 12;
 pub const seL4_PGDBits: usize = 12;
+/// The size of a VCPU object, see `ENABLE_ARM_HYP`
+#[cfg(target_arch = "aarch64")]
+pub const seL4_VCPUBits: usize = 12;
+/// The size of a VCPU object, see `ENABLE_RISCV_HYP`. It starts with the 16KB root of the G-stage
+/// table of its guest.
+#[cfg(target_arch = "riscv64")]
+pub const seL4_VCPUBits: usize = 15;
 pub const seL4_HugePageBits: usize = 30;
 pub const seL4_LargePageBits: usize = 21;
 
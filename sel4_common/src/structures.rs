@@ -19,6 +19,8 @@ pub enum exception_t {
 #[repr(C)]
 #[derive(Copy, Clone)]
 /// Represents the IPC buffer used for inter-process communication in seL4.
+///
+/// The words are shared with user level as they are, in the little-endian order of the core.
 pub struct seL4_IPCBuffer {
     /// The tag field of the IPC message.
     pub tag: usize,
@@ -36,6 +38,19 @@ pub struct seL4_IPCBuffer {
     pub receiveDepth: usize,
 }
 
+// The layout of libsel4: the extra cap CPtrs, or the badges of unwrapped caps on receive, follow
+// `userData`, and the buffer fills its frame
+const _: () = {
+    use core::mem::{offset_of, size_of};
+    const WORD: usize = size_of::<usize>();
+    assert!(offset_of!(seL4_IPCBuffer, caps_or_badges) == (seL4_MsgMaxLength + 2) * WORD);
+    assert!(
+        offset_of!(seL4_IPCBuffer, receiveCNode)
+            == (seL4_MsgMaxLength + 2 + seL4_MsgMaxExtraCaps) * WORD
+    );
+    assert!(size_of::<seL4_IPCBuffer>() == 1 << seL4_IPCBufferSizeBits);
+};
+
 impl seL4_IPCBuffer {
     pub fn get_extra_cptr(&self, i: usize) -> usize {
         self.caps_or_badges[i]
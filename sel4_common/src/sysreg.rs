@@ -0,0 +1,134 @@
+//! Typed access to the system registers the kernel touches.
+//!
+//! Each register is a module generated by [`define_sysreg`] from one table entry: the instructions
+//! which read and write it and the layout of its fields. The tables are in `arch::sysreg`, so a
+//! field is named once, next to the architecture manual reference, instead of as a magic mask at
+//! every call site.
+//!
+//! ```ignore
+//! use sel4_common::arch::sysreg::sstatus;
+//!
+//! if sstatus::get(sstatus::SIE) != 0 { ... }
+//! unsafe { sstatus::write(sstatus::SUM.set(sstatus::read(), 1)) };
+//! ```
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// A field of a system register, `bits` wide at bit `offset`
+pub struct sysreg_field_t {
+    offset: usize,
+    bits: usize,
+}
+
+impl sysreg_field_t {
+    pub const fn new(offset: usize, bits: usize) -> Self {
+        Self { offset, bits }
+    }
+
+    /// The bits of the field in its place in the register
+    #[inline]
+    pub const fn mask(&self) -> usize {
+        (((1u128 << self.bits) - 1) as usize) << self.offset
+    }
+
+    /// Extract the field from the register value `reg`
+    #[inline]
+    pub const fn get(&self, reg: usize) -> usize {
+        (reg & self.mask()) >> self.offset
+    }
+
+    /// The field set to `value` and all other bits clear
+    #[inline]
+    pub const fn val(&self, value: usize) -> usize {
+        (value << self.offset) & self.mask()
+    }
+
+    /// The register value `reg` with the field replaced by `value`
+    #[inline]
+    pub const fn set(&self, reg: usize, value: usize) -> usize {
+        (reg & !self.mask()) | self.val(value)
+    }
+}
+
+/// Generate the module of a system register, see the module docs.
+///
+/// `read` and `write` are the asm templates which move the register from and to `{0}`. Registers
+/// which can set or clear bits atomically, like the CSRs of riscv, also take the templates of
+/// those instructions, which return the previous value in `{0}` and take the mask in `{1}`.
+macro_rules! define_sysreg {
+    (
+        $(#[$doc:meta])*
+        $name:ident, read $read:literal, write $write:literal
+        $(, set $set:literal, clear $clear:literal)? => {
+            $( $(#[$field_doc:meta])* $field:ident, $offset:expr, $bits:expr; )*
+        }
+    ) => {
+        $(#[$doc])*
+        pub mod $name {
+            use $crate::sysreg::sysreg_field_t;
+
+            $(
+                $(#[$field_doc])*
+                pub const $field: sysreg_field_t = sysreg_field_t::new($offset, $bits);
+            )*
+
+            /// Read the register
+            #[inline]
+            pub fn read() -> usize {
+                let value: usize;
+                unsafe {
+                    core::arch::asm!($read, out(reg) value);
+                }
+                value
+            }
+
+            /// Write the register
+            ///
+            /// # Safety
+            /// Changes the state of the core, e.g. the address space or the trap vector.
+            #[inline]
+            pub unsafe fn write(value: usize) {
+                unsafe { core::arch::asm!($write, in(reg) value) };
+            }
+
+            /// Read one field of the register
+            #[inline]
+            pub fn get(field: sysreg_field_t) -> usize {
+                field.get(read())
+            }
+
+            /// Replace one field of the register, leaving the others alone
+            ///
+            /// # Safety
+            /// See [`write`].
+            #[inline]
+            pub unsafe fn modify(field: sysreg_field_t, value: usize) {
+                unsafe { write(field.set(read(), value)) };
+            }
+
+            $(
+                /// Set the bits of `mask` atomically, returns the previous value
+                ///
+                /// # Safety
+                /// See [`write`].
+                #[inline]
+                pub unsafe fn set_bits(mask: usize) -> usize {
+                    let old: usize;
+                    unsafe { core::arch::asm!($set, out(reg) old, in(reg) mask) };
+                    old
+                }
+
+                /// Clear the bits of `mask` atomically, returns the previous value
+                ///
+                /// # Safety
+                /// See [`write`].
+                #[inline]
+                pub unsafe fn clear_bits(mask: usize) -> usize {
+                    let old: usize;
+                    unsafe { core::arch::asm!($clear, out(reg) old, in(reg) mask) };
+                    old
+                }
+            )?
+        }
+    };
+}
+pub(crate) use define_sysreg;
@@ -0,0 +1,21 @@
+//! The timer the kernel schedules with.
+//!
+//! Every core has a counter which runs at a fixed rate and a compare value which raises the timer
+//! interrupt of the core once the counter reaches it. [`KernelTimer`] is that pair, implemented by
+//! the timers of `arch::timer`, where `kernel_timer_t` is the one the kernel is built with. The
+//! kernel either programs a periodic tick with it or, with `ENABLE_TICKLESS`, the next time the
+//! scheduler has something to do.
+
+/// A per-core timer counting in cycles of the timer clock
+pub trait KernelTimer {
+    /// The count of the timer
+    fn now() -> usize;
+
+    /// Raise the timer interrupt of the current core once the count reaches `deadline`, at once
+    /// if it is already past. A pending timer interrupt for an earlier deadline is cleared.
+    /// [`TIMER_NEVER`] turns the interrupt off.
+    fn set_deadline(deadline: usize);
+}
+
+/// The deadline which is never reached
+pub const TIMER_NEVER: usize = usize::MAX;
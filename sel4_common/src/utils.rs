@@ -1,9 +1,12 @@
 //! Utility functions and macros.
-use core::slice;
-
+use crate::arch::config::PTR_FIELD_BITS;
 use crate::sel4_config::*;
+// The conversions used to live here, most of the kernel still imports them from `utils`
+pub use crate::object_ref::*;
 #[macro_export]
 /// Define a bitfield struct with the given name, total words, type index, type offset, type bits, and a list of variants.
+///
+/// The layout of the fields is checked at compile time, a field crossing a word fails the build.
 macro_rules! plus_define_bitfield {
     ($name:ident, $total_words:expr, $type_index:expr, $type_offset:expr, $type_bits:expr =>
         { $($variant:ident, $type_value:expr => { $($field:ident, $get_field:ident, $set_field:ident, $index:expr, $offset:expr, $bits:expr, $shift:expr, $sign_ext: expr),* }),* }) => {
@@ -58,6 +61,13 @@ macro_rules! plus_define_bitfield {
                 (self.words[$type_index] >> $type_offset) & ((1usize << $type_bits) - 1)
             }
         }
+
+        // Every field has to lie within one word of the struct
+        $($(
+            const _: () = assert!($index < $total_words && $offset + $bits <= usize::BITS as usize);
+        )*)*
+        const _: () =
+            assert!($type_index < $total_words && $type_offset + $type_bits <= usize::BITS as usize);
     };
 }
 
@@ -118,74 +128,23 @@ pub macro global_ops($expr: expr) {
     unsafe { $expr }
 }
 
-#[inline]
-pub fn MAX_FREE_INDEX(bits: usize) -> usize {
-    BIT!(bits - seL4_MinUntypedBits)
-}
-
-#[inline]
-pub fn convert_ref_type_to_usize<T>(addr: &mut T) -> usize {
-    addr as *mut T as usize
-}
-
-#[inline]
-pub fn convert_to_type_ref<T>(addr: usize) -> &'static T {
-    assert_ne!(addr, 0);
-    unsafe { &*(addr as *mut T) }
-}
-
-#[inline]
-pub fn convert_to_mut_type_ref<T>(addr: usize) -> &'static mut T {
-    assert_ne!(addr, 0);
-    unsafe { &mut *(addr as *mut T) }
-}
-
-#[inline]
-pub fn convert_to_mut_type_ptr<T>(addr: usize) -> *mut T {
-    assert_ne!(addr, 0);
-    addr as *mut T
-}
-
-#[inline]
-pub fn convert_to_mut_type_ref_unsafe<T>(addr: usize) -> &'static mut T {
-    unsafe { &mut *(addr as *mut T) }
-}
-
-#[inline]
-pub fn convert_to_option_type_ref<T>(addr: usize) -> Option<&'static T> {
-    if addr == 0 {
-        return None;
-    }
-    Some(convert_to_type_ref::<T>(addr))
-}
-
-#[inline]
-pub fn convert_to_option_mut_type_ref<T>(addr: usize) -> Option<&'static mut T> {
-    if addr == 0 {
-        return None;
-    }
-    Some(convert_to_mut_type_ref::<T>(addr))
+/// Whether `ptr` survives being stored in a sign-extended pointer field of
+/// `PTR_FIELD_BITS` bits, i.e. the bits above the field are copies of its top bit.
+pub const fn ptr_fits_field(ptr: usize) -> bool {
+    let shift = wordBits - PTR_FIELD_BITS;
+    (((ptr << shift) as isize) >> shift) as usize == ptr
 }
 
-/// Get the slice through passed arguments
-///
-/// addr: The address of the slice
-/// len: The length of the slice
-#[inline]
-pub fn convert_to_mut_slice<T>(addr: usize, len: usize) -> &'static mut [T] {
-    unsafe { slice::from_raw_parts_mut(addr as _, len) }
+/// Whether every pointer in `[start, end)` fits a pointer field, see [`ptr_fits_field`].
+/// The representable pointers are contiguous as signed values, so both ends are enough. A range
+/// of kernel pointers may wrap past zero as unsigned values, e.g. the device window.
+pub const fn ptr_range_fits_field(start: usize, end: usize) -> bool {
+    (start as isize) < (end as isize) && ptr_fits_field(start) && ptr_fits_field(end - 1)
 }
 
-/// Convert a ptr to a reference
 #[inline]
-pub fn ptr_to_ref<T>(ptr: *const T) -> &'static T {
-    unsafe { ptr.as_ref().unwrap() }
-}
-
-/// Convert a ptr to a mutable reference
-#[inline]
-pub fn ptr_to_mut<T>(ptr: *mut T) -> &'static mut T {
-    unsafe { ptr.as_mut().unwrap() }
+pub fn MAX_FREE_INDEX(bits: usize) -> usize {
+    BIT!(bits - seL4_MinUntypedBits)
 }
 
 #[inline]
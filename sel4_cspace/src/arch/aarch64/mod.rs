@@ -19,6 +19,10 @@ pub enum CapTag {
     CapIrqHandlerCap = 16,
     CapZombieCap = 18,
     CapDomainCap = 20,
+    CapSchedContextCap = 22,
+    CapSchedControlCap = 24,
+    /// reL4 extension, free with and without `ENABLE_MCS`
+    CapMemoryControlCap = 26,
     CapFrameCap = 1,
     CapPageTableCap = 3,
     CapPageDirectoryCap = 5,
@@ -26,6 +30,10 @@ pub enum CapTag {
     CapPageGlobalDirectoryCap = 9,
     CapASIDControlCap = 11,
     CapASIDPoolCap = 13,
+    /// Only created with `ENABLE_ARM_HYP`
+    CapVCPUCap = 15,
+    /// Only created with `ENABLE_ARM_SMC`
+    CapSMCCap = 17,
 }
 
 plus_define_bitfield! {
@@ -74,6 +82,14 @@ plus_define_bitfield! {
             capZombieType, get_zombie_type, set_zombie_type, 0, 0, 7, 0, false
         },
         new_domain_cap, CapTag::CapDomainCap as usize => {},
+        new_memory_control_cap, CapTag::CapMemoryControlCap as usize => {},
+        new_sched_context_cap, CapTag::CapSchedContextCap as usize => {
+            capSCSizeBits, get_sc_size_bits, set_sc_size_bits, 1, 0, 6, 0, false,
+            capSCPtr, get_sc_ptr, set_sc_ptr, 0, 0, 48, 0, true
+        },
+        new_sched_control_cap, CapTag::CapSchedControlCap as usize => {
+            core, get_sched_control_core, set_sched_control_core, 1, 0, 64, 0, false
+        },
         new_frame_cap, CapTag::CapFrameCap as usize => {
             capFIsDevice, get_frame_is_device,set_frame_is_device, 0, 6, 1, 0, false,
             capFVMRights,get_frame_vm_rights, set_frame_vm_rights, 0, 7, 2, 0, false,
@@ -111,11 +127,25 @@ plus_define_bitfield! {
             capASIDBase, get_asid_base, set_asid_base, 0, 43, 16, 0, false,
             // FIXED: asid_pool need to shift left 11 bits.
             capASIDPool, get_asid_pool, set_asid_pool, 0, 0, 37, 11, true
+        },
+        new_vcpu_cap, CapTag::CapVCPUCap as usize => {
+            capVCPUPtr, get_vcpu_ptr, set_vcpu_ptr, 0, 0, 48, 0, true
+        },
+        new_smc_cap, CapTag::CapSMCCap as usize => {
+            capSMCBadge, get_smc_badge, set_smc_badge, 1, 0, 64, 0, false
         }
     }
 }
 
 impl cap_t {
+    /// 是否为设备内存的frame cap
+    ///
+    /// Whether this is a frame cap to device memory, which must not be mapped cacheable.
+    #[inline]
+    pub fn is_device_frame(&self) -> bool {
+        self.get_cap_type() == CapTag::CapFrameCap && self.get_frame_is_device() != 0
+    }
+
     pub fn get_cap_ptr(&self) -> usize {
         match self.get_cap_type() {
             CapTag::CapUntypedCap => self.get_untyped_ptr(),
@@ -124,6 +154,9 @@ impl cap_t {
             CapTag::CapCNodeCap => self.get_cnode_ptr(),
             CapTag::CapThreadCap => self.get_tcb_ptr(),
             CapTag::CapZombieCap => self.get_zombie_ptr(),
+            CapTag::CapSchedContextCap => self.get_sc_ptr(),
+            #[cfg(feature = "ENABLE_MCS")]
+            CapTag::CapReplyCap => self.get_reply_ptr(),
             CapTag::CapFrameCap => self.get_frame_base_ptr(),
             CapTag::CapPageTableCap => self.get_pt_base_ptr(),
             CapTag::CapPageDirectoryCap => self.get_pd_base_ptr(),
@@ -131,6 +164,7 @@ impl cap_t {
             CapTag::CapPageGlobalDirectoryCap => self.get_pgd_base_ptr(),
             CapTag::CapASIDControlCap => 0,
             CapTag::CapASIDPoolCap => self.get_asid_pool(),
+            CapTag::CapVCPUCap => self.get_vcpu_ptr(),
             _ => 0,
         }
     }
@@ -199,7 +233,10 @@ impl cte_t {
                 newCap.set_frame_mapped_asid(0);
                 ret.cap = newCap;
             }
-            CapTag::CapASIDControlCap | CapTag::CapASIDPoolCap => {
+            CapTag::CapASIDControlCap
+            | CapTag::CapASIDPoolCap
+            | CapTag::CapVCPUCap
+            | CapTag::CapSMCCap => {
                 ret.cap = cap.clone();
             }
             _ => {
@@ -222,6 +259,23 @@ pub fn arch_mask_cap_rights(rights: seL4_CapRights_t, cap: &cap_t) -> cap_t {
     }
 }
 
+/// The arch cap with `new_data` applied, see `cap_t::update_data`. An SMC cap takes `new_data`
+/// as its badge like an endpoint cap, the badge then being the only function ID it may call.
+pub fn arch_update_data(cap: &cap_t, preserve: bool, new_data: usize) -> cap_t {
+    match cap.get_cap_type() {
+        CapTag::CapSMCCap => {
+            if !preserve && cap.get_smc_badge() == 0 {
+                let mut new_cap = cap.clone();
+                new_cap.set_smc_badge(new_data);
+                new_cap
+            } else {
+                cap_t::new_null_cap()
+            }
+        }
+        _ => cap.clone(),
+    }
+}
+
 pub fn arch_same_region_as(cap1: &cap_t, cap2: &cap_t) -> bool {
     match cap1.get_cap_type() {
         CapTag::CapFrameCap => {
@@ -262,6 +316,14 @@ pub fn arch_same_region_as(cap1: &cap_t, cap2: &cap_t) -> bool {
                 return cap1.get_asid_pool() == cap2.get_asid_pool();
             }
         }
+        CapTag::CapVCPUCap => {
+            if cap2.get_cap_type() == CapTag::CapVCPUCap {
+                return cap1.get_vcpu_ptr() == cap2.get_vcpu_ptr();
+            }
+        }
+        CapTag::CapSMCCap => {
+            return cap2.get_cap_type() == CapTag::CapSMCCap;
+        }
         _ => panic!("unknown cap"),
     }
     false
@@ -52,6 +52,14 @@ plus_define_bitfield! {
             capZombieType, get_zombie_type, set_zombie_type, 0, 0, 7, 0, false
         },
         new_domain_cap, CapTag::CapDomainCap as usize => {},
+        new_memory_control_cap, CapTag::CapMemoryControlCap as usize => {},
+        new_sched_context_cap, CapTag::CapSchedContextCap as usize => {
+            capSCSizeBits, get_sc_size_bits, set_sc_size_bits, 1, 0, 6, 0, false,
+            capSCPtr, get_sc_ptr, set_sc_ptr, 0, 0, 39, 0, true
+        },
+        new_sched_control_cap, CapTag::CapSchedControlCap as usize => {
+            core, get_sched_control_core, set_sched_control_core, 1, 0, 64, 0, false
+        },
         new_frame_cap, CapTag::CapFrameCap as usize => {
             capFMappedASID, get_frame_mapped_asid, set_frame_mapped_asid, 1, 48, 16, 0, false,
             capFBasePtr, get_frame_base_ptr, set_frame_base_ptr, 1, 9, 39, 0, true,
@@ -70,11 +78,22 @@ plus_define_bitfield! {
         new_asid_pool_cap, CapTag::CapASIDPoolCap as usize => {
             capASIDBase, get_asid_base, set_asid_base, 0, 43, 16, 0, false,
             capASIDPool, get_asid_pool, set_asid_pool, 0, 0, 37, 2, true
+        },
+        new_vcpu_cap, CapTag::CapVCPUCap as usize => {
+            capVCPUPtr, get_vcpu_ptr, set_vcpu_ptr, 0, 0, 39, 0, true
         }
     }
 }
 
 impl cap_t {
+    /// 是否为设备内存的frame cap
+    ///
+    /// Whether this is a frame cap to device memory, which must not be mapped cacheable.
+    #[inline]
+    pub fn is_device_frame(&self) -> bool {
+        self.get_cap_type() == CapTag::CapFrameCap && self.get_frame_is_device() != 0
+    }
+
     pub fn get_cap_ptr(&self) -> usize {
         match self.get_cap_type() {
             CapTag::CapUntypedCap => self.get_untyped_ptr(),
@@ -83,9 +102,13 @@ impl cap_t {
             CapTag::CapCNodeCap => self.get_cnode_ptr(),
             CapTag::CapThreadCap => self.get_tcb_ptr(),
             CapTag::CapZombieCap => self.get_zombie_ptr(),
+            CapTag::CapSchedContextCap => self.get_sc_ptr(),
+            #[cfg(feature = "ENABLE_MCS")]
+            CapTag::CapReplyCap => self.get_reply_ptr(),
             CapTag::CapFrameCap => self.get_frame_base_ptr(),
             CapTag::CapPageTableCap => self.get_pt_base_ptr(),
             CapTag::CapASIDPoolCap => self.get_asid_pool(),
+            CapTag::CapVCPUCap => self.get_vcpu_ptr(),
             _ => 0,
         }
     }
@@ -105,10 +128,16 @@ pub enum CapTag {
     CapIrqHandlerCap = 16,
     CapZombieCap = 18,
     CapDomainCap = 20,
+    CapSchedContextCap = 22,
+    CapSchedControlCap = 24,
+    /// reL4 extension, free with and without `ENABLE_MCS`
+    CapMemoryControlCap = 26,
     CapFrameCap = 1,
     CapPageTableCap = 3,
     CapASIDControlCap = 11,
     CapASIDPoolCap = 13,
+    /// Only created with `ENABLE_RISCV_HYP`
+    CapVCPUCap = 15,
 }
 
 impl cte_t {
@@ -133,7 +162,7 @@ impl cte_t {
                 newCap.set_frame_mapped_asid(0);
                 ret.cap = newCap;
             }
-            CapTag::CapASIDControlCap | CapTag::CapASIDPoolCap => {
+            CapTag::CapASIDControlCap | CapTag::CapASIDPoolCap | CapTag::CapVCPUCap => {
                 ret.cap = cap.clone();
             }
             _ => {
@@ -156,6 +185,11 @@ pub fn arch_mask_cap_rights(rights: seL4_CapRights_t, cap: &cap_t) -> cap_t {
     }
 }
 
+/// The arch cap with `new_data` applied, see `cap_t::update_data`. No arch cap takes data.
+pub fn arch_update_data(cap: &cap_t, _preserve: bool, _new_data: usize) -> cap_t {
+    cap.clone()
+}
+
 pub fn arch_same_object_as(cap1: &cap_t, cap2: &cap_t) -> bool {
     if cap1.get_cap_type() == CapTag::CapFrameCap && cap2.get_cap_type() == CapTag::CapFrameCap {
         return cap1.get_frame_base_ptr() == cap2.get_frame_base_ptr()
@@ -189,6 +223,11 @@ pub fn arch_same_region_as(cap1: &cap_t, cap2: &cap_t) -> bool {
                 return cap1.get_asid_pool() == cap2.get_asid_pool();
             }
         }
+        CapTag::CapVCPUCap => {
+            if cap2.get_cap_type() == CapTag::CapVCPUCap {
+                return cap1.get_vcpu_ptr() == cap2.get_vcpu_ptr();
+            }
+        }
         _ => panic!("unknown cap"),
     }
     false
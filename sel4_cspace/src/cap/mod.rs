@@ -18,7 +18,7 @@ pub mod zombie;
 
 use sel4_common::{sel4_config::*, MASK};
 
-use crate::arch::{arch_same_object_as, cap_t, CapTag};
+use crate::arch::{arch_same_object_as, arch_update_data, cap_t, CapTag};
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
@@ -96,9 +96,16 @@ impl CNodeCapData {
 
 /// cap 的公用方法
 impl cap_t {
+    /// The cap with `new_data` applied, by CNode Mint with `preserve` false and by CNode Mutate
+    /// with `preserve` true.
+    ///
+    /// Badge 0 means unbadged. An endpoint or notification cap only takes `new_data` as its badge
+    /// when it is minted from an unbadged cap, and a badge once set never changes: minting a badged
+    /// cap or mutating an endpoint or notification cap gives a null cap. The same holds for the
+    /// badge of an arch SMC cap.
     pub fn update_data(&self, preserve: bool, new_data: usize) -> Self {
         if self.isArchCap() {
-            return self.clone();
+            return arch_update_data(self, preserve, new_data);
         }
         match self.get_cap_type() {
             CapTag::CapEndpointCap => {
@@ -149,6 +156,8 @@ impl cap_t {
             CapTag::CapCNodeCap => self.get_cnode_radix() + seL4_SlotBits,
             CapTag::CapPageTableCap => PT_SIZE_BITS,
             CapTag::CapReplyCap => seL4_ReplyBits,
+            CapTag::CapSchedContextCap => self.get_sc_size_bits(),
+            CapTag::CapVCPUCap => seL4_VCPUBits,
             _ => 0,
         }
     }
@@ -165,7 +174,17 @@ impl cap_t {
                 | CapTag::CapPageTableCap
                 | CapTag::CapZombieCap
                 | CapTag::CapThreadCap
-        )
+                | CapTag::CapSchedContextCap
+                | CapTag::CapVCPUCap
+        ) || (cfg!(feature = "ENABLE_MCS") && self.get_cap_type() == CapTag::CapReplyCap)
+    }
+
+    /// The reply object named by a reply cap, which takes the place of the caller with
+    /// `ENABLE_MCS`. There is no master reply cap then.
+    #[cfg(feature = "ENABLE_MCS")]
+    #[inline]
+    pub fn get_reply_ptr(&self) -> usize {
+        self.get_reply_tcb_ptr()
     }
 
     pub fn isArchCap(&self) -> bool {
@@ -193,13 +212,14 @@ pub fn same_region_as(cap1: &cap_t, cap2: &cap_t) -> bool {
         | CapTag::CapNotificationCap
         | CapTag::CapPageTableCap
         | CapTag::CapASIDPoolCap
-        | CapTag::CapThreadCap => {
+        | CapTag::CapThreadCap
+        | CapTag::CapSchedContextCap => {
             if cap2.get_cap_type() == cap1.get_cap_type() {
                 return cap1.get_cap_ptr() == cap2.get_cap_ptr();
             }
             false
         }
-        CapTag::CapASIDControlCap | CapTag::CapDomainCap => {
+        CapTag::CapASIDControlCap | CapTag::CapDomainCap | CapTag::CapMemoryControlCap => {
             if cap2.get_cap_type() == cap1.get_cap_type() {
                 return true;
             }
@@ -224,6 +244,19 @@ pub fn same_region_as(cap1: &cap_t, cap2: &cap_t) -> bool {
             }
             false
         }
+        CapTag::CapSchedControlCap => {
+            if cap2.get_cap_type() == CapTag::CapSchedControlCap {
+                return cap1.get_sched_control_core() == cap2.get_sched_control_core();
+            }
+            false
+        }
+        #[cfg(feature = "ENABLE_MCS")]
+        CapTag::CapReplyCap => {
+            if cap2.get_cap_type() == CapTag::CapReplyCap {
+                return cap1.get_reply_ptr() == cap2.get_reply_ptr();
+            }
+            false
+        }
         _ => false,
     }
 }
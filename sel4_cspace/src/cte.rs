@@ -7,16 +7,35 @@ use super::{
     structures::{finaliseSlot_ret, resolveAddressBits_ret_t},
 };
 use crate::cap::zombie::capCyclicZombie;
+#[cfg(feature = "ENABLE_LOOKUP_TRACE")]
+use crate::lookup_trace::{
+    lookup_trace_begin, lookup_trace_fail, lookup_trace_failure_t, lookup_trace_step,
+    lookup_trace_step_t,
+};
 use core::intrinsics::{likely, unlikely};
 use core::ptr;
 use sel4_common::utils::{convert_to_option_mut_type_ref, MAX_FREE_INDEX};
 use sel4_common::{
+    kernel_assert,
     sel4_config::wordRadix,
     structures::exception_t,
     utils::{convert_to_mut_type_ref, convert_to_type_ref},
     MASK,
 };
 
+/// CSpace 修改计数，每次修改 slot 中的 cap 时递增，用于判断 TCB 中缓存的查找结果是否失效
+#[cfg(feature = "ENABLE_LOOKUP_CACHE")]
+pub static mut cspace_epoch: usize = 1;
+
+/// 记录一次 CSpace 修改，使所有 TCB 的 cptr 查找缓存失效
+#[inline]
+pub fn cspace_modified() {
+    #[cfg(feature = "ENABLE_LOOKUP_CACHE")]
+    unsafe {
+        cspace_epoch = cspace_epoch.wrapping_add(1).max(1);
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct deriveCap_ret {
@@ -62,6 +81,8 @@ impl cte_t {
                     ret.cap = cap.clone();
                 }
             }
+            // Reply caps to reply objects are copied like any other cap
+            #[cfg(not(feature = "ENABLE_MCS"))]
             CapTag::CapReplyCap => {
                 ret.cap = cap_t::new_null_cap();
             }
@@ -145,55 +166,91 @@ impl cte_t {
         )
     }
 
-    ///清除`cte slot`中的`capability`
-    /// 因为涉及到几个函数之间的来回调用，不太好理解，所以用一个`CNode cap`删除的例子来帮助理解，
-    /// 假设现在有一个`CNode`的`slot`要执行`delete_all(true)`，会先调用`finalise(true)`，在`finaliseCap`中，
-    /// 会将`cnode_cap`设置为`zombie_cap`，然后进入`reduce_zombie`,
-    /// `reduce_zombie`会调用最后一个`slot`的`delete_all(false)`函数，然后再次进入`finalise(false)`
-    /// 假设最后一个`slot`存储的`cap`为一个二级`cnode_cap`，则会在`finaliseCap`中生成一个新的`zombie_cap`，
-    /// 之后再次进入`reduce_zombie(false)`，在其中进入`else`分支，
-    /// 执行`cteswap`将二级`cnode_cap`中的第一个`cap`与二级`cnode_cap`进行交换，使得二级`cnode_cap`指向自身，变成`cyclicZombie`。
-    /// 然后继续清除即可。至于二级`cnode_cap`其实无法被清除。
-    unsafe fn finalise(&mut self, immediate: bool) -> finaliseSlot_ret {
-        let mut ret = finaliseSlot_ret::default();
-        while self.cap.get_cap_type() != CapTag::CapNullCap {
-            let fc_ret = finaliseCap(&self.cap, self.is_final_cap(), false);
-            if cap_removable(&fc_ret.remainder, self) {
-                ret.status = exception_t::EXCEPTION_NONE;
-                ret.success = true;
-                ret.cleanupInfo = fc_ret.cleanupInfo;
-                return ret;
-            }
-            self.cap = fc_ret.remainder;
-            if !immediate && capCyclicZombie(&fc_ret.remainder, self) {
-                ret.status = exception_t::EXCEPTION_NONE;
-                ret.success = false;
-                ret.cleanupInfo = fc_ret.cleanupInfo;
-                return ret;
-            }
-            let status = self.reduce_zombie(immediate);
-            if exception_t::EXCEPTION_NONE != status {
-                ret.status = status;
-                ret.success = false;
-                ret.cleanupInfo = cap_t::new_null_cap();
-                return ret;
+    ///清除`cte slot`中的`capability`，直到剩下的`cap`可以直接删除。
+    /// 以`CNode cap`为例：`finaliseCap`会将`cnode_cap`变为`zombie_cap`，之后由`reduce_zombie`每次删除
+    /// `Zombie`中的最后一个`slot`并减少其`number`。删除的进度都保存在`slot`中的`Zombie`里，
+    /// 被抢占后重新执行同一个操作即可继续。`reduce_zombie`通过`delete_all(false)`递归一层，
+    /// 由`finalise_unexposed`处理最后一个`slot`，它不会再删除其他的`slot`，
+    /// 所以递归深度最多为2，内核栈的深度与`CNode`树的深度无关。
+    unsafe fn finalise(&mut self) -> finaliseSlot_ret {
+        unsafe {
+            let mut ret = finaliseSlot_ret::default();
+            while self.cap.get_cap_type() != CapTag::CapNullCap {
+                let fc_ret = finaliseCap(&self.cap, self.is_final_cap(), false);
+                if cap_removable(&fc_ret.remainder, self) {
+                    ret.status = exception_t::EXCEPTION_NONE;
+                    ret.success = true;
+                    ret.cleanupInfo = fc_ret.cleanupInfo;
+                    return ret;
+                }
+                self.cap = fc_ret.remainder;
+                cspace_modified();
+                let status = self.reduce_zombie();
+                if exception_t::EXCEPTION_NONE != status {
+                    ret.status = status;
+                    ret.success = false;
+                    ret.cleanupInfo = cap_t::new_null_cap();
+                    return ret;
+                }
+
+                let status = preemptionPoint();
+                if exception_t::EXCEPTION_NONE != status {
+                    ret.status = status;
+                    ret.success = false;
+                    ret.cleanupInfo = cap_t::new_null_cap();
+                    return ret;
+                }
             }
+            ret
+        }
+    }
 
-            let status = preemptionPoint();
-            if exception_t::EXCEPTION_NONE != status {
-                ret.status = status;
-                ret.success = false;
-                ret.cleanupInfo = cap_t::new_null_cap();
-                return ret;
+    /// 清除`Zombie`中最后一个`slot`的`capability`，该`slot`对用户不可见。
+    /// 假设其中存储的是一个二级`cnode_cap`，`finaliseCap`会生成一个新的`zombie_cap`，
+    /// 此时只执行`swap_zombie`将它与其第一个`slot`中的`cap`交换，直到它成为指向自身的`cyclicZombie`，
+    /// 然后将其留给外层`Zombie`的`finalise`在之后的循环中继续删除。
+    unsafe fn finalise_unexposed(&mut self) -> finaliseSlot_ret {
+        unsafe {
+            let mut ret = finaliseSlot_ret::default();
+            while self.cap.get_cap_type() != CapTag::CapNullCap {
+                let fc_ret = finaliseCap(&self.cap, self.is_final_cap(), false);
+                if cap_removable(&fc_ret.remainder, self) {
+                    ret.status = exception_t::EXCEPTION_NONE;
+                    ret.success = true;
+                    ret.cleanupInfo = fc_ret.cleanupInfo;
+                    return ret;
+                }
+                self.cap = fc_ret.remainder;
+                cspace_modified();
+                if capCyclicZombie(&fc_ret.remainder, self) {
+                    ret.status = exception_t::EXCEPTION_NONE;
+                    ret.success = false;
+                    ret.cleanupInfo = fc_ret.cleanupInfo;
+                    return ret;
+                }
+                self.swap_zombie();
+
+                let status = preemptionPoint();
+                if exception_t::EXCEPTION_NONE != status {
+                    ret.status = status;
+                    ret.success = false;
+                    ret.cleanupInfo = cap_t::new_null_cap();
+                    return ret;
+                }
             }
+            ret
         }
-        ret
     }
 
     /// 将当前的`cte slot`中的能力清除，因为可能是`cnode_cap`或者`tcb_cap`，其中都可以存储多个`cap`，
     /// 所以可能顺带将存储的`cap`也清除掉
     pub fn delete_all(&mut self, exposed: bool) -> exception_t {
-        let fs_ret = unsafe { self.finalise(exposed) };
+        let fs_ret = unsafe {
+            match exposed {
+                true => self.finalise(),
+                false => self.finalise_unexposed(),
+            }
+        };
         if fs_ret.status != exception_t::EXCEPTION_NONE {
             return fs_ret.status;
         }
@@ -236,54 +293,58 @@ impl cte_t {
             }
             self.cap = cap_t::new_null_cap();
             self.cteMDBNode = mdb_node_t::default();
+            cspace_modified();
             unsafe { post_cap_deletion(cleanup_info) };
         }
     }
 
     /// 每次删除`zombie cap`中的最后一个`capability`,用于删除unremovable的capability。
-    fn reduce_zombie(&mut self, immediate: bool) -> exception_t {
+    fn reduce_zombie(&mut self) -> exception_t {
         assert_eq!(self.cap.get_cap_type(), CapTag::CapZombieCap);
         let self_ptr = self as *mut cte_t as usize;
         let ptr = self.cap.get_zombie_ptr();
         let n = self.cap.get_zombie_number();
         let zombie_type = self.cap.get_zombie_type();
         assert!(n > 0);
-        if immediate {
-            let end_slot = unsafe { &mut *((ptr as *mut cte_t).add(n - 1)) };
-            let status = end_slot.delete_all(false);
-            if status != exception_t::EXCEPTION_NONE {
-                return status;
+        let end_slot = unsafe { &mut *((ptr as *mut cte_t).add(n - 1)) };
+        let status = end_slot.delete_all(false);
+        if status != exception_t::EXCEPTION_NONE {
+            return status;
+        }
+        match self.cap.get_cap_type() {
+            CapTag::CapNullCap => {
+                return exception_t::EXCEPTION_NONE;
             }
-            match self.cap.get_cap_type() {
-                CapTag::CapNullCap => {
-                    return exception_t::EXCEPTION_NONE;
-                }
-                CapTag::CapZombieCap => {
-                    let ptr2 = self.cap.get_zombie_ptr();
-                    if ptr == ptr2
-                        && self.cap.get_zombie_number() == n
-                        && self.cap.get_zombie_type() == zombie_type
-                    {
-                        assert_eq!(end_slot.cap.get_cap_type(), CapTag::CapNullCap);
-                        self.cap.set_zombie_number(n - 1);
-                    } else {
-                        assert!(ptr2 == self_ptr && ptr != self_ptr);
-                    }
-                }
-                _ => {
-                    panic!("Expected recursion to result in Zombie.")
+            CapTag::CapZombieCap => {
+                let ptr2 = self.cap.get_zombie_ptr();
+                if ptr == ptr2
+                    && self.cap.get_zombie_number() == n
+                    && self.cap.get_zombie_type() == zombie_type
+                {
+                    assert_eq!(end_slot.cap.get_cap_type(), CapTag::CapNullCap);
+                    self.cap.set_zombie_number(n - 1);
+                } else {
+                    assert!(ptr2 == self_ptr && ptr != self_ptr);
                 }
             }
-        } else {
-            assert_ne!(ptr, self_ptr);
-            let next_slot = convert_to_mut_type_ref::<cte_t>(ptr);
-            let cap1 = next_slot.cap;
-            let cap2 = self.cap;
-            cte_swap(&cap1, next_slot, &cap2, self);
+            _ => {
+                panic!("Expected recursion to result in Zombie.")
+            }
         }
         exception_t::EXCEPTION_NONE
     }
 
+    /// 将不指向自身的`zombie cap`与其第一个`slot`中的`cap`交换，使其成为`cyclicZombie`。
+    fn swap_zombie(&mut self) {
+        assert_eq!(self.cap.get_cap_type(), CapTag::CapZombieCap);
+        let ptr = self.cap.get_zombie_ptr();
+        assert_ne!(ptr, self as *mut cte_t as usize);
+        let next_slot = convert_to_mut_type_ref::<cte_t>(ptr);
+        let cap1 = next_slot.cap;
+        let cap2 = self.cap;
+        cte_swap(&cap1, next_slot, &cap2, self);
+    }
+
     #[cfg(target_arch = "riscv64")]
     #[inline]
     fn get_volatile_value(&self) -> usize {
@@ -353,6 +414,7 @@ pub fn cte_insert(new_cap: &cap_t, src_slot: &mut cte_t, dest_slot: &mut cte_t)
 
     dest_slot.cap = new_cap.clone();
     dest_slot.cteMDBNode = newMDB;
+    cspace_modified();
     src_slot
         .cteMDBNode
         .set_next(dest_slot as *const cte_t as usize);
@@ -369,6 +431,7 @@ pub fn insert_new_cap(parent: &mut cte_t, slot: &mut cte_t, cap: &cap_t) {
     let next = parent.cteMDBNode.get_next();
     slot.cap = cap.clone();
     slot.cteMDBNode = mdb_node_t::new(next, 1usize, 1usize, parent as *const cte_t as usize);
+    cspace_modified();
     if next != 0 {
         let next_ref = convert_to_mut_type_ref::<cte_t>(next);
         next_ref.cteMDBNode.set_prev(slot as *const cte_t as usize);
@@ -385,10 +448,19 @@ pub fn cte_move(new_cap: &cap_t, src_slot: &mut cte_t, dest_slot: &mut cte_t) {
     /* Haskell error: "cteInsert: mdb entry must be empty" */
     assert!(dest_slot.cteMDBNode.get_next() == 0 && dest_slot.cteMDBNode.get_prev() == 0);
     let mdb = src_slot.cteMDBNode;
+    kernel_assert!(
+        3001,
+        mdb.get_prev() == 0
+            || convert_to_mut_type_ref::<cte_t>(mdb.get_prev())
+                .cteMDBNode
+                .get_next()
+                == src_slot.get_ptr()
+    );
     dest_slot.cap = new_cap.clone();
     src_slot.cap = cap_t::new_null_cap();
     dest_slot.cteMDBNode = mdb;
     src_slot.cteMDBNode = mdb_node_t::new(0, 0, 0, 0);
+    cspace_modified();
 
     let prev_ptr = mdb.get_prev();
     if prev_ptr != 0 {
@@ -407,43 +479,42 @@ pub fn cte_move(new_cap: &cap_t, src_slot: &mut cte_t, dest_slot: &mut cte_t) {
 }
 
 /// 交换两个slot，并将新的cap数据填入
+///
+/// `slot2` 的mdb在 `slot1` 的邻居更新之后才读取，两个slot在mdb中相邻时（例如Rotate交换父子cap）链表仍然正确。
 pub fn cte_swap(cap1: &cap_t, slot1: &mut cte_t, cap2: &cap_t, slot2: &mut cte_t) {
+    kernel_assert!(3002, slot1.get_ptr() != slot2.get_ptr());
+    slot1.cap = cap2.clone();
+    slot2.cap = cap1.clone();
+
     let mdb1 = slot1.cteMDBNode;
-    let mdb2 = slot2.cteMDBNode;
-    {
-        let prev_ptr = mdb1.get_prev();
-        if prev_ptr != 0 {
-            convert_to_mut_type_ref::<cte_t>(prev_ptr)
-                .cteMDBNode
-                .set_next(slot2 as *const cte_t as usize);
-        }
-        let next_ptr = mdb1.get_next();
-        if next_ptr != 0 {
-            convert_to_mut_type_ref::<cte_t>(next_ptr)
-                .cteMDBNode
-                .set_prev(slot2 as *const cte_t as usize);
-        }
+    let prev_ptr = mdb1.get_prev();
+    if prev_ptr != 0 {
+        convert_to_mut_type_ref::<cte_t>(prev_ptr)
+            .cteMDBNode
+            .set_next(slot2 as *const cte_t as usize);
+    }
+    let next_ptr = mdb1.get_next();
+    if next_ptr != 0 {
+        convert_to_mut_type_ref::<cte_t>(next_ptr)
+            .cteMDBNode
+            .set_prev(slot2 as *const cte_t as usize);
     }
 
-    slot1.cap = cap2.clone();
-    //FIXME::result not right due to compiler
-
-    slot2.cap = cap1.clone();
+    let mdb2 = slot2.cteMDBNode;
     slot1.cteMDBNode = mdb2;
     slot2.cteMDBNode = mdb1;
-    {
-        let prev_ptr = mdb2.get_prev();
-        if prev_ptr != 0 {
-            convert_to_mut_type_ref::<cte_t>(prev_ptr)
-                .cteMDBNode
-                .set_next(slot1 as *const cte_t as usize);
-        }
-        let next_ptr = mdb2.get_next();
-        if next_ptr != 0 {
-            convert_to_mut_type_ref::<cte_t>(next_ptr)
-                .cteMDBNode
-                .set_prev(slot1 as *const cte_t as usize);
-        }
+    cspace_modified();
+    let prev_ptr = mdb2.get_prev();
+    if prev_ptr != 0 {
+        convert_to_mut_type_ref::<cte_t>(prev_ptr)
+            .cteMDBNode
+            .set_next(slot1 as *const cte_t as usize);
+    }
+    let next_ptr = mdb2.get_next();
+    if next_ptr != 0 {
+        convert_to_mut_type_ref::<cte_t>(next_ptr)
+            .cteMDBNode
+            .set_prev(slot1 as *const cte_t as usize);
     }
 }
 
@@ -496,8 +567,12 @@ pub fn resolve_address_bits(
     let mut n_bits = _n_bits;
     ret.bitsRemaining = n_bits;
     let mut nodeCap = node_cap.clone();
+    #[cfg(feature = "ENABLE_LOOKUP_TRACE")]
+    lookup_trace_begin(cap_ptr, n_bits);
 
     if unlikely(nodeCap.get_cap_type() != CapTag::CapCNodeCap) {
+        #[cfg(feature = "ENABLE_LOOKUP_TRACE")]
+        lookup_trace_fail(lookup_trace_failure_t::InvalidRoot);
         ret.status = exception_t::EXCEPTION_LOOKUP_FAULT;
         return ret;
     }
@@ -508,12 +583,42 @@ pub fn resolve_address_bits(
         let levelBits = radixBits + guardBits;
         assert_ne!(levelBits, 0);
         let capGuard = nodeCap.get_cnode_guard();
+        // 先检查guardBits，避免n_bits - guardBits下溢
+        if unlikely(guardBits > n_bits) {
+            #[cfg(feature = "ENABLE_LOOKUP_TRACE")]
+            {
+                lookup_trace_step(lookup_trace_step_t {
+                    cnode: nodeCap.get_cnode_ptr(),
+                    guard: capGuard,
+                    guard_size: guardBits,
+                    guard_found: 0,
+                    radix: radixBits,
+                    bits_remaining: n_bits,
+                });
+                lookup_trace_fail(lookup_trace_failure_t::DepthMismatch);
+            }
+            ret.status = exception_t::EXCEPTION_LOOKUP_FAULT;
+            return ret;
+        }
         let guard = (cap_ptr >> ((n_bits - guardBits) & MASK!(wordRadix))) & MASK!(guardBits);
-        if unlikely(guardBits > n_bits || guard != capGuard) {
+        #[cfg(feature = "ENABLE_LOOKUP_TRACE")]
+        lookup_trace_step(lookup_trace_step_t {
+            cnode: nodeCap.get_cnode_ptr(),
+            guard: capGuard,
+            guard_size: guardBits,
+            guard_found: guard,
+            radix: radixBits,
+            bits_remaining: n_bits,
+        });
+        if unlikely(guard != capGuard) {
+            #[cfg(feature = "ENABLE_LOOKUP_TRACE")]
+            lookup_trace_fail(lookup_trace_failure_t::GuardMismatch);
             ret.status = exception_t::EXCEPTION_LOOKUP_FAULT;
             return ret;
         }
         if unlikely(levelBits > n_bits) {
+            #[cfg(feature = "ENABLE_LOOKUP_TRACE")]
+            lookup_trace_fail(lookup_trace_failure_t::DepthMismatch);
             ret.status = exception_t::EXCEPTION_LOOKUP_FAULT;
             return ret;
         }
@@ -7,5 +7,6 @@ extern "C" {
 
     pub fn post_cap_deletion(cap: &cap_t);
 
+    /// Count one unit of work, `EXCEPTION_PREEMTED` when the operation has to stop here.
     pub fn preemptionPoint() -> exception_t;
 }
@@ -2,5 +2,9 @@ pub use super::arch::{cap_t, CapTag};
 pub use super::cap::same_object_as;
 pub use super::mdb::mdb_node_t;
 
-pub use super::cte::{cte_insert, cte_move, cte_swap, cte_t, insert_new_cap, resolve_address_bits};
+#[cfg(feature = "ENABLE_LOOKUP_CACHE")]
+pub use super::cte::cspace_epoch;
+pub use super::cte::{
+    cspace_modified, cte_insert, cte_move, cte_swap, cte_t, insert_new_cap, resolve_address_bits,
+};
 pub use super::structures::finaliseCap_ret;
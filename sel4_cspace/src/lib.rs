@@ -7,12 +7,15 @@
 #![allow(non_camel_case_types)]
 #![allow(non_upper_case_globals)]
 #![allow(clippy::clone_on_copy)]
+#![deny(unsafe_op_in_unsafe_fn)]
 #![feature(custom_test_frameworks)]
 #![test_runner(crate::tests::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 
 mod cap;
 mod cte;
+#[cfg(feature = "ENABLE_LOOKUP_TRACE")]
+pub mod lookup_trace;
 mod mdb;
 mod structures;
 
@@ -34,7 +37,10 @@ mod tests {
     use cte::{cte_insert, cte_move, cte_swap, cte_t, insert_new_cap, resolve_address_bits};
     use mdb::mdb_node_t;
     use riscv::register::{stvec, utvec::TrapMode};
-    use sel4_common::{arch::shutdown, println, utils::convert_to_mut_type_ref};
+    use sel4_common::{
+        arch::shutdown, println, sel4_config::wordBits, structures::exception_t,
+        utils::convert_to_mut_type_ref, BIT,
+    };
     global_asm!(include_str!("entry.asm"));
 
     use super::*;
@@ -175,6 +181,46 @@ mod tests {
         println!("Test cte_swap_test passed");
     }
 
+    #[test_case]
+    pub fn cte_swap_adjacent_test() {
+        println!("-----------------------------------");
+        println!("Entering cte_swap_adjacent_test case");
+        let cap1 = cap_t::new_asid_control_cap();
+        let cap2 = cap_t::new_domain_cap();
+        let mut cte1 = cte_t {
+            cap: cap_t::new_null_cap(),
+            cteMDBNode: mdb_node_t::new(0, 0, 0, 0),
+        };
+        let mut cte2 = cte_t {
+            cap: cap_t::new_null_cap(),
+            cteMDBNode: mdb_node_t::new(0, 0, 0, 0),
+        };
+        let mut cte3 = cte_t {
+            cap: cap_t::new_null_cap(),
+            cteMDBNode: mdb_node_t::new(0, 0, 0, 0),
+        };
+        let mut cte4 = cte_t {
+            cap: cap_t::new_null_cap(),
+            cteMDBNode: mdb_node_t::new(0, 0, 0, 0),
+        };
+
+        // cte1 -> cte2 -> cte3 -> cte4, then swap the neighbours cte2 and cte3
+        cte_insert(&cap1, &mut cte1, &mut cte2);
+        cte_insert(&cap2, &mut cte2, &mut cte3);
+        cte_insert(&cap2, &mut cte3, &mut cte4);
+        cte_swap(&cap1, &mut cte2, &cap2, &mut cte3);
+        assert_eq!(cte2.cap.get_cap_type(), CapTag::CapDomainCap);
+        assert_eq!(cte3.cap.get_cap_type(), CapTag::CapASIDControlCap);
+        assert_eq!(cte1.cteMDBNode.get_next(), &mut cte3 as *mut cte_t as usize);
+        assert_eq!(cte3.cteMDBNode.get_prev(), &mut cte1 as *mut cte_t as usize);
+        assert_eq!(cte3.cteMDBNode.get_next(), &mut cte2 as *mut cte_t as usize);
+        assert_eq!(cte2.cteMDBNode.get_prev(), &mut cte3 as *mut cte_t as usize);
+        assert_eq!(cte2.cteMDBNode.get_next(), &mut cte4 as *mut cte_t as usize);
+        assert_eq!(cte4.cteMDBNode.get_prev(), &mut cte2 as *mut cte_t as usize);
+
+        println!("Test cte_swap_adjacent_test passed");
+    }
+
     #[test_case]
     pub fn insert_new_cap_test() {
         println!("-----------------------------------");
@@ -240,6 +286,90 @@ mod tests {
         println!("Test resolve_address_bits_test passed");
     }
 
+    #[test_case]
+    pub fn large_cnode_boundary_test() {
+        println!("-----------------------------------");
+        println!("Entering large_cnode_boundary_test case");
+        // 2^13个slot的cnode，guard占满剩下的位，level正好是一个字长
+        let radix = 13;
+        let guard_size = wordBits - radix;
+        let guard = 0x5a5;
+        let base = 0x8000_0000usize;
+        let cap = cap_t::new_cnode_cap(radix, guard_size, guard, base);
+        let idx = BIT!(radix) - 1;
+        let res_ret = resolve_address_bits(&cap, (guard << radix) | idx, wordBits);
+        assert_eq!(res_ret.status, exception_t::EXCEPTION_NONE);
+        assert_eq!(res_ret.slot as usize, base + idx * 32);
+        // guard比剩余的位数多时必须查找失败
+        let res_ret = resolve_address_bits(&cap, idx, radix);
+        assert_eq!(res_ret.status, exception_t::EXCEPTION_LOOKUP_FAULT);
+        // guard_size + radix不能超过字长
+        let cap = cap_t::new_cnode_cap(radix, 0, 0, base);
+        let ok = cap.update_data(false, guard_size);
+        assert_eq!(ok.get_cap_type(), CapTag::CapCNodeCap);
+        assert_eq!(ok.get_cnode_guard_size(), guard_size);
+        let too_big = cap.update_data(false, guard_size + 1);
+        assert_eq!(too_big.get_cap_type(), CapTag::CapNullCap);
+        println!("Test large_cnode_boundary_test passed");
+    }
+
+    #[test_case]
+    pub fn badge_minting_test() {
+        println!("-----------------------------------");
+        println!("Entering badge_minting_test case");
+        let ep = cap_t::new_endpoint_cap(0, 1, 1, 1, 1, 0x8000_0000);
+        let nf = cap_t::new_notification_cap(0, 1, 1, 0x8000_1000);
+        for (cap, badge_of) in [
+            (ep, cap_t::get_ep_badge as fn(&cap_t) -> usize),
+            (nf, cap_t::get_nf_badge as fn(&cap_t) -> usize),
+        ] {
+            // Mint一个未标记的cap得到标记的cap，badge 0仍是未标记的
+            let badged = cap.update_data(false, 5);
+            assert_eq!(badged.get_cap_type(), cap.get_cap_type());
+            assert_eq!(badge_of(&badged), 5);
+            let unbadged = cap.update_data(false, 0);
+            assert_eq!(unbadged.get_cap_type(), cap.get_cap_type());
+            assert_eq!(badge_of(&unbadged), 0);
+            // badge设置后不能再改，Mutate也不能设置badge
+            assert_eq!(
+                badged.update_data(false, 7).get_cap_type(),
+                CapTag::CapNullCap
+            );
+            assert_eq!(
+                badged.update_data(false, 5).get_cap_type(),
+                CapTag::CapNullCap
+            );
+            assert_eq!(cap.update_data(true, 5).get_cap_type(), CapTag::CapNullCap);
+            assert_eq!(
+                badged.update_data(true, 0).get_cap_type(),
+                CapTag::CapNullCap
+            );
+        }
+        // 标记的cap是可撤销的新分支，复制同一个badge则不是
+        let mut orig = cte_t {
+            cap: ep,
+            cteMDBNode: mdb_node_t::new(0, 1, 1, 0),
+        };
+        let mut minted = cte_t {
+            cap: cap_t::new_null_cap(),
+            cteMDBNode: mdb_node_t::new(0, 0, 0, 0),
+        };
+        let mut copied = cte_t {
+            cap: cap_t::new_null_cap(),
+            cteMDBNode: mdb_node_t::new(0, 0, 0, 0),
+        };
+        let badged = orig.derive_cap(&ep.update_data(false, 5)).cap;
+        cte_insert(&badged, &mut orig, &mut minted);
+        assert_eq!(minted.cteMDBNode.get_revocable(), 1);
+        assert_eq!(minted.cteMDBNode.get_first_badged(), 1);
+        let copy = minted.derive_cap(&minted.cap.clone()).cap;
+        cte_insert(&copy, &mut minted, &mut copied);
+        assert_eq!(copied.cap.get_ep_badge(), 5);
+        assert_eq!(copied.cteMDBNode.get_revocable(), 0);
+        assert_eq!(copied.cteMDBNode.get_first_badged(), 0);
+        println!("Test badge_minting_test passed");
+    }
+
     #[test_case]
     pub fn shutdown_test() {
         println!("All Test Cases passed, shutdown");
@@ -0,0 +1,111 @@
+//! Trace of the last cap lookup, enabled by the `ENABLE_LOOKUP_TRACE` feature.
+//!
+//! [`resolve_address_bits`](crate::interface::resolve_address_bits) records every CNode level it
+//! visits. When the lookup fails the walk is written to the kernel log, next to the resulting
+//! `FailedLookup` error or cap fault, and kept until the next lookup so a debugger can read it
+//! from [`ksLookupTrace`]:
+//!
+//! ```text
+//! lookup of 0x... depth 64 failed: GuardMismatch
+//!   cnode 0x...: guard 0x0/2 found 0x1, radix 8, 64 bits left
+//! ```
+//!
+//! Every level consumes at least one bit, so a walk never has more than `wordBits` levels.
+
+use sel4_common::sel4_config::wordBits;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Why a lookup stopped
+pub enum lookup_trace_failure_t {
+    /// The root cap is not a CNode cap
+    InvalidRoot,
+    /// The guard of a CNode differs from the bits of the cptr
+    GuardMismatch,
+    /// Fewer bits are left than the guard and radix of a CNode take
+    DepthMismatch,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+/// One CNode visited by a lookup
+pub struct lookup_trace_step_t {
+    /// Address of the CNode
+    pub cnode: usize,
+    /// Guard of the CNode cap
+    pub guard: usize,
+    /// Size of the guard in bits
+    pub guard_size: usize,
+    /// Bits of the cptr compared against the guard
+    pub guard_found: usize,
+    /// Radix of the CNode
+    pub radix: usize,
+    /// Bits of the cptr left to resolve when the CNode was reached
+    pub bits_remaining: usize,
+}
+
+/// The walk of the last lookup
+pub struct lookup_trace_t {
+    /// The cptr being resolved
+    pub cptr: usize,
+    /// Depth the lookup started with
+    pub depth: usize,
+    /// Number of valid entries in `steps`
+    pub len: usize,
+    pub steps: [lookup_trace_step_t; wordBits],
+}
+
+#[no_mangle]
+pub static mut ksLookupTrace: lookup_trace_t = lookup_trace_t {
+    cptr: 0,
+    depth: 0,
+    len: 0,
+    steps: [lookup_trace_step_t {
+        cnode: 0,
+        guard: 0,
+        guard_size: 0,
+        guard_found: 0,
+        radix: 0,
+        bits_remaining: 0,
+    }; wordBits],
+};
+
+#[inline]
+/// Start the trace of a lookup of `cptr` with `depth` bits
+pub fn lookup_trace_begin(cptr: usize, depth: usize) {
+    unsafe {
+        ksLookupTrace.cptr = cptr;
+        ksLookupTrace.depth = depth;
+        ksLookupTrace.len = 0;
+    }
+}
+
+#[inline]
+/// Record a CNode visited by the current lookup
+pub fn lookup_trace_step(step: lookup_trace_step_t) {
+    let trace = unsafe { &mut ksLookupTrace };
+    if trace.len < wordBits {
+        trace.steps[trace.len] = step;
+        trace.len += 1;
+    }
+}
+
+/// Write the walk of the current lookup, which failed with `failure`, to the kernel log
+pub fn lookup_trace_fail(failure: lookup_trace_failure_t) {
+    let trace = unsafe { &ksLookupTrace };
+    log::warn!(
+        "lookup of {:#x} depth {} failed: {:?}",
+        trace.cptr,
+        trace.depth,
+        failure
+    );
+    for step in &trace.steps[..trace.len] {
+        log::warn!(
+            "  cnode {:#x}: guard {:#x}/{} found {:#x}, radix {}, {} bits left",
+            step.cnode,
+            step.guard,
+            step.guard_size,
+            step.guard_found,
+            step.radix,
+            step.bits_remaining
+        );
+    }
+}
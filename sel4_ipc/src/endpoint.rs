@@ -1,10 +1,15 @@
+#[cfg(feature = "ENABLE_MCS")]
+use crate::reply::reply_t;
 use crate::transfer::Transfer;
 use sel4_common::arch::ArchReg;
+use sel4_common::kernel_assert;
 use sel4_common::plus_define_bitfield;
-use sel4_common::utils::{convert_to_mut_type_ref, convert_to_option_mut_type_ref};
+use sel4_common::structures::exception_t;
+use sel4_cspace::deps::preemptionPoint;
+#[cfg(feature = "ENABLE_MCS")]
+use sel4_task::sched_context::sched_context_resume;
 use sel4_task::{
-    possible_switch_to, rescheduleRequired, schedule_tcb, set_thread_state, tcb_queue_t, tcb_t,
-    ThreadState,
+    possible_switch_to, rescheduleRequired, tcb_queue_t, tcb_t, ThreadState, ThreadStateMachine,
 };
 use sel4_vspace::pptr_t;
 
@@ -51,10 +56,21 @@ impl endpoint_t {
         self as *const Self as pptr_t
     }
 
+    #[inline]
+    /// Make the endpoint idle with an empty queue, called when it is retyped
+    pub fn init(&mut self) {
+        *self = endpoint_t::new(0, 0, EPState_Idle);
+    }
+
     #[inline]
     /// Get the state of the endpoint
     pub fn get_state(&self) -> EPState {
-        unsafe { core::mem::transmute::<u8, EPState>(self.get_usize_state() as u8) }
+        match self.get_usize_state() {
+            0 => EPState::Idle,
+            1 => EPState::Send,
+            2 => EPState::Recv,
+            state => unreachable!("invalid endpoint state {}", state),
+        }
     }
 
     #[inline]
@@ -78,13 +94,24 @@ impl endpoint_t {
     /// # Arguments
     /// * `tcb` - The tcb to cancel the IPC
     pub fn cancel_ipc(&mut self, tcb: &mut tcb_t) {
+        kernel_assert!(1001, tcb.tcbState.get_blocking_object() == self.get_ptr());
         let mut queue = self.get_queue();
         queue.ep_dequeue(tcb);
         self.set_queue(&queue);
+        #[cfg(feature = "ENABLE_BENCHMARK")]
+        crate::ep_stats::ep_stats_dequeued(self.get_ptr());
         if queue.head == 0 {
             self.set_state(EPState::Idle as usize);
         }
-        set_thread_state(tcb, ThreadState::ThreadStateInactive);
+        tcb.set_inactive();
+    }
+
+    /// Move the tcb blocked on the endpoint to the place of its new priority in the queue
+    #[cfg(feature = "ENABLE_ORDERED_IPC")]
+    pub fn reorder(&mut self, tcb: &mut tcb_t) {
+        let mut queue = self.get_queue();
+        queue.ep_reorder(tcb);
+        self.set_queue(&queue);
     }
 
     #[inline]
@@ -93,14 +120,16 @@ impl endpoint_t {
         match self.get_state() {
             EPState::Idle => {}
             _ => {
-                let mut op_thread = convert_to_option_mut_type_ref::<tcb_t>(self.get_queue_head());
+                let mut op_thread = self.get_queue().head_tcb();
                 self.set_state(EPState::Idle as usize);
                 self.set_queue_head(0);
                 self.set_queue_tail(0);
+                #[cfg(feature = "ENABLE_BENCHMARK")]
+                crate::ep_stats::ep_stats_emptied(self.get_ptr());
                 while let Some(thread) = op_thread {
-                    set_thread_state(thread, ThreadState::ThreadStateRestart);
+                    thread.set_restart();
                     thread.sched_enqueue();
-                    op_thread = convert_to_option_mut_type_ref::<tcb_t>(thread.tcbEPNext);
+                    op_thread = thread.ep_next();
                 }
                 rescheduleRequired();
             }
@@ -108,33 +137,42 @@ impl endpoint_t {
     }
 
     /// Cancel badged sends in the endpoint, and set the tcb to restart
+    ///
+    /// The senders are taken off the queue one by one with a preemption point after each, so a
+    /// preempted invocation leaves a consistent queue and picks up the senders left when it restarts.
     /// # Arguments
     /// * `badge` - The badge to cancel
-    pub fn cancel_badged_sends(&mut self, badge: usize) {
-        match self.get_state() {
-            EPState::Idle | EPState::Recv => {}
-            EPState::Send => {
-                let mut queue = self.get_queue();
+    pub fn cancel_badged_sends(&mut self, badge: usize) -> exception_t {
+        if self.get_state() != EPState::Send {
+            return exception_t::EXCEPTION_NONE;
+        }
+        rescheduleRequired();
+        let mut op_thread = self.get_queue().head_tcb();
+        while let Some(thread) = op_thread {
+            op_thread = thread.ep_next();
+            if thread.tcbState.get_blocking_ipc_badge() != badge {
+                continue;
+            }
+            let mut queue = self.get_queue();
+            queue.ep_dequeue(thread);
+            self.set_queue(&queue);
+            #[cfg(feature = "ENABLE_BENCHMARK")]
+            crate::ep_stats::ep_stats_dequeued(self.get_ptr());
+            if queue.empty() {
                 self.set_state(EPState::Idle as usize);
-                self.set_queue_head(0);
-                self.set_queue_tail(0);
-                let mut thread_ptr = queue.head;
-                while thread_ptr != 0 {
-                    let thread = convert_to_mut_type_ref::<tcb_t>(thread_ptr);
-                    thread_ptr = thread.tcbEPNext;
-                    if thread.tcbState.get_blocking_ipc_badge() == badge {
-                        set_thread_state(thread, ThreadState::ThreadStateRestart);
-                        thread.sched_enqueue();
-                        queue.ep_dequeue(thread);
-                    }
-                }
-                self.set_queue(&queue);
-                if queue.head != 0 {
-                    self.set_state(EPState::Send as usize);
-                }
-                rescheduleRequired();
+            }
+            #[cfg(feature = "ENABLE_MCS")]
+            if let Some(reply) = reply_t::of_tcb(thread) {
+                reply.unlink(thread);
+            }
+            thread.set_restart();
+            thread.sched_enqueue();
+            let status = unsafe { preemptionPoint() };
+            if status != exception_t::EXCEPTION_NONE {
+                return status;
             }
         }
+        exception_t::EXCEPTION_NONE
     }
 
     /// Send an IPC to the endpoint, if the endpoint is idle or send, the tcb will be blocked immediately
@@ -154,51 +192,84 @@ impl endpoint_t {
         badge: usize,
         can_grant_reply: bool,
     ) {
+        #[cfg(feature = "ENABLE_BENCHMARK")]
+        crate::ep_stats::ep_stats_count_send(self.get_ptr(), do_call);
+        kernel_assert!(
+            1002,
+            (self.get_state() == EPState::Idle) == (self.get_queue_head() == 0)
+        );
         match self.get_state() {
             EPState::Idle | EPState::Send => {
                 if blocking {
-                    src_thread
-                        .tcbState
-                        .set_ts_type(ThreadState::ThreadStateBlockedOnSend as usize);
-                    src_thread.tcbState.set_blocking_object(self.get_ptr());
-                    src_thread
-                        .tcbState
-                        .set_blocking_ipc_can_grant(can_grant as usize);
-                    src_thread.tcbState.set_blocking_ipc_badge(badge);
-                    src_thread
-                        .tcbState
-                        .set_blocking_ipc_can_grant_reply(can_grant_reply as usize);
-                    src_thread
-                        .tcbState
-                        .set_blocking_ipc_is_call(do_call as usize);
-                    schedule_tcb(src_thread);
+                    src_thread.block_on_send(
+                        self.get_ptr(),
+                        badge,
+                        can_grant,
+                        can_grant_reply,
+                        do_call,
+                    );
 
                     let mut queue = self.get_queue();
                     queue.ep_append(src_thread);
                     self.set_state(EPState::Send as usize);
                     self.set_queue(&queue);
+                    #[cfg(feature = "ENABLE_BENCHMARK")]
+                    crate::ep_stats::ep_stats_queued(self.get_ptr());
                 }
             }
 
             EPState::Recv => {
                 let mut queue = self.get_queue();
-                let op_dest_thread = convert_to_option_mut_type_ref::<tcb_t>(queue.head);
+                let op_dest_thread = queue.head_tcb();
                 assert!(op_dest_thread.is_some());
                 let dest_thread = op_dest_thread.unwrap();
+                kernel_assert!(
+                    1003,
+                    dest_thread.get_state() == ThreadState::ThreadStateBlockedOnReceive
+                );
                 queue.ep_dequeue(dest_thread);
                 self.set_queue(&queue);
+                #[cfg(feature = "ENABLE_BENCHMARK")]
+                crate::ep_stats::ep_stats_dequeued(self.get_ptr());
                 if queue.empty() {
                     self.set_state(EPState::Idle as usize);
                 }
-                src_thread.do_ipc_transfer(dest_thread, Some(self), badge, can_grant);
-                let reply_can_grant = dest_thread.tcbState.get_blocking_ipc_can_grant() != 0;
-                set_thread_state(dest_thread, ThreadState::ThreadStateRunning);
-                possible_switch_to(dest_thread);
-                if do_call {
-                    if can_grant || can_grant_reply {
-                        dest_thread.setup_caller_cap(src_thread, reply_can_grant);
-                    } else {
-                        set_thread_state(src_thread, ThreadState::ThreadStateInactive);
+                #[cfg(feature = "ENABLE_MCS")]
+                {
+                    let mut op_reply = reply_t::of_tcb(dest_thread);
+                    if let Some(reply) = op_reply.as_deref_mut() {
+                        reply.unlink(dest_thread);
+                    }
+                    src_thread.do_ipc_transfer(dest_thread, Some(self), badge, can_grant);
+                    if do_call {
+                        match op_reply {
+                            Some(reply) if can_grant || can_grant_reply => {
+                                reply.push(src_thread, dest_thread, true)
+                            }
+                            _ => src_thread.set_inactive(),
+                        }
+                    }
+                    dest_thread.set_running();
+                    if sched_context_resume(dest_thread) {
+                        possible_switch_to(dest_thread);
+                    }
+                }
+                #[cfg(not(feature = "ENABLE_MCS"))]
+                {
+                    src_thread.do_ipc_transfer(dest_thread, Some(self), badge, can_grant);
+                    let reply_can_grant = dest_thread.tcbState.get_blocking_ipc_can_grant() != 0;
+                    dest_thread.set_running();
+                    possible_switch_to(dest_thread);
+                    #[cfg(feature = "ENABLE_CALL_DONATION")]
+                    if do_call && sel4_task::get_ks_scheduler_action() == dest_thread.get_ptr() {
+                        sel4_task::donate_timeslice(src_thread, dest_thread);
+                    }
+                    if do_call {
+                        if can_grant || can_grant_reply {
+                            dest_thread.setup_caller_cap(src_thread, reply_can_grant);
+                        } else {
+                            src_thread.set_inactive();
+                        }
                     }
                 }
             }
@@ -211,47 +282,80 @@ impl endpoint_t {
     /// * `thread` - The thread to receive the IPC
     /// * `is_blocking` - If the IPC is blocking
     /// * `grant` - If the IPC can grant
-    pub fn receive_ipc(&mut self, thread: &mut tcb_t, is_blocking: bool, grant: bool) {
+    /// * `reply` - The reply object a caller blocks on, with `ENABLE_MCS`
+    pub fn receive_ipc(
+        &mut self,
+        thread: &mut tcb_t,
+        is_blocking: bool,
+        grant: bool,
+        #[cfg(feature = "ENABLE_MCS")] mut reply: Option<&mut reply_t>,
+    ) {
+        #[cfg(feature = "ENABLE_MCS")]
+        if let Some(reply) = reply.as_deref_mut() {
+            reply.reclaim(thread);
+        }
         if thread.complete_signal() {
             return;
         }
         match self.get_state() {
             EPState::Idle | EPState::Recv => {
                 if is_blocking {
-                    thread.tcbState.set_blocking_object(self.get_ptr());
-                    thread.tcbState.set_blocking_ipc_can_grant(grant as usize);
-                    set_thread_state(thread, ThreadState::ThreadStateBlockedOnReceive);
+                    thread.block_on_receive(self.get_ptr(), grant);
+                    #[cfg(feature = "ENABLE_MCS")]
+                    if let Some(reply) = reply {
+                        reply.link_receiver(thread);
+                    }
                     let mut queue = self.get_queue();
                     queue.ep_append(thread);
                     self.set_state(EPState::Recv as usize);
                     self.set_queue(&queue);
+                    #[cfg(feature = "ENABLE_BENCHMARK")]
+                    crate::ep_stats::ep_stats_queued(self.get_ptr());
                 } else {
                     // NBReceive failed
                     thread.tcbArch.set_register(ArchReg::Badge, 0);
                 }
             }
             EPState::Send => {
+                thread.prefetch_ipc_buffer();
                 let mut queue = self.get_queue();
                 assert!(!queue.empty());
-                let sender = convert_to_mut_type_ref::<tcb_t>(queue.head);
+                let sender = queue.head_tcb().unwrap();
+                kernel_assert!(
+                    1004,
+                    sender.get_state() == ThreadState::ThreadStateBlockedOnSend
+                );
                 queue.ep_dequeue(sender);
                 self.set_queue(&queue);
+                #[cfg(feature = "ENABLE_BENCHMARK")]
+                crate::ep_stats::ep_stats_dequeued(self.get_ptr());
                 if queue.empty() {
                     self.set_state(EPState::Idle as usize);
                 }
-                let badge = sender.tcbState.get_blocking_ipc_badge();
-                let can_grant = sender.tcbState.get_blocking_ipc_can_grant() != 0;
-                let can_grant_reply = sender.tcbState.get_blocking_ipc_can_grant_reply() != 0;
+                // The badge and rights were taken from the sender's cap when it blocked,
+                // read them in one go instead of touching the sender's tcbState repeatedly
+                let sender_state = sender.tcbState;
+                let badge = sender_state.get_blocking_ipc_badge();
+                let can_grant = sender_state.get_blocking_ipc_can_grant() != 0;
+                let can_grant_reply = sender_state.get_blocking_ipc_can_grant_reply() != 0;
+                let do_call = sender_state.get_blocking_ipc_is_call() != 0;
                 sender.do_ipc_transfer(thread, Some(self), badge, can_grant);
-                let do_call = sender.tcbState.get_blocking_ipc_is_call() != 0;
                 if do_call {
+                    #[cfg(feature = "ENABLE_MCS")]
+                    match reply {
+                        Some(reply) if can_grant || can_grant_reply => {
+                            reply.push(sender, thread, true)
+                        }
+                        _ => sender.set_inactive(),
+                    }
+                    #[cfg(not(feature = "ENABLE_MCS"))]
                     if can_grant || can_grant_reply {
                         thread.setup_caller_cap(sender, grant);
                     } else {
-                        set_thread_state(sender, ThreadState::ThreadStateInactive);
+                        sender.set_inactive();
                     }
                 } else {
-                    set_thread_state(sender, ThreadState::ThreadStateRunning);
+                    sender.set_running();
                     possible_switch_to(sender);
                 }
             }
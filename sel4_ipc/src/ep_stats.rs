@@ -0,0 +1,177 @@
+//! Per-endpoint IPC statistics, enabled by the `ENABLE_BENCHMARK` feature.
+//!
+//! Both words of an endpoint are taken by its queue and state, so the counters live in a table
+//! keyed by the endpoint address. The entry of an endpoint is freed when its final cap is
+//! finalised, the counters are cleared by `SysBenchmarkResetLog`, and read back with
+//! `SysBenchmarkGetEndpointStats`. The length of the queue is counted as threads are queued and
+//! taken off, to keep the high-watermark without walking the queue.
+//!
+//! Endpoints beyond the capacity of the table are not tracked.
+
+/// Number of endpoints that can be tracked at the same time.
+const EP_STATS_TABLE_SIZE: usize = 1024;
+
+#[derive(Clone, Copy, Debug, Default)]
+/// IPC counters of one endpoint
+pub struct ep_stats_t {
+    /// Number of `Call`s made on the endpoint
+    pub calls: usize,
+    /// Number of `Send`s and `NBSend`s made on the endpoint
+    pub sends: usize,
+    /// Largest number of threads queued on the endpoint at once
+    pub queue_high_watermark: usize,
+}
+
+#[derive(Clone, Copy)]
+struct ep_stats_entry_t {
+    ptr: usize,
+    /// Number of threads queued on the endpoint now
+    queued: usize,
+    stats: ep_stats_t,
+}
+
+const EMPTY_STATS: ep_stats_t = ep_stats_t {
+    calls: 0,
+    sends: 0,
+    queue_high_watermark: 0,
+};
+
+const EMPTY_ENTRY: ep_stats_entry_t = ep_stats_entry_t {
+    ptr: 0,
+    queued: 0,
+    stats: EMPTY_STATS,
+};
+
+static mut ksEndpointStats: [ep_stats_entry_t; EP_STATS_TABLE_SIZE] =
+    [EMPTY_ENTRY; EP_STATS_TABLE_SIZE];
+
+#[inline]
+fn ep_stats_table() -> &'static mut [ep_stats_entry_t; EP_STATS_TABLE_SIZE] {
+    unsafe { &mut *core::ptr::addr_of_mut!(ksEndpointStats) }
+}
+
+/// The index the entry of `ptr` is probed from.
+#[inline]
+fn ep_stats_home(ptr: usize) -> usize {
+    (ptr >> 4) % EP_STATS_TABLE_SIZE
+}
+
+/// Find the index of the entry of `ptr`, or of the empty entry where it would be inserted.
+fn ep_stats_find(ptr: usize) -> Option<usize> {
+    let table = ep_stats_table();
+    let start = ep_stats_home(ptr);
+    for i in 0..EP_STATS_TABLE_SIZE {
+        let index = (start + i) % EP_STATS_TABLE_SIZE;
+        if table[index].ptr == ptr || table[index].ptr == 0 {
+            return Some(index);
+        }
+    }
+    None
+}
+
+/// Free the entry at `hole`, moving back the entries after it which were probed past it, so every
+/// entry stays reachable from its home without tombstones.
+fn ep_stats_remove(mut hole: usize) {
+    let table = ep_stats_table();
+    table[hole] = EMPTY_ENTRY;
+    let mut index = hole;
+    for _ in 1..EP_STATS_TABLE_SIZE {
+        index = (index + 1) % EP_STATS_TABLE_SIZE;
+        if table[index].ptr == 0 {
+            return;
+        }
+        let from_home =
+            (index + EP_STATS_TABLE_SIZE - ep_stats_home(table[index].ptr)) % EP_STATS_TABLE_SIZE;
+        let from_hole = (index + EP_STATS_TABLE_SIZE - hole) % EP_STATS_TABLE_SIZE;
+        if from_home >= from_hole {
+            table[hole] = table[index];
+            table[index] = EMPTY_ENTRY;
+            hole = index;
+        }
+    }
+}
+
+/// Get the entry of the endpoint at `ptr` if it is tracked.
+fn ep_stats_tracked(ptr: usize) -> Option<&'static mut ep_stats_entry_t> {
+    ep_stats_find(ptr)
+        .map(|index| &mut ep_stats_table()[index])
+        .filter(|entry| entry.ptr == ptr)
+}
+
+/// Get the entry of the endpoint at `ptr`, starting to track it if needed.
+fn ep_stats_entry(ptr: usize) -> Option<&'static mut ep_stats_entry_t> {
+    ep_stats_find(ptr).map(|index| {
+        let entry = &mut ep_stats_table()[index];
+        entry.ptr = ptr;
+        entry
+    })
+}
+
+#[inline]
+/// Account a `Call` or a `Send` on the endpoint at `ptr`.
+pub fn ep_stats_count_send(ptr: usize, do_call: bool) {
+    if let Some(entry) = ep_stats_entry(ptr) {
+        if do_call {
+            entry.stats.calls += 1;
+        } else {
+            entry.stats.sends += 1;
+        }
+    }
+}
+
+#[inline]
+/// Update the queue high-watermark of the endpoint at `ptr` after a thread was queued on it.
+pub fn ep_stats_queued(ptr: usize) {
+    if let Some(entry) = ep_stats_entry(ptr) {
+        entry.queued += 1;
+        entry.stats.queue_high_watermark = entry.stats.queue_high_watermark.max(entry.queued);
+    }
+}
+
+#[inline]
+/// Account a thread taken off the queue of the endpoint at `ptr`.
+pub fn ep_stats_dequeued(ptr: usize) {
+    if let Some(entry) = ep_stats_tracked(ptr) {
+        entry.queued = entry.queued.saturating_sub(1);
+    }
+}
+
+/// Account the queue of the endpoint at `ptr` being emptied at once, by `cancel_all_ipc`.
+pub fn ep_stats_emptied(ptr: usize) {
+    if let Some(entry) = ep_stats_tracked(ptr) {
+        entry.queued = 0;
+    }
+}
+
+/// Get the counters of the endpoint at `ptr`, all zero if it is not tracked.
+pub fn ep_stats_get(ptr: usize) -> ep_stats_t {
+    ep_stats_tracked(ptr).map_or(EMPTY_STATS, |entry| entry.stats)
+}
+
+/// Stop tracking the endpoint at `ptr`, called when its final cap is finalised.
+pub fn ep_stats_clear(ptr: usize) {
+    if let Some(index) = ep_stats_find(ptr) {
+        if ep_stats_table()[index].ptr == ptr {
+            ep_stats_remove(index);
+        }
+    }
+}
+
+/// Clear the counters of every endpoint. Only the endpoints with threads queued stay tracked,
+/// with the threads queued now as their high-watermark.
+pub fn ep_stats_reset() {
+    let mut index = 0;
+    while index < EP_STATS_TABLE_SIZE {
+        let entry = &mut ep_stats_table()[index];
+        if entry.ptr != 0 && entry.queued == 0 {
+            // An entry moved back into `index` is looked at next
+            ep_stats_remove(index);
+            continue;
+        }
+        entry.stats = ep_stats_t {
+            queue_high_watermark: entry.queued,
+            ..EMPTY_STATS
+        };
+        index += 1;
+    }
+}
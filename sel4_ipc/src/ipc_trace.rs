@@ -0,0 +1,45 @@
+//! Per-message IPC tracing, enabled by the `ENABLE_IPC_TRACE` feature.
+//!
+//! While tracing is on, every message that is delivered writes one line to the kernel log, so it
+//! ends up in the console ring like any other kernel message:
+//!
+//! ```text
+//! ipc ep=0x... 0x...->0x... badge=0x... label=0x... len=N
+//! ```
+//!
+//! `ep` is 0 for a reply. Both the slowpath transfers and the fastpath are traced. Tracing starts
+//! off and is switched at runtime with `SysDebugIpcTrace`.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use sel4_common::message_info::seL4_MessageInfo_t;
+
+static IPC_TRACE_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Switch tracing on or off, returns whether it was on before.
+pub fn ipc_trace_set_enabled(enabled: bool) -> bool {
+    IPC_TRACE_ENABLED.swap(enabled, Ordering::Relaxed)
+}
+
+#[inline]
+/// Trace the message `info` delivered from the TCB at `sender` to the TCB at `receiver` through
+/// the endpoint at `ep`.
+pub fn ipc_trace_record(
+    ep: usize,
+    sender: usize,
+    receiver: usize,
+    badge: usize,
+    info: &seL4_MessageInfo_t,
+) {
+    if IPC_TRACE_ENABLED.load(Ordering::Relaxed) {
+        log::info!(
+            "ipc ep={:#x} {:#x}->{:#x} badge={:#x} label={:#x} len={}",
+            ep,
+            sender,
+            receiver,
+            badge,
+            info.get_usize_label(),
+            info.get_length()
+        );
+    }
+}
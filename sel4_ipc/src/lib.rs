@@ -7,11 +7,20 @@
 #![allow(non_snake_case)]
 #![allow(non_camel_case_types)]
 #![allow(non_upper_case_globals)]
+#![deny(unsafe_op_in_unsafe_fn)]
 
 mod endpoint;
+#[cfg(feature = "ENABLE_BENCHMARK")]
+pub mod ep_stats;
+#[cfg(feature = "ENABLE_IPC_TRACE")]
+pub mod ipc_trace;
 mod notification;
+#[cfg(feature = "ENABLE_MCS")]
+mod reply;
 mod transfer;
 
 pub use endpoint::*;
 pub use notification::*;
+#[cfg(feature = "ENABLE_MCS")]
+pub use reply::*;
 pub use transfer::*;
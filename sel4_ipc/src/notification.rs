@@ -1,9 +1,9 @@
 use crate::transfer::Transfer;
 use sel4_common::arch::ArchReg;
 use sel4_common::plus_define_bitfield;
-use sel4_common::utils::{convert_to_mut_type_ref, convert_to_option_mut_type_ref};
+use sel4_common::utils::convert_to_option_checked_mut_type_ref;
 use sel4_task::{
-    possible_switch_to, rescheduleRequired, set_thread_state, tcb_queue_t, tcb_t, ThreadState,
+    possible_switch_to, rescheduleRequired, tcb_queue_t, tcb_t, ThreadState, ThreadStateMachine,
 };
 
 #[derive(PartialEq, Eq, Debug)]
@@ -43,10 +43,21 @@ plus_define_bitfield! {
 }
 
 impl notification_t {
+    #[inline]
+    /// Make the notification idle, unbound and with an empty queue, called when it is retyped
+    pub fn init(&mut self) {
+        *self = notification_t::new(0, 0, 0, 0, NtfnState::Idle as usize);
+    }
+
     #[inline]
     /// Get the state of the notification
     pub fn get_state(&self) -> NtfnState {
-        unsafe { core::mem::transmute::<u8, NtfnState>(self.get_usize_state() as u8) }
+        match self.get_usize_state() {
+            0 => NtfnState::Idle,
+            1 => NtfnState::Waiting,
+            2 => NtfnState::Active,
+            state => unreachable!("invalid notification state {}", state),
+        }
     }
 
     #[inline]
@@ -58,6 +69,12 @@ impl notification_t {
         }
     }
 
+    #[inline]
+    /// Get the thread bound to the notification
+    pub fn bound_tcb(&self) -> Option<&'static mut tcb_t> {
+        convert_to_option_checked_mut_type_ref::<tcb_t>(self.get_bound_tcb())
+    }
+
     #[inline]
     /// Set the tcb queue to the notification
     pub fn set_queue(&mut self, queue: &tcb_queue_t) {
@@ -85,21 +102,29 @@ impl notification_t {
         if queue.head == 0 {
             self.set_state(NtfnState::Idle as usize);
         }
-        set_thread_state(tcb, ThreadState::ThreadStateInactive);
+        tcb.set_inactive();
+    }
+
+    /// Move the tcb blocked on the notification to the place of its new priority in the queue
+    #[cfg(feature = "ENABLE_ORDERED_IPC")]
+    pub fn reorder(&mut self, tcb: &mut tcb_t) {
+        let mut queue = self.get_queue();
+        queue.ep_reorder(tcb);
+        self.set_queue(&queue);
     }
 
     #[inline]
     /// Cancel all signal in the notification
     pub fn cacncel_all_signal(&mut self) {
         if self.get_state() == NtfnState::Waiting {
-            let mut op_thread = convert_to_option_mut_type_ref::<tcb_t>(self.get_queue_head());
+            let mut op_thread = self.get_queue().head_tcb();
             self.set_state(NtfnState::Idle as usize);
             self.set_queue_head(0);
             self.set_queue_tail(0);
             while let Some(thread) = op_thread {
-                set_thread_state(thread, ThreadState::ThreadStateRestart);
+                thread.set_restart();
                 thread.sched_enqueue();
-                op_thread = convert_to_option_mut_type_ref::<tcb_t>(thread.tcbEPNext);
+                op_thread = thread.ep_next();
             }
             rescheduleRequired();
         }
@@ -120,10 +145,10 @@ impl notification_t {
     #[inline]
     /// Safely unbind the tcb to the notification
     pub fn safe_unbind_tcb(&mut self) {
-        let tcb = self.get_bound_tcb();
+        let tcb = self.bound_tcb();
         self.unbind_tcb();
-        if tcb != 0 {
-            convert_to_mut_type_ref::<tcb_t>(tcb).unbind_notification();
+        if let Some(tcb) = tcb {
+            tcb.unbind_notification();
         }
     }
 
@@ -143,10 +168,10 @@ impl notification_t {
     pub fn send_signal(&mut self, badge: usize) {
         match self.get_state() {
             NtfnState::Idle => {
-                if let Some(tcb) = convert_to_option_mut_type_ref::<tcb_t>(self.get_bound_tcb()) {
+                if let Some(tcb) = self.bound_tcb() {
                     if tcb.get_state() == ThreadState::ThreadStateBlockedOnReceive {
                         tcb.cancel_ipc();
-                        set_thread_state(tcb, ThreadState::ThreadStateRunning);
+                        tcb.set_running();
                         tcb.tcbArch.set_register(ArchReg::Badge, badge);
                         possible_switch_to(tcb);
                     } else {
@@ -158,13 +183,13 @@ impl notification_t {
             }
             NtfnState::Waiting => {
                 let mut queue = self.get_queue();
-                if let Some(dest) = convert_to_option_mut_type_ref::<tcb_t>(queue.head) {
+                if let Some(dest) = queue.head_tcb() {
                     queue.ep_dequeue(dest);
                     self.set_queue(&queue);
                     if queue.empty() {
                         self.set_state(NtfnState::Idle as usize);
                     }
-                    set_thread_state(dest, ThreadState::ThreadStateRunning);
+                    dest.set_running();
                     dest.tcbArch.set_register(ArchReg::Badge, badge);
                     possible_switch_to(dest);
                 } else {
@@ -189,8 +214,7 @@ impl notification_t {
         match self.get_state() {
             NtfnState::Idle | NtfnState::Waiting => {
                 if is_blocking {
-                    recv_thread.tcbState.set_blocking_object(self.get_ptr());
-                    set_thread_state(recv_thread, ThreadState::ThreadStateBlockedOnNotification);
+                    recv_thread.block_on_notification(self.get_ptr());
                     let mut queue = self.get_queue();
                     queue.ep_append(recv_thread);
                     self.set_state(NtfnState::Waiting as usize);
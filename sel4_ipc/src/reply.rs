@@ -0,0 +1,207 @@
+//! Reply objects, enabled by the `ENABLE_MCS` feature.
+//!
+//! A thread receives with a reply object, named by the reply cap in `ArchReg::Reply`, and a
+//! caller it takes a call from blocks on that object until the reply cap is invoked. There is no
+//! implicit reply slot in the TCB.
+//!
+//! A receiver without a scheduling context runs on the one of the caller until it replies. The
+//! reply object is pushed on the call stack of the scheduling context, `scReply` is its top and
+//! `replyPrev` links down to the reply objects it was lent through before. Replying pops the top
+//! and gives the scheduling context back to the caller.
+
+use core::intrinsics::likely;
+
+use sel4_common::fault::{seL4_Fault_t, FaultType};
+use sel4_common::utils::{convert_to_mut_type_ref, convert_to_option_checked_mut_type_ref};
+use sel4_task::sched_context::{sched_context_resume, sched_context_t};
+use sel4_task::{possible_switch_to, tcb_t, ThreadState, ThreadStateMachine};
+use sel4_vspace::pptr_t;
+
+use crate::transfer::Transfer;
+
+#[repr(C)]
+#[derive(Debug)]
+/// A reply object
+pub struct reply_t {
+    /// The caller blocked on the reply object or the receiver waiting with it, 0 for none
+    pub replyTCB: usize,
+    /// The reply object below on the call stack, 0 for the bottom
+    pub replyPrev: usize,
+    /// The reply object above on the call stack, 0 for the top
+    pub replyNext: usize,
+    /// The scheduling context of the call stack, set only for the top
+    pub replySC: usize,
+}
+
+impl reply_t {
+    #[inline]
+    /// Get the raw pointer(usize) to the reply object
+    pub fn get_ptr(&self) -> pptr_t {
+        self as *const Self as pptr_t
+    }
+
+    #[inline]
+    /// The reply object `tcb` waits with or is blocked on
+    pub fn of_tcb(tcb: &tcb_t) -> Option<&'static mut reply_t> {
+        convert_to_option_checked_mut_type_ref::<reply_t>(tcb.tcbReplyObject)
+    }
+
+    #[inline]
+    /// The thread blocked on or waiting with the reply object
+    fn tcb(&self) -> Option<&'static mut tcb_t> {
+        convert_to_option_checked_mut_type_ref::<tcb_t>(self.replyTCB)
+    }
+
+    #[inline]
+    fn prev(&self) -> Option<&'static mut reply_t> {
+        convert_to_option_checked_mut_type_ref::<reply_t>(self.replyPrev)
+    }
+
+    #[inline]
+    fn next(&self) -> Option<&'static mut reply_t> {
+        convert_to_option_checked_mut_type_ref::<reply_t>(self.replyNext)
+    }
+
+    #[inline]
+    fn sc(&self) -> Option<&'static mut sched_context_t> {
+        convert_to_option_checked_mut_type_ref::<sched_context_t>(self.replySC)
+    }
+
+    /// Unlink the reply object from any caller and call stack, called when it is retyped.
+    pub fn init(&mut self) {
+        self.replyTCB = 0;
+        self.unlink_stack();
+    }
+
+    /// Free the reply object for `receiver` to receive with. A caller still blocked on it gets no
+    /// reply any more, another receiver waiting with it stops waiting.
+    pub fn reclaim(&mut self, receiver: &tcb_t) {
+        if let Some(tcb) = self.tcb() {
+            if tcb.get_ptr() != receiver.get_ptr() {
+                tcb.cancel_ipc();
+            }
+        }
+    }
+
+    /// `receiver` waits for a call with the reply object.
+    pub fn link_receiver(&mut self, receiver: &mut tcb_t) {
+        self.replyTCB = receiver.get_ptr();
+        receiver.tcbReplyObject = self.get_ptr();
+    }
+
+    /// `tcb` stops waiting with or being blocked on the reply object and becomes inactive.
+    pub fn unlink(&mut self, tcb: &mut tcb_t) {
+        self.replyTCB = 0;
+        tcb.tcbReplyObject = 0;
+        tcb.set_inactive();
+    }
+
+    /// `caller` blocks on the reply object, which `callee` received its call with. With
+    /// `can_donate` a callee without a scheduling context gets the one of the caller, unless the
+    /// call is a timeout fault and the scheduling context has no budget to lend.
+    pub fn push(&mut self, caller: &mut tcb_t, callee: &mut tcb_t, can_donate: bool) {
+        caller.block_on_reply();
+        caller.tcbReplyObject = self.get_ptr();
+        self.replyTCB = caller.get_ptr();
+        if !can_donate
+            || caller.tcbSchedContext == 0
+            || callee.tcbSchedContext != 0
+            || caller.tcbFault.get_fault_type() == FaultType::Timeout
+        {
+            return;
+        }
+        let sc = convert_to_mut_type_ref::<sched_context_t>(caller.tcbSchedContext);
+        if let Some(top) = call_stack_top(sc) {
+            top.replyNext = self.get_ptr();
+            top.replySC = 0;
+        }
+        self.replyPrev = sc.scReply;
+        self.replySC = sc.get_ptr();
+        sc.scReply = self.get_ptr();
+        sc.donate(callee);
+    }
+
+    /// Take the reply object, which `caller` is blocked on, off its call stack. The top of a call
+    /// stack gives the scheduling context back to `caller`, elsewhere the stack breaks in two.
+    pub fn remove(&mut self, caller: &mut tcb_t) {
+        match self.sc() {
+            Some(sc) => {
+                sc.scReply = self.replyPrev;
+                if let Some(prev) = self.prev() {
+                    prev.replyNext = 0;
+                    prev.replySC = sc.get_ptr();
+                }
+                if caller.tcbSchedContext == 0 {
+                    sc.donate(caller);
+                }
+            }
+            None => self.break_stack(),
+        }
+        self.unlink_stack();
+        self.unlink(caller);
+    }
+
+    /// Take the reply object, which `caller` is blocked on, off its call stack when the call is
+    /// cancelled. The scheduling context stays where it is.
+    pub fn remove_tcb(&mut self, caller: &mut tcb_t) {
+        if let Some(sc) = self.sc() {
+            sc.scReply = 0;
+        }
+        self.break_stack();
+        self.unlink_stack();
+        self.unlink(caller);
+    }
+
+    fn break_stack(&mut self) {
+        if let Some(next) = self.next() {
+            next.replyPrev = 0;
+        }
+        if let Some(prev) = self.prev() {
+            prev.replyNext = 0;
+        }
+    }
+
+    fn unlink_stack(&mut self) {
+        self.replyPrev = 0;
+        self.replyNext = 0;
+        self.replySC = 0;
+    }
+
+    /// Reply from `replier` to the caller blocked on the reply object, if there is one.
+    pub fn do_reply(&mut self, replier: &mut tcb_t, grant: bool) {
+        let Some(caller) = self.tcb() else {
+            return;
+        };
+        if caller.get_state() != ThreadState::ThreadStateBlockedOnReply {
+            return;
+        }
+        let fault_type = caller.tcbFault.get_fault_type();
+        self.remove(caller);
+        if likely(fault_type == FaultType::NullFault) {
+            replier.do_ipc_transfer(caller, None, 0, grant);
+            caller.set_running();
+        } else if replier.do_fault_reply_transfer(caller) {
+            caller.tcbFault = seL4_Fault_t::new_null_fault();
+            caller.set_restart();
+        } else {
+            caller.tcbFault = seL4_Fault_t::new_null_fault();
+        }
+        if caller.is_runnable() && sched_context_resume(caller) {
+            possible_switch_to(caller);
+        }
+    }
+}
+
+/// The reply object on top of the call stack of `sc`
+#[inline]
+fn call_stack_top(sc: &sched_context_t) -> Option<&'static mut reply_t> {
+    convert_to_option_checked_mut_type_ref::<reply_t>(sc.scReply)
+}
+
+/// Stop lending the scheduling context through its call stack, when it is unbound or deleted.
+pub fn sched_context_unbind_reply(sc: &mut sched_context_t) {
+    if let Some(top) = call_stack_top(sc) {
+        top.replySC = 0;
+    }
+    sc.scReply = 0;
+}
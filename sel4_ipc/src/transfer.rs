@@ -2,6 +2,10 @@ use core::intrinsics::likely;
 
 use super::endpoint::*;
 use super::notification::*;
+#[cfg(feature = "ENABLE_MCS")]
+use super::reply::reply_t;
+#[cfg(feature = "ENABLE_MCS")]
+use sel4_task::sched_context::sched_context_t;
 
 use sel4_common::arch::ArchReg;
 use sel4_common::arch::{n_exceptionMessage, n_syscallMessage};
@@ -11,13 +15,15 @@ use sel4_common::sel4_config::*;
 use sel4_common::structures::*;
 use sel4_common::utils::*;
 use sel4_cspace::interface::*;
-use sel4_task::{possible_switch_to, set_thread_state, tcb_t, ThreadState};
+use sel4_task::{possible_switch_to, tcb_t, ThreadState, ThreadStateMachine};
 use sel4_vspace::pptr_t;
 
 /// The trait for IPC transfer, please see doc.md for more details
 pub trait Transfer {
     fn cancel_ipc(&mut self);
 
+    /// Transfer the extra caps of a message. A cap to the endpoint the message is sent through is
+    /// unwrapped: the receiver gets its badge, 0 for an unbadged cap, instead of the cap itself.
     fn set_transfer_caps(
         &mut self,
         endpoint: Option<&endpoint_t>,
@@ -66,6 +72,10 @@ impl Transfer for tcb_t {
                 let ep = convert_to_mut_type_ref::<endpoint_t>(state.get_blocking_object());
                 assert_ne!(ep.get_state(), EPState::Idle);
                 ep.cancel_ipc(self);
+                #[cfg(feature = "ENABLE_MCS")]
+                if let Some(reply) = reply_t::of_tcb(self) {
+                    reply.unlink(self);
+                }
             }
             ThreadState::ThreadStateBlockedOnNotification => {
                 let ntfn = convert_to_mut_type_ref::<notification_t>(state.get_blocking_object());
@@ -74,10 +84,17 @@ impl Transfer for tcb_t {
 
             ThreadState::ThreadStateBlockedOnReply => {
                 self.tcbFault = seL4_Fault_t::new_null_fault();
-                let slot = self.get_cspace(tcbReply);
-                let caller_slot_ptr = slot.cteMDBNode.get_next();
-                if caller_slot_ptr != 0 {
-                    convert_to_mut_type_ref::<cte_t>(caller_slot_ptr).delete_one()
+                #[cfg(feature = "ENABLE_MCS")]
+                if let Some(reply) = reply_t::of_tcb(self) {
+                    reply.remove_tcb(self);
+                }
+                #[cfg(not(feature = "ENABLE_MCS"))]
+                {
+                    let slot = self.get_cspace(tcbReply);
+                    let caller_slot_ptr = slot.cteMDBNode.get_next();
+                    if caller_slot_ptr != 0 {
+                        convert_to_mut_type_ref::<cte_t>(caller_slot_ptr).delete_one()
+                    }
                 }
             }
             _ => {}
@@ -211,7 +228,86 @@ impl Transfer for tcb_t {
                     seL4_VMFault_PrefetchFault,
                     self.tcbFault.vm_fault_get_instruction_fault(),
                 );
-                receiver.set_mr(seL4_VMFault_FSR, self.tcbFault.vm_fault_get_fsr())
+                #[cfg(target_arch = "riscv64")]
+                {
+                    receiver.set_mr(seL4_VMFault_FSR, self.tcbFault.vm_fault_get_fsr())
+                }
+                #[cfg(target_arch = "aarch64")]
+                {
+                    let fsr = self.tcbFault.vm_fault_get_fsr();
+                    receiver.set_mr(seL4_VMFault_FSR, fsr);
+                    let access =
+                        vm_fault_access(fsr, self.tcbFault.vm_fault_get_instruction_fault());
+                    receiver.set_mr(seL4_VMFault_Width, access[0]);
+                    receiver.set_mr(seL4_VMFault_SignExtend, access[1]);
+                    receiver.set_mr(seL4_VMFault_Register, access[2]);
+                    receiver.set_mr(seL4_VMFault_Write, access[3])
+                }
+            }
+            #[cfg(any(feature = "ENABLE_MCS", feature = "ENABLE_CPU_LIMIT"))]
+            FaultType::Timeout => {
+                receiver.set_mr(seL4_Timeout_Data, self.tcbFault.timeout_get_badge());
+                // The scheduling context may have been unbound while the fault was queued
+                #[cfg(feature = "ENABLE_MCS")]
+                let consumed =
+                    convert_to_option_mut_type_ref::<sched_context_t>(self.tcbSchedContext)
+                        .map_or(0, |sc| sc.take_consumed());
+                // All the timer ticks the thread ran for, not only those since the limit was set
+                #[cfg(feature = "ENABLE_CPU_LIMIT")]
+                let consumed = self.tcbCPUTime;
+                receiver.set_mr(seL4_Timeout_Consumed, consumed)
+            }
+            #[cfg(target_arch = "aarch64")]
+            FaultType::VGICMaintenance => {
+                // The interrupt that was completed is not known if several were at once
+                let idx = if self.tcbFault.vgic_maintenance_get_idx_valid() != 0 {
+                    self.tcbFault.vgic_maintenance_get_idx()
+                } else {
+                    usize::MAX
+                };
+                receiver.set_mr(seL4_VGICMaintenance_IDX, idx)
+            }
+            FaultType::GuestVMFault => {
+                receiver.set_mr(
+                    seL4_GuestVMFault_IP,
+                    self.tcbArch.get_register(ArchReg::FaultIP),
+                );
+                receiver.set_mr(
+                    seL4_GuestVMFault_IPA,
+                    self.tcbFault.guest_vm_fault_get_ipa(),
+                );
+                receiver.set_mr(
+                    seL4_GuestVMFault_ISS,
+                    self.tcbFault.guest_vm_fault_get_iss(),
+                );
+                receiver.set_mr(
+                    seL4_GuestVMFault_Width,
+                    self.tcbFault.guest_vm_fault_get_width(),
+                );
+                receiver.set_mr(
+                    seL4_GuestVMFault_Write,
+                    self.tcbFault.guest_vm_fault_get_write(),
+                )
+            }
+            #[cfg(target_arch = "aarch64")]
+            FaultType::VCPUFault => {
+                receiver.set_mr(seL4_VCPUFault_HSR, self.tcbFault.vcpu_fault_get_hsr())
+            }
+            #[cfg(target_arch = "riscv64")]
+            FaultType::VCPUFault => {
+                receiver.set_mr(seL4_VCPUFault_HSR, self.tcbFault.vcpu_fault_get_hsr());
+                receiver.set_mr(seL4_VCPUFault_Data, self.tcbFault.vcpu_fault_get_data())
+            }
+            #[cfg(all(target_arch = "aarch64", feature = "ENABLE_MMIO_EMULATION"))]
+            FaultType::MMIOFault => {
+                receiver.set_mr(
+                    seL4_MMIOFault_IP,
+                    self.tcbArch.get_register(ArchReg::FaultIP),
+                );
+                receiver.set_mr(seL4_MMIOFault_Addr, self.tcbFault.mmio_fault_get_address());
+                receiver.set_mr(seL4_MMIOFault_Width, self.tcbFault.mmio_fault_get_width());
+                receiver.set_mr(seL4_MMIOFault_Write, self.tcbFault.mmio_fault_get_write());
+                receiver.set_mr(seL4_MMIOFault_Data, self.tcbFault.mmio_fault_get_data())
             }
             _ => {
                 panic!("invalid fault")
@@ -234,8 +330,9 @@ impl Transfer for tcb_t {
         let mut tag =
             seL4_MessageInfo_t::from_word_security(self.tcbArch.get_register(ArchReg::MsgInfo));
         let mut current_extra_caps = [0; seL4_MsgMaxExtraCaps];
-        if can_grant {
-            let _ = self.lookup_extra_caps(&mut current_extra_caps);
+        // A failed lookup sends no cap at all, not the ones looked up before it
+        if can_grant && self.lookup_extra_caps(&mut current_extra_caps).is_err() {
+            current_extra_caps[0] = 0;
         }
         let msg_transferred = self.copy_mrs(receiver, tag.get_length());
         receiver.set_transfer_caps(endpoint, &mut tag, &current_extra_caps);
@@ -244,12 +341,20 @@ impl Transfer for tcb_t {
             .tcbArch
             .set_register(ArchReg::MsgInfo, tag.to_word());
         receiver.tcbArch.set_register(ArchReg::Badge, badge);
+        #[cfg(feature = "ENABLE_IPC_TRACE")]
+        crate::ipc_trace::ipc_trace_record(
+            endpoint.map_or(0, |ep| ep.get_ptr()),
+            self.get_ptr(),
+            receiver.get_ptr(),
+            badge,
+            &tag,
+        );
     }
 
     fn do_fault_reply_transfer(&mut self, receiver: &mut tcb_t) -> bool {
         let tag =
             seL4_MessageInfo_t::from_word_security(self.tcbArch.get_register(ArchReg::MsgInfo));
-        let label = tag.get_label();
+        let label = tag.get_usize_label();
         let length = tag.get_length();
         match receiver.tcbFault.get_fault_type() {
             FaultType::UnknownSyscall => {
@@ -258,7 +363,7 @@ impl Transfer for tcb_t {
                     MessageID_Syscall,
                     core::cmp::min(length, n_syscallMessage),
                 );
-                return label as usize == 0;
+                return label == 0;
             }
             FaultType::UserException => {
                 self.copy_fault_mrs_for_reply(
@@ -266,7 +371,28 @@ impl Transfer for tcb_t {
                     MessageID_Exception,
                     core::cmp::min(length, n_exceptionMessage),
                 );
-                return label as usize == 0;
+                return label == 0;
+            }
+            // The faulting thread resumes on a reply with label 0 once it has budget again
+            FaultType::Timeout => label == 0,
+            // The load or store is done, the thread resumes after it whatever the label
+            #[cfg(all(target_arch = "aarch64", feature = "ENABLE_MMIO_EMULATION"))]
+            FaultType::MMIOFault => {
+                let fault = receiver.tcbFault;
+                if fault.mmio_fault_get_write() == 0 {
+                    let data = if length > 0 {
+                        self.tcbArch.get_register(ArchReg::Msg(0))
+                    } else {
+                        0
+                    };
+                    receiver.tcbArch.set_gp_register(
+                        fault.mmio_fault_get_register(),
+                        mmio_load_value(&fault, data),
+                    );
+                }
+                let ip = receiver.tcbArch.get_register(ArchReg::FaultIP);
+                receiver.tcbArch.set_register(ArchReg::FaultIP, ip + 4);
+                true
             }
             _ => true,
         }
@@ -293,6 +419,8 @@ impl Transfer for tcb_t {
         badge: usize,
         grant: bool,
     ) {
+        #[cfg(feature = "ENABLE_KERNEL_INFO_PAGE")]
+        sel4_common::kernel_info::kernel_info_count_ipc();
         if likely(self.tcbFault.get_fault_type() == FaultType::NullFault) {
             self.do_normal_transfer(receiver, endpoint, badge, grant)
         } else {
@@ -302,20 +430,61 @@ impl Transfer for tcb_t {
 
     fn do_reply(&mut self, receiver: &mut tcb_t, slot: &mut cte_t, grant: bool) {
         assert_eq!(receiver.get_state(), ThreadState::ThreadStateBlockedOnReply);
+        #[cfg(feature = "ENABLE_CALL_DONATION")]
+        sel4_task::return_timeslice(self, receiver);
         let fault_type = receiver.tcbFault.get_fault_type();
         if likely(fault_type == FaultType::NullFault) {
             self.do_ipc_transfer(receiver, None, 0, grant);
             slot.delete_one();
-            set_thread_state(receiver, ThreadState::ThreadStateRunning);
+            receiver.set_running();
             possible_switch_to(receiver);
         } else {
             slot.delete_one();
-            if self.do_fault_reply_transfer(receiver) {
-                set_thread_state(receiver, ThreadState::ThreadStateRestart);
+            let restart = self.do_fault_reply_transfer(receiver);
+            receiver.tcbFault = seL4_Fault_t::new_null_fault();
+            if restart {
+                receiver.set_restart();
                 possible_switch_to(receiver);
             } else {
-                set_thread_state(receiver, ThreadState::ThreadStateInactive);
+                receiver.set_inactive();
             }
         }
     }
 }
+
+/// The width in bytes, sign extension, register and direction of the data abort with the syndrome
+/// `fsr`, all 0 for a prefetch abort or when the syndrome does not hold them.
+#[cfg(target_arch = "aarch64")]
+fn vm_fault_access(fsr: usize, instruction_fault: usize) -> [usize; 4] {
+    use sel4_common::arch::sysreg::esr_el1;
+    if instruction_fault != 0 || esr_el1::ISV.get(fsr) == 0 {
+        return [0; 4];
+    }
+    [
+        1 << esr_el1::SAS.get(fsr),
+        esr_el1::SSE.get(fsr),
+        esr_el1::SRT.get(fsr),
+        esr_el1::WNR.get(fsr),
+    ]
+}
+
+/// The value a load of `fault` leaves in its register when `data` was read: truncated to the
+/// width of the access, sign extended if the load asked for it, and limited to 32 bits for a W
+/// register.
+#[cfg(all(target_arch = "aarch64", feature = "ENABLE_MMIO_EMULATION"))]
+fn mmio_load_value(fault: &seL4_Fault_t, data: usize) -> usize {
+    let bits = fault.mmio_fault_get_width() * 8;
+    let mut value = if bits >= usize::BITS as usize {
+        data
+    } else {
+        data & ((1 << bits) - 1)
+    };
+    if fault.mmio_fault_get_sign_extend() != 0 && bits < usize::BITS as usize {
+        let shift = usize::BITS as usize - bits;
+        value = (((value << shift) as isize) >> shift) as usize;
+    }
+    if fault.mmio_fault_get_sixty_four() == 0 {
+        value &= u32::MAX as usize;
+    }
+    value
+}
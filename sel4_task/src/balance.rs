@@ -0,0 +1,73 @@
+//! Idle-pull load balancing between cores, enabled by `ENABLE_SMP_LOAD_BALANCE`.
+//!
+//! When a core is about to run its idle thread it looks for the core with the most queued
+//! threads and pulls the highest priority one that is allowed to run here. A thread whose
+//! `tcbAffinityMask` is 0 may run on any core; `TCBSetAffinity` pins a thread by setting the
+//! mask to its single core, and [`AFFINITY_ANY_CORE`] resets it to 0. Pulls are counted per core
+//! in [`ksMigrationCount`].
+
+use sel4_common::sel4_config::{CONFIG_MAX_NUM_NODES, CONFIG_NUM_PRIORITIES};
+use sel4_common::utils::{convert_to_mut_type_ref, cpu_id};
+use sel4_common::BIT;
+
+use crate::scheduler::{ksCurDomain, ksSMP, ready_queues_index};
+use crate::tcb::tcb_t;
+
+/// The `TCBSetAffinity` argument which lets a thread run on any core again, all bits set since 0
+/// names the first core. The thread stays where it is until a core pulls it.
+pub const AFFINITY_ANY_CORE: usize = usize::MAX;
+
+/// Number of threads in the ready queues of each core.
+#[no_mangle]
+pub static mut ksReadyCount: [usize; CONFIG_MAX_NUM_NODES] = [0; CONFIG_MAX_NUM_NODES];
+
+/// Number of threads each core has pulled from another core.
+#[no_mangle]
+pub static mut ksMigrationCount: [usize; CONFIG_MAX_NUM_NODES] = [0; CONFIG_MAX_NUM_NODES];
+
+/// Whether `tcb` may be moved to `cpu`.
+#[inline]
+pub fn can_migrate_to(tcb: &tcb_t, cpu: usize) -> bool {
+    tcb.tcbAffinityMask == 0 || tcb.tcbAffinityMask & BIT!(cpu) != 0
+}
+
+/// Find the highest priority queued thread of `cpu` that may run on `target`.
+fn find_migratable(cpu: usize, target: usize) -> Option<&'static mut tcb_t> {
+    let dom = unsafe { ksCurDomain };
+    for prio in (0..CONFIG_NUM_PRIORITIES).rev() {
+        let mut ptr = unsafe { ksSMP[cpu].ksReadyQueues[ready_queues_index(dom, prio)].head };
+        while ptr != 0 {
+            let tcb = convert_to_mut_type_ref::<tcb_t>(ptr);
+            if can_migrate_to(tcb, target) {
+                return Some(tcb);
+            }
+            ptr = tcb.tcbSchedNext;
+        }
+    }
+    None
+}
+
+/// Called at idle entry: pull a thread from the busiest core onto the current core.
+/// The returned thread is no longer queued and already belongs to the current core.
+pub fn balance_pull() -> Option<&'static mut tcb_t> {
+    let me = cpu_id();
+    let mut busiest = me;
+    let mut most = 0;
+    for cpu in 0..CONFIG_MAX_NUM_NODES {
+        let count = unsafe { ksReadyCount[cpu] };
+        if cpu != me && count > most {
+            busiest = cpu;
+            most = count;
+        }
+    }
+    if busiest == me {
+        return None;
+    }
+    let tcb = find_migratable(busiest, me)?;
+    tcb.sched_dequeue();
+    tcb.migrate(me);
+    unsafe {
+        ksMigrationCount[me] += 1;
+    }
+    Some(tcb)
+}
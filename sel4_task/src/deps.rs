@@ -13,5 +13,13 @@ pub static mut ksIdleThreadTCB: ksIdleThreadTCB_data = ksIdleThreadTCB_data {
 };
 extern "C" {
     #[cfg(feature = "ENABLE_SMP")]
-    pub fn doMaskReschedule(mask: usize);
+    pub fn ipi_reschedule_mask(mask: usize);
+    #[cfg(feature = "ENABLE_SMP")]
+    pub fn tcbDebugAppend(tcb: *mut crate::tcb::tcb_t);
+    #[cfg(feature = "ENABLE_SMP")]
+    pub fn tcbDebugRemove(tcb: *mut crate::tcb::tcb_t);
+    #[cfg(feature = "ENABLE_MCS")]
+    pub fn handleTimeout(tptr: &mut crate::tcb::tcb_t) -> bool;
+    #[cfg(feature = "ENABLE_CPU_LIMIT")]
+    pub fn handleCPULimit(tptr: &mut crate::tcb::tcb_t);
 }
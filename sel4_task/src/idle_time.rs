@@ -0,0 +1,89 @@
+//! Time spent in the idle thread of each core, enabled by the `ENABLE_IDLE_TIME` feature.
+//!
+//! A period starts when a core switches to its idle thread and ends when it switches away, both
+//! in `set_current_thread`. Time is counted in the unit of `sel4_common::arch::get_time`, the
+//! timer count. The kernel reads the totals for `SysBenchmarkGetIdleTime` and mirrors them into
+//! the kernel info page, where they are brought up to date on every timer tick as well.
+
+use sel4_common::arch::get_time;
+use sel4_common::sel4_config::CONFIG_MAX_NUM_NODES;
+use sel4_common::utils::cpu_id;
+
+#[derive(Clone, Copy)]
+struct idle_time_t {
+    /// Idle time of the completed periods
+    total: usize,
+    /// Start of the running period, 0 while the core runs another thread
+    start: usize,
+}
+
+static mut ksIdleTime: [idle_time_t; CONFIG_MAX_NUM_NODES] =
+    [idle_time_t { total: 0, start: 0 }; CONFIG_MAX_NUM_NODES];
+
+/// Account the switch of the current core from the TCB at `prev` to the one at `next`, where
+/// `idle` is its idle thread.
+#[inline]
+pub fn idle_time_switch(prev: usize, next: usize, idle: usize) {
+    if prev == next {
+        return;
+    }
+    let core = cpu_id();
+    let now = get_time();
+    unsafe {
+        if prev == idle {
+            ksIdleTime[core].total += now.wrapping_sub(ksIdleTime[core].start);
+            ksIdleTime[core].start = 0;
+            publish(core);
+        }
+        if next == idle {
+            // A start of 0 would read as not idle
+            ksIdleTime[core].start = now.max(1);
+        }
+    }
+}
+
+/// Fold the running period of the current core into its total, so the kernel info page does not
+/// fall behind while the core stays idle.
+pub fn idle_time_tick() {
+    let core = cpu_id();
+    unsafe {
+        if ksIdleTime[core].start != 0 {
+            let now = get_time().max(1);
+            ksIdleTime[core].total += now.wrapping_sub(ksIdleTime[core].start);
+            ksIdleTime[core].start = now;
+            publish(core);
+        }
+    }
+}
+
+/// The idle time of `core` since boot or the last `idle_time_reset`, including the running period.
+pub fn idle_time_get(core: usize) -> usize {
+    if core >= CONFIG_MAX_NUM_NODES {
+        return 0;
+    }
+    let idle = unsafe { ksIdleTime[core] };
+    match idle.start {
+        0 => idle.total,
+        start => idle.total + get_time().wrapping_sub(start),
+    }
+}
+
+/// Clear the idle time of all cores. A core which is idle starts a new period now.
+pub fn idle_time_reset() {
+    let now = get_time().max(1);
+    for core in 0..CONFIG_MAX_NUM_NODES {
+        unsafe {
+            ksIdleTime[core].total = 0;
+            if ksIdleTime[core].start != 0 {
+                ksIdleTime[core].start = now;
+            }
+        }
+        publish(core);
+    }
+}
+
+#[inline]
+fn publish(_core: usize) {
+    #[cfg(feature = "ENABLE_KERNEL_INFO_PAGE")]
+    sel4_common::kernel_info::kernel_info_set_idle_time(_core, unsafe { ksIdleTime[_core].total });
+}
@@ -8,15 +8,30 @@
 #![allow(non_snake_case)]
 #![allow(non_camel_case_types)]
 #![allow(non_upper_case_globals)]
+#![deny(unsafe_op_in_unsafe_fn)]
 
+// With MCS the budget of the scheduling context already bounds the CPU time of a thread
+#[cfg(all(feature = "ENABLE_CPU_LIMIT", feature = "ENABLE_MCS"))]
+compile_error!("ENABLE_CPU_LIMIT is for builds without ENABLE_MCS");
+
+#[cfg(feature = "ENABLE_SMP_LOAD_BALANCE")]
+pub mod balance;
 mod deps;
+#[cfg(feature = "ENABLE_IDLE_TIME")]
+pub mod idle_time;
+#[cfg(feature = "ENABLE_MCS")]
+pub mod sched_context;
+#[cfg(feature = "ENABLE_SCHED_TRACE")]
+pub mod sched_trace;
 mod scheduler;
+mod state_machine;
 mod structures;
 pub mod tcb;
 mod tcb_queue;
 mod thread_state;
 
 pub use scheduler::*;
+pub use state_machine::*;
 pub use structures::*;
 pub use tcb::*;
 pub use tcb_queue::*;
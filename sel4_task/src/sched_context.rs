@@ -0,0 +1,492 @@
+//! Scheduling contexts, enabled by the `ENABLE_MCS` feature.
+//!
+//! A scheduling context grants the thread bound to it a budget of time in every period and is
+//! accounted as a sporadic server: time the thread runs is taken from the head refill and comes
+//! back as a refill one period after it was used. A thread whose head refill is used up or not
+//! yet released waits in a release queue and is made ready again by the tick that releases it.
+//! The refills and the release queue belong to the core set by `SchedControlConfigureFlags`.
+//!
+//! Time is counted in cycles of the timer counter. The kernel reads the counter once on every
+//! entry, see [`update_timestamp`], and adds the time since the previous entry to `ksConsumed`.
+//! It is charged to the scheduling context of the current thread only by [`commit_time`], when
+//! the kernel switches threads and when it returns to user space, so every cycle is charged
+//! exactly once, also across operations which are preempted and restarted. The budget is
+//! enforced on the timer tick and at preemption points, a thread may overrun it by up to a tick,
+//! which is taken from its next refills. With `ENABLE_TICKLESS` the timer fires at
+//! [`next_event`] instead, when the budget runs out. Threads without a scheduling context keep
+//! the round robin of `tcbTimeSlice`.
+//!
+//! Every scheduling context counts its overruns and its deadline misses, the periods in which a
+//! periodic thread ran out of budget while still runnable. `SchedContextConsumed` returns them
+//! after the consumed time and resets them.
+//!
+//! A call lends the scheduling context of the caller to a receiver without one through the reply
+//! object the receiver waits with, and the reply gives it back, see `sel4_ipc::reply_t`.
+//! `scReply` is the top of the stack of reply objects the scheduling context is lent through.
+
+use core::mem::size_of;
+
+use sel4_common::sel4_config::CONFIG_MAX_NUM_NODES;
+use sel4_common::utils::{convert_to_mut_type_ref, cpu_id};
+use sel4_common::BIT;
+
+use crate::deps::handleTimeout;
+use crate::scheduler::{get_currenct_thread, possible_switch_to, rescheduleRequired};
+use crate::tcb::tcb_t;
+use crate::tcb_queue::tcb_queue_t;
+
+/// Refills a scheduling context needs at least, the one in use and the one being built
+pub const MIN_REFILLS: usize = 2;
+
+/// Time of the last kernel entry of every core
+static mut ksCurTime: [usize; CONFIG_MAX_NUM_NODES] = [0; CONFIG_MAX_NUM_NODES];
+
+/// Time every core consumed since the last [`commit_time`]
+static mut ksConsumed: [usize; CONFIG_MAX_NUM_NODES] = [0; CONFIG_MAX_NUM_NODES];
+
+/// Threads of every core waiting for a refill, in release order
+static mut ksReleaseQueue: [tcb_queue_t; CONFIG_MAX_NUM_NODES] =
+    [tcb_queue_t { head: 0, tail: 0 }; CONFIG_MAX_NUM_NODES];
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+/// `rAmount` of budget available from `rTime` on
+pub struct refill_t {
+    pub rTime: usize,
+    pub rAmount: usize,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+/// A scheduling context object, followed by a ring of `scRefillMax` refills
+pub struct sched_context_t {
+    /// The period
+    pub scPeriod: usize,
+    /// The budget per period
+    pub scBudget: usize,
+    /// Time charged since the last `SchedContextConsumed`
+    pub scConsumed: usize,
+    /// The core whose release queue the bound thread waits in
+    pub scCore: usize,
+    /// The bound TCB, 0 for none
+    pub scTcb: usize,
+    /// The badge given by `SchedControlConfigureFlags`
+    pub scBadge: usize,
+    /// Size of the refill ring, 0 while the scheduling context is not configured
+    pub scRefillMax: usize,
+    /// Index of the head refill in the ring
+    pub scRefillHead: usize,
+    /// Number of refills in the ring
+    pub scRefillCount: usize,
+    /// The last reply object the scheduling context was lent through, 0 for none
+    pub scReply: usize,
+    /// Charges which took more than the head refill held, since the last `SchedContextConsumed`
+    pub scOverruns: usize,
+    /// Periods in which the bound thread ran out of budget while still runnable, since the last
+    /// `SchedContextConsumed`
+    pub scDeadlineMisses: usize,
+}
+
+/// The time of the last kernel entry of the current core.
+#[inline]
+pub fn current_time() -> usize {
+    unsafe { ksCurTime[cpu_id()] }
+}
+
+/// Read the time at kernel entry: the time since the previous entry is added to `ksConsumed`.
+pub fn update_timestamp(now: usize) {
+    unsafe {
+        let core = cpu_id();
+        ksConsumed[core] += now.wrapping_sub(ksCurTime[core]);
+        ksCurTime[core] = now;
+    }
+}
+
+/// Charge `ksConsumed` to the scheduling context of the current thread. The time of threads
+/// without one, including the idle thread, is not accounted.
+pub fn commit_time() {
+    let consumed = unsafe { core::mem::replace(&mut ksConsumed[cpu_id()], 0) };
+    let current = get_currenct_thread();
+    if current.tcbSchedContext != 0 {
+        let sc = convert_to_mut_type_ref::<sched_context_t>(current.tcbSchedContext);
+        if sc.scRefillCount != 0 {
+            sc.charge(consumed, current_time());
+        }
+    }
+}
+
+/// The most refills a scheduling context of `2^size_bits` bytes has room for.
+pub fn refill_absolute_max(size_bits: usize) -> usize {
+    (BIT!(size_bits) - size_of::<sched_context_t>()) / size_of::<refill_t>()
+}
+
+impl sched_context_t {
+    #[inline]
+    pub fn get_ptr(&self) -> usize {
+        self as *const sched_context_t as usize
+    }
+
+    #[inline]
+    fn refill(&mut self, index: usize) -> &mut refill_t {
+        let ring = self.get_ptr() + size_of::<sched_context_t>();
+        convert_to_mut_type_ref::<refill_t>(ring + index * size_of::<refill_t>())
+    }
+
+    /// The refill `i` places after the head.
+    #[inline]
+    fn refill_at(&mut self, i: usize) -> &mut refill_t {
+        let index = (self.scRefillHead + i) % self.scRefillMax;
+        self.refill(index)
+    }
+
+    fn refill_pop_head(&mut self) -> refill_t {
+        let head = *self.refill_at(0);
+        self.scRefillHead = (self.scRefillHead + 1) % self.scRefillMax;
+        self.scRefillCount -= 1;
+        head
+    }
+
+    /// Give back `amount` as a refill released at `time`. A refill which starts before the tail
+    /// ends is merged into it, when the ring is full the tail is merged and released later.
+    fn refill_schedule_used(&mut self, time: usize, amount: usize) {
+        if self.scRefillCount == 0 {
+            self.scRefillCount = 1;
+            *self.refill_at(0) = refill_t {
+                rTime: time,
+                rAmount: amount,
+            };
+            return;
+        }
+        let count = self.scRefillCount;
+        let max = self.scRefillMax;
+        let tail = self.refill_at(count - 1);
+        if tail.rTime + tail.rAmount >= time {
+            tail.rAmount += amount;
+        } else if count == max {
+            tail.rAmount += amount;
+            tail.rTime = tail.rTime.max((time + amount).saturating_sub(tail.rAmount));
+        } else {
+            self.scRefillCount += 1;
+            *self.refill_at(count) = refill_t {
+                rTime: time,
+                rAmount: amount,
+            };
+        }
+    }
+
+    /// Reset the refills to the full `budget`, available at once.
+    fn refill_new(&mut self, max_refills: usize, budget: usize, period: usize) {
+        self.scPeriod = period;
+        self.scBudget = budget;
+        self.scRefillMax = max_refills;
+        self.scRefillHead = 0;
+        self.scRefillCount = 1;
+        let now = unsafe { ksCurTime[self.scCore] };
+        *self.refill(0) = refill_t {
+            rTime: now,
+            rAmount: budget,
+        };
+    }
+
+    /// Whether the head refill is released at `now`. Refills which are released as well are
+    /// merged into the head.
+    pub fn refill_ready(&mut self, now: usize) -> bool {
+        if self.scRefillCount == 0 || self.refill_at(0).rTime > now {
+            return false;
+        }
+        while self.scRefillCount > 1 && self.refill_at(1).rTime <= now {
+            let head = self.refill_pop_head();
+            self.refill_at(0).rAmount += head.rAmount;
+        }
+        true
+    }
+
+    /// The time the head refill is released at, `usize::MAX` for a scheduling context which is
+    /// not configured.
+    fn release_time(&mut self) -> usize {
+        if self.scRefillCount == 0 {
+            return usize::MAX;
+        }
+        self.refill_at(0).rTime
+    }
+
+    /// Charge `usage`, which ended at `now`, to the refills from the head on. More than the
+    /// head holds is an overrun, which is counted and taken from the refills that follow. The
+    /// usage comes back one period after it started.
+    fn charge(&mut self, usage: usize, now: usize) {
+        self.scConsumed += usage;
+        if usage > self.refill_at(0).rAmount {
+            self.scOverruns += 1;
+        }
+        let usage = usage.min(self.scBudget);
+        if usage == 0 {
+            return;
+        }
+        let mut left = usage;
+        while left > 0 && self.scRefillCount > 0 {
+            let head = self.refill_at(0);
+            if head.rAmount > left {
+                head.rAmount -= left;
+                break;
+            }
+            left -= head.rAmount;
+            self.refill_pop_head();
+        }
+        self.refill_schedule_used(now.saturating_sub(usage) + self.scPeriod, usage);
+    }
+
+    /// Give the scheduling context `budget` every `period` with a ring of
+    /// `max_refills` refills, counted on `core`. A bound thread waiting for a refill is released
+    /// at once with the new budget.
+    pub fn configure(
+        &mut self,
+        budget: usize,
+        period: usize,
+        max_refills: usize,
+        badge: usize,
+        core: usize,
+    ) {
+        let waiting = if self.scTcb != 0 {
+            let tcb = convert_to_mut_type_ref::<tcb_t>(self.scTcb);
+            let waiting = tcb.tcbInReleaseQueue;
+            release_remove(tcb);
+            waiting.then_some(tcb)
+        } else {
+            None
+        };
+        self.scCore = core;
+        self.scBadge = badge;
+        self.refill_new(max_refills, budget, period);
+        if let Some(tcb) = waiting {
+            if tcb.is_runnable() {
+                possible_switch_to(tcb);
+            }
+        }
+    }
+
+    /// Bind `tcb`, which must not have a scheduling context. A runnable thread without budget
+    /// goes to the release queue.
+    pub fn bind_tcb(&mut self, tcb: &mut tcb_t) {
+        self.scTcb = tcb.get_ptr();
+        tcb.tcbSchedContext = self.get_ptr();
+        if tcb.is_runnable() && !self.refill_ready(current_time()) {
+            tcb.sched_dequeue();
+            release_enqueue(tcb);
+            if tcb.is_current() {
+                rescheduleRequired();
+            }
+        }
+    }
+
+    /// Unbind the bound TCB, if any, which goes back to round robin.
+    pub fn unbind_tcb(&mut self) {
+        if self.scTcb == 0 {
+            return;
+        }
+        let tcb = convert_to_mut_type_ref::<tcb_t>(self.scTcb);
+        if tcb.tcbInReleaseQueue {
+            release_remove(tcb);
+            if tcb.is_runnable() {
+                possible_switch_to(tcb);
+            }
+        }
+        tcb.tcbSchedContext = 0;
+        self.scTcb = 0;
+    }
+
+    /// Move the scheduling context from the TCB it is bound to, if any, to `to`, which has none.
+    /// Time the current thread used so far is charged before the scheduling context leaves it.
+    pub fn donate(&mut self, to: &mut tcb_t) {
+        if self.scTcb == get_currenct_thread().get_ptr() {
+            commit_time();
+        }
+        self.unbind_tcb();
+        self.scTcb = to.get_ptr();
+        to.tcbSchedContext = self.get_ptr();
+    }
+
+    /// Return the time charged since the last call and reset the count.
+    pub fn take_consumed(&mut self) -> usize {
+        core::mem::replace(&mut self.scConsumed, 0)
+    }
+
+    /// Return the overruns and the deadline misses counted since the last call and reset them.
+    pub fn take_misses(&mut self) -> (usize, usize) {
+        (
+            core::mem::replace(&mut self.scOverruns, 0),
+            core::mem::replace(&mut self.scDeadlineMisses, 0),
+        )
+    }
+}
+
+/// The release queue of the core the scheduling context of `tcb` is counted on.
+#[inline]
+fn release_queue_of(tcb: &tcb_t) -> &'static mut tcb_queue_t {
+    let sc = convert_to_mut_type_ref::<sched_context_t>(tcb.tcbSchedContext);
+    unsafe { &mut ksReleaseQueue[sc.scCore] }
+}
+
+/// Put `tcb`, which is not in a ready queue, in its release queue, after the threads released no
+/// later than it.
+fn release_enqueue(tcb: &mut tcb_t) {
+    let release = convert_to_mut_type_ref::<sched_context_t>(tcb.tcbSchedContext).release_time();
+    let queue = release_queue_of(tcb);
+    let mut prev = queue.tail;
+    while prev != 0 {
+        let prev_tcb = convert_to_mut_type_ref::<tcb_t>(prev);
+        let prev_sc = convert_to_mut_type_ref::<sched_context_t>(prev_tcb.tcbSchedContext);
+        if prev_sc.release_time() <= release {
+            break;
+        }
+        prev = prev_tcb.tcbSchedPrev;
+    }
+    let next = if prev == 0 {
+        queue.head
+    } else {
+        convert_to_mut_type_ref::<tcb_t>(prev).tcbSchedNext
+    };
+    tcb.tcbSchedPrev = prev;
+    tcb.tcbSchedNext = next;
+    if prev == 0 {
+        queue.head = tcb.get_ptr();
+    } else {
+        convert_to_mut_type_ref::<tcb_t>(prev).tcbSchedNext = tcb.get_ptr();
+    }
+    if next == 0 {
+        queue.tail = tcb.get_ptr();
+    } else {
+        convert_to_mut_type_ref::<tcb_t>(next).tcbSchedPrev = tcb.get_ptr();
+    }
+    tcb.tcbInReleaseQueue = true;
+    #[cfg(all(feature = "ENABLE_TICKLESS", feature = "ENABLE_SMP"))]
+    {
+        // The other core sleeps until its old head is released, the reschedule IPI makes it
+        // program the timer for the new one
+        let core = convert_to_mut_type_ref::<sched_context_t>(tcb.tcbSchedContext).scCore;
+        if prev == 0 && core != cpu_id() {
+            unsafe { crate::scheduler::ksSMP[cpu_id()].ipiReschedulePending |= BIT!(core) };
+        }
+    }
+}
+
+/// Take `tcb` out of the release queue it waits in, if any.
+pub fn release_remove(tcb: &mut tcb_t) {
+    if !tcb.tcbInReleaseQueue {
+        return;
+    }
+    let queue = release_queue_of(tcb);
+    if tcb.tcbSchedPrev != 0 {
+        convert_to_mut_type_ref::<tcb_t>(tcb.tcbSchedPrev).tcbSchedNext = tcb.tcbSchedNext;
+    } else {
+        queue.head = tcb.tcbSchedNext;
+    }
+    if tcb.tcbSchedNext != 0 {
+        convert_to_mut_type_ref::<tcb_t>(tcb.tcbSchedNext).tcbSchedPrev = tcb.tcbSchedPrev;
+    } else {
+        queue.tail = tcb.tcbSchedPrev;
+    }
+    tcb.tcbSchedPrev = 0;
+    tcb.tcbSchedNext = 0;
+    tcb.tcbInReleaseQueue = false;
+}
+
+/// Make the threads whose refill is released at `now` ready again.
+fn release_awaken(now: usize) {
+    loop {
+        let head = unsafe { ksReleaseQueue[cpu_id()].head };
+        if head == 0 {
+            return;
+        }
+        let tcb = convert_to_mut_type_ref::<tcb_t>(head);
+        if !convert_to_mut_type_ref::<sched_context_t>(tcb.tcbSchedContext).refill_ready(now) {
+            return;
+        }
+        release_remove(tcb);
+        if tcb.is_runnable() {
+            possible_switch_to(tcb);
+        }
+    }
+}
+
+/// The next time the current core has to account time: when the budget of the current thread
+/// runs out or the head of the release queue is released, `usize::MAX` for neither. Called on the
+/// way to user space, after `commit_time`, see `ENABLE_TICKLESS`.
+#[cfg(feature = "ENABLE_TICKLESS")]
+pub fn next_event() -> usize {
+    let mut next = usize::MAX;
+    let current = get_currenct_thread();
+    if current.tcbSchedContext != 0 && !current.tcbInReleaseQueue {
+        let sc = convert_to_mut_type_ref::<sched_context_t>(current.tcbSchedContext);
+        if sc.scRefillCount != 0 {
+            next = current_time().saturating_add(sc.refill_at(0).rAmount);
+        }
+    }
+    let head = unsafe { ksReleaseQueue[cpu_id()].head };
+    if head != 0 {
+        let tcb = convert_to_mut_type_ref::<tcb_t>(head);
+        let sc = convert_to_mut_type_ref::<sched_context_t>(tcb.tcbSchedContext);
+        next = next.min(sc.release_time());
+    }
+    next
+}
+
+/// Whether `tcb`, which just became runnable, may be scheduled. A thread whose scheduling
+/// context has no budget left goes to the release queue instead.
+pub fn sched_context_resume(tcb: &mut tcb_t) -> bool {
+    if tcb.tcbInReleaseQueue {
+        return false;
+    }
+    if tcb.tcbSchedContext == 0
+        || convert_to_mut_type_ref::<sched_context_t>(tcb.tcbSchedContext)
+            .refill_ready(current_time())
+    {
+        return true;
+    }
+    release_enqueue(tcb);
+    false
+}
+
+/// Commit the time consumed so far and stop the current thread if its budget is gone, called on
+/// the timer tick and at preemption points. A thread with a timeout handler raises a timeout
+/// fault to it, see `TCBSetTimeoutEndpoint`, any other one goes to the release queue.
+///
+/// # Returns
+///
+/// Whether the current thread may go on running.
+pub fn check_budget() -> bool {
+    commit_time();
+    let current = get_currenct_thread();
+    if current.tcbSchedContext == 0 || !current.is_runnable() {
+        return true;
+    }
+    if !current.tcbInReleaseQueue {
+        let sc = convert_to_mut_type_ref::<sched_context_t>(current.tcbSchedContext);
+        if sc.refill_ready(current_time()) {
+            return true;
+        }
+        // A periodic thread whose work did not fit in its budget finishes after the period ends,
+        // one whose budget equals its period is round robin and has no deadline
+        if sc.scBudget < sc.scPeriod {
+            sc.scDeadlineMisses += 1;
+        }
+        // The thread waits for the handler and is resumed with `sched_context_resume` on reply
+        if !unsafe { handleTimeout(current) } {
+            release_enqueue(current);
+        }
+    }
+    #[cfg(feature = "ENABLE_SCHED_TRACE")]
+    crate::sched_trace::sched_trace_cause(crate::sched_trace::sched_trace_cause_t::Budget);
+    rescheduleRequired();
+    false
+}
+
+/// Account the tick on the current core, called from `timerTick`.
+///
+/// # Returns
+///
+/// Whether `current` runs on a scheduling context, so its time slice must be left alone.
+pub fn sched_context_tick(current: &mut tcb_t) -> bool {
+    check_budget();
+    release_awaken(current_time());
+    current.tcbSchedContext != 0
+}
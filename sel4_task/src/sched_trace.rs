@@ -0,0 +1,101 @@
+//! Scheduler decision tracing, enabled by the `ENABLE_SCHED_TRACE` feature.
+//!
+//! While tracing is on, every decision of `schedule` writes one line to the kernel log, so it ends
+//! up in the console ring like any other kernel message:
+//!
+//! ```text
+//! sched t=T core=C cause=CAUSE 0x...->0x...
+//! ```
+//!
+//! `t` is the timer count of the decision, the addresses are the TCBs of the thread that ran
+//! before and of the chosen one, which can be the same. `cause` is the first event since the last
+//! decision that asked for one, see [`sched_trace_cause_t`]. A host-side tool can rebuild the
+//! timeline of each core from these lines. Tracing starts off and is switched at runtime with
+//! `SysDebugSchedTrace`.
+
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use sel4_common::sel4_config::CONFIG_MAX_NUM_NODES;
+use sel4_common::utils::cpu_id;
+
+static SCHED_TRACE_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The pending cause of each core, a `sched_trace_cause_t`
+static ksSchedTraceCause: [AtomicU8; CONFIG_MAX_NUM_NODES] = {
+    const INIT: AtomicU8 = AtomicU8::new(sched_trace_cause_t::Other as u8);
+    [INIT; CONFIG_MAX_NUM_NODES]
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+/// Why the scheduler was asked to choose a thread
+pub enum sched_trace_cause_t {
+    /// None of the events below, e.g. a TCB invocation changed a priority
+    Other = 0,
+    /// A thread was woken by IPC, a signal or a resume, see `possible_switch_to`
+    Wake = 1,
+    /// The time slice of the current thread expired
+    Timer = 2,
+    /// The current thread yielded
+    Yield = 3,
+    /// The scheduling context of the current thread ran out of budget
+    Budget = 4,
+    /// The current thread blocked or stopped
+    Block = 5,
+}
+
+impl sched_trace_cause_t {
+    fn name(self) -> &'static str {
+        match self {
+            sched_trace_cause_t::Other => "other",
+            sched_trace_cause_t::Wake => "wake",
+            sched_trace_cause_t::Timer => "timer",
+            sched_trace_cause_t::Yield => "yield",
+            sched_trace_cause_t::Budget => "budget",
+            sched_trace_cause_t::Block => "block",
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => sched_trace_cause_t::Wake,
+            2 => sched_trace_cause_t::Timer,
+            3 => sched_trace_cause_t::Yield,
+            4 => sched_trace_cause_t::Budget,
+            5 => sched_trace_cause_t::Block,
+            _ => sched_trace_cause_t::Other,
+        }
+    }
+}
+
+/// Switch tracing on or off, returns whether it was on before.
+pub fn sched_trace_set_enabled(enabled: bool) -> bool {
+    SCHED_TRACE_ENABLED.swap(enabled, Ordering::Relaxed)
+}
+
+#[inline]
+/// Note `cause` for the next decision of the current core, unless an earlier event did.
+pub fn sched_trace_cause(cause: sched_trace_cause_t) {
+    let _ = ksSchedTraceCause[cpu_id()].compare_exchange(
+        sched_trace_cause_t::Other as u8,
+        cause as u8,
+        Ordering::Relaxed,
+        Ordering::Relaxed,
+    );
+}
+
+#[inline]
+/// Trace the decision to run the TCB at `next` instead of the one at `prev`.
+pub fn sched_trace_record(prev: usize, next: usize) {
+    let cause =
+        ksSchedTraceCause[cpu_id()].swap(sched_trace_cause_t::Other as u8, Ordering::Relaxed);
+    if SCHED_TRACE_ENABLED.load(Ordering::Relaxed) {
+        log::info!(
+            "sched t={} core={} cause={} {:#x}->{:#x}",
+            sel4_common::arch::get_time(),
+            cpu_id(),
+            sched_trace_cause_t::from_u8(cause).name(),
+            prev,
+            next
+        );
+    }
+}
@@ -5,10 +5,11 @@
 //! new threads to run, managing ready queues, and handling domain scheduling.
 //!
 #[cfg(feature = "ENABLE_SMP")]
-use crate::deps::{doMaskReschedule, kernel_stack_alloc, ksIdleThreadTCB};
+#[cfg(feature = "ENABLE_SMP")]
+use crate::deps::ipi_reschedule_mask;
+use crate::deps::ksIdleThreadTCB;
 use core::arch::asm;
 use core::intrinsics::{likely, unlikely};
-use sel4_common::arch::ArchReg;
 #[cfg(feature = "ENABLE_SMP")]
 use sel4_common::sel4_config::{seL4_TCBBits, CONFIG_MAX_NUM_NODES};
 use sel4_common::sel4_config::{
@@ -18,7 +19,8 @@ use sel4_common::sel4_config::{
 use sel4_common::utils::{convert_to_mut_type_ref, convert_to_mut_type_ref_unsafe};
 use sel4_common::{BIT, MASK};
 
-use crate::tcb::{set_thread_state, tcb_t};
+use crate::state_machine::ThreadStateMachine;
+use crate::tcb::tcb_t;
 use crate::tcb_queue::tcb_queue_t;
 use crate::thread_state::ThreadState;
 #[cfg(feature = "ENABLE_SMP")]
@@ -214,6 +216,14 @@ pub fn set_current_scheduler_action(action: usize) {
 /// Set the current thread.
 pub fn set_current_thread(thread: &tcb_t) {
     unsafe {
+        #[cfg(all(feature = "ENABLE_IDLE_TIME", feature = "ENABLE_SMP"))]
+        crate::idle_time::idle_time_switch(
+            ksSMP[cpu_id()].ksCurThread,
+            thread.get_ptr(),
+            ksSMP[cpu_id()].ksIdleThread,
+        );
+        #[cfg(all(feature = "ENABLE_IDLE_TIME", not(feature = "ENABLE_SMP")))]
+        crate::idle_time::idle_time_switch(ksCurThread, thread.get_ptr(), ksIdleThread);
         #[cfg(feature = "ENABLE_SMP")]
         {
             ksSMP[cpu_id()].ksCurThread = thread.get_ptr();
@@ -397,6 +407,11 @@ fn chooseThread() {
             assert_ne!(thread, 0);
             convert_to_mut_type_ref::<tcb_t>(thread).switch_to_this();
         } else {
+            #[cfg(feature = "ENABLE_SMP_LOAD_BALANCE")]
+            if let Some(thread) = crate::balance::balance_pull() {
+                thread.switch_to_this();
+                return;
+            }
             get_idle_thread().switch_to_this();
         }
     }
@@ -418,6 +433,8 @@ pub fn rescheduleRequired() {
 /// Schedule threads.
 pub fn schedule() {
     if get_ks_scheduler_action() != SchedulerAction_ResumeCurrentThread {
+        #[cfg(feature = "ENABLE_SCHED_TRACE")]
+        let prev = get_currenct_thread().get_ptr();
         let was_runnable: bool;
         let current_tcb = get_currenct_thread();
         if current_tcb.is_runnable() {
@@ -447,11 +464,13 @@ pub fn schedule() {
                 candidate.switch_to_this();
             }
         }
+        #[cfg(feature = "ENABLE_SCHED_TRACE")]
+        crate::sched_trace::sched_trace_record(prev, get_currenct_thread().get_ptr());
     }
     set_ks_scheduler_action(SchedulerAction_ResumeCurrentThread);
     #[cfg(feature = "ENABLE_SMP")]
     unsafe {
-        doMaskReschedule(ksSMP[cpu_id()].ipiReschedulePending);
+        ipi_reschedule_mask(ksSMP[cpu_id()].ipiReschedulePending);
         ksSMP[cpu_id()].ipiReschedulePending = 0;
     }
 }
@@ -463,6 +482,8 @@ pub fn schedule_tcb(tcb_ref: &tcb_t) {
         && get_ks_scheduler_action() == SchedulerAction_ResumeCurrentThread
         && !tcb_ref.is_runnable()
     {
+        #[cfg(feature = "ENABLE_SCHED_TRACE")]
+        crate::sched_trace::sched_trace_cause(crate::sched_trace::sched_trace_cause_t::Block);
         rescheduleRequired();
     }
 }
@@ -471,6 +492,8 @@ pub fn schedule_tcb(tcb_ref: &tcb_t) {
 #[inline]
 /// Schedule the given tcb when current tcb is not in the same domain or not in the same cpu or current action is not to resume the current thread.
 pub fn possible_switch_to(target: &mut tcb_t) {
+    #[cfg(feature = "ENABLE_SCHED_TRACE")]
+    crate::sched_trace::sched_trace_cause(crate::sched_trace::sched_trace_cause_t::Wake);
     if unsafe { ksCurDomain != target.domain || target.tcbAffinity != cpu_id() } {
         target.sched_enqueue();
     } else if get_ks_scheduler_action() != SchedulerAction_ResumeCurrentThread {
@@ -485,6 +508,8 @@ pub fn possible_switch_to(target: &mut tcb_t) {
 #[inline]
 /// Schedule the given tcb when current tcb is not in the same domain or current action is not to resume the current thread.
 pub fn possible_switch_to(target: &mut tcb_t) {
+    #[cfg(feature = "ENABLE_SCHED_TRACE")]
+    crate::sched_trace::sched_trace_cause(crate::sched_trace::sched_trace_cause_t::Wake);
     if unsafe { ksCurDomain != target.domain } {
         target.sched_enqueue();
     } else if get_ks_scheduler_action() != SchedulerAction_ResumeCurrentThread {
@@ -495,15 +520,61 @@ pub fn possible_switch_to(target: &mut tcb_t) {
     }
 }
 
+#[cfg(feature = "ENABLE_CALL_DONATION")]
+#[inline]
+/// Hand the rest of the time slice of `caller` to `callee` on seL4_Call.
+///
+/// Only called when the kernel switches to `callee` directly. Without donation the callee starts
+/// with what is left of its own slice, as in seL4, which is what response time analyses of
+/// independent threads assume. With donation the pair runs on the budget of the caller, so a
+/// server can not extend the time a client holds the core. The slice moves: the caller has none
+/// while it waits, and [`return_timeslice`] gives back what is left on the reply. The callee keeps
+/// its own slice aside until then, unless the donated one expires first, see [`timerTick`].
+pub fn donate_timeslice(caller: &mut tcb_t, callee: &mut tcb_t) {
+    callee.tcbTimeSliceDonor = caller.get_ptr();
+    callee.tcbOwnTimeSlice = callee.tcbTimeSlice;
+    callee.tcbTimeSlice = caller.tcbTimeSlice;
+    caller.tcbTimeSlice = 0;
+}
+
+#[cfg(feature = "ENABLE_CALL_DONATION")]
+#[inline]
+/// Give what is left of the slice `replier` runs on back to `caller` on the reply, if `caller`
+/// donated it, and return `replier` to its own slice.
+pub fn return_timeslice(replier: &mut tcb_t, caller: &mut tcb_t) {
+    if replier.tcbTimeSliceDonor != caller.get_ptr() {
+        return;
+    }
+    caller.tcbTimeSlice = replier.tcbTimeSlice;
+    replier.tcbTimeSlice = replier.tcbOwnTimeSlice;
+    replier.tcbTimeSliceDonor = 0;
+}
+
 #[no_mangle]
-/// Schedule current thread if time slice is expired.
+/// Schedule current thread if time slice is expired. With `ENABLE_MCS` a thread with a
+/// scheduling context is charged to its budget instead. With `ENABLE_CPU_LIMIT` a thread that
+/// reaches its CPU time limit raises a timeout fault.
 pub fn timerTick() {
     let current = get_currenct_thread();
+    #[cfg(feature = "ENABLE_MCS")]
+    if crate::sched_context::sched_context_tick(current) {
+        return;
+    }
     // if hart_id() == 0 {
     //     debug!("timer tick current: {:#x}", current.get_ptr());
     // }
 
     if likely(current.get_state() == ThreadState::ThreadStateRunning) {
+        #[cfg(feature = "ENABLE_CPU_LIMIT")]
+        {
+            current.tcbCPUTime += 1;
+            if current.tcbCPULimit != 0 && current.tcbCPUTime >= current.tcbCPULimit {
+                // The limit fires once, the fault handler sets a new one to resume the thread
+                current.tcbCPULimit = 0;
+                unsafe { crate::deps::handleCPULimit(current) };
+                return;
+            }
+        }
         if current.tcbTimeSlice > 1 {
             // if hart_id() == 0 {
             //     debug!("tcbTimeSlice : {}", current.tcbTimeSlice);
@@ -515,7 +586,14 @@ pub fn timerTick() {
             // }
 
             current.tcbTimeSlice = CONFIG_TIME_SLICE;
+            // A donated slice is used up, there is nothing left to give back
+            #[cfg(feature = "ENABLE_CALL_DONATION")]
+            {
+                current.tcbTimeSliceDonor = 0;
+            }
             current.sched_append();
+            #[cfg(feature = "ENABLE_SCHED_TRACE")]
+            crate::sched_trace::sched_trace_cause(crate::sched_trace::sched_trace_cause_t::Timer);
             rescheduleRequired();
         }
     }
@@ -531,11 +609,10 @@ pub fn activateThread() {
             return;
         }
         ThreadState::ThreadStateRestart => {
-            let pc = thread.tcbArch.get_register(ArchReg::FaultIP);
-            // setNextPC(thread, pc);
-            thread.tcbArch.set_register(ArchReg::NextIP, pc);
+            // setNextPC(thread, getRestartPC(thread));
+            thread.tcbArch.rewind_to_restart_pc();
             // setThreadState(thread, ThreadStateRunning);
-            set_thread_state(thread, ThreadState::ThreadStateRunning);
+            thread.set_running();
         }
         // 诡异的语法...
         ThreadState::ThreadStateIdleThreadState => return {},
@@ -558,8 +635,8 @@ pub fn create_idle_thread() {
         // let tcb = convert_to_mut_type_ref::<tcb_t>(ksIdleThread as usize);
         let tcb = get_idle_thread();
         // Arch_configureIdleThread(tcb.tcbArch);
-        tcb.tcbArch.config_idle_thread(idle_thread as usize);
-        set_thread_state(tcb, ThreadState::ThreadStateIdleThreadState);
+        tcb.tcbArch.config_idle_thread(idle_thread as usize, 0);
+        tcb.set_idle();
     }
 }
 
@@ -575,15 +652,8 @@ pub fn create_idle_thread() {
             ksSMP[i].ksIdleThread = pptr.add(TCB_OFFSET) as usize;
             debug!("ksIdleThread: {:#x}", ksSMP[i].ksIdleThread);
             let tcb = convert_to_mut_type_ref::<tcb_t>(ksSMP[i].ksIdleThread);
-            tcb.tcbArch.set_register(NextIP, idle_thread as usize);
-            tcb.tcbArch
-                .set_register(SSTATUS, SSTATUS_SPP | SSTATUS_SPIE);
-            tcb.tcbArch.set_register(
-                sp,
-                unsafe { &kernel_stack_alloc.data[0][0] as *const u8 } as usize
-                    + (i + 1) * BIT!(CONFIG_KERNEL_STACK_BITS),
-            );
-            set_thread_state(tcb, ThreadState::ThreadStateIdleThreadState);
+            tcb.tcbArch.config_idle_thread(idle_thread as usize, i);
+            tcb.set_idle();
             tcb.tcbAffinity = i;
         }
     }
@@ -0,0 +1,124 @@
+//! Checked thread state transitions.
+//!
+//! [`ThreadStateMachine`] is the way to change the state of a thread: there is one method per
+//! target state, and the blocking states take the object the thread blocks on, so a blocked thread
+//! always has one. Every transition, including those through the raw [`set_thread_state`], is
+//! checked against [`thread_state_transition_valid`] by `kernel_assert!` in debug builds.
+
+use sel4_common::kernel_assert;
+use sel4_vspace::pptr_t;
+
+use crate::{set_thread_state, tcb_t, ThreadState};
+
+/// Whether a thread may go from state `from` to state `to`.
+pub fn thread_state_transition_valid(from: ThreadState, to: ThreadState) -> bool {
+    use ThreadState::*;
+    match to {
+        // A thread only ever becomes idle when the idle thread is created.
+        ThreadStateIdleThreadState => from == ThreadStateInactive,
+        // Only a thread which is executing a syscall or taking a fault can block ...
+        ThreadStateBlockedOnReceive
+        | ThreadStateBlockedOnSend
+        | ThreadStateBlockedOnNotification => {
+            from == ThreadStateRunning || from == ThreadStateRestart
+        }
+        // ... or wait for a reply, which also happens when a receiver picks up its call.
+        ThreadStateBlockedOnReply => {
+            from == ThreadStateRunning
+                || from == ThreadStateRestart
+                || from == ThreadStateBlockedOnSend
+        }
+        ThreadStateInactive | ThreadStateRunning | ThreadStateRestart => {
+            from != ThreadStateIdleThreadState
+        }
+        ThreadStateExited => true,
+    }
+}
+
+/// Transitions of the thread state, see the module docs.
+pub trait ThreadStateMachine {
+    /// The thread runs, e.g. when an IPC it was blocked on completes.
+    fn set_running(&mut self);
+    /// The thread restarts its current syscall when it is next scheduled.
+    fn set_restart(&mut self);
+    /// The thread stops until it is resumed.
+    fn set_inactive(&mut self);
+    /// The thread becomes the idle thread of its core.
+    fn set_idle(&mut self);
+    /// The thread waits for a message on the endpoint `ep`.
+    fn block_on_receive(&mut self, ep: pptr_t, can_grant: bool);
+    /// The thread waits for a receiver on the endpoint `ep`.
+    fn block_on_send(
+        &mut self,
+        ep: pptr_t,
+        badge: usize,
+        can_grant: bool,
+        can_grant_reply: bool,
+        is_call: bool,
+    );
+    /// The thread waits for a reply to its call.
+    fn block_on_reply(&mut self);
+    /// The thread waits for a signal on the notification `ntfn`.
+    fn block_on_notification(&mut self, ntfn: pptr_t);
+}
+
+impl ThreadStateMachine for tcb_t {
+    #[inline]
+    fn set_running(&mut self) {
+        set_thread_state(self, ThreadState::ThreadStateRunning);
+    }
+
+    #[inline]
+    fn set_restart(&mut self) {
+        set_thread_state(self, ThreadState::ThreadStateRestart);
+    }
+
+    #[inline]
+    fn set_inactive(&mut self) {
+        set_thread_state(self, ThreadState::ThreadStateInactive);
+    }
+
+    #[inline]
+    fn set_idle(&mut self) {
+        set_thread_state(self, ThreadState::ThreadStateIdleThreadState);
+    }
+
+    #[inline]
+    fn block_on_receive(&mut self, ep: pptr_t, can_grant: bool) {
+        kernel_assert!(2006, ep != 0);
+        self.tcbState.set_blocking_object(ep);
+        self.tcbState.set_blocking_ipc_can_grant(can_grant as usize);
+        set_thread_state(self, ThreadState::ThreadStateBlockedOnReceive);
+    }
+
+    #[inline]
+    fn block_on_send(
+        &mut self,
+        ep: pptr_t,
+        badge: usize,
+        can_grant: bool,
+        can_grant_reply: bool,
+        is_call: bool,
+    ) {
+        kernel_assert!(2007, ep != 0);
+        self.tcbState.set_blocking_object(ep);
+        self.tcbState.set_blocking_ipc_can_grant(can_grant as usize);
+        self.tcbState.set_blocking_ipc_badge(badge);
+        self.tcbState
+            .set_blocking_ipc_can_grant_reply(can_grant_reply as usize);
+        self.tcbState.set_blocking_ipc_is_call(is_call as usize);
+        set_thread_state(self, ThreadState::ThreadStateBlockedOnSend);
+    }
+
+    #[inline]
+    fn block_on_reply(&mut self) {
+        set_thread_state(self, ThreadState::ThreadStateBlockedOnReply);
+    }
+
+    #[inline]
+    fn block_on_notification(&mut self, ntfn: pptr_t) {
+        kernel_assert!(2008, ntfn != 0);
+        self.tcbState.set_blocking_object(ntfn);
+        set_thread_state(self, ThreadState::ThreadStateBlockedOnNotification);
+    }
+}
@@ -38,3 +38,16 @@ impl Default for lookupSlot_ret_t {
         }
     }
 }
+
+#[cfg(feature = "ENABLE_LOOKUP_CACHE")]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+/// Single-entry cache of the last successful cptr lookup of a TCB
+pub struct lookup_cache_t {
+    /// The cptr that was looked up
+    pub cptr: usize,
+    /// The slot the cptr resolved to
+    pub slot: usize,
+    /// The value of `cspace_epoch` when the entry was filled, 0 means invalid
+    pub epoch: usize,
+}
@@ -1,21 +1,21 @@
-use core::intrinsics::{likely, unlikely};
+use core::intrinsics::{likely, prefetch_write_data, unlikely};
 use sel4_common::arch::{
     msgRegisterNum, n_exceptionMessage, n_syscallMessage, vm_rights_t, ArchReg, ArchTCB,
 };
 use sel4_common::fault::*;
+use sel4_common::kernel_assert;
 use sel4_common::message_info::seL4_MessageInfo_t;
-use sel4_common::utils::{convert_to_mut_type_ref, pageBitsForSize};
+use sel4_common::utils::{
+    convert_to_mut_type_ref, convert_to_option_checked_mut_type_ref, convert_to_type_ref,
+    pageBitsForSize,
+};
 #[cfg(feature = "ENABLE_SMP")]
 use sel4_common::BIT;
 use sel4_common::MASK;
 use sel4_cspace::interface::{cap_t, cte_insert, cte_t, mdb_node_t, resolve_address_bits, CapTag};
-#[cfg(target_arch = "aarch64")]
-use sel4_vspace::{
-    find_vspace_for_asid, get_arm_global_user_vspace_base, kpptr_to_paddr,
-    setCurrentUserVSpaceRoot, ttbr_new,
-};
 use sel4_vspace::{pptr_t, set_vm_root};
 
+use crate::state_machine::ThreadStateMachine;
 use crate::tcb_queue::tcb_queue_t;
 use sel4_common::sel4_config::*;
 use sel4_common::structures::{exception_t, seL4_IPCBuffer};
@@ -25,6 +25,23 @@ use super::scheduler::{
     rescheduleRequired, schedule_tcb, set_current_thread,
 };
 use super::structures::lookupSlot_raw_ret_t;
+#[cfg(feature = "ENABLE_LOOKUP_CACHE")]
+use super::structures::lookup_cache_t;
+#[cfg(feature = "ENABLE_LOOKUP_CACHE")]
+use sel4_cspace::interface::cspace_epoch;
+
+#[cfg(feature = "ENABLE_LOOKUP_CACHE")]
+/// Number of `lookup_slot` calls served by the per-TCB lookup cache
+pub static mut lookup_cache_hits: usize = 0;
+#[cfg(feature = "ENABLE_LOOKUP_CACHE")]
+/// Number of `lookup_slot` calls that walked the CSpace
+pub static mut lookup_cache_misses: usize = 0;
+
+#[cfg(feature = "ENABLE_LOOKUP_CACHE")]
+const _: () = assert!(
+    tcbCNodeEntries * core::mem::size_of::<cte_t>() + core::mem::size_of::<lookup_cache_t>()
+        <= TCB_OFFSET
+);
 
 use super::thread_state::*;
 
@@ -65,6 +82,36 @@ pub struct tcb_t {
     pub tcbEPNext: usize,
     /// The previous TCB in the EP queue
     pub tcbEPPrev: usize,
+    /// The cores the TCB may be migrated to by load balancing, 0 for any core
+    #[cfg(feature = "ENABLE_SMP_LOAD_BALANCE")]
+    pub tcbAffinityMask: usize,
+    /// The scheduling context bound to the TCB, 0 for none, see `ENABLE_MCS`
+    #[cfg(feature = "ENABLE_MCS")]
+    pub tcbSchedContext: usize,
+    /// Whether the TCB waits in a release queue, linked through `tcbSchedNext`/`tcbSchedPrev`
+    #[cfg(feature = "ENABLE_MCS")]
+    pub tcbInReleaseQueue: bool,
+    /// The reply object the TCB receives with or is blocked on, 0 for none
+    #[cfg(feature = "ENABLE_MCS")]
+    pub tcbReplyObject: usize,
+    /// The CPtr of the endpoint a timeout fault is sent to, 0 for none, see `ENABLE_MCS`
+    #[cfg(feature = "ENABLE_MCS")]
+    pub tcbTimeoutHandler: usize,
+    /// Whether the syscalls of the TCB are logged, see `ENABLE_SYSCALL_TRACE`
+    #[cfg(feature = "ENABLE_SYSCALL_TRACE")]
+    pub tcbSyscallTrace: bool,
+    /// The timer ticks the TCB ran for, see `ENABLE_CPU_LIMIT`
+    #[cfg(feature = "ENABLE_CPU_LIMIT")]
+    pub tcbCPUTime: usize,
+    /// The `tcbCPUTime` at which the TCB raises a timeout fault, 0 for none
+    #[cfg(feature = "ENABLE_CPU_LIMIT")]
+    pub tcbCPULimit: usize,
+    /// The caller whose time slice the TCB runs on, 0 for none, see `ENABLE_CALL_DONATION`
+    #[cfg(feature = "ENABLE_CALL_DONATION")]
+    pub tcbTimeSliceDonor: usize,
+    /// The time slice of the TCB itself while it runs on a donated one, see `ENABLE_CALL_DONATION`
+    #[cfg(feature = "ENABLE_CALL_DONATION")]
+    pub tcbOwnTimeSlice: usize,
 }
 
 impl tcb_t {
@@ -78,7 +125,8 @@ impl tcb_t {
     }
 
     #[inline]
-    /// Initialize the TCB
+    /// Initialize the TCB when it is retyped: the register context starts out all zero, whatever
+    /// the memory held before
     pub fn init(&mut self) {
         self.tcbArch = ArchTCB::default();
     }
@@ -176,22 +224,31 @@ impl tcb_t {
 
     /// Enqueue the TCB to the scheduling queue
     pub fn sched_enqueue(&mut self) {
+        #[cfg(feature = "ENABLE_MCS")]
+        if self.tcbInReleaseQueue {
+            return;
+        }
         let self_ptr = self as *mut tcb_t;
         if self.tcbState.get_tcb_queued() == 0 {
             let dom = self.domain;
             let prio = self.tcbPriority;
             let idx = ready_queues_index(dom, prio);
             let queue = self.get_sched_queue(idx);
+            kernel_assert!(2001, (queue.head == 0) == (queue.tail == 0));
             if queue.tail == 0 {
                 queue.head = self_ptr as usize;
                 addToBitmap(self.get_cpu(), dom, prio);
-            } else {
-                convert_to_mut_type_ref::<tcb_t>(queue.tail).tcbSchedNext = self_ptr as usize;
+            } else if let Some(tail) = queue.tail_tcb() {
+                tail.tcbSchedNext = self_ptr as usize;
             }
             self.tcbSchedPrev = queue.tail;
             self.tcbSchedNext = 0;
             queue.tail = self_ptr as usize;
             self.tcbState.set_tcb_queued(1);
+            #[cfg(feature = "ENABLE_SMP_LOAD_BALANCE")]
+            unsafe {
+                crate::balance::ksReadyCount[self.tcbAffinity] += 1;
+            }
         }
 
         #[cfg(feature = "ENABLE_SMP")]
@@ -235,23 +292,27 @@ impl tcb_t {
             let prio = self.tcbPriority;
             let idx = ready_queues_index(dom, prio);
             let queue = self.get_sched_queue(idx);
-            if self.tcbSchedPrev != 0 {
-                convert_to_mut_type_ref::<tcb_t>(self.tcbSchedPrev).tcbSchedNext =
-                    self.tcbSchedNext;
+            kernel_assert!(2002, self.tcbSchedPrev != 0 || queue.head == self.get_ptr());
+            kernel_assert!(2003, self.tcbSchedNext != 0 || queue.tail == self.get_ptr());
+            if let Some(prev) = self.sched_prev() {
+                prev.tcbSchedNext = self.tcbSchedNext;
             } else {
                 queue.head = self.tcbSchedNext;
                 if likely(self.tcbSchedNext == 0) {
                     removeFromBitmap(self.get_cpu(), dom, prio);
                 }
             }
-            if self.tcbSchedNext != 0 {
-                convert_to_mut_type_ref::<tcb_t>(self.tcbSchedNext).tcbSchedPrev =
-                    self.tcbSchedPrev;
+            if let Some(next) = self.sched_next() {
+                next.tcbSchedPrev = self.tcbSchedPrev;
             } else {
                 queue.tail = self.tcbSchedPrev;
             }
             // unsafe { ksReadyQueues[idx] = queue; }
             self.tcbState.set_tcb_queued(0);
+            #[cfg(feature = "ENABLE_SMP_LOAD_BALANCE")]
+            unsafe {
+                crate::balance::ksReadyCount[self.tcbAffinity] -= 1;
+            }
         }
     }
 
@@ -259,20 +320,23 @@ impl tcb_t {
     /// # Note
     /// This function is as same as `sched_enqueue`, but it is used for the EP queue
     pub fn sched_append(&mut self) {
+        #[cfg(feature = "ENABLE_MCS")]
+        if self.tcbInReleaseQueue {
+            return;
+        }
         let self_ptr = self as *mut tcb_t;
         if self.tcbState.get_tcb_queued() == 0 {
             let dom = self.domain;
             let prio = self.tcbPriority;
             let idx = ready_queues_index(dom, prio);
             let queue = self.get_sched_queue(idx);
+            kernel_assert!(2004, (queue.head == 0) == (queue.tail == 0));
 
             if queue.head == 0 {
                 queue.head = self_ptr as usize;
                 addToBitmap(self.get_cpu(), dom, prio);
-            } else {
-                let next = queue.tail;
-                // unsafe { (*next).tcbSchedNext = self_ptr as usize };
-                convert_to_mut_type_ref::<tcb_t>(next).tcbSchedNext = self_ptr as usize;
+            } else if let Some(tail) = queue.tail_tcb() {
+                tail.tcbSchedNext = self_ptr as usize;
             }
             self.tcbSchedPrev = queue.tail;
             self.tcbSchedNext = 0;
@@ -280,6 +344,10 @@ impl tcb_t {
             // unsafe { ksReadyQueues[idx] = queue; }
 
             self.tcbState.set_tcb_queued(1);
+            #[cfg(feature = "ENABLE_SMP_LOAD_BALANCE")]
+            unsafe {
+                crate::balance::ksReadyCount[self.tcbAffinity] += 1;
+            }
         }
         #[cfg(feature = "ENABLE_SMP")]
         self.update_queue();
@@ -287,9 +355,26 @@ impl tcb_t {
 
     #[cfg(feature = "ENABLE_SMP")]
     #[inline]
+    /// Move the TCB to `new_core`, it is queued in the ready queues of that core from now on. The
+    /// TCB must not be queued and not be running on another core.
+    ///
+    /// This is `migrateTCB`, the TCB moves to the debug list of `new_core` as well.
+    pub fn migrate(&mut self, new_core: usize) {
+        kernel_assert!(2009, self.tcbState.get_tcb_queued() == 0);
+        unsafe {
+            crate::deps::tcbDebugRemove(self);
+            self.tcbAffinity = new_core;
+            crate::deps::tcbDebugAppend(self);
+        }
+    }
+
+    #[cfg(feature = "ENABLE_SMP")]
+    #[inline]
+    /// Ask the core of the TCB to reschedule with an IPI, at the end of the current kernel entry,
+    /// if the TCB was queued there and should preempt the thread it runs.
     fn update_queue(&self) {
         use super::scheduler::{ksCurDomain, ksSMP};
-        use sel4_common::utils::{convert_to_type_ref, cpu_id};
+        use sel4_common::utils::cpu_id;
         unsafe {
             if self.tcbAffinity != cpu_id() && self.domain == ksCurDomain {
                 let target_current =
@@ -307,29 +392,6 @@ impl tcb_t {
     pub fn set_vm_root(&mut self) -> Result<(), lookup_fault_t> {
         // let threadRoot = &(*getCSpace(thread as usize, tcbVTable)).cap;
         let thread_root = self.get_cspace(tcbVTable).cap;
-        #[cfg(target_arch = "aarch64")]
-        {
-            if !thread_root.is_valid_native_root() {
-                setCurrentUserVSpaceRoot(ttbr_new(
-                    0,
-                    kpptr_to_paddr(get_arm_global_user_vspace_base()),
-                ));
-                return Ok(());
-            }
-            let vspace_root = thread_root.get_pgd_base_ptr();
-            let asid = thread_root.get_pgd_mapped_asid();
-            let find_ret = find_vspace_for_asid(asid);
-
-            if let Some(root) = find_ret.vspace_root {
-                if find_ret.status != exception_t::EXCEPTION_NONE || root as usize != vspace_root {
-                    setCurrentUserVSpaceRoot(ttbr_new(
-                        0,
-                        kpptr_to_paddr(get_arm_global_user_vspace_base()),
-                    ));
-                    return Ok(());
-                }
-            }
-        }
         set_vm_root(&thread_root)
     }
 
@@ -340,7 +402,15 @@ impl tcb_t {
         //     debug!("switch_to_this: {:#x}", self.get_ptr());
         // }
         let _ = self.set_vm_root();
+        #[cfg(all(target_arch = "aarch64", feature = "ENABLE_ARM_MPAM"))]
+        self.tcbArch.load_mpam();
         self.sched_dequeue();
+        #[cfg(feature = "ENABLE_KERNEL_INFO_PAGE")]
+        if self.get_ptr() != get_currenct_thread().get_ptr() {
+            sel4_common::kernel_info::kernel_info_count_context_switch();
+        }
+        #[cfg(feature = "ENABLE_MCS")]
+        crate::sched_context::commit_time();
         set_current_thread(self);
     }
 
@@ -360,6 +430,30 @@ impl tcb_t {
         self as *mut tcb_t as usize
     }
 
+    #[inline]
+    /// The thread after this one in the endpoint or notification queue it is blocked on
+    pub fn ep_next(&self) -> Option<&'static mut tcb_t> {
+        convert_to_option_checked_mut_type_ref::<tcb_t>(self.tcbEPNext)
+    }
+
+    #[inline]
+    /// The thread before this one in the endpoint or notification queue it is blocked on
+    pub fn ep_prev(&self) -> Option<&'static mut tcb_t> {
+        convert_to_option_checked_mut_type_ref::<tcb_t>(self.tcbEPPrev)
+    }
+
+    #[inline]
+    /// The thread after this one in its ready queue, or release queue with MCS
+    pub fn sched_next(&self) -> Option<&'static mut tcb_t> {
+        convert_to_option_checked_mut_type_ref::<tcb_t>(self.tcbSchedNext)
+    }
+
+    #[inline]
+    /// The thread before this one in its ready queue, or release queue with MCS
+    pub fn sched_prev(&self) -> Option<&'static mut tcb_t> {
+        convert_to_option_checked_mut_type_ref::<tcb_t>(self.tcbSchedPrev)
+    }
+
     #[inline]
     /// Look up the slot of the TCB
     /// # Arguments
@@ -367,14 +461,47 @@ impl tcb_t {
     /// # Returns
     /// The lookup result structure
     pub fn lookup_slot(&mut self, cap_ptr: usize) -> lookupSlot_raw_ret_t {
+        #[cfg(feature = "ENABLE_LOOKUP_CACHE")]
+        {
+            let epoch = unsafe { cspace_epoch };
+            let cache = self.get_lookup_cache();
+            if cache.epoch == epoch && cache.cptr == cap_ptr {
+                unsafe { lookup_cache_hits += 1 };
+                return lookupSlot_raw_ret_t {
+                    status: exception_t::EXCEPTION_NONE,
+                    slot: cache.slot as *mut cte_t,
+                };
+            }
+            unsafe { lookup_cache_misses += 1 };
+        }
         let thread_root = self.get_cspace(tcbCTable).cap;
         let res_ret = resolve_address_bits(&thread_root, cap_ptr, wordBits);
+        #[cfg(feature = "ENABLE_LOOKUP_CACHE")]
+        if res_ret.status == exception_t::EXCEPTION_NONE {
+            *self.get_lookup_cache() = lookup_cache_t {
+                cptr: cap_ptr,
+                slot: res_ret.slot as usize,
+                epoch: unsafe { cspace_epoch },
+            };
+        }
         lookupSlot_raw_ret_t {
             status: res_ret.status,
             slot: res_ret.slot,
         }
     }
 
+    #[cfg(feature = "ENABLE_LOOKUP_CACHE")]
+    #[inline]
+    /// Get the cptr lookup cache of the TCB.
+    ///
+    /// The C kernel owns the layout of `tcb_t` (debug builds append fields to it), so the cache
+    /// lives in the unused space of the TCB object right after the TCB's CNode entries. That
+    /// space is zeroed on retype, which reads as an invalid entry.
+    pub fn get_lookup_cache(&mut self) -> &'static mut lookup_cache_t {
+        let cache = self.get_cspace_mut_ref(tcbCNodeEntries) as *mut cte_t;
+        unsafe { &mut *(cache as *mut lookup_cache_t) }
+    }
+
     #[inline]
     /// Setup the reply master of the TCB
     pub fn setup_reply_master(&mut self) {
@@ -389,23 +516,23 @@ impl tcb_t {
     /// Susupend the TCB, set the state to ThreadStateInactive and dequeue from the scheduling queue
     pub fn suspend(&mut self) {
         if self.get_state() == ThreadState::ThreadStateRunning {
-            self.tcbArch.set_register(
-                ArchReg::FaultIP,
-                self.tcbArch.get_register(ArchReg::FaultIP),
-            );
+            self.tcbArch.update_restart_pc();
         }
         // setThreadState(self as *mut Self, ThreadStateInactive);
-        set_thread_state(self, ThreadState::ThreadStateInactive);
+        self.set_inactive();
         self.sched_dequeue();
+        #[cfg(feature = "ENABLE_MCS")]
+        crate::sched_context::release_remove(self);
     }
 
     #[inline]
     /// Restart the TCB, set the state to ThreadStateRestart and enqueue to the scheduling queue waiting for reschedule
     pub fn restart(&mut self) {
         if self.is_stopped() {
+            #[cfg(not(feature = "ENABLE_MCS"))]
             self.setup_reply_master();
             // setThreadState(self as *mut Self, ThreadStateRestart);
-            set_thread_state(self, ThreadState::ThreadStateRestart);
+            self.set_restart();
             self.sched_enqueue();
             possible_switch_to(self);
         }
@@ -417,7 +544,7 @@ impl tcb_t {
     /// * `sender` - The sender TCB
     /// * `can_grant` - If the cap can be granted
     pub fn setup_caller_cap(&mut self, sender: &mut Self, can_grant: bool) {
-        set_thread_state(sender, ThreadState::ThreadStateBlockedOnReply);
+        sender.block_on_reply();
         let reply_slot = sender.get_cspace_mut_ref(tcbReply);
         let master_cap = reply_slot.cap;
 
@@ -442,35 +569,38 @@ impl tcb_t {
         caller_slot.delete_one();
     }
 
-    /// Look up the IPC buffer of the TCB
+    /// Set the user address of the IPC buffer of the TCB and publish it to user mode
     /// # Arguments
-    /// * `is_receiver` - If the TCB is receiver
-    /// # Returns
-    /// The IPC buffer of the TCB
-    pub fn lookup_ipc_buffer(&mut self, is_receiver: bool) -> Option<&'static seL4_IPCBuffer> {
-        let w_buffer_ptr = self.tcbIPCBuffer;
-        let buffer_cap = self.get_cspace(tcbBuffer).cap;
-        if unlikely(buffer_cap.get_cap_type() != CapTag::CapFrameCap) {
-            return None;
-        }
-
-        if unlikely(buffer_cap.get_frame_is_device() != 0) {
-            return None;
-        }
+    /// * `buffer` - The address of the IPC buffer in the vspace of the TCB
+    #[inline]
+    pub fn set_ipc_buffer(&mut self, buffer: usize) {
+        self.tcbIPCBuffer = buffer;
+        self.tcbArch.set_tcb_ipc_buffer(buffer);
+    }
 
-        let vm_rights: vm_rights_t =
-            unsafe { core::mem::transmute(buffer_cap.get_frame_vm_rights()) };
+    #[inline]
+    /// Prefetch the first cache line of the TCB's IPC buffer through the kernel window, so that
+    /// writing the message into the buffer does not stall on a cold line.
+    pub fn prefetch_ipc_buffer(&mut self) {
+        let buffer_cap = self.get_cspace(tcbBuffer).cap;
         if likely(
-            vm_rights == vm_rights_t::VMReadWrite
-                || (!is_receiver && vm_rights == vm_rights_t::VMReadOnly),
+            buffer_cap.get_cap_type() == CapTag::CapFrameCap
+                && buffer_cap.get_frame_is_device() == 0,
         ) {
-            let base_ptr = buffer_cap.get_frame_base_ptr();
             let page_bits = pageBitsForSize(buffer_cap.get_frame_size());
-            return Some(convert_to_mut_type_ref::<seL4_IPCBuffer>(
-                base_ptr + (w_buffer_ptr & MASK!(page_bits)),
-            ));
+            let ptr = buffer_cap.get_frame_base_ptr() + (self.tcbIPCBuffer & MASK!(page_bits));
+            unsafe { prefetch_write_data(ptr as *const seL4_IPCBuffer, 3) };
         }
-        return None;
+    }
+
+    /// Look up the IPC buffer of the TCB
+    /// # Arguments
+    /// * `is_receiver` - If the TCB is receiver
+    /// # Returns
+    /// The IPC buffer of the TCB
+    pub fn lookup_ipc_buffer(&mut self, is_receiver: bool) -> Option<&'static seL4_IPCBuffer> {
+        self.ipc_buffer_alias(is_receiver)
+            .map(convert_to_type_ref::<seL4_IPCBuffer>)
     }
 
     /// Look up the extra caps of the TCB
@@ -499,6 +629,9 @@ impl tcb_t {
             if i < seL4_MsgMaxExtraCaps {
                 res[i] = 0;
             }
+        } else {
+            // The caps are only named in the buffer, without one no cap is sent
+            res[0] = 0;
         }
         Ok(())
     }
@@ -531,6 +664,9 @@ impl tcb_t {
             if i < seL4_MsgMaxExtraCaps {
                 res[i] = 0;
             }
+        } else {
+            // The caps are only named in the buffer, without one no cap is sent
+            res[0] = 0;
         }
         Ok(())
     }
@@ -540,22 +676,44 @@ impl tcb_t {
         &mut self,
         is_receiver: bool,
     ) -> Option<&'static mut seL4_IPCBuffer> {
+        self.ipc_buffer_alias(is_receiver)
+            .map(convert_to_mut_type_ref::<seL4_IPCBuffer>)
+    }
+
+    #[inline]
+    /// The kernel alias of the TCB's IPC buffer: the address of the buffer in the kernel window,
+    /// taken from the frame cap in the buffer slot of the TCB.
+    ///
+    /// The user address of the buffer only provides the offset into the frame, it is never
+    /// dereferenced and the vspace of the TCB is never walked. The transfer code can thus reach
+    /// the buffers of a sender and a receiver in different vspaces without switching to either,
+    /// and unmapping or remapping the frame in between does not move the alias. The copy of the
+    /// frame cap in the buffer slot pins the frame: it cannot be retyped while the copy exists,
+    /// and deleting the copy empties the slot, after which the lookup fails.
+    /// # Arguments
+    /// * `is_receiver` - If the TCB is receiver, a receiver needs a writable frame
+    /// # Returns
+    /// The kernel address of the IPC buffer, `None` if the TCB has no usable buffer
+    fn ipc_buffer_alias(&mut self, is_receiver: bool) -> Option<usize> {
         let w_buffer_ptr = self.tcbIPCBuffer;
         let buffer_cap = self.get_cspace(tcbBuffer).cap;
-        if buffer_cap.get_cap_type() != CapTag::CapFrameCap {
+        if unlikely(buffer_cap.get_cap_type() != CapTag::CapFrameCap) {
+            return None;
+        }
+
+        if unlikely(buffer_cap.get_frame_is_device() != 0) {
             return None;
         }
 
         let vm_rights: vm_rights_t =
             unsafe { core::mem::transmute(buffer_cap.get_frame_vm_rights()) };
-        if vm_rights == vm_rights_t::VMReadWrite
-            || (!is_receiver && vm_rights == vm_rights_t::VMReadOnly)
-        {
+        if likely(
+            vm_rights == vm_rights_t::VMReadWrite
+                || (!is_receiver && vm_rights == vm_rights_t::VMReadOnly),
+        ) {
             let base_ptr = buffer_cap.get_frame_base_ptr();
             let page_bits = pageBitsForSize(buffer_cap.get_frame_size());
-            return Some(convert_to_mut_type_ref::<seL4_IPCBuffer>(
-                base_ptr + (w_buffer_ptr & MASK!(page_bits)),
-            ));
+            return Some(base_ptr + (w_buffer_ptr & MASK!(page_bits)));
         }
         return None;
     }
@@ -631,7 +789,12 @@ impl tcb_t {
             {
                 return None;
             }
-            return Some(convert_to_mut_type_ref::<cte_t>(lus_ret.slot as usize));
+            // A cap is never received over another one
+            let slot = convert_to_mut_type_ref::<cte_t>(lus_ret.slot as usize);
+            if slot.cap.get_cap_type() != CapTag::CapNullCap {
+                return None;
+            }
+            return Some(slot);
         }
         return None;
     }
@@ -699,7 +862,7 @@ impl tcb_t {
         let len = core::cmp::min(length, msgRegisterNum);
 
         for i in 0..len {
-            receiver.tcbArch.set_register(
+            receiver.tcbArch.set_user_register(
                 ArchReg::FaultMessage(id, i),
                 self.tcbArch.get_register(ArchReg::Msg(i)),
             );
@@ -709,7 +872,7 @@ impl tcb_t {
             for i in len..length {
                 receiver
                     .tcbArch
-                    .set_register(ArchReg::FaultMessage(id, i), buffer.msg[i])
+                    .set_user_register(ArchReg::FaultMessage(id, i), buffer.msg[i])
             }
         }
     }
@@ -781,8 +944,7 @@ impl tcb_t {
     /// Set the thread state
     #[inline]
     pub fn set_state(&mut self, state: ThreadState) {
-        self.tcbState.set_ts_type(state as usize);
-        schedule_tcb(self);
+        set_thread_state(self, state);
     }
 }
 
@@ -791,7 +953,13 @@ impl tcb_t {
 /// # Arguments
 /// * `tcb` - The TCB to set
 /// * `state` - The state
+///
+/// Prefer the transitions of [`crate::ThreadStateMachine`], which set the blocking object too.
 pub fn set_thread_state(tcb: &mut tcb_t, state: ThreadState) {
+    kernel_assert!(
+        2005,
+        crate::thread_state_transition_valid(tcb.get_state(), state)
+    );
     tcb.tcbState.set_ts_type(state as usize);
     schedule_tcb(tcb);
 }
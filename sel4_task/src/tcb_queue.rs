@@ -1,4 +1,4 @@
-use sel4_common::utils::convert_to_mut_type_ref;
+use sel4_common::utils::convert_to_option_checked_mut_type_ref;
 
 use super::tcb::tcb_t;
 
@@ -13,12 +13,51 @@ pub struct tcb_queue_t {
 }
 
 impl tcb_queue_t {
+    #[inline]
+    /// The thread at the head of the queue
+    pub fn head_tcb(&self) -> Option<&'static mut tcb_t> {
+        convert_to_option_checked_mut_type_ref::<tcb_t>(self.head)
+    }
+
+    #[inline]
+    /// The thread at the tail of the queue
+    pub fn tail_tcb(&self) -> Option<&'static mut tcb_t> {
+        convert_to_option_checked_mut_type_ref::<tcb_t>(self.tail)
+    }
+
     /// Append a tcb to the queue
+    ///
+    /// With `ENABLE_ORDERED_IPC` the queue is kept in priority order instead, the tcb goes behind
+    /// the threads of its own priority or higher.
     pub fn ep_append(&mut self, tcb: &mut tcb_t) {
-        if self.head == 0 {
-            self.head = tcb.get_ptr();
-        } else {
-            convert_to_mut_type_ref::<tcb_t>(self.tail).tcbEPNext = tcb.get_ptr();
+        #[cfg(feature = "ENABLE_ORDERED_IPC")]
+        {
+            let mut before = self.tail_tcb();
+            while let Some(prev) = before {
+                if prev.tcbPriority >= tcb.tcbPriority {
+                    break;
+                }
+                before = prev.ep_prev();
+            }
+            if let Some(prev) = before {
+                if prev.tcbEPNext != 0 {
+                    tcb.tcbEPPrev = prev.get_ptr();
+                    tcb.tcbEPNext = prev.tcbEPNext;
+                    prev.ep_next().unwrap().tcbEPPrev = tcb.get_ptr();
+                    prev.tcbEPNext = tcb.get_ptr();
+                    return;
+                }
+            } else if let Some(head) = self.head_tcb() {
+                tcb.tcbEPPrev = 0;
+                tcb.tcbEPNext = head.get_ptr();
+                head.tcbEPPrev = tcb.get_ptr();
+                self.head = tcb.get_ptr();
+                return;
+            }
+        }
+        match self.tail_tcb() {
+            Some(tail) => tail.tcbEPNext = tcb.get_ptr(),
+            None => self.head = tcb.get_ptr(),
         }
 
         tcb.tcbEPPrev = self.tail;
@@ -26,18 +65,23 @@ impl tcb_queue_t {
         self.tail = tcb.get_ptr();
     }
 
+    /// Move a tcb of the queue to the place of its new priority
+    #[cfg(feature = "ENABLE_ORDERED_IPC")]
+    pub fn ep_reorder(&mut self, tcb: &mut tcb_t) {
+        self.ep_dequeue(tcb);
+        self.ep_append(tcb);
+    }
+
     /// Dequeue a tcb from the queue
     pub fn ep_dequeue(&mut self, tcb: &mut tcb_t) {
-        if tcb.tcbEPPrev != 0 {
-            convert_to_mut_type_ref::<tcb_t>(tcb.tcbEPPrev).tcbEPNext = tcb.tcbEPNext;
-        } else {
-            self.head = tcb.tcbEPNext;
+        match tcb.ep_prev() {
+            Some(prev) => prev.tcbEPNext = tcb.tcbEPNext,
+            None => self.head = tcb.tcbEPNext,
         }
 
-        if tcb.tcbEPNext != 0 {
-            convert_to_mut_type_ref::<tcb_t>(tcb.tcbEPNext).tcbEPPrev = tcb.tcbEPPrev;
-        } else {
-            self.tail = tcb.tcbEPPrev;
+        match tcb.ep_next() {
+            Some(next) => next.tcbEPPrev = tcb.tcbEPPrev,
+            None => self.tail = tcb.tcbEPPrev,
         }
     }
 
@@ -1,6 +1,6 @@
 use sel4_common::plus_define_bitfield;
 
-#[derive(PartialEq, PartialOrd, Debug)]
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
 /// The state of a thread
 pub enum ThreadState {
     ThreadStateInactive = 0,
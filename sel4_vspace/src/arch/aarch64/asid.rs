@@ -9,8 +9,8 @@ use sel4_cspace::arch::cap_t;
 
 use crate::{asid_map_t, asid_pool_t, asid_t, findVSpaceForASID_ret, set_vm_root, PGDE, PTE};
 
-use super::asid_pool_from_addr;
 use super::machine::invalidate_local_tlb_asid;
+use super::{asid_pool_from_addr, invalidate_tlb_by_asid};
 
 pub const asid_map_asid_map_none: usize = 0;
 pub const asid_map_asid_map_vspace: usize = 1;
@@ -53,11 +53,11 @@ pub fn find_vspace_for_asid(asid: usize) -> findVSpaceForASID_ret {
     };
 
     match find_map_for_asid(asid) {
-        Some(asid_map) => {
+        Some(asid_map) if asid_map.get_type() == asid_map_asid_map_vspace => {
             ret.vspace_root = Some(asid_map.get_vspace_root() as *mut PGDE);
             ret.status = exception_t::EXCEPTION_NONE;
         }
-        None => {}
+        _ => {}
     }
     ret
 }
@@ -70,7 +70,9 @@ pub fn delete_asid(asid: usize, vspace: *mut PTE, cap: &cap_t) -> Result<(), loo
         if asid_map.get_type() == asid_map_asid_map_vspace
             && asid_map.get_vspace_root() == vspace as usize
         {
-            invalidate_local_tlb_asid(asid);
+            #[cfg(feature = "ENABLE_ARM_HYP")]
+            super::release_vmid(asid);
+            invalidate_tlb_by_asid(asid);
             pool[asid & MASK!(asidLowBits)] = asid_map_t::new_none();
             return set_vm_root(cap);
         }
@@ -88,12 +90,25 @@ pub fn delete_asid_pool(
     if pool as usize == pool_in_table {
         // clear all asid in target asid pool
         let pool = convert_to_mut_type_ref::<asid_pool_t>(pool_in_table);
+        #[cfg(feature = "ENABLE_SMP")]
+        let mut remote_mask = super::asid_core_mask(0);
         for offset in 0..BIT!(asidLowBits) {
             let asid_map = pool[offset];
             if asid_map.get_type() == asid_map_asid_map_vspace {
+                #[cfg(feature = "ENABLE_ARM_HYP")]
+                super::release_vmid(asid_base + offset);
                 invalidate_local_tlb_asid(asid_base + offset);
+                #[cfg(feature = "ENABLE_SMP")]
+                {
+                    remote_mask |= super::asid_core_mask(asid_base + offset);
+                }
             }
         }
+        // One whole TLB flush on the other cores instead of a round trip per ASID
+        #[cfg(feature = "ENABLE_SMP")]
+        unsafe {
+            crate::deps::ipi_remote_invalidate_tlb(remote_mask);
+        }
         set_asid_pool_by_index(asid_base >> asidLowBits, 0);
         return set_vm_root(default_vspace_cap);
     }
@@ -104,7 +119,7 @@ pub fn delete_asid_pool(
 #[inline]
 pub fn write_it_asid_pool(it_ap_cap: &cap_t, it_vspace_cap: &cap_t) {
     let ap = asid_pool_from_addr(it_ap_cap.get_cap_ptr());
-    let asid_map = asid_map_t::new_vspace(it_vspace_cap.get_pgd_base_ptr());
+    let asid_map = asid_map_t::new_vspace(it_vspace_cap.get_pgd_base_ptr(), 0, 0, 0);
     ap[IT_ASID] = asid_map;
     set_asid_pool_by_index(IT_ASID >> asidLowBits, ap as *const _ as usize);
 }
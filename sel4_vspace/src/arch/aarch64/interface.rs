@@ -3,8 +3,8 @@ use core::ops::{Deref, DerefMut};
 
 use super::machine::*;
 use crate::{
-    ap_from_vm_rights, asid_t, find_map_for_asid, find_vspace_for_asid, pptr_t, pptr_to_paddr,
-    vm_attributes_t, vptr_t, PDE, PGDE, PTE, PUDE,
+    ap_from_vm_rights, asid_map_asid_map_vspace, asid_t, find_map_for_asid, find_vspace_for_asid,
+    kpptr_to_paddr, pptr_t, pptr_to_paddr, vm_attributes_t, vptr_t, PDE, PGDE, PTE, PUDE,
 };
 use sel4_common::arch::config::PPTR_BASE;
 use sel4_common::sel4_config::{ARM_Large_Page, ARM_Small_Page};
@@ -118,12 +118,56 @@ pub fn set_kernel_page_table_by_index(idx: usize, pte: PTE) {
         armKSGlobalKernelPT[idx] = pte;
     }
 }
+/// The root `vspace_root` stands for and its ASID, as long as the cap still names the root its
+/// ASID is assigned to.
+///
+/// # Returns
+///
+/// `Err` with the lookup fault of the ASID, if there is one, when the root is not usable, for
+/// example because its ASID was deleted or it was revoked and the ASID reassigned.
+pub(crate) fn find_vm_root(
+    vspace_root: &cap_t,
+) -> Result<(*mut PGDE, asid_t), Option<lookup_fault_t>> {
+    if !vspace_root.is_valid_native_root() {
+        return Err(None);
+    }
+    let pgd = vspace_root.get_pgd_base_ptr() as *mut PGDE;
+    let asid = vspace_root.get_pgd_mapped_asid();
+    // A guest address space holds stage-2 descriptors, a thread never runs on it
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    if super::is_guest_asid(asid) {
+        return Err(None);
+    }
+    let find_ret = find_vspace_for_asid(asid);
+    if unlikely(find_ret.status != exception_t::EXCEPTION_NONE) {
+        return Err(find_ret.lookup_fault);
+    }
+    match find_ret.vspace_root {
+        Some(root) if root == pgd => Ok((pgd, asid)),
+        _ => Err(None),
+    }
+}
+
 /// 根据给定的`vspace_root`设置相应的页表，会检查`vspace_root`是否合法，如果不合法默认设置为内核页表
 ///
-/// Use page table in vspace_root to set the satp register.
+/// Use page table in vspace_root to set the ttbr0 register. A root that is not usable leaves the
+/// core on the global user vspace, which maps nothing, so the thread faults on its first access
+/// instead of running on a table that may have been freed. The root is loaded with its ASID, so
+/// its TLB entries are tagged with it and the invalidations by ASID reach them.
 pub fn set_vm_root(vspace_root: &cap_t) -> Result<(), lookup_fault_t> {
-    setCurrentUserVSpaceRoot(pptr_to_paddr(vspace_root.get_pgd_base_ptr()));
-    Ok(())
+    match find_vm_root(vspace_root) {
+        Ok((pgd, asid)) => {
+            setCurrentUserVSpaceRoot(ttbr_new(asid, pptr_to_paddr(pgd as usize)));
+            Ok(())
+        }
+        Err(lookup_fault) => {
+            setCurrentUserVSpaceRoot(ttbr_new(
+                0,
+                kpptr_to_paddr(get_arm_global_user_vspace_base()),
+            ));
+            lookup_fault.map_or(Ok(()), Err)
+        }
+    }
 }
 
 pub fn activate_kernel_window() {
@@ -200,6 +244,10 @@ pub fn set_vm_root_for_flush_with_thread_root(
     {
         return false;
     }
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    if super::is_guest_asid(asid) {
+        return false;
+    }
 
     // armv_context_switch(vspace, asid);
     setCurrentUserVSpaceRoot(ttbr_new(asid, vspace as usize));
@@ -208,7 +256,7 @@ pub fn set_vm_root_for_flush_with_thread_root(
 
 pub fn page_upper_directory_mapped(asid: asid_t, vaddr: vptr_t, pud: &PUDE) -> Option<*mut PGDE> {
     match find_map_for_asid(asid) {
-        Some(asid_map) => {
+        Some(asid_map) if asid_map.get_type() == asid_map_asid_map_vspace => {
             let lookup_ret = PGDE::new_from_pte(asid_map.get_vspace_root()).lookup_pgd_slot(vaddr);
             if lookup_ret.status != exception_t::EXCEPTION_NONE {
                 return None;
@@ -224,13 +272,13 @@ pub fn page_upper_directory_mapped(asid: asid_t, vaddr: vptr_t, pud: &PUDE) -> O
 
             return Some(slot);
         }
-        None => None,
+        _ => None,
     }
 }
 
 pub fn page_directory_mapped(asid: asid_t, vaddr: vptr_t, pd: &PDE) -> Option<*mut PUDE> {
     match find_map_for_asid(asid) {
-        Some(asid_map) => {
+        Some(asid_map) if asid_map.get_type() == asid_map_asid_map_vspace => {
             let lookup_ret = PGDE::new_from_pte(asid_map.get_vspace_root()).lookup_pud_slot(vaddr);
             if lookup_ret.status != exception_t::EXCEPTION_NONE {
                 return None;
@@ -246,14 +294,14 @@ pub fn page_directory_mapped(asid: asid_t, vaddr: vptr_t, pd: &PDE) -> Option<*m
 
             return Some(slot);
         }
-        None => None,
+        _ => None,
     }
 }
 
 /// TODO: Make pt as usize of
 pub fn page_table_mapped(asid: asid_t, vaddr: vptr_t, pt: &PTE) -> Option<*mut PDE> {
     match find_map_for_asid(asid) {
-        Some(asid_map) => {
+        Some(asid_map) if asid_map.get_type() == asid_map_asid_map_vspace => {
             let lookup_ret = PGDE::new_from_pte(asid_map.get_vspace_root()).lookup_pd_slot(vaddr);
             if lookup_ret.status != exception_t::EXCEPTION_NONE {
                 return None;
@@ -267,18 +315,43 @@ pub fn page_table_mapped(asid: asid_t, vaddr: vptr_t, pt: &PTE) -> Option<*mut P
 
             return Some(slot);
         }
-        None => None,
+        _ => None,
     }
 }
 
 #[inline]
 pub fn invalidate_tlb_by_asid(asid: asid_t) {
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    if super::is_guest_asid(asid) {
+        super::invalidate_guest_tlb(asid);
+        return;
+    }
     invalidate_local_tlb_asid(asid);
+    #[cfg(feature = "ENABLE_SMP")]
+    remote_invalidate_tlb_by_asid(asid);
 }
 
 #[inline]
 pub fn invalidate_tlb_by_asid_va(asid: asid_t, vaddr: vptr_t) {
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    if super::is_guest_asid(asid) {
+        super::invalidate_guest_tlb(asid);
+        return;
+    }
     invalidate_local_tlb_va_asid((asid << 48) | vaddr >> seL4_PageBits);
+    // The other cores drop the whole ASID, there is no call for a single page
+    #[cfg(feature = "ENABLE_SMP")]
+    remote_invalidate_tlb_by_asid(asid);
+}
+
+/// Invalidate the TLB entries of `asid` on the other cores that may hold them. The cores running
+/// a vspace root loaded without an ASID drop their whole TLB, `asid` does not match its entries.
+#[cfg(feature = "ENABLE_SMP")]
+pub(crate) fn remote_invalidate_tlb_by_asid(asid: asid_t) {
+    unsafe {
+        crate::deps::ipi_remote_invalidate_tlb_by_asid(asid, asid_core_mask(asid));
+        crate::deps::ipi_remote_invalidate_tlb(asid_core_mask(0));
+    }
 }
 
 pub fn unmap_page_upper_directory(asid: asid_t, vaddr: vptr_t, pud: &PUDE) {
@@ -317,7 +390,24 @@ pub fn unmap_page_table(asid: asid_t, vaddr: vptr_t, pt: &PTE) {
     }
 }
 
-/// Unmap a page table
+/// Drop the TLB entries of a page just unmapped from the vspace of `asid`.
+fn invalidate_tlb_unmapped_page(asid: asid_t) {
+    #[cfg(feature = "ENABLE_ARM_HYP")]
+    if super::is_guest_asid(asid) {
+        super::invalidate_guest_tlb(asid);
+        return;
+    }
+    // TODO: Use Clean By VA instead of this line.
+    unsafe { core::arch::asm!("tlbi vmalle1; dsb sy; isb") };
+    #[cfg(feature = "ENABLE_SMP")]
+    remote_invalidate_tlb_by_asid(asid);
+}
+
+/// Unmap the frame at `pptr` from `vptr` in the vspace of `asid`.
+///
+/// The mapping recorded in a frame cap can be stale: if the vspace of `asid` was deleted, its
+/// asid map entry is none and there is nothing to unmap. The entry is only cleared if it still
+/// maps this frame, so a recycled page table which now belongs to another vspace is left alone.
 /// TODO: Remove result Result<(), lookup_fault_t>
 pub fn unmapPage(
     page_size: usize,
@@ -326,30 +416,29 @@ pub fn unmapPage(
     pptr: pptr_t,
 ) -> Result<(), lookup_fault_t> {
     let addr = pptr_to_paddr(pptr);
-    let find_ret = find_vspace_for_asid(asid);
-    if unlikely(find_ret.status != exception_t::EXCEPTION_NONE) {
-        return Ok(());
-    }
+    let vspace_root = match find_map_for_asid(asid) {
+        Some(asid_map) if asid_map.get_type() == asid_map_asid_map_vspace => {
+            asid_map.get_vspace_root()
+        }
+        _ => return Ok(()),
+    };
     match page_size {
         ARM_Small_Page => {
-            let lu_ret =
-                PGDE::new_from_pte(find_ret.vspace_root.unwrap() as usize).lookup_pt_slot(vptr);
+            let lu_ret = PGDE::new_from_pte(vspace_root).lookup_pt_slot(vptr);
             if unlikely(lu_ret.status != exception_t::EXCEPTION_NONE) {
                 return Ok(());
             }
             let pte = ptr_to_mut(lu_ret.ptSlot);
             if pte.pte_ptr_get_present() && pte.pte_ptr_get_page_base_address() == addr {
                 *pte = PTE(0);
-                // TODO: Use Clean By VA instead of this line.
-                unsafe { core::arch::asm!("tlbi vmalle1; dsb sy; isb") };
+                invalidate_tlb_unmapped_page(asid);
                 log::warn!("Need to clean D-Cache using cleanByVA_PoU");
             }
             Ok(())
         }
         ARM_Large_Page => {
             log::info!("unmap large page: {:#x?}", vptr);
-            let lu_ret =
-                PGDE::new_from_pte(find_ret.vspace_root.unwrap() as usize).lookup_pd_slot(vptr);
+            let lu_ret = PGDE::new_from_pte(vspace_root).lookup_pd_slot(vptr);
             if unlikely(lu_ret.status != exception_t::EXCEPTION_NONE) {
                 return Ok(());
             }
@@ -357,8 +446,7 @@ pub fn unmapPage(
             // TODO: Rename get_pt_base_address to get_base_address
             if pde.get_present() && pde.get_pt_base_address() == addr {
                 *pde = PDE(0);
-                // TODO: Use Clean By VA instead of this line.
-                unsafe { core::arch::asm!("tlbi vmalle1; dsb sy; isb") };
+                invalidate_tlb_unmapped_page(asid);
                 log::warn!("Need to clean D-Cache using cleanByVA_PoU");
             }
             Ok(())
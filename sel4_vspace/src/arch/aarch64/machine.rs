@@ -1,7 +1,19 @@
 use core::arch::asm;
 
 use aarch64_cpu::registers::{Writeable, TTBR0_EL1, TTBR1_EL1};
+#[cfg(feature = "ENABLE_SMP")]
+use core::sync::atomic::{AtomicUsize, Ordering};
 use sel4_common::MASK;
+#[cfg(feature = "ENABLE_SMP")]
+use sel4_common::{sel4_config::CONFIG_MAX_NUM_NODES, utils::cpu_id, BIT};
+
+/// The ASID in `TTBR0_EL1` of each core, 0 for a user vspace root loaded without one
+#[cfg(feature = "ENABLE_SMP")]
+static ksCurUserASID: [AtomicUsize; CONFIG_MAX_NUM_NODES] = {
+    const INIT: AtomicUsize = AtomicUsize::new(0);
+    [INIT; CONFIG_MAX_NUM_NODES]
+};
+
 #[inline]
 pub fn setCurrentKernelVSpaceRoot(val: usize) {
     TTBR1_EL1.set(val as _);
@@ -12,6 +24,22 @@ pub fn setCurrentUserVSpaceRoot(val: usize) {
     TTBR0_EL1.set(val as _);
     // FIXME: use aisd instead of flush tlb
     unsafe { core::arch::asm!("tlbi vmalle1; dsb sy; isb") };
+    #[cfg(feature = "ENABLE_SMP")]
+    ksCurUserASID[cpu_id()].store(val >> 48, Ordering::Release);
+}
+
+/// The cores whose TLB may hold entries of `asid`, with `asid` 0 the cores running a vspace root
+/// loaded without an ASID. Switching the user vspace root flushes the local TLB, so only the
+/// vspace root a core currently runs can have entries there.
+#[cfg(feature = "ENABLE_SMP")]
+pub fn asid_core_mask(asid: usize) -> usize {
+    let mut mask = 0;
+    for (core, cur) in ksCurUserASID.iter().enumerate() {
+        if cur.load(Ordering::Acquire) == asid {
+            mask |= BIT!(core);
+        }
+    }
+    mask
 }
 
 #[inline]
@@ -153,6 +181,55 @@ pub fn clean_invalidate_d_poc() {
     }
 }
 
+/// Clean and invalidate the level 1 caches of `cache_type`, an `arm_cache_type`, by set/way.
+pub fn clean_invalidate_l1_by_type(cache_type: usize) {
+    dsb();
+    if cache_type & arm_cache_type::ARMCacheD as usize != 0 {
+        clean_invalidate_d_by_level(0);
+        dsb();
+    }
+    if cache_type & arm_cache_type::ARMCacheI as usize != 0 {
+        invalidate_i_pou();
+        dsb();
+    }
+}
+
+/// Clean and invalidate the data cache lines of `[start, end)` to the point of coherency.
+pub fn clean_invalidate_d_range(start: usize, end: usize) {
+    let line = 4 << ((read_ctr() >> 16) & MASK!(4));
+    let mut vaddr = start & !(line - 1);
+    while vaddr < end {
+        unsafe {
+            asm!("dc civac, {}", in(reg) vaddr);
+        }
+        vaddr += line;
+    }
+    dsb();
+}
+
+/// Invalidate the instruction cache lines of `[start, end)` to the point of unification.
+pub fn invalidate_i_range(start: usize, end: usize) {
+    let line = 4 << (read_ctr() & MASK!(4));
+    let mut vaddr = start & !(line - 1);
+    while vaddr < end {
+        unsafe {
+            asm!("ic ivau, {}", in(reg) vaddr);
+        }
+        vaddr += line;
+    }
+    dsb();
+    isb();
+}
+
+#[inline]
+fn read_ctr() -> usize {
+    let ctr: usize;
+    unsafe {
+        asm!("mrs {}, ctr_el0", out(reg) ctr);
+    }
+    ctr
+}
+
 #[inline]
 fn clean_invalidate_d_by_level(l: usize) {
     let lsize = read_cache_size(l, 0);
@@ -4,13 +4,23 @@ mod interface;
 mod machine;
 mod pagetable;
 mod pte;
+#[cfg(feature = "ENABLE_ARM_HYP")]
+mod stage2;
 mod structures;
 mod utils;
 pub use asid::*;
 pub use boot::*;
 pub use interface::*;
-pub use machine::{setCurrentUserVSpaceRoot, ttbr_new};
+#[cfg(feature = "ENABLE_SMP")]
+pub(crate) use machine::asid_core_mask;
+pub use machine::{
+    clean_invalidate_d_range, clean_invalidate_l1_by_type, clean_invalidate_l1_caches,
+    invalidate_i_range, invalidate_local_tlb, invalidate_local_tlb_asid, setCurrentUserVSpaceRoot,
+    ttbr_new,
+};
 pub use pagetable::create_it_pud_cap;
 pub use pte::PTEFlags;
+#[cfg(feature = "ENABLE_ARM_HYP")]
+pub use stage2::*;
 pub use structures::*;
 pub use utils::*;
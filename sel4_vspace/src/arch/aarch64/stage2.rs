@@ -0,0 +1,289 @@
+//! Stage-2 translation of guest physical memory, enabled by the `ENABLE_ARM_HYP` feature.
+//!
+//! A guest address space is a vspace root assigned to an ASID with `ARMASIDPoolAssignVM`. It uses
+//! the same four level tables as a native vspace, starting at level 0, but its leaves are stage-2
+//! descriptors and the addresses mapped in it are intermediate physical addresses (IPAs) of the
+//! guest. Its TLB entries are tagged with a VMID instead of the ASID: the VMIDs are allocated to
+//! guest ASIDs on demand, round robin, and taken back from their owner in turn when all of them are
+//! in use, like the hardware ASIDs of the C kernel.
+//!
+//! `VTCR_EL2` and `VTTBR_EL2` are only accessible at EL2, so the feature needs the kernel to be
+//! entered at EL2 with `HCR_EL2.E2H` set by the loader, where the EL1 system register accesses of
+//! the kernel go to their EL2 counterparts. The VCPUs do not enter their guest yet,
+//! [`set_guest_vm_root`] is what a VCPU switch will load before it does.
+
+use core::arch::asm;
+
+use sel4_common::{
+    arch::vm_rights_t,
+    sel4_config::{asidLowBits, ARM_Huge_Page, ARM_Large_Page, ARM_Small_Page},
+    utils::convert_to_option_mut_type_ref,
+    BIT, MASK,
+};
+
+use super::machine::{dsb, isb};
+use super::{asid_map_asid_map_vspace, get_asid_pool_by_index};
+use crate::{asid_map_t, asid_pool_t, asid_t, pptr_to_paddr, vm_attributes_t, PDE, PTE, PUDE};
+
+/// The number of VMID bits used, the 16 bit VMIDs of `FEAT_VMID16` are not used
+pub const VMID_BITS: usize = 8;
+
+/// The ASID owning each VMID, 0 for none. VMID 0 stays with the host translation regime.
+static mut armKSVMIDTable: [asid_t; BIT!(VMID_BITS)] = [0; BIT!(VMID_BITS)];
+
+/// The VMID the next search starts from
+static mut armKSNextVMID: usize = 1;
+
+/// The size of the guest IPA space in bits, set by [`vtcr_init`]
+static mut armKSGuestIPABits: usize = 0;
+
+/// Fields of `VTCR_EL2`: 4KB granule, walks start at level 0 and are inner shareable, write-back
+/// write-allocate cacheable
+const VTCR_SL0_LEVEL0: usize = 0b10 << 6;
+const VTCR_IRGN0_WBWA: usize = 0b01 << 8;
+const VTCR_ORGN0_WBWA: usize = 0b01 << 10;
+const VTCR_SH0_INNER: usize = 0b11 << 12;
+const VTCR_RES1: usize = BIT!(31);
+
+bitflags::bitflags! {
+    /// Flags of a stage-2 block or page descriptor.
+    pub struct S2PTEFlags: usize {
+        /// Whether the descriptor is valid.
+        const VALID =           BIT!(0);
+        /// The descriptor gives a 4KB page (not a 2M, 1G block).
+        const NON_BLOCK =       BIT!(1);
+        /// Memory attributes: Device-nGnRnE.
+        const MEMATTR_DEVICE_nGnRnE = 0b0000 << 2;
+        /// Memory attributes: Device-nGnRE.
+        const MEMATTR_DEVICE_nGnRE = 0b0001 << 2;
+        /// Memory attributes: Normal, outer and inner non-cacheable.
+        const MEMATTR_NORMAL_NONCACHE = 0b0101 << 2;
+        /// Memory attributes: Normal, outer and inner write-back cacheable.
+        const MEMATTR_NORMAL =  0b1111 << 2;
+        /// Stage-2 access permission: readable by the guest.
+        const S2AP_READ =       BIT!(6);
+        /// Stage-2 access permission: writable by the guest.
+        const S2AP_WRITE =      BIT!(7);
+        /// Shareability: Inner Shareable.
+        const SH_INNER =        0b11 << 8;
+        /// The Access flag.
+        const AF =              BIT!(10);
+        /// Execute-never at EL1 and EL0 of the guest.
+        const XN =              BIT!(54);
+    }
+}
+
+/// Detect the IPA size and set up `VTCR_EL2`, called on every core before a guest address space
+/// is used.
+pub fn vtcr_init() {
+    let mmfr0: usize;
+    unsafe {
+        asm!("mrs {}, id_aa64mmfr0_el1", out(reg) mmfr0);
+    }
+    let pa_range = mmfr0 & 0xf;
+    let pa_bits = match pa_range {
+        0 => 32,
+        1 => 36,
+        2 => 40,
+        3 => 42,
+        4 => 44,
+        _ => 48,
+    };
+    // A walk starting at level 0 needs an IPA space of 40 bits at least
+    assert!(
+        pa_bits >= 40,
+        "stage-2 translation needs 40 PA bits, got {}",
+        pa_bits
+    );
+    let vtcr = (64 - pa_bits)
+        | VTCR_SL0_LEVEL0
+        | VTCR_IRGN0_WBWA
+        | VTCR_ORGN0_WBWA
+        | VTCR_SH0_INNER
+        | pa_range.min(5) << 16
+        | VTCR_RES1;
+    unsafe {
+        armKSGuestIPABits = pa_bits;
+        asm!("msr vtcr_el2, {}", in(reg) vtcr);
+    }
+    isb();
+}
+
+/// The highest IPA a guest address space can map.
+#[inline]
+pub fn guest_ipa_top() -> usize {
+    unsafe { BIT!(armKSGuestIPABits) - 1 }
+}
+
+#[inline]
+pub const fn vttbr_new(vmid: usize, addr: usize) -> usize {
+    (vmid & MASK!(VMID_BITS)) << 48 | (addr & 0xffffffffffff)
+}
+
+/// The asid map entry of `asid`, if it is a guest address space.
+fn find_guest_map_for_asid(asid: asid_t) -> Option<&'static mut asid_map_t> {
+    let pool =
+        convert_to_option_mut_type_ref::<asid_pool_t>(get_asid_pool_by_index(asid >> asidLowBits))?;
+    let asid_map = &mut pool[asid & MASK!(asidLowBits)];
+    if asid_map.get_type() == asid_map_asid_map_vspace && asid_map.get_is_guest() != 0 {
+        Some(asid_map)
+    } else {
+        None
+    }
+}
+
+/// Whether `asid` is assigned to a guest address space.
+#[inline]
+pub fn is_guest_asid(asid: asid_t) -> bool {
+    find_guest_map_for_asid(asid).is_some()
+}
+
+#[inline]
+fn next_vmid(vmid: usize) -> usize {
+    if vmid == MASK!(VMID_BITS) {
+        1
+    } else {
+        vmid + 1
+    }
+}
+
+/// Find a free VMID, taking back the next one in turn if there is none.
+fn find_free_vmid() -> usize {
+    unsafe {
+        let start = armKSNextVMID;
+        let mut vmid = start;
+        while armKSVMIDTable[vmid] != 0 {
+            vmid = next_vmid(vmid);
+            if vmid == start {
+                if let Some(asid_map) = find_guest_map_for_asid(armKSVMIDTable[vmid]) {
+                    asid_map.set_stored_vmid_valid(0);
+                }
+                armKSVMIDTable[vmid] = 0;
+                invalidate_tlb_by_vmid(vmid);
+            }
+        }
+        armKSNextVMID = next_vmid(vmid);
+        vmid
+    }
+}
+
+/// The VMID of the guest address space of `asid`, allocated if it has none.
+pub fn get_vmid(asid: asid_t) -> usize {
+    let asid_map = find_guest_map_for_asid(asid).expect("no guest address space for the ASID");
+    if asid_map.get_stored_vmid_valid() != 0 {
+        return asid_map.get_stored_hw_vmid();
+    }
+    let vmid = find_free_vmid();
+    asid_map.set_stored_hw_vmid(vmid);
+    asid_map.set_stored_vmid_valid(1);
+    unsafe {
+        armKSVMIDTable[vmid] = asid;
+    }
+    vmid
+}
+
+/// Give back the VMID of `asid` and drop its TLB entries, called when the guest address space is
+/// deleted.
+pub fn release_vmid(asid: asid_t) {
+    if let Some(asid_map) = find_guest_map_for_asid(asid) {
+        if asid_map.get_stored_vmid_valid() != 0 {
+            let vmid = asid_map.get_stored_hw_vmid();
+            asid_map.set_stored_vmid_valid(0);
+            unsafe {
+                armKSVMIDTable[vmid] = 0;
+            }
+            invalidate_tlb_by_vmid(vmid);
+        }
+    }
+}
+
+/// Drop the stage-1 and stage-2 TLB entries of `vmid` on all the cores of the inner shareable
+/// domain, so no IPI is needed.
+pub fn invalidate_tlb_by_vmid(vmid: usize) {
+    let vttbr: usize;
+    unsafe {
+        asm!("mrs {}, vttbr_el2", out(reg) vttbr);
+        asm!("msr vttbr_el2, {}", in(reg) vttbr_new(vmid, 0));
+        isb();
+        dsb();
+        asm!("tlbi vmalls12e1is");
+        dsb();
+        asm!("msr vttbr_el2, {}", in(reg) vttbr);
+    }
+    isb();
+}
+
+/// Drop the TLB entries of the guest address space of `asid`, if it has a VMID.
+pub fn invalidate_guest_tlb(asid: asid_t) {
+    if let Some(asid_map) = find_guest_map_for_asid(asid) {
+        if asid_map.get_stored_vmid_valid() != 0 {
+            invalidate_tlb_by_vmid(asid_map.get_stored_hw_vmid());
+        }
+    }
+}
+
+/// Load the guest address space of `asid` into `VTTBR_EL2`.
+pub fn set_guest_vm_root(asid: asid_t) {
+    let vmid = get_vmid(asid);
+    let vspace_root = find_guest_map_for_asid(asid).unwrap().get_vspace_root();
+    unsafe {
+        asm!("msr vttbr_el2, {}", in(reg) vttbr_new(vmid, pptr_to_paddr(vspace_root)));
+    }
+    isb();
+}
+
+/// The stage-2 leaf descriptor mapping the frame at `paddr` of `page_size` into a guest.
+pub fn make_guest_pte(
+    paddr: usize,
+    vm_rights: vm_rights_t,
+    attributes: vm_attributes_t,
+    page_size: usize,
+) -> usize {
+    let mut flags = S2PTEFlags::VALID | S2PTEFlags::AF | S2PTEFlags::SH_INNER;
+    flags |= if attributes.get_armDevice() {
+        S2PTEFlags::MEMATTR_DEVICE_nGnRE
+    } else if attributes.get_armPageCacheable() {
+        S2PTEFlags::MEMATTR_NORMAL
+    } else {
+        S2PTEFlags::MEMATTR_NORMAL_NONCACHE
+    };
+    if attributes.get_armExecuteNever() {
+        flags |= S2PTEFlags::XN;
+    }
+    flags |= match vm_rights {
+        vm_rights_t::VMKernelOnly => S2PTEFlags::empty(),
+        vm_rights_t::VMReadOnly => S2PTEFlags::S2AP_READ,
+        vm_rights_t::VMReadWrite => S2PTEFlags::S2AP_READ | S2PTEFlags::S2AP_WRITE,
+    };
+    if page_size != ARM_Huge_Page && page_size != ARM_Large_Page {
+        flags |= S2PTEFlags::NON_BLOCK;
+    }
+    (paddr & 0xfffffffff000) | flags.bits()
+}
+
+/// Stage-2 counterpart of `make_user_1st_level`.
+pub fn make_guest_1st_level(
+    paddr: usize,
+    vm_rights: vm_rights_t,
+    attributes: vm_attributes_t,
+) -> PUDE {
+    PUDE::new_from_pte(make_guest_pte(paddr, vm_rights, attributes, ARM_Huge_Page))
+}
+
+/// Stage-2 counterpart of `make_user_2nd_level`.
+pub fn make_guest_2nd_level(
+    paddr: usize,
+    vm_rights: vm_rights_t,
+    attributes: vm_attributes_t,
+) -> PDE {
+    PDE::new_from_pte(make_guest_pte(paddr, vm_rights, attributes, ARM_Large_Page))
+}
+
+/// Stage-2 counterpart of `makeUser3rdLevel`.
+pub fn make_guest_3rd_level(
+    paddr: usize,
+    vm_rights: vm_rights_t,
+    attributes: vm_attributes_t,
+) -> PTE {
+    PTE(make_guest_pte(paddr, vm_rights, attributes, ARM_Small_Page))
+}
@@ -25,7 +25,27 @@ impl vm_attributes_t {
         }
     }
 
+    /// Kernel-internal bit selecting Device-nGnRE for device frames.
+    pub fn get_armDevice(&self) -> bool {
+        (self.0 & 0x8) != 0
+    }
+
+    /// The attributes a user passes in, `ARMPageCacheable`, `ARMParityEnabled` and
+    /// `ARMExecuteNever`. The kernel-internal bits are cleared.
+    pub fn from_user_word(w: usize) -> Self {
+        Self(w & 0x7)
+    }
+
+    /// Attributes used to map a device frame: never cacheable and mapped as Device-nGnRE,
+    /// whatever the caller asked for. Decoding rejects executable device frames before.
+    pub fn for_device_frame(&self) -> Self {
+        Self((self.0 | 0x8) & !0x1)
+    }
+
     pub fn get_attr_index(&self) -> mair_types {
+        if self.get_armDevice() {
+            return mair_types::DEVICE_nGnRE;
+        }
         if self.get_armPageCacheable() {
             return mair_types::NORMAL;
         }
@@ -131,8 +151,11 @@ plus_define_bitfield! {
 plus_define_bitfield! {
     asid_map_t, 1, 0, 0, 1 => {
         new_none, 0 => {},
-        new_vspace, 0 => {
-            vspace_root , get_vspace_root , set_vspace_root , 0, 0, 48, 0 ,true
+        new_vspace, 1 => {
+            vspace_root , get_vspace_root , set_vspace_root , 0, 1, 47, 1 ,true,
+            stored_hw_vmid, get_stored_hw_vmid, set_stored_hw_vmid, 0, 48, 8, 0, false,
+            stored_vmid_valid, get_stored_vmid_valid, set_stored_vmid_valid, 0, 56, 1, 0, false,
+            is_guest, get_is_guest, set_is_guest, 0, 57, 1, 0, false
         }
     }
 }
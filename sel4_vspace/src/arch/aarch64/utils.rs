@@ -177,10 +177,10 @@ impl_multi!(PGDE, PUDE, PDE, PTE {
         self as *const Self as usize
     }
 
-	#[inline]
-	pub fn get_mut_ptr(&mut self) -> usize {
-		self as *mut Self as usize
-	}
+    #[inline]
+    pub fn get_mut_ptr(&mut self) -> usize {
+        self as *mut Self as usize
+    }
 
     /// Get the next level paddr
     #[inline]
@@ -396,7 +396,7 @@ impl PGDE {
                         }
                     }
                 }
-                _ => panic!("invalid pt slot type:{}", pudSlot.get_type()),
+                pude_tag_t::pude_invalid => {}
             }
         }
         ret
@@ -11,6 +11,8 @@ use sel4_cspace::arch::cap_t;
 
 use crate::{asid_pool_t, asid_t, findVSpaceForASID_ret, pptr_t, set_vm_root, PTE};
 
+use super::promote::vspace_forget_asid;
+
 ///存放`asid pool`的数组，每一个下标对应一个`asid pool`，
 ///一个`asid pool`可以存放`asidLowBits`个asid值
 #[no_mangle]
@@ -40,6 +42,9 @@ pub fn delete_asid(
         if poolPtr as usize != 0 && (*poolPtr).array[asid & MASK!(asidLowBits)] == vspace {
             #[cfg(target_arch = "riscv64")]
             hwASIDFlush(asid);
+            #[cfg(feature = "ENABLE_SHARED_PT")]
+            super::shared_pt_remove_asids(asid, 1);
+            vspace_forget_asid(asid);
             (*poolPtr).array[asid & MASK!(asidLowBits)] = 0 as *mut PTE;
             set_vm_root(&default_vspace_cap)
         } else {
@@ -113,6 +118,8 @@ pub fn delete_asid_pool(
 ) -> Result<(), lookup_fault_t> {
     unsafe {
         if riscvKSASIDTable[asid_base >> asidLowBits] == pool {
+            #[cfg(feature = "ENABLE_SHARED_PT")]
+            super::shared_pt_remove_asids(asid_base, BIT!(asidLowBits));
             riscvKSASIDTable[asid_base >> asidLowBits] = 0 as *mut asid_pool_t;
             set_vm_root(default_vspace_cap)
         } else {
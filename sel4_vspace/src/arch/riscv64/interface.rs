@@ -6,49 +6,70 @@ use crate::PTEFlags;
 use crate::RISCV_GET_PT_INDEX;
 use core::intrinsics::unlikely;
 use sel4_common::sel4_config::CONFIG_PT_LEVELS;
-use sel4_common::{fault::lookup_fault_t, structures::exception_t, utils::convert_to_mut_type_ref};
+use sel4_common::{fault::lookup_fault_t, structures::exception_t};
 use sel4_cspace::interface::{cap_t, CapTag};
 
 use crate::PTE;
 
-use super::{kpptr_to_paddr, pagetable::kernel_root_pageTable, pptr_to_paddr, setVSpaceRoot};
+use super::{
+    kpptr_to_paddr, pagetable::kernel_root_pageTable, pptr_to_paddr, promote::vspace_demote_table,
+    setVSpaceRoot,
+};
 
-///根据给定的`vspace_root`设置相应的页表，会检查`vspace_root`是否合法，如果不合法默认设置为内核页表
+/// The root page table `vspace_root` stands for and its ASID, as long as the cap still names the
+/// root its ASID is assigned to.
 ///
-/// Use page table in vspace_root to set the satp register.
-pub fn set_vm_root(vspace_root: &cap_t) -> Result<(), lookup_fault_t> {
+/// # Returns
+///
+/// `Err` with the lookup fault of the ASID, if there is one, when the root is not usable, for
+/// example because its ASID was deleted or it was revoked and the ASID reassigned.
+pub(crate) fn find_vm_root(
+    vspace_root: &cap_t,
+) -> Result<(*mut PTE, asid_t), Option<lookup_fault_t>> {
     if vspace_root.get_cap_type() != CapTag::CapPageTableCap {
-        unsafe {
-            setVSpaceRoot(kpptr_to_paddr(kernel_root_pageTable.as_ptr() as usize), 0);
-            return Ok(());
-        }
+        return Err(None);
     }
-    let lvl1pt = convert_to_mut_type_ref::<PTE>(vspace_root.get_pt_base_ptr());
+    let lvl1pt = vspace_root.get_pt_base_ptr() as *mut PTE;
     let asid = vspace_root.get_pt_mapped_asid();
     let find_ret = find_vspace_for_asid(asid);
-    let mut ret = Ok(());
-    if unlikely(
-        find_ret.status != exception_t::EXCEPTION_NONE
-            || find_ret.vspace_root.is_none()
-            || find_ret.vspace_root.unwrap() != lvl1pt,
-    ) {
-        unsafe {
-            if let Some(lookup_fault) = find_ret.lookup_fault {
-                ret = Err(lookup_fault);
+    if unlikely(find_ret.status != exception_t::EXCEPTION_NONE) {
+        return Err(find_ret.lookup_fault);
+    }
+    match find_ret.vspace_root {
+        Some(root) if root == lvl1pt => Ok((lvl1pt, asid)),
+        _ => Err(None),
+    }
+}
+
+///根据给定的`vspace_root`设置相应的页表，会检查`vspace_root`是否合法，如果不合法默认设置为内核页表
+///
+/// Use page table in vspace_root to set the satp register. A root that is not usable leaves the
+/// core on the kernel page table, which has no user mappings, so the thread faults on its first
+/// access instead of running on a page table that may have been freed.
+pub fn set_vm_root(vspace_root: &cap_t) -> Result<(), lookup_fault_t> {
+    match find_vm_root(vspace_root) {
+        Ok((lvl1pt, asid)) => {
+            setVSpaceRoot(pptr_to_paddr(lvl1pt as usize), asid);
+            Ok(())
+        }
+        Err(lookup_fault) => {
+            unsafe {
+                setVSpaceRoot(kpptr_to_paddr(kernel_root_pageTable.as_ptr() as usize), 0);
             }
-            setVSpaceRoot(kpptr_to_paddr(kernel_root_pageTable.as_ptr() as usize), 0);
+            lookup_fault.map_or(Ok(()), Err)
         }
     }
-    setVSpaceRoot(pptr_to_paddr(lvl1pt as *mut PTE as usize), asid);
-    ret
 }
 pub fn unmap_page_table(asid: asid_t, vptr: vptr_t, pt: &mut PTE) {
     let target_pt = pt as *mut PTE;
+    vspace_demote_table(target_pt as usize);
     let find_ret = find_vspace_for_asid(asid);
     if find_ret.status != exception_t::EXCEPTION_NONE {
         return;
     }
     assert_ne!(find_ret.vspace_root.unwrap(), target_pt);
+    #[cfg(feature = "ENABLE_SHARED_PT")]
+    super::shared_pt_remove_below(asid, target_pt as usize);
     let mut pt = find_ret.vspace_root.unwrap();
     let mut ptSlot = unsafe { &mut *(pt.add(RISCV_GET_PT_INDEX(vptr, 0))) };
     let mut i = 0;
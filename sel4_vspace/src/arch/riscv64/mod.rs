@@ -2,17 +2,31 @@ mod asid;
 mod boot;
 mod interface;
 mod pagetable;
+mod promote;
 mod pte;
 mod satp;
+#[cfg(feature = "ENABLE_SHARED_PT")]
+mod shared_pt;
+#[cfg(feature = "ENABLE_RISCV_HYP")]
+mod stage2;
 mod structures;
 mod utils;
 pub use asid::*;
 pub use boot::*;
+#[cfg(test)]
+pub(crate) use interface::find_vm_root;
 pub use interface::{set_vm_root, unmap_page_table};
 pub use pagetable::{
     activate_kernel_vspace, copyGlobalMappings, rust_map_kernel_window, unmapPage,
 };
+pub use promote::{
+    vspace_demote, vspace_promote, vspace_promote_check, vspace_promoted_slot, vspace_promotion_t,
+};
 pub use pte::PTEFlags;
-pub use satp::{setVSpaceRoot, sfence};
+pub use satp::{fence_i, fence_rw, setVSpaceRoot, sfence};
+#[cfg(feature = "ENABLE_SHARED_PT")]
+pub use shared_pt::*;
+#[cfg(feature = "ENABLE_RISCV_HYP")]
+pub use stage2::*;
 pub use structures::*;
 pub use utils::*;
@@ -14,7 +14,9 @@ use sel4_common::{
 };
 
 use super::{
-    kpptr_to_paddr, setVSpaceRoot,
+    kpptr_to_paddr,
+    promote::vspace_demote,
+    setVSpaceRoot,
     utils::{RISCV_GET_LVL_PGSIZE_BITS, RISCV_GET_PT_INDEX},
     RISCV_GET_LVL_PGSIZE,
 };
@@ -162,6 +164,10 @@ pub fn copyGlobalMappings(Lvl1pt: usize) {
 /// `vptr`:该页表项对应的应用程序访问的虚拟地址（mapped_address）
 ///
 /// `pptr`:分配的页面对应的虚拟地址(frame_base_ptr)
+///
+/// The mapping recorded in a frame cap can be stale: if the vspace of `asid` was deleted this is
+/// a no-op. The pte is only cleared if it still maps this frame, so a recycled page table which
+/// now belongs to another vspace is left alone.
 #[no_mangle]
 pub fn unmapPage(
     page_size: usize,
@@ -199,10 +205,12 @@ pub fn unmapPage(
         }
         Ok(())
     */
+    // The vspace of `asid` was deleted, the mapping went with it.
     let find_ret = find_vspace_for_asid(asid);
     if find_ret.status != exception_t::EXCEPTION_NONE {
-        return Err(find_ret.lookup_fault.unwrap());
+        return Ok(());
     }
+    vspace_demote(asid, vptr);
     // TODO: Unify lookup_pt_slot
     let lu_ret = unsafe { (*find_ret.vspace_root.unwrap()).lookup_pt_slot(vptr) };
     if lu_ret.ptBitsLeft != pageBitsForSize(page_size) {
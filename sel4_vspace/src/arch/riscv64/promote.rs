@@ -0,0 +1,182 @@
+//! 将整张末级页表的映射合并为一个2M大页
+//!
+//! Consolidation of a whole last-level page table into a single 2M mapping.
+//!
+//! When the 512 entries of a last-level page table map physically contiguous memory with the same
+//! attributes, the entry linking the table in can be replaced by one megapage leaf. The table
+//! itself is left untouched, so the frame and page table caps stay valid: every promoted region is
+//! recorded, and is demoted back to the table before anything looks at or changes the 4K mappings
+//! below it.
+
+use sel4_common::sel4_config::{seL4_PageBits, CONFIG_PT_LEVELS, PT_INDEX_BITS};
+use sel4_common::structures::exception_t;
+use sel4_common::{BIT, MASK};
+
+use super::{pptr_to_paddr, sfence, PTEFlags, RISCV_GET_LVL_PGSIZE_BITS, RISCV_GET_PT_INDEX};
+use crate::{asid_t, find_vspace_for_asid, pptr_t, vptr_t, PTE};
+
+/// Number of promoted regions that can exist at the same time.
+const PROMOTED_MAX_REGIONS: usize = 64;
+
+/// Bits of a leaf PTE holding its flags.
+const PTE_FLAGS_BITS: usize = 10;
+
+#[derive(Clone, Copy)]
+struct promoted_region_t {
+    asid: asid_t,
+    vaddr: vptr_t,
+    table: pptr_t,
+}
+
+const EMPTY_REGION: promoted_region_t = promoted_region_t {
+    asid: 0,
+    vaddr: 0,
+    table: 0,
+};
+
+static mut ksPromotedRegions: [promoted_region_t; PROMOTED_MAX_REGIONS] =
+    [EMPTY_REGION; PROMOTED_MAX_REGIONS];
+
+#[inline]
+fn promote_bits() -> usize {
+    RISCV_GET_LVL_PGSIZE_BITS(CONFIG_PT_LEVELS - 2)
+}
+
+/// Find the slot of `root` which covers the 2M region at `vaddr` with a last-level table.
+fn lookup_region_slot(root: &mut PTE, vaddr: vptr_t) -> Option<&'static mut PTE> {
+    let mut pt = root as *mut PTE;
+    for level in 0..CONFIG_PT_LEVELS - 2 {
+        let slot = unsafe { &*pt.add(RISCV_GET_PT_INDEX(vaddr, level)) };
+        if !slot.is_pte_table() {
+            return None;
+        }
+        pt = slot.get_pte_from_ppn_mut() as *mut PTE;
+    }
+    Some(unsafe { &mut *pt.add(RISCV_GET_PT_INDEX(vaddr, CONFIG_PT_LEVELS - 2)) })
+}
+
+/// Replace the entry in `slot` by `pte`, invalidating it and flushing the TLB in between.
+fn break_before_make(slot: &mut PTE, pte: PTE) {
+    *slot = PTE::new(0, PTEFlags::empty());
+    sfence();
+    *slot = pte;
+    sfence();
+}
+
+/// A promotion which passed `vspace_promote_check`.
+pub struct vspace_promotion_t {
+    slot: &'static mut PTE,
+    vaddr: vptr_t,
+    table: pptr_t,
+}
+
+/// Check that the 2M region containing `vaddr` in the vspace `root` can be promoted to a megapage.
+///
+/// Fails with `EXCEPTION_LOOKUP_FAULT` if the region is not mapped through a last-level table,
+/// with `EXCEPTION_SYSCALL_ERROR` if its 4K mappings cannot be merged, and with
+/// `EXCEPTION_FAULT` if too many regions are promoted already.
+pub fn vspace_promote_check(
+    root: &mut PTE,
+    vaddr: vptr_t,
+) -> Result<vspace_promotion_t, exception_t> {
+    let vaddr = vaddr & !MASK!(promote_bits());
+    let slot = match lookup_region_slot(root, vaddr) {
+        Some(slot) if slot.is_pte_table() => slot,
+        _ => return Err(exception_t::EXCEPTION_LOOKUP_FAULT),
+    };
+    let table = slot.get_pte_from_ppn_mut() as *mut PTE;
+    let first = unsafe { &*table };
+    let flags = first.0 & MASK!(PTE_FLAGS_BITS);
+    let ppn = first.get_ppn();
+    if first.get_valid() == 0 || first.is_pte_table() || ppn & MASK!(PT_INDEX_BITS) != 0 {
+        return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+    }
+    for i in 1..BIT!(PT_INDEX_BITS) {
+        let pte = unsafe { &*table.add(i) };
+        if pte.0 & MASK!(PTE_FLAGS_BITS) != flags || pte.get_ppn() != ppn + i {
+            return Err(exception_t::EXCEPTION_SYSCALL_ERROR);
+        }
+    }
+    if unsafe { ksPromotedRegions.iter().all(|r| r.table != 0) } {
+        return Err(exception_t::EXCEPTION_FAULT);
+    }
+    Ok(vspace_promotion_t {
+        slot,
+        vaddr,
+        table: table as pptr_t,
+    })
+}
+
+/// Replace the table of a checked region of the vspace of `asid` by a megapage.
+pub fn vspace_promote(promotion: vspace_promotion_t, asid: asid_t) {
+    let region = unsafe { ksPromotedRegions.iter_mut().find(|r| r.table == 0) }
+        .expect("vspace_promote_check found a free region");
+    *region = promoted_region_t {
+        asid,
+        vaddr: promotion.vaddr,
+        table: promotion.table,
+    };
+    let first = unsafe { &*(promotion.table as *const PTE) };
+    break_before_make(promotion.slot, PTE(first.0));
+}
+
+/// Turn the promoted region containing `vaddr` in the vspace of `asid`, if any, back into its
+/// last-level table.
+pub fn vspace_demote(asid: asid_t, vaddr: vptr_t) {
+    let vaddr = vaddr & !MASK!(promote_bits());
+    let region = unsafe {
+        ksPromotedRegions
+            .iter_mut()
+            .find(|r| r.table != 0 && r.asid == asid && r.vaddr == vaddr)
+    };
+    let region = match region {
+        Some(region) => region,
+        None => return,
+    };
+    let table = region.table;
+    *region = EMPTY_REGION;
+
+    let find_ret = find_vspace_for_asid(asid);
+    if find_ret.status != exception_t::EXCEPTION_NONE {
+        return;
+    }
+    let root = unsafe { &mut *find_ret.vspace_root.unwrap() };
+    if let Some(slot) = lookup_region_slot(root, vaddr) {
+        let first = unsafe { &*(table as *const PTE) };
+        if slot.get_valid() != 0 && !slot.is_pte_table() && slot.get_ppn() == first.get_ppn() {
+            let paddr = pptr_to_paddr(table);
+            break_before_make(slot, PTE::new(paddr >> seL4_PageBits, PTEFlags::V));
+        }
+    }
+}
+
+/// The slot of `vaddr` in the last-level table below the promoted region of `asid` containing it,
+/// if any. A region is demoted before any of its 4K mappings changes, so decoders check that slot
+/// rather than the megapage.
+pub fn vspace_promoted_slot(asid: asid_t, vaddr: vptr_t) -> Option<*mut PTE> {
+    let base = vaddr & !MASK!(promote_bits());
+    let region = unsafe {
+        ksPromotedRegions
+            .iter()
+            .find(|r| r.table != 0 && r.asid == asid && r.vaddr == base)
+    }?;
+    let table = region.table as *mut PTE;
+    Some(unsafe { table.add(RISCV_GET_PT_INDEX(vaddr, CONFIG_PT_LEVELS - 1)) })
+}
+
+/// Demote every promoted region using the last-level table `table`.
+pub fn vspace_demote_table(table: pptr_t) {
+    let regions = unsafe { ksPromotedRegions };
+    for region in regions.iter().filter(|r| r.table == table) {
+        vspace_demote(region.asid, region.vaddr);
+    }
+}
+
+/// Forget the promoted regions of `asid`, whose vspace is being deleted.
+pub fn vspace_forget_asid(asid: asid_t) {
+    for region in unsafe { ksPromotedRegions.iter_mut() } {
+        if region.asid == asid {
+            *region = EMPTY_REGION;
+        }
+    }
+}
@@ -14,7 +14,7 @@ use crate::{
     asid_t, find_vspace_for_asid, lookupPTSlot_ret_t, vptr_t, PTE,
 };
 
-use super::paddr_to_pptr;
+use super::{paddr_to_pptr, promote::vspace_demote_table};
 
 bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -89,11 +89,14 @@ impl PTE {
 
     pub fn unmap_page_table(&mut self, asid: asid_t, vptr: vptr_t) {
         let target_pt = self as *mut PTE;
+        vspace_demote_table(target_pt as usize);
         let find_ret = find_vspace_for_asid(asid);
         if find_ret.status != exception_t::EXCEPTION_NONE {
             return;
         }
         assert_ne!(find_ret.vspace_root.unwrap(), target_pt);
+        #[cfg(feature = "ENABLE_SHARED_PT")]
+        super::shared_pt_remove_below(asid, target_pt as usize);
         let mut pt = find_ret.vspace_root.unwrap();
         let mut ptSlot = unsafe { &mut *(pt.add(RISCV_GET_PT_INDEX(vptr, 0))) };
         let mut i = 0;
@@ -160,6 +163,20 @@ impl PTE {
         (self.0 & 0x2usize) >> 1
     }
 
+    /// The kernel address of the user code at `vptr` in the vspace rooted at `self`, if `vptr` is
+    /// mapped user executable
+    pub fn lookup_user_text(&mut self, vptr: vptr_t) -> Option<usize> {
+        let ret = self.lookup_pt_slot(vptr);
+        let pte = unsafe { &*ret.ptSlot };
+        let flags = PTEFlags::from_bits_truncate(pte.0);
+        if pte.is_pte_table() || !flags.contains(PTEFlags::V | PTEFlags::U | PTEFlags::X) {
+            return None;
+        }
+        Some(paddr_to_pptr(
+            (pte.get_ppn() << seL4_PageBits) + (vptr & MASK!(ret.ptBitsLeft)),
+        ))
+    }
+
     ///用于记录某个虚拟地址`vptr`对应的pte表项在内存中的位置
     pub fn lookup_pt_slot(&mut self, vptr: vptr_t) -> lookupPTSlot_ret_t {
         let mut level = CONFIG_PT_LEVELS - 1;
@@ -1,4 +1,4 @@
-use riscv::register::satp;
+use sel4_common::arch::sysreg::satp;
 
 ///`satp`寄存器对应的内存备份
 #[repr(C)]
@@ -10,10 +10,7 @@ pub struct satp_t {
 impl satp_t {
     pub fn new(mode: usize, asid: usize, ppn: usize) -> Self {
         satp_t {
-            words: 0
-                | (mode & 0xfusize) << 60
-                | (asid & 0xffffusize) << 44
-                | (ppn & 0xfffffffffffusize) << 0,
+            words: satp::MODE.val(mode) | satp::ASID.val(asid) | satp::PPN.val(ppn),
         }
     }
 }
@@ -29,6 +26,8 @@ pub fn sfence() {
     sfence_local();
     let mask = get_sbi_mask_for_all_remote_harts();
     remote_sfence_vma(mask, 0, 0);
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    sel4_common::arch::remote_hfence_gvma(mask);
 }
 
 #[cfg(feature = "ENABLE_SMP")]
@@ -37,11 +36,14 @@ pub fn sfence_local() {
     unsafe {
         core::arch::asm!("sfence.vma");
     }
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    super::stage2::hfence_gvma();
 }
 
 ///对汇编指令`sfence.vma`的简单封装，清空`cache`、`tlb`
 ///
-/// Risc-v's sfence.vma
+/// Risc-v's sfence.vma. With `ENABLE_RISCV_HYP` the G-stage TLB is flushed as well, a table
+/// that was unmapped may belong to a guest.
 #[cfg(not(feature = "ENABLE_SMP"))]
 #[inline]
 pub fn sfence() {
@@ -49,6 +51,30 @@ pub fn sfence() {
     unsafe {
         core::arch::asm!("sfence.vma");
     }
+    #[cfg(feature = "ENABLE_RISCV_HYP")]
+    super::stage2::hfence_gvma();
+}
+
+///对汇编指令`fence rw, rw`的简单封装
+///
+/// RISC-V keeps data caches coherent, so data cache maintenance reduces to ordering memory accesses.
+#[inline]
+pub fn fence_rw() {
+    #[cfg(target_arch = "riscv64")]
+    unsafe {
+        core::arch::asm!("fence rw, rw");
+    }
+}
+
+///对汇编指令`fence.i`的简单封装，使取指与之前的写操作同步
+///
+/// Synchronise the instruction stream of this hart with prior stores.
+#[inline]
+pub fn fence_i() {
+    #[cfg(target_arch = "riscv64")]
+    unsafe {
+        core::arch::asm!("fence.i");
+    }
 }
 
 ///设置页表，创建一个新的satp的值，然后将其写入satp寄存器
@@ -58,7 +84,9 @@ pub fn sfence() {
 #[no_mangle]
 pub fn setVSpaceRoot(addr: usize, asid: usize) {
     let satp = satp_t::new(8usize, asid, addr >> 12);
-    satp::write(satp.words);
+    unsafe {
+        satp::write(satp.words);
+    }
     #[cfg(not(feature = "ENABLE_SMP"))]
     sfence();
     #[cfg(feature = "ENABLE_SMP")]
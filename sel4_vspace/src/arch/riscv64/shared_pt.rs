@@ -0,0 +1,187 @@
+//! 页表共享，由`ENABLE_SHARED_PT`开启
+//!
+//! Sharing of page tables between address spaces, enabled by `ENABLE_SHARED_PT`.
+//!
+//! A page table keeps the single mapping recorded in its cap, and can additionally be linked into
+//! other vspaces at the same level. Those extra mappings are not backed by caps, so they are
+//! counted here per table object. The table can only be unmapped through its cap once the count
+//! has dropped back to zero, and deleting the last cap to the table removes whatever is left. A
+//! mapping also goes away with the vspace it is in, when its ASID or ASID pool is deleted or the
+//! page table it is linked into is unmapped.
+//!
+//! This departs from seL4, where a page table is mapped exactly once.
+
+use sel4_common::sel4_config::CONFIG_PT_LEVELS;
+use sel4_common::structures::exception_t;
+use sel4_common::utils::convert_to_type_ref;
+
+use super::{sfence, PTEFlags, RISCV_GET_LVL_PGSIZE_BITS, RISCV_GET_PT_INDEX};
+use crate::{asid_t, find_vspace_for_asid, pptr_t, vptr_t, PTE};
+
+/// Number of shared mappings that can exist at the same time.
+const SHARED_PT_MAX_MAPPINGS: usize = 256;
+
+#[derive(Clone, Copy)]
+struct shared_pt_mapping_t {
+    table: pptr_t,
+    asid: asid_t,
+    vaddr: vptr_t,
+}
+
+const EMPTY_MAPPING: shared_pt_mapping_t = shared_pt_mapping_t {
+    table: 0,
+    asid: 0,
+    vaddr: 0,
+};
+
+static mut ksSharedPTMappings: [shared_pt_mapping_t; SHARED_PT_MAX_MAPPINGS] =
+    [EMPTY_MAPPING; SHARED_PT_MAX_MAPPINGS];
+
+/// Find the slot of `root` which links `table` in at `vaddr`, with the number of address bits the
+/// slot covers.
+fn find_table_slot(root: &mut PTE, vaddr: vptr_t, table: pptr_t) -> Option<(&mut PTE, usize)> {
+    let mut pt = root as *mut PTE;
+    for level in 0..CONFIG_PT_LEVELS - 1 {
+        let slot = unsafe { &mut *pt.add(RISCV_GET_PT_INDEX(vaddr, level)) };
+        if !slot.is_pte_table() {
+            return None;
+        }
+        let next = slot.get_pte_from_ppn_mut() as *mut PTE;
+        if next as usize == table {
+            return Some((slot, RISCV_GET_LVL_PGSIZE_BITS(level)));
+        }
+        pt = next;
+    }
+    None
+}
+
+/// Number of address bits covered by the slot linking `table` into `root` at `vaddr`, `None` if
+/// `table` is not mapped there.
+pub fn shared_pt_level_bits(root: &mut PTE, vaddr: vptr_t, table: pptr_t) -> Option<usize> {
+    find_table_slot(root, vaddr, table).map(|(_, bits)| bits)
+}
+
+/// Number of shared mappings of `table`, not counting the one recorded in its cap.
+pub fn shared_pt_count(table: pptr_t) -> usize {
+    unsafe {
+        ksSharedPTMappings
+            .iter()
+            .filter(|m| m.table == table)
+            .count()
+    }
+}
+
+/// Whether another shared mapping can be recorded.
+pub fn shared_pt_has_room() -> bool {
+    unsafe { ksSharedPTMappings.iter().any(|m| m.table == 0) }
+}
+
+/// Whether `table` is shared into the vspace of `asid` at `vaddr`.
+pub fn shared_pt_contains(table: pptr_t, asid: asid_t, vaddr: vptr_t) -> bool {
+    unsafe {
+        ksSharedPTMappings
+            .iter()
+            .any(|m| m.table == table && m.asid == asid && m.vaddr == vaddr)
+    }
+}
+
+/// Record a shared mapping of `table`, returns false if there is no room left.
+pub fn shared_pt_add(table: pptr_t, asid: asid_t, vaddr: vptr_t) -> bool {
+    match unsafe { ksSharedPTMappings.iter_mut().find(|m| m.table == 0) } {
+        Some(m) => {
+            *m = shared_pt_mapping_t { table, asid, vaddr };
+            true
+        }
+        None => false,
+    }
+}
+
+/// Unlink `table` from the vspace of the shared mapping `m` if it is still there.
+fn shared_pt_unlink(m: &shared_pt_mapping_t) {
+    let find_ret = find_vspace_for_asid(m.asid);
+    if find_ret.status != exception_t::EXCEPTION_NONE {
+        return;
+    }
+    let root = unsafe { &mut *find_ret.vspace_root.unwrap() };
+    if let Some((slot, _)) = find_table_slot(root, m.vaddr, m.table) {
+        *slot = PTE::new(0, PTEFlags::empty());
+    }
+}
+
+/// Remove the shared mapping of `table` in the vspace of `asid` at `vaddr`, returns false if there
+/// is no such mapping.
+pub fn shared_pt_remove(table: pptr_t, asid: asid_t, vaddr: vptr_t) -> bool {
+    let found = unsafe {
+        ksSharedPTMappings
+            .iter_mut()
+            .find(|m| m.table == table && m.asid == asid && m.vaddr == vaddr)
+    };
+    match found {
+        Some(m) => {
+            shared_pt_unlink(m);
+            *m = EMPTY_MAPPING;
+            sfence();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Whether the walk from `root` at `vaddr` reaches `table` through the page table `parent`.
+fn linked_below(root: *mut PTE, vaddr: vptr_t, table: pptr_t, parent: pptr_t) -> bool {
+    let mut pt = root as usize;
+    let mut through = false;
+    for level in 0..CONFIG_PT_LEVELS - 1 {
+        let slot = convert_to_type_ref::<PTE>(
+            pt + RISCV_GET_PT_INDEX(vaddr, level) * core::mem::size_of::<PTE>(),
+        );
+        if !slot.is_pte_table() {
+            return false;
+        }
+        let next = slot.get_pte_from_ppn_mut() as *mut PTE as usize;
+        if next == table {
+            return through;
+        }
+        through |= next == parent;
+        pt = next;
+    }
+    false
+}
+
+/// Unlink and remove every shared mapping `pred` holds for.
+fn shared_pt_remove_where(pred: impl Fn(&shared_pt_mapping_t) -> bool) {
+    let mut removed = false;
+    for m in unsafe { ksSharedPTMappings.iter_mut() } {
+        if m.table != 0 && pred(m) {
+            shared_pt_unlink(m);
+            *m = EMPTY_MAPPING;
+            removed = true;
+        }
+    }
+    if removed {
+        sfence();
+    }
+}
+
+/// Remove the shared mappings in the vspace of `asid` linked in below `parent`, called before
+/// `parent` is unmapped from that vspace. They could not be found from the root any more after
+/// that, and would keep their tables from ever being unmapped through the cap.
+pub fn shared_pt_remove_below(asid: asid_t, parent: pptr_t) {
+    let find_ret = find_vspace_for_asid(asid);
+    if find_ret.status != exception_t::EXCEPTION_NONE {
+        return;
+    }
+    let root = find_ret.vspace_root.unwrap();
+    shared_pt_remove_where(|m| m.asid == asid && linked_below(root, m.vaddr, m.table, parent));
+}
+
+/// Remove the shared mappings in the vspaces of the `count` ASIDs from `asid_base`, called before
+/// those ASIDs are deleted with their vspace or their ASID pool.
+pub fn shared_pt_remove_asids(asid_base: asid_t, count: usize) {
+    shared_pt_remove_where(|m| (asid_base..asid_base + count).contains(&m.asid));
+}
+
+/// Remove every shared mapping of `table`, called when its last cap is deleted.
+pub fn shared_pt_remove_all(table: pptr_t) {
+    shared_pt_remove_where(|m| m.table == table);
+}
@@ -0,0 +1,78 @@
+//! G-stage translation of guest physical memory, enabled by the `ENABLE_RISCV_HYP` feature.
+//!
+//! The G-stage root of a guest lives at the start of its VCPU object. It is assigned an ASID with
+//! `RISCVASIDPoolAssign` like a vspace root, so page tables and frames are mapped into it with the
+//! usual invocations, the VCPU cap taking the place of the vspace root cap. Sv39x4 widens the root
+//! to 2048 entries over 16KB, but only the first 512 are ever mapped: guest physical addresses stay
+//! below `USER_TOP` like virtual ones, and the walks and unmaps of a vspace work on it unchanged.
+//! The rest of the root stays empty, a guest access there is a guest page fault.
+//!
+//! G-stage leaves need `U` set, which `PTE::make_user_pte` does already. VMIDs are not allocated,
+//! all guests run with VMID 0 and loading another root flushes the G-stage TLB.
+
+use core::arch::asm;
+
+use sel4_common::arch::sysreg::hgatp;
+
+use super::{kpptr_to_paddr, pptr_to_paddr};
+use crate::{asid_t, find_vspace_for_asid, PTE};
+
+/// The size of a G-stage root in bits
+pub const GSTAGE_ROOT_BITS: usize = 14;
+
+/// `hgatp.MODE` for Sv39x4
+const HGATP_MODE_SV39X4: usize = 8;
+
+/// The root of a G-stage table, four pages of level 2 entries.
+#[repr(C, align(16384))]
+pub struct gstage_root_t {
+    pub entries: [PTE; 1 << (GSTAGE_ROOT_BITS - 3)],
+}
+
+impl gstage_root_t {
+    #[inline]
+    pub fn get_ptr(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// The part of the root the vspace walks and maps, which covers the guest physical addresses
+    /// below `USER_TOP`.
+    #[inline]
+    pub fn as_vspace_root(&self) -> *mut PTE {
+        self.entries.as_ptr() as *mut PTE
+    }
+}
+
+/// The root loaded for a VCPU that has no usable G-stage table, it maps nothing.
+static mut riscvKSEmptyGuestRoot: gstage_root_t = gstage_root_t {
+    entries: [PTE(0); 1 << (GSTAGE_ROOT_BITS - 3)],
+};
+
+/// Whether `root` is still the G-stage root assigned to `asid`.
+pub fn is_guest_root_of_asid(root: &gstage_root_t, asid: asid_t) -> bool {
+    asid != 0 && find_vspace_for_asid(asid).vspace_root == Some(root.as_vspace_root())
+}
+
+/// Load `root` into `hgatp`, or the empty root for none.
+pub fn set_guest_vm_root(root: Option<&gstage_root_t>) {
+    let paddr = match root {
+        Some(root) => pptr_to_paddr(root.get_ptr()),
+        None => unsafe { kpptr_to_paddr(riscvKSEmptyGuestRoot.get_ptr()) },
+    };
+    let value = hgatp::MODE.val(HGATP_MODE_SV39X4) | hgatp::PPN.val(paddr >> 12);
+    if hgatp::read() != value {
+        unsafe {
+            hgatp::write(value);
+        }
+        hfence_gvma();
+    }
+}
+
+/// Drop the G-stage TLB entries of all VMIDs on this hart.
+#[inline]
+pub fn hfence_gvma() {
+    unsafe {
+        // hfence.gvma zero, zero, the assembler only knows it with the H extension
+        asm!(".insn r 0x73, 0, 0x31, x0, x0, x0");
+    }
+}
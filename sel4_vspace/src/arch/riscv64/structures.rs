@@ -1,6 +1,16 @@
 use sel4_common::{sel4_config::asidLowBits, utils::convert_to_option_mut_type_ref, BIT};
 
-use crate::{pptr_t, PTE};
+use crate::{pptr_t, vm_attributes_t, PTE};
+
+impl vm_attributes_t {
+    /// 设备页的映射属性：始终不可执行
+    ///
+    /// Attributes used to map a device frame. RISC-V leaves the memory type to the platform's
+    /// PMAs, so only execution is refused.
+    pub fn for_device_frame(&self) -> Self {
+        Self(self.0 | 0x1)
+    }
+}
 
 ///lookup_pt_slot函数的返回值，
 /// `ptSlot`：找到的虚地址对应的`pte`的存放槽
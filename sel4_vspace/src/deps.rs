@@ -0,0 +1,6 @@
+extern "C" {
+    #[cfg(all(feature = "ENABLE_SMP", target_arch = "aarch64"))]
+    pub fn ipi_remote_invalidate_tlb_by_asid(asid: usize, mask: usize);
+    #[cfg(all(feature = "ENABLE_SMP", target_arch = "aarch64"))]
+    pub fn ipi_remote_invalidate_tlb(mask: usize);
+}
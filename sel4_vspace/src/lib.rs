@@ -3,11 +3,16 @@
 #![allow(internal_features)]
 #![allow(non_camel_case_types)]
 #![allow(non_upper_case_globals)]
+#![deny(unsafe_op_in_unsafe_fn)]
 #![feature(decl_macro)]
 #![feature(core_intrinsics)]
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::tests::test_runner)]
+#![reexport_test_harness_main = "test_main"]
 mod arch;
 mod asid;
 mod boot;
+mod deps;
 mod pagetable;
 // mod pte;
 mod structures;
@@ -25,3 +30,158 @@ pub use pagetable::PageTable;
 pub use structures::*;
 pub use utils::checkVPAlignment;
 // pub use riscv::*;
+
+#[cfg(test)]
+mod tests {
+    use core::arch::global_asm;
+    use riscv::register::{stvec, utvec::TrapMode};
+    use sel4_common::{
+        arch::shutdown,
+        fault::LookupFaultType,
+        println,
+        sel4_config::{asidLowBits, PT_INDEX_BITS},
+        BIT, MASK,
+    };
+    use sel4_cspace::interface::cap_t;
+    global_asm!(include_str!("entry.asm"));
+
+    use super::*;
+
+    /// The ASID of the thread the tests run, out of the pool of the initial thread
+    const TEST_ASID: usize = BIT!(asidLowBits) + 1;
+
+    static mut test_pool: asid_pool_t = asid_pool_t {
+        array: [0 as *mut PTE; BIT!(asidLowBits)],
+    };
+    static mut test_root: [PTE; BIT!(PT_INDEX_BITS)] = [PTE::pte_invalid(); BIT!(PT_INDEX_BITS)];
+    static mut other_root: [PTE; BIT!(PT_INDEX_BITS)] = [PTE::pte_invalid(); BIT!(PT_INDEX_BITS)];
+
+    /// Assign `TEST_ASID` to `root`, like `ASIDPoolAssign` does.
+    fn assign_test_asid(root: *mut PTE) {
+        unsafe {
+            set_asid_pool_by_index(TEST_ASID >> asidLowBits, test_pool.get_ptr());
+            test_pool.array[TEST_ASID & MASK!(asidLowBits)] = root;
+        }
+    }
+
+    /// The vspace cap of the running thread, mapped with `TEST_ASID`.
+    fn thread_root_cap() -> cap_t {
+        cap_t::new_page_table_cap(TEST_ASID, unsafe { test_root.as_ptr() } as usize, 1, 0)
+    }
+
+    pub fn test_runner(tests: &[&dyn Fn()]) {
+        println!("Running {} tests", tests.len());
+        for test in tests {
+            test();
+        }
+    }
+
+    #[test_case]
+    pub fn find_vm_root_test() {
+        println!("-----------------------------------");
+        println!("Entering find_vm_root_test case");
+        let root = unsafe { test_root.as_mut_ptr() };
+        assign_test_asid(root);
+        assert_eq!(find_vm_root(&thread_root_cap()), Ok((root, TEST_ASID)));
+        println!("Test find_vm_root_test passed");
+        println!("-----------------------------------");
+    }
+
+    #[test_case]
+    pub fn find_vm_root_deleted_asid_test() {
+        println!("-----------------------------------");
+        println!("Entering find_vm_root_deleted_asid_test case");
+        assign_test_asid(unsafe { test_root.as_mut_ptr() });
+        // The vspace of the thread is revoked, which deletes its ASID
+        assign_test_asid(0 as *mut PTE);
+        let fault = find_vm_root(&thread_root_cap()).unwrap_err().unwrap();
+        assert_eq!(fault.get_lookup_fault_type(), LookupFaultType::InvaildRoot);
+        println!("Test find_vm_root_deleted_asid_test passed");
+        println!("-----------------------------------");
+    }
+
+    #[test_case]
+    pub fn find_vm_root_deleted_pool_test() {
+        println!("-----------------------------------");
+        println!("Entering find_vm_root_deleted_pool_test case");
+        assign_test_asid(unsafe { test_root.as_mut_ptr() });
+        set_asid_pool_by_index(TEST_ASID >> asidLowBits, 0);
+        let fault = find_vm_root(&thread_root_cap()).unwrap_err().unwrap();
+        assert_eq!(fault.get_lookup_fault_type(), LookupFaultType::InvaildRoot);
+        println!("Test find_vm_root_deleted_pool_test passed");
+        println!("-----------------------------------");
+    }
+
+    #[test_case]
+    pub fn find_vm_root_reassigned_test() {
+        println!("-----------------------------------");
+        println!("Entering find_vm_root_reassigned_test case");
+        // The root of the thread was revoked and its ASID handed to another vspace, the stale cap
+        // must not switch to either of them
+        assign_test_asid(unsafe { other_root.as_mut_ptr() });
+        assert_eq!(find_vm_root(&thread_root_cap()), Err(None));
+        println!("Test find_vm_root_reassigned_test passed");
+        println!("-----------------------------------");
+    }
+
+    #[test_case]
+    pub fn find_vm_root_null_cap_test() {
+        println!("-----------------------------------");
+        println!("Entering find_vm_root_null_cap_test case");
+        assign_test_asid(unsafe { test_root.as_mut_ptr() });
+        assert_eq!(find_vm_root(&cap_t::new_null_cap()), Err(None));
+        println!("Test find_vm_root_null_cap_test passed");
+        println!("-----------------------------------");
+    }
+
+    #[cfg(feature = "ENABLE_SHARED_PT")]
+    #[test_case]
+    pub fn shared_pt_unmap_after_sharer_gone_test() {
+        println!("-----------------------------------");
+        println!("Entering shared_pt_unmap_after_sharer_gone_test case");
+        // A page table shared into the vspace of the thread
+        let shared = [PTE::pte_invalid(); BIT!(PT_INDEX_BITS)];
+        let table = shared.as_ptr() as usize;
+        let vaddr = BIT!(30);
+        assert!(shared_pt_add(table, TEST_ASID, vaddr));
+        assert_eq!(shared_pt_count(table), 1);
+        // The vspace that shared the table is deleted with its ASID, like `delete_asid` does
+        shared_pt_remove_asids(TEST_ASID, 1);
+        assign_test_asid(0 as *mut PTE);
+        // Nothing is left to keep the table from being unmapped through its cap
+        assert_eq!(shared_pt_count(table), 0);
+        assert!(!shared_pt_contains(table, TEST_ASID, vaddr));
+        assert!(!shared_pt_remove(table, TEST_ASID, vaddr));
+        println!("Test shared_pt_unmap_after_sharer_gone_test passed");
+        println!("-----------------------------------");
+    }
+
+    #[test_case]
+    pub fn shutdown_test() {
+        println!("All Test Cases passed, shutdown");
+        shutdown();
+    }
+
+    #[panic_handler]
+    fn panic(info: &core::panic::PanicInfo) -> ! {
+        println!("{}", info);
+        shutdown()
+    }
+
+    #[no_mangle]
+    pub fn call_test_main() {
+        extern "C" {
+            fn trap_entry();
+        }
+        unsafe {
+            stvec::write(trap_entry as usize, TrapMode::Direct);
+        }
+        crate::test_main();
+    }
+    #[no_mangle]
+    pub fn c_handle_syscall() {
+        unsafe {
+            core::arch::asm!("sret");
+        }
+    }
+}
@@ -0,0 +1,339 @@
+//! Where the `unsafe` code of the kernel lives, run with `cargo xtask audit`.
+//!
+//! Raw pointers and registers are reached through a few audited modules: kernel objects through
+//! `sel4_common::object_ref`, device registers through `sel4_common::mmio` and system registers
+//! through `sel4_common::sysreg`. Every other `unsafe` is counted per file against
+//! [`BASELINE`]. A file may lose `unsafe` but not gain it, so new unsafe code either goes into an
+//! audited module or shows up in review as a change of the baseline, which `--update` rewrites.
+//!
+//! `--miri` runs the unit tests of `sel4_common` under Miri, for the aarch64 build of the crate
+//! like the kernel, and `--kani` checks its proof harnesses with Kani. Neither tool comes with
+//! the toolchain of the kernel, they are installed separately.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{exit, Command};
+
+use crate::{fail, repo_root, succeeded};
+
+/// The crates of the kernel
+const CRATES: [&str; 6] = [
+    "kernel",
+    "sel4_common",
+    "sel4_cspace",
+    "sel4_ipc",
+    "sel4_task",
+    "sel4_vspace",
+];
+
+/// The modules which may hold `unsafe` without counting it
+const AUDITED: [&str; 3] = [
+    "sel4_common/src/mmio.rs",
+    "sel4_common/src/object_ref.rs",
+    "sel4_common/src/sysreg.rs",
+];
+
+/// The `unsafe` count of each file outside [`AUDITED`], relative to the repository root
+const BASELINE: &str = "unsafe-audit.txt";
+
+const USAGE: &str = "\
+Usage: cargo xtask audit [options]
+
+Options:
+    --update    Write the current counts to the baseline
+    --miri      Also run the unit tests of sel4_common under Miri
+    --kani      Also check the proof harnesses of sel4_common with Kani";
+
+pub fn audit(args: impl Iterator<Item = String>) {
+    let (mut update, mut miri, mut kani) = (false, false, false);
+    for arg in args {
+        match arg.as_str() {
+            "--update" => update = true,
+            "--miri" => miri = true,
+            "--kani" => kani = true,
+            _ => fail(&format!("unknown option `{}`\n\n{}", arg, USAGE)),
+        }
+    }
+
+    let counts = count_tree();
+    let baseline_path = repo_root().join(BASELINE);
+    if update {
+        fs::write(&baseline_path, render_baseline(&counts))
+            .unwrap_or_else(|err| fail(&err.to_string()));
+        println!("{} files with unsafe, baseline written", counts.len());
+    } else {
+        let baseline = fs::read_to_string(&baseline_path).unwrap_or_default();
+        let baseline = parse_baseline(&baseline);
+        if !compare(&counts, &baseline) {
+            exit(1);
+        }
+    }
+
+    let mut passed = true;
+    if miri {
+        println!("==> sel4_common under Miri");
+        passed &= run_cargo(&[
+            "miri",
+            "test",
+            "-p",
+            "sel4_common",
+            "--target",
+            "aarch64-unknown-linux-gnu",
+        ]);
+    }
+    if kani {
+        println!("==> sel4_common with Kani");
+        passed &= run_cargo(&["kani", "--tests", "-p", "sel4_common"]);
+    }
+    if !passed {
+        exit(1);
+    }
+}
+
+fn run_cargo(args: &[&str]) -> bool {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let status = Command::new(cargo)
+        .current_dir(repo_root())
+        .args(args)
+        .status();
+    if status.is_err() {
+        eprintln!(
+            "cannot run `cargo {}`, is it installed?",
+            args[..2].join(" ")
+        );
+    }
+    succeeded(status)
+}
+
+/// The `unsafe` count of every source file of [`CRATES`] outside [`AUDITED`] which has any
+fn count_tree() -> BTreeMap<String, usize> {
+    let root = repo_root();
+    let mut files = Vec::new();
+    for name in CRATES {
+        collect_sources(&root.join(name).join("src"), &mut files);
+    }
+    let mut counts = BTreeMap::new();
+    for file in files {
+        let path = file
+            .strip_prefix(&root)
+            .unwrap()
+            .to_string_lossy()
+            .replace('\\', "/");
+        if AUDITED.contains(&path.as_str()) {
+            continue;
+        }
+        let source = fs::read_to_string(&file).unwrap_or_else(|err| fail(&err.to_string()));
+        let count = count_unsafe(&source);
+        if count != 0 {
+            counts.insert(path, count);
+        }
+    }
+    counts
+}
+
+fn collect_sources(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.map_while(Result::ok) {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_sources(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path);
+        }
+    }
+}
+
+/// Report the files whose count differs from the baseline, false if any has more `unsafe`.
+fn compare(counts: &BTreeMap<String, usize>, baseline: &BTreeMap<String, usize>) -> bool {
+    let mut passed = true;
+    let mut shrunk = false;
+    for (path, &count) in counts.iter() {
+        let allowed = baseline.get(path).copied().unwrap_or(0);
+        if count > allowed {
+            eprintln!(
+                "{}: {} unsafe, the baseline allows {}",
+                path, count, allowed
+            );
+            passed = false;
+        } else if count < allowed {
+            println!("{}: {} unsafe, down from {}", path, count, allowed);
+            shrunk = true;
+        }
+    }
+    for (path, allowed) in baseline.iter() {
+        if !counts.contains_key(path) {
+            println!("{}: no unsafe, down from {}", path, allowed);
+            shrunk = true;
+        }
+    }
+    if !passed {
+        eprintln!(
+            "move the new unsafe code into one of {}, or update {} with --update",
+            AUDITED.join(", "),
+            BASELINE
+        );
+    } else if shrunk {
+        println!("lower the baseline with --update");
+    } else {
+        println!("{} files with unsafe, as in the baseline", counts.len());
+    }
+    passed
+}
+
+fn render_baseline(counts: &BTreeMap<String, usize>) -> String {
+    let mut text = String::from(
+        "# The `unsafe` of each kernel source file outside the audited modules, see\n\
+         # `cargo xtask audit`. Counts may only go down.\n",
+    );
+    for (path, count) in counts.iter() {
+        text.push_str(&format!("{} {}\n", count, path));
+    }
+    text
+}
+
+fn parse_baseline(text: &str) -> BTreeMap<String, usize> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (count, path) = line.split_once(' ')?;
+            Some((path.trim().to_string(), count.parse().ok()?))
+        })
+        .collect()
+}
+
+/// The number of `unsafe` keywords in `source`, leaving out comments and literals.
+fn count_unsafe(source: &str) -> usize {
+    let chars: Vec<char> = source.chars().collect();
+    let mut count = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c == '/' && next == Some('/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && next == Some('*') {
+            i = skip_block_comment(&chars, i);
+        } else if c == '"' {
+            i = skip_string(&chars, i + 1, 0);
+        } else if c == 'r' && matches!(next, Some('"') | Some('#')) {
+            let hashes = chars[i + 1..].iter().take_while(|&&c| c == '#').count();
+            match chars.get(i + 1 + hashes) {
+                Some('"') => i = skip_string(&chars, i + 2 + hashes, hashes),
+                _ => i += 1,
+            }
+        } else if c == '\'' {
+            i = skip_char(&chars, i);
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            if chars[start..i].iter().collect::<String>() == "unsafe" {
+                count += 1;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    count
+}
+
+/// The index after the block comment at `start`, they nest
+fn skip_block_comment(chars: &[char], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < chars.len() {
+        match (chars[i], chars.get(i + 1).copied()) {
+            ('/', Some('*')) => {
+                depth += 1;
+                i += 2;
+            }
+            ('*', Some('/')) => {
+                depth -= 1;
+                i += 2;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    i
+}
+
+/// The index after the string whose contents start at `start`, raw with `hashes` if not 0
+fn skip_string(chars: &[char], start: usize, hashes: usize) -> usize {
+    let raw = hashes > 0 || (start >= 2 && chars[start - 2] == 'r');
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if !raw => i += 2,
+            '"' if chars[i + 1..].iter().take(hashes).all(|&c| c == '#')
+                && i + hashes < chars.len() =>
+            {
+                return i + 1 + hashes;
+            }
+            _ => i += 1,
+        }
+    }
+    i
+}
+
+/// The index after the char literal at `start`, or after the quote of a lifetime
+fn skip_char(chars: &[char], start: usize) -> usize {
+    match (chars.get(start + 1), chars.get(start + 2)) {
+        (Some('\\'), _) => {
+            let mut i = start + 2;
+            while i < chars.len() && chars[i] != '\'' {
+                i += 1;
+            }
+            i + 1
+        }
+        (Some(_), Some('\'')) => start + 3,
+        _ => start + 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_unsafe_test() {
+        let source = r####"
+            // unsafe in a comment
+            /* unsafe /* nested unsafe */ still a comment, unsafe */
+            fn f<'a>(x: &'a u8) -> char {
+                let s = "unsafe \" unsafe";
+                let r = r#"unsafe " unsafe"#;
+                let unsafe_op = unsafe { *x };
+                let q = '"';
+                unsafe { g() };
+                'u'
+            }
+            unsafe impl Send for t {}
+        "####;
+        assert_eq!(count_unsafe(source), 3);
+    }
+
+    #[test]
+    fn baseline_test() {
+        let counts = BTreeMap::from([
+            ("kernel/src/a.rs".to_string(), 2),
+            ("kernel/src/b.rs".to_string(), 10),
+        ]);
+        assert_eq!(parse_baseline(&render_baseline(&counts)), counts);
+        let mut grown = counts.clone();
+        *grown.get_mut("kernel/src/a.rs").unwrap() += 1;
+        assert!(!compare(&grown, &counts));
+        grown.remove("kernel/src/a.rs");
+        assert!(compare(&grown, &counts));
+    }
+}
@@ -0,0 +1,91 @@
+//! Verdict on the serial output of a simulation.
+//!
+//! The root task is sel4test, which prints `All is well in the universe` once every test has
+//! passed. A run fails if the kernel panicked, if sel4test reports a failed test or if the
+//! output stops before the final line.
+
+/// Printed by sel4test after the last test passed
+pub const PASS_MARKER: &str = "All is well in the universe";
+/// Printed by the panic handler of the kernel
+pub const PANIC_MARKER: &str = "rel4_kernel: PANICED";
+/// Printed by sel4test for a test that failed
+const TEST_FAIL_MARKERS: [&str; 2] = ["Test suite failed", "Test failed"];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum verdict_t {
+    Passed,
+    Panicked,
+    TestFailed,
+    /// The output stopped, or the time ran out, before sel4test finished
+    Incomplete,
+}
+
+impl verdict_t {
+    pub fn passed(&self) -> bool {
+        *self == verdict_t::Passed
+    }
+}
+
+/// Whether the simulation can be stopped after `line`, the verdict is known.
+pub fn is_final_line(line: &str) -> bool {
+    line.contains(PASS_MARKER) || line.contains(PANIC_MARKER) || line.contains(TEST_FAIL_MARKERS[0])
+}
+
+/// The verdict on the whole serial output of a run.
+pub fn parse_log(log: &str) -> verdict_t {
+    if log.contains(PANIC_MARKER) {
+        verdict_t::Panicked
+    } else if log
+        .lines()
+        .any(|line| TEST_FAIL_MARKERS.iter().any(|marker| line.contains(marker)))
+    {
+        verdict_t::TestFailed
+    } else if log.contains(PASS_MARKER) {
+        verdict_t::Passed
+    } else {
+        verdict_t::Incomplete
+    }
+}
+
+/// The `Test suite passed. N tests passed. ...` summary of sel4test, if the run got that far.
+pub fn summary_line(log: &str) -> Option<&str> {
+    log.lines()
+        .find(|line| line.contains("Test suite passed") || line.contains("Test suite failed"))
+        .map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_log_test() {
+        let passed = "Test suite passed. 120 tests passed. 57 tests disabled.\n\
+                      All is well in the universe\n";
+        assert_eq!(parse_log(passed), verdict_t::Passed);
+        assert_eq!(
+            summary_line(passed),
+            Some("Test suite passed. 120 tests passed. 57 tests disabled.")
+        );
+        assert_eq!(
+            parse_log("Starting test 3: IPC0001\nrel4_kernel: PANICED at ipc\n"),
+            verdict_t::Panicked
+        );
+        assert_eq!(
+            parse_log("Test failed\nTest suite failed. 1/120 tests failed.\n"),
+            verdict_t::TestFailed
+        );
+        assert_eq!(
+            parse_log("Starting test 3: IPC0001\n"),
+            verdict_t::Incomplete
+        );
+    }
+
+    #[test]
+    fn is_final_line_test() {
+        assert!(is_final_line("All is well in the universe"));
+        assert!(is_final_line("rel4_kernel: PANICED"));
+        assert!(!is_final_line("Test failed"));
+        assert!(!is_final_line("Starting test 1: BIND0001"));
+    }
+}
@@ -0,0 +1,304 @@
+//! Build and test the kernel on every architecture, run with `cargo xtask`.
+//!
+//! `cargo xtask test` builds the kernel library for riscv64 and aarch64, links each into a
+//! sel4test image with the seL4 build of the workspace the repository was synced into, see the
+//! README of `kernel`, and runs the image under QEMU. The image is linked with the script the
+//! kernel build generated for the platform, passed to the seL4 build as `REL4_LINKER_SCRIPT`.
+//! The serial output of every run is kept in `target/xtask/<arch>/simulate.log` and judged by
+//! [`log::parse_log`].
+//!
+//! The kernel is built with `ENABLE_DEBUG_RUN`, so the tests of the workspace can reach the
+//! conformance suites registered with `SysDebugRun`.
+//!
+//! `cargo xtask audit` checks where the `unsafe` code of the kernel lives, see [`audit`].
+
+#![allow(non_camel_case_types)]
+
+mod audit;
+mod log;
+
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{exit, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::log::{is_final_line, parse_log, summary_line, verdict_t};
+
+/// An architecture of the test matrix
+struct arch_t {
+    name: &'static str,
+    target: &'static str,
+    platform: &'static str,
+}
+
+const ARCHS: [arch_t; 2] = [
+    arch_t {
+        name: "riscv64",
+        target: "riscv64imac-unknown-none-elf",
+        platform: "spike",
+    },
+    arch_t {
+        name: "aarch64",
+        target: "aarch64-unknown-none-softfloat",
+        platform: "qemu-arm-virt",
+    },
+];
+
+/// Kernel features every test image is built with
+const TEST_FEATURES: &str = "ENABLE_DEBUG_RUN";
+
+struct options_t {
+    archs: Vec<&'static arch_t>,
+    features: Vec<String>,
+    /// The sel4test workspace holding `init-build.sh`
+    workspace: PathBuf,
+    cpus: usize,
+    timeout: Duration,
+}
+
+const USAGE: &str = "\
+Usage: cargo xtask test [options]
+       cargo xtask audit [--update] [--miri] [--kani]
+
+Options:
+    --arch <riscv64|aarch64>   Test one architecture only, may be repeated
+    --features <list>          Extra kernel features, comma separated
+    --workspace <dir>          The sel4test workspace, by default the parent of the repository
+    --cpus <n>                 Cores of the kernel and of QEMU, default 1
+    --timeout <seconds>        Time a simulation may take, default 120";
+
+fn main() {
+    let mut args = env::args().skip(1);
+    match args.next().as_deref() {
+        Some("test") => {}
+        Some("audit") => {
+            audit::audit(args);
+            return;
+        }
+        Some("help") | Some("--help") | None => {
+            println!("{}", USAGE);
+            return;
+        }
+        Some(other) => fail(&format!("unknown command `{}`\n\n{}", other, USAGE)),
+    }
+    let options = parse_options(args);
+
+    let mut results = Vec::new();
+    for arch in options.archs.iter() {
+        let verdict = test_arch(arch, &options);
+        results.push((arch.name, verdict));
+    }
+
+    println!();
+    println!("{:<10} result", "arch");
+    for (name, verdict) in results.iter() {
+        println!("{:<10} {:?}", name, verdict);
+    }
+    if !results.iter().all(|(_, verdict)| verdict.passed()) {
+        exit(1);
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> options_t {
+    let mut options = options_t {
+        archs: Vec::new(),
+        features: vec![TEST_FEATURES.to_string()],
+        workspace: repo_root()
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(repo_root),
+        cpus: 1,
+        timeout: Duration::from_secs(120),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .unwrap_or_else(|| fail(&format!("`{}` needs a value", arg)))
+        };
+        match arg.as_str() {
+            "--arch" => {
+                let name = value();
+                match ARCHS.iter().find(|arch| arch.name == name) {
+                    Some(arch) => options.archs.push(arch),
+                    None => fail(&format!("unknown architecture `{}`", name)),
+                }
+            }
+            "--features" => options
+                .features
+                .extend(value().split(',').map(str::to_string)),
+            "--workspace" => options.workspace = PathBuf::from(value()),
+            "--cpus" => options.cpus = parse_number(&value()),
+            "--timeout" => options.timeout = Duration::from_secs(parse_number(&value()) as u64),
+            _ => fail(&format!("unknown option `{}`\n\n{}", arg, USAGE)),
+        }
+    }
+    if options.archs.is_empty() {
+        options.archs = ARCHS.iter().collect();
+    }
+    if options.cpus > 1 && !options.features.iter().any(|f| f == "ENABLE_SMP") {
+        options.features.push("ENABLE_SMP".to_string());
+    }
+    options
+}
+
+fn parse_number(value: &str) -> usize {
+    value
+        .parse()
+        .unwrap_or_else(|_| fail(&format!("`{}` is not a number", value)))
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    exit(2)
+}
+
+/// The repository root, the parent of this crate.
+fn repo_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .to_path_buf()
+}
+
+/// Build, link and simulate the kernel for `arch`.
+fn test_arch(arch: &arch_t, options: &options_t) -> verdict_t {
+    println!("==> {}: building the kernel", arch.name);
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let build = Command::new(cargo)
+        .current_dir(repo_root())
+        .args(["build", "--release", "--target", arch.target])
+        .args(["--features", &options.features.join(",")])
+        .args(["--message-format", "json-render-diagnostics"])
+        .stderr(Stdio::inherit())
+        .output();
+    let messages = match build {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        }
+        _ => {
+            eprintln!("{}: the kernel does not build", arch.name);
+            return verdict_t::Incomplete;
+        }
+    };
+    let Some(script) = linker_script(&messages) else {
+        eprintln!("{}: the build did not lay out the kernel image", arch.name);
+        return verdict_t::Incomplete;
+    };
+
+    let build_dir = repo_root().join("target/xtask").join(arch.name);
+    let _ = fs::remove_dir_all(&build_dir);
+    fs::create_dir_all(&build_dir).unwrap_or_else(|err| fail(&err.to_string()));
+
+    println!("==> {}: building the sel4test image", arch.name);
+    let mut init = Command::new(options.workspace.join("init-build.sh"));
+    init.current_dir(&build_dir)
+        .arg(format!("-DPLATFORM={}", arch.platform))
+        .arg("-DSIMULATION=TRUE")
+        .arg(format!("-DREL4_LINKER_SCRIPT={}", script.display()));
+    if options.cpus > 1 {
+        init.arg("-DSMP=TRUE");
+    }
+    if !succeeded(init.status())
+        || !succeeded(Command::new("ninja").current_dir(&build_dir).status())
+    {
+        eprintln!(
+            "{}: the image does not build, is {} a sel4test workspace?",
+            arch.name,
+            options.workspace.display()
+        );
+        return verdict_t::Incomplete;
+    }
+
+    println!("==> {}: simulating", arch.name);
+    let log = simulate(&build_dir, options);
+    let log_path = build_dir.join("simulate.log");
+    if let Err(err) = fs::write(&log_path, &log) {
+        eprintln!("{}: cannot keep the log: {}", arch.name, err);
+    }
+    let verdict = parse_log(&log);
+    println!(
+        "{}: {:?}, {}, log in {}",
+        arch.name,
+        verdict,
+        summary_line(&log).unwrap_or("no summary"),
+        log_path.display()
+    );
+    verdict
+}
+
+/// The linker script `kernel/layout.rs` wrote to the `OUT_DIR` of the kernel, found in the JSON
+/// messages of `cargo build`. Cargo names the directory after a hash, so it is not known before.
+fn linker_script(messages: &str) -> Option<PathBuf> {
+    const OUT_DIR: &str = "\"out_dir\":\"";
+    messages
+        .lines()
+        .filter(|line| line.contains("\"reason\":\"build-script-executed\""))
+        .filter(|line| line.contains("rel4_kernel"))
+        .find_map(|line| {
+            let start = line.find(OUT_DIR)? + OUT_DIR.len();
+            let len = line[start..].find('"')?;
+            Some(Path::new(&line[start..start + len]).join("linker.ld"))
+        })
+}
+
+fn succeeded(status: std::io::Result<std::process::ExitStatus>) -> bool {
+    status.map(|status| status.success()).unwrap_or(false)
+}
+
+/// Run `simulate` of the seL4 build in `build_dir` until the verdict is known or the time is
+/// up, QEMU does not exit by itself once sel4test is done.
+///
+/// # Returns
+///
+/// The serial output.
+fn simulate(build_dir: &Path, options: &options_t) -> String {
+    let mut simulate = Command::new(build_dir.join("simulate"));
+    simulate
+        .current_dir(build_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit());
+    if options.cpus > 1 {
+        simulate.args(["--cpu-num", &options.cpus.to_string()]);
+    }
+    let mut child = match simulate.spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            eprintln!("cannot start simulate: {}", err);
+            return String::new();
+        }
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    let stdout = child.stdout.take().unwrap();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                return;
+            }
+        }
+    });
+
+    let deadline = Instant::now() + options.timeout;
+    let mut log = String::new();
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        match receiver.recv_timeout(left) {
+            Ok(line) => {
+                println!("{}", line);
+                log.push_str(&line);
+                log.push('\n');
+                if is_final_line(&line) {
+                    break;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+    log
+}